@@ -0,0 +1,176 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Compares executing a graph DSL file against many small source files, parsing the DSL file for
+//! every source file, and parsing it once and reusing its compiled queries and regular
+//! expressions, from one thread and from several threads.  Run with `cargo bench --bench
+//! compiled_file`.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
+
+const SOURCE_FILES: usize = 400;
+const THREADS: usize = 4;
+
+const DSL_SOURCE: &str = r#"
+(function_definition name: (identifier) @name parameters: (parameters) @params) @def
+{
+  node @def.node
+  attr (@def.node) kind = "function", name = (source-text @name)
+  scan (source-text @name) {
+    "^_" {
+      attr (@def.node) private = #true
+    }
+    "^test_([a-z_]+)$" {
+      attr (@def.node) tested = $1
+    }
+  }
+  scan (source-text @params) {
+    "[a-z_][a-z0-9_]*" {
+      node param
+      attr (param) name = $0
+      edge @def.node -> param
+    }
+  }
+}
+
+(call function: (identifier) @name) @call
+{
+  node @call.node
+  attr (@call.node) kind = "call", name = (source-text @name)
+}
+
+(call function: (attribute object: (_) @object attribute: (identifier) @name)) @call
+{
+  node @call.node
+  attr (@call.node) kind = "method-call", name = (source-text @name)
+  scan (source-text @object) {
+    "^self$" {
+      attr (@call.node) on_self = #true
+    }
+  }
+}
+
+(assignment left: (identifier) @name right: (_) @value)
+{
+  node @name.node
+  attr (@name.node) kind = "variable", name = (source-text @name)
+  scan (source-text @value) {
+    "^[0-9]+$" {
+      attr (@name.node) constant = #true
+    }
+    "^\"(.*)\"$" {
+      attr (@name.node) string = $1
+    }
+  }
+}
+
+(import_statement name: (dotted_name) @name)
+{
+  node @name.node
+  attr (@name.node) kind = "import", name = (source-text @name)
+}
+"#;
+
+fn python_source(i: usize) -> String {
+    format!(
+        "import os\n\ndef f{i}(a, b):\n    x{i} = g(a) + b\n    self.h(x{i})\n    return x{i}\n\ndef test_f{i}():\n    y = \"f{i}\"\n    z = {i}\n    assert f{i}(1, 2) == z\n",
+        i = i
+    )
+}
+
+fn parse_sources() -> Vec<(Tree, String)> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    (0..SOURCE_FILES)
+        .map(|i| {
+            let source = python_source(i);
+            let tree = parser.parse(&source, None).unwrap();
+            (tree, source)
+        })
+        .collect()
+}
+
+fn parse_file() -> File {
+    File::from_str(tree_sitter_python::language(), DSL_SOURCE).expect("Cannot parse file")
+}
+
+fn execute(file: &File, sources: &[(Tree, String)]) {
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    for (tree, source) in sources {
+        file.execute(tree, source, &config, &NoCancellation)
+            .expect("Cannot execute file");
+    }
+}
+
+fn execute_parsing_every_time(sources: &[(Tree, String)]) -> Duration {
+    let start = Instant::now();
+    for source in sources {
+        execute(&parse_file(), std::slice::from_ref(source));
+    }
+    start.elapsed()
+}
+
+fn execute_compiled(sources: &[(Tree, String)]) -> Duration {
+    let start = Instant::now();
+    execute(&parse_file(), sources);
+    start.elapsed()
+}
+
+fn execute_compiled_in_threads(sources: Arc<Vec<(Tree, String)>>) -> Duration {
+    let start = Instant::now();
+    let file = Arc::new(parse_file());
+    let chunk_len = sources.len().div_ceil(THREADS);
+    let threads = (0..THREADS)
+        .map(|i| {
+            let file = file.clone();
+            let sources = sources.clone();
+            thread::spawn(move || {
+                let start = (i * chunk_len).min(sources.len());
+                let end = ((i + 1) * chunk_len).min(sources.len());
+                execute(&file, &sources[start..end]);
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let sources = Arc::new(parse_sources());
+    // warm up
+    execute_compiled(&sources);
+    let parsing_every_time = execute_parsing_every_time(&sources);
+    let compiled = execute_compiled(&sources);
+    let compiled_in_threads = execute_compiled_in_threads(sources.clone());
+    println!(
+        "parsing the DSL file for every source file: {:?} for {} source files",
+        parsing_every_time, SOURCE_FILES
+    );
+    println!(
+        "reusing the parsed DSL file:                {:?} for {} source files",
+        compiled, SOURCE_FILES
+    );
+    println!(
+        "reusing it from {} threads:                  {:?} for {} source files",
+        THREADS, compiled_in_threads, SOURCE_FILES
+    );
+}
@@ -0,0 +1,111 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Compares executing three graph DSL files that share 60% of their stanza queries on the same
+//! syntax tree, with and without a shared query cache.  Run with `cargo bench --bench
+//! query_cache`.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::QueryCache;
+use tree_sitter_graph::Variables;
+
+const ITERATIONS: usize = 20;
+
+/// The queries shared by all files, and the queries that only one file has
+const SHARED_QUERIES: [&str; 3] = [
+    "(function_definition name: (identifier) @name)",
+    "(call function: (identifier) @name)",
+    "(assignment left: (identifier) @name)",
+];
+const OWN_QUERIES: [[&str; 2]; 3] = [
+    [
+        "(return_statement (_) @name)",
+        "(binary_operator left: (_) @name)",
+    ],
+    [
+        "(parameters (identifier) @name)",
+        "(if_statement condition: (_) @name)",
+    ],
+    [
+        "(for_statement left: (_) @name)",
+        "(comparison_operator (_) @name)",
+    ],
+];
+
+fn python_source() -> String {
+    (0..2000)
+        .map(|i| {
+            format!(
+                "def f{i}(a, b):\n    x{i} = g(a) + b\n    for y in h(x{i}):\n        if y < a:\n            return y\n    return x{i}\n\n",
+                i = i
+            )
+        })
+        .collect()
+}
+
+fn dsl_sources() -> Vec<String> {
+    OWN_QUERIES
+        .iter()
+        .map(|own| {
+            SHARED_QUERIES
+                .iter()
+                .chain(own)
+                .map(|query| {
+                    format!(
+                        "{} {{ node n attr (n) name = (source-text @name) }}\n",
+                        query
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn execute_all(files: &[File], tree: &Tree, source: &str, use_cache: bool) -> Duration {
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let cache = QueryCache::new();
+        for file in files {
+            let mut config = ExecutionConfig::new(&functions, &globals);
+            if use_cache {
+                config = config.query_cache(&cache);
+            }
+            file.execute(tree, source, &config, &NoCancellation)
+                .expect("Could not execute file");
+        }
+    }
+    start.elapsed() / ITERATIONS as u32
+}
+
+fn main() {
+    let source = python_source();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(&source, None).unwrap();
+    let files = dsl_sources()
+        .iter()
+        .map(|dsl_source| {
+            File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file")
+        })
+        .collect::<Vec<_>>();
+    // warm up
+    execute_all(&files, &tree, &source, false);
+    let without_cache = execute_all(&files, &tree, &source, false);
+    let with_cache = execute_all(&files, &tree, &source, true);
+    println!("without query cache: {:?} per round", without_cache);
+    println!("with query cache:    {:?} per round", with_cache);
+}
@@ -0,0 +1,97 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Compares executing a graph DSL file with and without accounting for resource usage.  Run with
+//! `cargo bench --bench resource_usage` to measure the counters, and with `cargo bench --bench
+//! resource_usage --no-default-features --features serde` to measure them compiled to no-ops.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
+
+const ITERATIONS: usize = 20;
+
+const DSL_SOURCE: &str = r#"
+(function_definition name: (identifier) @name parameters: (parameters) @params) @def
+{
+  node @def.node
+  attr (@def.node) kind = "function", name = (source-text @name)
+  scan (source-text @params) {
+    "[a-z]+" {
+      node param
+      attr (param) name = $0
+      edge @def.node -> param
+    }
+  }
+}
+
+(call function: (identifier) @name) @call
+{
+  node @call.node
+  attr (@call.node) kind = "call", parts = [(source-text @name), (node-kind @call)]
+}
+
+(assignment left: (identifier) @name right: (_) @value)
+{
+  node @name.node
+  attr (@name.node) kind = "variable", name = (source-text @name)
+  let kind = (node-kind @value)
+  if (eq kind "binary_operator") {
+    attr (@name.node) computed = #true
+  }
+}
+"#;
+
+fn python_source() -> String {
+    (0..2000)
+        .map(|i| {
+            format!(
+                "def f{i}(a, b):\n    x{i} = g(a) + b\n    for y in h(x{i}):\n        if y < a:\n            return y\n    return x{i}\n\n",
+                i = i
+            )
+        })
+        .collect()
+}
+
+fn execute(file: &File, tree: &Tree, source: &str, with_usage: bool) -> Duration {
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        if with_usage {
+            file.execute_with_usage(tree, source, &config, &NoCancellation)
+                .expect("Could not execute file");
+        } else {
+            file.execute(tree, source, &config, &NoCancellation)
+                .expect("Could not execute file");
+        }
+    }
+    start.elapsed() / ITERATIONS as u32
+}
+
+fn main() {
+    let source = python_source();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(&source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), DSL_SOURCE).expect("Cannot parse file");
+    // warm up
+    execute(&file, &tree, &source, false);
+    let without_usage = execute(&file, &tree, &source, false);
+    let with_usage = execute(&file, &tree, &source, true);
+    println!("without resource usage: {:?} per execution", without_usage);
+    println!("with resource usage:    {:?} per execution", with_usage);
+}
@@ -0,0 +1,139 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Writes the edges of a graph as CSV while the graph is being built, using a graph observer.
+//! Run with `cargo run --example csv_edges [PYTHON_FILE]`.  Without a file, a small built-in
+//! Python source is used.
+
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+
+use tree_sitter::Parser;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::graph::GraphNodeRef;
+use tree_sitter_graph::graph::SyntaxNodeRef;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::GraphObserver;
+use tree_sitter_graph::MutationContext;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
+
+const DSL_SOURCE: &str = r#"
+(module) @module
+{
+  node @module.node
+}
+
+(function_definition) @def
+{
+  node @def.node
+}
+
+(module (function_definition) @def) @module
+{
+  edge @module.node -> @def.node
+}
+
+(call) @call
+{
+  node @call.node
+}
+
+(function_definition body: (block (expression_statement (call) @call))) @def
+{
+  edge @def.node -> @call.node
+}
+"#;
+
+const PYTHON_SOURCE: &str = r#"
+def f(x):
+    g(x)
+    h(x)
+
+def g(y):
+    h(y)
+"#;
+
+/// Writes a CSV row for every edge as soon as it is created, with the kinds of the syntax nodes
+/// that its source and sink were created for.
+struct CsvEdgeWriter<W: Write> {
+    output: W,
+    node_kinds: Vec<&'static str>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> CsvEdgeWriter<W> {
+    fn new(mut output: W) -> io::Result<Self> {
+        writeln!(output, "source,source_kind,sink,sink_kind,dsl_line")?;
+        Ok(Self {
+            output,
+            node_kinds: Vec::new(),
+            error: None,
+        })
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        match self.error {
+            Some(error) => Err(error),
+            None => self.output.flush(),
+        }
+    }
+}
+
+impl<W: Write> GraphObserver for CsvEdgeWriter<W> {
+    fn on_node_created(
+        &mut self,
+        node: GraphNodeRef,
+        syntax_node: SyntaxNodeRef,
+        ctx: &MutationContext,
+    ) {
+        let index = node.index();
+        if self.node_kinds.len() <= index {
+            self.node_kinds.resize(index + 1, "");
+        }
+        self.node_kinds[index] = ctx.graph()[syntax_node].kind();
+    }
+
+    fn on_edge_created(&mut self, source: GraphNodeRef, sink: GraphNodeRef, ctx: &MutationContext) {
+        if self.error.is_some() {
+            return;
+        }
+        let kind = |node: GraphNodeRef| self.node_kinds.get(node.index()).copied().unwrap_or("");
+        let result = writeln!(
+            self.output,
+            "{},{},{},{},{}",
+            source.index(),
+            kind(source),
+            sink.index(),
+            kind(sink),
+            ctx.location().row + 1,
+        );
+        self.error = result.err();
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let source = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => PYTHON_SOURCE.to_string(),
+    };
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language())?;
+    let tree = parser.parse(&source, None).ok_or("cannot parse source")?;
+    let file = File::from_str(tree_sitter_python::language(), DSL_SOURCE)?;
+
+    let stdout = io::stdout();
+    let observer = RefCell::new(CsvEdgeWriter::new(io::BufWriter::new(stdout.lock()))?);
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).observer(&observer);
+    file.execute(&tree, &source, &config, &NoCancellation)?;
+    observer.into_inner().finish()?;
+    Ok(())
+}
@@ -7,7 +7,7 @@
 
 //! Defines the AST structure of a graph DSL file
 
-use regex::Regex;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
@@ -15,11 +15,18 @@ use tree_sitter::CaptureQuantifier;
 use tree_sitter::Language;
 use tree_sitter::Query;
 
+use crate::graph::Float;
+use crate::graph::Value;
 use crate::parser::Range;
+use crate::regex_engine::Regex;
 use crate::Identifier;
 use crate::Location;
 
 /// A graph DSL file
+///
+/// Parsing a file compiles its stanza queries and the regular expressions of its `scan`
+/// statements once, and executions reuse them.  A file is not modified by executing it, so the
+/// same file can be executed from several threads at the same time.
 #[derive(Debug)]
 pub struct File {
     pub language: Language,
@@ -27,12 +34,26 @@ pub struct File {
     pub globals: Vec<Global>,
     /// The scoped variables that are inherited by child nodes
     pub inherited_variables: HashSet<Identifier>,
+    /// The attributes that graph nodes inherit from their ancestors after execution
+    pub inherited_attributes: Vec<InheritedAttribute>,
     /// The combined query of all stanzas in the file
     pub query: Option<Query>,
     /// The list of stanzas in the file
     pub stanzas: Vec<Stanza>,
     /// Attribute shorthands defined in the file
     pub shorthands: AttributeShorthands,
+    /// Node types defined in the file
+    pub node_types: NodeTypes,
+    /// The phases declared in the file, in the order in which they are executed
+    pub phases: Vec<Identifier>,
+    /// The DSL version declared by the file's version directive, if any
+    pub declared_version: Option<u32>,
+    /// The rows of `; #allow(shadow)` comments, which suppress shadowing warnings
+    pub shadowing_allowed: BTreeSet<usize>,
+    /// The ranges of the stanzas that failed to parse, if the file was parsed
+    /// [with recovery][File::from_str_with_recovery].  These stanzas are not part of `stanzas`,
+    /// and are not executed.
+    pub invalid_stanzas: Vec<Range>,
 }
 
 impl File {
@@ -41,15 +62,42 @@ impl File {
             language,
             globals: Vec::new(),
             inherited_variables: HashSet::new(),
+            inherited_attributes: Vec::new(),
             query: None,
             stanzas: Vec::new(),
             shorthands: AttributeShorthands::new(),
+            node_types: NodeTypes::new(),
+            phases: Vec::new(),
+            declared_version: None,
+            shadowing_allowed: BTreeSet::new(),
+            invalid_stanzas: Vec::new(),
+        }
+    }
+
+    /// Returns the version of the DSL that the file was parsed with.  This is the version declared
+    /// by the file, or the latest supported version if it does not declare one.
+    pub fn dsl_version(&self) -> u32 {
+        self.declared_version.unwrap_or(crate::DSL_VERSION)
+    }
+
+    /// Returns the position of a stanza's phase in the execution order.  Stanzas that precede all
+    /// phase declarations are executed first.
+    pub fn phase_index(&self, stanza: &Stanza) -> usize {
+        match &stanza.phase {
+            Some(phase) => {
+                1 + self
+                    .phases
+                    .iter()
+                    .position(|declared| declared == phase)
+                    .expect("undeclared phase")
+            }
+            None => 0,
         }
     }
 }
 
 /// A global variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Global {
     /// The name of the global variable
     pub name: Identifier,
@@ -60,6 +108,36 @@ pub struct Global {
     pub location: Location,
 }
 
+impl Global {
+    /// Returns whether the global variable has a default value, which is used if the host does
+    /// not provide one.
+    pub fn has_default(&self) -> bool {
+        self.default.is_some()
+    }
+}
+
+/// An attribute that graph nodes inherit from their nearest ancestor along edges of a certain kind
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InheritedAttribute {
+    /// The name of the inherited attribute
+    pub name: Identifier,
+    /// The name of the edge attribute that identifies the edges to follow
+    pub edge_attribute: Identifier,
+    /// The value of the edge attribute of the edges to follow
+    pub edge_value: String,
+    pub location: Location,
+}
+
+impl std::fmt::Display for InheritedAttribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "inherit {} along edge {} = {:?}",
+            self.name, self.edge_attribute, self.edge_value
+        )
+    }
+}
+
 /// One stanza within a file
 #[derive(Debug)]
 pub struct Stanza {
@@ -71,11 +149,18 @@ pub struct Stanza {
     pub full_match_stanza_capture_index: usize,
     /// Capture index of the full match in the file query
     pub full_match_file_capture_index: usize,
+    /// The phase that this stanza belongs to, or `None` if it precedes all phase declarations
+    pub phase: Option<Identifier>,
+    /// The name of this stanza, given by a `stanza` declaration, which overlays use to override
+    /// it
+    pub name: Option<Identifier>,
     pub range: Range,
+    /// The source of the stanza query, as it appears in the file query
+    pub(crate) query_source: String,
 }
 
 /// A statement that can appear in a graph DSL stanza
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Statement {
     // Variables
     DeclareImmutable(DeclareImmutable),
@@ -87,6 +172,9 @@ pub enum Statement {
     // Edges
     CreateEdge(CreateEdge),
     AddEdgeAttribute(AddEdgeAttribute),
+    // Graph nodes or edges
+    AddAttributes(AddAttributes),
+    RemoveAttributes(RemoveAttributes),
     // Regular expression
     Scan(Scan),
     // Debugging
@@ -107,6 +195,8 @@ impl std::fmt::Display for Statement {
             Self::AddGraphNodeAttribute(stmt) => stmt.fmt(f),
             Self::CreateEdge(stmt) => stmt.fmt(f),
             Self::AddEdgeAttribute(stmt) => stmt.fmt(f),
+            Self::AddAttributes(stmt) => stmt.fmt(f),
+            Self::RemoveAttributes(stmt) => stmt.fmt(f),
             Self::Scan(stmt) => stmt.fmt(f),
             Self::Print(stmt) => stmt.fmt(f),
             Self::If(stmt) => stmt.fmt(f),
@@ -116,7 +206,7 @@ impl std::fmt::Display for Statement {
 }
 
 /// An `attr` statement that adds an attribute to an edge
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AddEdgeAttribute {
     pub source: Expression,
     pub sink: Expression,
@@ -141,7 +231,7 @@ impl std::fmt::Display for AddEdgeAttribute {
 }
 
 /// An `attr` statement that adds an attribute to a graph node
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AddGraphNodeAttribute {
     pub node: Expression,
     pub attributes: Vec<Attribute>,
@@ -164,8 +254,93 @@ impl std::fmt::Display for AddGraphNodeAttribute {
     }
 }
 
+/// An `attr` statement that adds the same attributes to several graph nodes or edges
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AddAttributes {
+    pub targets: Vec<AttributeTarget>,
+    pub attributes: Vec<Attribute>,
+    pub location: Location,
+}
+
+impl From<AddAttributes> for Statement {
+    fn from(statement: AddAttributes) -> Statement {
+        Statement::AddAttributes(statement)
+    }
+}
+
+impl std::fmt::Display for AddAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "attr (")?;
+        for (index, target) in self.targets.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", target)?;
+        }
+        write!(f, ")")?;
+        for attr in &self.attributes {
+            write!(f, " {}", attr)?;
+        }
+        write!(f, " at {}", self.location)
+    }
+}
+
+/// A `delattr` statement that removes attributes from graph nodes or edges
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RemoveAttributes {
+    pub targets: Vec<AttributeTarget>,
+    pub names: Vec<Identifier>,
+    pub location: Location,
+}
+
+impl From<RemoveAttributes> for Statement {
+    fn from(statement: RemoveAttributes) -> Statement {
+        Statement::RemoveAttributes(statement)
+    }
+}
+
+impl std::fmt::Display for RemoveAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "delattr (")?;
+        for (index, target) in self.targets.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", target)?;
+        }
+        write!(f, ")")?;
+        for (index, name) in self.names.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, " {}", name)?;
+        }
+        write!(f, " at {}", self.location)
+    }
+}
+
+/// A graph node or edge that an [`AddAttributes`][] or [`RemoveAttributes`][] statement
+/// applies to
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AttributeTarget {
+    GraphNode(Expression),
+    Edge {
+        source: Expression,
+        sink: Expression,
+    },
+}
+
+impl std::fmt::Display for AttributeTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::GraphNode(node) => write!(f, "{}", node),
+            Self::Edge { source, sink } => write!(f, "{} -> {}", source, sink),
+        }
+    }
+}
+
 /// A `set` statement that updates the value of a mutable variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Assign {
     pub variable: Variable,
     pub value: Expression,
@@ -189,23 +364,60 @@ impl std::fmt::Display for Assign {
 }
 
 /// The name and value of an attribute
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Attribute {
     pub name: Identifier,
     pub value: Expression,
+    /// How the value is added to the attribute
+    pub operation: AttributeOperation,
 }
 
 impl std::fmt::Display for Attribute {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{} = {}", self.name, self.value)
+        write!(f, "{} {} {}", self.name, self.operation, self.value)
+    }
+}
+
+/// How an [`Attribute`][] is added to a graph node or edge
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttributeOperation {
+    /// The attribute is set, using `=`.  It is an error if it already has a different value.
+    Set,
+    /// The value is appended to a list-valued attribute, using `+=`
+    Append,
+    /// The value replaces the value of an attribute that is already set, using `:=`
+    Override,
+}
+
+impl AttributeOperation {
+    /// Returns the operation for an attribute of a shorthand that is used with this operation.
+    /// Appending to or overriding a shorthand appends to or overrides all of its attributes.
+    pub(crate) fn combine(self, inner: AttributeOperation) -> AttributeOperation {
+        match self {
+            Self::Set => inner,
+            outer => outer,
+        }
+    }
+}
+
+impl std::fmt::Display for AttributeOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Set => write!(f, "="),
+            Self::Append => write!(f, "+="),
+            Self::Override => write!(f, ":="),
+        }
     }
 }
 
 /// An `edge` statement that creates a new edge
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CreateEdge {
     pub source: Expression,
     pub sink: Expression,
+    /// The inline attributes that are added to the edge, as if by an `attr` statement that
+    /// follows this statement
+    pub attributes: Vec<Attribute>,
     pub location: Location,
 }
 
@@ -217,18 +429,25 @@ impl From<CreateEdge> for Statement {
 
 impl std::fmt::Display for CreateEdge {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "edge {} -> {} at {}",
-            self.source, self.sink, self.location,
-        )
+        write!(f, "edge {} -> {}", self.source, self.sink)?;
+        for attr in &self.attributes {
+            write!(f, ", {}", attr)?;
+        }
+        write!(f, " at {}", self.location)
     }
 }
 
 /// A `node` statement that creates a new graph node
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CreateGraphNode {
     pub node: Variable,
+    /// The node type that the graph node is an instance of, if any
+    pub node_type: Option<Identifier>,
+    /// The default attributes of the node type, which are filled in by the checker
+    pub defaults: Vec<(Identifier, Value)>,
+    /// The inline attributes that are added to the graph node, as if by an `attr` statement
+    /// that follows this statement
+    pub attributes: Vec<Attribute>,
     pub location: Location,
 }
 
@@ -240,12 +459,19 @@ impl From<CreateGraphNode> for Statement {
 
 impl std::fmt::Display for CreateGraphNode {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "node {} at {}", self.node, self.location)
+        write!(f, "node {}", self.node)?;
+        if let Some(node_type) = &self.node_type {
+            write!(f, " : {}", node_type)?;
+        }
+        for attr in &self.attributes {
+            write!(f, ", {}", attr)?;
+        }
+        write!(f, " at {}", self.location)
     }
 }
 
 /// A `let` statement that declares a new immutable variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct DeclareImmutable {
     pub variable: Variable,
     pub value: Expression,
@@ -269,7 +495,7 @@ impl std::fmt::Display for DeclareImmutable {
 }
 
 /// A `var` statement that declares a new mutable variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct DeclareMutable {
     pub variable: Variable,
     pub value: Expression,
@@ -293,7 +519,7 @@ impl std::fmt::Display for DeclareMutable {
 }
 
 /// A `print` statement that prints out some debugging information
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Print {
     pub values: Vec<Expression>,
     pub location: Location,
@@ -316,7 +542,7 @@ impl std::fmt::Display for Print {
 }
 
 /// A `scan` statement that matches regular expressions against a string
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Scan {
     pub value: Expression,
     pub arms: Vec<ScanArm>,
@@ -336,7 +562,7 @@ impl std::fmt::Display for Scan {
 }
 
 /// One arm of a `scan` statement
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ScanArm {
     pub regex: Regex,
     pub statements: Vec<Statement>,
@@ -358,7 +584,7 @@ impl std::fmt::Display for ScanArm {
 }
 
 /// A `cond` conditional statement that selects the first branch with a matching condition
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct If {
     pub arms: Vec<IfArm>,
     pub location: Location,
@@ -390,7 +616,7 @@ impl std::fmt::Display for If {
 }
 
 /// One arm of a `cond` statement
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct IfArm {
     pub conditions: Vec<Condition>,
     pub statements: Vec<Statement>,
@@ -399,7 +625,7 @@ pub struct IfArm {
 
 struct DisplayConditions<'a>(&'a Vec<Condition>);
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Condition {
     Some {
         value: Expression,
@@ -447,9 +673,10 @@ impl std::fmt::Display for Condition {
 }
 
 /// A `for in` statement
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ForIn {
     pub variable: UnscopedVariable,
+    pub index: Option<UnscopedVariable>,
     pub value: Expression,
     pub statements: Vec<Statement>,
     pub location: Location,
@@ -463,16 +690,20 @@ impl From<ForIn> for Statement {
 
 impl std::fmt::Display for ForIn {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        write!(f, "for ")?;
+        if let Some(index) = &self.index {
+            write!(f, "{}, ", index)?;
+        }
         write!(
             f,
-            "for {} in {} {{ ... }} at {}",
+            "{} in {} {{ ... }} at {}",
             self.variable, self.value, self.location,
         )
     }
 }
 
 /// A reference to a variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Variable {
     Scoped(ScopedVariable),
     Unscoped(UnscopedVariable),
@@ -488,7 +719,7 @@ impl std::fmt::Display for Variable {
 }
 
 /// A reference to a scoped variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ScopedVariable {
     pub scope: Box<Expression>,
     pub name: Identifier,
@@ -508,7 +739,7 @@ impl std::fmt::Display for ScopedVariable {
 }
 
 /// A reference to a global or local variable
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct UnscopedVariable {
     pub name: Identifier,
     pub location: Location,
@@ -527,7 +758,7 @@ impl std::fmt::Display for UnscopedVariable {
 }
 
 /// An expression that can appear in a graph DSL file
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Expression {
     // Literals
     FalseLiteral,
@@ -535,21 +766,25 @@ pub enum Expression {
     TrueLiteral,
     // Constants
     IntegerConstant(IntegerConstant),
+    FloatConstant(FloatConstant),
     StringConstant(StringConstant),
     // Literals
     ListLiteral(ListLiteral),
     SetLiteral(SetLiteral),
+    MapLiteral(MapLiteral),
     // Comprehensions
     ListComprehension(ListComprehension),
     SetComprehension(SetComprehension),
     // Syntax nodes
     Capture(Capture),
+    CaptureAggregate(CaptureAggregate),
     // Variables
     Variable(Variable),
     // Functions
     Call(Call),
     // Regular expression
     RegexCapture(RegexCapture),
+    MatchOffset(MatchOffset),
 }
 
 impl std::fmt::Display for Expression {
@@ -559,24 +794,29 @@ impl std::fmt::Display for Expression {
             Expression::NullLiteral => write!(f, "#null"),
             Expression::TrueLiteral => write!(f, "true"),
             Expression::IntegerConstant(expr) => expr.fmt(f),
+            Expression::FloatConstant(expr) => expr.fmt(f),
             Expression::StringConstant(expr) => expr.fmt(f),
             Expression::ListLiteral(expr) => expr.fmt(f),
             Expression::SetLiteral(expr) => expr.fmt(f),
+            Expression::MapLiteral(expr) => expr.fmt(f),
             Expression::ListComprehension(expr) => expr.fmt(f),
             Expression::SetComprehension(expr) => expr.fmt(f),
             Expression::Capture(expr) => expr.fmt(f),
+            Expression::CaptureAggregate(expr) => expr.fmt(f),
             Expression::Variable(expr) => expr.fmt(f),
             Expression::Call(expr) => expr.fmt(f),
             Expression::RegexCapture(expr) => expr.fmt(f),
+            Expression::MatchOffset(expr) => expr.fmt(f),
         }
     }
 }
 
 /// A function call
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Call {
     pub function: Identifier,
     pub parameters: Vec<Expression>,
+    pub location: Location,
 }
 
 impl From<Call> for Expression {
@@ -596,7 +836,7 @@ impl std::fmt::Display for Call {
 }
 
 /// A capture expression that references a syntax node
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Capture {
     /// The name of the capture
     pub name: Identifier,
@@ -621,10 +861,64 @@ impl std::fmt::Display for Capture {
     }
 }
 
+/// A count, first, or last call on a quantified capture, which is computed from the nodes of the
+/// query match without building a list of them
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CaptureAggregate {
+    pub aggregate: Aggregate,
+    pub capture: Capture,
+    pub location: Location,
+}
+
+impl From<CaptureAggregate> for Expression {
+    fn from(expr: CaptureAggregate) -> Expression {
+        Expression::CaptureAggregate(expr)
+    }
+}
+
+impl std::fmt::Display for CaptureAggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "({} {})", self.aggregate, self.capture)
+    }
+}
+
+/// The aggregates that can be computed for a quantified capture
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Aggregate {
+    /// The number of nodes bound to the capture
+    Count,
+    /// The first node bound to the capture, or null
+    First,
+    /// The last node bound to the capture, or null
+    Last,
+}
+
+impl Aggregate {
+    /// Returns the aggregate that is computed by the function with the given name, if any.
+    pub fn from_function_name(name: &str) -> Option<Aggregate> {
+        match name {
+            "count" => Some(Aggregate::Count),
+            "first" => Some(Aggregate::First),
+            "last" => Some(Aggregate::Last),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Aggregate::Count => write!(f, "count"),
+            Aggregate::First => write!(f, "first"),
+            Aggregate::Last => write!(f, "last"),
+        }
+    }
+}
+
 /// An integer constant
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct IntegerConstant {
-    pub value: u32,
+    pub value: i64,
 }
 
 impl From<IntegerConstant> for Expression {
@@ -639,8 +933,26 @@ impl std::fmt::Display for IntegerConstant {
     }
 }
 
+/// A float constant
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FloatConstant {
+    pub value: Float,
+}
+
+impl From<FloatConstant> for Expression {
+    fn from(expr: FloatConstant) -> Expression {
+        Expression::FloatConstant(expr)
+    }
+}
+
+impl std::fmt::Display for FloatConstant {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
 /// An ordered list of values
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ListLiteral {
     pub elements: Vec<Expression>,
 }
@@ -668,7 +980,7 @@ impl std::fmt::Display for ListLiteral {
 }
 
 /// An list comprehension
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ListComprehension {
     pub element: Box<Expression>,
     pub variable: UnscopedVariable,
@@ -692,10 +1004,15 @@ impl std::fmt::Display for ListComprehension {
     }
 }
 
-/// A reference to one of the regex captures in a `scan` statement
-#[derive(Debug, Eq, PartialEq)]
+/// A reference to one of the regex captures in a `scan` statement, by number, as in `$1`, or by
+/// the name of a named group, as in `$dir`
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RegexCapture {
+    /// The index of the capture group.  For named groups, it is filled in by the checker.
     pub match_index: usize,
+    /// The name of the capture group, if it is referenced by name
+    pub name: Option<Identifier>,
+    pub location: Location,
 }
 
 impl From<RegexCapture> for Expression {
@@ -706,12 +1023,40 @@ impl From<RegexCapture> for Expression {
 
 impl std::fmt::Display for RegexCapture {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "${}", self.match_index)
+        match &self.name {
+            Some(name) => write!(f, "${}", name),
+            None => write!(f, "${}", self.match_index),
+        }
+    }
+}
+
+/// A reference to the offset of the start or end of the match in a `scan` statement,
+/// `$match_start` or `$match_end`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchOffset {
+    /// Whether this is the offset of the end of the match, instead of its start
+    pub end: bool,
+    pub location: Location,
+}
+
+impl From<MatchOffset> for Expression {
+    fn from(expr: MatchOffset) -> Expression {
+        Expression::MatchOffset(expr)
+    }
+}
+
+impl std::fmt::Display for MatchOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.end {
+            write!(f, "$match_end")
+        } else {
+            write!(f, "$match_start")
+        }
     }
 }
 
 /// An unordered set of values
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SetLiteral {
     pub elements: Vec<Expression>,
 }
@@ -738,8 +1083,40 @@ impl std::fmt::Display for SetLiteral {
     }
 }
 
+/// A map from names to values.  An empty map is written `{:}`, to tell it apart from an empty
+/// set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MapLiteral {
+    pub entries: Vec<(Identifier, Expression)>,
+}
+
+impl From<MapLiteral> for Expression {
+    fn from(expr: MapLiteral) -> Expression {
+        Expression::MapLiteral(expr)
+    }
+}
+
+impl std::fmt::Display for MapLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.entries.is_empty() {
+            return write!(f, "{{:}}");
+        }
+        write!(f, "{{")?;
+        let mut first = true;
+        for (key, value) in &self.entries {
+            if first {
+                write!(f, "{}: {}", key, value)?;
+                first = false;
+            } else {
+                write!(f, ", {}: {}", key, value)?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
 /// An set comprehension
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SetComprehension {
     pub element: Box<Expression>,
     pub variable: UnscopedVariable,
@@ -764,7 +1141,7 @@ impl std::fmt::Display for SetComprehension {
 }
 
 /// A string constant
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StringConstant {
     pub value: String,
 }
@@ -800,7 +1177,7 @@ impl From<ScopedVariable> for Expression {
 }
 
 /// Attribute shorthands
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AttributeShorthands(HashMap<Identifier, AttributeShorthand>);
 
 impl AttributeShorthands {
@@ -820,26 +1197,83 @@ impl AttributeShorthands {
         self.0.values()
     }
 
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut AttributeShorthand> {
+        self.0.values_mut()
+    }
+
     pub fn into_iter(self) -> impl Iterator<Item = AttributeShorthand> {
         self.0.into_values()
     }
 }
 
 /// An attribute shorthand
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AttributeShorthand {
     pub name: Identifier,
-    pub variable: UnscopedVariable,
+    /// The variable that receives the value given to the shorthand, or None if the shorthand
+    /// takes no value
+    pub variable: Option<UnscopedVariable>,
     pub attributes: Vec<Attribute>,
     pub location: Location,
 }
 
 impl std::fmt::Display for AttributeShorthand {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "attribute {} = {} =>", self.name, self.variable,)?;
+        write!(f, "attribute {}", self.name)?;
+        if let Some(variable) = &self.variable {
+            write!(f, " = {}", variable)?;
+        }
+        write!(f, " =>")?;
         for attr in &self.attributes {
             write!(f, " {}", attr)?;
         }
         write!(f, " at {}", self.location)
     }
 }
+
+/// Node types
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NodeTypes(HashMap<Identifier, NodeType>);
+
+impl NodeTypes {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn get(&self, name: &Identifier) -> Option<&NodeType> {
+        self.0.get(name)
+    }
+
+    pub fn add(&mut self, node_type: NodeType) {
+        self.0.insert(node_type.name.clone(), node_type);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NodeType> {
+        self.0.values()
+    }
+}
+
+/// A node type, which gives default values for the attributes of the graph nodes that are
+/// instances of it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NodeType {
+    pub name: Identifier,
+    pub attributes: Vec<Attribute>,
+    pub location: Location,
+}
+
+impl std::fmt::Display for NodeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "nodetype {} {{", self.name)?;
+        let mut first = true;
+        for attr in &self.attributes {
+            if first {
+                first = false;
+            } else {
+                write!(f, ",")?;
+            }
+            write!(f, " {}", attr)?;
+        }
+        write!(f, " }} at {}", self.location)
+    }
+}
@@ -5,7 +5,11 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::collections::BTreeSet;
+use std::io::IsTerminal as _;
+use std::io::Write as _;
 use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Context as _;
@@ -13,13 +17,25 @@ use anyhow::Result;
 use clap::builder::ArgAction;
 use clap::App;
 use clap::Arg;
+use serde::Serialize;
+use tree_sitter::Language;
 use tree_sitter::Parser;
 use tree_sitter_config::Config;
 use tree_sitter_graph::ast::File;
+use tree_sitter_graph::diagnostics::Diagnostic;
 use tree_sitter_graph::functions::Functions;
 use tree_sitter_graph::graph;
+use tree_sitter_graph::graph::CombinedGraph;
+use tree_sitter_graph::graph::EdgeTypeSchema;
+use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::graph::PositionEncoding;
+use tree_sitter_graph::graph::RedactionRules;
+use tree_sitter_graph::graph::SerializationConfig;
+use tree_sitter_graph::graph::SyntaxNodeRendering;
+use tree_sitter_graph::graph::DEFAULT_SOURCE_PATH_ATTRIBUTE;
 use tree_sitter_graph::parse_error::ParseError;
 use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::ExecutionError;
 use tree_sitter_graph::Identifier;
 use tree_sitter_graph::NoCancellation;
 use tree_sitter_graph::Variables;
@@ -29,6 +45,12 @@ const BUILD_VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
 const MAX_PARSE_ERRORS: usize = 5;
 
+/// The global variable that holds the path of the source file, unless it is given with `--global`
+const FILE_PATH_GLOBAL: &str = "FILE_PATH";
+
+/// The exit code when the graph violates the edge type schema given with `--edge-schema`
+const EDGE_TYPE_VIOLATIONS_EXIT_CODE: i32 = 3;
+
 fn main() -> Result<()> {
     init_log();
 
@@ -36,8 +58,55 @@ fn main() -> Result<()> {
         .version(BUILD_VERSION)
         .author("Douglas Creager <dcreager@dcreager.net>")
         .about("Generates graph structures from tree-sitter syntax trees")
-        .arg(Arg::with_name("tsg").index(1).required(true))
-        .arg(Arg::with_name("source").index(2).required(true))
+        .arg(
+            Arg::with_name("tsg")
+                .index(1)
+                .required_unless_present("list-node-kinds"),
+        )
+        .arg(
+            Arg::with_name("source")
+                .index(2)
+                .multiple_values(true)
+                .required_unless_present_any(&["list-node-kinds", "recursive", "check-only"]),
+        )
+        .arg(
+            Arg::with_name("check-only")
+                .long("check-only")
+                .takes_value(false)
+                .conflicts_with_all(&["recursive", "combine"])
+                .help("Check the TSG file without executing it, selecting the language with --scope or a source file"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .long("recursive")
+                .short('r')
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Execute the TSG file against every file in a directory and its subdirectories"),
+        )
+        .arg(
+            Arg::with_name("include-ext")
+                .long("include-ext")
+                .takes_value(true)
+                .value_name("EXTENSIONS")
+                .requires("recursive")
+                .help("Only include files with one of these comma-separated extensions with --recursive"),
+        )
+        .arg(
+            Arg::with_name("output-suffix")
+                .long("output-suffix")
+                .takes_value(true)
+                .value_name("SUFFIX")
+                .conflicts_with_all(&["output", "combine"])
+                .help("With several source files, write the graph of every source file next to it, to its path with this suffix"),
+        )
+        .arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .takes_value(false)
+                .conflicts_with("combine")
+                .help("With several source files, stop at the first source file that fails"),
+        )
         .arg(
             Arg::with_name("quiet")
                 .short('q')
@@ -51,61 +120,561 @@ fn main() -> Result<()> {
                 .help("Use lazy evaluation (experimental)"),
         )
         .arg(Arg::with_name("scope").long("scope").takes_value(true))
-        .arg(Arg::with_name("json").long("json").takes_value(false))
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["pretty", "json", "dot"])
+                .help("The format of the output [default: pretty]"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .takes_value(false)
+                .conflicts_with("format")
+                .help("Same as --format json"),
+        )
+        .arg(
+            Arg::with_name("compact")
+                .long("compact")
+                .takes_value(false)
+                .help("Write JSON output on one line"),
+        )
         .arg(
             Arg::with_name("output")
                 .short('o')
                 .long("output")
-                .requires("json")
-                .takes_value(true),
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Write the output to a file instead of standard output"),
+        )
+        .arg(
+            Arg::with_name("position-encoding")
+                .long("position-encoding")
+                .takes_value(true)
+                .possible_values(&["utf8", "utf16", "utf32"])
+                .help("Include syntax node positions in the JSON output, with columns in this encoding"),
+        )
+        .arg(
+            Arg::with_name("include-spans")
+                .long("include-spans")
+                .takes_value(false)
+                .help("Include the spans of syntax nodes and of the syntax nodes owning graph nodes in the JSON output"),
+        )
+        .arg(
+            Arg::with_name("syntax-nodes")
+                .long("syntax-nodes")
+                .takes_value(true)
+                .possible_values(&["id", "span", "text", "full"])
+                .help("How syntax node values are rendered in the JSON output"),
         )
         .arg(
             Arg::with_name("allow-parse-errors")
                 .long("allow-parse-errors")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("no-fancy-errors")
+                .long("no-fancy-errors")
+                .takes_value(false)
+                .help("Display errors of the TSG file as plain text instead of annotated snippets"),
+        )
         .arg(
             Arg::with_name("global")
                 .long("global")
                 .takes_value(true)
-                .action(ArgAction::Append),
+                .value_name("NAME=VALUE")
+                .action(ArgAction::Append)
+                .help("Set a global variable to a string, or to a JSON value with NAME:=JSON"),
+        )
+        .arg(
+            Arg::with_name("global-int")
+                .long("global-int")
+                .takes_value(true)
+                .value_name("NAME=VALUE")
+                .action(ArgAction::Append)
+                .help("Set a global variable to an integer"),
+        )
+        .arg(
+            Arg::with_name("global-bool")
+                .long("global-bool")
+                .takes_value(true)
+                .value_name("NAME=VALUE")
+                .action(ArgAction::Append)
+                .help("Set a global variable to a boolean"),
+        )
+        .arg(
+            Arg::with_name("globals-file")
+                .long("globals-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Set the global variables of a JSON object"),
+        )
+        .arg(
+            Arg::with_name("explain-attr")
+                .long("explain-attr")
+                .takes_value(true)
+                .number_of_values(2)
+                .value_names(&["NODE", "ATTR"])
+                .conflicts_with("lazy")
+                .help("Explain how an attribute of a graph node was computed"),
+        )
+        .arg(
+            Arg::with_name("by-stanza")
+                .long("by-stanza")
+                .takes_value(false)
+                .conflicts_with("lazy")
+                .help("Print the graph nodes, edges, and attributes produced by each stanza"),
+        )
+        .arg(
+            Arg::with_name("match-report")
+                .long("match-report")
+                .takes_value(false)
+                .conflicts_with_all(&["lazy", "format", "json", "by-stanza", "combine", "explain-attr"])
+                .help("Print the matches of each stanza, and the graph nodes they created, as JSON"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .takes_value(false)
+                .conflicts_with("combine")
+                .help(
+                    "Print the resources used by the execution, in total and per stanza, to standard error",
+                ),
+        )
+        .arg(
+            Arg::with_name("report-orphans")
+                .long("report-orphans")
+                .takes_value(false)
+                .help("Warn about graph nodes without attributes or edges, by stanza"),
+        )
+        .arg(
+            Arg::with_name("prune-orphans")
+                .long("prune-orphans")
+                .takes_value(false)
+                .help("Remove graph nodes without attributes or edges from the output"),
+        )
+        .arg(
+            Arg::with_name("edge-schema")
+                .long("edge-schema")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Check the kinds of the endpoints of edges against a JSON edge type schema"),
+        )
+        .arg(
+            Arg::with_name("redact")
+                .long("redact")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Drop or rewrite attribute values using JSON redaction rules before output"),
+        )
+        .arg(
+            Arg::with_name("combine")
+                .long("combine")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "explain-attr",
+                    "position-encoding",
+                    "include-spans",
+                    "syntax-nodes",
+                ])
+                .help("Combine the graphs of several source files into one graph, with file boundaries"),
+        )
+        .arg(
+            Arg::with_name("path-attribute")
+                .long("path-attribute")
+                .takes_value(true)
+                .value_name("NAME")
+                .requires("combine")
+                .help("The attribute that records the source file of every graph node with --combine"),
+        )
+        .arg(
+            Arg::with_name("list-node-kinds")
+                .long("list-node-kinds")
+                .takes_value(true)
+                .value_name("SOURCE")
+                .conflicts_with_all(&["tsg", "source", "recursive"])
+                .help("List the node kinds and fields of the language of a source file"),
         )
         .get_matches();
 
-    let tsg_path = Path::new(matches.value_of("tsg").unwrap());
-    let source_path = Path::new(matches.value_of("source").unwrap());
     let current_dir = std::env::current_dir().unwrap();
+    if let Some(source_path) = matches.value_of("list-node-kinds") {
+        let language = load_language(
+            Path::new(source_path),
+            &current_dir,
+            matches.value_of("scope"),
+        )?;
+        print!("{}", list_node_kinds(language));
+        return Ok(());
+    }
+
+    let tsg_path = Path::new(matches.value_of("tsg").unwrap());
+    let error_format = if matches.is_present("no-fancy-errors") {
+        ErrorFormat::Plain
+    } else {
+        ErrorFormat::Fancy {
+            colored: std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal(),
+        }
+    };
+    if matches.is_present("check-only") {
+        // the language is selected by scope, or else by the first source file
+        let scope = matches.value_of("scope");
+        let language_path = match matches.value_of("source") {
+            Some(source_path) => Path::new(source_path),
+            None if scope.is_some() => tsg_path,
+            None => {
+                return Err(anyhow!(
+                    "--check-only requires --scope or a source file to select the language"
+                ))
+            }
+        };
+        let language = load_language(language_path, &current_dir, scope)?;
+        let (_, file) = read_tsg_file(tsg_path, language, error_format)?;
+        for warning in file.check_warnings(None) {
+            eprintln!("Warning: {}", warning);
+        }
+        return Ok(());
+    }
+    let mut source_paths = matches
+        .values_of("source")
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if let Some(dir) = matches.value_of("recursive") {
+        let extensions = matches.value_of("include-ext").map(|extensions| {
+            extensions
+                .split(',')
+                .map(|extension| extension.trim().trim_start_matches('.'))
+                .collect::<Vec<_>>()
+        });
+        walk_source_files(Path::new(dir), extensions.as_deref(), &mut source_paths)?;
+        if source_paths.is_empty() {
+            return Err(anyhow!("No source files found in {}", dir));
+        }
+    }
+    let combine = matches.is_present("combine");
+    // without --combine, several source files are executed one by one, with a graph for each
+    let per_file = !combine && (source_paths.len() > 1 || matches.is_present("recursive"));
+    for flag in ["output-suffix", "fail-fast"] {
+        if matches.is_present(flag) && !per_file {
+            return Err(anyhow!("--{} requires several source files", flag));
+        }
+    }
+    let source_path = source_paths[0].as_path();
+    let match_report = matches.is_present("match-report");
+    let format = match matches.value_of("format") {
+        Some("json") => OutputFormat::Json,
+        Some("dot") => OutputFormat::Dot,
+        Some(_) => OutputFormat::Pretty,
+        None if matches.is_present("json") || match_report || per_file => OutputFormat::Json,
+        None => OutputFormat::Pretty,
+    };
+    if per_file && format != OutputFormat::Json {
+        return Err(anyhow!("Several source files require --format json"));
+    }
+    for json_flag in [
+        "compact",
+        "position-encoding",
+        "include-spans",
+        "syntax-nodes",
+        "combine",
+    ] {
+        if matches.is_present(json_flag) && format != OutputFormat::Json {
+            return Err(anyhow!("--{} requires --format json", json_flag));
+        }
+    }
+    let compact = matches.is_present("compact");
+    let output_path = matches.value_of("output").map(Path::new);
+    if let Some(output_path) = output_path {
+        check_output_directory(output_path)?;
+    }
     let quiet = matches.is_present("quiet");
     let lazy = matches.is_present("lazy");
-    let globals = matches.get_many::<String>("global").unwrap_or_default();
-    let mut globals_ = Variables::new();
-    for kv in globals {
-        let kv_ = kv
-            .split_once('=')
-            .with_context(|| format!("Expected key-value pair separated by '=', got {}.", kv))?;
-        globals_.add(
-            Identifier::from(kv_.0),
-            graph::Value::String(kv_.1.to_string()),
-        )?;
+    let explain_attr = matches
+        .values_of("explain-attr")
+        .map(|values| values.collect::<Vec<_>>());
+    let by_stanza = matches.is_present("by-stanza");
+    if by_stanza && format != OutputFormat::Pretty {
+        return Err(anyhow!("--by-stanza requires --format pretty"));
+    }
+    if per_file && (explain_attr.is_some() || by_stanza || match_report) {
+        return Err(anyhow!(
+            "--explain-attr, --by-stanza, and --match-report require a single source file"
+        ));
+    }
+    let stats = matches.is_present("stats");
+    let report_orphans = matches.is_present("report-orphans");
+    let prune_orphans = matches.is_present("prune-orphans");
+    let edge_schema = match matches.value_of("edge-schema") {
+        Some(schema_path) => {
+            let schema = std::fs::read_to_string(schema_path)
+                .with_context(|| format!("Cannot read edge type schema {}", schema_path))?;
+            let schema = EdgeTypeSchema::from_json(&schema)
+                .with_context(|| format!("Cannot parse edge type schema {}", schema_path))?;
+            Some(schema)
+        }
+        None => None,
+    };
+    let redaction_rules = match matches.value_of("redact") {
+        Some(rules_path) => {
+            let rules = std::fs::read_to_string(rules_path)
+                .with_context(|| format!("Cannot read redaction rules {}", rules_path))?;
+            let rules = RedactionRules::from_json(&rules)
+                .with_context(|| format!("Cannot parse redaction rules {}", rules_path))?;
+            Some(rules)
+        }
+        None => None,
+    };
+    let globals_file = matches.value_of("globals-file");
+    let mut globals_ = match globals_file {
+        Some(globals_path) => {
+            let globals = std::fs::read_to_string(globals_path)
+                .with_context(|| format!("Cannot read global variables {}", globals_path))?;
+            Variables::from_json(&globals)
+                .with_context(|| format!("Cannot parse global variables {}", globals_path))?
+        }
+        None => Variables::new(),
+    };
+    for flag in ["global", "global-int", "global-bool"] {
+        for kv in matches.get_many::<String>(flag).unwrap_or_default() {
+            let (name, value) = parse_global(flag, kv)?;
+            if globals_.get(&name).is_some() {
+                return Err(match globals_file {
+                    Some(globals_path) => anyhow!(
+                        "Global variable {} is set both in {} and with --{}",
+                        name,
+                        globals_path,
+                        flag
+                    ),
+                    None => anyhow!("Global variable {} is set more than once", name),
+                });
+            }
+            globals_.add(name, value)?;
+        }
     }
 
-    let config = Config::load()?;
-    let mut loader = Loader::new()?;
-    let loader_config = config.get()?;
-    loader.find_all_languages(&loader_config)?;
-    let language = loader.select_language(source_path, &current_dir, matches.value_of("scope"))?;
+    let language = load_language(source_path, &current_dir, matches.value_of("scope"))?;
+    let (tsg, file) = read_tsg_file(tsg_path, language, error_format)?;
+    for warning in file.check_warnings(Some(&globals_)) {
+        eprintln!("Warning: {}", warning);
+    }
 
-    let tsg = std::fs::read(tsg_path)
-        .with_context(|| format!("Cannot read TSG file {}", tsg_path.display()))?;
-    let tsg = String::from_utf8(tsg)?;
-    let file = match File::from_str(language, &tsg) {
-        Ok(file) => file,
-        Err(err) => {
-            eprintln!("{}", err.display_pretty(tsg_path, &tsg));
-            return Err(anyhow!("Cannot parse TSG file {}", tsg_path.display()));
+    let allow_parse_errors = matches.is_present("allow-parse-errors");
+    let functions = Functions::stdlib();
+    let options = ExecutionOptions {
+        lazy,
+        provenance: explain_attr.is_some()
+            || by_stanza
+            || match_report
+            || report_orphans
+            || prune_orphans,
+        report_orphans,
+        prune_orphans,
+    };
+
+    if combine {
+        let path_attribute = matches
+            .value_of("path-attribute")
+            .unwrap_or(DEFAULT_SOURCE_PATH_ATTRIBUTE);
+        let mut combined = CombinedGraph::with_path_attribute(Identifier::from(path_attribute));
+        let sources = source_paths
+            .iter()
+            .map(std::fs::read_to_string)
+            .collect::<Vec<_>>();
+        let mut parser = Parser::new();
+        parser.set_language(language)?;
+        let trees = sources
+            .iter()
+            .map(|source| match source {
+                Ok(source) => parser.parse(source, None),
+                Err(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let mut edge_type_violations = Vec::new();
+        for ((path, source), tree) in source_paths.iter().zip(&sources).zip(&trees) {
+            let (source, tree) = match (source, tree) {
+                (Err(e), _) => {
+                    combined.add_failure(path, format!("Cannot read source file: {}", e));
+                    continue;
+                }
+                (Ok(_), None) => {
+                    combined.add_failure(path, "Cannot parse source file");
+                    continue;
+                }
+                (Ok(source), Some(tree)) => (source, tree),
+            };
+            if !allow_parse_errors {
+                if let Some(parse_error) = ParseError::first(tree) {
+                    combined.add_failure(path, parse_error.display(path, source));
+                    continue;
+                }
+            }
+            let file_globals = file_globals(&globals_, path);
+            let config = options.config(&functions, &file_globals);
+            let mut graph = match file.execute(tree, source, &config, &NoCancellation) {
+                Ok(graph) => graph,
+                Err(e) => {
+                    combined.add_failure(path, e);
+                    continue;
+                }
+            };
+            if let Some(report) = graph.orphan_report() {
+                for group in &report.groups {
+                    eprintln!("Warning: {}: {}", path.display(), group);
+                }
+            }
+            // edge kinds are checked against the graph as it was produced, before any redaction
+            if let Some(schema) = &edge_schema {
+                for violation in graph.validate_edge_types(schema) {
+                    edge_type_violations.push(format!("{}: {}", path.display(), violation));
+                }
+            }
+            if let Some(redaction_rules) = &redaction_rules {
+                graph.redact(redaction_rules);
+            }
+            // a file whose graph cannot be added is recorded as a failure
+            let _ = combined.add_file(path, graph);
         }
+        for failure in combined.failures() {
+            eprintln!(
+                "Warning: skipped {}: {}",
+                failure.path.display(),
+                failure.error
+            );
+        }
+        write_output(output_path, &to_json(&combined, compact))?;
+        if !edge_type_violations.is_empty() {
+            for violation in &edge_type_violations {
+                eprintln!("Error: {}", violation);
+            }
+            std::io::stdout().flush()?;
+            std::process::exit(EDGE_TYPE_VIOLATIONS_EXIT_CODE);
+        }
+        return Ok(());
+    }
+
+    let serialization = SerializationOptions {
+        position_encoding: matches
+            .value_of("position-encoding")
+            .map(|encoding| match encoding {
+                "utf16" => PositionEncoding::Utf16,
+                "utf32" => PositionEncoding::Utf32,
+                _ => PositionEncoding::Utf8,
+            }),
+        include_spans: matches.is_present("include-spans"),
+        syntax_node_rendering: matches
+            .value_of("syntax-nodes")
+            .map(|rendering| match rendering {
+                "span" => SyntaxNodeRendering::Span,
+                "text" => SyntaxNodeRendering::SourceText,
+                "full" => SyntaxNodeRendering::Full,
+                _ => SyntaxNodeRendering::Id,
+            }),
     };
 
+    if per_file {
+        let output_suffix = matches.value_of("output-suffix");
+        let fail_fast = matches.is_present("fail-fast");
+        let mut parser = Parser::new();
+        parser.set_language(language)?;
+        let mut json_lines = String::new();
+        let mut failures = 0;
+        let mut edge_type_violations = Vec::new();
+        let mut execute_source_file = |path: &Path| -> Result<String> {
+            let source = std::fs::read_to_string(path)
+                .with_context(|| format!("Cannot read source file {}", path.display()))?;
+            let tree = parser
+                .parse(&source, None)
+                .ok_or_else(|| anyhow!("Cannot parse {}", path.display()))?;
+            if !allow_parse_errors {
+                if let Some(parse_error) = ParseError::first(&tree) {
+                    return Err(anyhow!("{}", parse_error.display(path, &source)));
+                }
+            }
+            let file_globals = file_globals(&globals_, path);
+            let config = options.config(&functions, &file_globals);
+            let mut graph = file
+                .execute(&tree, &source, &config, &NoCancellation)
+                .map_err(|e| match error_format {
+                    ErrorFormat::Plain => {
+                        anyhow!("{}", e.display_pretty(path, &source, tsg_path, &tsg))
+                    }
+                    ErrorFormat::Fancy { .. } => {
+                        eprintln!(
+                            "{}",
+                            error_format.execution_error(&e, path, &source, tsg_path, &tsg)
+                        );
+                        anyhow!("Cannot execute TSG file on {}", path.display())
+                    }
+                })?;
+            if let Some(report) = graph.orphan_report() {
+                for group in &report.groups {
+                    eprintln!("Warning: {}: {}", path.display(), group);
+                }
+            }
+            // edge kinds are checked against the graph as it was produced, before any redaction
+            if let Some(schema) = &edge_schema {
+                for violation in graph.validate_edge_types(schema) {
+                    edge_type_violations.push(format!("{}: {}", path.display(), violation));
+                }
+            }
+            if let Some(redaction_rules) = &redaction_rules {
+                graph.redact(redaction_rules);
+            }
+            Ok(match output_suffix {
+                Some(_) => serialization.to_json(&graph, &source, compact),
+                None => format!(
+                    "{{\"path\":{},\"graph\":{}}}",
+                    to_json(&path.display().to_string(), true),
+                    serialization.to_json(&graph, &source, true)
+                ),
+            })
+        };
+        for path in &source_paths {
+            match execute_source_file(path) {
+                Ok(json) => match output_suffix {
+                    Some(suffix) => {
+                        let mut output_path = path.as_os_str().to_owned();
+                        output_path.push(suffix);
+                        write_output(Some(Path::new(&output_path)), &json)?;
+                    }
+                    None if output_path.is_some() => {
+                        json_lines += &json;
+                        json_lines += "\n";
+                    }
+                    None => println!("{}", json),
+                },
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    failures += 1;
+                    if fail_fast {
+                        break;
+                    }
+                }
+            }
+        }
+        if output_path.is_some() {
+            write_output(output_path, &json_lines)?;
+        }
+        for violation in &edge_type_violations {
+            eprintln!("Error: {}", violation);
+        }
+        if failures > 0 {
+            return Err(anyhow!(
+                "{} of {} source files failed",
+                failures,
+                source_paths.len()
+            ));
+        }
+        if !edge_type_violations.is_empty() {
+            std::io::stdout().flush()?;
+            std::process::exit(EDGE_TYPE_VIOLATIONS_EXIT_CODE);
+        }
+        return Ok(());
+    }
+
     let source = std::fs::read(source_path)
         .with_context(|| format!("Cannot read source file {}", source_path.display()))?;
     let source = String::from_utf8(source)?;
@@ -114,7 +683,6 @@ fn main() -> Result<()> {
     let tree = parser
         .parse(&source, None)
         .ok_or_else(|| anyhow!("Cannot parse {}", source_path.display()))?;
-    let allow_parse_errors = matches.is_present("allow-parse-errors");
     if !allow_parse_errors {
         let parse_errors = ParseError::all(&tree);
         if !parse_errors.is_empty() {
@@ -133,27 +701,399 @@ fn main() -> Result<()> {
         }
     }
 
-    let functions = Functions::stdlib();
-    let mut config = ExecutionConfig::new(&functions, &globals_).lazy(lazy);
-    let graph = match file.execute(&tree, &source, &mut config, &NoCancellation) {
+    let file_globals = file_globals(&globals_, source_path);
+    let config = options.config(&functions, &file_globals);
+    let result = if stats {
+        file.execute_with_usage(&tree, &source, &config, &NoCancellation)
+            .map(|(graph, usage)| {
+                eprintln!("{}", usage);
+                eprintln!("{}", usage.statistics);
+                graph
+            })
+    } else {
+        file.execute(&tree, &source, &config, &NoCancellation)
+    };
+    let mut graph = match result {
         Ok(graph) => graph,
         Err(e) => {
-            eprintln!("{}", e.display_pretty(source_path, &source, tsg_path, &tsg));
+            eprintln!(
+                "{}",
+                error_format.execution_error(&e, source_path, &source, tsg_path, &tsg)
+            );
             return Err(anyhow!("Cannot execute TSG file {}", tsg_path.display()));
         }
     };
 
-    let json = matches.is_present("json");
-    let output_path = matches.value_of("output").map(|str| Path::new(str));
-    if json {
-        graph.display_json(output_path).unwrap_or(());
-    } else if !quiet {
-        print!("{}", graph.pretty_print());
+    if let Some(report) = graph.orphan_report() {
+        for group in &report.groups {
+            eprintln!("Warning: {}", group);
+        }
+    }
+
+    // edge kinds are checked against the graph as it was produced, before any redaction
+    let edge_type_violations = edge_schema.map(|schema| graph.validate_edge_types(&schema));
+    if let Some(redaction_rules) = redaction_rules {
+        graph.redact(&redaction_rules);
+    }
+
+    if match_report {
+        let report = file.match_report(&tree, &source, Some(&graph));
+        write_output(output_path, &to_json(&report, compact))?;
+    } else if format == OutputFormat::Json {
+        write_output(
+            output_path,
+            &serialization.to_json(&graph, &source, compact),
+        )?;
+    } else if format == OutputFormat::Dot {
+        write_output(output_path, &graph.display_dot().to_string())?;
+    } else if by_stanza {
+        write_output(
+            output_path,
+            &graph.pretty_print_by_stanza(&file).to_string(),
+        )?;
+    } else if !quiet || output_path.is_some() {
+        write_output(output_path, &graph.pretty_print().to_string())?;
+    }
+
+    if let Some(explain_attr) = explain_attr {
+        let (index, attr) = (explain_attr[0], explain_attr[1]);
+        let node = index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| graph.iter_nodes().find(|node| node.index() == index))
+            .ok_or_else(|| anyhow!("No graph node {}", index))?;
+        match graph.explain_attribute(node, attr) {
+            Some(explanation) => print!("{}", explanation),
+            None => {
+                return Err(anyhow!(
+                    "No attribute {} on graph node {} to explain",
+                    attr,
+                    index
+                ))
+            }
+        }
+    }
+
+    if let Some(violations) = edge_type_violations {
+        if !violations.is_empty() {
+            for violation in &violations {
+                eprintln!("Error: {}", violation);
+            }
+            std::io::stdout().flush()?;
+            std::process::exit(EDGE_TYPE_VIOLATIONS_EXIT_CODE);
+        }
+    }
+
+    Ok(())
+}
+
+/// The options of the execution of the TSG file, which is configured separately for every source
+/// file, since every source file has its own global variables
+struct ExecutionOptions {
+    lazy: bool,
+    provenance: bool,
+    report_orphans: bool,
+    prune_orphans: bool,
+}
+
+impl ExecutionOptions {
+    fn config<'a, 'g>(
+        &self,
+        functions: &'a Functions,
+        globals: &'a Variables<'g>,
+    ) -> ExecutionConfig<'a, 'g> {
+        ExecutionConfig::new(functions, globals)
+            .lazy(self.lazy)
+            .provenance(self.provenance)
+            .report_orphans(self.report_orphans)
+            .prune_orphans(self.prune_orphans)
+    }
+}
+
+/// The options of the JSON serialization of graphs
+struct SerializationOptions {
+    position_encoding: Option<PositionEncoding>,
+    include_spans: bool,
+    syntax_node_rendering: Option<SyntaxNodeRendering>,
+}
+
+impl SerializationOptions {
+    /// Serializes a graph as JSON.  Syntax node positions are only included if any of the
+    /// serialization flags is given.
+    fn to_json(&self, graph: &Graph, source: &str, compact: bool) -> String {
+        if self.position_encoding.is_none()
+            && !self.include_spans
+            && self.syntax_node_rendering.is_none()
+        {
+            return to_json(graph, compact);
+        }
+        let config = SerializationConfig::new(source)
+            .position_encoding(self.position_encoding.unwrap_or(PositionEncoding::Utf8))
+            .include_spans(self.include_spans)
+            .syntax_node_rendering(self.syntax_node_rendering.unwrap_or_default());
+        to_json(&graph.serialize_with(&config), compact)
+    }
+}
+
+/// Parses the value of a `--global`, `--global-int`, or `--global-bool` flag, which sets a global
+/// variable as `NAME=VALUE`.  A `--global` flag sets a string, unless it is given as
+/// `NAME:=JSON`, which sets the value of a JSON value.
+fn parse_global(flag: &str, kv: &str) -> Result<(Identifier, graph::Value)> {
+    let (name, value) = kv
+        .split_once('=')
+        .with_context(|| format!("Expected key-value pair separated by '=', got {}.", kv))?;
+    let value = match (flag, name.strip_suffix(':')) {
+        ("global", Some(json_name)) => {
+            let value = graph::Value::from_json(value).with_context(|| {
+                format!("Cannot parse JSON value of global variable {}", json_name)
+            })?;
+            return Ok((Identifier::from(json_name), value));
+        }
+        ("global-int", _) => graph::Value::Integer(value.parse().with_context(|| {
+            format!(
+                "Expected an integer for global variable {}, got {}",
+                name, value
+            )
+        })?),
+        ("global-bool", _) => graph::Value::Boolean(value.parse().with_context(|| {
+            format!(
+                "Expected true or false for global variable {}, got {}",
+                name, value
+            )
+        })?),
+        _ => graph::Value::String(value.to_string()),
+    };
+    Ok((Identifier::from(name), value))
+}
+
+/// Returns the global variables for a source file: the ones given with `--global`, and the path
+/// of the source file as `FILE_PATH`, unless it is given with `--global`.
+fn file_globals<'a>(globals: &'a Variables<'a>, path: &Path) -> Variables<'a> {
+    let mut file_globals = Variables::nested(globals);
+    let name = Identifier::from(FILE_PATH_GLOBAL);
+    if globals.get(&name).is_none() {
+        file_globals
+            .add(name, graph::Value::String(path.display().to_string()))
+            .expect("FILE_PATH is not defined yet");
     }
+    file_globals
+}
 
+/// Adds the files in a directory and its subdirectories to `paths`, in order of their paths.
+/// Hidden files and directories, whose names start with `.`, and symbolic links are skipped.  If
+/// extensions are given, only files with one of them are added.
+fn walk_source_files(
+    dir: &Path,
+    extensions: Option<&[&str]>,
+    paths: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))))
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .with_context(|| format!("Cannot read directory {}", dir.display()))?;
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, file_type) in entries {
+        let hidden = path
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk_source_files(&path, extensions, paths)?;
+        } else if file_type.is_file() {
+            let included = match extensions {
+                Some(extensions) => path.extension().map_or(false, |extension| {
+                    extensions.iter().any(|included| extension == *included)
+                }),
+                None => true,
+            };
+            if included {
+                paths.push(path);
+            }
+        }
+    }
     Ok(())
 }
 
+/// The formats in which the graph can be output
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum OutputFormat {
+    Pretty,
+    Json,
+    Dot,
+}
+
+/// Loads the language with the given scope, or else the language of the given source file.
+fn load_language(source_path: &Path, current_dir: &Path, scope: Option<&str>) -> Result<Language> {
+    let config = Config::load()?;
+    let mut loader = Loader::new()?;
+    let loader_config = config.get()?;
+    loader.find_all_languages(&loader_config)?;
+    loader.select_language(source_path, current_dir, scope)
+}
+
+/// Reads and parses a TSG file, which also checks it, returning its content and the parsed file.
+/// Parse errors are displayed in the given format.
+fn read_tsg_file(
+    tsg_path: &Path,
+    language: Language,
+    error_format: ErrorFormat,
+) -> Result<(String, File)> {
+    let tsg = std::fs::read(tsg_path)
+        .with_context(|| format!("Cannot read TSG file {}", tsg_path.display()))?;
+    let tsg = String::from_utf8(tsg)?;
+    match File::from_str(language, &tsg) {
+        Ok(file) => Ok((tsg, file)),
+        Err(err) => {
+            eprintln!("{}", error_format.parse_error(&err, tsg_path, &tsg));
+            Err(anyhow!("Cannot parse TSG file {}", tsg_path.display()))
+        }
+    }
+}
+
+/// How errors of the TSG file are displayed
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    /// Plain text, as displayed by the errors themselves
+    Plain,
+    /// Annotated snippets of the TSG file and the source file
+    Fancy { colored: bool },
+}
+
+impl ErrorFormat {
+    fn parse_error(
+        self,
+        error: &tree_sitter_graph::ParseError,
+        tsg_path: &Path,
+        tsg: &str,
+    ) -> String {
+        match self {
+            ErrorFormat::Plain => error.display_pretty(tsg_path, tsg).to_string(),
+            ErrorFormat::Fancy { colored } => Diagnostic::from_parse_error(error, tsg)
+                .tsg_path(tsg_path)
+                .colored(colored)
+                .to_string(),
+        }
+    }
+
+    fn execution_error(
+        self,
+        error: &ExecutionError,
+        source_path: &Path,
+        source: &str,
+        tsg_path: &Path,
+        tsg: &str,
+    ) -> String {
+        match self {
+            ErrorFormat::Plain => error
+                .display_pretty(source_path, source, tsg_path, tsg)
+                .to_string(),
+            ErrorFormat::Fancy { colored } => Diagnostic::from_execution_error(error, tsg, source)
+                .tsg_path(tsg_path)
+                .source_path(source_path)
+                .colored(colored)
+                .to_string(),
+        }
+    }
+}
+
+/// Serializes a value as JSON, pretty-printed unless `compact` is set.
+fn to_json<T: Serialize>(value: &T, compact: bool) -> String {
+    let json = if compact {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    };
+    json.expect("Cannot serialize output as JSON")
+}
+
+/// Checks that the directory of an output file exists, so that a missing directory is reported
+/// before the graph is built, instead of after.
+fn check_output_directory(path: &Path) -> Result<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    if !directory.is_dir() {
+        return Err(anyhow!(
+            "Cannot write output to {}: directory {} does not exist",
+            path.display(),
+            directory.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the output to a file, or to standard output if no path is given.  A file is written
+/// to a temporary file in the same directory first, which is then renamed, so that a failure
+/// while writing never leaves a truncated file behind.
+fn write_output(path: Option<&Path>, contents: &str) -> Result<()> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            std::io::stdout().write_all(contents.as_bytes())?;
+            return Ok(());
+        }
+    };
+    check_output_directory(path)?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot write output to {}: not a file", path.display()))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    std::fs::write(&temp_path, contents)
+        .with_context(|| format!("Cannot write output to {}", temp_path.display()))?;
+    if let Err(e) = std::fs::rename(&temp_path, path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e).with_context(|| format!("Cannot write output to {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Lists the named and anonymous node kinds and the fields of a language, which are the names
+/// that can be used in the queries of stanzas.
+fn list_node_kinds(language: Language) -> String {
+    let mut named = BTreeSet::new();
+    let mut anonymous = BTreeSet::new();
+    for id in 0..language.node_kind_count() as u16 {
+        if !language.node_kind_is_visible(id) {
+            continue;
+        }
+        if let Some(kind) = language.node_kind_for_id(id) {
+            if language.node_kind_is_named(id) {
+                named.insert(kind);
+            } else {
+                anonymous.insert(kind);
+            }
+        }
+    }
+    // Field IDs start at 1.
+    let fields = (1..=language.field_count() as u16)
+        .filter_map(|id| language.field_name_for_id(id))
+        .collect::<BTreeSet<_>>();
+    let mut result = String::new();
+    result += "node kinds:\n";
+    for kind in named {
+        result += &format!("  {}\n", kind);
+    }
+    result += "anonymous node kinds:\n";
+    for kind in anonymous {
+        result += &format!("  {:?}\n", kind);
+    }
+    result += "fields:\n";
+    for field in fields {
+        result += &format!("  {}:\n", field);
+    }
+    result
+}
+
 fn init_log() {
     let _ = env_logger::builder()
         .format_level(false)
@@ -0,0 +1,119 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Executing a graph DSL file against many source files in parallel, with [`rayon`][]
+//!
+//! [`rayon`]: https://docs.rs/rayon/
+
+use std::path::PathBuf;
+
+use rayon::iter::IntoParallelIterator;
+use rayon::iter::ParallelIterator;
+use thiserror::Error;
+use tree_sitter::Language;
+use tree_sitter::LanguageError;
+use tree_sitter::Parser;
+
+use crate::ast::File;
+use crate::functions::Functions;
+use crate::graph::Graph;
+use crate::CancellationFlag;
+use crate::ExecutionConfig;
+use crate::ExecutionError;
+use crate::Variables;
+
+/// A source file to execute a graph DSL file against, with [`execute_many`][]
+#[derive(Clone, Debug)]
+pub struct SourceInput {
+    /// The path of the source file.  It identifies the file, and is not read.
+    pub path: PathBuf,
+    /// The source text of the file
+    pub source: String,
+}
+
+impl SourceInput {
+    pub fn new(path: impl Into<PathBuf>, source: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// An error that can occur when executing a graph DSL file against one of the source files given
+/// to [`execute_many`][]
+#[derive(Debug, Error)]
+pub enum BulkError {
+    #[error("Cannot parse {0}")]
+    Parse(PathBuf),
+    #[error(transparent)]
+    Execution(#[from] ExecutionError),
+}
+
+/// Parses source files and executes a graph DSL file against each of them, in parallel on the
+/// [`rayon`][] thread pool.  Returns the result for every source file, in the order of the inputs.
+///
+/// Every worker thread parses with its own parser.  The compiled file and the functions are
+/// shared by all of them.  For every source file, `config_factory` is given empty global
+/// variables to fill in, like the path of the file, and returns the configuration to execute the
+/// file with.
+///
+/// A graph borrows the syntax tree it was created from, which is dropped once the source file has
+/// been executed.  The `output` function therefore turns every graph into the result that is
+/// kept, like its JSON representation or the values of some attributes.
+///
+/// Once the cancellation flag is set, running executions are cancelled, and the source files that
+/// were not started yet fail with [`ExecutionError::Cancelled`][] without being parsed.
+///
+/// Returns an error if the parsers cannot use `language`.
+///
+/// [`rayon`]: https://docs.rs/rayon/
+pub fn execute_many<I, C, O, R>(
+    file: &File,
+    language: Language,
+    inputs: I,
+    functions: &Functions,
+    config_factory: C,
+    cancellation_flag: &(dyn CancellationFlag + Sync),
+    output: O,
+) -> Result<Vec<Result<R, BulkError>>, LanguageError>
+where
+    I: IntoParallelIterator<Item = SourceInput>,
+    C: for<'c> Fn(
+            &SourceInput,
+            &'c Functions,
+            &'c mut Variables<'static>,
+        ) -> ExecutionConfig<'c, 'static>
+        + Sync,
+    O: Fn(&SourceInput, Graph) -> R + Sync,
+    R: Send,
+{
+    Parser::new().set_language(language)?;
+    let results = inputs
+        .into_par_iter()
+        .map_init(
+            || {
+                let mut parser = Parser::new();
+                parser.set_language(language).expect("language was checked");
+                parser
+            },
+            |parser, input| {
+                cancellation_flag
+                    .check("scheduling source files")
+                    .map_err(ExecutionError::from)?;
+                let tree = parser
+                    .parse(&input.source, None)
+                    .ok_or_else(|| BulkError::Parse(input.path.clone()))?;
+                let mut globals = Variables::new();
+                let config = config_factory(&input, functions, &mut globals);
+                let graph = file.execute(&tree, &input.source, &config, cancellation_flag)?;
+                Ok(output(&input, graph))
+            },
+        )
+        .collect();
+    Ok(results)
+}
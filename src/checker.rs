@@ -5,6 +5,7 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::Path;
 
@@ -12,13 +13,17 @@ use thiserror::Error;
 use tree_sitter::CaptureQuantifier;
 use tree_sitter::CaptureQuantifier::One;
 use tree_sitter::CaptureQuantifier::OneOrMore;
+use tree_sitter::CaptureQuantifier::Zero;
 use tree_sitter::CaptureQuantifier::ZeroOrMore;
 use tree_sitter::CaptureQuantifier::ZeroOrOne;
 use tree_sitter::Query;
 
 use crate::ast;
+use crate::functions::Functions;
+use crate::graph::Value;
 use crate::parse_error::Excerpt;
 use crate::parser::FULL_MATCH;
+use crate::regex_engine::RegexEngine;
 use crate::variables::MutVariables;
 use crate::variables::VariableError;
 use crate::variables::VariableMap;
@@ -32,16 +37,36 @@ pub enum CheckError {
     CannotHideGlobalVariable(String, Location),
     #[error("Cannot set global variable {0} at {1}")]
     CannotSetGlobalVariable(String, Location),
+    #[error("Cannot set scoped variable {0} at {1}, which is only declared with let")]
+    CannotSetImmutableScopedVariable(String, Location),
     #[error("Duplicate global variable {0} at {1}")]
     DuplicateGlobalVariable(String, Location),
+    #[error("Expected constant value for attribute {0} at {1}")]
+    ExpectedConstantValue(String, Location),
     #[error("Expected list value at {0}")]
     ExpectedListValue(Location),
     #[error("Expected local value at {0}")]
     ExpectedLocalValue(Location),
     #[error("Expected optional value at {0}")]
     ExpectedOptionalValue(Location),
+    #[error("Function {0} expects a capture quantified with * or +, but @{1} is not at {2}")]
+    ExpectedQuantifiedCapture(String, String, Location),
+    #[error("Attribute {0} is set twice at {3}, by {1} and by {2}")]
+    ConflictingShorthandAttribute(String, String, String, Location),
+    #[error("Cannot mix graph node and edge targets in attr statement at {0}")]
+    MixedAttributeTargets(Location),
     #[error("Nullable regular expression /{0}/ at {1}")]
     NullableRegex(String, Location),
+    #[error("Attribute shorthand {0} at {1} expands to itself")]
+    RecursiveShorthand(String, Location),
+    #[error("Scoped variable {0} is read before the phase in which it is set at {1}")]
+    ScopedVariableSetInLaterPhase(String, Location),
+    #[error("Cannot set scoped variable {0} at {1}, which is never declared")]
+    UndeclaredScopedVariable(String, Location),
+    #[error("Undefined regex capture {0} at {1}")]
+    UndefinedRegexCapture(String, Location),
+    #[error("Undefined node type {0} at {1}")]
+    UndefinedNodeType(String, Location),
     #[error("Undefined syntax capture @{0} at {1}")]
     UndefinedSyntaxCapture(String, Location),
     #[error("Undefined variable {0} at {1}")]
@@ -50,9 +75,77 @@ pub enum CheckError {
     UnusedCaptures(String, Location),
     #[error("{0}: {1} at {2}")]
     Variable(VariableError, String, Location),
+    #[error("Function {0} expects {1} arguments, got {2} at {3}")]
+    WrongArity(String, String, usize, Location),
+}
+
+/// A warning about a graph DSL file.  Unlike a [`CheckError`][], a warning does not prevent the
+/// file from being executed.  Warnings are reported by
+/// [`File::check_warnings`][crate::ast::File::check_warnings].
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum CheckWarning {
+    /// A capture has the same name as a global variable.  The fields are the name, the location of
+    /// the stanza, and the location of the global declaration, if the global is declared.
+    #[error("Capture @{0} in stanza at {1} has the same name as global variable {0}{}", declared_at(.2))]
+    CaptureShadowsGlobal(String, Location, Option<Location>),
+    /// A local variable has the same name as a global variable that the host provides.  The
+    /// fields are the name, the location of the local variable, and the location of the global
+    /// declaration, if the global is declared.
+    #[error("Local variable {0} at {1} shadows global variable {0}{}", declared_at(.2))]
+    LocalShadowsGlobal(String, Location, Option<Location>),
+    /// A local variable has the same name as a local variable of an enclosing block.  The fields
+    /// are the name, the location of the inner variable, and the location of the outer one.
+    #[error("Local variable {0} at {1} shadows local variable {0} declared at {2}")]
+    LocalShadowsLocal(String, Location, Location),
+}
+
+fn declared_at(location: &Option<Location>) -> String {
+    match location {
+        Some(location) => format!(" declared at {}", location),
+        None => String::new(),
+    }
+}
+
+impl CheckWarning {
+    /// Returns the location that the warning is reported at.
+    pub fn location(&self) -> Location {
+        match self {
+            CheckWarning::CaptureShadowsGlobal(_, location, _) => *location,
+            CheckWarning::LocalShadowsGlobal(_, location, _) => *location,
+            CheckWarning::LocalShadowsLocal(_, location, _) => *location,
+        }
+    }
 }
 
 impl CheckError {
+    /// Returns the location that the error is reported at.
+    pub fn location(&self) -> Location {
+        match self {
+            CheckError::CannotHideGlobalVariable(_, location) => *location,
+            CheckError::CannotSetGlobalVariable(_, location) => *location,
+            CheckError::CannotSetImmutableScopedVariable(_, location) => *location,
+            CheckError::DuplicateGlobalVariable(_, location) => *location,
+            CheckError::ExpectedConstantValue(_, location) => *location,
+            CheckError::ExpectedListValue(location) => *location,
+            CheckError::ExpectedLocalValue(location) => *location,
+            CheckError::ExpectedOptionalValue(location) => *location,
+            CheckError::ExpectedQuantifiedCapture(_, _, location) => *location,
+            CheckError::ConflictingShorthandAttribute(_, _, _, location) => *location,
+            CheckError::MixedAttributeTargets(location) => *location,
+            CheckError::NullableRegex(_, location) => *location,
+            CheckError::RecursiveShorthand(_, location) => *location,
+            CheckError::ScopedVariableSetInLaterPhase(_, location) => *location,
+            CheckError::UndeclaredScopedVariable(_, location) => *location,
+            CheckError::UndefinedRegexCapture(_, location) => *location,
+            CheckError::UndefinedNodeType(_, location) => *location,
+            CheckError::UndefinedSyntaxCapture(_, location) => *location,
+            CheckError::UndefinedVariable(_, location) => *location,
+            CheckError::UnusedCaptures(_, location) => *location,
+            CheckError::Variable(_, _, location) => *location,
+            CheckError::WrongArity(_, _, _, location) => *location,
+        }
+    }
+
     pub fn display_pretty<'a>(
         &'a self,
         path: &'a Path,
@@ -74,19 +167,7 @@ struct DisplayCheckErrorPretty<'a> {
 
 impl std::fmt::Display for DisplayCheckErrorPretty<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let location = match self.error {
-            CheckError::CannotHideGlobalVariable(_, location) => *location,
-            CheckError::CannotSetGlobalVariable(_, location) => *location,
-            CheckError::DuplicateGlobalVariable(_, location) => *location,
-            CheckError::ExpectedListValue(location) => *location,
-            CheckError::ExpectedLocalValue(location) => *location,
-            CheckError::ExpectedOptionalValue(location) => *location,
-            CheckError::NullableRegex(_, location) => *location,
-            CheckError::UndefinedSyntaxCapture(_, location) => *location,
-            CheckError::UndefinedVariable(_, location) => *location,
-            CheckError::UnusedCaptures(_, location) => *location,
-            CheckError::Variable(_, _, location) => *location,
-        };
+        let location = self.error.location();
         writeln!(f, "{}", self.error)?;
         write!(
             f,
@@ -105,11 +186,97 @@ impl std::fmt::Display for DisplayCheckErrorPretty<'_> {
 
 /// Checker context
 struct CheckContext<'a> {
+    functions: Option<&'a Functions>,
     globals: &'a dyn Variables<VariableResult>,
+    node_types: &'a HashMap<Identifier, Vec<(Identifier, Value)>>,
+    shorthands: &'a HashMap<Identifier, ShorthandAttributes>,
+    scoped_variables: &'a mut ScopedVariablePhases,
     file_query: &'a Query,
     stanza_index: usize,
     stanza_query: &'a Query,
     locals: &'a mut dyn MutVariables<VariableResult>,
+    /// The names of the capture groups of the `scan` arm being checked, if any
+    regex_groups: Option<&'a [Option<String>]>,
+}
+
+/// The node types and attribute shorthands of the file being checked
+struct Declarations<'a> {
+    node_types: &'a HashMap<Identifier, Vec<(Identifier, Value)>>,
+    shorthands: &'a HashMap<Identifier, ShorthandAttributes>,
+}
+
+/// The phases in which scoped variables are set and read, by variable name
+#[derive(Default)]
+struct ScopedVariablePhases {
+    /// The phase of the stanza being checked
+    phase: usize,
+    /// The earliest phase in which each scoped variable is set
+    sets: HashMap<Identifier, usize>,
+    /// The scoped variables that are read, with the phase and location of the read
+    reads: Vec<(Identifier, usize, Location)>,
+    /// Whether any declaration of each scoped variable is mutable
+    declarations: HashMap<Identifier, bool>,
+    /// The scoped variables that are assigned with `set`, with the variable as written and the
+    /// location of the assignment
+    assignments: Vec<(Identifier, String, Location)>,
+}
+
+impl ScopedVariablePhases {
+    fn set(&mut self, name: &Identifier) {
+        let phase = self.sets.entry(name.clone()).or_insert(self.phase);
+        *phase = (*phase).min(self.phase);
+    }
+
+    fn read(&mut self, name: &Identifier, location: Location) {
+        self.reads.push((name.clone(), self.phase, location));
+    }
+
+    fn declare(&mut self, name: &Identifier, mutable: bool) {
+        self.set(name);
+        *self.declarations.entry(name.clone()).or_default() |= mutable;
+    }
+
+    fn assign(&mut self, variable: &ast::ScopedVariable) {
+        self.set(&variable.name);
+        self.assignments.push((
+            variable.name.clone(),
+            variable.to_string(),
+            variable.location,
+        ));
+    }
+
+    /// Reports a read of a scoped variable that is only set in later phases, which can therefore
+    /// never see a value, and assignments to scoped variables that are never declared, or only
+    /// declared immutable.  Whether an assignment finds a mutable variable on its scope can only
+    /// be determined during execution.
+    fn check(&self) -> Result<(), CheckError> {
+        for (name, variable, location) in &self.assignments {
+            match self.declarations.get(name) {
+                Some(true) => {}
+                Some(false) => {
+                    return Err(CheckError::CannotSetImmutableScopedVariable(
+                        variable.clone(),
+                        *location,
+                    ))
+                }
+                None => {
+                    return Err(CheckError::UndeclaredScopedVariable(
+                        variable.clone(),
+                        *location,
+                    ))
+                }
+            }
+        }
+        for (name, phase, location) in &self.reads {
+            if self.sets.get(name).is_some_and(|set| set > phase) {
+                return Err(CheckError::ScopedVariableSetInLaterPhase(
+                    name.to_string(),
+                    *location,
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -123,6 +290,16 @@ struct VariableResult {
 
 impl ast::File {
     pub fn check(&mut self) -> Result<(), CheckError> {
+        self.check_file(None)
+    }
+
+    /// Checks the file, additionally verifying the number of arguments of every call to a
+    /// function that has a declared signature in the given library.
+    pub fn check_with_functions(&mut self, functions: &Functions) -> Result<(), CheckError> {
+        self.check_file(Some(functions))
+    }
+
+    fn check_file(&mut self, functions: Option<&Functions>) -> Result<(), CheckError> {
         let mut globals = VariableMap::new();
         for global in &self.globals {
             globals
@@ -141,31 +318,314 @@ impl ast::File {
                     )
                 })?;
         }
+        let node_types = self
+            .node_types
+            .iter()
+            .map(|node_type| Ok((node_type.name.clone(), node_type.check()?)))
+            .collect::<Result<HashMap<_, _>, CheckError>>()?;
+        let shorthands = self.shorthands.check()?;
+        let declarations = Declarations {
+            node_types: &node_types,
+            shorthands: &shorthands,
+        };
         let file_query = self.query.as_ref().unwrap();
-        for (index, stanza) in self.stanzas.iter_mut().enumerate() {
-            stanza.check(&globals, file_query, index)?;
+        let shorthand_query = Query::new(self.language, "").expect("empty query is valid");
+        for shorthand in self.shorthands.iter_mut() {
+            shorthand.check(
+                functions,
+                &globals,
+                &declarations,
+                file_query,
+                &shorthand_query,
+            )?;
+        }
+        let mut scoped_variables = ScopedVariablePhases::default();
+        for index in 0..self.stanzas.len() {
+            scoped_variables.phase = self.phase_index(&self.stanzas[index]);
+            self.stanzas[index].check(
+                functions,
+                &globals,
+                &declarations,
+                &mut scoped_variables,
+                file_query,
+                index,
+            )?;
+        }
+        scoped_variables.check()
+    }
+}
+
+//-----------------------------------------------------------------------------
+// Node types
+
+impl ast::NodeType {
+    /// Returns the default attribute values of this node type, which must be constants.
+    fn check(&self) -> Result<Vec<(Identifier, Value)>, CheckError> {
+        self.attributes
+            .iter()
+            .map(|attribute| {
+                let value = attribute.value.constant_value().ok_or_else(|| {
+                    CheckError::ExpectedConstantValue(attribute.name.to_string(), self.location)
+                })?;
+                Ok((attribute.name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+impl ast::Expression {
+    /// Returns the value of this expression if it is a literal, or a list, set, or map of literals.
+    fn constant_value(&self) -> Option<Value> {
+        match self {
+            Self::FalseLiteral => Some(Value::Boolean(false)),
+            Self::NullLiteral => Some(Value::Null),
+            Self::TrueLiteral => Some(Value::Boolean(true)),
+            Self::IntegerConstant(expr) => Some(Value::Integer(expr.value)),
+            Self::FloatConstant(expr) => Some(Value::Float(expr.value)),
+            Self::StringConstant(expr) => Some(Value::String(expr.value.clone())),
+            Self::ListLiteral(expr) => expr
+                .elements
+                .iter()
+                .map(|element| element.constant_value())
+                .collect::<Option<Vec<_>>>()
+                .map(Value::List),
+            Self::SetLiteral(expr) => expr
+                .elements
+                .iter()
+                .map(|element| element.constant_value())
+                .collect::<Option<_>>()
+                .map(Value::Set),
+            Self::MapLiteral(expr) => expr
+                .entries
+                .iter()
+                .map(|(key, value)| Some((key.clone(), value.constant_value()?)))
+                .collect::<Option<_>>()
+                .map(Value::Map),
+            _ => None,
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+// Attribute shorthands
+
+/// The attributes that an attribute shorthand sets, by name, and how they are set
+struct ShorthandAttributes {
+    location: Location,
+    attributes: Vec<(Identifier, ast::AttributeOperation)>,
+}
+
+impl ast::AttributeShorthands {
+    /// Returns the attributes that each shorthand sets, rejecting shorthands that expand to
+    /// themselves, directly or through other shorthands.
+    fn check(&self) -> Result<HashMap<Identifier, ShorthandAttributes>, CheckError> {
+        let shorthands = self
+            .iter()
+            .map(|shorthand| {
+                let attributes = shorthand
+                    .attributes
+                    .iter()
+                    .map(|attribute| (attribute.name.clone(), attribute.operation))
+                    .collect();
+                (
+                    shorthand.name.clone(),
+                    ShorthandAttributes {
+                        location: shorthand.location,
+                        attributes,
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let mut finished = HashSet::new();
+        for name in shorthands.keys() {
+            check_shorthand_recursion(&shorthands, name, &mut Vec::new(), &mut finished)?;
+        }
+        Ok(shorthands)
+    }
+}
+
+fn check_shorthand_recursion<'a>(
+    shorthands: &'a HashMap<Identifier, ShorthandAttributes>,
+    name: &'a Identifier,
+    expanding: &mut Vec<&'a Identifier>,
+    finished: &mut HashSet<&'a Identifier>,
+) -> Result<(), CheckError> {
+    let shorthand = match shorthands.get(name) {
+        Some(shorthand) => shorthand,
+        None => return Ok(()),
+    };
+    if finished.contains(name) {
+        return Ok(());
+    }
+    if expanding.contains(&name) {
+        return Err(CheckError::RecursiveShorthand(
+            name.to_string(),
+            shorthand.location,
+        ));
+    }
+    expanding.push(name);
+    for (attribute, _) in &shorthand.attributes {
+        check_shorthand_recursion(shorthands, attribute, expanding, finished)?;
+    }
+    expanding.pop();
+    finished.insert(name);
+    Ok(())
+}
+
+impl ast::AttributeShorthand {
+    /// Checks the attributes of this shorthand.  A shorthand can be used in any stanza, so its
+    /// attributes can only refer to its variable and to global variables, and not to captures.
+    fn check(
+        &mut self,
+        functions: Option<&Functions>,
+        globals: &dyn Variables<VariableResult>,
+        declarations: &Declarations,
+        file_query: &Query,
+        shorthand_query: &Query,
+    ) -> Result<(), CheckError> {
+        let mut scoped_variables = ScopedVariablePhases::default();
+        let mut locals = VariableMap::new();
+        let mut ctx = CheckContext {
+            functions,
+            globals,
+            node_types: declarations.node_types,
+            shorthands: declarations.shorthands,
+            scoped_variables: &mut scoped_variables,
+            file_query,
+            stanza_index: 0,
+            stanza_query: shorthand_query,
+            locals: &mut locals,
+            regex_groups: None,
+        };
+        if let Some(variable) = &mut self.variable {
+            variable.check_add(
+                &mut ctx,
+                VariableResult {
+                    is_local: true,
+                    quantifier: One,
+                },
+                false,
+            )?;
+        }
+        check_shorthand_conflicts(
+            ctx.shorthands,
+            &self.attributes,
+            "the shorthand",
+            self.location,
+        )?;
+        for attribute in &mut self.attributes {
+            attribute.check(&mut ctx)?;
         }
         Ok(())
     }
 }
 
+/// An attribute that is set by a list of attributes, after expanding shorthands
+struct ExpandedAttribute<'a> {
+    name: &'a Identifier,
+    operation: ast::AttributeOperation,
+    /// The shorthand in the list that expanded to the attribute, if any
+    shorthand: Option<&'a Identifier>,
+}
+
+fn expand_shorthands<'a>(
+    shorthands: &'a HashMap<Identifier, ShorthandAttributes>,
+    name: &'a Identifier,
+    operation: ast::AttributeOperation,
+    shorthand: Option<&'a Identifier>,
+    expanded: &mut Vec<ExpandedAttribute<'a>>,
+) {
+    match shorthands.get(name) {
+        Some(attributes) => {
+            for (attribute, attribute_operation) in &attributes.attributes {
+                expand_shorthands(
+                    shorthands,
+                    attribute,
+                    operation.combine(*attribute_operation),
+                    shorthand.or(Some(name)),
+                    expanded,
+                );
+            }
+        }
+        None => expanded.push(ExpandedAttribute {
+            name,
+            operation,
+            shorthand,
+        }),
+    }
+}
+
+/// Rejects a list of attributes that sets the same attribute twice without appending or
+/// overriding, if at least one of them comes from a shorthand.  Such a list fails during
+/// execution unless both values happen to be equal, and reporting it here gives the location of
+/// the shorthand as well as that of the list.
+fn check_shorthand_conflicts(
+    shorthands: &HashMap<Identifier, ShorthandAttributes>,
+    attributes: &[ast::Attribute],
+    written_in: &str,
+    location: Location,
+) -> Result<(), CheckError> {
+    let mut expanded = Vec::new();
+    for attribute in attributes {
+        expand_shorthands(
+            shorthands,
+            &attribute.name,
+            attribute.operation,
+            None,
+            &mut expanded,
+        );
+    }
+    let origin = |attribute: &ExpandedAttribute| match attribute.shorthand {
+        Some(name) => format!(
+            "shorthand {} defined at {}",
+            name, shorthands[name].location
+        ),
+        None => written_in.to_string(),
+    };
+    for (index, first) in expanded.iter().enumerate() {
+        for second in &expanded[index + 1..] {
+            if first.name == second.name
+                && first.operation == ast::AttributeOperation::Set
+                && second.operation == ast::AttributeOperation::Set
+                && (first.shorthand.is_some() || second.shorthand.is_some())
+            {
+                return Err(CheckError::ConflictingShorthandAttribute(
+                    first.name.to_string(),
+                    origin(first),
+                    origin(second),
+                    location,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 //-----------------------------------------------------------------------------
 // Stanza
 
 impl ast::Stanza {
     fn check(
         &mut self,
+        functions: Option<&Functions>,
         globals: &dyn Variables<VariableResult>,
+        declarations: &Declarations,
+        scoped_variables: &mut ScopedVariablePhases,
         file_query: &Query,
         stanza_index: usize,
     ) -> Result<(), CheckError> {
         let mut locals = VariableMap::new();
         let mut ctx = CheckContext {
+            functions,
             globals,
+            node_types: declarations.node_types,
+            shorthands: declarations.shorthands,
+            scoped_variables,
             file_query,
             stanza_index,
             stanza_query: &self.query,
             locals: &mut locals,
+            regex_groups: None,
         };
         self.full_match_file_capture_index =
             ctx.file_query
@@ -224,6 +684,8 @@ impl ast::Statement {
             Self::AddGraphNodeAttribute(stmt) => stmt.check(ctx),
             Self::CreateEdge(stmt) => stmt.check(ctx),
             Self::AddEdgeAttribute(stmt) => stmt.check(ctx),
+            Self::AddAttributes(stmt) => stmt.check(ctx),
+            Self::RemoveAttributes(stmt) => stmt.check(ctx),
             Self::Scan(stmt) => stmt.check(ctx),
             Self::Print(stmt) => stmt.check(ctx),
             Self::If(stmt) => stmt.check(ctx),
@@ -267,6 +729,13 @@ impl ast::Assign {
 
 impl ast::CreateGraphNode {
     fn check(&mut self, ctx: &mut CheckContext) -> Result<StatementResult, CheckError> {
+        if let Some(node_type) = &self.node_type {
+            self.defaults = ctx
+                .node_types
+                .get(node_type)
+                .ok_or_else(|| CheckError::UndefinedNodeType(node_type.to_string(), self.location))?
+                .clone();
+        }
         let node_result = self.node.check_add(
             ctx,
             VariableResult {
@@ -275,9 +744,18 @@ impl ast::CreateGraphNode {
             },
             false,
         )?;
-        Ok(StatementResult {
-            used_captures: node_result.used_captures,
-        })
+        let mut used_captures = node_result.used_captures;
+        check_shorthand_conflicts(
+            ctx.shorthands,
+            &self.attributes,
+            "the statement",
+            self.location,
+        )?;
+        for attribute in &mut self.attributes {
+            let attr_result = attribute.check(ctx)?;
+            used_captures.extend(attr_result.used_captures);
+        }
+        Ok(StatementResult { used_captures })
     }
 }
 
@@ -286,6 +764,12 @@ impl ast::AddGraphNodeAttribute {
         let mut used_captures = HashSet::new();
         let node_result = self.node.check(ctx)?;
         used_captures.extend(node_result.used_captures);
+        check_shorthand_conflicts(
+            ctx.shorthands,
+            &self.attributes,
+            "the statement",
+            self.location,
+        )?;
         for attribute in &mut self.attributes {
             let attr_result = attribute.check(ctx)?;
             used_captures.extend(attr_result.used_captures);
@@ -301,6 +785,16 @@ impl ast::CreateEdge {
         used_captures.extend(source_result.used_captures);
         let sink_result = self.sink.check(ctx)?;
         used_captures.extend(sink_result.used_captures);
+        check_shorthand_conflicts(
+            ctx.shorthands,
+            &self.attributes,
+            "the statement",
+            self.location,
+        )?;
+        for attribute in &mut self.attributes {
+            let attr_result = attribute.check(ctx)?;
+            used_captures.extend(attr_result.used_captures);
+        }
         Ok(StatementResult { used_captures })
     }
 }
@@ -312,6 +806,12 @@ impl ast::AddEdgeAttribute {
         used_captures.extend(source_result.used_captures);
         let sink_result = self.sink.check(ctx)?;
         used_captures.extend(sink_result.used_captures);
+        check_shorthand_conflicts(
+            ctx.shorthands,
+            &self.attributes,
+            "the statement",
+            self.location,
+        )?;
         for attribute in &mut self.attributes {
             let attr_result = attribute.check(ctx)?;
             used_captures.extend(attr_result.used_captures);
@@ -320,6 +820,62 @@ impl ast::AddEdgeAttribute {
     }
 }
 
+/// Checks the targets of an `attr` or `delattr` statement, which must be all graph nodes or all
+/// edges, and returns the captures they use.
+fn check_attribute_targets(
+    ctx: &mut CheckContext,
+    targets: &mut [ast::AttributeTarget],
+    location: Location,
+) -> Result<HashSet<Identifier>, CheckError> {
+    let mut used_captures = HashSet::new();
+    let is_edge = |target: &ast::AttributeTarget| match target {
+        ast::AttributeTarget::GraphNode(_) => false,
+        ast::AttributeTarget::Edge { .. } => true,
+    };
+    if targets
+        .iter()
+        .any(|target| is_edge(target) != is_edge(&targets[0]))
+    {
+        return Err(CheckError::MixedAttributeTargets(location));
+    }
+    for target in targets {
+        match target {
+            ast::AttributeTarget::GraphNode(node) => {
+                used_captures.extend(node.check(ctx)?.used_captures);
+            }
+            ast::AttributeTarget::Edge { source, sink } => {
+                used_captures.extend(source.check(ctx)?.used_captures);
+                used_captures.extend(sink.check(ctx)?.used_captures);
+            }
+        }
+    }
+    Ok(used_captures)
+}
+
+impl ast::AddAttributes {
+    fn check(&mut self, ctx: &mut CheckContext) -> Result<StatementResult, CheckError> {
+        let mut used_captures = check_attribute_targets(ctx, &mut self.targets, self.location)?;
+        check_shorthand_conflicts(
+            ctx.shorthands,
+            &self.attributes,
+            "the statement",
+            self.location,
+        )?;
+        for attribute in &mut self.attributes {
+            let attr_result = attribute.check(ctx)?;
+            used_captures.extend(attr_result.used_captures);
+        }
+        Ok(StatementResult { used_captures })
+    }
+}
+
+impl ast::RemoveAttributes {
+    fn check(&mut self, ctx: &mut CheckContext) -> Result<StatementResult, CheckError> {
+        let used_captures = check_attribute_targets(ctx, &mut self.targets, self.location)?;
+        Ok(StatementResult { used_captures })
+    }
+}
+
 impl ast::Scan {
     fn check(&mut self, ctx: &mut CheckContext) -> Result<StatementResult, CheckError> {
         let mut used_captures = HashSet::new();
@@ -336,20 +892,26 @@ impl ast::Scan {
             // regex "\b" matches empty strings within a larger non-empty one.
             // Therefore, there is also a runtime check that checks that a match was
             // non-empty. This is all to prevent non-termination of scan.
-            if let Some(_) = arm.regex.captures("") {
+            if arm.regex.find_range("").is_some() {
                 return Err(CheckError::NullableRegex(
                     arm.regex.to_string(),
                     arm.location,
                 ));
             }
 
+            let regex_groups = arm.regex.group_names();
             let mut arm_locals = VariableMap::nested(ctx.locals);
             let mut arm_ctx = CheckContext {
+                functions: ctx.functions,
                 globals: ctx.globals,
+                node_types: ctx.node_types,
+                shorthands: ctx.shorthands,
+                scoped_variables: ctx.scoped_variables,
                 file_query: ctx.file_query,
                 stanza_index: ctx.stanza_index,
                 stanza_query: ctx.stanza_query,
                 locals: &mut arm_locals,
+                regex_groups: Some(&regex_groups),
             };
 
             for statement in &mut arm.statements {
@@ -384,11 +946,16 @@ impl ast::If {
 
             let mut arm_locals = VariableMap::nested(ctx.locals);
             let mut arm_ctx = CheckContext {
+                functions: ctx.functions,
                 globals: ctx.globals,
+                node_types: ctx.node_types,
+                shorthands: ctx.shorthands,
+                scoped_variables: ctx.scoped_variables,
                 file_query: ctx.file_query,
                 stanza_index: ctx.stanza_index,
                 stanza_query: ctx.stanza_query,
                 locals: &mut arm_locals,
+                regex_groups: ctx.regex_groups,
             };
 
             for statement in &mut arm.statements {
@@ -434,23 +1001,35 @@ impl ast::ForIn {
         if !value_result.is_local {
             return Err(CheckError::ExpectedLocalValue(self.location));
         }
-        if value_result.quantifier != ZeroOrMore && value_result.quantifier != OneOrMore {
+        if !value_result.may_be_list(&self.value) {
             return Err(CheckError::ExpectedListValue(self.location));
         }
         used_captures.extend(value_result.used_captures.iter().cloned());
 
         let mut loop_locals = VariableMap::nested(ctx.locals);
         let mut loop_ctx = CheckContext {
+            functions: ctx.functions,
             globals: ctx.globals,
+            node_types: ctx.node_types,
+            shorthands: ctx.shorthands,
+            scoped_variables: ctx.scoped_variables,
             file_query: ctx.file_query,
             stanza_index: ctx.stanza_index,
             stanza_query: ctx.stanza_query,
             locals: &mut loop_locals,
+            regex_groups: ctx.regex_groups,
         };
         let var_result = self
             .variable
             .check_add(&mut loop_ctx, value_result.into(), false)?;
         used_captures.extend(var_result.used_captures);
+        if let Some(index) = &mut self.index {
+            let index_value = VariableResult {
+                is_local: true,
+                quantifier: One,
+            };
+            index.check_add(&mut loop_ctx, index_value, false)?;
+        }
 
         for statement in &mut self.statements {
             let stmt_result = statement.check(&mut loop_ctx)?;
@@ -472,6 +1051,19 @@ struct ExpressionResult {
     used_captures: HashSet<Identifier>,
 }
 
+impl ExpressionResult {
+    /// Returns whether the expression with this result can evaluate to a list.  Expressions of
+    /// quantified captures always do, and single or optional captures never do.  The values of
+    /// other expressions, such as function calls and variables, are only known at execution time.
+    fn may_be_list(&self, value: &ast::Expression) -> bool {
+        match self.quantifier {
+            ZeroOrMore | OneOrMore => true,
+            One => !matches!(value, ast::Expression::Capture(_)),
+            Zero | ZeroOrOne => false,
+        }
+    }
+}
+
 impl ast::Expression {
     fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
         match self {
@@ -491,15 +1083,19 @@ impl ast::Expression {
                 used_captures: HashSet::default(),
             }),
             Self::IntegerConstant(expr) => expr.check(ctx),
+            Self::FloatConstant(expr) => expr.check(ctx),
             Self::StringConstant(expr) => expr.check(ctx),
             Self::ListLiteral(expr) => expr.check(ctx),
             Self::SetLiteral(expr) => expr.check(ctx),
+            Self::MapLiteral(expr) => expr.check(ctx),
             Self::ListComprehension(expr) => expr.check(ctx),
             Self::SetComprehension(expr) => expr.check(ctx),
             Self::Capture(expr) => expr.check(ctx),
+            Self::CaptureAggregate(expr) => expr.check(ctx),
             Self::Variable(expr) => expr.check_get(ctx),
             Self::Call(expr) => expr.check(ctx),
             Self::RegexCapture(expr) => expr.check(ctx),
+            Self::MatchOffset(expr) => expr.check(ctx),
         }
     }
 }
@@ -514,6 +1110,16 @@ impl ast::IntegerConstant {
     }
 }
 
+impl ast::FloatConstant {
+    fn check(&mut self, _ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+        Ok(ExpressionResult {
+            is_local: true,
+            quantifier: One,
+            used_captures: HashSet::default(),
+        })
+    }
+}
+
 impl ast::StringConstant {
     fn check(&mut self, _ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
         Ok(ExpressionResult {
@@ -558,6 +1164,23 @@ impl ast::SetLiteral {
     }
 }
 
+impl ast::MapLiteral {
+    fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+        let mut is_local = true;
+        let mut used_captures = HashSet::new();
+        for (_, value) in &mut self.entries {
+            let value_result = value.check(ctx)?;
+            is_local &= value_result.is_local;
+            used_captures.extend(value_result.used_captures);
+        }
+        Ok(ExpressionResult {
+            is_local,
+            quantifier: One,
+            used_captures,
+        })
+    }
+}
+
 impl ast::ListComprehension {
     fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
         let mut used_captures = HashSet::new();
@@ -566,18 +1189,23 @@ impl ast::ListComprehension {
         if !value_result.is_local {
             return Err(CheckError::ExpectedLocalValue(self.location));
         }
-        if value_result.quantifier != ZeroOrMore && value_result.quantifier != OneOrMore {
+        if !value_result.may_be_list(&self.value) {
             return Err(CheckError::ExpectedListValue(self.location));
         }
         used_captures.extend(value_result.used_captures.iter().cloned());
 
         let mut loop_locals = VariableMap::nested(ctx.locals);
         let mut loop_ctx = CheckContext {
+            functions: ctx.functions,
             globals: ctx.globals,
+            node_types: ctx.node_types,
+            shorthands: ctx.shorthands,
+            scoped_variables: ctx.scoped_variables,
             file_query: ctx.file_query,
             stanza_index: ctx.stanza_index,
             stanza_query: ctx.stanza_query,
             locals: &mut loop_locals,
+            regex_groups: ctx.regex_groups,
         };
         let var_result = self
             .variable
@@ -603,18 +1231,23 @@ impl ast::SetComprehension {
         if !value_result.is_local {
             return Err(CheckError::ExpectedLocalValue(self.location));
         }
-        if value_result.quantifier != ZeroOrMore && value_result.quantifier != OneOrMore {
+        if !value_result.may_be_list(&self.value) {
             return Err(CheckError::ExpectedListValue(self.location));
         }
         used_captures.extend(value_result.used_captures.iter().cloned());
 
         let mut loop_locals = VariableMap::nested(ctx.locals);
         let mut loop_ctx = CheckContext {
+            functions: ctx.functions,
             globals: ctx.globals,
+            node_types: ctx.node_types,
+            shorthands: ctx.shorthands,
+            scoped_variables: ctx.scoped_variables,
             file_query: ctx.file_query,
             stanza_index: ctx.stanza_index,
             stanza_query: ctx.stanza_query,
             locals: &mut loop_locals,
+            regex_groups: ctx.regex_groups,
         };
         let var_result = self
             .variable
@@ -654,8 +1287,41 @@ impl ast::Capture {
     }
 }
 
+impl ast::CaptureAggregate {
+    fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+        let capture_result = self.capture.check(ctx)?;
+        let quantifier = match capture_result.quantifier {
+            ZeroOrMore if self.aggregate == ast::Aggregate::Count => One,
+            ZeroOrMore => ZeroOrOne,
+            OneOrMore => One,
+            _ => {
+                return Err(CheckError::ExpectedQuantifiedCapture(
+                    self.aggregate.to_string(),
+                    self.capture.name.to_string(),
+                    self.location,
+                ))
+            }
+        };
+        Ok(ExpressionResult {
+            is_local: true,
+            quantifier,
+            used_captures: capture_result.used_captures,
+        })
+    }
+}
+
 impl ast::Call {
     fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+        if let Some(signature) = ctx.functions.and_then(|f| f.signature(&self.function)) {
+            if !signature.accepts_count(self.parameters.len()) {
+                return Err(CheckError::WrongArity(
+                    self.function.to_string(),
+                    signature.expected_count(),
+                    self.parameters.len(),
+                    self.location,
+                ));
+            }
+        }
         let mut is_local = true;
         let mut used_captures = HashSet::new();
         for parameter in &mut self.parameters {
@@ -672,7 +1338,43 @@ impl ast::Call {
 }
 
 impl ast::RegexCapture {
-    fn check(&mut self, _ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+    fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+        let groups = ctx
+            .regex_groups
+            .ok_or_else(|| CheckError::UndefinedRegexCapture(self.to_string(), self.location))?;
+        match &self.name {
+            Some(name) => {
+                self.match_index = groups
+                    .iter()
+                    .position(|group| group.as_deref() == Some(name.as_str()))
+                    .ok_or_else(|| {
+                        CheckError::UndefinedRegexCapture(self.to_string(), self.location)
+                    })?;
+            }
+            None if self.match_index >= groups.len() => {
+                return Err(CheckError::UndefinedRegexCapture(
+                    self.to_string(),
+                    self.location,
+                ));
+            }
+            None => {}
+        }
+        Ok(ExpressionResult {
+            is_local: true,
+            quantifier: One,
+            used_captures: HashSet::default(),
+        })
+    }
+}
+
+impl ast::MatchOffset {
+    fn check(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
+        if ctx.regex_groups.is_none() {
+            return Err(CheckError::UndefinedRegexCapture(
+                self.to_string(),
+                self.location,
+            ));
+        }
         Ok(ExpressionResult {
             is_local: true,
             quantifier: One,
@@ -786,9 +1488,10 @@ impl ast::ScopedVariable {
         &mut self,
         ctx: &mut CheckContext,
         _value: VariableResult,
-        _mutable: bool,
+        mutable: bool,
     ) -> Result<StatementResult, CheckError> {
         let scope_result = self.scope.check(ctx)?;
+        ctx.scoped_variables.declare(&self.name, mutable);
         Ok(scope_result.into())
     }
 
@@ -798,11 +1501,13 @@ impl ast::ScopedVariable {
         _value: VariableResult,
     ) -> Result<StatementResult, CheckError> {
         let scope_result = self.scope.check(ctx)?;
+        ctx.scoped_variables.assign(self);
         Ok(scope_result.into())
     }
 
     fn check_get(&mut self, ctx: &mut CheckContext) -> Result<ExpressionResult, CheckError> {
         let scope_result = self.scope.check(ctx)?;
+        ctx.scoped_variables.read(&self.name, self.location);
         Ok(ExpressionResult {
             is_local: false,
             quantifier: One, // FIXME we don't really know
@@ -857,3 +1562,143 @@ impl Into<VariableResult> for ExpressionResult {
         }
     }
 }
+
+//-----------------------------------------------------------------------------
+// Warnings
+
+impl ast::File {
+    /// Returns warnings about names in this file that shadow other names: captures and local
+    /// variables with the same name as a global variable, and local variables with the same name
+    /// as a local variable of an enclosing block.  Globals are the ones declared by the file, and
+    /// the ones in `globals`, if the host provides them.
+    ///
+    /// Warnings for a statement, or for the captures of a stanza, are suppressed by a
+    /// `; #allow(shadow)` comment at the end of the line on which the statement or stanza starts,
+    /// or on the line before it.
+    pub fn check_warnings(&self, globals: Option<&crate::Variables>) -> Vec<CheckWarning> {
+        let mut warnings = ShadowingWarnings {
+            file: self,
+            globals,
+            scopes: Vec::new(),
+            warnings: Vec::new(),
+        };
+        for stanza in &self.stanzas {
+            warnings.check_stanza(stanza);
+        }
+        warnings.warnings
+    }
+
+    fn allows_shadowing(&self, location: Location) -> bool {
+        self.shadowing_allowed.contains(&location.row)
+            || (location.row > 0 && self.shadowing_allowed.contains(&(location.row - 1)))
+    }
+}
+
+/// Collects shadowing warnings, keeping track of the local variables declared in each enclosing
+/// block.
+struct ShadowingWarnings<'a> {
+    file: &'a ast::File,
+    globals: Option<&'a crate::Variables<'a>>,
+    scopes: Vec<HashMap<Identifier, Location>>,
+    warnings: Vec<CheckWarning>,
+}
+
+impl ShadowingWarnings<'_> {
+    /// Returns whether a name is a global variable, and the location of its declaration, if it
+    /// is declared by the file.
+    fn global(&self, name: &Identifier) -> Option<Option<Location>> {
+        if let Some(global) = self.file.globals.iter().find(|global| &global.name == name) {
+            return Some(Some(global.location));
+        }
+        self.globals?.get(name).map(|_| None)
+    }
+
+    fn check_stanza(&mut self, stanza: &ast::Stanza) {
+        let location = stanza.range.start;
+        if !self.file.allows_shadowing(location) {
+            for name in stanza.query.capture_names() {
+                if name.as_str() == FULL_MATCH {
+                    continue;
+                }
+                if let Some(declared) = self.global(&Identifier::from(name.as_str())) {
+                    self.warnings.push(CheckWarning::CaptureShadowsGlobal(
+                        name.to_string(),
+                        location,
+                        declared,
+                    ));
+                }
+            }
+        }
+        self.check_block(&stanza.statements, &[]);
+    }
+
+    /// Checks the statements of a block, whose scope starts out with the given variables.
+    fn check_block(&mut self, statements: &[ast::Statement], variables: &[&ast::UnscopedVariable]) {
+        self.scopes.push(HashMap::new());
+        for variable in variables {
+            self.declare(variable);
+        }
+        for statement in statements {
+            match statement {
+                ast::Statement::DeclareImmutable(ast::DeclareImmutable {
+                    variable: ast::Variable::Unscoped(variable),
+                    ..
+                })
+                | ast::Statement::DeclareMutable(ast::DeclareMutable {
+                    variable: ast::Variable::Unscoped(variable),
+                    ..
+                }) => self.declare(variable),
+                ast::Statement::Scan(statement) => {
+                    for arm in &statement.arms {
+                        self.check_block(&arm.statements, &[]);
+                    }
+                }
+                ast::Statement::If(statement) => {
+                    for arm in &statement.arms {
+                        self.check_block(&arm.statements, &[]);
+                    }
+                }
+                ast::Statement::ForIn(statement) => {
+                    let variables = statement
+                        .index
+                        .iter()
+                        .chain(Some(&statement.variable))
+                        .collect::<Vec<_>>();
+                    self.check_block(&statement.statements, &variables);
+                }
+                _ => {}
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, variable: &ast::UnscopedVariable) {
+        let (current, enclosing) = self.scopes.split_last().expect("missing scope");
+        if !self.file.allows_shadowing(variable.location) {
+            if let Some(declared) = self.global(&variable.name) {
+                self.warnings.push(CheckWarning::LocalShadowsGlobal(
+                    variable.name.to_string(),
+                    variable.location,
+                    declared,
+                ));
+            } else if !current.contains_key(&variable.name) {
+                let outer = enclosing
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(&variable.name));
+                if let Some(outer) = outer {
+                    self.warnings.push(CheckWarning::LocalShadowsLocal(
+                        variable.name.to_string(),
+                        variable.location,
+                        *outer,
+                    ));
+                }
+            }
+        }
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .entry(variable.name.clone())
+            .or_insert(variable.location);
+    }
+}
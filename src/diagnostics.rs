@@ -0,0 +1,474 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Diagnostics that display errors as annotated snippets of the graph DSL file and the source file
+//!
+//! A [`Diagnostic`][] shows the lines of the graph DSL file that an error is reported at, with
+//! the failing expression underlined, and, for execution errors, the syntax node that the
+//! stanza's query matched in the source file:
+//!
+//! ``` text
+//! error: Function plus expects argument 1 to be number, got string
+//!  --> test.tsg:4:19
+//!   |
+//! 1 | (assignment left: (_) @left)
+//!   | ---------------------------- in this stanza
+//! ...
+//! 4 |   attr (n) name = (plus (source-text @left) 1)
+//!   |                   ^^^^^^^^^^^^^^^^^^^^^^^^^^^^ in this call to plus
+//!   |   -------------------------------------------- while executing this statement
+//!   |
+//!  ::: test.py:2:1
+//!   |
+//! 2 | x = 1
+//!   | ----- matching this (assignment) node
+//! ```
+//!
+//! Diagnostics are plain text by default.  Use [`Diagnostic::colored`][] to color them with ANSI
+//! escape codes, for instance when they are written to a terminal.
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::execution::error::Context;
+use crate::parser::DisplayQueryErrorKind;
+use crate::ExecutionError;
+use crate::Location;
+use crate::ParseError;
+
+const ERROR_STYLE: &str = "\x1b[1;31m";
+const SECONDARY_STYLE: &str = "\x1b[1;34m";
+const EMPHASIS_STYLE: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// An error, displayed as annotated snippets of the files it is reported in.  Create one using
+/// [`from_parse_error`][Diagnostic::from_parse_error] or
+/// [`from_execution_error`][Diagnostic::from_execution_error], and display it.
+pub struct Diagnostic<'a> {
+    message: String,
+    labels: Vec<Label<'a>>,
+    notes: Vec<String>,
+    tsg_path: Option<&'a Path>,
+    source_path: Option<&'a Path>,
+    colored: bool,
+}
+
+/// The file that a label points into
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Origin {
+    Tsg,
+    Source,
+}
+
+/// A message attached to a region of a line of a file
+struct Label<'a> {
+    origin: Origin,
+    text: &'a str,
+    row: usize,
+    /// The underlined columns, counted in characters
+    columns: Range<usize>,
+    message: String,
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Creates a diagnostic for an error in a graph DSL file, whose content is given.
+    pub fn from_parse_error(error: &ParseError, tsg_source: &'a str) -> Self {
+        let mut labels = Vec::new();
+        let message = match error {
+            ParseError::QueryError(err, _) => {
+                labels.push(Label::at_token(
+                    tsg_source,
+                    Location {
+                        row: err.row,
+                        column: err.column,
+                    },
+                    first_line(&DisplayQueryErrorKind(err).to_string()),
+                ));
+                first_line(&error.to_string())
+            }
+            ParseError::QueryErrors(errors) => {
+                for (err, _) in errors {
+                    labels.push(Label::at_token(
+                        tsg_source,
+                        Location {
+                            row: err.row,
+                            column: err.column,
+                        },
+                        first_line(&DisplayQueryErrorKind(err).to_string()),
+                    ));
+                }
+                format!("Invalid query patterns in {} stanzas", errors.len())
+            }
+            error => {
+                if let Some(location) = error.location() {
+                    labels.push(Label::at_token(tsg_source, location, String::new()));
+                }
+                error.to_string()
+            }
+        };
+        Self::new(message, labels, Vec::new())
+    }
+
+    /// Creates a diagnostic for an error that occurred while executing a graph DSL file, whose
+    /// content is given, against a source file, whose content is given as well.
+    pub fn from_execution_error(
+        error: &ExecutionError,
+        tsg_source: &'a str,
+        source: &'a str,
+    ) -> Self {
+        let mut labels = Vec::new();
+        let mut calls = Vec::new();
+        let mut statements = Vec::new();
+        let mut source_labels = Vec::new();
+        let mut notes = Vec::new();
+        let mut cause = error;
+        while let ExecutionError::InContext(context, inner) = cause {
+            match context {
+                Context::Statement(contexts) => {
+                    for (index, context) in contexts.iter().enumerate() {
+                        let message = if index == 0 {
+                            "while executing this statement"
+                        } else {
+                            "and this statement"
+                        };
+                        statements.push(Label::at_line(
+                            tsg_source,
+                            context.statement_location,
+                            message.to_string(),
+                        ));
+                        if index == 0 || context.stanza_location != contexts[0].stanza_location {
+                            statements.push(Label::at_token(
+                                tsg_source,
+                                context.stanza_location,
+                                "in this stanza".to_string(),
+                            ));
+                        }
+                        source_labels.push(Label::at_source_region(
+                            source,
+                            context.source_location,
+                            context.source_end_location,
+                            format!("matching this ({}) node", context.node_kind),
+                        ));
+                    }
+                }
+                Context::Call { function, location } => {
+                    calls.push(Label::at_token(
+                        tsg_source,
+                        *location,
+                        format!("in this call to {}", function),
+                    ));
+                }
+                Context::Other(message) => notes.push(message.clone()),
+                Context::Progress(progress) => notes.push(progress.to_string()),
+            }
+            cause = inner;
+        }
+        match cause {
+            ExecutionError::WrongArity {
+                location: Some(location),
+                ..
+            } => {
+                labels.push(Label::at_token(
+                    tsg_source,
+                    *location,
+                    "called here".to_string(),
+                ));
+            }
+            ExecutionError::DependencyCycle(steps) => {
+                for step in steps {
+                    labels.push(Label::at_line(
+                        tsg_source,
+                        step.statement_location,
+                        format!("{} is defined here", step.variable),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        // the innermost call is the most specific location of the error
+        labels.extend(calls.into_iter().rev());
+        labels.extend(statements);
+        labels.extend(source_labels);
+        Self::new(cause.to_string(), labels, notes)
+    }
+
+    fn new(message: String, labels: Vec<Label<'a>>, notes: Vec<String>) -> Self {
+        Self {
+            message,
+            labels,
+            notes,
+            tsg_path: None,
+            source_path: None,
+            colored: false,
+        }
+    }
+
+    /// Sets the path of the graph DSL file, which is displayed with the location of its snippets.
+    pub fn tsg_path(mut self, path: &'a Path) -> Self {
+        self.tsg_path = Some(path);
+        self
+    }
+
+    /// Sets the path of the source file, which is displayed with the location of its snippets.
+    pub fn source_path(mut self, path: &'a Path) -> Self {
+        self.source_path = Some(path);
+        self
+    }
+
+    /// Sets whether the diagnostic is colored with ANSI escape codes.  It is not by default.
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    /// Returns the message of the diagnostic, which is displayed in its first line.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn style(&self, f: &mut fmt::Formatter, style: &str, text: &str) -> fmt::Result {
+        if self.colored {
+            write!(f, "{}{}{}", style, text, RESET)
+        } else {
+            write!(f, "{}", text)
+        }
+    }
+
+    fn fmt_origin(
+        &self,
+        f: &mut fmt::Formatter,
+        origin: Origin,
+        first: bool,
+        gutter: usize,
+    ) -> fmt::Result {
+        let mut labels = self
+            .labels
+            .iter()
+            .filter(|label| label.origin == origin)
+            .collect::<Vec<_>>();
+        let header = match labels.first() {
+            Some(label) => label,
+            None => return Ok(()),
+        };
+        let path = match origin {
+            Origin::Tsg => self.tsg_path,
+            Origin::Source => self.source_path,
+        };
+        let arrow = if first { "-->" } else { ":::" };
+        write!(f, "{}", " ".repeat(gutter))?;
+        self.style(f, SECONDARY_STYLE, arrow)?;
+        match path {
+            Some(path) => write!(f, " {}:", path.display())?,
+            None => write!(f, " ")?,
+        }
+        writeln!(f, "{}:{}", header.row + 1, header.columns.start + 1)?;
+        self.fmt_gutter(f, gutter, "")?;
+        writeln!(f)?;
+        // labels on the same line stay in the order of importance
+        labels.sort_by_key(|label| label.row);
+        let mut previous_row = None;
+        for label in &labels {
+            if previous_row != Some(label.row) {
+                // a single line between two labeled lines is shown, longer gaps are elided
+                match previous_row {
+                    Some(previous_row) if label.row == previous_row + 2 => {
+                        self.fmt_gutter(f, gutter, &label.row.to_string())?;
+                        writeln!(f, " {}", label.line_at(label.row - 1))?;
+                    }
+                    Some(previous_row) if label.row > previous_row + 2 => {
+                        self.style(f, SECONDARY_STYLE, "...")?;
+                        writeln!(f)?;
+                    }
+                    _ => {}
+                }
+                self.fmt_gutter(f, gutter, &(label.row + 1).to_string())?;
+                writeln!(f, " {}", label.line())?;
+                previous_row = Some(label.row);
+            }
+            let primary = std::ptr::eq(*label, &self.labels[0]);
+            let (style, mark) = if primary {
+                (ERROR_STYLE, "^")
+            } else {
+                (SECONDARY_STYLE, "-")
+            };
+            self.fmt_gutter(f, gutter, "")?;
+            write!(f, " {}", " ".repeat(label.columns.start))?;
+            self.style(f, style, &mark.repeat(label.columns.len()))?;
+            if !label.message.is_empty() {
+                write!(f, " ")?;
+                self.style(f, style, &label.message)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the gutter of a line, with the given line number, which may be empty.
+    fn fmt_gutter(&self, f: &mut fmt::Formatter, gutter: usize, line_number: &str) -> fmt::Result {
+        self.style(
+            f,
+            SECONDARY_STYLE,
+            &format!("{:>width$} |", line_number, width = gutter),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.style(f, ERROR_STYLE, "error")?;
+        self.style(f, EMPHASIS_STYLE, &format!(": {}", self.message))?;
+        writeln!(f)?;
+        let gutter = self
+            .labels
+            .iter()
+            .map(|label| (label.row + 1).to_string().len())
+            .max()
+            .unwrap_or(0);
+        let mut first = true;
+        for origin in [Origin::Tsg, Origin::Source] {
+            if !self.labels.iter().any(|label| label.origin == origin) {
+                continue;
+            }
+            if !first {
+                self.fmt_gutter(f, gutter, "")?;
+                writeln!(f)?;
+            }
+            self.fmt_origin(f, origin, first, gutter)?;
+            first = false;
+        }
+        if !first && !self.notes.is_empty() {
+            self.fmt_gutter(f, gutter, "")?;
+            writeln!(f)?;
+        }
+        for note in &self.notes {
+            write!(f, "{} = ", " ".repeat(gutter))?;
+            self.style(f, EMPHASIS_STYLE, "note")?;
+            writeln!(f, ": {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Label<'a> {
+    /// Creates a label in the graph DSL file that underlines the token at the given location.  A
+    /// parenthesized expression is underlined up to its closing parenthesis if it is on the same
+    /// line, or else up to the end of the line.
+    fn at_token(tsg_source: &'a str, location: Location, message: String) -> Self {
+        let line = line(tsg_source, location.row);
+        let chars = line.chars().skip(location.column).collect::<Vec<_>>();
+        let length = match chars.first() {
+            Some('(') | Some('[') | Some('{') => balanced_length(&chars),
+            Some(c) if is_token_char(*c) => chars.iter().take_while(|c| is_token_char(**c)).count(),
+            _ => 1,
+        };
+        Self {
+            origin: Origin::Tsg,
+            text: tsg_source,
+            row: location.row,
+            columns: location.column..location.column + length,
+            message,
+        }
+    }
+
+    /// Creates a label in the graph DSL file that underlines the rest of the line at the given
+    /// location.
+    fn at_line(tsg_source: &'a str, location: Location, message: String) -> Self {
+        let line = line(tsg_source, location.row);
+        let length = line.trim_end().chars().count().max(location.column + 1) - location.column;
+        Self {
+            origin: Origin::Tsg,
+            text: tsg_source,
+            row: location.row,
+            columns: location.column..location.column + length,
+            message,
+        }
+    }
+
+    /// Creates a label in the source file that underlines the region between the given locations,
+    /// whose columns are counted in bytes, as tree-sitter does.  A region that spans several lines
+    /// is underlined up to the end of its first line.
+    fn at_source_region(source: &'a str, start: Location, end: Location, message: String) -> Self {
+        let line = line(source, start.row);
+        let to_chars = |column: usize| match line.get(..column) {
+            Some(prefix) => prefix.chars().count(),
+            None => column,
+        };
+        let start_column = to_chars(start.column);
+        let end_column = if end.row == start.row {
+            to_chars(end.column)
+        } else {
+            line.trim_end().chars().count()
+        };
+        Self {
+            origin: Origin::Source,
+            text: source,
+            row: start.row,
+            columns: start_column..end_column.max(start_column + 1),
+            message,
+        }
+    }
+
+    fn line(&self) -> &'a str {
+        line(self.text, self.row)
+    }
+
+    fn line_at(&self, row: usize) -> &'a str {
+        line(self.text, row)
+    }
+}
+
+/// Returns a line of a text, without its line terminator, or an empty line if the text has fewer
+/// lines.
+fn line(text: &str, row: usize) -> &str {
+    text.lines().nth(row).unwrap_or_default()
+}
+
+fn first_line(text: &str) -> String {
+    text.lines().next().unwrap_or_default().to_string()
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '@' | '$' | '?' | '!' | '#' | '%')
+}
+
+/// Returns the length of a parenthesized expression, up to its closing parenthesis, skipping
+/// string literals, or the length of the given characters if it is not closed.
+fn balanced_length(chars: &[char]) -> usize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return index + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    chars
+        .iter()
+        .collect::<String>()
+        .trim_end()
+        .chars()
+        .count()
+        .max(1)
+}
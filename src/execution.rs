@@ -5,33 +5,67 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::cell::RefCell;
+use std::ops::Range;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::warn;
 use thiserror::Error;
 use tree_sitter::CaptureQuantifier;
 use tree_sitter::Node;
+use tree_sitter::QueryCapture;
+use tree_sitter::QueryCursor;
 use tree_sitter::QueryMatch;
 use tree_sitter::Tree;
 
+use crate::ast::Aggregate;
+use crate::ast::CaptureAggregate;
 use crate::ast::CreateEdge;
 use crate::ast::File;
+use crate::ast::Global;
 use crate::ast::Stanza;
 use crate::ast::Variable;
 use crate::execution::error::ExecutionError;
+use crate::execution::hook::GraphMutationHook;
+use crate::execution::hook::GraphObserver;
+use crate::execution::hook::ObserverCell;
+use crate::execution::query_cache::QueryCache;
+use crate::execution::source::ParsedSource;
+use crate::execution::usage::ResourceUsage;
+use crate::execution::usage::UsageCounters;
 use crate::functions::Functions;
 use crate::graph::Attributes;
 use crate::graph::Graph;
+use crate::graph::GraphNodeRef;
 use crate::graph::Value;
 use crate::variables::Globals;
 use crate::Identifier;
 use crate::Location;
 
+mod batch;
 pub(crate) mod error;
+pub(crate) mod hook;
 mod lazy;
+mod memo;
+pub(crate) mod predicates;
+pub(crate) mod query_cache;
+pub(crate) mod report;
+pub(crate) mod session;
+pub(crate) mod source;
 mod strict;
+pub(crate) mod usage;
 
 impl File {
     /// Executes this graph DSL file against a source file.  You must provide the parsed syntax
     /// tree (`tree`) as well as the source text that it was parsed from (`source`).  You also
     /// provide the set of functions and global variables that are available during execution.
+    ///
+    /// The syntax tree must have been parsed from the whole source text.  Execution fails with
+    /// [`ExecutionError::TreeSourceMismatch`][] if the tree's root node does not end where the
+    /// source text ends, which catches most cases of executing against a stale tree.  To make
+    /// such mistakes harder, keep the tree and its source text together in a [`ParsedSource`][]
+    /// and use [`execute_parsed`][Self::execute_parsed].
     pub fn execute<'a, 'tree>(
         &self,
         tree: &'tree Tree,
@@ -44,11 +78,45 @@ impl File {
         Ok(graph)
     }
 
+    /// Executes this graph DSL file against a parsed source file, which keeps the syntax tree
+    /// together with the source text it was parsed from.  See [`execute`][Self::execute] for
+    /// details.
+    pub fn execute_parsed<'tree>(
+        &self,
+        parsed: &'tree ParsedSource,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<Graph<'tree>, ExecutionError> {
+        self.execute(&parsed.tree, &parsed.text, config, cancellation_flag)
+    }
+
+    /// Executes this graph DSL file against a source file, returning the resulting graph together
+    /// with the errors of any matches or stanzas that were skipped, as determined by the
+    /// configuration's [`ErrorPolicy`][].  With the default policy, [`ErrorPolicy::Abort`][], the
+    /// list of errors is always empty.
+    pub fn execute_partial<'tree>(
+        &self,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<(Graph<'tree>, Vec<MatchError>), ExecutionError> {
+        let mut graph = Graph::new();
+        let errors =
+            self.execute_partial_into(&mut graph, tree, source, config, cancellation_flag)?;
+        Ok((graph, errors))
+    }
+
     /// Executes this graph DSL file against a source file, saving the results into an existing
     /// `Graph` instance.  You must provide the parsed syntax tree (`tree`) as well as the source
     /// text that it was parsed from (`source`).  You also provide the set of functions and global
     /// variables that are available during execution. This variant is useful when you need to
     /// “pre-seed” the graph with some predefined nodes and/or edges before executing the DSL file.
+    ///
+    /// Executing files against several source files into the same graph combines their results,
+    /// as long as the syntax trees outlive the graph.  A pre-seeded node, such as a shared root,
+    /// can be passed to every execution in a global variable.  To combine graphs that were
+    /// created separately, use [`Graph::merge`][].
     pub fn execute_into<'a, 'tree>(
         &self,
         graph: &mut Graph<'tree>,
@@ -57,14 +125,212 @@ impl File {
         config: &ExecutionConfig,
         cancellation_flag: &dyn CancellationFlag,
     ) -> Result<(), ExecutionError> {
-        if config.lazy {
-            self.execute_lazy_into(graph, tree, source, config, cancellation_flag)
+        self.execute_partial_into(graph, tree, source, config, cancellation_flag)?;
+        Ok(())
+    }
+
+    /// Executes this graph DSL file against a source file, saving the results into an existing
+    /// `Graph` instance, and returning the errors of any matches or stanzas that were skipped, as
+    /// determined by the configuration's [`ErrorPolicy`][].
+    pub fn execute_partial_into<'tree>(
+        &self,
+        graph: &mut Graph<'tree>,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<Vec<MatchError>, ExecutionError> {
+        self.check_invalid_stanzas(config)?;
+        source::check_tree_source(tree, source, config.expected_source_hash)?;
+        graph.reset_attribute_index();
+        let errors = if config.lazy {
+            self.execute_lazy_into(graph, tree, source, config, cancellation_flag)?;
+            Vec::new()
         } else {
-            self.execute_strict_into(graph, tree, source, config, cancellation_flag)
+            self.execute_strict_into(graph, tree, source, config, cancellation_flag)?
+        };
+        self.inherit_attributes(graph);
+        Self::check_orphans(graph, config);
+        Ok(errors)
+    }
+
+    /// Executes this graph DSL file against a source file, like [`execute`][Self::execute], and
+    /// returns the resources that the execution used together with the graph.
+    pub fn execute_with_usage<'tree>(
+        &self,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<(Graph<'tree>, ResourceUsage), ExecutionError> {
+        let mut graph = Graph::new();
+        let mut usage = ResourceUsage::default();
+        self.execute_into_with_usage(
+            &mut graph,
+            tree,
+            source,
+            config,
+            cancellation_flag,
+            &mut usage,
+        )?;
+        Ok((graph, usage))
+    }
+
+    /// Executes this graph DSL file against a source file, saving the results into an existing
+    /// `Graph` instance, like [`execute_into`][Self::execute_into], and stores the resources that
+    /// the execution used in `usage`.  The usage is stored even if the execution fails, so that
+    /// the work done before the failure can be accounted for.
+    pub fn execute_into_with_usage<'tree>(
+        &self,
+        graph: &mut Graph<'tree>,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+        usage: &mut ResourceUsage,
+    ) -> Result<(), ExecutionError> {
+        let start = Instant::now();
+        let counters = UsageCounters::new(self);
+        let result = self.execute_into(
+            graph,
+            tree,
+            source,
+            &config.with_usage(&counters),
+            cancellation_flag,
+        );
+        *usage = counters.into_usage(start.elapsed());
+        result
+    }
+
+    /// Fails if this file has invalid stanzas, unless the configuration allows them, in which case
+    /// a warning is logged for every invalid stanza, which is skipped.
+    fn check_invalid_stanzas(&self, config: &ExecutionConfig) -> Result<(), ExecutionError> {
+        if self.invalid_stanzas.is_empty() {
+            return Ok(());
+        }
+        if !config.allow_partial {
+            return Err(ExecutionError::InvalidStanzas(format!(
+                "at {}",
+                self.invalid_stanzas
+                    .iter()
+                    .map(|range| range.start.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+        for range in &self.invalid_stanzas {
+            warn!("Skipping invalid stanza at {}", range.start);
         }
+        Ok(())
+    }
+
+    /// Applies the file's attribute inheritance directives to a graph, logging a warning for
+    /// every graph node whose inherited value is ambiguous.
+    fn inherit_attributes(&self, graph: &mut Graph) {
+        for inherited in &self.inherited_attributes {
+            let ambiguities = graph.inherit_attribute(
+                inherited.name.clone(),
+                inherited.edge_attribute.clone(),
+                inherited.edge_value.as_str(),
+            );
+            for ambiguity in ambiguities {
+                warn!(
+                    "{} (from {} at {})",
+                    ambiguity, inherited, inherited.location
+                );
+            }
+        }
+    }
+
+    /// Reports, and possibly removes, the orphan graph nodes of a graph, as enabled by the
+    /// configuration, logging a warning for every stanza that created orphans.
+    fn check_orphans(graph: &mut Graph, config: &ExecutionConfig) {
+        let report = if config.prune_orphans {
+            graph.prune_orphans()
+        } else if config.report_orphans {
+            graph.find_orphans()
+        } else {
+            return;
+        };
+        for group in &report.groups {
+            warn!("{}", group);
+        }
+        graph.set_orphan_report(report);
+    }
+
+    /// Executes this graph DSL file using query matches that were produced elsewhere, for instance
+    /// by a combined query that is also used for other purposes.  Each [`ExternalMatch`][]
+    /// identifies the stanza it matches, and provides the syntax nodes for the captures of that
+    /// stanza's query, which is available as [`Stanza::query`][crate::ast::Stanza::query].
+    ///
+    /// The matches can be supplied in any order.  They are executed stanza by stanza, in the order
+    /// in which the stanzas appear in the file, and the matches of each stanza are executed in
+    /// order of the start of the matched syntax node, the same order in which the stanza's own
+    /// query would produce them.  Matches of a stanza that start at the same position are executed
+    /// in the order in which they are supplied.  Matches are always executed strictly; the `lazy`
+    /// setting of the configuration is ignored.
+    pub fn execute_with_matches<'tree, I>(
+        &self,
+        source: &'tree str,
+        matches: I,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<Graph<'tree>, ExecutionError>
+    where
+        I: IntoIterator<Item = ExternalMatch<'tree>>,
+    {
+        let mut graph = Graph::new();
+        self.execute_with_matches_into(&mut graph, source, matches, config, cancellation_flag)?;
+        Ok(graph)
+    }
+
+    /// Executes this graph DSL file using query matches that were produced elsewhere, saving the
+    /// results into an existing `Graph` instance, and returning the errors of any matches or
+    /// stanzas that were skipped, as determined by the configuration's [`ErrorPolicy`][].  See
+    /// [`execute_with_matches`][Self::execute_with_matches] for details.
+    pub fn execute_with_matches_into<'tree, I>(
+        &self,
+        graph: &mut Graph<'tree>,
+        source: &'tree str,
+        matches: I,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<Vec<MatchError>, ExecutionError>
+    where
+        I: IntoIterator<Item = ExternalMatch<'tree>>,
+    {
+        self.check_invalid_stanzas(config)?;
+        graph.reset_attribute_index();
+        let errors =
+            self.execute_external_into(graph, source, matches, config, cancellation_flag)?;
+        self.inherit_attributes(graph);
+        Self::check_orphans(graph, config);
+        Ok(errors)
+    }
+
+    /// Returns the global variables declared by this file that have no default value and are not
+    /// provided by the given variables, in the order in which they are declared.  Execution fails
+    /// with [`ExecutionError::MissingGlobalVariable`][] or
+    /// [`ExecutionError::MissingGlobalVariables`][] if any are missing, before any stanza is
+    /// executed, so hosts can use this to validate their globals up front.
+    pub fn missing_globals(&self, globals: &Globals) -> Vec<&Global> {
+        self.globals
+            .iter()
+            .filter(|global| !global.has_default() && globals.get(&global.name).is_none())
+            .collect()
     }
 
     pub(self) fn check_globals(&self, globals: &mut Globals) -> Result<(), ExecutionError> {
+        let mut missing = self
+            .missing_globals(globals)
+            .into_iter()
+            .map(|global| global.name.as_str().to_string())
+            .collect::<Vec<_>>();
+        if missing.len() == 1 {
+            return Err(ExecutionError::MissingGlobalVariable(missing.remove(0)));
+        } else if !missing.is_empty() {
+            return Err(ExecutionError::MissingGlobalVariables(missing));
+        }
         for global in &self.globals {
             match globals.get(&global.name) {
                 None => {
@@ -77,10 +343,6 @@ impl File {
                                     global.name
                                 ))
                             })?;
-                    } else {
-                        return Err(ExecutionError::MissingGlobalVariable(
-                            global.name.as_str().to_string(),
-                        ));
                     }
                 }
                 Some(value) => {
@@ -196,6 +458,20 @@ impl Stanza {
     }
 }
 
+/// A match of a stanza's query that was produced outside of this library, to be executed using
+/// [`File::execute_with_matches`][].
+#[derive(Clone, Debug)]
+pub struct ExternalMatch<'tree> {
+    /// The index of the matched stanza in the file's [`stanzas`][File::stanzas]
+    pub stanza_index: usize,
+    /// The syntax node matched by the stanza's query as a whole
+    pub node: Node<'tree>,
+    /// The syntax nodes of the named captures of the stanza's query.  Every capture of the query
+    /// must be given as many nodes as its quantifier requires, and captures with multiple nodes are
+    /// given in order.
+    pub captures: Vec<(String, Node<'tree>)>,
+}
+
 pub struct Match<'a, 'tree> {
     mat: QueryMatch<'a, 'tree>,
     full_capture_index: u32,
@@ -249,7 +525,118 @@ impl<'a, 'tree> Match<'a, 'tree> {
     }
 }
 
+thread_local! {
+    /// The query cursors of the current thread that are not in use
+    static QUERY_CURSORS: RefCell<Vec<QueryCursor>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Calls a function with a query cursor, reusing the cursors of earlier executions on the same
+/// thread instead of allocating a new one every time.  The cursor is taken out of the pool while
+/// the function runs, so that nested executions get cursors of their own.
+pub(crate) fn with_query_cursor<R>(f: impl FnOnce(&mut QueryCursor) -> R) -> R {
+    let mut cursor = QUERY_CURSORS
+        .with(|cursors| cursors.borrow_mut().pop())
+        .unwrap_or_else(QueryCursor::new);
+    let result = f(&mut cursor);
+    QUERY_CURSORS.with(|cursors| cursors.borrow_mut().push(cursor));
+    result
+}
+
+/// Returns the text that a `scan` statement matches against for a value: the value itself for
+/// strings, and the source text of syntax nodes, which is borrowed from the source text instead of
+/// being copied.
+pub(crate) fn scan_subject<'a>(
+    value: &'a Value,
+    source: &'a str,
+) -> Result<&'a str, ExecutionError> {
+    match value {
+        Value::String(value) => Ok(value),
+        Value::SyntaxNode(node) => source.get(node.byte_range()).ok_or_else(|| {
+            ExecutionError::TreeSourceMismatch(format!(
+                "syntax node {} is outside the source text",
+                node
+            ))
+        }),
+        _ => Err(ExecutionError::ExpectedString(format!(
+            "or syntax node to scan, got {}",
+            value
+        ))),
+    }
+}
+
+/// The match of the `scan` arm that is being executed
+#[derive(Default)]
+pub(crate) struct ScanMatch {
+    /// The text of the capture groups, starting with the whole match.  Groups that did not take
+    /// part in the match are empty.
+    pub(crate) captures: Vec<String>,
+    /// The offset of the start of the match in the scanned string, in characters
+    pub(crate) start: usize,
+    /// The offset of the end of the match in the scanned string, in characters
+    pub(crate) end: usize,
+}
+
+impl ScanMatch {
+    /// Creates the match of a `scan` arm from the byte ranges of its capture groups in `rest`,
+    /// which is the part of the scanned string that starts `offset` characters into it.
+    pub(crate) fn new(rest: &str, offset: usize, ranges: Vec<Option<Range<usize>>>) -> Self {
+        let range = ranges[0].clone().expect("missing regex capture");
+        let start = offset + rest[..range.start].chars().count();
+        let end = start + rest[range].chars().count();
+        let captures = ranges
+            .into_iter()
+            .map(|range| range.map_or("", |range| &rest[range]).to_string())
+            .collect();
+        Self {
+            captures,
+            start,
+            end,
+        }
+    }
+}
+
+/// Returns the syntax nodes of a match for a capture.
+pub(crate) fn nodes_for_capture_index<'a, 'tree>(
+    captures: &'a [QueryCapture<'tree>],
+    index: u32,
+) -> impl Iterator<Item = Node<'tree>> + 'a {
+    captures
+        .iter()
+        .filter(move |capture| capture.index == index)
+        .map(|capture| capture.node)
+}
+
+impl CaptureAggregate {
+    /// Computes the aggregate of the nodes bound to the capture.
+    pub(crate) fn evaluate<'tree>(
+        &self,
+        graph: &mut Graph<'tree>,
+        mut nodes: impl Iterator<Item = Node<'tree>>,
+    ) -> Value {
+        let node = match self.aggregate {
+            Aggregate::Count => return Value::Integer(nodes.count() as i64),
+            Aggregate::First => nodes.next(),
+            Aggregate::Last => nodes.last(),
+        };
+        match node {
+            Some(node) => graph.add_syntax_node(node).into(),
+            None => Value::Null,
+        }
+    }
+}
+
+/// Identifies an attribute of a graph node or edge, to find the statement that set it.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(crate) enum GraphElementKey {
+    NodeAttribute(GraphNodeRef, Identifier),
+    EdgeAttribute(GraphNodeRef, GraphNodeRef, Identifier),
+}
+
+/// The default maximum depth of evaluation, see [`ExecutionConfig::max_depth`][].
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
 /// Configuration for the execution of a File
+#[derive(Clone)]
 pub struct ExecutionConfig<'a, 'g> {
     pub(crate) functions: &'a Functions,
     pub(crate) globals: &'a Globals<'g>,
@@ -257,6 +644,17 @@ pub struct ExecutionConfig<'a, 'g> {
     pub(crate) location_attr: Option<Identifier>,
     pub(crate) variable_name_attr: Option<Identifier>,
     pub(crate) match_node_attr: Option<Identifier>,
+    pub(crate) on_error: ErrorPolicy,
+    pub(crate) provenance: bool,
+    pub(crate) mutation_hook: Option<&'a dyn GraphMutationHook>,
+    pub(crate) observer: Option<&'a dyn ObserverCell>,
+    pub(crate) expected_source_hash: Option<u64>,
+    pub(crate) report_orphans: bool,
+    pub(crate) prune_orphans: bool,
+    pub(crate) max_depth: usize,
+    pub(crate) allow_partial: bool,
+    pub(crate) query_cache: Option<&'a QueryCache>,
+    pub(crate) usage: Option<&'a UsageCounters>,
 }
 
 impl<'a, 'g> ExecutionConfig<'a, 'g> {
@@ -268,6 +666,17 @@ impl<'a, 'g> ExecutionConfig<'a, 'g> {
             location_attr: None,
             variable_name_attr: None,
             match_node_attr: None,
+            on_error: ErrorPolicy::Abort,
+            provenance: false,
+            mutation_hook: None,
+            observer: None,
+            expected_source_hash: None,
+            report_orphans: false,
+            prune_orphans: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            allow_partial: false,
+            query_cache: None,
+            usage: None,
         }
     }
 
@@ -278,25 +687,180 @@ impl<'a, 'g> ExecutionConfig<'a, 'g> {
         match_node_attr: Identifier,
     ) -> Self {
         Self {
-            functions: self.functions,
-            globals: self.globals,
-            lazy: self.lazy,
             location_attr: location_attr.into(),
             variable_name_attr: variable_name_attr.into(),
             match_node_attr: match_node_attr.into(),
+            ..self
         }
     }
 
     pub fn lazy(self, lazy: bool) -> Self {
+        Self { lazy, ..self }
+    }
+
+    /// Sets what happens when executing a stanza fails for some match.
+    pub fn on_error(self, on_error: ErrorPolicy) -> Self {
+        Self { on_error, ..self }
+    }
+
+    /// Sets whether to record the provenance of attribute values, which can be inspected after
+    /// execution using [`Graph::explain_attribute`][].  Recording provenance costs time and memory,
+    /// and is therefore disabled by default.  Provenance is only recorded by strict execution.
+    pub fn provenance(self, provenance: bool) -> Self {
+        Self { provenance, ..self }
+    }
+
+    /// Sets a hook that decides whether graph nodes, edges, and attributes are added to the graph
+    /// during execution.  Without a hook, all mutations are performed.  The hook is also asked
+    /// about mutations that are later undone because of the [error policy][Self::on_error].
+    pub fn mutation_hook(self, mutation_hook: &'a dyn GraphMutationHook) -> Self {
+        Self {
+            mutation_hook: Some(mutation_hook),
+            ..self
+        }
+    }
+
+    /// Sets an observer that is called whenever a graph node, edge, or attribute is added to the
+    /// graph during execution.  The observer is borrowed mutably while it is called, and can be
+    /// taken back out of its cell after execution.  Without an observer, nothing is called.
+    /// The observer is also called for mutations that are later undone because of the
+    /// [error policy][Self::on_error].
+    pub fn observer<O: GraphObserver>(self, observer: &'a RefCell<O>) -> Self {
         Self {
-            functions: self.functions,
-            globals: self.globals,
-            lazy,
-            location_attr: self.location_attr,
-            variable_name_attr: self.variable_name_attr,
-            match_node_attr: self.match_node_attr,
+            observer: Some(observer),
+            ..self
         }
     }
+
+    /// Sets the hash of the source text that execution expects, as computed by [`source_hash`][].
+    /// Execution fails with [`ExecutionError::TreeSourceMismatch`][] if the source text it is given
+    /// has a different hash.  Without an expected hash, only the cheaper check that the syntax tree
+    /// spans the whole source text is performed.
+    ///
+    /// [`source_hash`]: crate::source_hash
+    pub fn expect_source_hash(self, hash: u64) -> Self {
+        Self {
+            expected_source_hash: Some(hash),
+            ..self
+        }
+    }
+
+    /// Sets whether to look for orphan graph nodes at the end of execution: graph nodes that have
+    /// no attributes, no edges, and are not referred to by any attribute value.  The orphans are
+    /// logged as warnings, grouped by the stanza that created them, and are available from
+    /// [`Graph::orphan_report`][].  The stanzas are only known if
+    /// [`provenance`][Self::provenance] is recorded as well.
+    pub fn report_orphans(self, report_orphans: bool) -> Self {
+        Self {
+            report_orphans,
+            ..self
+        }
+    }
+
+    /// Sets whether to remove orphan graph nodes from the graph at the end of execution, as
+    /// described for [`Graph::prune_orphans`][].  The removed orphans are reported as with
+    /// [`report_orphans`][Self::report_orphans].
+    pub fn prune_orphans(self, prune_orphans: bool) -> Self {
+        Self {
+            prune_orphans,
+            ..self
+        }
+    }
+
+    /// Sets the maximum depth of evaluation.  Lazy evaluation follows chains of variables, such as
+    /// a scoped variable that is defined in terms of the same variable of a parent syntax node,
+    /// whose length can grow with the depth of the syntax tree.  Strict evaluation descends into
+    /// nested statements and expressions, which can be nested as deeply as the file was
+    /// [allowed to nest them][File::from_str_with_max_nesting_depth] when it was parsed.
+    /// Evaluation fails with [`ExecutionError::DepthLimitExceeded`][] instead of exhausting the
+    /// stack when it goes deeper than this.  The default is [`DEFAULT_MAX_DEPTH`][].
+    pub fn max_depth(self, max_depth: usize) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    /// Sets whether files with invalid stanzas, as parsed by
+    /// [`File::from_str_with_recovery`][], can be executed.  The invalid stanzas are skipped, and a
+    /// warning is logged for each of them.  Otherwise, executing such a file fails with
+    /// [`ExecutionError::InvalidStanzas`][], which is the default.
+    pub fn allow_partial(self, allow_partial: bool) -> Self {
+        Self {
+            allow_partial,
+            ..self
+        }
+    }
+
+    /// Sets a cache of the matches of stanza queries, which is shared with other executions on the
+    /// same syntax tree.  Stanzas with the same query as a stanza that was executed earlier with
+    /// the same cache reuse its matches.  See [`QueryCache`][] for details.
+    pub fn query_cache(self, query_cache: &'a QueryCache) -> Self {
+        Self {
+            query_cache: Some(query_cache),
+            ..self
+        }
+    }
+
+    /// Returns a copy of this configuration that records resource usage in the given counters.
+    fn with_usage<'b>(&'b self, usage: &'b UsageCounters) -> ExecutionConfig<'b, 'g> {
+        ExecutionConfig {
+            usage: Some(usage),
+            ..self.clone()
+        }
+    }
+
+    /// Records resource usage, if the execution was asked to account for it.
+    pub(crate) fn record_usage(&self, record: impl FnOnce(&UsageCounters)) {
+        if let Some(usage) = self.usage {
+            record(usage);
+        }
+    }
+}
+
+/// Determines how execution proceeds when executing a stanza fails for some match.
+///
+/// The skip policies only apply to strict execution.  Lazy execution evaluates all statements
+/// after every match has been processed, so errors cannot be attributed to a single match, and
+/// always abort execution.  Cancellation always aborts execution, regardless of the policy.
+///
+/// Undoing a match or stanza restores the graph, its provenance, and all scoped variables, but
+/// does not call the mutation hook or observer again.  They are called as mutations happen, and
+/// can therefore see mutations that are later undone.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorPolicy {
+    /// Abort execution and return the error.  This is the default.
+    #[default]
+    Abort,
+    /// Undo all effects of the failing match, record the error, and continue with the next match.
+    SkipMatch,
+    /// Undo all effects of every match of the failing stanza, record the error, and continue with
+    /// the next stanza.
+    SkipStanza,
+}
+
+/// An error that caused a match or stanza to be skipped during execution
+#[derive(Debug)]
+pub struct MatchError {
+    /// The location of the stanza that failed
+    pub stanza_location: Location,
+    /// The start position of the syntax node matched by the stanza's query
+    pub match_location: Location,
+    /// The underlying execution error
+    pub error: ExecutionError,
+}
+
+impl MatchError {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        matches!(self.error, ExecutionError::Cancelled(_))
+    }
+}
+
+impl std::fmt::Display for MatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Skipped match at {} of stanza at {}: {}",
+            self.match_location, self.stanza_location, self.error
+        )
+    }
 }
 
 /// Trait to signal that the execution is cancelled
@@ -311,6 +875,48 @@ impl CancellationFlag for NoCancellation {
     }
 }
 
+/// A cancellation flag that cancels execution once a deadline has passed.  The deadline is
+/// checked between query matches, between statements, and between the iterations of `scan`
+/// statements.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tree_sitter_graph::CancelAfter;
+/// # use tree_sitter_graph::CancellationFlag;
+/// let flag = CancelAfter::new(Duration::from_secs(60));
+/// assert!(flag.check("executing statement").is_ok());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CancelAfter {
+    deadline: Instant,
+}
+
+impl CancelAfter {
+    /// Creates a flag that cancels execution once the given duration has passed from now.
+    pub fn new(duration: Duration) -> Self {
+        Self::at(Instant::now() + duration)
+    }
+
+    /// Creates a flag that cancels execution once the given deadline has passed.
+    pub fn at(deadline: Instant) -> Self {
+        Self { deadline }
+    }
+
+    /// Returns the deadline after which execution is cancelled.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl CancellationFlag for CancelAfter {
+    fn check(&self, at: &'static str) -> Result<(), CancellationError> {
+        if Instant::now() >= self.deadline {
+            return Err(CancellationError(at));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("Cancelled at \"{0}\"")]
 pub struct CancellationError(pub &'static str);
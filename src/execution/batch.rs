@@ -0,0 +1,322 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Batching of calls to [batchable functions][crate::functions::BatchFunction]
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use log::debug;
+
+use crate::ast;
+use crate::execution::error::ExecutionError;
+use crate::functions::Functions;
+use crate::graph::Graph;
+use crate::graph::Value;
+use crate::Identifier;
+use crate::Location;
+
+/// The calls to batchable functions that were collected ahead of time, and their results once
+/// they have been executed.
+#[derive(Default)]
+pub(crate) struct BatchedCalls {
+    /// The pending calls of each function, with the functions in the order in which they were
+    /// first called
+    pending: Vec<(Identifier, Vec<PendingCall>)>,
+    pending_sets: HashSet<(Identifier, Vec<Value>)>,
+    results: HashMap<Identifier, HashMap<Vec<Value>, Value>>,
+}
+
+/// A call to a batchable function that was collected, but not executed yet
+struct PendingCall {
+    arguments: Vec<Value>,
+    /// The index of the match that the call was first collected for
+    match_index: usize,
+    location: Location,
+}
+
+impl BatchedCalls {
+    /// Collects the arguments of the given calls for the match with the given index.  The value of
+    /// a capture is computed by `capture`.  Calls whose arguments cannot be computed ahead of time
+    /// are skipped.  The results of previously executed batches are discarded, so that every call
+    /// is answered by the batch it was collected for.
+    pub(crate) fn collect<'tree>(
+        &mut self,
+        match_index: usize,
+        calls: &[&ast::Call],
+        functions: &Functions,
+        graph: &mut Graph<'tree>,
+        source: &'tree str,
+        capture: &mut dyn FnMut(&mut Graph<'tree>, &ast::Capture) -> Value,
+    ) {
+        let mut evaluator = StaticEvaluator {
+            functions,
+            graph,
+            source,
+            capture,
+        };
+        self.results.clear();
+        for call in calls {
+            let arguments = match evaluator.evaluate_all(&call.parameters) {
+                Some(arguments) => arguments,
+                None => continue,
+            };
+            let key = (call.function.clone(), arguments);
+            if self.pending_sets.contains(&key) {
+                continue;
+            }
+            let index = match self.pending.iter().position(|(name, _)| name == &key.0) {
+                Some(index) => index,
+                None => {
+                    self.pending.push((key.0.clone(), Vec::new()));
+                    self.pending.len() - 1
+                }
+            };
+            self.pending[index].1.push(PendingCall {
+                arguments: key.1.clone(),
+                match_index,
+                location: call.location,
+            });
+            self.pending_sets.insert(key);
+        }
+    }
+
+    /// Calls each batchable function once, with all of the argument sets collected for it.  If a
+    /// batch fails, its calls are made one at a time instead, to tell the failing calls apart
+    /// from the others.  The results of the calls that succeed are recorded, and the error of a
+    /// failing call of the earliest match is returned together with the index of that match.  The
+    /// calls that fail have no recorded result, so they are made again when their matches are
+    /// executed.
+    pub(crate) fn execute(
+        &mut self,
+        functions: &Functions,
+        graph: &Graph,
+        source: &str,
+    ) -> Result<(), (usize, ExecutionError)> {
+        self.pending_sets.clear();
+        let mut failure = None;
+        for (name, calls) in std::mem::take(&mut self.pending) {
+            debug!(
+                "batched call to {} with {} argument sets",
+                name,
+                calls.len()
+            );
+            let arguments = calls.iter().map(|call| call.arguments.clone()).collect();
+            let results = self.results.entry(name.clone()).or_default();
+            let error = match functions.call_batch(&name, graph, source, arguments) {
+                Ok(values) => {
+                    results.extend(calls.into_iter().map(|call| call.arguments).zip(values));
+                    continue;
+                }
+                Err(error) => error,
+            };
+            debug!(
+                "batched call to {} failed, making its calls individually: {}",
+                name, error
+            );
+            for call in calls {
+                match functions.call_batch(&name, graph, source, vec![call.arguments.clone()]) {
+                    Ok(values) => results.extend(std::iter::once(call.arguments).zip(values)),
+                    Err(error) => {
+                        let earliest = match &failure {
+                            Some((match_index, _)) => call.match_index < *match_index,
+                            None => true,
+                        };
+                        if earliest {
+                            failure = Some((call.match_index, error.at_call(&name, call.location)));
+                        }
+                    }
+                }
+            }
+        }
+        match failure {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the result of a batched call, if it was executed.
+    pub(crate) fn get(&self, function: &Identifier, arguments: &[Value]) -> Option<&Value> {
+        self.results.get(function)?.get(arguments)
+    }
+}
+
+/// Evaluates expressions that can be computed ahead of time, without executing any statements.
+struct StaticEvaluator<'a, 'tree> {
+    functions: &'a Functions,
+    graph: &'a mut Graph<'tree>,
+    source: &'tree str,
+    capture: &'a mut dyn FnMut(&mut Graph<'tree>, &ast::Capture) -> Value,
+}
+
+impl StaticEvaluator<'_, '_> {
+    fn evaluate_all(&mut self, expressions: &[ast::Expression]) -> Option<Vec<Value>> {
+        expressions
+            .iter()
+            .map(|expression| self.evaluate(expression))
+            .collect()
+    }
+
+    fn evaluate(&mut self, expression: &ast::Expression) -> Option<Value> {
+        match expression {
+            ast::Expression::FalseLiteral => Some(Value::Boolean(false)),
+            ast::Expression::NullLiteral => Some(Value::Null),
+            ast::Expression::TrueLiteral => Some(Value::Boolean(true)),
+            ast::Expression::IntegerConstant(expr) => Some(Value::Integer(expr.value)),
+            ast::Expression::FloatConstant(expr) => Some(Value::Float(expr.value)),
+            ast::Expression::StringConstant(expr) => Some(Value::String(expr.value.clone())),
+            ast::Expression::ListLiteral(expr) => {
+                self.evaluate_all(&expr.elements).map(Value::List)
+            }
+            ast::Expression::SetLiteral(expr) => self
+                .evaluate_all(&expr.elements)
+                .map(|elements| Value::Set(elements.into_iter().collect())),
+            ast::Expression::MapLiteral(expr) => expr
+                .entries
+                .iter()
+                .map(|(key, value)| Some((key.clone(), self.evaluate(value)?)))
+                .collect::<Option<_>>()
+                .map(Value::Map),
+            ast::Expression::Capture(expr) => Some((self.capture)(self.graph, expr)),
+            ast::Expression::Call(expr) if self.functions.is_pure(&expr.function) => {
+                let mut arguments = self.evaluate_all(&expr.parameters)?.into_iter();
+                self.functions
+                    .call(&expr.function, self.graph, self.source, &mut arguments)
+                    .ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ast::Stanza {
+    /// Returns the calls to batchable functions in this stanza, in the order in which they appear.
+    pub(crate) fn batchable_calls(&self, functions: &Functions) -> Vec<&ast::Call> {
+        let mut calls = Vec::new();
+        for statement in &self.statements {
+            statement.batchable_calls(functions, &mut calls);
+        }
+        calls
+    }
+}
+
+impl ast::Statement {
+    fn batchable_calls<'a>(&'a self, functions: &Functions, calls: &mut Vec<&'a ast::Call>) {
+        let mut expressions = Vec::new();
+        let mut statements: &[ast::Statement] = &[];
+        match self {
+            Self::DeclareImmutable(stmt) => expressions.push(&stmt.value),
+            Self::DeclareMutable(stmt) => expressions.push(&stmt.value),
+            Self::Assign(stmt) => expressions.push(&stmt.value),
+            Self::CreateGraphNode(stmt) => {
+                expressions.extend(stmt.attributes.iter().map(|attr| &attr.value));
+            }
+            Self::AddGraphNodeAttribute(stmt) => {
+                expressions.push(&stmt.node);
+                expressions.extend(stmt.attributes.iter().map(|attr| &attr.value));
+            }
+            Self::CreateEdge(stmt) => {
+                expressions.extend(vec![&stmt.source, &stmt.sink]);
+                expressions.extend(stmt.attributes.iter().map(|attr| &attr.value));
+            }
+            Self::AddEdgeAttribute(stmt) => {
+                expressions.extend(vec![&stmt.source, &stmt.sink]);
+                expressions.extend(stmt.attributes.iter().map(|attr| &attr.value));
+            }
+            Self::AddAttributes(stmt) => {
+                for target in &stmt.targets {
+                    match target {
+                        ast::AttributeTarget::GraphNode(node) => expressions.push(node),
+                        ast::AttributeTarget::Edge { source, sink } => {
+                            expressions.extend(vec![source, sink])
+                        }
+                    }
+                }
+                expressions.extend(stmt.attributes.iter().map(|attr| &attr.value));
+            }
+            Self::RemoveAttributes(stmt) => {
+                for target in &stmt.targets {
+                    match target {
+                        ast::AttributeTarget::GraphNode(node) => expressions.push(node),
+                        ast::AttributeTarget::Edge { source, sink } => {
+                            expressions.extend(vec![source, sink])
+                        }
+                    }
+                }
+            }
+            Self::Scan(stmt) => {
+                expressions.push(&stmt.value);
+                for arm in &stmt.arms {
+                    for statement in &arm.statements {
+                        statement.batchable_calls(functions, calls);
+                    }
+                }
+            }
+            Self::Print(stmt) => expressions.extend(stmt.values.iter()),
+            Self::If(stmt) => {
+                for arm in &stmt.arms {
+                    for condition in &arm.conditions {
+                        match condition {
+                            ast::Condition::Some { value, .. }
+                            | ast::Condition::None { value, .. }
+                            | ast::Condition::Bool { value, .. } => expressions.push(value),
+                        }
+                    }
+                    for statement in &arm.statements {
+                        statement.batchable_calls(functions, calls);
+                    }
+                }
+            }
+            Self::ForIn(stmt) => {
+                expressions.push(&stmt.value);
+                statements = &stmt.statements;
+            }
+        }
+        for expression in expressions {
+            expression.batchable_calls(functions, calls);
+        }
+        for statement in statements {
+            statement.batchable_calls(functions, calls);
+        }
+    }
+}
+
+impl ast::Expression {
+    fn batchable_calls<'a>(&'a self, functions: &Functions, calls: &mut Vec<&'a ast::Call>) {
+        match self {
+            Self::ListLiteral(expr) => expr
+                .elements
+                .iter()
+                .for_each(|element| element.batchable_calls(functions, calls)),
+            Self::SetLiteral(expr) => expr
+                .elements
+                .iter()
+                .for_each(|element| element.batchable_calls(functions, calls)),
+            Self::MapLiteral(expr) => expr
+                .entries
+                .iter()
+                .for_each(|(_, value)| value.batchable_calls(functions, calls)),
+            Self::ListComprehension(expr) => {
+                expr.element.batchable_calls(functions, calls);
+                expr.value.batchable_calls(functions, calls);
+            }
+            Self::SetComprehension(expr) => {
+                expr.element.batchable_calls(functions, calls);
+                expr.value.batchable_calls(functions, calls);
+            }
+            Self::Call(expr) => {
+                if functions.is_batchable(&expr.function) {
+                    calls.push(expr);
+                }
+                expr.parameters
+                    .iter()
+                    .for_each(|parameter| parameter.batchable_calls(functions, calls));
+            }
+            _ => {}
+        }
+    }
+}
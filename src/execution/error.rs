@@ -11,7 +11,9 @@ use thiserror::Error;
 use crate::ast::Stanza;
 use crate::ast::Statement;
 use crate::execution::CancellationError;
+use crate::functions::ParameterType;
 use crate::parse_error::Excerpt;
+use crate::Identifier;
 use crate::Location;
 
 /// An error that can occur while executing a graph DSL file
@@ -19,12 +21,28 @@ use crate::Location;
 pub enum ExecutionError {
     #[error(transparent)]
     Cancelled(#[from] CancellationError),
+    #[error("Cannot append to non-list attribute {0}")]
+    CannotAppendToAttribute(String),
+    #[error("Cannot override attribute that is not set {0}")]
+    CannotOverrideAttribute(String),
     #[error("Cannot assign immutable variable {0}")]
     CannotAssignImmutableVariable(String),
     #[error("Cannot assign scoped variable {0}")]
     CannotAssignScopedVariable(String),
     #[error("Cannot define mutable scoped variable {0}")]
     CannotDefineMutableScopedVariable(String),
+    #[error("Conflicting syntax nodes {0}")]
+    ConflictingSyntaxNodes(String),
+    /// Evaluation nested deeper than the [maximum depth][crate::ExecutionConfig::max_depth].  The
+    /// location is that of the innermost statement, function call, or variable that was being
+    /// evaluated.
+    #[error("Evaluation exceeded the maximum depth of {max_depth}{}", display_call_location(.location))]
+    DepthLimitExceeded {
+        max_depth: usize,
+        location: Option<Location>,
+    },
+    #[error("Cyclic dependency between variables {}", display_cycle(.0))]
+    DependencyCycle(Vec<DependencyCycleStep>),
     #[error("Duplicate attribute {0}")]
     DuplicateAttribute(String),
     #[error("Duplicate edge {0}")]
@@ -35,24 +53,46 @@ pub enum ExecutionError {
     ExpectedGraphNode(String),
     #[error("Expected a list {0}")]
     ExpectedList(String),
+    #[error("Expected a set {0}")]
+    ExpectedSet(String),
+    #[error("Expected a map {0}")]
+    ExpectedMap(String),
     #[error("Expected a boolean {0}")]
     ExpectedBoolean(String),
     #[error("Expected an integer {0}")]
     ExpectedInteger(String),
+    #[error("Expected a float {0}")]
+    ExpectedFloat(String),
+    #[error("Integer overflow {0}")]
+    IntegerOverflow(String),
+    #[error("Expected a location {0}")]
+    ExpectedLocation(String),
     #[error("Expected a string {0}")]
     ExpectedString(String),
     #[error("Expected a syntax node {0}")]
     ExpectedSyntaxNode(String),
+    #[error("Invalid match {0}")]
+    InvalidMatch(String),
+    #[error("Cannot execute a file with invalid stanzas {0}")]
+    InvalidStanzas(String),
     #[error("Invalid parameters {0}")]
     InvalidParameters(String),
+    #[error("Invalid predicate {0}")]
+    InvalidPredicate(String),
     #[error("Scoped variables can only be attached to syntax nodes {0}")]
     InvalidVariableScope(String),
+    #[error("Graph mutation rejected: {0}")]
+    MutationRejected(String),
     #[error("Missing global variable {0}")]
     MissingGlobalVariable(String),
+    #[error("Missing global variables {}", .0.join(", "))]
+    MissingGlobalVariables(Vec<String>),
     #[error("Recursively defined scoped variable {0}")]
     RecursivelyDefinedScopedVariable(String),
     #[error("Recursively defined variable {0}")]
     RecursivelyDefinedVariable(String),
+    #[error("Syntax tree does not match source text: {0}")]
+    TreeSourceMismatch(String),
     #[error("Undefined capture {0}")]
     UndefinedCapture(String),
     #[error("Undefined function {0}")]
@@ -71,22 +111,123 @@ pub enum ExecutionError {
     VariableScopesAlreadyForced(String),
     #[error("Function {0} failed: {1}")]
     FunctionFailed(String, String),
+    #[error("Function {function} expects {expected} arguments, got {got}{}", display_call_location(.location))]
+    WrongArity {
+        function: String,
+        expected: String,
+        got: usize,
+        location: Option<Location>,
+    },
+    #[error("Function {function} expects argument {} to be {expected}, got {got}", .index + 1)]
+    WrongArgumentType {
+        function: String,
+        index: usize,
+        expected: ParameterType,
+        got: ParameterType,
+    },
     #[error("{0}. Caused by: {1}")]
     InContext(Context, Box<ExecutionError>),
 }
 
+fn display_call_location(location: &Option<Location>) -> String {
+    match location {
+        Some(location) => format!(" at {}", location),
+        None => String::new(),
+    }
+}
+
+/// A variable in a cycle of variables whose values depend on each other, as reported by
+/// [`ExecutionError::DependencyCycle`][]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DependencyCycleStep {
+    /// The variable, as written in the statement that defines it
+    pub variable: String,
+    /// The location of the statement that defines the variable
+    pub statement_location: Location,
+    /// The kind of the syntax node of the variable: the scope of a scoped variable, or the node
+    /// matched by the stanza of a local variable
+    pub node_kind: String,
+    /// The location of the syntax node of the variable
+    pub node_location: Location,
+}
+
+impl std::fmt::Display for DependencyCycleStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} defined at {} on ({}) node at {}",
+            self.variable, self.statement_location, self.node_kind, self.node_location
+        )
+    }
+}
+
+fn display_cycle(steps: &[DependencyCycleStep]) -> String {
+    let mut result = String::new();
+    for step in steps {
+        result += &format!("{} -> ", step);
+    }
+    if let Some(first) = steps.first() {
+        result += &first.variable;
+    }
+    result
+}
+
+/// The context in which an [`ExecutionError::InContext`][] error occurred
 #[derive(Clone, Debug)]
 pub enum Context {
+    /// The statement that was executed, and the match of its stanza.  Errors caused by two
+    /// statements, such as conflicting attribute values, have a context for each.
     Statement(Vec<StatementContext>),
+    /// The call of a function in the graph DSL file that failed
+    Call {
+        /// The name of the function
+        function: String,
+        /// The location of the call in the graph DSL file
+        location: Location,
+    },
+    /// A description of what was being done
     Other(String),
+    /// How much of the execution was done when it was cancelled
+    Progress(ExecutionProgress),
+}
+
+/// How much of an execution was done before it was cancelled, as reported by
+/// [`ExecutionError::progress`][]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ExecutionProgress {
+    /// The number of stanzas whose matches were all executed.  Lazy execution processes the
+    /// matches of all stanzas together, and evaluates their values afterwards, so it never
+    /// completes a stanza before the execution as a whole is done.
+    pub stanzas_completed: usize,
+    /// The number of query matches whose statements were executed
+    pub matches_processed: usize,
 }
 
+impl std::fmt::Display for ExecutionProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Completed {} stanzas and processed {} matches",
+            self.stanzas_completed, self.matches_processed
+        )
+    }
+}
+
+/// A statement of a graph DSL file that was executed when an error occurred, in a [`Context`][]
 #[derive(Clone, Debug)]
 pub struct StatementContext {
+    /// The statement, as displayed by the AST
     pub statement: String,
+    /// The location of the statement in the graph DSL file
     pub statement_location: Location,
+    /// The location of the stanza of the statement in the graph DSL file
     pub stanza_location: Location,
+    /// The location of the syntax node that the stanza's query matched, in the source file
     pub source_location: Location,
+    /// The location of the end of the syntax node that the stanza's query matched, in the source
+    /// file
+    pub source_end_location: Location,
+    /// The kind of the syntax node that the stanza's query matched
     pub node_kind: String,
 }
 
@@ -97,6 +238,7 @@ impl StatementContext {
             statement_location: stmt.location(),
             stanza_location: stanza.range.start,
             source_location: Location::from(source_node.range().start_point),
+            source_end_location: Location::from(source_node.range().end_point),
             node_kind: source_node.kind().to_string(),
         }
     }
@@ -135,7 +277,11 @@ impl std::fmt::Display for Context {
                     first = false;
                 }
             }
+            Self::Call { function, location } => {
+                write!(f, "Calling function {} at {}", function, location)?
+            }
             Self::Other(msg) => write!(f, "{}", msg)?,
+            Self::Progress(progress) => write!(f, "{}", progress)?,
         }
         Ok(())
     }
@@ -170,10 +316,16 @@ impl<R> ResultWithExecutionError<R> for Result<R, ExecutionError> {
     {
         self.map_err(|e| match e {
             cancelled @ ExecutionError::Cancelled(_) => cancelled,
-            in_other_context @ ExecutionError::InContext(Context::Other(_), _) => {
-                ExecutionError::InContext(with_context(), Box::new(in_other_context))
+            ExecutionError::InContext(context @ Context::Statement(_), inner) => {
+                match with_context() {
+                    Context::Statement(_) => ExecutionError::InContext(context, inner),
+                    // A description of what the statement was doing belongs inside its context
+                    other => ExecutionError::InContext(
+                        context,
+                        Box::new(ExecutionError::InContext(other, inner)),
+                    ),
+                }
             }
-            in_stmt_context @ ExecutionError::InContext(_, _) => in_stmt_context,
             _ => ExecutionError::InContext(with_context(), Box::new(e)),
         })
     }
@@ -195,6 +347,95 @@ impl ExecutionError {
             tsg,
         }
     }
+
+    /// Returns the error without the contexts in which it occurred, for matching on the kind of
+    /// error.
+    pub fn root_cause(&self) -> &ExecutionError {
+        let mut error = self;
+        while let Self::InContext(_, cause) = error {
+            error = cause;
+        }
+        error
+    }
+
+    /// Returns the statement that was executed when the error occurred, if it is known.  Errors
+    /// caused by two statements return the first.
+    pub fn statement_context(&self) -> Option<&StatementContext> {
+        let mut error = self;
+        while let Self::InContext(context, cause) = error {
+            if let Context::Statement(statements) = context {
+                return statements.first();
+            }
+            error = cause;
+        }
+        None
+    }
+
+    /// Returns how much of the execution was done, if the execution was cancelled.
+    pub fn progress(&self) -> Option<&ExecutionProgress> {
+        let mut error = self;
+        while let Self::InContext(context, cause) = error {
+            if let Context::Progress(progress) = context {
+                return Some(progress);
+            }
+            error = cause;
+        }
+        None
+    }
+
+    /// Records how much of the execution was done, if this error cancelled it.
+    pub(crate) fn with_progress(self, progress: ExecutionProgress) -> ExecutionError {
+        match self {
+            cancelled @ Self::Cancelled(_) => {
+                Self::InContext(Context::Progress(progress), Box::new(cancelled))
+            }
+            error => error,
+        }
+    }
+
+    /// Records the location of the function call that caused an error.  Arity errors record it
+    /// themselves, and other errors are put in the context of the call.
+    pub(crate) fn at_call(self, function: &Identifier, call_location: Location) -> ExecutionError {
+        match self {
+            Self::WrongArity {
+                function,
+                expected,
+                got,
+                location: None,
+            } => Self::WrongArity {
+                function,
+                expected,
+                got,
+                location: Some(call_location),
+            },
+            error @ (Self::WrongArity { .. } | Self::Cancelled(_)) => error,
+            error => Self::InContext(
+                Context::Call {
+                    function: function.to_string(),
+                    location: call_location,
+                },
+                Box::new(error),
+            ),
+        }
+    }
+
+    /// Records the location at which evaluation exceeded the maximum depth, if this error was
+    /// caused by that, and the location is not known yet.
+    pub(crate) fn at_depth_limit(self, location: Location) -> ExecutionError {
+        match self {
+            Self::DepthLimitExceeded {
+                max_depth,
+                location: None,
+            } => Self::DepthLimitExceeded {
+                max_depth,
+                location: Some(location),
+            },
+            Self::InContext(context, error) => {
+                Self::InContext(context, Box::new(error.at_depth_limit(location)))
+            }
+            error => error,
+        }
+    }
 }
 
 struct DisplayExecutionErrorPretty<'a> {
@@ -236,11 +477,55 @@ impl DisplayExecutionErrorPretty<'_> {
                             first = false;
                         }
                     }
+                    Context::Call { function, location } => {
+                        writeln!(f, "{:>5}: Error calling function {}", index, function)?;
+                        write!(
+                            f,
+                            "{}",
+                            Excerpt::from_source(
+                                self.tsg_path,
+                                self.tsg,
+                                location.row,
+                                location.to_column_range(),
+                                7
+                            )
+                        )?;
+                    }
                     Context::Other(msg) => writeln!(f, "{:>5}: {}", index, msg)?,
+                    Context::Progress(progress) => writeln!(f, "{:>5}: {}", index, progress)?,
                 };
                 self.fmt_entry(f, index + 1, cause)?;
                 Ok(())
             }
+            ExecutionError::DependencyCycle(steps) => {
+                writeln!(f, "{:>5}: Cyclic dependency between variables", index)?;
+                for (i, step) in steps.iter().enumerate() {
+                    writeln!(
+                        f,
+                        "{}{}{} on ({}) node at {}, defined",
+                        " ".repeat(7),
+                        if i == 0 { "" } else { "which depends on " },
+                        step.variable,
+                        step.node_kind,
+                        step.node_location
+                    )?;
+                    write!(
+                        f,
+                        "{}",
+                        Excerpt::from_source(
+                            self.tsg_path,
+                            self.tsg,
+                            step.statement_location.row,
+                            step.statement_location.to_column_range(),
+                            7
+                        )
+                    )?;
+                }
+                if let Some(first) = steps.first() {
+                    writeln!(f, "{}which depends on {}", " ".repeat(7), first.variable)?;
+                }
+                Ok(())
+            }
             other => writeln!(f, "{:>5}: {}", index, other),
         }
     }
@@ -0,0 +1,178 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Hooks that intercept or observe the mutations of a graph during execution
+
+use std::cell::RefCell;
+use std::cell::RefMut;
+
+use crate::execution::error::ExecutionError;
+use crate::graph::Graph;
+use crate::graph::GraphNodeRef;
+use crate::graph::SyntaxNodeRef;
+use crate::graph::Value;
+use crate::Identifier;
+use crate::Location;
+
+/// Decides whether graph nodes, edges, and attributes are added to the graph during execution.
+/// Install a hook using [`ExecutionConfig::mutation_hook`][crate::ExecutionConfig::mutation_hook].
+/// All methods allow the mutation by default.
+///
+/// Hooks see the graph as it has been built so far.  Note that lazy execution creates all graph
+/// nodes and edges before adding any attributes, except the default attributes of [node
+/// types][crate::reference#node-types], so a hook cannot rely on attributes being present when
+/// an edge is created in lazy execution.
+pub trait GraphMutationHook {
+    /// Called before a `node` statement creates a graph node.  If the node is skipped, the
+    /// statement's variable is set to null instead.
+    fn before_node(&self, _ctx: &MutationContext) -> Decision {
+        Decision::Allow
+    }
+
+    /// Called before an `edge` statement creates an edge between two graph nodes.
+    fn before_edge(
+        &self,
+        _source: GraphNodeRef,
+        _sink: GraphNodeRef,
+        _ctx: &MutationContext,
+    ) -> Decision {
+        Decision::Allow
+    }
+
+    /// Called before an `attr` statement adds an attribute to a graph node or edge.
+    fn before_attr(
+        &self,
+        _target: MutationTarget,
+        _name: &Identifier,
+        _value: &Value,
+        _ctx: &MutationContext,
+    ) -> Decision {
+        Decision::Allow
+    }
+}
+
+/// Observes the graph nodes, edges, and attributes that are added to the graph during execution,
+/// for example to stream them into a database while the graph is being built.  Install an
+/// observer using [`ExecutionConfig::observer`][crate::ExecutionConfig::observer].  All methods do
+/// nothing by default.
+///
+/// Observers are called right after a statement mutated the graph, so within a stanza they are
+/// called in the order of its statements.  Lazy execution creates all graph nodes first, then all
+/// edges, and then adds all attributes, so its observers see the mutations in that order, each
+/// in the order of their statements.  Mutations that a [`GraphMutationHook`][] skips are not observed, and
+/// neither are graph nodes that functions create.
+pub trait GraphObserver {
+    /// Called after a `node` statement created a graph node.  The syntax node is the node matched
+    /// by the stanza's query, which is added to the graph.
+    fn on_node_created(
+        &mut self,
+        _node: GraphNodeRef,
+        _syntax_node: SyntaxNodeRef,
+        _ctx: &MutationContext,
+    ) {
+    }
+
+    /// Called after an `edge` statement created an edge between two graph nodes.  Statements for
+    /// an edge that already exists do not create it again.
+    fn on_edge_created(
+        &mut self,
+        _source: GraphNodeRef,
+        _sink: GraphNodeRef,
+        _ctx: &MutationContext,
+    ) {
+    }
+
+    /// Called after an `attr` statement added an attribute to a graph node or edge.  The value is
+    /// the value of the attribute after the statement, which includes the earlier elements if the
+    /// statement appended to the attribute.
+    fn on_attr_added(
+        &mut self,
+        _target: MutationTarget,
+        _name: &Identifier,
+        _value: &Value,
+        _ctx: &MutationContext,
+    ) {
+    }
+}
+
+/// A [`GraphObserver`][] that execution can borrow mutably through a shared configuration
+pub(crate) trait ObserverCell {
+    fn borrow_observer(&self) -> RefMut<'_, dyn GraphObserver>;
+}
+
+impl<O: GraphObserver> ObserverCell for RefCell<O> {
+    fn borrow_observer(&self) -> RefMut<'_, dyn GraphObserver> {
+        RefMut::map(self.borrow_mut(), |observer| {
+            observer as &mut dyn GraphObserver
+        })
+    }
+}
+
+/// The decision of a [`GraphMutationHook`][] about a mutation of the graph
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Decision {
+    /// Perform the mutation.
+    Allow,
+    /// Silently drop the mutation, and continue execution.
+    Skip,
+    /// Fail execution with an [`ExecutionError::MutationRejected`][] error with this message.
+    Reject(String),
+}
+
+impl Decision {
+    /// Returns whether the mutation should be performed, or the error if it is rejected.
+    pub(crate) fn proceed(self) -> Result<bool, ExecutionError> {
+        match self {
+            Decision::Allow => Ok(true),
+            Decision::Skip => Ok(false),
+            Decision::Reject(message) => Err(ExecutionError::MutationRejected(message)),
+        }
+    }
+}
+
+/// The graph node or edge that an attribute is added to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MutationTarget {
+    GraphNode(GraphNodeRef),
+    Edge(GraphNodeRef, GraphNodeRef),
+}
+
+/// The context in which a graph mutation happens
+pub struct MutationContext<'a, 'tree> {
+    graph: &'a Graph<'tree>,
+    location: Location,
+    stanza_location: Location,
+}
+
+impl<'a, 'tree> MutationContext<'a, 'tree> {
+    pub(crate) fn new(
+        graph: &'a Graph<'tree>,
+        location: Location,
+        stanza_location: Location,
+    ) -> Self {
+        Self {
+            graph,
+            location,
+            stanza_location,
+        }
+    }
+
+    /// Returns the graph as it has been built so far.
+    pub fn graph(&self) -> &'a Graph<'tree> {
+        self.graph
+    }
+
+    /// Returns the location of the statement that mutates the graph.
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    /// Returns the location of the stanza that contains the statement.
+    pub fn stanza_location(&self) -> Location {
+        self.stanza_location
+    }
+}
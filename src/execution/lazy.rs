@@ -13,21 +13,36 @@ use log::{debug, trace};
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::ops::Range;
 
-use tree_sitter::QueryCursor;
 use tree_sitter::QueryMatch;
 use tree_sitter::Tree;
 
 use crate::ast;
+use crate::execution::batch::BatchedCalls;
 use crate::execution::error::ExecutionError;
+use crate::execution::error::ExecutionProgress;
 use crate::execution::error::ResultWithExecutionError;
 use crate::execution::error::StatementContext;
+use crate::execution::hook::GraphMutationHook;
+use crate::execution::hook::GraphObserver;
+use crate::execution::hook::MutationContext;
+use crate::execution::hook::MutationTarget;
+use crate::execution::hook::ObserverCell;
+use crate::execution::memo::MemoizedCalls;
+use crate::execution::predicates::StanzaPredicates;
+use crate::execution::scan_subject;
+use crate::execution::usage::UsageCounters;
+use crate::execution::with_query_cursor;
 use crate::execution::ExecutionConfig;
+use crate::execution::GraphElementKey;
+use crate::execution::ScanMatch;
 use crate::functions::Functions;
 use crate::graph;
 use crate::graph::Attributes;
 use crate::graph::Graph;
 use crate::graph::Value;
+use crate::regex_engine::RegexEngine;
 use crate::variables::Globals;
 use crate::variables::MutVariables;
 use crate::variables::VariableMap;
@@ -55,12 +70,8 @@ impl ast::File {
         let mut globals = Globals::nested(config.globals);
         self.check_globals(&mut globals)?;
         let mut config = ExecutionConfig {
-            functions: config.functions,
             globals: &globals,
-            lazy: config.lazy,
-            location_attr: config.location_attr.clone(),
-            variable_name_attr: config.variable_name_attr.clone(),
-            match_node_attr: config.match_node_attr.clone(),
+            ..config.clone()
         };
 
         let mut locals = VariableMap::new();
@@ -70,8 +81,52 @@ impl ast::File {
         let mut function_parameters = Vec::new();
         let mut prev_element_debug_info = HashMap::new();
 
+        let file_query = self.query.as_ref().expect("missing file query");
+        let predicates = self
+            .stanzas
+            .iter()
+            .enumerate()
+            .map(|(index, stanza)| StanzaPredicates::new(stanza, file_query, index, &globals))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let batchable_calls = self
+            .stanzas
+            .iter()
+            .map(|stanza| stanza.batchable_calls(config.functions))
+            .collect::<Vec<_>>();
+        let mut batched_calls = BatchedCalls::default();
+        let mut memoized_calls = MemoizedCalls::default();
+        let mut match_index = 0;
+        let mut progress = ExecutionProgress::default();
+        // the time since the previous match was executed is spent finding the next one
+        let mut previous_match_end = config.usage.and_then(UsageCounters::start_timer);
+
         self.try_visit_matches_lazy(tree, source, |stanza, mat| {
             cancellation_flag.check("processing matches")?;
+            if !predicates[mat.pattern_index].matches(mat.captures, source) {
+                return Ok(());
+            }
+            match_index += 1;
+            config.record_usage(|usage| usage.enter_stanza(mat.pattern_index));
+            config.record_usage(UsageCounters::record_match);
+            let match_start = config.usage.and_then(UsageCounters::start_timer);
+            let calls = &batchable_calls[mat.pattern_index];
+            if !calls.is_empty() {
+                batched_calls.collect(
+                    match_index,
+                    calls,
+                    config.functions,
+                    graph,
+                    source,
+                    &mut |graph, capture| {
+                        Value::from_nodes(
+                            graph,
+                            mat.nodes_for_capture_index(capture.file_capture_index as u32),
+                            capture.quantifier,
+                        )
+                    },
+                );
+            }
             stanza.execute_lazy(
                 source,
                 &mat,
@@ -85,9 +140,26 @@ impl ast::File {
                 &mut prev_element_debug_info,
                 &self.inherited_variables,
                 &self.shorthands,
+                match_index,
+                &mut memoized_calls,
                 cancellation_flag,
-            )
-        })?;
+            )?;
+            progress.matches_processed += 1;
+            if let (Some(previous_end), Some(match_start)) = (previous_match_end, match_start) {
+                let matching_time = match_start.duration_since(previous_end);
+                let execution_time = match_start.elapsed();
+                config.record_usage(|usage| {
+                    usage.record_stanza_time(mat.pattern_index, matching_time, execution_time)
+                });
+                previous_match_end = Some(match_start + execution_time);
+            }
+            Ok(())
+        })
+        .map_err(|error: ExecutionError| error.with_progress(progress))?;
+
+        batched_calls
+            .execute(config.functions, graph, source)
+            .map_err(|(_, error)| error)?;
 
         let mut exec = EvaluationContext {
             source,
@@ -99,12 +171,23 @@ impl ast::File {
             function_parameters: &mut function_parameters,
             prev_element_debug_info: &mut prev_element_debug_info,
             cancellation_flag,
+            mutation_hook: config.mutation_hook,
+            observer: config.observer,
+            batched_calls: Some(&batched_calls),
+            memoized_calls: &mut memoized_calls,
+            depth: 0,
+            max_depth: config.max_depth,
+            usage: config.usage,
         };
-        lazy_graph.evaluate(&mut exec)?;
+        let cancelled = |error: ExecutionError| error.with_progress(progress);
+        lazy_graph.evaluate(&mut exec).map_err(cancelled)?;
         // make sure any unforced values are now forced, to surface any problems
         // hidden by the fact that the values were unused
-        store.evaluate_all(&mut exec)?;
-        scoped_store.evaluate_all(&mut exec)?;
+        store.evaluate_all(&mut exec).map_err(cancelled)?;
+        scoped_store.evaluate_all(&mut exec).map_err(cancelled)?;
+        scoped_store
+            .add_owned_graph_nodes(&mut exec)
+            .map_err(cancelled)?;
 
         Ok(())
     }
@@ -118,14 +201,40 @@ impl ast::File {
     where
         F: FnMut(&ast::Stanza, QueryMatch<'_, 'tree>) -> Result<(), E>,
     {
-        let mut cursor = QueryCursor::new();
-        let query = self.query.as_ref().unwrap();
-        let matches = cursor.matches(query, tree.root_node(), source.as_bytes());
-        for mat in matches {
-            let stanza = &self.stanzas[mat.pattern_index];
-            visit(stanza, mat)?;
-        }
-        Ok(())
+        with_query_cursor(|cursor| {
+            let query = self.query.as_ref().unwrap();
+            if self.phases.is_empty() {
+                let matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+                for mat in matches {
+                    let stanza = &self.stanzas[mat.pattern_index];
+                    visit(stanza, mat)?;
+                }
+                return Ok(());
+            }
+            // All matches of a phase are visited before any match of the next one.  The stanzas,
+            // and therefore the patterns of the file query, are ordered by phase.
+            let mut start = 0;
+            while start < self.stanzas.len() {
+                let phase = &self.stanzas[start].phase;
+                let end = start
+                    + self.stanzas[start..]
+                        .iter()
+                        .take_while(|stanza| &stanza.phase == phase)
+                        .count();
+                if let Some(phase) = phase {
+                    debug!("phase {}", phase);
+                }
+                let matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+                for mat in matches {
+                    if (start..end).contains(&mat.pattern_index) {
+                        let stanza = &self.stanzas[mat.pattern_index];
+                        visit(stanza, mat)?;
+                    }
+                }
+                start = end;
+            }
+            Ok(())
+        })
     }
 }
 
@@ -135,7 +244,7 @@ struct ExecutionContext<'a, 'c, 'g, 'tree> {
     graph: &'a mut Graph<'tree>,
     config: &'a ExecutionConfig<'c, 'g>,
     locals: &'a mut dyn MutVariables<LazyValue>,
-    current_regex_captures: &'a Vec<String>,
+    scan_match: &'a ScanMatch,
     mat: &'a QueryMatch<'a, 'tree>,
     full_match_file_capture_index: usize,
     store: &'a mut LazyStore,
@@ -146,6 +255,10 @@ struct ExecutionContext<'a, 'c, 'g, 'tree> {
     error_context: StatementContext,
     inherited_variables: &'a HashSet<Identifier>,
     shorthands: &'a ast::AttributeShorthands,
+    /// The index of the current match, which keeps the calls to memoized functions of different
+    /// matches apart
+    match_index: usize,
+    memoized_calls: &'a mut MemoizedCalls,
     cancellation_flag: &'a dyn CancellationFlag,
 }
 
@@ -160,12 +273,73 @@ pub(self) struct EvaluationContext<'a, 'tree> {
     pub function_parameters: &'a mut Vec<graph::Value>, // re-usable buffer to reduce memory allocations
     pub prev_element_debug_info: &'a mut HashMap<GraphElementKey, DebugInfo>,
     pub cancellation_flag: &'a dyn CancellationFlag,
+    pub mutation_hook: Option<&'a dyn GraphMutationHook>,
+    pub observer: Option<&'a dyn ObserverCell>,
+    /// The results of calls to batchable functions that were made ahead of time, once the matches
+    /// have been executed
+    pub batched_calls: Option<&'a BatchedCalls>,
+    /// The results of calls to memoized functions, by the match that made them
+    pub memoized_calls: &'a mut MemoizedCalls,
+    /// The number of values that are currently being evaluated, one inside the other
+    pub depth: usize,
+    pub max_depth: usize,
+    pub usage: Option<&'a UsageCounters>,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub(super) enum GraphElementKey {
-    NodeAttribute(graph::GraphNodeRef, Identifier),
-    EdgeAttribute(graph::GraphNodeRef, graph::GraphNodeRef, Identifier),
+impl<'tree> EvaluationContext<'_, 'tree> {
+    /// Records resource usage, if the execution was asked to account for it.
+    pub fn record_usage(&self, record: impl FnOnce(&UsageCounters)) {
+        if let Some(usage) = self.usage {
+            record(usage);
+        }
+    }
+
+    /// Returns the context of a graph mutation by the statement with the given debug info.
+    pub fn mutation_context(&self, debug_info: &DebugInfo) -> MutationContext<'_, 'tree> {
+        MutationContext::new(
+            self.graph,
+            debug_info.location(),
+            debug_info.stanza_location(),
+        )
+    }
+
+    /// Calls the observer of the execution, if there is one, for the statement with the given
+    /// debug info.
+    pub fn observe(
+        &self,
+        debug_info: &DebugInfo,
+        observe: impl FnOnce(&mut dyn GraphObserver, &MutationContext),
+    ) {
+        if let Some(observer) = self.observer {
+            observe(
+                &mut *observer.borrow_observer(),
+                &self.mutation_context(debug_info),
+            );
+        }
+    }
+
+    /// Calls the observer of the execution, if there is one, for an attribute that the statement
+    /// with the given debug info added.
+    pub fn observe_attribute(
+        &self,
+        target: MutationTarget,
+        name: &Identifier,
+        debug_info: &DebugInfo,
+    ) {
+        self.observe(debug_info, |observer, ctx| {
+            let attributes = match target {
+                MutationTarget::GraphNode(node) => &ctx.graph()[node].attributes,
+                MutationTarget::Edge(source, sink) => {
+                    &ctx.graph()[source]
+                        .get_edge(sink)
+                        .expect("missing edge")
+                        .attributes
+                }
+            };
+            let value = attributes.get(name).expect("missing attribute");
+            observer.on_attr_added(target, name, value, ctx)
+        });
+    }
 }
 
 impl ast::Stanza {
@@ -183,9 +357,11 @@ impl ast::Stanza {
         prev_element_debug_info: &mut HashMap<GraphElementKey, DebugInfo>,
         inherited_variables: &HashSet<Identifier>,
         shorthands: &ast::AttributeShorthands,
+        match_index: usize,
+        memoized_calls: &mut MemoizedCalls,
         cancellation_flag: &dyn CancellationFlag,
     ) -> Result<(), ExecutionError> {
-        let current_regex_captures = vec![];
+        let scan_match = ScanMatch::default();
         locals.clear();
         let node = mat
             .nodes_for_capture_index(self.full_match_file_capture_index as u32)
@@ -200,7 +376,7 @@ impl ast::Stanza {
                 graph,
                 config,
                 locals,
-                current_regex_captures: &current_regex_captures,
+                scan_match: &scan_match,
                 mat,
                 full_match_file_capture_index: self.full_match_file_capture_index,
                 store,
@@ -211,6 +387,8 @@ impl ast::Stanza {
                 error_context,
                 inherited_variables,
                 shorthands,
+                match_index,
+                memoized_calls,
                 cancellation_flag,
             };
             statement
@@ -225,6 +403,7 @@ impl ast::Stanza {
 impl ast::Statement {
     fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         exec.cancellation_flag.check("executing statement")?;
+        exec.config.record_usage(UsageCounters::record_statement);
         match self {
             Self::DeclareImmutable(statement) => statement.execute_lazy(exec),
             Self::DeclareMutable(statement) => statement.execute_lazy(exec),
@@ -233,6 +412,8 @@ impl ast::Statement {
             Self::AddGraphNodeAttribute(statement) => statement.execute_lazy(exec),
             Self::CreateEdge(statement) => statement.execute_lazy(exec),
             Self::AddEdgeAttribute(statement) => statement.execute_lazy(exec),
+            Self::AddAttributes(statement) => statement.execute_lazy(exec),
+            Self::RemoveAttributes(statement) => statement.execute_lazy(exec),
             Self::Scan(statement) => statement.execute_lazy(exec),
             Self::Print(statement) => statement.execute_lazy(exec),
             Self::If(statement) => statement.execute_lazy(exec),
@@ -264,7 +445,18 @@ impl ast::Assign {
 
 impl ast::CreateGraphNode {
     fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
+        if let Some(hook) = exec.config.mutation_hook {
+            let ctx = MutationContext::new(
+                exec.graph,
+                exec.error_context.statement_location,
+                exec.error_context.stanza_location,
+            );
+            if !hook.before_node(&ctx).proceed()? {
+                return self.node.add_lazy(exec, graph::Value::Null.into(), false);
+            }
+        }
         let graph_node = exec.graph.add_graph_node();
+        exec.config.record_usage(UsageCounters::record_graph_node);
         self.node
             .add_debug_attrs(&mut exec.graph[graph_node].attributes, exec.config)?;
         if let Some(match_node_attr) = &exec.config.match_node_attr {
@@ -284,7 +476,43 @@ impl ast::CreateGraphNode {
                     ))
                 })?;
         }
-        self.node.add_lazy(exec, graph_node.into(), false)
+        for (name, value) in &self.defaults {
+            exec.graph[graph_node]
+                .attributes
+                .add_default(name.clone(), value.clone());
+        }
+        exec.graph.index_attributes(graph_node);
+        if let Some(observer) = exec.config.observer {
+            let match_node = exec
+                .mat
+                .nodes_for_capture_index(exec.full_match_file_capture_index as u32)
+                .next()
+                .expect("missing capture for full match");
+            let syn_node = exec.graph.add_syntax_node(match_node);
+            let ctx = MutationContext::new(
+                exec.graph,
+                exec.error_context.statement_location,
+                exec.error_context.stanza_location,
+            );
+            observer
+                .borrow_observer()
+                .on_node_created(graph_node, syn_node, &ctx);
+        }
+        self.node.add_lazy(exec, graph_node.into(), false)?;
+        if !self.attributes.is_empty() {
+            let mut attributes = Vec::new();
+            let mut add_attribute = |a| attributes.push(a);
+            for attribute in &self.attributes {
+                attribute.execute_lazy(exec, &mut add_attribute, ast::AttributeOperation::Set)?;
+            }
+            let stmt = LazyAddGraphNodeAttribute::new(
+                graph_node.into(),
+                attributes,
+                exec.error_context.clone().into(),
+            );
+            exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
+        }
+        Ok(())
     }
 }
 
@@ -294,23 +522,54 @@ impl ast::AddGraphNodeAttribute {
         let mut attributes = Vec::new();
         let mut add_attribute = |a| attributes.push(a);
         for attribute in &self.attributes {
-            attribute.execute_lazy(exec, &mut add_attribute)?;
+            attribute.execute_lazy(exec, &mut add_attribute, ast::AttributeOperation::Set)?;
         }
         let stmt =
             LazyAddGraphNodeAttribute::new(node, attributes, exec.error_context.clone().into());
-        exec.lazy_graph.push(stmt.into());
+        exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
         Ok(())
     }
 }
 
 impl ast::CreateEdge {
     fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
-        let source = self.source.evaluate_lazy(exec)?;
-        let sink = self.sink.evaluate_lazy(exec)?;
-        let mut attributes = Attributes::new();
-        self.add_debug_attrs(&mut attributes, exec.config)?;
-        let stmt = LazyCreateEdge::new(source, sink, attributes, exec.error_context.clone().into());
-        exec.lazy_graph.push(stmt.into());
+        let mut source = self.source.evaluate_lazy(exec)?;
+        let mut sink = self.sink.evaluate_lazy(exec)?;
+        if !self.attributes.is_empty() {
+            // the edge and its inline attributes are added by separate statements, which must
+            // evaluate the source and sink only once
+            source = exec
+                .store
+                .add(source, exec.error_context.clone().into())
+                .into();
+            sink = exec
+                .store
+                .add(sink, exec.error_context.clone().into())
+                .into();
+        }
+        let mut debug_attributes = Attributes::new();
+        self.add_debug_attrs(&mut debug_attributes, exec.config)?;
+        let stmt = LazyCreateEdge::new(
+            source.clone(),
+            sink.clone(),
+            debug_attributes,
+            exec.error_context.clone().into(),
+        );
+        exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
+        if !self.attributes.is_empty() {
+            let mut attributes = Vec::new();
+            let mut add_attribute = |a| attributes.push(a);
+            for attribute in &self.attributes {
+                attribute.execute_lazy(exec, &mut add_attribute, ast::AttributeOperation::Set)?;
+            }
+            let stmt = LazyAddEdgeAttribute::new(
+                source,
+                sink,
+                attributes,
+                exec.error_context.clone().into(),
+            );
+            exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
+        }
         Ok(())
     }
 }
@@ -322,59 +581,134 @@ impl ast::AddEdgeAttribute {
         let mut attributes = Vec::new();
         let mut add_attribute = |a| attributes.push(a);
         for attribute in &self.attributes {
-            attribute.execute_lazy(exec, &mut add_attribute)?;
+            attribute.execute_lazy(exec, &mut add_attribute, ast::AttributeOperation::Set)?;
         }
         let stmt =
             LazyAddEdgeAttribute::new(source, sink, attributes, exec.error_context.clone().into());
-        exec.lazy_graph.push(stmt.into());
+        exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
+        Ok(())
+    }
+}
+
+impl ast::AddAttributes {
+    fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
+        let mut targets = Vec::new();
+        for target in &self.targets {
+            targets.push(match target {
+                ast::AttributeTarget::GraphNode(node) => (node.evaluate_lazy(exec)?, None),
+                ast::AttributeTarget::Edge { source, sink } => {
+                    (source.evaluate_lazy(exec)?, Some(sink.evaluate_lazy(exec)?))
+                }
+            });
+        }
+        let mut attributes = Vec::new();
+        let mut add_attribute = |a| attributes.push(a);
+        for attribute in &self.attributes {
+            attribute.execute_lazy(exec, &mut add_attribute, ast::AttributeOperation::Set)?;
+        }
+        let attributes = attributes
+            .into_iter()
+            .map(|attribute: LazyAttribute| {
+                attribute.stored(exec.store, exec.error_context.clone().into())
+            })
+            .collect::<Vec<_>>();
+        for (node_or_source, sink) in targets {
+            let debug_info = exec.error_context.clone().into();
+            let stmt = match sink {
+                None => {
+                    LazyAddGraphNodeAttribute::new(node_or_source, attributes.clone(), debug_info)
+                        .into()
+                }
+                Some(sink) => {
+                    LazyAddEdgeAttribute::new(node_or_source, sink, attributes.clone(), debug_info)
+                        .into()
+                }
+            };
+            exec.lazy_graph.push(exec.mat.pattern_index, stmt);
+        }
+        Ok(())
+    }
+}
+
+impl ast::RemoveAttributes {
+    fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
+        for target in &self.targets {
+            let (node_or_source, sink) = match target {
+                ast::AttributeTarget::GraphNode(node) => (node.evaluate_lazy(exec)?, None),
+                ast::AttributeTarget::Edge { source, sink } => {
+                    (source.evaluate_lazy(exec)?, Some(sink.evaluate_lazy(exec)?))
+                }
+            };
+            let stmt = LazyRemoveAttributes::new(
+                node_or_source,
+                sink,
+                self.names.clone(),
+                exec.error_context.clone().into(),
+            );
+            exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
+        }
         Ok(())
     }
 }
 
 impl ast::Scan {
     fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
-        let match_string = self.value.evaluate_eager(exec)?.into_string()?;
+        let source = exec.source;
+        match self.value.evaluate_eager(exec)? {
+            Value::List(values) => values
+                .iter()
+                .try_for_each(|value| self.scan_lazy(exec, scan_subject(value, source)?)),
+            value => self.scan_lazy(exec, scan_subject(&value, source)?),
+        }
+    }
 
+    /// Runs the arms of this statement over the matches in a text.
+    fn scan_lazy(
+        &self,
+        exec: &mut ExecutionContext,
+        match_string: &str,
+    ) -> Result<(), ExecutionError> {
         let mut i = 0;
-        let mut matches = Vec::new();
+        // the offset of i in characters
+        let mut offset = 0;
         while i < match_string.len() {
-            matches.clear();
+            exec.config.record_usage(|usage| {
+                usage.record_scan_iteration(self.arms.len(), match_string.len() - i)
+            });
+            let mut first_match: Option<(Vec<Option<Range<usize>>>, usize)> = None;
             for (index, arm) in self.arms.iter().enumerate() {
                 exec.cancellation_flag.check("processing scan matches")?;
-                let captures = arm.regex.captures(&match_string[i..]);
-                if let Some(captures) = captures {
-                    if captures
-                        .get(0)
-                        .expect("missing regex capture")
-                        .range()
-                        .is_empty()
-                    {
+                if let Some(captures) = arm.regex.capture_ranges(&match_string[i..]) {
+                    let range = captures[0].as_ref().expect("missing regex capture");
+                    if range.is_empty() {
                         return Err(ExecutionError::EmptyRegexCapture(format!(
                             "for regular expression /{}/",
                             arm.regex
                         )));
                     }
-                    matches.push((captures, index));
+                    let is_first = match &first_match {
+                        Some((first, _)) => {
+                            range.start < first[0].as_ref().expect("missing regex capture").start
+                        }
+                        None => true,
+                    };
+                    if is_first {
+                        first_match = Some((captures, index));
+                    }
                 }
             }
 
-            if matches.is_empty() {
-                return Ok(());
-            }
-
-            matches.sort_by_key(|(captures, index)| {
-                let range = captures.get(0).expect("missing regex capture").range();
-                (range.start, *index)
-            });
-
-            let (regex_captures, block_index) = &matches[0];
-            let arm = &self.arms[*block_index];
+            let (regex_captures, block_index) = match first_match {
+                Some(first_match) => first_match,
+                None => return Ok(()),
+            };
+            let arm = &self.arms[block_index];
+            let match_end = regex_captures[0]
+                .as_ref()
+                .expect("missing regex capture")
+                .end;
 
-            let mut current_regex_captures = Vec::new();
-            for regex_capture in regex_captures.iter() {
-                current_regex_captures
-                    .push(regex_capture.map(|m| m.as_str()).unwrap_or("").to_string());
-            }
+            let scan_match = ScanMatch::new(&match_string[i..], offset, regex_captures);
 
             let mut arm_locals = VariableMap::nested(exec.locals);
             let mut arm_exec = ExecutionContext {
@@ -382,7 +716,7 @@ impl ast::Scan {
                 graph: exec.graph,
                 config: exec.config,
                 locals: &mut arm_locals,
-                current_regex_captures: &current_regex_captures,
+                scan_match: &scan_match,
                 mat: exec.mat,
                 full_match_file_capture_index: exec.full_match_file_capture_index,
                 store: exec.store,
@@ -393,6 +727,8 @@ impl ast::Scan {
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                match_index: exec.match_index,
+                memoized_calls: exec.memoized_calls,
                 cancellation_flag: exec.cancellation_flag,
             };
 
@@ -407,11 +743,8 @@ impl ast::Scan {
                     .with_context(|| arm_exec.error_context.clone().into())?;
             }
 
-            i += regex_captures
-                .get(0)
-                .expect("missing regex capture")
-                .range()
-                .end;
+            i += match_end;
+            offset = scan_match.end;
         }
 
         Ok(())
@@ -430,7 +763,7 @@ impl ast::Print {
             arguments.push(argument);
         }
         let stmt = LazyPrint::new(arguments, exec.error_context.clone().into());
-        exec.lazy_graph.push(stmt.into());
+        exec.lazy_graph.push(exec.mat.pattern_index, stmt.into());
         Ok(())
     }
 }
@@ -449,7 +782,7 @@ impl ast::If {
                     graph: exec.graph,
                     config: exec.config,
                     locals: &mut arm_locals,
-                    current_regex_captures: exec.current_regex_captures,
+                    scan_match: exec.scan_match,
                     mat: exec.mat,
                     full_match_file_capture_index: exec.full_match_file_capture_index,
                     store: exec.store,
@@ -460,6 +793,8 @@ impl ast::If {
                     error_context: exec.error_context.clone(),
                     inherited_variables: exec.inherited_variables,
                     shorthands: exec.shorthands,
+                    match_index: exec.match_index,
+                    memoized_calls: exec.memoized_calls,
                     cancellation_flag: exec.cancellation_flag,
                 };
                 for stmt in &arm.statements {
@@ -490,14 +825,14 @@ impl ast::ForIn {
     fn execute_lazy(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let values = self.value.evaluate_eager(exec)?.into_list()?;
         let mut loop_locals = VariableMap::nested(exec.locals);
-        for value in values {
+        for (index, value) in values.into_iter().enumerate() {
             loop_locals.clear();
             let mut loop_exec = ExecutionContext {
                 source: exec.source,
                 graph: exec.graph,
                 config: exec.config,
                 locals: &mut loop_locals,
-                current_regex_captures: exec.current_regex_captures,
+                scan_match: exec.scan_match,
                 mat: exec.mat,
                 full_match_file_capture_index: exec.full_match_file_capture_index,
                 store: exec.store,
@@ -508,10 +843,16 @@ impl ast::ForIn {
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                match_index: exec.match_index,
+                memoized_calls: exec.memoized_calls,
                 cancellation_flag: exec.cancellation_flag,
             };
             self.variable
                 .add_lazy(&mut loop_exec, value.into(), false)?;
+            if let Some(variable) = &self.index {
+                let index = graph::Value::from(index as i64);
+                variable.add_lazy(&mut loop_exec, index.into(), false)?;
+            }
             for stmt in &self.statements {
                 loop_exec.error_context.statement = format!("{}", stmt);
                 loop_exec.error_context.statement_location = stmt.location();
@@ -529,15 +870,25 @@ impl ast::Expression {
             Self::NullLiteral => Ok(graph::Value::Null.into()),
             Self::TrueLiteral => Ok(true.into()),
             Self::IntegerConstant(expr) => expr.evaluate_lazy(exec),
+            Self::FloatConstant(expr) => expr.evaluate_lazy(exec),
             Self::StringConstant(expr) => expr.evaluate_lazy(exec),
             Self::ListLiteral(expr) => expr.evaluate_lazy(exec),
             Self::SetLiteral(expr) => expr.evaluate_lazy(exec),
+            Self::MapLiteral(expr) => expr.evaluate_lazy(exec),
             Self::ListComprehension(expr) => expr.evaluate_lazy(exec),
             Self::SetComprehension(expr) => expr.evaluate_lazy(exec),
             Self::Capture(expr) => expr.evaluate_lazy(exec),
+            Self::CaptureAggregate(expr) => Ok(expr
+                .evaluate(
+                    exec.graph,
+                    exec.mat
+                        .nodes_for_capture_index(expr.capture.file_capture_index as u32),
+                )
+                .into()),
             Self::Variable(expr) => expr.evaluate_lazy(exec),
             Self::Call(expr) => expr.evaluate_lazy(exec),
             Self::RegexCapture(expr) => expr.evaluate_lazy(exec),
+            Self::MatchOffset(expr) => expr.evaluate_lazy(exec),
         }
     }
 
@@ -553,7 +904,14 @@ impl ast::Expression {
             inherited_variables: exec.inherited_variables,
             function_parameters: exec.function_parameters,
             prev_element_debug_info: exec.prev_element_debug_info,
+            memoized_calls: exec.memoized_calls,
             cancellation_flag: exec.cancellation_flag,
+            mutation_hook: exec.config.mutation_hook,
+            observer: exec.config.observer,
+            batched_calls: None,
+            depth: 0,
+            max_depth: exec.config.max_depth,
+            usage: exec.config.usage,
         })
     }
 }
@@ -564,6 +922,12 @@ impl ast::IntegerConstant {
     }
 }
 
+impl ast::FloatConstant {
+    fn evaluate_lazy(&self, _exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
+        Ok(graph::Value::Float(self.value).into())
+    }
+}
+
 impl ast::StringConstant {
     fn evaluate_lazy(&self, _exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
         Ok(self.value.clone().into())
@@ -592,7 +956,7 @@ impl ast::ListComprehension {
                 graph: exec.graph,
                 config: exec.config,
                 locals: &mut loop_locals,
-                current_regex_captures: exec.current_regex_captures,
+                scan_match: exec.scan_match,
                 mat: exec.mat,
                 full_match_file_capture_index: exec.full_match_file_capture_index,
                 store: exec.store,
@@ -603,6 +967,8 @@ impl ast::ListComprehension {
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                match_index: exec.match_index,
+                memoized_calls: exec.memoized_calls,
                 cancellation_flag: exec.cancellation_flag,
             };
             self.variable
@@ -624,6 +990,16 @@ impl ast::SetLiteral {
     }
 }
 
+impl ast::MapLiteral {
+    fn evaluate_lazy(&self, exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
+        let mut entries = Vec::new();
+        for (key, value) in &self.entries {
+            entries.push((key.clone(), value.evaluate_lazy(exec)?));
+        }
+        Ok(LazyMap::new(entries).into())
+    }
+}
+
 impl ast::SetComprehension {
     fn evaluate_lazy(&self, exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
         let values = self.value.evaluate_eager(exec)?.into_list()?;
@@ -636,7 +1012,7 @@ impl ast::SetComprehension {
                 graph: exec.graph,
                 config: exec.config,
                 locals: &mut loop_locals,
-                current_regex_captures: exec.current_regex_captures,
+                scan_match: exec.scan_match,
                 mat: exec.mat,
                 full_match_file_capture_index: exec.full_match_file_capture_index,
                 store: exec.store,
@@ -647,6 +1023,8 @@ impl ast::SetComprehension {
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                match_index: exec.match_index,
+                memoized_calls: exec.memoized_calls,
                 cancellation_flag: exec.cancellation_flag,
             };
             self.variable
@@ -676,17 +1054,39 @@ impl ast::Call {
         for parameter in &self.parameters {
             parameters.push(parameter.evaluate_lazy(exec)?);
         }
-        Ok(LazyCall::new(self.function.clone(), parameters).into())
+        let memoized_by = exec
+            .config
+            .functions
+            .is_memoized(&self.function)
+            .then_some(exec.match_index);
+        Ok(LazyCall::new(
+            self.function.clone(),
+            parameters,
+            self.location,
+            memoized_by,
+        )
+        .into())
     }
 }
 
 impl ast::RegexCapture {
     fn evaluate_lazy(&self, exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
-        let value = exec.current_regex_captures[self.match_index].clone();
+        let value = exec.scan_match.captures[self.match_index].clone();
         Ok(value.into())
     }
 }
 
+impl ast::MatchOffset {
+    fn evaluate_lazy(&self, exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
+        let offset = if self.end {
+            exec.scan_match.end
+        } else {
+            exec.scan_match.start
+        };
+        Ok(graph::Value::Integer(offset as i64).into())
+    }
+}
+
 impl ast::Variable {
     fn evaluate_lazy(&self, exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
         match self {
@@ -724,7 +1124,8 @@ impl ast::Variable {
 impl ast::ScopedVariable {
     fn evaluate_lazy(&self, exec: &mut ExecutionContext) -> Result<LazyValue, ExecutionError> {
         let scope = self.scope.evaluate_lazy(exec)?;
-        let value = LazyScopedVariable::new(scope, self.name.clone());
+        let order = exec.scoped_store.next_order(exec.mat.pattern_index);
+        let value = LazyScopedVariable::new(scope, self.name.clone(), order);
         Ok(value.into())
     }
 
@@ -734,31 +1135,38 @@ impl ast::ScopedVariable {
         value: LazyValue,
         mutable: bool,
     ) -> Result<(), ExecutionError> {
-        if mutable {
-            return Err(ExecutionError::CannotDefineMutableScopedVariable(format!(
-                "{}",
-                self
-            )));
-        }
         let scope = self.scope.evaluate_lazy(exec)?;
-        let variable = exec.store.add(value, exec.error_context.clone().into());
+        let variable = exec
+            .store
+            .add_variable(self, value, exec.error_context.clone().into());
+        let order = exec.scoped_store.next_order(exec.mat.pattern_index);
         exec.scoped_store.add(
             scope,
             self.name.clone(),
             variable.into(),
+            mutable,
+            order,
             exec.error_context.clone().into(),
         )
     }
 
     fn set_lazy(
         &self,
-        _exec: &mut ExecutionContext,
-        _value: LazyValue,
+        exec: &mut ExecutionContext,
+        value: LazyValue,
     ) -> Result<(), ExecutionError> {
-        Err(ExecutionError::CannotAssignScopedVariable(format!(
-            "{}",
-            self
-        )))
+        let scope = self.scope.evaluate_lazy(exec)?;
+        let variable = exec
+            .store
+            .add_variable(self, value, exec.error_context.clone().into());
+        let order = exec.scoped_store.next_order(exec.mat.pattern_index);
+        exec.scoped_store.set(
+            scope,
+            self.name.clone(),
+            variable.into(),
+            order,
+            exec.error_context.clone().into(),
+        )
     }
 }
 
@@ -786,7 +1194,9 @@ impl ast::UnscopedVariable {
                 self
             )));
         }
-        let value = exec.store.add(value, exec.error_context.clone().into());
+        let value = exec
+            .store
+            .add_variable(self, value, exec.error_context.clone().into());
         exec.locals
             .add(self.name.clone(), value.into(), mutable)
             .map_err(|_| ExecutionError::DuplicateVariable(format!(" local {}", self)))
@@ -803,7 +1213,9 @@ impl ast::UnscopedVariable {
                 self
             )));
         }
-        let value = exec.store.add(value, exec.error_context.clone().into());
+        let value = exec
+            .store
+            .add_variable(self, value, exec.error_context.clone().into());
         exec.locals
             .set(self.name.clone(), value.into())
             .map_err(|_| {
@@ -817,20 +1229,25 @@ impl ast::UnscopedVariable {
 }
 
 impl ast::Attribute {
+    /// Executes the attribute.  If `operation` is not [`Set`][ast::AttributeOperation::Set],
+    /// because this attribute is part of a shorthand that was appended to or overridden, the
+    /// value is appended or overridden whatever the operation of this attribute.
     fn execute_lazy<F>(
         &self,
         exec: &mut ExecutionContext,
         add_attribute: &mut F,
+        operation: ast::AttributeOperation,
     ) -> Result<(), ExecutionError>
     where
         F: FnMut(LazyAttribute) -> (),
     {
         exec.cancellation_flag.check("executing attribute")?;
         let value = self.value.evaluate_lazy(exec)?;
+        let operation = operation.combine(self.operation);
         if let Some(shorthand) = exec.shorthands.get(&self.name) {
-            shorthand.execute_lazy(exec, add_attribute, value)
+            shorthand.execute_lazy(exec, add_attribute, value, operation)
         } else {
-            add_attribute(LazyAttribute::new(self.name.clone(), value));
+            add_attribute(LazyAttribute::new(self.name.clone(), value, operation));
             Ok(())
         }
     }
@@ -842,6 +1259,7 @@ impl ast::AttributeShorthand {
         exec: &mut ExecutionContext,
         add_attribute: &mut F,
         value: LazyValue,
+        operation: ast::AttributeOperation,
     ) -> Result<(), ExecutionError>
     where
         F: FnMut(LazyAttribute) -> (),
@@ -852,7 +1270,7 @@ impl ast::AttributeShorthand {
             graph: exec.graph,
             config: exec.config,
             locals: &mut shorthand_locals,
-            current_regex_captures: exec.current_regex_captures,
+            scan_match: exec.scan_match,
             mat: exec.mat,
             full_match_file_capture_index: exec.full_match_file_capture_index,
             store: exec.store,
@@ -863,11 +1281,15 @@ impl ast::AttributeShorthand {
             error_context: exec.error_context.clone(),
             inherited_variables: exec.inherited_variables,
             shorthands: exec.shorthands,
+            match_index: exec.match_index,
+            memoized_calls: exec.memoized_calls,
             cancellation_flag: exec.cancellation_flag,
         };
-        self.variable.add_lazy(&mut shorthand_exec, value, false)?;
+        if let Some(variable) = &self.variable {
+            variable.add_lazy(&mut shorthand_exec, value, false)?;
+        }
         for attr in &self.attributes {
-            attr.execute_lazy(&mut shorthand_exec, add_attribute)?;
+            attr.execute_lazy(&mut shorthand_exec, add_attribute, operation)?;
         }
         Ok(())
     }
@@ -12,12 +12,17 @@ use log::{debug, trace};
 use std::convert::From;
 use std::fmt;
 
+use crate::ast::AttributeOperation;
+use crate::execution::error::Context;
 use crate::execution::error::ExecutionError;
 use crate::execution::error::ResultWithExecutionError;
+use crate::execution::hook::MutationTarget;
+use crate::execution::usage::UsageCounters;
 use crate::graph::Attributes;
 use crate::Identifier;
 
 use super::store::DebugInfo;
+use super::store::LazyStore;
 use super::values::*;
 use super::EvaluationContext;
 use super::GraphElementKey;
@@ -26,7 +31,10 @@ use super::GraphElementKey;
 #[derive(Debug)]
 pub(super) struct LazyGraph {
     edge_statements: Vec<LazyStatement>,
-    attr_statements: Vec<LazyStatement>,
+    // Attribute statements with the index of their stanza.  They are evaluated in stanza order,
+    // and then in match order, so that appended attribute values are in the same order as in
+    // strict execution.
+    attr_statements: Vec<(usize, LazyStatement)>,
     print_statements: Vec<LazyStatement>,
 }
 
@@ -39,11 +47,14 @@ impl LazyGraph {
         }
     }
 
-    pub(super) fn push(&mut self, stmt: LazyStatement) {
+    pub(super) fn push(&mut self, stanza_index: usize, stmt: LazyStatement) {
         match stmt {
-            LazyStatement::AddGraphNodeAttribute(_) => self.attr_statements.push(stmt),
+            LazyStatement::AddGraphNodeAttribute(_) => {
+                self.attr_statements.push((stanza_index, stmt))
+            }
             LazyStatement::CreateEdge(_) => self.edge_statements.push(stmt),
-            LazyStatement::AddEdgeAttribute(_) => self.attr_statements.push(stmt),
+            LazyStatement::AddEdgeAttribute(_) => self.attr_statements.push((stanza_index, stmt)),
+            LazyStatement::RemoveAttributes(_) => self.attr_statements.push((stanza_index, stmt)),
             LazyStatement::Print(_) => self.print_statements.push(stmt),
         }
     }
@@ -52,7 +63,9 @@ impl LazyGraph {
         for stmt in &self.edge_statements {
             stmt.evaluate(exec)?;
         }
-        for stmt in &self.attr_statements {
+        let mut attr_statements = self.attr_statements.iter().collect::<Vec<_>>();
+        attr_statements.sort_by_key(|(stanza_index, _)| *stanza_index);
+        for (_, stmt) in attr_statements {
             stmt.evaluate(exec)?;
         }
         for stmt in &self.print_statements {
@@ -68,6 +81,7 @@ pub(super) enum LazyStatement {
     AddGraphNodeAttribute(LazyAddGraphNodeAttribute),
     CreateEdge(LazyCreateEdge),
     AddEdgeAttribute(LazyAddEdgeAttribute),
+    RemoveAttributes(LazyRemoveAttributes),
     Print(LazyPrint),
 }
 
@@ -79,15 +93,23 @@ impl LazyStatement {
         let result = match self {
             Self::AddGraphNodeAttribute(stmt) => stmt
                 .evaluate(exec)
+                .map_err(|e| e.at_depth_limit(stmt.debug_info.location()))
                 .with_context(|| stmt.debug_info.clone().into()),
             Self::CreateEdge(stmt) => stmt
                 .evaluate(exec)
+                .map_err(|e| e.at_depth_limit(stmt.debug_info.location()))
                 .with_context(|| stmt.debug_info.clone().into()),
             Self::AddEdgeAttribute(stmt) => stmt
                 .evaluate(exec)
+                .map_err(|e| e.at_depth_limit(stmt.debug_info.location()))
+                .with_context(|| stmt.debug_info.clone().into()),
+            Self::RemoveAttributes(stmt) => stmt
+                .evaluate(exec)
+                .map_err(|e| e.at_depth_limit(stmt.debug_info.location()))
                 .with_context(|| stmt.debug_info.clone().into()),
             Self::Print(stmt) => stmt
                 .evaluate(exec)
+                .map_err(|e| e.at_depth_limit(stmt.debug_info.location()))
                 .with_context(|| stmt.debug_info.clone().into()),
         };
         trace!("}}");
@@ -113,6 +135,12 @@ impl From<LazyCreateEdge> for LazyStatement {
     }
 }
 
+impl From<LazyRemoveAttributes> for LazyStatement {
+    fn from(stmt: LazyRemoveAttributes) -> Self {
+        Self::RemoveAttributes(stmt)
+    }
+}
+
 impl From<LazyPrint> for LazyStatement {
     fn from(stmt: LazyPrint) -> Self {
         Self::Print(stmt)
@@ -125,6 +153,7 @@ impl fmt::Display for LazyStatement {
             Self::AddGraphNodeAttribute(stmt) => stmt.fmt(f),
             Self::CreateEdge(stmt) => stmt.fmt(f),
             Self::AddEdgeAttribute(stmt) => stmt.fmt(f),
+            Self::RemoveAttributes(stmt) => stmt.fmt(f),
             Self::Print(stmt) => stmt.fmt(f),
         }
     }
@@ -158,10 +187,63 @@ impl LazyAddGraphNodeAttribute {
             .with_context(|| "Evaluating target node".to_string().into())?;
         for attribute in &self.attributes {
             let value = attribute.value.evaluate(exec)?;
-            let prev_debug_info = exec.prev_element_debug_info.insert(
-                GraphElementKey::NodeAttribute(node, attribute.name.clone()),
-                self.debug_info.clone(),
-            );
+            if let Some(hook) = exec.mutation_hook {
+                let target = MutationTarget::GraphNode(node);
+                let ctx = exec.mutation_context(&self.debug_info);
+                if !hook
+                    .before_attr(target, &attribute.name, &value, &ctx)
+                    .proceed()?
+                {
+                    continue;
+                }
+            }
+            exec.record_usage(UsageCounters::record_attribute);
+            let key = GraphElementKey::NodeAttribute(node, attribute.name.clone());
+            match attribute.operation {
+                AttributeOperation::Set => {}
+                AttributeOperation::Append => {
+                    let attributes = &mut exec.graph[node].attributes;
+                    if attributes.append(attribute.name.clone(), value).is_err() {
+                        return Err(ExecutionError::CannotAppendToAttribute(format!(
+                            "{} on {}",
+                            attribute.name, node,
+                        )))
+                        .with_context(|| conflict_context(exec, &key, &self.debug_info));
+                    }
+                    exec.prev_element_debug_info
+                        .entry(key)
+                        .or_insert_with(|| self.debug_info.clone());
+                    exec.graph.index_attribute(node, &attribute.name);
+                    exec.observe_attribute(
+                        MutationTarget::GraphNode(node),
+                        &attribute.name,
+                        &self.debug_info,
+                    );
+                    continue;
+                }
+                AttributeOperation::Override => {
+                    let attributes = &mut exec.graph[node].attributes;
+                    if attributes.get(&attribute.name).is_none() {
+                        return Err(ExecutionError::CannotOverrideAttribute(format!(
+                            "{} on {}",
+                            attribute.name, node,
+                        )));
+                    }
+                    attributes.set(attribute.name.clone(), value);
+                    exec.prev_element_debug_info
+                        .insert(key, self.debug_info.clone());
+                    exec.graph.index_attribute(node, &attribute.name);
+                    exec.observe_attribute(
+                        MutationTarget::GraphNode(node),
+                        &attribute.name,
+                        &self.debug_info,
+                    );
+                    continue;
+                }
+            }
+            let prev_debug_info = exec
+                .prev_element_debug_info
+                .insert(key, self.debug_info.clone());
             if let Err(_) = exec.graph[node]
                 .attributes
                 .add(attribute.name.clone(), value)
@@ -178,6 +260,12 @@ impl LazyAddGraphNodeAttribute {
                         .into()
                 });
             };
+            exec.graph.index_attribute(node, &attribute.name);
+            exec.observe_attribute(
+                MutationTarget::GraphNode(node),
+                &attribute.name,
+                &self.debug_info,
+            );
         }
         Ok(())
     }
@@ -193,6 +281,19 @@ impl fmt::Display for LazyAddGraphNodeAttribute {
     }
 }
 
+/// Returns the context of a conflicting attribute statement, which includes the statement that
+/// previously set the attribute, if there was one.
+fn conflict_context(
+    exec: &EvaluationContext,
+    key: &GraphElementKey,
+    debug_info: &DebugInfo,
+) -> Context {
+    match exec.prev_element_debug_info.get(key) {
+        Some(prev_debug_info) => (prev_debug_info.clone().into(), debug_info.clone().into()).into(),
+        None => debug_info.clone().into(),
+    }
+}
+
 /// Lazy statement to create a graph edge
 #[derive(Debug)]
 pub(super) struct LazyCreateEdge {
@@ -226,10 +327,26 @@ impl LazyCreateEdge {
             .sink
             .evaluate_as_graph_node(exec)
             .with_context(|| "Evaluating edge sink".to_string().into())?;
-        let edge = match exec.graph[source].add_edge(sink) {
-            Ok(edge) | Err(edge) => edge,
+        if let Some(hook) = exec.mutation_hook {
+            let ctx = exec.mutation_context(&self.debug_info);
+            if !hook.before_edge(source, sink, &ctx).proceed()? {
+                return Ok(());
+            }
+        }
+        let (edge, created) = match exec.graph[source].add_edge(sink) {
+            Ok(edge) => (edge, true),
+            Err(edge) => (edge, false),
         };
         edge.attributes = self.attributes.clone();
+        if created {
+            exec.record_usage(|usage| {
+                usage.enter_stanza_at(self.debug_info.stanza_location());
+                usage.record_edge();
+            });
+            exec.observe(&self.debug_info, |observer, ctx| {
+                observer.on_edge_created(source, sink, ctx)
+            });
+        }
         Ok(())
     }
 }
@@ -279,6 +396,17 @@ impl LazyAddEdgeAttribute {
             .with_context(|| "Evaluating edge sink".to_string().into())?;
         for attribute in &self.attributes {
             let value = attribute.value.evaluate(exec)?;
+            if let Some(hook) = exec.mutation_hook {
+                let target = MutationTarget::Edge(source, sink);
+                let ctx = exec.mutation_context(&self.debug_info);
+                if !hook
+                    .before_attr(target, &attribute.name, &value, &ctx)
+                    .proceed()?
+                {
+                    continue;
+                }
+            }
+            exec.record_usage(UsageCounters::record_attribute);
             let edge = match exec.graph[source].get_edge_mut(sink) {
                 Some(edge) => Ok(edge),
                 None => Err(ExecutionError::UndefinedEdge(format!(
@@ -286,10 +414,52 @@ impl LazyAddEdgeAttribute {
                     source, sink, self.debug_info,
                 ))),
             }?;
-            let prev_debug_info = exec.prev_element_debug_info.insert(
-                GraphElementKey::EdgeAttribute(source, sink, attribute.name.clone()),
-                self.debug_info.clone(),
-            );
+            let key = GraphElementKey::EdgeAttribute(source, sink, attribute.name.clone());
+            match attribute.operation {
+                AttributeOperation::Set => {}
+                AttributeOperation::Append => {
+                    if edge
+                        .attributes
+                        .append(attribute.name.clone(), value)
+                        .is_err()
+                    {
+                        return Err(ExecutionError::CannotAppendToAttribute(format!(
+                            "{} on edge ({} -> {})",
+                            attribute.name, source, sink,
+                        )))
+                        .with_context(|| conflict_context(exec, &key, &self.debug_info));
+                    }
+                    exec.prev_element_debug_info
+                        .entry(key)
+                        .or_insert_with(|| self.debug_info.clone());
+                    exec.observe_attribute(
+                        MutationTarget::Edge(source, sink),
+                        &attribute.name,
+                        &self.debug_info,
+                    );
+                    continue;
+                }
+                AttributeOperation::Override => {
+                    if edge.attributes.get(&attribute.name).is_none() {
+                        return Err(ExecutionError::CannotOverrideAttribute(format!(
+                            "{} on edge ({} -> {})",
+                            attribute.name, source, sink,
+                        )));
+                    }
+                    edge.attributes.set(attribute.name.clone(), value);
+                    exec.prev_element_debug_info
+                        .insert(key, self.debug_info.clone());
+                    exec.observe_attribute(
+                        MutationTarget::Edge(source, sink),
+                        &attribute.name,
+                        &self.debug_info,
+                    );
+                    continue;
+                }
+            }
+            let prev_debug_info = exec
+                .prev_element_debug_info
+                .insert(key, self.debug_info.clone());
             if let Err(_) = edge.attributes.add(attribute.name.clone(), value) {
                 return Err(ExecutionError::DuplicateAttribute(format!(
                     "{} on edge ({} -> {})",
@@ -303,6 +473,11 @@ impl LazyAddEdgeAttribute {
                         .into()
                 });
             }
+            exec.observe_attribute(
+                MutationTarget::Edge(source, sink),
+                &attribute.name,
+                &self.debug_info,
+            );
         }
         Ok(())
     }
@@ -318,6 +493,89 @@ impl fmt::Display for LazyAddEdgeAttribute {
     }
 }
 
+/// Lazy statement to remove graph node or edge attributes
+#[derive(Debug)]
+pub(super) struct LazyRemoveAttributes {
+    node_or_source: LazyValue,
+    sink: Option<LazyValue>,
+    names: Vec<Identifier>,
+    debug_info: DebugInfo,
+}
+
+impl LazyRemoveAttributes {
+    pub(super) fn new(
+        node_or_source: LazyValue,
+        sink: Option<LazyValue>,
+        names: Vec<Identifier>,
+        debug_info: DebugInfo,
+    ) -> Self {
+        Self {
+            node_or_source,
+            sink,
+            names,
+            debug_info,
+        }
+    }
+
+    pub(super) fn evaluate(&self, exec: &mut EvaluationContext) -> Result<(), ExecutionError> {
+        let node_or_source = self
+            .node_or_source
+            .evaluate_as_graph_node(exec)
+            .with_context(|| "Evaluating target node".to_string().into())?;
+        let sink = match &self.sink {
+            Some(sink) => sink
+                .evaluate_as_graph_node(exec)
+                .with_context(|| "Evaluating edge sink".to_string().into())?,
+            None => {
+                for name in &self.names {
+                    exec.graph[node_or_source].attributes.remove(name);
+                    exec.prev_element_debug_info
+                        .remove(&GraphElementKey::NodeAttribute(
+                            node_or_source,
+                            name.clone(),
+                        ));
+                }
+                return Ok(());
+            }
+        };
+        let edge = match exec.graph[node_or_source].get_edge_mut(sink) {
+            Some(edge) => Ok(edge),
+            None => Err(ExecutionError::UndefinedEdge(format!(
+                "({} -> {}) at {}",
+                node_or_source, sink, self.debug_info,
+            ))),
+        }?;
+        for name in &self.names {
+            edge.attributes.remove(name);
+        }
+        for name in &self.names {
+            exec.prev_element_debug_info
+                .remove(&GraphElementKey::EdgeAttribute(
+                    node_or_source,
+                    sink,
+                    name.clone(),
+                ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LazyRemoveAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.sink {
+            Some(sink) => write!(f, "delattr ({} -> {})", self.node_or_source, sink)?,
+            None => write!(f, "delattr ({})", self.node_or_source)?,
+        }
+        for (index, name) in self.names.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, " {}", name)?;
+        }
+        write!(f, " at {}", self.debug_info)
+    }
+}
+
 /// Lazy statement to print values
 #[derive(Debug)]
 pub(super) struct LazyPrint {
@@ -374,20 +632,34 @@ impl fmt::Display for LazyPrint {
 }
 
 /// Lazy attribute
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(super) struct LazyAttribute {
     name: Identifier,
     value: LazyValue,
+    operation: AttributeOperation,
 }
 
 impl LazyAttribute {
-    pub(super) fn new(name: Identifier, value: LazyValue) -> Self {
-        Self { name, value }
+    pub(super) fn new(name: Identifier, value: LazyValue, operation: AttributeOperation) -> Self {
+        Self {
+            name,
+            value,
+            operation,
+        }
+    }
+
+    /// Moves the attribute's value into the store, so that it is evaluated only once when the
+    /// attribute is added to several targets.
+    pub(super) fn stored(self, store: &mut LazyStore, debug_info: DebugInfo) -> Self {
+        Self {
+            value: store.add(self.value, debug_info).into(),
+            ..self
+        }
     }
 }
 
 impl fmt::Display for LazyAttribute {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} = {}", self.name, self.value,)
+        write!(f, "{} {} {}", self.name, self.operation, self.value)
     }
 }
@@ -16,13 +16,16 @@ use std::fmt;
 use std::rc::Rc;
 
 use crate::execution::error::Context;
+use crate::execution::error::DependencyCycleStep;
 use crate::execution::error::ExecutionError;
 use crate::execution::error::ResultWithExecutionError;
 use crate::execution::error::StatementContext;
 use crate::graph;
 use crate::graph::SyntaxNodeID;
 use crate::graph::SyntaxNodeRef;
+use crate::graph::Tag;
 use crate::Identifier;
+use crate::Location;
 
 use super::values::*;
 use super::EvaluationContext;
@@ -56,70 +59,216 @@ impl fmt::Display for LazyVariable {
 #[derive(Default)]
 pub(super) struct LazyStore {
     elements: Vec<Thunk>,
+    /// The store locations of the thunks that are being forced, in the order in which forcing
+    /// started, which is used to report cycles
+    forcing: RefCell<Vec<usize>>,
 }
 
 impl LazyStore {
     pub(super) fn new() -> Self {
         Self {
             elements: Vec::new(),
+            forcing: RefCell::new(Vec::new()),
         }
     }
 
     pub(super) fn add(&mut self, value: LazyValue, debug_info: DebugInfo) -> LazyVariable {
+        self.add_thunk(Thunk::new(value, None, debug_info))
+    }
+
+    /// Adds the value of a variable to the store.  The variable is named in the error if its value
+    /// turns out to depend on itself.
+    pub(super) fn add_variable<V: fmt::Display>(
+        &mut self,
+        variable: &V,
+        value: LazyValue,
+        debug_info: DebugInfo,
+    ) -> LazyVariable {
+        self.add_thunk(Thunk::new(value, Some(format!("{}", variable)), debug_info))
+    }
+
+    fn add_thunk(&mut self, thunk: Thunk) -> LazyVariable {
         let store_location = self.elements.len();
         let variable = LazyVariable::new(store_location);
-        trace!("store {} = {}", store_location, value);
-        self.elements.push(Thunk::new(value, debug_info));
+        trace!("store {} = {}", store_location, thunk.state.borrow());
+        self.elements.push(thunk);
         variable
     }
 
+    /// Records the syntax node that a scoped variable stored in the given value belongs to.
+    pub(super) fn set_scope(&self, value: &LazyValue, scope: SyntaxNodeRef) {
+        if let LazyValue::Variable(variable) = value {
+            self.elements[variable.store_location]
+                .scope
+                .set(Some(scope));
+        }
+    }
+
     pub(super) fn evaluate(
         &self,
         variable: &LazyVariable,
         exec: &mut EvaluationContext,
     ) -> Result<graph::Value, ExecutionError> {
-        let variable = &self.elements[variable.store_location];
-        let debug_info = variable.debug_info.clone();
-        let value = variable.force(exec).with_context(|| debug_info.0.into())?;
+        let debug_info = self.elements[variable.store_location].debug_info.clone();
+        let value = self
+            .force(variable.store_location, exec)
+            .map_err(|e| e.at_depth_limit(debug_info.location()))
+            .with_context(|| debug_info.0.into())?;
         Ok(value)
     }
 
     pub(super) fn evaluate_all(&self, exec: &mut EvaluationContext) -> Result<(), ExecutionError> {
-        for variable in &self.elements {
+        for (store_location, variable) in self.elements.iter().enumerate() {
             let debug_info = variable.debug_info.clone();
-            variable.force(exec).with_context(|| debug_info.0.into())?;
+            self.force(store_location, exec)
+                .with_context(|| debug_info.0.into())?;
         }
         Ok(())
     }
+
+    fn force(
+        &self,
+        store_location: usize,
+        exec: &mut EvaluationContext,
+    ) -> Result<graph::Value, ExecutionError> {
+        let thunk = &self.elements[store_location];
+        if thunk.is_forcing() {
+            return Err(self.cycle(store_location));
+        }
+        self.forcing.borrow_mut().push(store_location);
+        let value = thunk.force(exec);
+        self.forcing.borrow_mut().pop();
+        value
+    }
+
+    /// Returns the error for a thunk whose value depends on itself, which lists the thunks that
+    /// are being forced, starting from the given one.
+    fn cycle(&self, store_location: usize) -> ExecutionError {
+        let forcing = self.forcing.borrow();
+        let start = forcing
+            .iter()
+            .position(|l| *l == store_location)
+            .unwrap_or(0);
+        let steps = forcing[start..]
+            .iter()
+            .map(|l| self.elements[*l].cycle_step())
+            .collect();
+        ExecutionError::DependencyCycle(steps)
+    }
 }
 
 /// Data structure to hold scoped variables with lazy keys and values
 pub(super) struct LazyScopedVariables {
     variables: HashMap<Identifier, Cell<ScopedValues>>,
+    /// The number of declarations, assignments, and reads of scoped variables so far
+    operations: usize,
+}
+
+/// The position of a declaration, assignment, or read of a scoped variable in execution order.
+/// Strict execution executes all matches of a stanza before the next stanza, so the position is
+/// the index of the stanza, followed by the number of the operation within the whole execution.
+pub(super) type ScopedVariableOrder = (usize, usize);
+
+/// A declaration or assignment of a scoped variable
+struct ScopedEntry {
+    scope: LazyValue,
+    value: LazyValue,
+    debug_info: DebugInfo,
+    order: ScopedVariableOrder,
+    /// Whether the variable is declared mutable, or `None` if this is an assignment
+    declared_mutable: Option<bool>,
+}
+
+/// The values of a scoped variable on one syntax node: the value of its declaration, followed by
+/// the values of its assignments, in execution order
+struct ScopedHistory {
+    mutable: bool,
+    values: Vec<(ScopedVariableOrder, LazyValue)>,
+}
+
+impl ScopedHistory {
+    /// Returns the value that is visible to a read at the given position: the value of the last
+    /// assignment before the read.  Reads that come before the declaration see its value, since
+    /// lazy execution can read scoped variables before the stanza that declares them.
+    fn value_at(&self, order: ScopedVariableOrder) -> &LazyValue {
+        self.values
+            .iter()
+            .rev()
+            .find(|(assigned, _)| *assigned < order)
+            .map(|(_, value)| value)
+            .unwrap_or(&self.values[0].1)
+    }
+
+    fn last_value(&self) -> &LazyValue {
+        &self.values[self.values.len() - 1].1
+    }
 }
 
 impl LazyScopedVariables {
     pub(super) fn new() -> Self {
         LazyScopedVariables {
             variables: HashMap::new(),
+            operations: 0,
         }
     }
 
+    /// Returns the position of the next operation on a scoped variable, which is executed by the
+    /// given stanza.
+    pub(super) fn next_order(&mut self, stanza_index: usize) -> ScopedVariableOrder {
+        self.operations += 1;
+        (stanza_index, self.operations)
+    }
+
     pub(super) fn add(
         &mut self,
         scope: LazyValue,
         name: Identifier,
         value: LazyValue,
+        mutable: bool,
+        order: ScopedVariableOrder,
+        debug_info: DebugInfo,
+    ) -> Result<(), ExecutionError> {
+        self.push(
+            name,
+            ScopedEntry {
+                scope,
+                value,
+                debug_info,
+                order,
+                declared_mutable: Some(mutable),
+            },
+        )
+    }
+
+    pub(super) fn set(
+        &mut self,
+        scope: LazyValue,
+        name: Identifier,
+        value: LazyValue,
+        order: ScopedVariableOrder,
         debug_info: DebugInfo,
     ) -> Result<(), ExecutionError> {
+        self.push(
+            name,
+            ScopedEntry {
+                scope,
+                value,
+                debug_info,
+                order,
+                declared_mutable: None,
+            },
+        )
+    }
+
+    fn push(&mut self, name: Identifier, entry: ScopedEntry) -> Result<(), ExecutionError> {
         let values = self
             .variables
             .entry(name.clone())
             .or_insert_with(|| Cell::new(ScopedValues::new()));
         match values.replace(ScopedValues::Forcing) {
-            ScopedValues::Unforced(mut pairs) => {
-                pairs.push((scope, value, debug_info));
-                values.replace(ScopedValues::Unforced(pairs));
+            ScopedValues::Unforced(mut entries) => {
+                entries.push(entry);
+                values.replace(ScopedValues::Unforced(entries));
                 Ok(())
             }
             ScopedValues::Forcing => Err(ExecutionError::RecursivelyDefinedScopedVariable(
@@ -139,6 +288,7 @@ impl LazyScopedVariables {
         &self,
         scope: &SyntaxNodeRef,
         name: &Identifier,
+        order: ScopedVariableOrder,
         exec: &mut EvaluationContext,
     ) -> Result<LazyValue, ExecutionError> {
         let cell = match self.variables.get(name) {
@@ -155,8 +305,8 @@ impl LazyScopedVariables {
 
         let mut result = None;
 
-        if let Some(value) = map.get(&scope.index) {
-            result = Some(value.clone());
+        if let Some(history) = map.get(&scope.index) {
+            result = Some(history.value_at(order).clone());
         } else if exec.inherited_variables.contains(name) {
             let mut parent = exec
                 .graph
@@ -164,8 +314,8 @@ impl LazyScopedVariables {
                 .get(&scope.index)
                 .and_then(|n| n.parent());
             while let Some(scope) = parent {
-                if let Some(value) = map.get(&(scope.id() as u32)) {
-                    result = Some(value.clone());
+                if let Some(history) = map.get(&(scope.id() as u32)) {
+                    result = Some(history.value_at(order).clone());
                     break;
                 }
                 parent = scope.parent();
@@ -176,10 +326,41 @@ impl LazyScopedVariables {
         result.ok_or_else(|| ExecutionError::UndefinedScopedVariable(format!("{}.{}", scope, name)))
     }
 
+    /// Returns the scoped variables ordered by name.  Forcing a variable can have side effects,
+    /// such as creating graph nodes, so variables must be forced in a deterministic order.
+    fn sorted_variables(&self) -> Vec<(&Identifier, &Cell<ScopedValues>)> {
+        let mut variables = self.variables.iter().collect::<Vec<_>>();
+        variables.sort_by_key(|(name, _)| *name);
+        variables
+    }
+
     pub(super) fn evaluate_all(&self, exec: &mut EvaluationContext) -> Result<(), ExecutionError> {
-        for (name, cell) in &self.variables {
+        for (name, cell) in self.sorted_variables() {
+            let values = cell.replace(ScopedValues::Forcing);
+            let map = self.force(name, values, exec)?;
+            cell.replace(ScopedValues::Forced(map));
+        }
+        Ok(())
+    }
+
+    /// Records every graph node stored in a scoped variable as owned by the variable's scope.
+    pub(super) fn add_owned_graph_nodes(
+        &self,
+        exec: &mut EvaluationContext,
+    ) -> Result<(), ExecutionError> {
+        for (name, cell) in self.sorted_variables() {
             let values = cell.replace(ScopedValues::Forcing);
             let map = self.force(name, values, exec)?;
+            // Syntax node IDs are not stable, so visit the scopes in the order of their
+            // declarations instead.
+            let mut histories = map.iter().collect::<Vec<_>>();
+            histories.sort_by_key(|(_, history)| history.values[0].0);
+            for (scope, history) in histories {
+                if let graph::Value::GraphNode(node) = history.last_value().evaluate(exec)? {
+                    exec.graph
+                        .add_owned_graph_node(*scope, Tag::from(name.clone()), node);
+                }
+            }
             cell.replace(ScopedValues::Forced(map));
         }
         Ok(())
@@ -190,35 +371,58 @@ impl LazyScopedVariables {
         name: &Identifier,
         values: ScopedValues,
         exec: &mut EvaluationContext,
-    ) -> Result<HashMap<SyntaxNodeID, LazyValue>, ExecutionError> {
+    ) -> Result<HashMap<SyntaxNodeID, ScopedHistory>, ExecutionError> {
         match values {
-            ScopedValues::Unforced(pairs) => {
-                let mut values = HashMap::new();
+            ScopedValues::Unforced(mut entries) => {
+                entries.sort_by_key(|entry| entry.order);
+                let mut values = HashMap::<SyntaxNodeID, ScopedHistory>::new();
                 let mut debug_infos = HashMap::new();
-                for (scope, value, debug_info) in pairs.into_iter() {
-                    let node = scope
+                for entry in entries.into_iter() {
+                    let debug_info = entry.debug_info;
+                    let node = entry
+                        .scope
                         .evaluate_as_syntax_node(exec)
                         .with_context(|| format!("Evaluating scope of variable _.{}", name,).into())
                         .with_context(|| debug_info.0.clone().into())?;
-                    match (
-                        values.insert(node.index, value.clone()),
-                        debug_infos.insert(node.index, debug_info.clone()),
-                    ) {
-                        (Some(_), Some(prev_debug_info)) => {
-                            return Err(ExecutionError::DuplicateVariable(format!(
-                                "{}.{}",
-                                node, name,
-                            )))
-                            .with_context(|| (prev_debug_info.0, debug_info.0).into());
+                    exec.store.set_scope(&entry.value, node);
+                    match entry.declared_mutable {
+                        Some(mutable) => {
+                            if let Some(prev_debug_info) =
+                                debug_infos.insert(node.index, debug_info.clone())
+                            {
+                                return Err(ExecutionError::DuplicateVariable(format!(
+                                    "{}.{}",
+                                    node, name,
+                                )))
+                                .with_context(|| (prev_debug_info.0, debug_info.0).into());
+                            }
+                            values.insert(
+                                node.index,
+                                ScopedHistory {
+                                    mutable,
+                                    values: vec![(entry.order, entry.value)],
+                                },
+                            );
                         }
-                        (Some(_), None) => {
-                            unreachable!(
-                                "previous value for syntax node {} without previous debug info",
-                                node
-                            )
-                        }
-                        _ => {}
-                    };
+                        None => match values.get_mut(&node.index) {
+                            Some(history) if history.mutable => {
+                                history.values.push((entry.order, entry.value));
+                            }
+                            Some(_) => {
+                                return Err(ExecutionError::CannotAssignImmutableVariable(
+                                    format!("{}.{}", node, name),
+                                ))
+                                .with_context(|| debug_info.0.into());
+                            }
+                            None => {
+                                return Err(ExecutionError::UndefinedScopedVariable(format!(
+                                    "{}.{}",
+                                    node, name,
+                                )))
+                                .with_context(|| debug_info.0.into());
+                            }
+                        },
+                    }
                 }
                 Ok(values)
             }
@@ -231,9 +435,9 @@ impl LazyScopedVariables {
 }
 
 enum ScopedValues {
-    Unforced(Vec<(LazyValue, LazyValue, DebugInfo)>),
+    Unforced(Vec<ScopedEntry>),
     Forcing,
-    Forced(HashMap<SyntaxNodeID, LazyValue>),
+    Forced(HashMap<SyntaxNodeID, ScopedHistory>),
 }
 
 impl ScopedValues {
@@ -245,6 +449,10 @@ impl ScopedValues {
 /// Thunk holding a lazy value or a forced graph value
 struct Thunk {
     state: Rc<RefCell<ThunkState>>,
+    /// The variable whose value this is, if any
+    variable: Option<String>,
+    /// The syntax node of the scoped variable whose value this is, once its scope is known
+    scope: Cell<Option<SyntaxNodeRef>>,
     debug_info: DebugInfo,
 }
 
@@ -265,13 +473,19 @@ impl fmt::Display for ThunkState {
 }
 
 impl Thunk {
-    fn new(value: LazyValue, debug_info: DebugInfo) -> Thunk {
+    fn new(value: LazyValue, variable: Option<String>, debug_info: DebugInfo) -> Thunk {
         Thunk {
             state: Rc::new(RefCell::new(ThunkState::Unforced(value))),
+            variable,
+            scope: Cell::new(None),
             debug_info,
         }
     }
 
+    fn is_forcing(&self) -> bool {
+        matches!(*self.state.borrow(), ThunkState::Forcing)
+    }
+
     fn force(&self, exec: &mut EvaluationContext) -> Result<graph::Value, ExecutionError> {
         let state = self.state.replace(ThunkState::Forcing);
         trace!("force {}", state);
@@ -290,12 +504,41 @@ impl Thunk {
         *self.state.borrow_mut() = ThunkState::Forced(value.clone());
         Ok(value)
     }
+
+    /// Describes this thunk as a step in a cycle.  The syntax node is the scope of a scoped
+    /// variable, or else the node matched by the stanza that defined the value.
+    fn cycle_step(&self) -> DependencyCycleStep {
+        let context = &self.debug_info.0;
+        let (node_kind, node_location) = match self.scope.get() {
+            Some(scope) => (scope.kind().to_string(), scope.location()),
+            None => (context.node_kind.clone(), context.source_location),
+        };
+        DependencyCycleStep {
+            variable: self
+                .variable
+                .clone()
+                .unwrap_or_else(|| context.statement.clone()),
+            statement_location: context.statement_location,
+            node_kind,
+            node_location,
+        }
+    }
 }
 
 /// Debug info for tracking origins of values
 #[derive(Debug, Clone)]
 pub(super) struct DebugInfo(StatementContext);
 
+impl DebugInfo {
+    pub(super) fn location(&self) -> Location {
+        self.0.statement_location
+    }
+
+    pub(super) fn stanza_location(&self) -> Location {
+        self.0.stanza_location
+    }
+}
+
 impl From<StatementContext> for DebugInfo {
     fn from(value: StatementContext) -> Self {
         Self(value)
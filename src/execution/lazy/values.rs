@@ -18,6 +18,7 @@ use crate::graph::GraphNodeRef;
 use crate::graph::SyntaxNodeRef;
 use crate::graph::Value;
 use crate::Identifier;
+use crate::Location;
 
 use super::store::*;
 use super::EvaluationContext;
@@ -28,6 +29,7 @@ pub(super) enum LazyValue {
     Value(Value),
     List(LazyList),
     Set(LazySet),
+    Map(LazyMap),
     Variable(LazyVariable),
     ScopedVariable(LazyScopedVariable),
     Call(LazyCall),
@@ -45,8 +47,8 @@ impl From<bool> for LazyValue {
     }
 }
 
-impl From<u32> for LazyValue {
-    fn from(value: u32) -> Self {
+impl From<i64> for LazyValue {
+    fn from(value: i64) -> Self {
         LazyValue::Value(value.into())
     }
 }
@@ -99,6 +101,12 @@ impl From<LazySet> for LazyValue {
     }
 }
 
+impl From<LazyMap> for LazyValue {
+    fn from(value: LazyMap) -> Self {
+        LazyValue::Map(value)
+    }
+}
+
 impl From<LazyVariable> for LazyValue {
     fn from(value: LazyVariable) -> Self {
         LazyValue::Variable(value)
@@ -120,15 +128,25 @@ impl From<LazyCall> for LazyValue {
 impl LazyValue {
     pub(super) fn evaluate(&self, exec: &mut EvaluationContext) -> Result<Value, ExecutionError> {
         exec.cancellation_flag.check("evaluating value")?;
+        if exec.depth >= exec.max_depth {
+            return Err(ExecutionError::DepthLimitExceeded {
+                max_depth: exec.max_depth,
+                location: None,
+            });
+        }
         trace!("eval {} {{", self);
+        exec.depth += 1;
         let ret = match self {
             Self::Value(value) => Ok(value.clone()),
             Self::List(expr) => expr.evaluate(exec),
             Self::Set(expr) => expr.evaluate(exec),
+            Self::Map(expr) => expr.evaluate(exec),
             Self::Variable(expr) => expr.evaluate(exec),
             Self::ScopedVariable(expr) => expr.evaluate(exec),
             Self::Call(expr) => expr.evaluate(exec),
-        }?;
+        };
+        exec.depth -= 1;
+        let ret = ret?;
         trace!("}} = {}", ret);
         Ok(ret)
     }
@@ -162,6 +180,7 @@ impl fmt::Display for LazyValue {
             Self::Value(value) => write!(f, "{}", value),
             Self::List(expr) => expr.fmt(f),
             Self::Set(expr) => expr.fmt(f),
+            Self::Map(expr) => expr.fmt(f),
             Self::Variable(expr) => expr.fmt(f),
             Self::ScopedVariable(expr) => expr.fmt(f),
             Self::Call(expr) => expr.fmt(f),
@@ -174,13 +193,16 @@ impl fmt::Display for LazyValue {
 pub(super) struct LazyScopedVariable {
     scope: Box<LazyValue>,
     name: Identifier,
+    /// The position of the read in execution order, which determines the assignments it sees
+    order: ScopedVariableOrder,
 }
 
 impl LazyScopedVariable {
-    pub(super) fn new(scope: LazyValue, name: Identifier) -> Self {
+    pub(super) fn new(scope: LazyValue, name: Identifier, order: ScopedVariableOrder) -> Self {
         Self {
             scope: scope.into(),
             name,
+            order,
         }
     }
 
@@ -191,7 +213,7 @@ impl LazyScopedVariable {
             .evaluate_as_syntax_node(exec)
             .with_context(|| format!("Evaluating scope of variable _.{}", self.name).into())?;
         let scoped_store = &exec.scoped_store;
-        scoped_store.evaluate(&scope, &self.name, exec)
+        scoped_store.evaluate(&scope, &self.name, self.order, exec)
     }
 
     pub(super) fn evaluate(&self, exec: &mut EvaluationContext) -> Result<Value, ExecutionError> {
@@ -223,7 +245,9 @@ impl LazyList {
             .iter()
             .map(|e| e.evaluate(exec))
             .collect::<Result<_, _>>()?;
-        Ok(Value::List(elements))
+        let value = Value::List(elements);
+        exec.record_usage(|usage| usage.record_value(&value));
+        Ok(value)
     }
 }
 
@@ -280,35 +304,109 @@ impl fmt::Display for LazySet {
     }
 }
 
+/// Lazy map literal
+#[derive(Clone, Debug)]
+pub(super) struct LazyMap {
+    entries: Vec<(Identifier, LazyValue)>,
+}
+
+impl LazyMap {
+    pub(super) fn new(entries: Vec<(Identifier, LazyValue)>) -> Self {
+        Self { entries }
+    }
+
+    pub(super) fn evaluate(&self, exec: &mut EvaluationContext) -> Result<Value, ExecutionError> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value.evaluate(exec)?)))
+            .collect::<Result<_, ExecutionError>>()?;
+        Ok(Value::Map(entries))
+    }
+}
+
+impl fmt::Display for LazyMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(map")?;
+        for (key, value) in &self.entries {
+            write!(f, " {}: {}", key, value)?;
+        }
+        write!(f, ")")
+    }
+}
+
 /// Lazy function call
 #[derive(Clone, Debug)]
 pub(super) struct LazyCall {
     function: Identifier,
     arguments: Vec<LazyValue>,
+    location: Location,
+    /// The index of the match that made the call, if the function is memoized
+    memoized_by: Option<usize>,
 }
 
 impl LazyCall {
-    pub(super) fn new(function: Identifier, arguments: Vec<LazyValue>) -> Self {
+    pub(super) fn new(
+        function: Identifier,
+        arguments: Vec<LazyValue>,
+        location: Location,
+        memoized_by: Option<usize>,
+    ) -> Self {
         Self {
             function,
             arguments,
+            location,
+            memoized_by,
         }
     }
 
     pub(super) fn evaluate(&self, exec: &mut EvaluationContext) -> Result<Value, ExecutionError> {
+        exec.record_usage(|usage| usage.record_call(&self.function));
+        let value = self
+            .call(exec)
+            .map_err(|e| e.at_depth_limit(self.location))?;
+        exec.record_usage(|usage| usage.record_value(&value));
+        Ok(value)
+    }
+
+    fn call(&self, exec: &mut EvaluationContext) -> Result<Value, ExecutionError> {
         for argument in &self.arguments {
             let argument = argument.evaluate(exec)?;
             exec.function_parameters.push(argument);
         }
+        let start = exec.function_parameters.len() - self.arguments.len();
+        if let Some(value) = exec
+            .batched_calls
+            .and_then(|batched| batched.get(&self.function, &exec.function_parameters[start..]))
+        {
+            exec.function_parameters.truncate(start);
+            return Ok(value.clone());
+        }
+        if let Some(match_index) = self.memoized_by {
+            let arguments = exec.function_parameters.split_off(start);
+            return exec
+                .memoized_calls
+                .call(
+                    match_index,
+                    exec.functions,
+                    &self.function,
+                    exec.graph,
+                    exec.source,
+                    arguments,
+                )
+                .map_err(|e| e.at_call(&self.function, self.location));
+        }
 
-        exec.functions.call(
-            &self.function,
-            exec.graph,
-            exec.source,
-            &mut exec
-                .function_parameters
-                .drain(exec.function_parameters.len() - self.arguments.len()..),
-        )
+        exec.functions
+            .call(
+                &self.function,
+                exec.graph,
+                exec.source,
+                &mut exec
+                    .function_parameters
+                    .drain(exec.function_parameters.len() - self.arguments.len()..),
+            )
+            .map_err(|e| e.at_call(&self.function, self.location))
     }
 }
 
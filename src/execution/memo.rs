@@ -0,0 +1,52 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Memoization of calls to [memoized functions][crate::functions::Functions::add_memoized]
+
+use std::collections::HashMap;
+
+use crate::execution::error::ExecutionError;
+use crate::functions::Functions;
+use crate::graph::Graph;
+use crate::graph::Value;
+use crate::Identifier;
+
+/// The results of calls to memoized functions, keyed by the match that made them, the function,
+/// and its arguments.  Strict execution clears the results after every match, while lazy
+/// execution, which evaluates calls after all matches have been executed, keeps them apart by
+/// match.
+#[derive(Default)]
+pub(crate) struct MemoizedCalls {
+    results: HashMap<(usize, Identifier, Vec<Value>), Value>,
+}
+
+impl MemoizedCalls {
+    pub(crate) fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    /// Calls a memoized function on behalf of a match, reusing the result of an earlier call made
+    /// by the same match with the same arguments.  Failed calls are not remembered.
+    pub(crate) fn call(
+        &mut self,
+        match_index: usize,
+        functions: &Functions,
+        name: &Identifier,
+        graph: &mut Graph,
+        source: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, ExecutionError> {
+        let key = (match_index, name.clone(), arguments);
+        if let Some(value) = self.results.get(&key) {
+            functions.record_memoized_hit();
+            return Ok(value.clone());
+        }
+        let value = functions.call(name, graph, source, &mut key.2.iter().cloned())?;
+        self.results.insert(key, value.clone());
+        Ok(value)
+    }
+}
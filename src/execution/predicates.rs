@@ -0,0 +1,218 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Query predicates that are evaluated by the executor instead of by tree-sitter, because
+//! tree-sitter does not support them, or because they depend on the global variables of an
+//! execution.
+
+use regex::Regex;
+use tree_sitter::Query;
+use tree_sitter::QueryCapture;
+use tree_sitter::QueryPredicateArg;
+
+use crate::ast::Stanza;
+use crate::execution::error::ExecutionError;
+use crate::execution::nodes_for_capture_index;
+use crate::variables::Globals;
+use crate::Identifier;
+
+const EQ_GLOBAL: &str = "eq-global?";
+const MATCH_GLOBAL: &str = "match-global?";
+const ANY_OF: &str = "any-of?";
+const NOT_ANY_OF: &str = "not-any-of?";
+
+/// The predicates that can be used in stanza queries, including the ones that tree-sitter
+/// evaluates itself
+const SUPPORTED_PREDICATES: &str =
+    "#eq?, #not-eq?, #match?, #not-match?, #any-of?, #not-any-of?, #eq-global?, and #match-global?";
+
+/// Checks that all predicates of a pattern are supported, and that the arguments of its
+/// `#any-of?` and `#not-any-of?` predicates are a capture followed by strings.  Tree-sitter
+/// evaluates `#eq?` and `#match?` itself, and leaves other predicates to its users, so a
+/// misspelled predicate would otherwise be ignored.  Returns a message describing the first
+/// invalid predicate.
+pub(crate) fn check_predicates(query: &Query, pattern_index: usize) -> Result<(), String> {
+    if let Some((_, is_positive)) = query.property_predicates(pattern_index).first() {
+        let operator = if *is_positive { "is?" } else { "is-not?" };
+        return Err(format!(
+            "#{} is not supported, supported predicates are {}",
+            operator, SUPPORTED_PREDICATES,
+        ));
+    }
+    for predicate in query.general_predicates(pattern_index) {
+        let operator = &*predicate.operator;
+        match operator {
+            EQ_GLOBAL | MATCH_GLOBAL => {}
+            ANY_OF | NOT_ANY_OF => match &predicate.args[..] {
+                [QueryPredicateArg::Capture(_), values @ ..]
+                    if values
+                        .iter()
+                        .all(|value| matches!(value, QueryPredicateArg::String(_))) => {}
+                _ => {
+                    return Err(format!(
+                        "#{} expects a capture followed by strings",
+                        operator
+                    ))
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "#{} is not supported, supported predicates are {}",
+                    operator, SUPPORTED_PREDICATES,
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the names of the global variables that the global predicates of a pattern refer to.
+pub(super) fn global_predicate_names(
+    query: &Query,
+    pattern_index: usize,
+) -> impl Iterator<Item = Identifier> + '_ {
+    query
+        .general_predicates(pattern_index)
+        .iter()
+        .filter(|predicate| {
+            let operator = &*predicate.operator;
+            operator == EQ_GLOBAL || operator == MATCH_GLOBAL
+        })
+        .filter_map(|predicate| match predicate.args.get(1) {
+            Some(QueryPredicateArg::String(name)) => Some(Identifier::from(&**name)),
+            _ => None,
+        })
+}
+
+/// The predicates of a stanza that are evaluated by the executor, resolved against the globals
+/// of an execution
+pub(super) struct StanzaPredicates {
+    predicates: Vec<StanzaPredicate>,
+}
+
+enum StanzaPredicate {
+    Eq {
+        capture_index: u32,
+        value: String,
+    },
+    Match {
+        capture_index: u32,
+        regex: Regex,
+    },
+    AnyOf {
+        capture_index: u32,
+        values: Vec<String>,
+        is_positive: bool,
+    },
+}
+
+impl StanzaPredicates {
+    /// Resolves the predicates of a stanza that are evaluated by the executor.  The query and
+    /// pattern index identify the stanza's pattern in the query that is used to find its matches.
+    pub(super) fn new(
+        stanza: &Stanza,
+        query: &Query,
+        pattern_index: usize,
+        globals: &Globals,
+    ) -> Result<Self, ExecutionError> {
+        let mut predicates = Vec::new();
+        for predicate in query.general_predicates(pattern_index) {
+            let operator = &*predicate.operator;
+            if operator == ANY_OF || operator == NOT_ANY_OF {
+                if let [QueryPredicateArg::Capture(capture_index), values @ ..] =
+                    &predicate.args[..]
+                {
+                    predicates.push(StanzaPredicate::AnyOf {
+                        capture_index: *capture_index,
+                        values: values
+                            .iter()
+                            .filter_map(|value| match value {
+                                QueryPredicateArg::String(value) => Some(value.to_string()),
+                                QueryPredicateArg::Capture(_) => None,
+                            })
+                            .collect(),
+                        is_positive: operator == ANY_OF,
+                    });
+                }
+                continue;
+            }
+            if operator != EQ_GLOBAL && operator != MATCH_GLOBAL {
+                continue;
+            }
+            let (capture_index, name) = match &predicate.args[..] {
+                [QueryPredicateArg::Capture(capture_index), QueryPredicateArg::String(name)] => {
+                    (*capture_index, Identifier::from(&**name))
+                }
+                _ => {
+                    return Err(ExecutionError::InvalidPredicate(format!(
+                        "#{} expects a capture and a global variable name in stanza at {}",
+                        operator, stanza.range.start,
+                    )))
+                }
+            };
+            let value = globals
+                .get(&name)
+                .ok_or_else(|| {
+                    ExecutionError::MissingGlobalVariable(format!(
+                        "{} used in #{} in stanza at {}",
+                        name, operator, stanza.range.start,
+                    ))
+                })?
+                .as_str()
+                .map_err(|_| {
+                    ExecutionError::InvalidPredicate(format!(
+                        "#{} expects global variable {} to be a string in stanza at {}",
+                        operator, name, stanza.range.start,
+                    ))
+                })?;
+            if operator == EQ_GLOBAL {
+                predicates.push(StanzaPredicate::Eq {
+                    capture_index,
+                    value: value.to_string(),
+                });
+            } else {
+                let regex = Regex::new(value).map_err(|e| {
+                    ExecutionError::InvalidPredicate(format!(
+                        "#{} expects global variable {} to be a regular expression in stanza at {}: {}",
+                        operator, name, stanza.range.start, e,
+                    ))
+                })?;
+                predicates.push(StanzaPredicate::Match {
+                    capture_index,
+                    regex,
+                });
+            }
+        }
+        Ok(Self { predicates })
+    }
+
+    /// Returns whether a match satisfies all predicates.  Predicates on captures that did
+    /// not match any syntax node are satisfied.
+    pub(super) fn matches(&self, captures: &[QueryCapture], source: &str) -> bool {
+        self.predicates.iter().all(|predicate| {
+            let (capture_index, matches): (u32, &dyn Fn(&str) -> bool) = match predicate {
+                StanzaPredicate::Eq {
+                    capture_index,
+                    value,
+                } => (*capture_index, &move |text| text == value),
+                StanzaPredicate::Match {
+                    capture_index,
+                    regex,
+                } => (*capture_index, &move |text| regex.is_match(text)),
+                StanzaPredicate::AnyOf {
+                    capture_index,
+                    values,
+                    is_positive,
+                } => (*capture_index, &move |text| {
+                    values.iter().any(|value| value == text) == *is_positive
+                }),
+            };
+            nodes_for_capture_index(captures, capture_index)
+                .all(|node| matches(&source[node.byte_range()]))
+        })
+    }
+}
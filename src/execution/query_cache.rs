@@ -0,0 +1,252 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Sharing the matches of stanza queries between executions of different files on the same tree
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tree_sitter::Node;
+use tree_sitter::QueryCapture;
+use tree_sitter::Tree;
+
+use crate::ast::Stanza;
+use crate::execution::with_query_cursor;
+
+/// A cache of the matches of stanza queries, which can be shared by the executions of several
+/// graph DSL files on the same syntax tree, with
+/// [`ExecutionConfig::query_cache`][crate::ExecutionConfig::query_cache].  Stanzas whose queries
+/// are the same, apart from whitespace and comments, reuse the matches that the first of them
+/// found, instead of matching the tree again.
+///
+/// The cache only holds matches for one tree at a time.  Using it with a different tree, including
+/// a tree that was reparsed after an edit, discards everything it holds.  The cache keeps a reference to the tree it holds matches for, until it is
+/// used with another tree or [cleared][Self::clear].
+///
+/// Only strict execution uses the cache.  Lazy execution matches all stanzas with a single query.
+#[derive(Default)]
+pub struct QueryCache {
+    state: Mutex<QueryCacheState>,
+}
+
+#[derive(Default)]
+struct QueryCacheState {
+    tree: Option<Tree>,
+    tree_id: Option<TreeId>,
+    matches: HashMap<String, Arc<Vec<Vec<CachedCapture>>>>,
+    hits: usize,
+    misses: usize,
+}
+
+/// A capture of a cached match.  Nodes are stored by their ID, together with their byte range,
+/// which is used to find them again.  The root node has no ID, because the ID of the root node
+/// differs between copies of the same tree.
+#[derive(Clone, Copy)]
+struct CachedCapture {
+    index: u32,
+    id: Option<usize>,
+    start_byte: usize,
+    end_byte: usize,
+}
+
+impl QueryCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Discards all cached matches, and the reference to their tree.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tree = None;
+        state.tree_id = None;
+        state.matches.clear();
+    }
+
+    /// Returns the number of stanza queries whose matches were found in the cache.
+    pub fn hits(&self) -> usize {
+        self.state.lock().unwrap().hits
+    }
+
+    /// Returns the number of stanza queries whose matches were not found in the cache, and were
+    /// computed and added to it.
+    pub fn misses(&self) -> usize {
+        self.state.lock().unwrap().misses
+    }
+
+    /// Visits the matches of the query of a stanza, using the cached matches if there are any, and
+    /// caching them otherwise.
+    pub(crate) fn try_visit_matches<'tree, E>(
+        &self,
+        stanza: &Stanza,
+        tree: &'tree Tree,
+        source: &'tree str,
+        visit: &mut dyn FnMut(&[QueryCapture<'tree>]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let key = normalize_query(&stanza.query_source);
+        let tree_id = tree_id(tree);
+        let cached = {
+            let mut state = self.state.lock().unwrap();
+            if state.tree_id != tree_id {
+                state.tree = tree_id.as_ref().map(|_| tree.clone());
+                state.tree_id = tree_id.clone();
+                state.matches.clear();
+            }
+            state.matches.get(&key).cloned()
+        };
+        // A cached match whose nodes cannot be found again is treated like a missing one.
+        let resolved = cached.and_then(|cached| resolve_matches(tree.root_node(), &cached));
+        let matches = match resolved {
+            Some(matches) => {
+                self.state.lock().unwrap().hits += 1;
+                matches
+            }
+            None => {
+                let matches = with_query_cursor(|cursor| {
+                    cursor
+                        .matches(&stanza.query, tree.root_node(), source.as_bytes())
+                        .map(|mat| mat.captures.to_vec())
+                        .collect::<Vec<_>>()
+                });
+                let cached = matches
+                    .iter()
+                    .map(|captures| {
+                        captures
+                            .iter()
+                            .map(|capture| CachedCapture::new(tree, capture))
+                            .collect()
+                    })
+                    .collect();
+                let mut state = self.state.lock().unwrap();
+                state.misses += 1;
+                // the cache may have moved on to another tree in the meantime
+                if tree_id.is_some() && state.tree_id == tree_id {
+                    state.matches.insert(key, Arc::new(cached));
+                }
+                matches
+            }
+        };
+        for captures in &matches {
+            visit(captures)?;
+        }
+        Ok(())
+    }
+}
+
+impl CachedCapture {
+    fn new(tree: &Tree, capture: &QueryCapture) -> Self {
+        Self {
+            index: capture.index,
+            id: Some(capture.node.id()).filter(|_| capture.node != tree.root_node()),
+            start_byte: capture.node.start_byte(),
+            end_byte: capture.node.end_byte(),
+        }
+    }
+
+    /// Finds the node of this capture in the tree with the given root.  The node is the smallest
+    /// one that spans its byte range, or one of its ancestors that span the same range.
+    fn resolve<'tree>(&self, root: Node<'tree>) -> Option<Node<'tree>> {
+        let id = match self.id {
+            Some(id) => id,
+            None => return Some(root),
+        };
+        let mut node = root.descendant_for_byte_range(self.start_byte, self.end_byte)?;
+        while node.id() != id {
+            node = node.parent()?;
+            if node.start_byte() != self.start_byte || node.end_byte() != self.end_byte {
+                return None;
+            }
+        }
+        Some(node)
+    }
+}
+
+fn resolve_matches<'tree>(
+    root: Node<'tree>,
+    cached: &[Vec<CachedCapture>],
+) -> Option<Vec<Vec<QueryCapture<'tree>>>> {
+    cached
+        .iter()
+        .map(|captures| {
+            captures
+                .iter()
+                .map(|capture| {
+                    Some(QueryCapture {
+                        node: capture.resolve(root)?,
+                        index: capture.index,
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Identifies a tree and its copies, as long as the cache holds a copy of it.  The ID of the root
+/// node cannot be used, because it is different for every copy of a tree, and could be reused by
+/// another tree once this one is dropped.  The children of the root node are shared by all copies
+/// of a tree, so their IDs can be used.  A tree that is reparsed after an edit shares the children
+/// that do not overlap the edit with the original tree, but not the others, so all of them are
+/// part of the ID, together with the extent of the root node, which covers text after the last
+/// child.
+#[derive(Clone, PartialEq, Eq)]
+struct TreeId {
+    end_byte: usize,
+    children: Vec<usize>,
+}
+
+/// Returns the ID of a tree, or `None` if the tree is empty.
+fn tree_id(tree: &Tree) -> Option<TreeId> {
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let children = root
+        .children(&mut cursor)
+        .map(|child| child.id())
+        .collect::<Vec<_>>();
+    if children.is_empty() {
+        return None;
+    }
+    Some(TreeId {
+        end_byte: root.end_byte(),
+        children,
+    })
+}
+
+/// Normalizes the source of a query, so that queries that only differ in whitespace and comments
+/// have the same source.  Whitespace in strings is kept.
+fn normalize_query(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut chars = source.chars();
+    let mut pending_space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                pending_space = true;
+            }
+            c if c.is_whitespace() => pending_space = true,
+            c => {
+                if pending_space && !result.is_empty() {
+                    result.push(' ');
+                }
+                pending_space = false;
+                result.push(c);
+                if c == '"' {
+                    while let Some(c) = chars.next() {
+                        result.push(c);
+                        match c {
+                            '\\' => result.extend(chars.next()),
+                            '"' => break,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+    }
+    result
+}
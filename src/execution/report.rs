@@ -0,0 +1,244 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Reports of the matches of the stanzas of a file, for editors and other tools
+
+use tree_sitter::Tree;
+
+use crate::ast::File;
+use crate::graph::Graph;
+use crate::graph::GraphNodeRef;
+use crate::graph::SourceLocation;
+use crate::parser::Range;
+use crate::Identifier;
+
+/// The version of the JSON schema of [`MatchReport`][]s.  It is increased whenever the schema
+/// changes in a way that consumers can notice.
+pub const MATCH_REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// The matches of the stanzas of a file against a source file, created by
+/// [`File::match_report`][].
+#[derive(Clone, Debug)]
+pub struct MatchReport {
+    /// The stanzas of the file, in the order in which they appear in the file, including the
+    /// stanzas that did not match
+    pub stanzas: Vec<StanzaMatches>,
+}
+
+/// The matches of a stanza in a [`MatchReport`][]
+#[derive(Clone, Debug)]
+pub struct StanzaMatches {
+    /// The index of the stanza in the file's [`stanzas`][File::stanzas]
+    pub index: usize,
+    /// The name of the stanza, if it has one
+    pub name: Option<Identifier>,
+    /// The range of the stanza in the graph DSL file
+    pub range: Range,
+    /// The matches of the stanza's query, in the order in which the query produced them
+    pub matches: Vec<StanzaMatch>,
+}
+
+/// A match of a stanza's query in a [`MatchReport`][]
+#[derive(Clone, Debug)]
+pub struct StanzaMatch {
+    /// The location of the syntax node matched by the query as a whole
+    pub location: SourceLocation,
+    /// The syntax nodes bound to the named captures of the query, ordered by capture, with the
+    /// nodes of a quantified capture in order
+    pub captures: Vec<CaptureBinding>,
+    /// The graph nodes created by the match, or None if the report was created without a graph,
+    /// or from a graph whose provenance was not recorded
+    pub nodes: Option<Vec<GraphNodeRef>>,
+}
+
+/// A syntax node bound to a capture in a [`StanzaMatch`][]
+#[derive(Clone, Debug)]
+pub struct CaptureBinding {
+    /// The name of the capture, without the `@`
+    pub name: String,
+    /// The kind of the syntax node
+    pub kind: String,
+    /// The location of the syntax node
+    pub location: SourceLocation,
+}
+
+impl File {
+    /// Reports the matches of every stanza of this file against a source file, with the syntax
+    /// nodes bound to their captures.  If the graph that was built by executing this file against
+    /// the same syntax tree is given, and its provenance was recorded, as enabled using
+    /// [`ExecutionConfig::provenance`][crate::ExecutionConfig::provenance], every match also
+    /// lists the graph nodes it created.
+    pub fn match_report<'tree>(
+        &self,
+        tree: &'tree Tree,
+        source: &'tree str,
+        graph: Option<&Graph<'tree>>,
+    ) -> MatchReport {
+        let stanzas = self
+            .stanzas
+            .iter()
+            .enumerate()
+            .map(|(index, stanza)| {
+                let mut matches = Vec::new();
+                let _: Result<(), ()> = stanza.try_visit_matches(tree, source, |mat| {
+                    let node = mat.full_capture();
+                    let captures = mat
+                        .named_captures()
+                        .flat_map(|(name, _, nodes)| {
+                            nodes.map(move |node| CaptureBinding {
+                                name: name.clone(),
+                                kind: node.kind().to_string(),
+                                location: SourceLocation::from(&node),
+                            })
+                        })
+                        .collect();
+                    let nodes = graph
+                        .and_then(|graph| {
+                            graph.nodes_created_by_match(stanza.range.start, node.byte_range())
+                        })
+                        .map(|nodes| nodes.to_vec());
+                    matches.push(StanzaMatch {
+                        location: SourceLocation::from(&node),
+                        captures,
+                        nodes,
+                    });
+                    Ok(())
+                });
+                StanzaMatches {
+                    index,
+                    name: stanza.name.clone(),
+                    range: stanza.range,
+                    matches,
+                }
+            })
+            .collect();
+        MatchReport { stanzas }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+
+    use serde::ser::SerializeMap;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    use crate::graph::SourceLocation;
+    use crate::parser::Range;
+    use crate::Location;
+
+    use super::CaptureBinding;
+    use super::MatchReport;
+    use super::StanzaMatch;
+    use super::StanzaMatches;
+    use super::MATCH_REPORT_SCHEMA_VERSION;
+
+    impl MatchReport {
+        /// Writes the report as JSON, in the schema described in the
+        /// [reference][crate::reference#match-reports], to the given file or to standard output.
+        pub fn display_json(&self, path: Option<&Path>) -> std::io::Result<()> {
+            let s = serde_json::to_string_pretty(self).unwrap();
+            match path {
+                Some(path) => fs::File::create(path)?.write_all(s.as_bytes()),
+                None => std::io::stdout().write_all(s.as_bytes()),
+            }
+        }
+    }
+
+    impl Serialize for MatchReport {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("schema_version", &MATCH_REPORT_SCHEMA_VERSION)?;
+            map.serialize_entry("stanzas", &self.stanzas)?;
+            map.end()
+        }
+    }
+
+    impl Serialize for StanzaMatches {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(4))?;
+            map.serialize_entry("index", &self.index)?;
+            map.serialize_entry("name", &self.name)?;
+            map.serialize_entry("range", &SerializeRange(&self.range))?;
+            map.serialize_entry("matches", &self.matches)?;
+            map.end()
+        }
+    }
+
+    impl Serialize for StanzaMatch {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry("range", &SerializeSourceLocation(&self.location))?;
+            map.serialize_entry("captures", &self.captures)?;
+            if let Some(nodes) = &self.nodes {
+                let nodes = nodes.iter().map(|node| node.index()).collect::<Vec<_>>();
+                map.serialize_entry("nodes", &nodes)?;
+            }
+            map.end()
+        }
+    }
+
+    impl Serialize for CaptureBinding {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(3))?;
+            map.serialize_entry("name", &self.name)?;
+            map.serialize_entry("kind", &self.kind)?;
+            map.serialize_entry("range", &SerializeSourceLocation(&self.location))?;
+            map.end()
+        }
+    }
+
+    struct SerializeRange<'a>(&'a Range);
+
+    impl Serialize for SerializeRange<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("start", &SerializeLocation(None, self.0.start))?;
+            map.serialize_entry("end", &SerializeLocation(None, self.0.end))?;
+            map.end()
+        }
+    }
+
+    struct SerializeSourceLocation<'a>(&'a SourceLocation);
+
+    impl Serialize for SerializeSourceLocation<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let location = self.0;
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry(
+                "start",
+                &SerializeLocation(
+                    Some(location.start_byte),
+                    Location::from(location.start_point),
+                ),
+            )?;
+            map.serialize_entry(
+                "end",
+                &SerializeLocation(Some(location.end_byte), Location::from(location.end_point)),
+            )?;
+            map.end()
+        }
+    }
+
+    /// A position, with its byte offset if it is a position in the source file
+    struct SerializeLocation(Option<usize>, Location);
+
+    impl Serialize for SerializeLocation {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            if let Some(byte) = self.0 {
+                map.serialize_entry("byte", &byte)?;
+            }
+            map.serialize_entry("row", &self.1.row)?;
+            map.serialize_entry("column", &self.1.column)?;
+            map.end()
+        }
+    }
+}
@@ -0,0 +1,450 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Repeated execution of a file against the same syntax tree with different globals
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use log::debug;
+use tree_sitter::Tree;
+
+use crate::ast;
+use crate::execution::error::ExecutionError;
+use crate::execution::hook::GraphMutationHook;
+use crate::execution::predicates::global_predicate_names;
+use crate::execution::query_cache::QueryCache;
+use crate::execution::source::check_tree_source;
+use crate::execution::usage::UsageCounters;
+use crate::execution::CancellationFlag;
+use crate::execution::ExecutionConfig;
+use crate::functions::Functions;
+use crate::graph::Graph;
+use crate::graph::Value;
+use crate::variables::Globals;
+use crate::Identifier;
+
+/// Executes a file repeatedly against the same syntax tree, with different global variables,
+/// re-executing only the stanzas that can be affected by the globals that changed.
+///
+/// The stanzas of the file are divided into groups that are provably independent of each other:
+/// stanzas that use a scoped variable with the same name are in the same group, and stanzas can
+/// only reach each other's graph nodes through scoped variables.  The graph produced by each group
+/// is kept, and when the globals change, only the groups that refer to a changed global are
+/// re-executed.  The kept graphs are then merged into the result.  Because of this, the graph
+/// nodes of the result can be numbered differently than when executing the file in one go, but the
+/// graphs are otherwise the same, and have the same [canonical hash][Graph::canonical_hash].
+///
+/// Independence cannot be proven if a stanza calls a function that is not declared
+/// [pure][crate::functions::Signature::pure], other than `node`, since such a function can inspect
+/// or change the whole graph.  The standard library's `node-for`, `find-node`, and
+/// `find-nodes-by-attr` functions are examples.  Sessions for such files always execute the whole
+/// file, which [`SessionStats`][] records.
+///
+/// Sessions always execute strictly, without recording provenance, and abort on the first error;
+/// these settings of the configuration are ignored.  Sessions do not call the configuration's
+/// [observer][ExecutionConfig::observer] either, since they reuse graph nodes of earlier
+/// executions.
+pub struct ExecutionSession<'a, 'tree> {
+    file: &'a ast::File,
+    tree: &'tree Tree,
+    source: &'tree str,
+    functions: &'a Functions,
+    location_attr: Option<Identifier>,
+    variable_name_attr: Option<Identifier>,
+    match_node_attr: Option<Identifier>,
+    mutation_hook: Option<&'a dyn GraphMutationHook>,
+    allow_partial: bool,
+    query_cache: Option<&'a QueryCache>,
+    max_depth: usize,
+    usage: Option<&'a UsageCounters>,
+    // The independent groups of stanzas, or None if their independence cannot be proven
+    groups: Option<Vec<StanzaGroup>>,
+    // The graph produced by each group, and the values of the globals it refers to, when it was
+    // last executed
+    results: Vec<Option<(Graph<'tree>, Vec<Option<Value>>)>>,
+    stats: SessionStats,
+}
+
+/// The number of times an [`ExecutionSession`][] took each execution path
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SessionStats {
+    /// The number of executions that executed every stanza
+    pub full_executions: usize,
+    /// The number of executions that reused the results of some stanzas
+    pub selective_executions: usize,
+    /// The total number of stanzas that were executed
+    pub stanzas_executed: usize,
+    /// The total number of stanzas whose results were reused
+    pub stanzas_reused: usize,
+}
+
+/// A group of stanzas that is independent of all other stanzas
+struct StanzaGroup {
+    stanzas: Vec<usize>,
+    globals: Vec<Identifier>,
+}
+
+impl<'a, 'tree> ExecutionSession<'a, 'tree> {
+    /// Creates a session for executing a file against a syntax tree and the source text it was
+    /// parsed from.  The functions, debug attributes, mutation hook, query cache, maximum depth,
+    /// resource usage counters, and whether invalid stanzas are allowed are taken from the
+    /// configuration and used for every execution; its globals are not used.
+    pub fn new(
+        file: &'a ast::File,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig<'a, '_>,
+    ) -> Self {
+        let groups = StanzaGroup::find(file, config.functions);
+        if groups.is_none() {
+            debug!("stanzas cannot be proven independent, sessions execute the whole file");
+        }
+        let results = groups.iter().flatten().map(|_| None).collect::<Vec<_>>();
+        Self {
+            file,
+            tree,
+            source,
+            functions: config.functions,
+            location_attr: config.location_attr.clone(),
+            variable_name_attr: config.variable_name_attr.clone(),
+            match_node_attr: config.match_node_attr.clone(),
+            mutation_hook: config.mutation_hook,
+            allow_partial: config.allow_partial,
+            query_cache: config.query_cache,
+            max_depth: config.max_depth,
+            usage: config.usage,
+            groups,
+            results,
+            stats: SessionStats::default(),
+        }
+    }
+
+    /// Returns whether the stanzas of the file were proven independent, so that executions can
+    /// reuse the results of earlier ones.
+    pub fn is_selective(&self) -> bool {
+        self.groups.is_some()
+    }
+
+    /// Returns how often each execution path was taken.
+    pub fn stats(&self) -> SessionStats {
+        self.stats
+    }
+
+    /// Executes the file with the given globals.  Stanzas that only refer to globals whose values
+    /// are the same as in the previous execution are not executed again; their results are
+    /// reused.  The first execution executes every stanza.
+    pub fn re_execute_with_globals(
+        &mut self,
+        globals: &Globals,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<Graph<'tree>, ExecutionError> {
+        check_tree_source(self.tree, self.source, None)?;
+        let config = ExecutionConfig {
+            location_attr: self.location_attr.clone(),
+            variable_name_attr: self.variable_name_attr.clone(),
+            match_node_attr: self.match_node_attr.clone(),
+            mutation_hook: self.mutation_hook,
+            allow_partial: self.allow_partial,
+            query_cache: self.query_cache,
+            max_depth: self.max_depth,
+            usage: self.usage,
+            ..ExecutionConfig::new(self.functions, globals)
+        };
+        let groups = match &self.groups {
+            Some(groups) => groups,
+            None => {
+                self.stats.full_executions += 1;
+                self.stats.stanzas_executed += self.file.stanzas.len();
+                return self
+                    .file
+                    .execute(self.tree, self.source, &config, cancellation_flag);
+            }
+        };
+        self.file.check_invalid_stanzas(&config)?;
+
+        let mut reused = 0;
+        for (group, result) in groups.iter().zip(self.results.iter_mut()) {
+            let values = group
+                .globals
+                .iter()
+                .map(|name| globals.get(name).cloned())
+                .collect::<Vec<_>>();
+            if let Some((_, previous)) = result {
+                if *previous == values {
+                    reused += group.stanzas.len();
+                    continue;
+                }
+            }
+            debug!("executing stanzas {:?}", group.stanzas);
+            let stanzas = group.stanzas.iter().copied().collect::<HashSet<_>>();
+            let mut graph = Graph::new();
+            // Do not keep the results of earlier executions if this one fails.
+            *result = None;
+            self.file.execute_selected_strict_into(
+                &mut graph,
+                self.tree,
+                self.source,
+                &config,
+                cancellation_flag,
+                &|index| stanzas.contains(&index),
+            )?;
+            *result = Some((graph, values));
+        }
+
+        if reused == 0 {
+            self.stats.full_executions += 1;
+        } else {
+            self.stats.selective_executions += 1;
+        }
+        self.stats.stanzas_reused += reused;
+        self.stats.stanzas_executed += self.file.stanzas.len() - reused;
+
+        let mut graph = Graph::new();
+        for (result, _) in self.results.iter().flatten() {
+            graph.merge(result.copy_contents())?;
+        }
+        self.file.inherit_attributes(&mut graph);
+        Ok(graph)
+    }
+}
+
+impl StanzaGroup {
+    /// Divides the stanzas of a file into independent groups, ordered by their first stanza.
+    /// Returns None if the stanzas cannot be proven independent.
+    fn find(file: &ast::File, functions: &Functions) -> Option<Vec<StanzaGroup>> {
+        // Shorthands can be used by any stanza, so their dependencies are shared by all stanzas.
+        let mut shared = Dependencies::default();
+        for shorthand in file.shorthands.iter() {
+            for attribute in &shorthand.attributes {
+                shared.expression(&attribute.value, functions);
+            }
+        }
+
+        let mut dependencies = Vec::new();
+        for stanza in &file.stanzas {
+            let mut stanza_dependencies = Dependencies::default();
+            for statement in &stanza.statements {
+                stanza_dependencies.statement(statement, functions);
+            }
+            stanza_dependencies
+                .variables
+                .extend(global_predicate_names(&stanza.query, 0));
+            stanza_dependencies
+                .variables
+                .extend(shared.variables.iter().cloned());
+            stanza_dependencies
+                .scoped_variables
+                .extend(shared.scoped_variables.iter().cloned());
+            if stanza_dependencies.impure || shared.impure {
+                return None;
+            }
+            dependencies.push(stanza_dependencies);
+        }
+
+        // Stanzas that use a scoped variable of the same name are in the same group.
+        let mut group_of = (0..file.stanzas.len()).collect::<Vec<_>>();
+        fn root(group_of: &mut [usize], mut index: usize) -> usize {
+            while group_of[index] != index {
+                group_of[index] = group_of[group_of[index]];
+                index = group_of[index];
+            }
+            index
+        }
+        let mut users = HashMap::<&Identifier, usize>::new();
+        for (index, stanza_dependencies) in dependencies.iter().enumerate() {
+            for name in &stanza_dependencies.scoped_variables {
+                let other = *users.entry(name).or_insert(index);
+                let (a, b) = (root(&mut group_of, index), root(&mut group_of, other));
+                group_of[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut groups = Vec::<StanzaGroup>::new();
+        let mut group_indices = HashMap::new();
+        let mut group_globals = Vec::<HashSet<Identifier>>::new();
+        for (index, stanza_dependencies) in dependencies.into_iter().enumerate() {
+            let root = root(&mut group_of, index);
+            let group = *group_indices.entry(root).or_insert_with(|| {
+                groups.push(StanzaGroup {
+                    stanzas: Vec::new(),
+                    globals: Vec::new(),
+                });
+                group_globals.push(HashSet::new());
+                groups.len() - 1
+            });
+            groups[group].stanzas.push(index);
+            group_globals[group].extend(stanza_dependencies.variables);
+        }
+        for (group, globals) in groups.iter_mut().zip(group_globals) {
+            group.globals = globals.into_iter().collect();
+            group.globals.sort();
+        }
+        Some(groups)
+    }
+}
+
+/// The variables and functions that a stanza depends on.  All unscoped variables are recorded,
+/// because a local variable can only be told apart from a global by resolving its scope; treating
+/// locals as globals only makes the analysis more conservative.
+#[derive(Default)]
+struct Dependencies {
+    variables: HashSet<Identifier>,
+    scoped_variables: HashSet<Identifier>,
+    impure: bool,
+}
+
+impl Dependencies {
+    fn statement(&mut self, statement: &ast::Statement, functions: &Functions) {
+        match statement {
+            ast::Statement::DeclareImmutable(stmt) => {
+                self.variable(&stmt.variable, functions);
+                self.expression(&stmt.value, functions);
+            }
+            ast::Statement::DeclareMutable(stmt) => {
+                self.variable(&stmt.variable, functions);
+                self.expression(&stmt.value, functions);
+            }
+            ast::Statement::Assign(stmt) => {
+                self.variable(&stmt.variable, functions);
+                self.expression(&stmt.value, functions);
+            }
+            ast::Statement::CreateGraphNode(stmt) => {
+                self.variable(&stmt.node, functions);
+                self.attributes(&stmt.attributes, functions);
+            }
+            ast::Statement::AddGraphNodeAttribute(stmt) => {
+                self.expression(&stmt.node, functions);
+                self.attributes(&stmt.attributes, functions);
+            }
+            ast::Statement::CreateEdge(stmt) => {
+                self.expression(&stmt.source, functions);
+                self.expression(&stmt.sink, functions);
+                self.attributes(&stmt.attributes, functions);
+            }
+            ast::Statement::AddEdgeAttribute(stmt) => {
+                self.expression(&stmt.source, functions);
+                self.expression(&stmt.sink, functions);
+                self.attributes(&stmt.attributes, functions);
+            }
+            ast::Statement::AddAttributes(stmt) => {
+                for target in &stmt.targets {
+                    match target {
+                        ast::AttributeTarget::GraphNode(node) => self.expression(node, functions),
+                        ast::AttributeTarget::Edge { source, sink } => {
+                            self.expression(source, functions);
+                            self.expression(sink, functions);
+                        }
+                    }
+                }
+                self.attributes(&stmt.attributes, functions);
+            }
+            ast::Statement::RemoveAttributes(stmt) => {
+                for target in &stmt.targets {
+                    match target {
+                        ast::AttributeTarget::GraphNode(node) => self.expression(node, functions),
+                        ast::AttributeTarget::Edge { source, sink } => {
+                            self.expression(source, functions);
+                            self.expression(sink, functions);
+                        }
+                    }
+                }
+            }
+            ast::Statement::Scan(stmt) => {
+                self.expression(&stmt.value, functions);
+                for arm in &stmt.arms {
+                    self.statements(&arm.statements, functions);
+                }
+            }
+            ast::Statement::Print(stmt) => {
+                for value in &stmt.values {
+                    self.expression(value, functions);
+                }
+            }
+            ast::Statement::If(stmt) => {
+                for arm in &stmt.arms {
+                    for condition in &arm.conditions {
+                        match condition {
+                            ast::Condition::Some { value, .. }
+                            | ast::Condition::None { value, .. }
+                            | ast::Condition::Bool { value, .. } => {
+                                self.expression(value, functions)
+                            }
+                        }
+                    }
+                    self.statements(&arm.statements, functions);
+                }
+            }
+            ast::Statement::ForIn(stmt) => {
+                self.expression(&stmt.value, functions);
+                self.statements(&stmt.statements, functions);
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[ast::Statement], functions: &Functions) {
+        for statement in statements {
+            self.statement(statement, functions);
+        }
+    }
+
+    fn attributes(&mut self, attributes: &[ast::Attribute], functions: &Functions) {
+        for attribute in attributes {
+            self.expression(&attribute.value, functions);
+        }
+    }
+
+    fn variable(&mut self, variable: &ast::Variable, functions: &Functions) {
+        match variable {
+            ast::Variable::Unscoped(variable) => {
+                self.variables.insert(variable.name.clone());
+            }
+            ast::Variable::Scoped(variable) => {
+                self.scoped_variables.insert(variable.name.clone());
+                self.expression(&variable.scope, functions);
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &ast::Expression, functions: &Functions) {
+        match expression {
+            ast::Expression::ListLiteral(expr) => {
+                for element in &expr.elements {
+                    self.expression(element, functions);
+                }
+            }
+            ast::Expression::SetLiteral(expr) => {
+                for element in &expr.elements {
+                    self.expression(element, functions);
+                }
+            }
+            ast::Expression::MapLiteral(expr) => {
+                for (_, value) in &expr.entries {
+                    self.expression(value, functions);
+                }
+            }
+            ast::Expression::ListComprehension(expr) => {
+                self.expression(&expr.element, functions);
+                self.expression(&expr.value, functions);
+            }
+            ast::Expression::SetComprehension(expr) => {
+                self.expression(&expr.element, functions);
+                self.expression(&expr.value, functions);
+            }
+            ast::Expression::Variable(variable) => self.variable(variable, functions),
+            ast::Expression::Call(expr) => {
+                if expr.function.as_str() != "node" && !functions.is_pure(&expr.function) {
+                    self.impure = true;
+                }
+                for parameter in &expr.parameters {
+                    self.expression(parameter, functions);
+                }
+            }
+            _ => {}
+        }
+    }
+}
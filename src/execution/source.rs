@@ -0,0 +1,78 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Checks that a syntax tree belongs to the source text it is executed against
+
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+
+use crate::execution::error::ExecutionError;
+
+/// A syntax tree together with the source text it was parsed from.  Keeping the two together
+/// avoids accidentally executing a file against a stale tree after the source text changed.
+pub struct ParsedSource {
+    pub tree: Tree,
+    pub text: String,
+}
+
+impl ParsedSource {
+    /// Creates a parsed source from a syntax tree and the source text it was parsed from.
+    pub fn new(tree: Tree, text: String) -> Self {
+        Self { tree, text }
+    }
+
+    /// Parses source text with the given parser.  Returns `None` if the parser has no language,
+    /// or if parsing was cancelled or timed out.
+    pub fn parse(parser: &mut Parser, text: String) -> Option<Self> {
+        let tree = parser.parse(&text, None)?;
+        Some(Self { tree, text })
+    }
+
+    /// Returns the hash of the source text, as computed by [`source_hash`][].
+    pub fn source_hash(&self) -> u64 {
+        source_hash(&self.text)
+    }
+}
+
+/// Returns a hash of a source text, which can be given to
+/// [`ExecutionConfig::expect_source_hash`][crate::ExecutionConfig::expect_source_hash].  The hash
+/// is the 64-bit FNV-1a hash of the UTF-8 bytes of the text, so it is the same across platforms
+/// and versions of this library.
+pub fn source_hash(text: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    text.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// Checks that the syntax tree spans the whole source text, and that the source text has the
+/// expected hash, if any.
+pub(crate) fn check_tree_source(
+    tree: &Tree,
+    source: &str,
+    expected_hash: Option<u64>,
+) -> Result<(), ExecutionError> {
+    let root = tree.root_node();
+    if root.end_byte() != source.len() {
+        return Err(ExecutionError::TreeSourceMismatch(format!(
+            "syntax tree ends at byte {}, but source text has {} bytes",
+            root.end_byte(),
+            source.len()
+        )));
+    }
+    if let Some(expected_hash) = expected_hash {
+        let hash = source_hash(source);
+        if hash != expected_hash {
+            return Err(ExecutionError::TreeSourceMismatch(format!(
+                "source text has hash {:016x}, but {:016x} was expected",
+                hash, expected_hash
+            )));
+        }
+    }
+    Ok(())
+}
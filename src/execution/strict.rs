@@ -5,19 +5,30 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::cell::RefCell;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use tree_sitter::QueryCursor;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::Range;
+use std::time::Duration;
+
+use log::debug;
+use tree_sitter::CaptureQuantifier;
+use tree_sitter::QueryCapture;
 use tree_sitter::QueryMatch;
 use tree_sitter::Tree;
 
+use crate::ast::AddAttributes;
 use crate::ast::AddEdgeAttribute;
 use crate::ast::AddGraphNodeAttribute;
 use crate::ast::Assign;
 use crate::ast::Attribute;
+use crate::ast::AttributeOperation;
 use crate::ast::AttributeShorthand;
 use crate::ast::AttributeShorthands;
+use crate::ast::AttributeTarget;
 use crate::ast::Call;
 use crate::ast::Capture;
 use crate::ast::Condition;
@@ -27,13 +38,17 @@ use crate::ast::DeclareImmutable;
 use crate::ast::DeclareMutable;
 use crate::ast::Expression;
 use crate::ast::File;
+use crate::ast::FloatConstant;
 use crate::ast::ForIn;
 use crate::ast::If;
 use crate::ast::IntegerConstant;
 use crate::ast::ListComprehension;
 use crate::ast::ListLiteral;
+use crate::ast::MapLiteral;
+use crate::ast::MatchOffset;
 use crate::ast::Print;
 use crate::ast::RegexCapture;
+use crate::ast::RemoveAttributes;
 use crate::ast::Scan;
 use crate::ast::ScopedVariable;
 use crate::ast::SetComprehension;
@@ -43,17 +58,42 @@ use crate::ast::Statement;
 use crate::ast::StringConstant;
 use crate::ast::UnscopedVariable;
 use crate::ast::Variable;
+use crate::execution::batch::BatchedCalls;
+use crate::execution::error::Context;
 use crate::execution::error::ExecutionError;
+use crate::execution::error::ExecutionProgress;
 use crate::execution::error::ResultWithExecutionError;
 use crate::execution::error::StatementContext;
+use crate::execution::hook::GraphObserver;
+use crate::execution::hook::MutationContext;
+use crate::execution::hook::MutationTarget;
+use crate::execution::memo::MemoizedCalls;
+use crate::execution::nodes_for_capture_index;
+use crate::execution::predicates::StanzaPredicates;
+use crate::execution::scan_subject;
+use crate::execution::usage::UsageCounters;
+use crate::execution::with_query_cursor;
 use crate::execution::CancellationFlag;
+use crate::execution::ErrorPolicy;
 use crate::execution::ExecutionConfig;
+use crate::execution::ExternalMatch;
+use crate::execution::GraphElementKey;
+use crate::execution::MatchError;
+use crate::execution::ScanMatch;
 use crate::graph::Graph;
+use crate::graph::GraphNodeRef;
+use crate::graph::Origin;
+use crate::graph::ProvenanceStep;
+use crate::graph::ProvenanceStepRef;
+use crate::graph::StanzaOutput;
 use crate::graph::SyntaxNodeID;
 use crate::graph::SyntaxNodeRef;
+use crate::graph::Tag;
 use crate::graph::Value;
+use crate::regex_engine::RegexEngine;
 use crate::variables::Globals;
 use crate::variables::MutVariables;
+use crate::variables::VariableError;
 use crate::variables::VariableMap;
 use crate::variables::Variables;
 use crate::Identifier;
@@ -72,40 +112,299 @@ impl File {
         source: &'tree str,
         config: &ExecutionConfig,
         cancellation_flag: &dyn CancellationFlag,
-    ) -> Result<(), ExecutionError> {
+    ) -> Result<Vec<MatchError>, ExecutionError> {
+        self.execute_matches_strict_into(
+            graph,
+            source,
+            config,
+            cancellation_flag,
+            |index, visit| self.visit_stanza_matches_strict(index, tree, source, config, visit),
+        )
+    }
+
+    /// Executes the stanzas of this graph DSL file for which `selected` returns true, given the
+    /// index of the stanza, skipping all other stanzas.
+    pub(super) fn execute_selected_strict_into<'tree>(
+        &self,
+        graph: &mut Graph<'tree>,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+        selected: &dyn Fn(usize) -> bool,
+    ) -> Result<Vec<MatchError>, ExecutionError> {
+        self.execute_matches_strict_into(
+            graph,
+            source,
+            config,
+            cancellation_flag,
+            |index, visit| {
+                if !selected(index) {
+                    return Ok(());
+                }
+                self.visit_stanza_matches_strict(index, tree, source, config, visit)
+            },
+        )
+    }
+
+    /// Visits the matches of the stanza with the given index, using the query cache of the
+    /// configuration, if any.
+    fn visit_stanza_matches_strict<'tree>(
+        &self,
+        index: usize,
+        tree: &'tree Tree,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        visit: &mut dyn FnMut(&[QueryCapture<'tree>]) -> Result<(), MatchError>,
+    ) -> Result<(), MatchError> {
+        let stanza = &self.stanzas[index];
+        match config.query_cache {
+            Some(cache) => cache.try_visit_matches(stanza, tree, source, visit),
+            None => stanza.try_visit_matches_strict(tree, source, |mat| visit(mat.captures)),
+        }
+    }
+
+    /// Executes this graph DSL file using matches that were produced elsewhere.  The matches are
+    /// validated up front, so that an invalid match does not leave a partially executed graph.
+    pub(super) fn execute_external_into<'tree, I>(
+        &self,
+        graph: &mut Graph<'tree>,
+        source: &'tree str,
+        matches: I,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+    ) -> Result<Vec<MatchError>, ExecutionError>
+    where
+        I: IntoIterator<Item = ExternalMatch<'tree>>,
+    {
+        let mut stanza_matches = self.stanzas.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        for mat in matches {
+            let stanza = self.stanzas.get(mat.stanza_index).ok_or_else(|| {
+                ExecutionError::InvalidMatch(format!(
+                    "for stanza {}, but there are only {} stanzas",
+                    mat.stanza_index,
+                    self.stanzas.len()
+                ))
+            })?;
+            let captures = stanza.external_match_captures(&mat)?;
+            stanza_matches[mat.stanza_index].push((mat.node.start_byte(), captures));
+        }
+        for matches in &mut stanza_matches {
+            matches.sort_by_key(|(start_byte, _)| *start_byte);
+        }
+
+        self.execute_matches_strict_into(
+            graph,
+            source,
+            config,
+            cancellation_flag,
+            |index, visit| {
+                for (_, captures) in &stanza_matches[index] {
+                    visit(captures)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Executes the stanzas of this file, in order, against the matches that `visit_matches`
+    /// produces for the stanza with the given index.
+    fn execute_matches_strict_into<'tree, V>(
+        &self,
+        graph: &mut Graph<'tree>,
+        source: &'tree str,
+        config: &ExecutionConfig,
+        cancellation_flag: &dyn CancellationFlag,
+        mut visit_matches: V,
+    ) -> Result<Vec<MatchError>, ExecutionError>
+    where
+        V: FnMut(
+            usize,
+            &mut dyn FnMut(&[QueryCapture<'tree>]) -> Result<(), MatchError>,
+        ) -> Result<(), MatchError>,
+    {
         let mut globals = Globals::nested(config.globals);
         self.check_globals(&mut globals)?;
-        let mut config = ExecutionConfig {
-            functions: config.functions,
+        let config = ExecutionConfig {
             globals: &globals,
-            lazy: config.lazy,
-            location_attr: config.location_attr.clone(),
-            variable_name_attr: config.variable_name_attr.clone(),
-            match_node_attr: config.match_node_attr.clone(),
+            ..config.clone()
         };
 
         let mut locals = VariableMap::new();
         let mut scoped = ScopedVariables::new();
-        let current_regex_captures = Vec::new();
+        let scan_match = ScanMatch::default();
         let mut function_parameters = Vec::new();
+        let mut errors = Vec::new();
+        let mut provenance = config.provenance.then(ProvenanceTracker::new);
+        let mut attribute_statements = CheckpointedMap::new();
+        let mut batched_calls = BatchedCalls::default();
+        let mut memoized_calls = MemoizedCalls::default();
+        let mut progress = ExecutionProgress::default();
 
-        self.try_visit_matches_strict(tree, source, |stanza, mat| {
-            stanza.execute(
-                source,
-                &mat,
-                graph,
-                &mut config,
-                &mut locals,
-                &mut scoped,
-                &current_regex_captures,
-                &mut function_parameters,
-                &self.inherited_variables,
-                &self.shorthands,
-                cancellation_flag,
-            )
-        })?;
+        let predicates = self
+            .stanzas
+            .iter()
+            .map(|stanza| StanzaPredicates::new(stanza, &stanza.query, 0, &globals))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(())
+        for (index, (stanza, predicates)) in self.stanzas.iter().zip(&predicates).enumerate() {
+            if index == 0 || self.stanzas[index - 1].phase != stanza.phase {
+                if let Some(phase) = &stanza.phase {
+                    debug!("phase {}", phase);
+                }
+            }
+            config.record_usage(|usage| usage.enter_stanza(index));
+            // the time spent executing matches is subtracted from the time spent on the stanza
+            let stanza_start = config.usage.and_then(UsageCounters::start_timer);
+            let mut execution_time = Duration::ZERO;
+            // If the stanza calls batchable functions, collect all of its matches first, so that
+            // the calls can be made in one batch before the matches are executed.
+            let batchable_calls = stanza.batchable_calls(config.functions);
+            let mut buffered_matches = None;
+            let mut batch_result = Ok(());
+            if !batchable_calls.is_empty() {
+                let mut matches = Vec::new();
+                visit_matches(index, &mut |captures| {
+                    if predicates.matches(captures, source) {
+                        matches.push(captures.to_vec());
+                    }
+                    Ok(())
+                })
+                .map_err(|error| error.error)?;
+                for (match_index, captures) in matches.iter().enumerate() {
+                    batched_calls.collect(
+                        match_index,
+                        &batchable_calls,
+                        config.functions,
+                        graph,
+                        source,
+                        &mut |graph, capture| {
+                            Value::from_nodes(
+                                graph,
+                                nodes_for_capture_index(
+                                    captures,
+                                    capture.stanza_capture_index as u32,
+                                ),
+                                capture.quantifier,
+                            )
+                        },
+                    );
+                }
+                // Under SkipMatch, the failing calls are made again when their matches are
+                // executed, which skips only those matches.
+                if let Err((match_index, error)) =
+                    batched_calls.execute(config.functions, graph, source)
+                {
+                    if config.on_error != ErrorPolicy::SkipMatch {
+                        batch_result = Err(stanza.match_error(&matches[match_index], error));
+                    }
+                }
+                buffered_matches = Some(matches);
+            }
+            if config.on_error == ErrorPolicy::SkipStanza {
+                graph.checkpoint();
+                scoped.checkpoint();
+                attribute_statements.checkpoint();
+                if let Some(provenance) = provenance.as_mut() {
+                    provenance.checkpoint();
+                }
+            }
+            let mut execute_match = |captures: &[QueryCapture<'tree>]| {
+                cancellation_flag
+                    .check("processing matches")
+                    .map_err(|error| stanza.match_error(captures, error.into()))?;
+                if !predicates.matches(captures, source) {
+                    return Ok(());
+                }
+                config.record_usage(UsageCounters::record_match);
+                let match_start = config.usage.and_then(UsageCounters::start_timer);
+                if config.on_error == ErrorPolicy::SkipMatch {
+                    graph.checkpoint();
+                    scoped.checkpoint();
+                    attribute_statements.checkpoint();
+                    if let Some(provenance) = provenance.as_mut() {
+                        provenance.checkpoint();
+                    }
+                }
+                let result = stanza
+                    .execute(
+                        source,
+                        captures,
+                        graph,
+                        &config,
+                        &mut locals,
+                        &mut scoped,
+                        &scan_match,
+                        &mut function_parameters,
+                        &self.inherited_variables,
+                        &self.shorthands,
+                        provenance.as_mut(),
+                        &mut attribute_statements,
+                        &batched_calls,
+                        &mut memoized_calls,
+                        cancellation_flag,
+                    )
+                    .map_err(|error| stanza.match_error(captures, error));
+                if result.is_ok() {
+                    progress.matches_processed += 1;
+                }
+                if let Some(match_start) = match_start {
+                    execution_time += match_start.elapsed();
+                }
+                match result {
+                    Err(error)
+                        if config.on_error == ErrorPolicy::SkipMatch && !error.is_cancelled() =>
+                    {
+                        graph.rollback();
+                        scoped.rollback();
+                        attribute_statements.rollback();
+                        if let Some(provenance) = provenance.as_mut() {
+                            provenance.rollback();
+                        }
+                        errors.push(error);
+                        Ok(())
+                    }
+                    result => result,
+                }
+            };
+            let result = batch_result.and_then(|()| match &buffered_matches {
+                Some(matches) => matches
+                    .iter()
+                    .try_for_each(|captures| execute_match(captures)),
+                None => visit_matches(index, &mut execute_match),
+            });
+            if let Some(stanza_start) = stanza_start {
+                let matching_time = stanza_start.elapsed().saturating_sub(execution_time);
+                config.record_usage(|usage| {
+                    usage.record_stanza_time(index, matching_time, execution_time)
+                });
+            }
+            match result {
+                Err(error)
+                    if config.on_error == ErrorPolicy::SkipStanza && !error.is_cancelled() =>
+                {
+                    graph.rollback();
+                    scoped.rollback();
+                    attribute_statements.rollback();
+                    if let Some(provenance) = provenance.as_mut() {
+                        provenance.rollback();
+                    }
+                    errors.push(error);
+                }
+                Err(error) => return Err(error.error.with_progress(progress)),
+                Ok(()) => {}
+            }
+            graph.commit();
+            scoped.commit();
+            attribute_statements.commit();
+            if let Some(provenance) = provenance.as_mut() {
+                provenance.commit();
+            }
+            progress.stanzas_completed += 1;
+        }
+        scoped.add_owned_graph_nodes(graph);
+
+        Ok(errors)
     }
 
     pub(super) fn try_visit_matches_strict<'tree, E, F>(
@@ -131,58 +430,416 @@ struct ExecutionContext<'a, 'c, 'g, 's, 'tree> {
     config: &'a ExecutionConfig<'c, 'g>,
     locals: &'a mut dyn MutVariables<Value>,
     scoped: &'a mut ScopedVariables<'s>,
-    current_regex_captures: &'a Vec<String>,
+    scan_match: &'a ScanMatch,
     function_parameters: &'a mut Vec<Value>,
-    mat: &'a QueryMatch<'a, 'tree>,
+    captures: &'a [QueryCapture<'tree>],
     full_match_stanza_capture_index: usize,
     error_context: StatementContext,
     inherited_variables: &'a HashSet<Identifier>,
     shorthands: &'a AttributeShorthands,
+    provenance: Option<&'a mut ProvenanceTracker>,
+    /// The statements that set attributes, to report both statements of a conflict
+    attribute_statements: &'a mut CheckpointedMap<GraphElementKey, StatementContext>,
+    /// The results of calls to batchable functions that were made ahead of time
+    batched_calls: &'a BatchedCalls,
+    /// The results of calls to memoized functions made by the current match
+    memoized_calls: &'a mut MemoizedCalls,
+    /// The number of statements and expressions that are being evaluated, one inside the other
+    depth: usize,
     cancellation_flag: &'a dyn CancellationFlag,
 }
 
+impl ExecutionContext<'_, '_, '_, '_, '_> {
+    /// Records that execution descends one level into a nested statement or expression, failing
+    /// if that is deeper than the configured maximum depth.  Execution is recursive, so this
+    /// protects against running out of stack space on deeply nested files.
+    fn enter_nested(&mut self) -> Result<(), ExecutionError> {
+        if self.depth >= self.config.max_depth {
+            return Err(ExecutionError::DepthLimitExceeded {
+                max_depth: self.config.max_depth,
+                location: None,
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Records the step for a value that is about to be stored in a variable, taking the most
+    /// recently evaluated value as its input.
+    fn prepare_assignment(
+        &mut self,
+        variable: &dyn std::fmt::Display,
+    ) -> Option<ProvenanceStepRef> {
+        let provenance = self.provenance.as_deref_mut()?;
+        let step = self.graph.add_provenance_step(ProvenanceStep {
+            origin: Origin::Assignment(variable.to_string()),
+            location: provenance.location,
+            inputs: provenance.pending.pop().into_iter().collect(),
+        });
+        provenance.assigned = Some(step);
+        Some(step)
+    }
+
+    /// Forgets the step of the most recently evaluated value, which is not used.
+    fn discard_value(&mut self) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            provenance.pending.pop();
+        }
+    }
+
+    /// Records that the next value stored in a variable was produced by the given step.
+    fn reuse_assignment(&mut self, step: Option<ProvenanceStepRef>) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            provenance.assigned = step;
+        }
+    }
+
+    /// Records the step for a newly created graph node.
+    fn record_graph_node(&mut self) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            let step = self.graph.add_provenance_step(ProvenanceStep {
+                origin: Origin::GraphNode,
+                location: provenance.location,
+                inputs: Vec::new(),
+            });
+            provenance.pending.push(step);
+        }
+    }
+
+    /// Records the step for an attribute statement, taking the most recently evaluated value as
+    /// its input.
+    fn prepare_attribute(&mut self, name: &Identifier) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            let step = self.graph.add_provenance_step(ProvenanceStep {
+                origin: Origin::Attribute(name.clone()),
+                location: provenance.location,
+                inputs: provenance.pending.pop().into_iter().collect(),
+            });
+            provenance.attribute = Some(step);
+        }
+    }
+
+    /// Records that a graph node attribute was set by the most recent attribute statement.
+    fn record_attribute(&mut self, node: GraphNodeRef, name: Identifier) {
+        if let Some(step) = self
+            .provenance
+            .as_deref_mut()
+            .and_then(|provenance| provenance.attribute.take())
+        {
+            self.graph.set_attribute_provenance(node, name, step);
+        }
+    }
+
+    /// Records that the current match created an edge.
+    fn record_edge(&mut self, source: GraphNodeRef, sink: GraphNodeRef) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            provenance.output.edges.push((source, sink));
+        }
+    }
+
+    /// Records that the current match set an attribute of a graph node or edge.
+    fn record_output_attribute(&mut self, target: MutationTarget, name: &Identifier) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            provenance.output.attributes.push((target, name.clone()));
+        }
+    }
+
+    /// Returns the context in which the current statement mutates the graph, for mutation hooks.
+    fn mutation_context(&self) -> MutationContext<'_, '_> {
+        MutationContext::new(
+            self.graph,
+            self.error_context.statement_location,
+            self.error_context.stanza_location,
+        )
+    }
+
+    /// Calls the observer of the execution, if there is one, for the current statement.
+    fn observe(&self, observe: impl FnOnce(&mut dyn GraphObserver, &MutationContext)) {
+        if let Some(observer) = self.config.observer {
+            observe(&mut *observer.borrow_observer(), &self.mutation_context());
+        }
+    }
+
+    /// Remembers the statement that set an attribute.
+    fn record_attribute_statement(&mut self, key: GraphElementKey) {
+        if self.attribute_statements.get(&key).is_none() {
+            let error_context = self.error_context.clone();
+            self.attribute_statements.insert(key, error_context);
+        }
+    }
+
+    /// Forgets the statement that set an attribute, because the attribute was removed.
+    fn forget_attribute_statement(&mut self, key: &GraphElementKey) {
+        self.attribute_statements.remove(key);
+    }
+
+    /// Returns the context of a conflicting attribute statement, which includes the statement
+    /// that previously set the attribute, if it is known.
+    fn conflict_context(&self, key: &GraphElementKey) -> Context {
+        match self.attribute_statements.get(key) {
+            Some(previous) => (previous.clone(), self.error_context.clone()).into(),
+            None => self.error_context.clone().into(),
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            provenance.locals.push(HashMap::new());
+        }
+    }
+
+    fn exit_scope(&mut self) {
+        if let Some(provenance) = self.provenance.as_deref_mut() {
+            provenance.locals.pop();
+        }
+    }
+}
+
+/// The state needed to record the provenance of values during execution.  The steps themselves
+/// are stored in the graph; this tracks which steps produced the values that are currently being
+/// evaluated or are stored in variables.
+struct ProvenanceTracker {
+    /// Steps of evaluated values that have not been used yet
+    pending: Vec<ProvenanceStepRef>,
+    /// Steps of the values of local variables, for each nested block
+    locals: Vec<HashMap<Identifier, ProvenanceStepRef>>,
+    /// Steps of the values of scoped variables
+    scoped: CheckpointedMap<(SyntaxNodeID, Identifier), ProvenanceStepRef>,
+    /// Location of the statement that is executing
+    location: Location,
+    /// Step of the value of the most recently read variable
+    read: Option<ProvenanceStepRef>,
+    /// Step of the value that is about to be stored in a variable
+    assigned: Option<ProvenanceStepRef>,
+    /// Step of the attribute statement that is executing
+    attribute: Option<ProvenanceStepRef>,
+    /// Edges created and attributes set by the current match, which are recorded in the graph if
+    /// the match succeeds
+    output: StanzaOutput,
+}
+
+impl ProvenanceTracker {
+    fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            locals: vec![HashMap::new()],
+            scoped: CheckpointedMap::new(),
+            location: Location::default(),
+            read: None,
+            assigned: None,
+            attribute: None,
+            output: StanzaOutput::default(),
+        }
+    }
+
+    /// Clears the state of the previous match.  Scoped variables are kept, because they outlive
+    /// the match that set them.
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.locals = vec![HashMap::new()];
+        self.read = None;
+        self.assigned = None;
+        self.attribute = None;
+        self.output = StanzaOutput::default();
+    }
+
+    fn checkpoint(&mut self) {
+        self.scoped.checkpoint();
+    }
+
+    fn commit(&mut self) {
+        self.scoped.commit();
+    }
+
+    fn rollback(&mut self) {
+        self.scoped.rollback();
+    }
+
+    fn add_local(&mut self, name: &Identifier) {
+        if let Some(step) = self.assigned.take() {
+            self.locals
+                .last_mut()
+                .expect("missing local scope")
+                .insert(name.clone(), step);
+        }
+    }
+
+    fn set_local(&mut self, name: &Identifier) {
+        if let Some(step) = self.assigned.take() {
+            if let Some(locals) = self.locals.iter_mut().rev().find(|l| l.contains_key(name)) {
+                locals.insert(name.clone(), step);
+            }
+        }
+    }
+
+    fn read_local(&mut self, name: &Identifier) {
+        self.read = self
+            .locals
+            .iter()
+            .rev()
+            .find_map(|locals| locals.get(name))
+            .copied();
+    }
+
+    fn set_scoped(&mut self, scope: SyntaxNodeID, name: &Identifier) {
+        if let Some(step) = self.assigned.take() {
+            self.scoped.insert((scope, name.clone()), step);
+        }
+    }
+
+    fn read_scoped(&mut self, scope: SyntaxNodeID, name: &Identifier) {
+        self.read = self.scoped.get(&(scope, name.clone())).copied();
+    }
+}
+
+/// A map whose changes since a checkpoint can be rolled back
+struct CheckpointedMap<K, V> {
+    map: HashMap<K, V>,
+    /// The original value of every key changed since the last checkpoint, if one is active
+    saved: Option<HashMap<K, Option<V>>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> CheckpointedMap<K, V> {
+    fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            saved: None,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.save(&key);
+        self.map.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.save(key);
+        self.map.remove(key);
+    }
+
+    fn save(&mut self, key: &K) {
+        if let Some(saved) = &mut self.saved {
+            let map = &self.map;
+            saved
+                .entry(key.clone())
+                .or_insert_with(|| map.get(key).cloned());
+        }
+    }
+
+    fn checkpoint(&mut self) {
+        self.saved = Some(HashMap::new());
+    }
+
+    fn commit(&mut self) {
+        self.saved = None;
+    }
+
+    fn rollback(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            for (key, value) in saved {
+                match value {
+                    Some(value) => self.map.insert(key, value),
+                    None => self.map.remove(&key),
+                };
+            }
+        }
+    }
+}
+
 struct ScopedVariables<'a> {
     scopes: HashMap<SyntaxNodeID, VariableMap<'a, Value>>,
+    /// The original contents of every scope modified since the last checkpoint, if one is active
+    saved_scopes: Option<HashMap<SyntaxNodeID, Option<VariableMap<'a, Value>>>>,
 }
 
 impl<'a> ScopedVariables<'a> {
     fn new() -> Self {
         Self {
             scopes: HashMap::new(),
+            saved_scopes: None,
         }
     }
 
     fn get_mut(&mut self, scope: SyntaxNodeRef) -> &mut VariableMap<'a, Value> {
+        if let Some(saved_scopes) = &mut self.saved_scopes {
+            let scopes = &self.scopes;
+            saved_scopes
+                .entry(scope.index)
+                .or_insert_with(|| scopes.get(&scope.index).map(VariableMap::clone_detached));
+        }
         self.scopes.entry(scope.index).or_insert(VariableMap::new())
     }
 
+    fn checkpoint(&mut self) {
+        self.saved_scopes = Some(HashMap::new());
+    }
+
+    fn commit(&mut self) {
+        self.saved_scopes = None;
+    }
+
+    fn rollback(&mut self) {
+        if let Some(saved_scopes) = self.saved_scopes.take() {
+            for (index, saved) in saved_scopes {
+                match saved {
+                    Some(variables) => self.scopes.insert(index, variables),
+                    None => self.scopes.remove(&index),
+                };
+            }
+        }
+    }
+
     fn try_get(&self, index: SyntaxNodeID) -> Option<&VariableMap<'a, Value>> {
         self.scopes.get(&index)
     }
+
+    /// Records every graph node stored in a scoped variable as owned by the variable's scope.
+    fn add_owned_graph_nodes(&self, graph: &mut Graph) {
+        for (index, variables) in &self.scopes {
+            for (name, value) in variables.iter() {
+                if let Value::GraphNode(node) = value {
+                    graph.add_owned_graph_node(*index, Tag::from(name.clone()), *node);
+                }
+            }
+        }
+    }
 }
 
 impl Stanza {
     fn execute<'a, 'g, 'l, 's, 'tree>(
         &self,
         source: &'tree str,
-        mat: &QueryMatch<'_, 'tree>,
+        captures: &[QueryCapture<'tree>],
         graph: &mut Graph<'tree>,
         config: &ExecutionConfig<'_, 'g>,
         locals: &mut VariableMap<'l, Value>,
         scoped: &mut ScopedVariables<'s>,
-        current_regex_captures: &Vec<String>,
+        scan_match: &ScanMatch,
         function_parameters: &mut Vec<Value>,
         inherited_variables: &HashSet<Identifier>,
         shorthands: &AttributeShorthands,
+        mut provenance: Option<&mut ProvenanceTracker>,
+        attribute_statements: &mut CheckpointedMap<GraphElementKey, StatementContext>,
+        batched_calls: &BatchedCalls,
+        memoized_calls: &mut MemoizedCalls,
         cancellation_flag: &dyn CancellationFlag,
     ) -> Result<(), ExecutionError> {
         locals.clear();
+        memoized_calls.clear();
+        if let Some(provenance) = provenance.as_deref_mut() {
+            provenance.reset();
+        }
+        let first_node = graph.node_count();
         for statement in &self.statements {
             let error_context = {
-                let node = mat
-                    .nodes_for_capture_index(self.full_match_stanza_capture_index as u32)
-                    .next()
-                    .expect("missing full capture");
+                let node =
+                    nodes_for_capture_index(captures, self.full_match_stanza_capture_index as u32)
+                        .next()
+                        .expect("missing full capture");
                 StatementContext::new(&statement, &self, &node)
             };
             let mut exec = ExecutionContext {
@@ -191,22 +848,94 @@ impl Stanza {
                 config,
                 locals,
                 scoped,
-                current_regex_captures,
+                scan_match,
                 function_parameters,
-                mat: &mat,
+                captures,
                 full_match_stanza_capture_index: self.full_match_stanza_capture_index,
                 error_context,
                 inherited_variables,
                 shorthands,
+                provenance: provenance.as_deref_mut(),
+                attribute_statements,
+                batched_calls,
+                memoized_calls,
+                depth: 0,
                 cancellation_flag,
             };
             statement
                 .execute(&mut exec)
                 .with_context(|| exec.error_context.into())?;
         }
+        if let Some(provenance) = provenance {
+            let mut output = std::mem::take(&mut provenance.output);
+            output.nodes = graph.graph_nodes_from(first_node).collect();
+            let matched =
+                nodes_for_capture_index(captures, self.full_match_stanza_capture_index as u32)
+                    .next()
+                    .expect("missing full capture")
+                    .byte_range();
+            graph.add_stanza_output(self.range.start, matched, output);
+        }
         Ok(())
     }
 
+    /// Returns the captures of a match that was produced elsewhere, after checking that they agree
+    /// with the captures of this stanza's query.
+    fn external_match_captures<'tree>(
+        &self,
+        mat: &ExternalMatch<'tree>,
+    ) -> Result<Vec<QueryCapture<'tree>>, ExecutionError> {
+        let mut captures = vec![QueryCapture {
+            node: mat.node,
+            index: self.full_match_stanza_capture_index as u32,
+        }];
+        for (name, node) in &mat.captures {
+            let index = self
+                .query
+                .capture_index_for_name(name)
+                .filter(|index| *index as usize != self.full_match_stanza_capture_index)
+                .ok_or_else(|| {
+                    ExecutionError::UndefinedCapture(format!(
+                        "@{} in stanza at {}",
+                        name, self.range.start
+                    ))
+                })?;
+            captures.push(QueryCapture { node: *node, index });
+        }
+        let quantifiers = self.query.capture_quantifiers(0);
+        for (index, name) in self.query.capture_names().iter().enumerate() {
+            if index == self.full_match_stanza_capture_index {
+                continue;
+            }
+            let count = nodes_for_capture_index(&captures, index as u32).count();
+            let valid = match quantifiers[index] {
+                CaptureQuantifier::Zero => count == 0,
+                CaptureQuantifier::ZeroOrOne => count <= 1,
+                CaptureQuantifier::ZeroOrMore => true,
+                CaptureQuantifier::One => count == 1,
+                CaptureQuantifier::OneOrMore => count >= 1,
+            };
+            if !valid {
+                return Err(ExecutionError::InvalidMatch(format!(
+                    "for stanza at {}, which has {} nodes for capture @{}",
+                    self.range.start, count, name
+                )));
+            }
+        }
+        Ok(captures)
+    }
+
+    fn match_error(&self, captures: &[QueryCapture], error: ExecutionError) -> MatchError {
+        let node = nodes_for_capture_index(captures, self.full_match_stanza_capture_index as u32)
+            .next()
+            .expect("missing full capture");
+        MatchError {
+            stanza_location: self.range.start,
+            match_location: node.start_position().into(),
+            error,
+        }
+    }
+
     pub(super) fn try_visit_matches_strict<'tree, E, F>(
         &self,
         tree: &'tree Tree,
@@ -216,12 +945,13 @@ impl Stanza {
     where
         F: FnMut(QueryMatch<'_, 'tree>) -> Result<(), E>,
     {
-        let mut cursor = QueryCursor::new();
-        let matches = cursor.matches(&self.query, tree.root_node(), source.as_bytes());
-        for mat in matches {
-            visit(mat)?;
-        }
-        Ok(())
+        with_query_cursor(|cursor| {
+            let matches = cursor.matches(&self.query, tree.root_node(), source.as_bytes());
+            for mat in matches {
+                visit(mat)?;
+            }
+            Ok(())
+        })
     }
 }
 
@@ -235,6 +965,8 @@ impl Statement {
             Statement::AddGraphNodeAttribute(s) => s.location,
             Statement::CreateEdge(s) => s.location,
             Statement::AddEdgeAttribute(s) => s.location,
+            Statement::AddAttributes(s) => s.location,
+            Statement::RemoveAttributes(s) => s.location,
             Statement::Scan(s) => s.location,
             Statement::Print(s) => s.location,
             Statement::If(s) => s.location,
@@ -244,7 +976,15 @@ impl Statement {
 
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         exec.cancellation_flag.check("executing statement")?;
-        match self {
+        exec.enter_nested()
+            .map_err(|e| e.at_depth_limit(self.location()))?;
+        exec.config.record_usage(UsageCounters::record_statement);
+        let outer = exec.provenance.as_deref_mut().map(|provenance| {
+            let outer = (provenance.pending.len(), provenance.location);
+            provenance.location = self.location();
+            outer
+        });
+        let result = match self {
             Statement::DeclareImmutable(statement) => statement.execute(exec),
             Statement::DeclareMutable(statement) => statement.execute(exec),
             Statement::Assign(statement) => statement.execute(exec),
@@ -252,17 +992,28 @@ impl Statement {
             Statement::AddGraphNodeAttribute(statement) => statement.execute(exec),
             Statement::CreateEdge(statement) => statement.execute(exec),
             Statement::AddEdgeAttribute(statement) => statement.execute(exec),
+            Statement::AddAttributes(statement) => statement.execute(exec),
+            Statement::RemoveAttributes(statement) => statement.execute(exec),
             Statement::Scan(statement) => statement.execute(exec),
             Statement::Print(statement) => statement.execute(exec),
             Statement::If(statement) => statement.execute(exec),
             Statement::ForIn(statement) => statement.execute(exec),
+        };
+        exec.depth -= 1;
+        if let (Some(provenance), Some((pending, location))) =
+            (exec.provenance.as_deref_mut(), outer)
+        {
+            provenance.pending.truncate(pending);
+            provenance.location = location;
         }
+        result.map_err(|e| e.at_depth_limit(self.location()))
     }
 }
 
 impl DeclareImmutable {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let value = self.value.evaluate(exec)?;
+        exec.prepare_assignment(&self.variable);
         self.variable.add(exec, value, false)
     }
 }
@@ -270,6 +1021,7 @@ impl DeclareImmutable {
 impl DeclareMutable {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let value = self.value.evaluate(exec)?;
+        exec.prepare_assignment(&self.variable);
         self.variable.add(exec, value, true)
     }
 }
@@ -277,21 +1029,28 @@ impl DeclareMutable {
 impl Assign {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let value = self.value.evaluate(exec)?;
+        exec.prepare_assignment(&self.variable);
         self.variable.set(exec, value)
     }
 }
 
 impl CreateGraphNode {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
+        if let Some(hook) = exec.config.mutation_hook {
+            if !hook.before_node(&exec.mutation_context()).proceed()? {
+                exec.prepare_assignment(&self.node);
+                return self.node.add(exec, Value::Null, false);
+            }
+        }
         let graph_node = exec.graph.add_graph_node();
+        exec.config.record_usage(UsageCounters::record_graph_node);
         self.node
             .add_debug_attrs(&mut exec.graph[graph_node].attributes, exec.config)?;
         if let Some(match_node_attr) = &exec.config.match_node_attr {
-            let match_node = exec
-                .mat
-                .nodes_for_capture_index(exec.full_match_stanza_capture_index as u32)
-                .next()
-                .expect("missing capture for full match");
+            let match_node =
+                nodes_for_capture_index(exec.captures, exec.full_match_stanza_capture_index as u32)
+                    .next()
+                    .expect("missing capture for full match");
             let syn_node = exec.graph.add_syntax_node(match_node);
             exec.graph[graph_node]
                 .attributes
@@ -303,40 +1062,148 @@ impl CreateGraphNode {
                     ))
                 })?;
         }
+        for (name, value) in &self.defaults {
+            exec.graph[graph_node]
+                .attributes
+                .add_default(name.clone(), value.clone());
+        }
+        exec.graph.index_attributes(graph_node);
+        if exec.config.observer.is_some() {
+            let match_node =
+                nodes_for_capture_index(exec.captures, exec.full_match_stanza_capture_index as u32)
+                    .next()
+                    .expect("missing capture for full match");
+            let syn_node = exec.graph.add_syntax_node(match_node);
+            exec.observe(|observer, ctx| observer.on_node_created(graph_node, syn_node, ctx));
+        }
         let value = Value::GraphNode(graph_node);
-        self.node.add(exec, value, false)
+        exec.record_graph_node();
+        exec.prepare_assignment(&self.node);
+        self.node.add(exec, value, false)?;
+        let add_attribute = |exec: &mut ExecutionContext,
+                             name: Identifier,
+                             value: Value,
+                             operation: AttributeOperation| {
+            add_graph_node_attribute(exec, graph_node, name, value, operation, self)
+        };
+        for attribute in &self.attributes {
+            attribute.execute(exec, &add_attribute, AttributeOperation::Set)?;
+        }
+        Ok(())
     }
 }
 
 impl AddGraphNodeAttribute {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let node = self.node.evaluate(exec)?.into_graph_node_ref()?;
-        let add_attribute = |exec: &mut ExecutionContext, name: Identifier, value: Value| {
-            exec.graph[node]
-                .attributes
-                .add(name.clone(), value)
-                .map_err(|_| {
-                    ExecutionError::DuplicateAttribute(format!(
-                        " {} on graph node ({}) in {}",
-                        name, node, self,
-                    ))
-                })
+        let add_attribute = |exec: &mut ExecutionContext,
+                             name: Identifier,
+                             value: Value,
+                             operation: AttributeOperation| {
+            add_graph_node_attribute(exec, node, name, value, operation, self)
         };
         for attribute in &self.attributes {
-            attribute.execute(exec, &add_attribute)?;
+            attribute.execute(exec, &add_attribute, AttributeOperation::Set)?;
         }
         Ok(())
     }
 }
 
+/// Adds an attribute to a graph node, reporting conflicts as errors in the given statement.
+fn add_graph_node_attribute(
+    exec: &mut ExecutionContext,
+    node: GraphNodeRef,
+    name: Identifier,
+    value: Value,
+    operation: AttributeOperation,
+    statement: &dyn fmt::Display,
+) -> Result<(), ExecutionError> {
+    if let Some(hook) = exec.config.mutation_hook {
+        let target = MutationTarget::GraphNode(node);
+        if !hook
+            .before_attr(target, &name, &value, &exec.mutation_context())
+            .proceed()?
+        {
+            return Ok(());
+        }
+    }
+    let key = GraphElementKey::NodeAttribute(node, name.clone());
+    let attributes = &mut exec.graph[node].attributes;
+    match operation {
+        AttributeOperation::Set => {
+            if attributes.add(name.clone(), value).is_err() {
+                return Err(ExecutionError::DuplicateAttribute(format!(
+                    " {} on graph node ({}) in {}",
+                    name, node, statement,
+                )))
+                .with_context(|| exec.conflict_context(&key));
+            }
+        }
+        AttributeOperation::Append => {
+            if attributes.append(name.clone(), value).is_err() {
+                return Err(ExecutionError::CannotAppendToAttribute(format!(
+                    "{} on graph node ({}) in {}",
+                    name, node, statement,
+                )))
+                .with_context(|| exec.conflict_context(&key));
+            }
+        }
+        AttributeOperation::Override => {
+            if attributes.get(&name).is_none() {
+                return Err(ExecutionError::CannotOverrideAttribute(format!(
+                    "{} on graph node ({}) in {}",
+                    name, node, statement,
+                )));
+            }
+            attributes.set(name.clone(), value);
+        }
+    }
+    exec.graph.index_attribute(node, &name);
+    exec.observe(|observer, ctx| {
+        let value = ctx.graph()[node]
+            .attributes
+            .get(&name)
+            .expect("missing attribute");
+        observer.on_attr_added(MutationTarget::GraphNode(node), &name, value, ctx)
+    });
+    exec.config.record_usage(UsageCounters::record_attribute);
+    exec.record_attribute_statement(key);
+    exec.record_output_attribute(MutationTarget::GraphNode(node), &name);
+    exec.record_attribute(node, name);
+    Ok(())
+}
+
 impl CreateEdge {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let source = self.source.evaluate(exec)?.into_graph_node_ref()?;
         let sink = self.sink.evaluate(exec)?.into_graph_node_ref()?;
-        let edge = match exec.graph[source].add_edge(sink) {
-            Ok(edge) | Err(edge) => edge,
+        if let Some(hook) = exec.config.mutation_hook {
+            if !hook
+                .before_edge(source, sink, &exec.mutation_context())
+                .proceed()?
+            {
+                return Ok(());
+            }
+        }
+        let (edge, created) = match exec.graph[source].add_edge(sink) {
+            Ok(edge) => (edge, true),
+            Err(edge) => (edge, false),
         };
         self.add_debug_attrs(&mut edge.attributes, exec.config)?;
+        if created {
+            exec.config.record_usage(UsageCounters::record_edge);
+            exec.record_edge(source, sink);
+            exec.observe(|observer, ctx| observer.on_edge_created(source, sink, ctx));
+        }
+        let add_attribute = |exec: &mut ExecutionContext,
+                             name: Identifier,
+                             value: Value,
+                             operation: AttributeOperation| {
+            add_edge_attribute(exec, source, sink, name, value, operation, self)
+        };
+        for attribute in &self.attributes {
+            attribute.execute(exec, &add_attribute, AttributeOperation::Set)?;
+        }
         Ok(())
     }
 }
@@ -345,23 +1212,185 @@ impl AddEdgeAttribute {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let source = self.source.evaluate(exec)?.into_graph_node_ref()?;
         let sink = self.sink.evaluate(exec)?.into_graph_node_ref()?;
-        let add_attribute = |exec: &mut ExecutionContext, name: Identifier, value: Value| {
-            let edge = match exec.graph[source].get_edge_mut(sink) {
-                Some(edge) => Ok(edge),
-                None => Err(ExecutionError::UndefinedEdge(format!(
-                    "({} -> {}) in {}",
-                    source, sink, self,
-                ))),
-            }?;
-            edge.attributes.add(name.clone(), value).map_err(|_| {
-                ExecutionError::DuplicateAttribute(format!(
+        let add_attribute = |exec: &mut ExecutionContext,
+                             name: Identifier,
+                             value: Value,
+                             operation: AttributeOperation| {
+            add_edge_attribute(exec, source, sink, name, value, operation, self)
+        };
+        for attribute in &self.attributes {
+            attribute.execute(exec, &add_attribute, AttributeOperation::Set)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds an attribute to an edge, reporting conflicts as errors in the given statement.
+fn add_edge_attribute(
+    exec: &mut ExecutionContext,
+    source: GraphNodeRef,
+    sink: GraphNodeRef,
+    name: Identifier,
+    value: Value,
+    operation: AttributeOperation,
+    statement: &dyn fmt::Display,
+) -> Result<(), ExecutionError> {
+    if let Some(hook) = exec.config.mutation_hook {
+        let target = MutationTarget::Edge(source, sink);
+        if !hook
+            .before_attr(target, &name, &value, &exec.mutation_context())
+            .proceed()?
+        {
+            return Ok(());
+        }
+    }
+    let edge = match exec.graph[source].get_edge_mut(sink) {
+        Some(edge) => Ok(edge),
+        None => Err(ExecutionError::UndefinedEdge(format!(
+            "({} -> {}) in {}",
+            source, sink, statement,
+        ))),
+    }?;
+    let key = GraphElementKey::EdgeAttribute(source, sink, name.clone());
+    match operation {
+        AttributeOperation::Set => {
+            if edge.attributes.add(name.clone(), value).is_err() {
+                return Err(ExecutionError::DuplicateAttribute(format!(
                     " {} on edge ({} -> {}) in {}",
-                    name, source, sink, self,
-                ))
-            })
+                    name, source, sink, statement,
+                )))
+                .with_context(|| exec.conflict_context(&key));
+            }
+        }
+        AttributeOperation::Append => {
+            if edge.attributes.append(name.clone(), value).is_err() {
+                return Err(ExecutionError::CannotAppendToAttribute(format!(
+                    "{} on edge ({} -> {}) in {}",
+                    name, source, sink, statement,
+                )))
+                .with_context(|| exec.conflict_context(&key));
+            }
+        }
+        AttributeOperation::Override => {
+            if edge.attributes.get(&name).is_none() {
+                return Err(ExecutionError::CannotOverrideAttribute(format!(
+                    "{} on edge ({} -> {}) in {}",
+                    name, source, sink, statement,
+                )));
+            }
+            edge.attributes.set(name.clone(), value);
+        }
+    }
+    exec.observe(|observer, ctx| {
+        let edge = ctx.graph()[source].get_edge(sink).expect("missing edge");
+        let target = MutationTarget::Edge(source, sink);
+        let value = edge.attributes.get(&name).expect("missing attribute");
+        observer.on_attr_added(target, &name, value, ctx)
+    });
+    exec.config.record_usage(UsageCounters::record_attribute);
+    exec.record_attribute_statement(key);
+    exec.record_output_attribute(MutationTarget::Edge(source, sink), &name);
+    Ok(())
+}
+
+impl AddAttributes {
+    fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
+        let mut targets = Vec::new();
+        for target in &self.targets {
+            targets.push(match target {
+                AttributeTarget::GraphNode(node) => {
+                    (node.evaluate(exec)?.into_graph_node_ref()?, None)
+                }
+                AttributeTarget::Edge { source, sink } => (
+                    source.evaluate(exec)?.into_graph_node_ref()?,
+                    Some(sink.evaluate(exec)?.into_graph_node_ref()?),
+                ),
+            });
+        }
+        // The attribute values are evaluated once, and then added to each target in turn.
+        let attributes = RefCell::new(Vec::new());
+        let add_attribute = |exec: &mut ExecutionContext,
+                             name: Identifier,
+                             value: Value,
+                             operation: AttributeOperation| {
+            let step = exec
+                .provenance
+                .as_deref_mut()
+                .and_then(|provenance| provenance.attribute.take());
+            attributes.borrow_mut().push((name, value, operation, step));
+            Ok(())
         };
         for attribute in &self.attributes {
-            attribute.execute(exec, &add_attribute)?;
+            attribute.execute(exec, &add_attribute, AttributeOperation::Set)?;
+        }
+        for (index, (node_or_source, sink)) in targets.into_iter().enumerate() {
+            for (name, value, operation, step) in attributes.borrow().iter() {
+                if let Some(provenance) = exec.provenance.as_deref_mut() {
+                    provenance.attribute = *step;
+                }
+                let (name, value) = (name.clone(), value.clone());
+                match sink {
+                    None => add_graph_node_attribute(
+                        exec,
+                        node_or_source,
+                        name,
+                        value,
+                        *operation,
+                        self,
+                    ),
+                    Some(sink) => add_edge_attribute(
+                        exec,
+                        node_or_source,
+                        sink,
+                        name,
+                        value,
+                        *operation,
+                        self,
+                    ),
+                }
+                .with_context(|| format!("Adding attributes to {}", self.targets[index]).into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RemoveAttributes {
+    fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
+        for target in &self.targets {
+            match target {
+                AttributeTarget::GraphNode(node) => {
+                    let node = node.evaluate(exec)?.into_graph_node_ref()?;
+                    for name in &self.names {
+                        exec.graph[node].attributes.remove(name);
+                        exec.forget_attribute_statement(&GraphElementKey::NodeAttribute(
+                            node,
+                            name.clone(),
+                        ));
+                    }
+                }
+                AttributeTarget::Edge { source, sink } => {
+                    let source = source.evaluate(exec)?.into_graph_node_ref()?;
+                    let sink = sink.evaluate(exec)?.into_graph_node_ref()?;
+                    let edge = match exec.graph[source].get_edge_mut(sink) {
+                        Some(edge) => Ok(edge),
+                        None => Err(ExecutionError::UndefinedEdge(format!(
+                            "({} -> {}) in {}",
+                            source, sink, self,
+                        ))),
+                    }?;
+                    for name in &self.names {
+                        edge.attributes.remove(name);
+                    }
+                    for name in &self.names {
+                        exec.forget_attribute_statement(&GraphElementKey::EdgeAttribute(
+                            source,
+                            sink,
+                            name.clone(),
+                        ));
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -369,49 +1398,60 @@ impl AddEdgeAttribute {
 
 impl Scan {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
-        let match_string = self.value.evaluate(exec)?.into_string()?;
+        let source = exec.source;
+        match self.value.evaluate(exec)? {
+            Value::List(values) => values
+                .iter()
+                .try_for_each(|value| self.scan(exec, scan_subject(value, source)?)),
+            value => self.scan(exec, scan_subject(&value, source)?),
+        }
+    }
 
+    /// Runs the arms of this statement over the matches in a text.
+    fn scan(&self, exec: &mut ExecutionContext, match_string: &str) -> Result<(), ExecutionError> {
         let mut i = 0;
-        let mut matches = Vec::new();
+        // the offset of i in characters
+        let mut offset = 0;
         while i < match_string.len() {
             exec.cancellation_flag.check("processing scan matches")?;
-            matches.clear();
+            exec.config.record_usage(|usage| {
+                usage.record_scan_iteration(self.arms.len(), match_string.len() - i)
+            });
+            let mut first_match: Option<(Vec<Option<Range<usize>>>, usize)> = None;
             for (index, arm) in self.arms.iter().enumerate() {
-                let captures = arm.regex.captures(&match_string[i..]);
-                if let Some(captures) = captures {
-                    if captures
-                        .get(0)
-                        .expect("missing regex capture")
-                        .range()
-                        .is_empty()
-                    {
+                if let Some(captures) = arm.regex.capture_ranges(&match_string[i..]) {
+                    let range = captures[0].as_ref().expect("missing regex capture");
+                    if range.is_empty() {
                         return Err(ExecutionError::EmptyRegexCapture(format!(
                             "for regular expression /{}/",
                             arm.regex
                         )));
                     }
-                    matches.push((captures, index));
+                    let is_first = match &first_match {
+                        Some((first, _)) => {
+                            range.start < first[0].as_ref().expect("missing regex capture").start
+                        }
+                        None => true,
+                    };
+                    if is_first {
+                        first_match = Some((captures, index));
+                    }
                 }
             }
 
-            if matches.is_empty() {
-                return Ok(());
-            }
-
-            matches.sort_by_key(|(captures, index)| {
-                let range = captures.get(0).expect("missing regex capture").range();
-                (range.start, *index)
-            });
-
-            let (regex_captures, block_index) = &matches[0];
-            let arm = &self.arms[*block_index];
+            let (regex_captures, block_index) = match first_match {
+                Some(first_match) => first_match,
+                None => return Ok(()),
+            };
+            let arm = &self.arms[block_index];
+            let match_end = regex_captures[0]
+                .as_ref()
+                .expect("missing regex capture")
+                .end;
 
-            let mut current_regex_captures = Vec::new();
-            for regex_capture in regex_captures.iter() {
-                current_regex_captures
-                    .push(regex_capture.map(|m| m.as_str()).unwrap_or("").to_string());
-            }
+            let scan_match = ScanMatch::new(&match_string[i..], offset, regex_captures);
 
+            exec.enter_scope();
             let mut arm_locals = VariableMap::nested(exec.locals);
             let mut arm_exec = ExecutionContext {
                 source: exec.source,
@@ -419,13 +1459,18 @@ impl Scan {
                 config: exec.config,
                 locals: &mut arm_locals,
                 scoped: exec.scoped,
-                current_regex_captures: &current_regex_captures,
+                scan_match: &scan_match,
                 function_parameters: exec.function_parameters,
-                mat: exec.mat,
+                captures: exec.captures,
                 full_match_stanza_capture_index: exec.full_match_stanza_capture_index,
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                provenance: exec.provenance.as_deref_mut(),
+                attribute_statements: exec.attribute_statements,
+                batched_calls: exec.batched_calls,
+                memoized_calls: exec.memoized_calls,
+                depth: exec.depth,
                 cancellation_flag: exec.cancellation_flag,
             };
 
@@ -438,12 +1483,10 @@ impl Scan {
                     })
                     .with_context(|| arm_exec.error_context.clone().into())?;
             }
+            exec.exit_scope();
 
-            i += regex_captures
-                .get(0)
-                .expect("missing regex capture")
-                .range()
-                .end;
+            i += match_end;
+            offset = scan_match.end;
         }
 
         Ok(())
@@ -473,6 +1516,7 @@ impl If {
                 result &= condition.test(exec)?;
             }
             if result {
+                exec.enter_scope();
                 let mut arm_locals = VariableMap::nested(exec.locals);
                 let mut arm_exec = ExecutionContext {
                     source: exec.source,
@@ -480,13 +1524,18 @@ impl If {
                     config: exec.config,
                     locals: &mut arm_locals,
                     scoped: exec.scoped,
-                    current_regex_captures: exec.current_regex_captures,
+                    scan_match: exec.scan_match,
                     function_parameters: exec.function_parameters,
-                    mat: exec.mat,
+                    captures: exec.captures,
                     full_match_stanza_capture_index: exec.full_match_stanza_capture_index,
                     error_context: exec.error_context.clone(),
                     inherited_variables: exec.inherited_variables,
                     shorthands: exec.shorthands,
+                    provenance: exec.provenance.as_deref_mut(),
+                    attribute_statements: exec.attribute_statements,
+                    batched_calls: exec.batched_calls,
+                    memoized_calls: exec.memoized_calls,
+                    depth: exec.depth,
                     cancellation_flag: exec.cancellation_flag,
                 };
                 for stmt in &arm.statements {
@@ -494,6 +1543,7 @@ impl If {
                     stmt.execute(&mut arm_exec)
                         .with_context(|| arm_exec.error_context.clone().into())?;
                 }
+                exec.exit_scope();
                 break;
             }
         }
@@ -514,8 +1564,10 @@ impl Condition {
 impl ForIn {
     fn execute(&self, exec: &mut ExecutionContext) -> Result<(), ExecutionError> {
         let values = self.value.evaluate(exec)?.into_list()?;
+        let assignment = exec.prepare_assignment(&self.variable);
+        exec.enter_scope();
         let mut loop_locals = VariableMap::nested(exec.locals);
-        for value in values {
+        for (index, value) in values.into_iter().enumerate() {
             loop_locals.clear();
             let mut loop_exec = ExecutionContext {
                 source: exec.source,
@@ -523,43 +1575,122 @@ impl ForIn {
                 config: exec.config,
                 locals: &mut loop_locals,
                 scoped: exec.scoped,
-                current_regex_captures: exec.current_regex_captures,
+                scan_match: exec.scan_match,
                 function_parameters: exec.function_parameters,
-                mat: exec.mat,
+                captures: exec.captures,
                 full_match_stanza_capture_index: exec.full_match_stanza_capture_index,
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                provenance: exec.provenance.as_deref_mut(),
+                attribute_statements: exec.attribute_statements,
+                batched_calls: exec.batched_calls,
+                memoized_calls: exec.memoized_calls,
+                depth: exec.depth,
                 cancellation_flag: exec.cancellation_flag,
             };
+            loop_exec.reuse_assignment(assignment);
             self.variable.add(&mut loop_exec, value, false)?;
+            if let Some(variable) = &self.index {
+                variable.add(&mut loop_exec, (index as i64).into(), false)?;
+            }
             for stmt in &self.statements {
                 loop_exec.error_context.update_statement(stmt);
                 stmt.execute(&mut loop_exec)
                     .with_context(|| loop_exec.error_context.clone().into())?;
             }
         }
+        exec.exit_scope();
         Ok(())
     }
 }
 
 impl Expression {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
-        match self {
+        exec.enter_nested()?;
+        let pending = exec
+            .provenance
+            .as_ref()
+            .map(|provenance| provenance.pending.len());
+        let value = match self {
             Expression::FalseLiteral => Ok(Value::Boolean(false)),
             Expression::NullLiteral => Ok(Value::Null),
             Expression::TrueLiteral => Ok(Value::Boolean(true)),
             Expression::IntegerConstant(expr) => expr.evaluate(exec),
+            Expression::FloatConstant(expr) => expr.evaluate(exec),
             Expression::StringConstant(expr) => expr.evaluate(exec),
             Expression::ListLiteral(expr) => expr.evaluate(exec),
             Expression::SetLiteral(expr) => expr.evaluate(exec),
+            Expression::MapLiteral(expr) => expr.evaluate(exec),
             Expression::ListComprehension(expr) => expr.evaluate(exec),
             Expression::SetComprehension(expr) => expr.evaluate(exec),
             Expression::Capture(expr) => expr.evaluate(exec),
+            Expression::CaptureAggregate(expr) => Ok(expr.evaluate(
+                exec.graph,
+                nodes_for_capture_index(exec.captures, expr.capture.stanza_capture_index as u32),
+            )),
             Expression::Variable(expr) => expr.evaluate(exec),
             Expression::Call(expr) => expr.evaluate(exec),
             Expression::RegexCapture(expr) => expr.evaluate(exec),
+            Expression::MatchOffset(expr) => expr.evaluate(exec),
+        };
+        exec.depth -= 1;
+        let value = value?;
+        if let Some(pending) = pending {
+            self.record_provenance(exec, pending);
         }
+        Ok(value)
+    }
+
+    /// Records the step that produced the value of this expression, whose inputs are the values
+    /// evaluated since there were `pending` unused steps.
+    fn record_provenance(&self, exec: &mut ExecutionContext, pending: usize) {
+        let provenance = match exec.provenance.as_deref_mut() {
+            Some(provenance) => provenance,
+            None => return,
+        };
+        let mut inputs = provenance.pending.split_off(pending);
+        let mut location = provenance.location;
+        let origin = match self {
+            Expression::FalseLiteral
+            | Expression::NullLiteral
+            | Expression::TrueLiteral
+            | Expression::IntegerConstant(_)
+            | Expression::FloatConstant(_)
+            | Expression::StringConstant(_) => Origin::Literal,
+            Expression::ListLiteral(_) | Expression::ListComprehension(_) => Origin::List,
+            Expression::SetLiteral(_) | Expression::SetComprehension(_) => Origin::Set,
+            Expression::MapLiteral(_) => Origin::Map,
+            Expression::Capture(expr) => Origin::Capture(expr.name.clone()),
+            Expression::CaptureAggregate(expr) => {
+                Origin::Call(Identifier::from(expr.aggregate.to_string().as_str()))
+            }
+            Expression::Call(expr) => Origin::Call(expr.function.clone()),
+            Expression::RegexCapture(expr) => Origin::RegexCapture(expr.match_index),
+            Expression::MatchOffset(_) => Origin::RegexCapture(0),
+            Expression::Variable(Variable::Unscoped(variable))
+                if exec.config.globals.get(&variable.name).is_some() =>
+            {
+                inputs.clear();
+                location = variable.location;
+                Origin::Global(variable.name.clone())
+            }
+            Expression::Variable(variable) => {
+                // The scope of a scoped variable does not contribute to its value.
+                inputs = provenance.read.take().into_iter().collect();
+                location = match variable {
+                    Variable::Scoped(variable) => variable.location,
+                    Variable::Unscoped(variable) => variable.location,
+                };
+                Origin::Variable(variable.to_string())
+            }
+        };
+        let step = exec.graph.add_provenance_step(ProvenanceStep {
+            origin,
+            location,
+            inputs,
+        });
+        provenance.pending.push(step);
     }
 }
 
@@ -569,6 +1700,12 @@ impl IntegerConstant {
     }
 }
 
+impl FloatConstant {
+    fn evaluate(&self, _exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
+        Ok(Value::Float(self.value))
+    }
+}
+
 impl StringConstant {
     fn evaluate(&self, _exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         Ok(Value::String(self.value.clone()))
@@ -582,13 +1719,17 @@ impl ListLiteral {
             .iter()
             .map(|e| e.evaluate(exec))
             .collect::<Result<_, _>>()?;
-        Ok(Value::List(elements))
+        let value = Value::List(elements);
+        exec.config.record_usage(|usage| usage.record_value(&value));
+        Ok(value)
     }
 }
 
 impl ListComprehension {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         let values = self.value.evaluate(exec)?.into_list()?;
+        let assignment = exec.prepare_assignment(&self.variable);
+        exec.enter_scope();
         let mut elements = Vec::new();
         let mut loop_locals = VariableMap::nested(exec.locals);
         for value in values {
@@ -599,20 +1740,29 @@ impl ListComprehension {
                 config: exec.config,
                 locals: &mut loop_locals,
                 scoped: exec.scoped,
-                current_regex_captures: exec.current_regex_captures,
+                scan_match: exec.scan_match,
                 function_parameters: exec.function_parameters,
-                mat: exec.mat,
+                captures: exec.captures,
                 full_match_stanza_capture_index: exec.full_match_stanza_capture_index,
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                provenance: exec.provenance.as_deref_mut(),
+                attribute_statements: exec.attribute_statements,
+                batched_calls: exec.batched_calls,
+                memoized_calls: exec.memoized_calls,
+                depth: exec.depth,
                 cancellation_flag: exec.cancellation_flag,
             };
+            loop_exec.reuse_assignment(assignment);
             self.variable.add(&mut loop_exec, value, false)?;
             let element = self.element.evaluate(&mut loop_exec)?;
             elements.push(element);
         }
-        Ok(Value::List(elements))
+        exec.exit_scope();
+        let value = Value::List(elements);
+        exec.config.record_usage(|usage| usage.record_value(&value));
+        Ok(value)
     }
 }
 
@@ -627,9 +1777,22 @@ impl SetLiteral {
     }
 }
 
+impl MapLiteral {
+    fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value.evaluate(exec)?)))
+            .collect::<Result<_, ExecutionError>>()?;
+        Ok(Value::Map(entries))
+    }
+}
+
 impl SetComprehension {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         let values = self.value.evaluate(exec)?.into_list()?;
+        let assignment = exec.prepare_assignment(&self.variable);
+        exec.enter_scope();
         let mut elements = BTreeSet::new();
         let mut loop_locals = VariableMap::nested(exec.locals);
         for value in values {
@@ -640,19 +1803,26 @@ impl SetComprehension {
                 config: exec.config,
                 locals: &mut loop_locals,
                 scoped: exec.scoped,
-                current_regex_captures: exec.current_regex_captures,
+                scan_match: exec.scan_match,
                 function_parameters: exec.function_parameters,
-                mat: exec.mat,
+                captures: exec.captures,
                 full_match_stanza_capture_index: exec.full_match_stanza_capture_index,
                 error_context: exec.error_context.clone(),
                 inherited_variables: exec.inherited_variables,
                 shorthands: exec.shorthands,
+                provenance: exec.provenance.as_deref_mut(),
+                attribute_statements: exec.attribute_statements,
+                batched_calls: exec.batched_calls,
+                memoized_calls: exec.memoized_calls,
+                depth: exec.depth,
                 cancellation_flag: exec.cancellation_flag,
             };
+            loop_exec.reuse_assignment(assignment);
             self.variable.add(&mut loop_exec, value, false)?;
             let element = self.element.evaluate(&mut loop_exec)?;
             elements.insert(element);
         }
+        exec.exit_scope();
         Ok(Value::Set(elements))
     }
 }
@@ -661,8 +1831,7 @@ impl Capture {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         Ok(Value::from_nodes(
             exec.graph,
-            exec.mat
-                .nodes_for_capture_index(self.stanza_capture_index as u32),
+            nodes_for_capture_index(exec.captures, self.stanza_capture_index as u32),
             self.quantifier,
         )
         .into())
@@ -671,31 +1840,78 @@ impl Capture {
 
 impl Call {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
+        exec.config
+            .record_usage(|usage| usage.record_call(&self.function));
+        let value = self
+            .call(exec)
+            .map_err(|e| e.at_depth_limit(self.location))?;
+        exec.config.record_usage(|usage| usage.record_value(&value));
+        Ok(value)
+    }
+
+    fn call(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         for parameter in &self.parameters {
             let parameter = parameter.evaluate(exec)?;
             exec.function_parameters.push(parameter);
         }
-        exec.config.functions.call(
-            &self.function,
-            exec.graph,
-            exec.source,
-            &mut exec
-                .function_parameters
-                .drain(exec.function_parameters.len() - self.parameters.len()..),
-        )
+        let start = exec.function_parameters.len() - self.parameters.len();
+        if let Some(value) = exec
+            .batched_calls
+            .get(&self.function, &exec.function_parameters[start..])
+        {
+            exec.function_parameters.truncate(start);
+            return Ok(value.clone());
+        }
+        if exec.config.functions.is_memoized(&self.function) {
+            let arguments = exec.function_parameters.split_off(start);
+            return exec
+                .memoized_calls
+                .call(
+                    0,
+                    exec.config.functions,
+                    &self.function,
+                    exec.graph,
+                    exec.source,
+                    arguments,
+                )
+                .map_err(|e| e.at_call(&self.function, self.location));
+        }
+        exec.config
+            .functions
+            .call(
+                &self.function,
+                exec.graph,
+                exec.source,
+                &mut exec
+                    .function_parameters
+                    .drain(exec.function_parameters.len() - self.parameters.len()..),
+            )
+            .map_err(|e| e.at_call(&self.function, self.location))
     }
 }
 
 impl RegexCapture {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         let capture = exec
-            .current_regex_captures
+            .scan_match
+            .captures
             .get(self.match_index)
             .ok_or(ExecutionError::UndefinedRegexCapture(format!("{}", self)))?;
         Ok(Value::String(capture.clone()))
     }
 }
 
+impl MatchOffset {
+    fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
+        let offset = if self.end {
+            exec.scan_match.end
+        } else {
+            exec.scan_match.start
+        };
+        Ok(Value::Integer(offset as i64))
+    }
+}
+
 impl Variable {
     fn evaluate(&self, exec: &mut ExecutionContext) -> Result<Value, ExecutionError> {
         let value = self.get(exec)?;
@@ -750,6 +1966,9 @@ impl ScopedVariable {
             .try_get(scope.index)
             .and_then(|v| v.get(&self.name))
         {
+            if let Some(provenance) = exec.provenance.as_deref_mut() {
+                provenance.read_scoped(scope.index, &self.name);
+            }
             return Ok(value);
         }
 
@@ -766,6 +1985,9 @@ impl ScopedVariable {
                     .try_get(scope.id() as u32)
                     .and_then(|v| v.get(&self.name))
                 {
+                    if let Some(provenance) = exec.provenance.as_deref_mut() {
+                        provenance.read_scoped(scope.id() as u32, &self.name);
+                    }
                     return Ok(value);
                 }
                 parent = scope.parent();
@@ -797,7 +2019,11 @@ impl ScopedVariable {
         let variables = exec.scoped.get_mut(scope);
         variables
             .add(self.name.clone(), value, mutable)
-            .map_err(|_| ExecutionError::DuplicateVariable(format!("{}", self)))
+            .map_err(|_| ExecutionError::DuplicateVariable(format!("{}", self)))?;
+        if let Some(provenance) = exec.provenance.as_deref_mut() {
+            provenance.set_scoped(scope.index, &self.name);
+        }
+        Ok(())
     }
 
     fn set(&self, exec: &mut ExecutionContext, value: Value) -> Result<(), ExecutionError> {
@@ -814,7 +2040,16 @@ impl ScopedVariable {
         let variables = exec.scoped.get_mut(scope);
         variables
             .set(self.name.clone(), value)
-            .map_err(|_| ExecutionError::DuplicateVariable(format!("{}", self)))
+            .map_err(|e| match e {
+                VariableError::CannotAssignImmutableVariable(_) => {
+                    ExecutionError::CannotAssignImmutableVariable(format!("{}", self))
+                }
+                _ => ExecutionError::UndefinedVariable(format!("{} on node {}", self, scope)),
+            })?;
+        if let Some(provenance) = exec.provenance.as_deref_mut() {
+            provenance.set_scoped(scope.index, &self.name);
+        }
+        Ok(())
     }
 }
 
@@ -823,6 +2058,9 @@ impl UnscopedVariable {
         if let Some(value) = exec.config.globals.get(&self.name) {
             Some(value)
         } else {
+            if let Some(provenance) = exec.provenance.as_deref_mut() {
+                provenance.read_local(&self.name);
+            }
             exec.locals.get(&self.name)
         }
         .ok_or_else(|| ExecutionError::UndefinedVariable(format!("{}", self)))
@@ -842,7 +2080,11 @@ impl UnscopedVariable {
         }
         exec.locals
             .add(self.name.clone(), value, mutable)
-            .map_err(|_| ExecutionError::DuplicateVariable(format!(" local {}", self)))
+            .map_err(|_| ExecutionError::DuplicateVariable(format!(" local {}", self)))?;
+        if let Some(provenance) = exec.provenance.as_deref_mut() {
+            provenance.add_local(&self.name);
+        }
+        Ok(())
     }
 
     fn set(&self, exec: &mut ExecutionContext, value: Value) -> Result<(), ExecutionError> {
@@ -858,25 +2100,40 @@ impl UnscopedVariable {
             } else {
                 ExecutionError::UndefinedVariable(format!("{}", self))
             }
-        })
+        })?;
+        if let Some(provenance) = exec.provenance.as_deref_mut() {
+            provenance.set_local(&self.name);
+        }
+        Ok(())
     }
 }
 
 impl Attribute {
+    /// Executes the attribute.  If `operation` is not [`Set`][AttributeOperation::Set], because
+    /// this attribute is part of a shorthand that was appended to or overridden, the value is
+    /// appended or overridden whatever the operation of this attribute.
     fn execute<F>(
         &self,
         exec: &mut ExecutionContext,
         add_attribute: &F,
+        operation: AttributeOperation,
     ) -> Result<(), ExecutionError>
     where
-        F: Fn(&mut ExecutionContext, Identifier, Value) -> Result<(), ExecutionError>,
+        F: Fn(
+            &mut ExecutionContext,
+            Identifier,
+            Value,
+            AttributeOperation,
+        ) -> Result<(), ExecutionError>,
     {
         exec.cancellation_flag.check("executing attribute")?;
         let value = self.value.evaluate(exec)?;
+        let operation = operation.combine(self.operation);
         if let Some(shorthand) = exec.shorthands.get(&self.name) {
-            shorthand.execute(exec, add_attribute, value)
+            shorthand.execute(exec, add_attribute, value, operation)
         } else {
-            add_attribute(exec, self.name.clone(), value)
+            exec.prepare_attribute(&self.name);
+            add_attribute(exec, self.name.clone(), value, operation)
         }
     }
 }
@@ -887,10 +2144,23 @@ impl AttributeShorthand {
         exec: &mut ExecutionContext,
         add_attribute: &F,
         value: Value,
+        operation: AttributeOperation,
     ) -> Result<(), ExecutionError>
     where
-        F: Fn(&mut ExecutionContext, Identifier, Value) -> Result<(), ExecutionError>,
+        F: Fn(
+            &mut ExecutionContext,
+            Identifier,
+            Value,
+            AttributeOperation,
+        ) -> Result<(), ExecutionError>,
     {
+        match &self.variable {
+            Some(variable) => {
+                exec.prepare_assignment(variable);
+            }
+            None => exec.discard_value(),
+        }
+        exec.enter_scope();
         let mut shorthand_locals = VariableMap::new();
         let mut shorthand_exec = ExecutionContext {
             source: exec.source,
@@ -898,19 +2168,27 @@ impl AttributeShorthand {
             config: exec.config,
             locals: &mut shorthand_locals,
             scoped: exec.scoped,
-            current_regex_captures: exec.current_regex_captures,
+            scan_match: exec.scan_match,
             function_parameters: exec.function_parameters,
-            mat: exec.mat,
+            captures: exec.captures,
             full_match_stanza_capture_index: exec.full_match_stanza_capture_index,
             error_context: exec.error_context.clone(),
             inherited_variables: exec.inherited_variables,
             shorthands: exec.shorthands,
+            provenance: exec.provenance.as_deref_mut(),
+            attribute_statements: exec.attribute_statements,
+            batched_calls: exec.batched_calls,
+            memoized_calls: exec.memoized_calls,
+            depth: exec.depth,
             cancellation_flag: exec.cancellation_flag,
         };
-        self.variable.add(&mut shorthand_exec, value, false)?;
+        if let Some(variable) = &self.variable {
+            variable.add(&mut shorthand_exec, value, false)?;
+        }
         for attr in &self.attributes {
-            attr.execute(&mut shorthand_exec, add_attribute)?;
+            attr.execute(&mut shorthand_exec, add_attribute, operation)?;
         }
+        exec.exit_scope();
         Ok(())
     }
 }
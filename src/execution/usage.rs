@@ -0,0 +1,382 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Accounting of the resources used by an execution
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::ast::File;
+use crate::graph::Value;
+use crate::Identifier;
+use crate::Location;
+
+/// The resources used by one execution of a graph DSL file, as returned by
+/// [`File::execute_with_usage`][crate::ast::File::execute_with_usage].  The counters are
+/// maintained by the `resource-usage` feature, which is enabled by default.  Without it, they are
+/// compiled to no-ops and always zero, and only the wall time is measured.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResourceUsage {
+    /// The number of query matches that were executed, not counting the matches that were
+    /// rejected by the predicates of their stanza
+    pub matches: usize,
+    /// The number of statements that were executed, including the statements of `if`, `for`, and
+    /// `scan` blocks every time they are executed
+    pub statements: usize,
+    /// The number of graph nodes that were created
+    pub graph_nodes: usize,
+    /// The number of edges that were created, not counting edges that already existed
+    pub edges: usize,
+    /// The number of attributes that were set, appended to, or overridden
+    pub attributes: usize,
+    /// The number of iterations of `scan` statements, which is the number of times their arms
+    /// searched for the next match
+    pub scan_iterations: usize,
+    /// The total length, in bytes, of the text that the regular expressions of `scan` arms were
+    /// run against.  Text is counted once for every arm that searches it.
+    pub regex_bytes_scanned: usize,
+    /// The number of calls of every function, including calls whose result was reused
+    pub function_calls: BTreeMap<Identifier, usize>,
+    /// The length of the longest list that was created by a list expression or returned by a
+    /// function
+    pub peak_list_length: usize,
+    /// The time that the execution took
+    pub wall_time: Duration,
+    /// The resources used by every stanza
+    pub statistics: ExecutionStatistics,
+}
+
+impl ResourceUsage {
+    /// Returns the total number of function calls.
+    pub fn total_function_calls(&self) -> usize {
+        self.function_calls.values().sum()
+    }
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "matches:             {}", self.matches)?;
+        writeln!(f, "statements:          {}", self.statements)?;
+        writeln!(f, "graph nodes:         {}", self.graph_nodes)?;
+        writeln!(f, "edges:               {}", self.edges)?;
+        writeln!(f, "attributes:          {}", self.attributes)?;
+        writeln!(f, "scan iterations:     {}", self.scan_iterations)?;
+        writeln!(f, "regex bytes scanned: {}", self.regex_bytes_scanned)?;
+        writeln!(f, "function calls:      {}", self.total_function_calls())?;
+        for (name, calls) in &self.function_calls {
+            writeln!(f, "  {}: {}", name, calls)?;
+        }
+        writeln!(f, "peak list length:    {}", self.peak_list_length)?;
+        write!(f, "wall time:           {:?}", self.wall_time)
+    }
+}
+
+/// The resources used by every stanza of a graph DSL file in one execution, as part of a
+/// [`ResourceUsage`][].  Like the other counters, they are maintained by the `resource-usage`
+/// feature, and are always zero without it.
+///
+/// The matches of a stanza are timed separately from the execution of its block.  Lazy execution
+/// evaluates the values of all stanzas after their blocks have been executed, and the time spent
+/// doing so is not attributed to any stanza.  The edges of lazy execution are counted for the
+/// stanza that created them when they are evaluated.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExecutionStatistics {
+    /// The resources used by every stanza, in the order in which the stanzas appear in the file
+    pub stanzas: Vec<StanzaStatistics>,
+}
+
+/// The resources used by one stanza, in [`ExecutionStatistics`][]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StanzaStatistics {
+    /// The index of the stanza in the file's [`stanzas`][File::stanzas]
+    pub index: usize,
+    /// The location of the stanza in the graph DSL file
+    pub location: Location,
+    /// The name of the stanza, if it has one
+    pub name: Option<Identifier>,
+    /// The number of query matches that were executed
+    pub matches: usize,
+    /// The number of graph nodes that were created
+    pub graph_nodes: usize,
+    /// The number of edges that were created, not counting edges that already existed
+    pub edges: usize,
+    /// The number of iterations of `scan` statements
+    pub scan_iterations: usize,
+    /// The time spent finding the matches of the stanza's query, including the matches that were
+    /// rejected by its predicates
+    pub matching_time: Duration,
+    /// The time spent executing the stanza's block for its matches
+    pub execution_time: Duration,
+}
+
+impl StanzaStatistics {
+    /// Returns the total time spent on the stanza, matching and executing.
+    pub fn total_time(&self) -> Duration {
+        self.matching_time + self.execution_time
+    }
+}
+
+/// Displays a table of the stanzas, with the ones that took the most time first.
+impl fmt::Display for ExecutionStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut stanzas = self.stanzas.iter().collect::<Vec<_>>();
+        stanzas.sort_by_key(|stanza| std::cmp::Reverse(stanza.total_time()));
+        write!(
+            f,
+            "{:<24} {:>9} {:>9} {:>9} {:>9} {:>12} {:>12}",
+            "stanza", "matches", "nodes", "edges", "scans", "matching", "execution"
+        )?;
+        for stanza in stanzas {
+            let label = match &stanza.name {
+                Some(name) => format!("{} {}", stanza.location, name),
+                None => stanza.location.to_string(),
+            };
+            write!(
+                f,
+                "\n{:<24} {:>9} {:>9} {:>9} {:>9} {:>12} {:>12}",
+                label,
+                stanza.matches,
+                stanza.graph_nodes,
+                stanza.edges,
+                stanza.scan_iterations,
+                format!("{:.3}ms", stanza.matching_time.as_secs_f64() * 1000.0),
+                format!("{:.3}ms", stanza.execution_time.as_secs_f64() * 1000.0),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The counters of a [`ResourceUsage`][] while an execution is running.  They are only updated
+/// through shared references, so that they can be reached through the execution configuration.
+pub(crate) struct UsageCounters {
+    matches: Cell<usize>,
+    statements: Cell<usize>,
+    graph_nodes: Cell<usize>,
+    edges: Cell<usize>,
+    attributes: Cell<usize>,
+    scan_iterations: Cell<usize>,
+    regex_bytes_scanned: Cell<usize>,
+    function_calls: RefCell<HashMap<Identifier, usize>>,
+    peak_list_length: Cell<usize>,
+    stanzas: RefCell<Vec<StanzaStatistics>>,
+    stanza_indices: HashMap<Location, usize>,
+    /// The index of the stanza whose match is being executed
+    current_stanza: Cell<Option<usize>>,
+}
+
+#[cfg(feature = "resource-usage")]
+fn increment(counter: &Cell<usize>, amount: usize) {
+    counter.set(counter.get() + amount);
+}
+
+#[cfg(not(feature = "resource-usage"))]
+#[inline(always)]
+fn increment(_counter: &Cell<usize>, _amount: usize) {}
+
+impl UsageCounters {
+    pub(crate) fn new(file: &File) -> Self {
+        let stanzas = file
+            .stanzas
+            .iter()
+            .enumerate()
+            .map(|(index, stanza)| StanzaStatistics {
+                index,
+                location: stanza.range.start,
+                name: stanza.name.clone(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let stanza_indices = stanzas
+            .iter()
+            .map(|stanza| (stanza.location, stanza.index))
+            .collect();
+        Self {
+            matches: Cell::default(),
+            statements: Cell::default(),
+            graph_nodes: Cell::default(),
+            edges: Cell::default(),
+            attributes: Cell::default(),
+            scan_iterations: Cell::default(),
+            regex_bytes_scanned: Cell::default(),
+            function_calls: RefCell::default(),
+            peak_list_length: Cell::default(),
+            stanzas: RefCell::new(stanzas),
+            stanza_indices,
+            current_stanza: Cell::default(),
+        }
+    }
+
+    /// Attributes the counters that are recorded from now on to the stanza with the given index.
+    pub(crate) fn enter_stanza(&self, index: usize) {
+        self.current_stanza.set(Some(index));
+    }
+
+    /// Attributes the counters that are recorded from now on to the stanza at the given location.
+    pub(crate) fn enter_stanza_at(&self, location: Location) {
+        self.current_stanza
+            .set(self.stanza_indices.get(&location).copied());
+    }
+
+    #[cfg(feature = "resource-usage")]
+    fn record_in_stanza(&self, record: impl FnOnce(&mut StanzaStatistics)) {
+        if let Some(index) = self.current_stanza.get() {
+            record(&mut self.stanzas.borrow_mut()[index]);
+        }
+    }
+
+    #[cfg(not(feature = "resource-usage"))]
+    #[inline(always)]
+    fn record_in_stanza(&self, _record: impl FnOnce(&mut StanzaStatistics)) {}
+
+    /// Returns the current time, to time the matching and the execution of stanzas, or None if
+    /// they are not timed.
+    #[cfg(feature = "resource-usage")]
+    pub(crate) fn start_timer(&self) -> Option<Instant> {
+        Some(Instant::now())
+    }
+
+    #[cfg(not(feature = "resource-usage"))]
+    #[inline(always)]
+    pub(crate) fn start_timer(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Adds time spent matching and executing the stanza with the given index.
+    pub(crate) fn record_stanza_time(&self, index: usize, matching: Duration, execution: Duration) {
+        let stanza = &mut self.stanzas.borrow_mut()[index];
+        stanza.matching_time += matching;
+        stanza.execution_time += execution;
+    }
+
+    pub(crate) fn record_match(&self) {
+        increment(&self.matches, 1);
+        self.record_in_stanza(|stanza| stanza.matches += 1);
+    }
+
+    pub(crate) fn record_statement(&self) {
+        increment(&self.statements, 1);
+    }
+
+    pub(crate) fn record_graph_node(&self) {
+        increment(&self.graph_nodes, 1);
+        self.record_in_stanza(|stanza| stanza.graph_nodes += 1);
+    }
+
+    pub(crate) fn record_edge(&self) {
+        increment(&self.edges, 1);
+        self.record_in_stanza(|stanza| stanza.edges += 1);
+    }
+
+    pub(crate) fn record_attribute(&self) {
+        increment(&self.attributes, 1);
+    }
+
+    /// Records an iteration of a `scan` statement, whose arms search a text of the given length.
+    pub(crate) fn record_scan_iteration(&self, arms: usize, text_length: usize) {
+        increment(&self.scan_iterations, 1);
+        increment(&self.regex_bytes_scanned, arms * text_length);
+        self.record_in_stanza(|stanza| stanza.scan_iterations += 1);
+    }
+
+    #[cfg(feature = "resource-usage")]
+    pub(crate) fn record_call(&self, function: &Identifier) {
+        let mut function_calls = self.function_calls.borrow_mut();
+        match function_calls.get_mut(function) {
+            Some(calls) => *calls += 1,
+            None => {
+                function_calls.insert(function.clone(), 1);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "resource-usage"))]
+    #[inline(always)]
+    pub(crate) fn record_call(&self, _function: &Identifier) {}
+
+    /// Records a value that was created during execution, which counts towards the peak list
+    /// length if it is a list.
+    #[cfg(feature = "resource-usage")]
+    pub(crate) fn record_value(&self, value: &Value) {
+        if let Value::List(values) = value {
+            let peak = &self.peak_list_length;
+            peak.set(peak.get().max(values.len()));
+        }
+    }
+
+    #[cfg(not(feature = "resource-usage"))]
+    #[inline(always)]
+    pub(crate) fn record_value(&self, _value: &Value) {}
+
+    pub(crate) fn into_usage(self, wall_time: Duration) -> ResourceUsage {
+        ResourceUsage {
+            matches: self.matches.get(),
+            statements: self.statements.get(),
+            graph_nodes: self.graph_nodes.get(),
+            edges: self.edges.get(),
+            attributes: self.attributes.get(),
+            scan_iterations: self.scan_iterations.get(),
+            regex_bytes_scanned: self.regex_bytes_scanned.get(),
+            function_calls: self.function_calls.into_inner().into_iter().collect(),
+            peak_list_length: self.peak_list_length.get(),
+            wall_time,
+            statistics: ExecutionStatistics {
+                stanzas: self.stanzas.into_inner(),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use serde::ser::SerializeMap;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    use super::ResourceUsage;
+    use super::StanzaStatistics;
+
+    impl Serialize for ResourceUsage {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(11))?;
+            map.serialize_entry("matches", &self.matches)?;
+            map.serialize_entry("statements", &self.statements)?;
+            map.serialize_entry("graph_nodes", &self.graph_nodes)?;
+            map.serialize_entry("edges", &self.edges)?;
+            map.serialize_entry("attributes", &self.attributes)?;
+            map.serialize_entry("scan_iterations", &self.scan_iterations)?;
+            map.serialize_entry("regex_bytes_scanned", &self.regex_bytes_scanned)?;
+            map.serialize_entry("function_calls", &self.function_calls)?;
+            map.serialize_entry("peak_list_length", &self.peak_list_length)?;
+            map.serialize_entry("wall_time_seconds", &self.wall_time.as_secs_f64())?;
+            map.serialize_entry("stanzas", &self.statistics.stanzas)?;
+            map.end()
+        }
+    }
+
+    impl Serialize for StanzaStatistics {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(10))?;
+            map.serialize_entry("index", &self.index)?;
+            map.serialize_entry("row", &self.location.row)?;
+            map.serialize_entry("column", &self.location.column)?;
+            map.serialize_entry("name", &self.name)?;
+            map.serialize_entry("matches", &self.matches)?;
+            map.serialize_entry("graph_nodes", &self.graph_nodes)?;
+            map.serialize_entry("edges", &self.edges)?;
+            map.serialize_entry("scan_iterations", &self.scan_iterations)?;
+            map.serialize_entry("matching_seconds", &self.matching_time.as_secs_f64())?;
+            map.serialize_entry("execution_seconds", &self.execution_time.as_secs_f64())?;
+            map.end()
+        }
+    }
+}
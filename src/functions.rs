@@ -7,22 +7,41 @@
 
 //! Functions that can be called by graph DSL files
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use crate::execution::error::ExecutionError;
 use crate::graph::Graph;
+use crate::graph::GraphNodeRef;
+use crate::graph::SyntaxNodeRef;
 use crate::graph::Value;
 use crate::Identifier;
 
 /// The implementation of a function that can be called from the graph DSL.
 ///
 /// You have access to the graph, as it has been constructed up to the point of the function call,
-/// as well as the text content of the source file that's being processed.
+/// as well as the text content of the source file that's being processed.  The source text is the
+/// text that the syntax tree was parsed from, so the [byte ranges][crate::graph::SyntaxNodeRef::byte_range]
+/// of syntax nodes index into it.
 ///
-/// Any other data that you need must be passed in as a parameter to the function.  You can use the
-/// [`Parameters`][] trait to consume those parameters and verify that you received the correct
-/// number and type of them.
+/// Any other data that you need must be passed in as a parameter to the function, or be part of
+/// the function itself, like a project index that an import resolving function consults.  You can
+/// use the [`Parameters`][] trait to consume the parameters and verify that you received the
+/// correct number and type of them.  A function can also add graph nodes to the graph, and return
+/// [references][crate::graph::GraphNodeRef] to them.
+///
+/// Closures that take the same arguments as [`call`][Self::call] are functions as well.  Use
+/// [`ValueFunction`][] for closures that only need the values of their arguments.
+///
+/// Functions are added to a [`Functions`][] library, which must be `Send` and `Sync`, so that the
+/// same library can be shared by executions on several threads.  Functions with state must
+/// therefore protect it with atomics or locks, and must not assume that they are called from a
+/// single execution at a time.
 pub trait Function {
     fn call(
         &self,
@@ -32,8 +51,126 @@ pub trait Function {
     ) -> Result<Value, ExecutionError>;
 }
 
-/// A helper trait for consuming the parameters of a function.  You will typically use it as
-/// follows:
+impl<F> Function for F
+where
+    F: Fn(&mut Graph, &str, &mut dyn Parameters) -> Result<Value, ExecutionError>,
+{
+    fn call(
+        &self,
+        graph: &mut Graph,
+        source: &str,
+        parameters: &mut dyn Parameters,
+    ) -> Result<Value, ExecutionError> {
+        self(graph, source, parameters)
+    }
+}
+
+/// A function that only needs the values of its arguments, implemented by a closure that takes
+/// all arguments at once.
+///
+/// ```
+/// # use tree_sitter_graph::functions::Functions;
+/// # use tree_sitter_graph::functions::ValueFunction;
+/// # use tree_sitter_graph::graph::Value;
+/// # use tree_sitter_graph::Identifier;
+/// let mut functions = Functions::new();
+/// functions.add(
+///     Identifier::from("count"),
+///     ValueFunction(|arguments: Vec<Value>| Ok(Value::Integer(arguments.len() as i64))),
+/// );
+/// ```
+pub struct ValueFunction<F>(pub F);
+
+impl<F> Function for ValueFunction<F>
+where
+    F: Fn(Vec<Value>) -> Result<Value, ExecutionError>,
+{
+    fn call(
+        &self,
+        _graph: &mut Graph,
+        _source: &str,
+        parameters: &mut dyn Parameters,
+    ) -> Result<Value, ExecutionError> {
+        let mut arguments = Vec::new();
+        while let Ok(argument) = parameters.param() {
+            arguments.push(argument);
+        }
+        (self.0)(arguments)
+    }
+}
+
+/// A function that can be called with many sets of arguments at once, for instance because each
+/// call performs I/O whose cost is better shared.  Register a batchable function using
+/// [`Functions::add_batchable`][].
+///
+/// During execution, the arguments of all calls to a batchable function in a stanza are collected
+/// for all of the stanza's matches, and the function is called once with all of them, before the
+/// matches are executed.  In lazy execution, the calls of all stanzas are collected and batched
+/// together.  Only calls whose arguments can be computed ahead of time are batched: their
+/// arguments may consist of literals, captures, and calls to [pure][Signature::pure] functions.
+/// Other calls are made individually, as a batch with a single set of arguments, when they are
+/// executed.  If a batch fails, its calls are made one at a time instead, and each call that
+/// fails is an error of the match it was made for, as it would be without batching.
+///
+/// Because calls are made ahead of time, and some of them are not used when a stanza does not
+/// execute a statement, a batchable function should not have side effects.
+pub trait BatchFunction {
+    /// Calls the function with each set of arguments, in order, returning one result for each
+    /// set.  Each set of arguments occurs only once.
+    fn call_batch(
+        &self,
+        graph: &Graph,
+        source: &str,
+        arguments: Vec<Vec<Value>>,
+    ) -> Result<Vec<Value>, ExecutionError>;
+}
+
+/// Calls a batchable function with a single set of arguments.
+struct Unbatched<F> {
+    name: Identifier,
+    function: Arc<F>,
+}
+
+impl<F: BatchFunction> Function for Unbatched<F> {
+    fn call(
+        &self,
+        graph: &mut Graph,
+        source: &str,
+        parameters: &mut dyn Parameters,
+    ) -> Result<Value, ExecutionError> {
+        let mut arguments = Vec::new();
+        while let Ok(argument) = parameters.param() {
+            arguments.push(argument);
+        }
+        let results = self.function.call_batch(graph, source, vec![arguments])?;
+        Ok(single_result(&self.name, results, 1)?
+            .pop()
+            .expect("result count already checked"))
+    }
+}
+
+/// Checks that a batchable function returned one result for each set of arguments.
+fn single_result(
+    name: &Identifier,
+    results: Vec<Value>,
+    expected: usize,
+) -> Result<Vec<Value>, ExecutionError> {
+    if results.len() != expected {
+        return Err(ExecutionError::FunctionFailed(
+            name.to_string(),
+            format!(
+                "Expected {} results for batched call, got {}",
+                expected,
+                results.len()
+            ),
+        ));
+    }
+    Ok(results)
+}
+
+/// A helper trait for consuming the parameters of a function.  To convert the parameters to the
+/// types you expect, with errors that say which argument of which function was wrong, wrap them
+/// in [`Arguments`][].  Otherwise you will typically use it as follows:
 ///
 /// ```
 /// # use tree_sitter_graph::functions::Parameters;
@@ -83,10 +220,503 @@ where
     }
 }
 
+/// Consumes the arguments of a call to a named function, converting them to the types the
+/// function expects.  Errors name the function and the position of the argument, in the same way
+/// as the errors of [signature][Signature] checks.
+///
+/// ```
+/// # use tree_sitter_graph::functions::Arguments;
+/// # use tree_sitter_graph::graph::Value;
+/// # use tree_sitter_graph::ExecutionError;
+/// # fn main() -> Result<(), ExecutionError> {
+/// # let values = vec![Value::from("ab"), Value::Integer(3)];
+/// # let parameters = &mut values.into_iter();
+/// let mut arguments = Arguments::new("repeat", parameters);
+/// let text = arguments.param_str()?;
+/// let count = arguments.param_int()?;
+/// let separator = arguments.param_opt();
+/// arguments.finish()?;
+/// # assert_eq!((text.as_str(), count, separator), ("ab", 3, None));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Arguments are parameters themselves, so they can be passed on to helpers that consume the
+/// remaining parameters.
+pub struct Arguments<'a> {
+    function: &'a str,
+    parameters: &'a mut dyn Parameters,
+    position: usize,
+}
+
+impl<'a> Arguments<'a> {
+    /// Creates arguments that consume the parameters of a call to the named function.
+    pub fn new(function: &'a str, parameters: &'a mut dyn Parameters) -> Self {
+        Self {
+            function,
+            parameters,
+            position: 0,
+        }
+    }
+
+    /// Returns the number of arguments that have been consumed.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the next argument, or an error if all arguments have been consumed.
+    pub fn param(&mut self) -> Result<Value, ExecutionError> {
+        let value = self
+            .parameters
+            .param()
+            .map_err(|_| ExecutionError::WrongArity {
+                function: self.function.to_string(),
+                expected: format!("at least {}", self.position + 1),
+                got: self.position,
+                location: None,
+            })?;
+        self.position += 1;
+        Ok(value)
+    }
+
+    /// Returns the next argument, or `None` if all arguments have been consumed.
+    pub fn param_opt(&mut self) -> Option<Value> {
+        let value = self.parameters.param().ok()?;
+        self.position += 1;
+        Some(value)
+    }
+
+    /// Returns the next argument, or an error if it does not have the expected type.
+    pub fn param_of_type(&mut self, expected: ParameterType) -> Result<Value, ExecutionError> {
+        let value = self.param()?;
+        if !expected.accepts(&value) {
+            return Err(ExecutionError::WrongArgumentType {
+                function: self.function.to_string(),
+                index: self.position - 1,
+                expected,
+                got: ParameterType::of(&value),
+            });
+        }
+        Ok(value)
+    }
+
+    /// Returns the next argument, which must be a boolean.
+    pub fn param_bool(&mut self) -> Result<bool, ExecutionError> {
+        self.param_of_type(ParameterType::Boolean)?.into_boolean()
+    }
+
+    /// Returns the next argument, which must be an integer.
+    pub fn param_int(&mut self) -> Result<i64, ExecutionError> {
+        self.param_of_type(ParameterType::Integer)?.into_integer()
+    }
+
+    /// Returns the next argument, which must be a number.  Integers are promoted to floats.
+    pub fn param_float(&mut self) -> Result<f64, ExecutionError> {
+        self.param_of_type(ParameterType::Number)?.into_float()
+    }
+
+    /// Returns the next argument, which must be a string.
+    pub fn param_str(&mut self) -> Result<String, ExecutionError> {
+        self.param_of_type(ParameterType::String)?.into_string()
+    }
+
+    /// Returns the next argument, which must be a list.
+    pub fn param_list(&mut self) -> Result<Vec<Value>, ExecutionError> {
+        self.param_of_type(ParameterType::List)?.into_list()
+    }
+
+    /// Returns the next argument, which must be a set.
+    pub fn param_set(&mut self) -> Result<BTreeSet<Value>, ExecutionError> {
+        self.param_of_type(ParameterType::Set)?.into_set()
+    }
+
+    /// Returns the next argument, which must be a map.
+    pub fn param_map(&mut self) -> Result<BTreeMap<Identifier, Value>, ExecutionError> {
+        self.param_of_type(ParameterType::Map)?.into_map()
+    }
+
+    /// Returns the next argument, which must be a syntax node.
+    pub fn param_syntax_node(&mut self) -> Result<SyntaxNodeRef, ExecutionError> {
+        self.param_of_type(ParameterType::SyntaxNode)?
+            .into_syntax_node_ref()
+    }
+
+    /// Returns the next argument, which must be a graph node.
+    pub fn param_graph_node(&mut self) -> Result<GraphNodeRef, ExecutionError> {
+        self.param_of_type(ParameterType::GraphNode)?
+            .into_graph_node_ref()
+    }
+
+    /// Returns all remaining arguments.
+    pub fn rest(&mut self) -> Vec<Value> {
+        let mut rest = Vec::new();
+        while let Some(value) = self.param_opt() {
+            rest.push(value);
+        }
+        rest
+    }
+
+    /// Ensures that all arguments have been consumed.
+    pub fn finish(&mut self) -> Result<(), ExecutionError> {
+        let expected = self.position;
+        let extra = self.rest().len();
+        if extra > 0 {
+            return Err(ExecutionError::WrongArity {
+                function: self.function.to_string(),
+                expected: expected.to_string(),
+                got: expected + extra,
+                location: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Parameters for Arguments<'_> {
+    fn param(&mut self) -> Result<Value, ExecutionError> {
+        Arguments::param(self)
+    }
+
+    fn finish(&mut self) -> Result<(), ExecutionError> {
+        Arguments::finish(self)
+    }
+}
+
+/// The type of a function parameter or result, as declared in a [`Signature`][].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParameterType {
+    /// Any value
+    Any,
+    Null,
+    Boolean,
+    Integer,
+    Float,
+    /// An integer or a float
+    Number,
+    String,
+    List,
+    Set,
+    Map,
+    Location,
+    SyntaxNode,
+    GraphNode,
+}
+
+impl ParameterType {
+    /// Returns the type of a value.
+    pub fn of(value: &Value) -> ParameterType {
+        match value {
+            Value::Null => Self::Null,
+            Value::Boolean(_) => Self::Boolean,
+            Value::Integer(_) => Self::Integer,
+            Value::Float(_) => Self::Float,
+            Value::String(_) => Self::String,
+            Value::List(_) => Self::List,
+            Value::Set(_) => Self::Set,
+            Value::Map(_) => Self::Map,
+            Value::Location(_) => Self::Location,
+            Value::SyntaxNode(_) => Self::SyntaxNode,
+            Value::GraphNode(_) => Self::GraphNode,
+        }
+    }
+
+    /// Returns whether a value has this type.
+    pub fn accepts(self, value: &Value) -> bool {
+        match (self, Self::of(value)) {
+            (Self::Any, _) => true,
+            (Self::Number, Self::Integer | Self::Float) => true,
+            (expected, actual) => expected == actual,
+        }
+    }
+}
+
+impl fmt::Display for ParameterType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "any"),
+            Self::Null => write!(f, "null"),
+            Self::Boolean => write!(f, "bool"),
+            Self::Integer => write!(f, "int"),
+            Self::Float => write!(f, "float"),
+            Self::Number => write!(f, "number"),
+            Self::String => write!(f, "string"),
+            Self::List => write!(f, "list"),
+            Self::Set => write!(f, "set"),
+            Self::Map => write!(f, "map"),
+            Self::Location => write!(f, "location"),
+            Self::SyntaxNode => write!(f, "syntax_node"),
+            Self::GraphNode => write!(f, "graph_node"),
+        }
+    }
+}
+
+/// The declared signature of a function: the types of its required and optional parameters, an
+/// optional variadic tail, and the type of its result.  Functions registered with a signature
+/// have their arguments checked by the [`Functions`][] registry before they are called.  Use the
+/// [`sig!`][crate::sig] macro to write signatures concisely.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signature {
+    required: Vec<ParameterType>,
+    optional: Vec<ParameterType>,
+    variadic: Option<ParameterType>,
+    result: ParameterType,
+    pure: bool,
+}
+
+impl Signature {
+    /// Creates a signature with the given required parameters and result type.
+    pub fn new(required: Vec<ParameterType>, result: ParameterType) -> Signature {
+        Signature {
+            required,
+            optional: Vec::new(),
+            variadic: None,
+            result,
+            pure: false,
+        }
+    }
+
+    /// Adds an optional parameter, which follows the required parameters and any earlier
+    /// optional ones.
+    pub fn optional(mut self, parameter: ParameterType) -> Signature {
+        self.optional.push(parameter);
+        self
+    }
+
+    /// Allows any number of further arguments of the given type after the declared parameters.
+    pub fn variadic(self, parameter: ParameterType) -> Signature {
+        Signature {
+            variadic: Some(parameter),
+            ..self
+        }
+    }
+
+    /// Declares that the function has no side effects, and that its result only depends on its
+    /// arguments.  Calls to pure functions can be evaluated ahead of time, to compute the
+    /// arguments of [batched calls][Functions::add_batchable].
+    pub fn pure(self) -> Signature {
+        Signature { pure: true, ..self }
+    }
+
+    /// Returns whether the function is declared to have no side effects.
+    pub fn is_pure(&self) -> bool {
+        self.pure
+    }
+
+    /// Returns the types of the required parameters.
+    pub fn required_parameters(&self) -> &[ParameterType] {
+        &self.required
+    }
+
+    /// Returns the types of the optional parameters.
+    pub fn optional_parameters(&self) -> &[ParameterType] {
+        &self.optional
+    }
+
+    /// Returns the type of the variadic tail, if there is one.
+    pub fn variadic_parameter(&self) -> Option<ParameterType> {
+        self.variadic
+    }
+
+    /// Returns the declared result type.
+    pub fn result(&self) -> ParameterType {
+        self.result
+    }
+
+    /// Returns whether a call with the given number of arguments matches this signature.
+    pub fn accepts_count(&self, count: usize) -> bool {
+        count >= self.required.len()
+            && (self.variadic.is_some() || count <= self.required.len() + self.optional.len())
+    }
+
+    /// Describes the number of arguments this signature accepts.
+    pub fn expected_count(&self) -> String {
+        let min = self.required.len();
+        let max = min + self.optional.len();
+        if self.variadic.is_some() {
+            format!("at least {}", min)
+        } else if min == max {
+            format!("{}", min)
+        } else {
+            format!("{} to {}", min, max)
+        }
+    }
+
+    /// Returns the declared type of the argument at the given index, if it is allowed at all.
+    pub fn parameter(&self, index: usize) -> Option<ParameterType> {
+        self.required
+            .iter()
+            .chain(self.optional.iter())
+            .nth(index)
+            .copied()
+            .or(self.variadic)
+    }
+
+    /// Checks the number and types of the arguments of a call to the named function.
+    pub fn check(&self, function: &Identifier, arguments: &[Value]) -> Result<(), ExecutionError> {
+        if !self.accepts_count(arguments.len()) {
+            return Err(ExecutionError::WrongArity {
+                function: function.to_string(),
+                expected: self.expected_count(),
+                got: arguments.len(),
+                location: None,
+            });
+        }
+        for (index, argument) in arguments.iter().enumerate() {
+            let expected = self
+                .parameter(index)
+                .expect("argument count already checked");
+            if !expected.accepts(argument) {
+                return Err(ExecutionError::WrongArgumentType {
+                    function: function.to_string(),
+                    index,
+                    expected,
+                    got: ParameterType::of(argument),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parameters = Vec::new();
+        parameters.extend(self.required.iter().map(|p| p.to_string()));
+        parameters.extend(self.optional.iter().map(|p| format!("{}?", p)));
+        parameters.extend(self.variadic.iter().map(|p| format!("{}...", p)));
+        write!(f, "({}) -> {}", parameters.join(", "), self.result)
+    }
+}
+
+/// Creates a [`Signature`][crate::functions::Signature] from a list of parameter types and a
+/// result type.  Types are written as `any`, `null`, `bool`, `int`, `float`, `number` (an `int`
+/// or a `float`), `string`, `list`, `set`, `location`, `syntax_node`, or `graph_node`.  Optional
+/// parameters are followed by `?`, and the last parameter can be made variadic by following it
+/// with `...`:
+///
+/// ```
+/// # use tree_sitter_graph::sig;
+/// let replace = sig!(string, string, string -> string);
+/// let join = sig!(list, string? -> string);
+/// let format = sig!(string, any... -> string);
+/// let node = sig!(-> graph_node);
+/// ```
+#[macro_export]
+macro_rules! sig {
+    (@required [$($required:ident)*] -> $result:ident) => {
+        $crate::sig!(@finish [$($required)*] [] [] $result)
+    };
+    (@required [$($required:ident)*] $parameter:ident ... -> $result:ident) => {
+        $crate::sig!(@finish [$($required)*] [] [$parameter] $result)
+    };
+    (@required [$($required:ident)*] $parameter:ident ? , $($rest:tt)*) => {
+        $crate::sig!(@optional [$($required)*] [$parameter] $($rest)*)
+    };
+    (@required [$($required:ident)*] $parameter:ident ? -> $result:ident) => {
+        $crate::sig!(@finish [$($required)*] [$parameter] [] $result)
+    };
+    (@required [$($required:ident)*] $parameter:ident , $($rest:tt)*) => {
+        $crate::sig!(@required [$($required)* $parameter] $($rest)*)
+    };
+    (@required [$($required:ident)*] $parameter:ident -> $result:ident) => {
+        $crate::sig!(@finish [$($required)* $parameter] [] [] $result)
+    };
+    (@optional [$($required:ident)*] [$($optional:ident)*] $parameter:ident ... -> $result:ident) => {
+        $crate::sig!(@finish [$($required)*] [$($optional)*] [$parameter] $result)
+    };
+    (@optional [$($required:ident)*] [$($optional:ident)*] $parameter:ident ? , $($rest:tt)*) => {
+        $crate::sig!(@optional [$($required)*] [$($optional)* $parameter] $($rest)*)
+    };
+    (@optional [$($required:ident)*] [$($optional:ident)*] $parameter:ident ? -> $result:ident) => {
+        $crate::sig!(@finish [$($required)*] [$($optional)* $parameter] [] $result)
+    };
+    (@finish [$($required:ident)*] [$($optional:ident)*] [$($variadic:ident)?] $result:ident) => {
+        $crate::functions::Signature::new(
+            ::std::vec![$($crate::sig!(@type $required)),*],
+            $crate::sig!(@type $result),
+        )
+        $(.optional($crate::sig!(@type $optional)))*
+        $(.variadic($crate::sig!(@type $variadic)))?
+    };
+    (@type any) => { $crate::functions::ParameterType::Any };
+    (@type null) => { $crate::functions::ParameterType::Null };
+    (@type bool) => { $crate::functions::ParameterType::Boolean };
+    (@type int) => { $crate::functions::ParameterType::Integer };
+    (@type float) => { $crate::functions::ParameterType::Float };
+    (@type number) => { $crate::functions::ParameterType::Number };
+    (@type string) => { $crate::functions::ParameterType::String };
+    (@type list) => { $crate::functions::ParameterType::List };
+    (@type set) => { $crate::functions::ParameterType::Set };
+    (@type map) => { $crate::functions::ParameterType::Map };
+    (@type location) => { $crate::functions::ParameterType::Location };
+    (@type syntax_node) => { $crate::functions::ParameterType::SyntaxNode };
+    (@type graph_node) => { $crate::functions::ParameterType::GraphNode };
+    ($($tokens:tt)*) => {
+        $crate::sig!(@required [] $($tokens)*)
+    };
+}
+
+struct RegisteredFunction {
+    function: Arc<dyn Function + Send + Sync>,
+    signature: Option<Signature>,
+    batch: Option<Arc<dyn BatchFunction + Send + Sync>>,
+    memoized: bool,
+}
+
+/// The capabilities that standard library functions with access to the environment of the
+/// process need, which are only added to a library with
+/// [`add_standard_functions_with`][Functions::add_standard_functions_with] if the embedder opts
+/// in.  All capabilities are disabled by default.
+///
+/// ```
+/// # use tree_sitter_graph::functions::Capabilities;
+/// # use tree_sitter_graph::functions::Functions;
+/// let mut functions = Functions::new();
+/// functions.add_standard_functions_with(Capabilities {
+///     time: true,
+///     ..Default::default()
+/// });
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    /// Whether the [`now-iso8601`][`crate::reference::functions#now-iso8601`] function is
+    /// available
+    pub time: bool,
+    /// Whether the [`env`][`crate::reference::functions#env`] function is available
+    pub env: bool,
+    /// The value that `now-iso8601` returns instead of the current time, for reproducible builds
+    pub fixed_time: Option<String>,
+}
+
+impl Capabilities {
+    /// Returns the name of the capability that a standard library function needs, if it needs
+    /// one.
+    pub fn required_by(function: &str) -> Option<&'static str> {
+        match function {
+            "now-iso8601" => Some("time"),
+            "env" => Some("env"),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the error for a call to a function that is not in a library, which names the
+/// capability that the function needs, if it is a standard library function that needs one.
+fn undefined_function(name: &Identifier) -> ExecutionError {
+    match Capabilities::required_by(name.as_str()) {
+        Some(capability) => ExecutionError::UndefinedFunction(format!(
+            "{} (requires the {} capability)",
+            name, capability
+        )),
+        None => ExecutionError::UndefinedFunction(format!("{}", name)),
+    }
+}
+
 /// A library of named functions.
 #[derive(Default)]
 pub struct Functions {
-    functions: HashMap<Identifier, Arc<dyn Function + Send + Sync>>,
+    functions: HashMap<Identifier, RegisteredFunction>,
+    memoized_hits: AtomicUsize,
 }
 
 impl Functions {
@@ -96,81 +726,611 @@ impl Functions {
     }
 
     /// Returns the standard library of functions, as defined in the [language
-    /// reference][`crate::reference::functions`].
+    /// reference][`crate::reference::functions`].  Functions that depend on a Cargo feature are
+    /// only included if that feature is enabled.  Functions that need a
+    /// [capability][Capabilities] are not included.
     pub fn stdlib() -> Functions {
         let mut functions = Functions::new();
+        functions.add_standard_functions_with(Capabilities::default());
+        functions
+    }
+
+    /// Adds the standard library of functions to this library, including the functions that need
+    /// one of the given [capabilities][Capabilities].
+    pub fn add_standard_functions_with(&mut self, capabilities: Capabilities) {
+        let functions = self;
         // general functions
-        functions.add(Identifier::from("eq"), stdlib::Eq);
-        functions.add(Identifier::from("is-null"), stdlib::IsNull);
+        functions.add_with_signature(
+            Identifier::from("eq"),
+            sig!(any, any -> bool).pure(),
+            stdlib::Eq,
+        );
+        functions.add_with_signature(
+            Identifier::from("is-null"),
+            sig!(any -> bool).pure(),
+            stdlib::IsNull,
+        );
         // tree functions
-        functions.add(
+        functions.add_with_signature(
             Identifier::from("named-child-index"),
+            sig!(syntax_node -> int).pure(),
             stdlib::syntax::NamedChildIndex,
         );
-        functions.add(Identifier::from("source-text"), stdlib::syntax::SourceText);
-        functions.add(Identifier::from("start-row"), stdlib::syntax::StartRow);
-        functions.add(
+        functions.add_with_signature(
+            Identifier::from("source-text"),
+            sig!(syntax_node -> string).pure(),
+            stdlib::syntax::SourceText,
+        );
+        functions.add_with_signature(
+            Identifier::from("start-row"),
+            sig!(syntax_node -> int).pure(),
+            stdlib::syntax::StartRow,
+        );
+        functions.add_with_signature(
             Identifier::from("start-column"),
+            sig!(syntax_node -> int).pure(),
             stdlib::syntax::StartColumn,
         );
-        functions.add(Identifier::from("end-row"), stdlib::syntax::EndRow);
-        functions.add(Identifier::from("end-column"), stdlib::syntax::EndColumn);
-        functions.add(Identifier::from("node-type"), stdlib::syntax::NodeType);
-        functions.add(
+        functions.add_with_signature(
+            Identifier::from("end-row"),
+            sig!(syntax_node -> int).pure(),
+            stdlib::syntax::EndRow,
+        );
+        functions.add_with_signature(
+            Identifier::from("end-column"),
+            sig!(syntax_node -> int).pure(),
+            stdlib::syntax::EndColumn,
+        );
+        functions.add_with_signature(
+            Identifier::from("location-of"),
+            sig!(syntax_node, syntax_node? -> location).pure(),
+            stdlib::syntax::LocationOf,
+        );
+        functions.add_with_signature(
+            Identifier::from("node-type"),
+            sig!(syntax_node -> string).pure(),
+            stdlib::syntax::NodeType,
+        );
+        functions.add_with_signature(
+            Identifier::from("node-kind"),
+            sig!(syntax_node -> string).pure(),
+            stdlib::syntax::NodeType,
+        );
+        functions.add_with_signature(
+            Identifier::from("is-named"),
+            sig!(syntax_node -> bool).pure(),
+            stdlib::syntax::IsNamed,
+        );
+        functions.add_with_signature(
+            Identifier::from("node-range"),
+            sig!(syntax_node -> list).pure(),
+            stdlib::syntax::NodeRange,
+        );
+        functions.add_with_signature(
             Identifier::from("named-child-count"),
+            sig!(syntax_node -> int).pure(),
             stdlib::syntax::NamedChildCount,
         );
+        functions.add_with_signature(
+            Identifier::from("child-count"),
+            sig!(syntax_node -> int).pure(),
+            stdlib::syntax::ChildCount,
+        );
+        functions.add_with_signature(
+            Identifier::from("parent"),
+            sig!(syntax_node -> any).pure(),
+            stdlib::syntax::Parent,
+        );
+        functions.add_with_signature(
+            Identifier::from("next-sibling"),
+            sig!(syntax_node -> any).pure(),
+            stdlib::syntax::NextSibling,
+        );
+        functions.add_with_signature(
+            Identifier::from("prev-sibling"),
+            sig!(syntax_node -> any).pure(),
+            stdlib::syntax::PrevSibling,
+        );
+        functions.add_with_signature(
+            Identifier::from("next-named-sibling"),
+            sig!(syntax_node -> any).pure(),
+            stdlib::syntax::NextNamedSibling,
+        );
+        functions.add_with_signature(
+            Identifier::from("prev-named-sibling"),
+            sig!(syntax_node -> any).pure(),
+            stdlib::syntax::PrevNamedSibling,
+        );
+        functions.add_with_signature(
+            Identifier::from("child"),
+            sig!(syntax_node, int -> any).pure(),
+            stdlib::syntax::Child,
+        );
+        functions.add_with_signature(
+            Identifier::from("named-child"),
+            sig!(syntax_node, int -> any).pure(),
+            stdlib::syntax::NamedChild,
+        );
+        functions.add_with_signature(
+            Identifier::from("child-by-field-name"),
+            sig!(syntax_node, string -> any).pure(),
+            stdlib::syntax::ChildByFieldName,
+        );
+        functions.add_with_signature(
+            Identifier::from("leading-comments"),
+            sig!(syntax_node, list? -> list).pure(),
+            stdlib::syntax::LeadingComments,
+        );
+        functions.add_with_signature(
+            Identifier::from("trailing-comment"),
+            sig!(syntax_node, list? -> any).pure(),
+            stdlib::syntax::TrailingComment,
+        );
+        // location functions
+        functions.add_with_signature(
+            Identifier::from("range-contains"),
+            sig!(any, any -> bool).pure(),
+            stdlib::location::RangeContains,
+        );
+        functions.add_with_signature(
+            Identifier::from("ranges-overlap"),
+            sig!(any, any -> bool).pure(),
+            stdlib::location::RangesOverlap,
+        );
         // graph functions
-        functions.add(Identifier::from("node"), stdlib::graph::Node);
+        functions.add_with_signature(
+            Identifier::from("node"),
+            sig!(-> graph_node),
+            stdlib::graph::Node,
+        );
+        functions.add_with_signature(
+            Identifier::from("node-for"),
+            sig!(string -> graph_node),
+            stdlib::graph::NodeFor,
+        );
+        functions.add_with_signature(
+            Identifier::from("find-node"),
+            sig!(string -> any),
+            stdlib::graph::FindNode,
+        );
+        functions.add_with_signature(
+            Identifier::from("find-nodes-by-attr"),
+            sig!(string, any -> list),
+            stdlib::graph::FindNodesByAttr,
+        );
         // boolean functions
-        functions.add(Identifier::from("not"), stdlib::bool::Not);
-        functions.add(Identifier::from("and"), stdlib::bool::And);
-        functions.add(Identifier::from("or"), stdlib::bool::Or);
+        functions.add_with_signature(
+            Identifier::from("not"),
+            sig!(bool -> bool).pure(),
+            stdlib::bool::Not,
+        );
+        functions.add_with_signature(
+            Identifier::from("and"),
+            sig!(bool... -> bool).pure(),
+            stdlib::bool::And,
+        );
+        functions.add_with_signature(
+            Identifier::from("or"),
+            sig!(bool... -> bool).pure(),
+            stdlib::bool::Or,
+        );
         // math functions
-        functions.add(Identifier::from("plus"), stdlib::math::Plus);
+        functions.add_with_signature(
+            Identifier::from("plus"),
+            sig!(number... -> number).pure(),
+            stdlib::math::Plus,
+        );
+        functions.add_with_signature(
+            Identifier::from("minus"),
+            sig!(number, number... -> number).pure(),
+            stdlib::math::Minus,
+        );
+        // conversion functions
+        functions.add_with_signature(
+            Identifier::from("parse-int"),
+            sig!(string, int? -> int).pure(),
+            stdlib::conversion::ParseInt,
+        );
+        functions.add_with_signature(
+            Identifier::from("int-to-string"),
+            sig!(int, int? -> string).pure(),
+            stdlib::conversion::IntToString,
+        );
+        functions.add_with_signature(
+            Identifier::from("char-to-int"),
+            sig!(string -> int).pure(),
+            stdlib::conversion::CharToInt,
+        );
+        functions.add_with_signature(
+            Identifier::from("int-to-char"),
+            sig!(int -> string).pure(),
+            stdlib::conversion::IntToChar,
+        );
         // string functions
-        functions.add(Identifier::from("format"), stdlib::string::Format);
-        functions.add(Identifier::from("replace"), stdlib::string::Replace);
+        functions.add_with_signature(
+            Identifier::from("format"),
+            sig!(string, any... -> string).pure(),
+            stdlib::string::Format,
+        );
+        functions.add_with_signature(
+            Identifier::from("replace"),
+            sig!(string, string, string -> string).pure(),
+            stdlib::string::Replace,
+        );
+        functions.add_with_signature(
+            Identifier::from("split"),
+            sig!(string, string -> list).pure(),
+            stdlib::string::Split,
+        );
+        functions.add_with_signature(
+            Identifier::from("trim"),
+            sig!(string -> string).pure(),
+            stdlib::string::Trim,
+        );
+        functions.add_with_signature(
+            Identifier::from("to-lower"),
+            sig!(string -> string).pure(),
+            stdlib::string::ToLower,
+        );
+        functions.add_with_signature(
+            Identifier::from("to-upper"),
+            sig!(string -> string).pure(),
+            stdlib::string::ToUpper,
+        );
+        functions.add_with_signature(
+            Identifier::from("starts-with"),
+            sig!(string, string -> bool).pure(),
+            stdlib::string::StartsWith,
+        );
+        functions.add_with_signature(
+            Identifier::from("ends-with"),
+            sig!(string, string -> bool).pure(),
+            stdlib::string::EndsWith,
+        );
+        functions.add_with_signature(
+            Identifier::from("contains"),
+            sig!(string, string -> bool).pure(),
+            stdlib::string::Contains,
+        );
+        functions.add_with_signature(
+            Identifier::from("index-of"),
+            sig!(string, string -> any).pure(),
+            stdlib::string::IndexOf,
+        );
+        functions.add_with_signature(
+            Identifier::from("substring"),
+            sig!(string, int, int? -> string).pure(),
+            stdlib::string::Substring,
+        );
+        #[cfg(feature = "unicode-normalization")]
+        {
+            functions.add_with_signature(
+                Identifier::from("nfc"),
+                sig!(string -> string).pure(),
+                stdlib::string::Nfc,
+            );
+            functions.add_with_signature(
+                Identifier::from("nfkc"),
+                sig!(string -> string).pure(),
+                stdlib::string::Nfkc,
+            );
+            functions.add_with_signature(
+                Identifier::from("fold-identifier"),
+                sig!(string -> string).pure(),
+                stdlib::string::FoldIdentifier,
+            );
+        }
         // list functions
-        functions.add(Identifier::from("concat"), stdlib::list::Concat);
-        functions.add(Identifier::from("is-empty"), stdlib::list::IsEmpty);
-        functions.add(Identifier::from("join"), stdlib::list::Join);
-        functions.add(Identifier::from("length"), stdlib::list::Length);
-        functions
-    }
-
-    /// Adds a new function to this library.
-    pub fn add<F>(&mut self, name: Identifier, function: F)
-    where
-        F: Function + Send + Sync + 'static,
-    {
-        self.functions.insert(name, Arc::new(function));
-    }
-
-    /// Calls a named function, returning an error if there is no function with that name.
-    pub fn call(
-        &self,
-        name: &Identifier,
-        graph: &mut Graph,
-        source: &str,
-        parameters: &mut dyn Parameters,
-    ) -> Result<Value, ExecutionError> {
-        let function = self
-            .functions
-            .get(name)
-            .ok_or(ExecutionError::UndefinedFunction(format!("{}", name)))?;
-        function.call(graph, source, parameters)
-    }
-}
-
-/// Implementations of the [standard library functions][`crate::reference::functions`]
-pub mod stdlib {
-    use regex::Regex;
-
+        functions.add_with_signature(
+            Identifier::from("concat"),
+            sig!(list... -> list).pure(),
+            stdlib::list::Concat,
+        );
+        functions.add_with_signature(
+            Identifier::from("is-empty"),
+            sig!(list -> bool).pure(),
+            stdlib::list::IsEmpty,
+        );
+        functions.add_with_signature(
+            Identifier::from("join"),
+            sig!(list, string? -> string).pure(),
+            stdlib::list::Join,
+        );
+        functions.add_with_signature(
+            Identifier::from("length"),
+            sig!(list -> int).pure(),
+            stdlib::list::Length,
+        );
+        functions.add_with_signature(
+            Identifier::from("concat-lists"),
+            sig!(list... -> list).pure(),
+            stdlib::list::Concat,
+        );
+        functions.add_with_signature(
+            Identifier::from("reverse"),
+            sig!(list -> list).pure(),
+            stdlib::list::Reverse,
+        );
+        functions.add_with_signature(
+            Identifier::from("sort"),
+            sig!(list -> list).pure(),
+            stdlib::list::Sort,
+        );
+        functions.add_with_signature(
+            Identifier::from("nth"),
+            sig!(list, int -> any).pure(),
+            stdlib::list::Nth,
+        );
+        functions.add_with_signature(
+            Identifier::from("first"),
+            sig!(list -> any).pure(),
+            stdlib::list::First,
+        );
+        functions.add_with_signature(
+            Identifier::from("last"),
+            sig!(list -> any).pure(),
+            stdlib::list::Last,
+        );
+        functions.add_with_signature(
+            Identifier::from("last?"),
+            sig!(list, int -> bool).pure(),
+            stdlib::list::IsLast,
+        );
+        functions.add_with_signature(
+            Identifier::from("contains?"),
+            sig!(any, any -> bool).pure(),
+            stdlib::list::IsElement,
+        );
+        functions.add_with_signature(
+            Identifier::from("to-list"),
+            sig!(any -> list).pure(),
+            stdlib::list::ToList,
+        );
+        // set functions
+        functions.add_with_signature(
+            Identifier::from("set-add"),
+            sig!(set, any... -> set).pure(),
+            stdlib::set::Add,
+        );
+        functions.add_with_signature(
+            Identifier::from("set-union"),
+            sig!(set... -> set).pure(),
+            stdlib::set::Union,
+        );
+        functions.add_with_signature(
+            Identifier::from("set-intersect"),
+            sig!(set, set... -> set).pure(),
+            stdlib::set::Intersect,
+        );
+        functions.add_with_signature(
+            Identifier::from("set-difference"),
+            sig!(set, set... -> set).pure(),
+            stdlib::set::Difference,
+        );
+        functions.add_with_signature(
+            Identifier::from("to-set"),
+            sig!(any -> set).pure(),
+            stdlib::set::ToSet,
+        );
+        // map functions
+        functions.add_with_signature(
+            Identifier::from("map-get"),
+            sig!(map, string -> any).pure(),
+            stdlib::map::Get,
+        );
+        functions.add_with_signature(
+            Identifier::from("map-set"),
+            sig!(map, string, any -> map).pure(),
+            stdlib::map::Set,
+        );
+        functions.add_with_signature(
+            Identifier::from("map-keys"),
+            sig!(map -> list).pure(),
+            stdlib::map::Keys,
+        );
+        functions.add_with_signature(
+            Identifier::from("map-contains?"),
+            sig!(map, string -> bool).pure(),
+            stdlib::map::Contains,
+        );
+        // capability functions
+        if capabilities.time {
+            functions.add_with_signature(
+                Identifier::from("now-iso8601"),
+                sig!(-> string),
+                stdlib::capability::NowIso8601 {
+                    fixed: capabilities.fixed_time,
+                },
+            );
+        }
+        if capabilities.env {
+            functions.add_with_signature(
+                Identifier::from("env"),
+                sig!(string -> any),
+                stdlib::capability::Env,
+            );
+        }
+    }
+
+    /// Adds a new function to this library.  The function is responsible for checking its own
+    /// parameters.
+    pub fn add<F>(&mut self, name: Identifier, function: F)
+    where
+        F: Function + Send + Sync + 'static,
+    {
+        self.functions.insert(
+            name,
+            RegisteredFunction {
+                function: Arc::new(function),
+                signature: None,
+                batch: None,
+                memoized: false,
+            },
+        );
+    }
+
+    /// Adds a new function to this library, with a declared signature.  The number and types of
+    /// the arguments of every call are checked against the signature before the function is
+    /// called, and the checker uses the signature to verify the number of arguments of calls in
+    /// graph DSL files parsed with [`File::from_str_with_functions`][crate::ast::File::from_str_with_functions].
+    pub fn add_with_signature<F>(&mut self, name: Identifier, signature: Signature, function: F)
+    where
+        F: Function + Send + Sync + 'static,
+    {
+        self.functions.insert(
+            name,
+            RegisteredFunction {
+                function: Arc::new(function),
+                signature: Some(signature),
+                batch: None,
+                memoized: false,
+            },
+        );
+    }
+
+    /// Adds a new [batchable function][BatchFunction] to this library, with a declared signature.
+    /// Calls that are not batched call the function with a single set of arguments.
+    pub fn add_batchable<F>(&mut self, name: Identifier, signature: Signature, function: F)
+    where
+        F: BatchFunction + Send + Sync + 'static,
+    {
+        let function = Arc::new(function);
+        self.functions.insert(
+            name.clone(),
+            RegisteredFunction {
+                function: Arc::new(Unbatched {
+                    name,
+                    function: function.clone(),
+                }),
+                signature: Some(signature),
+                batch: Some(function),
+                memoized: false,
+            },
+        );
+    }
+
+    /// Adds a new memoized function to this library.  The function is responsible for checking
+    /// its own parameters.
+    ///
+    /// Within a single stanza match, the function is called once for each distinct set of
+    /// arguments, and later calls with the same arguments reuse its result, so it must not have
+    /// side effects.  Results are not shared between matches.  Arguments are compared by value:
+    /// lists, sets, and strings are equal if their contents are, while graph nodes and syntax
+    /// nodes are equal if they are the same node.  The number of calls that reused a result is
+    /// returned by [`memoized_hits`][Self::memoized_hits].
+    pub fn add_memoized<F>(&mut self, name: Identifier, function: F)
+    where
+        F: Function + Send + Sync + 'static,
+    {
+        self.functions.insert(
+            name,
+            RegisteredFunction {
+                function: Arc::new(function),
+                signature: None,
+                batch: None,
+                memoized: true,
+            },
+        );
+    }
+
+    /// Returns whether a named function was registered as a memoized function.
+    pub fn is_memoized(&self, name: &Identifier) -> bool {
+        self.functions
+            .get(name)
+            .is_some_and(|registered| registered.memoized)
+    }
+
+    /// Returns the number of calls to memoized functions that reused the result of an earlier
+    /// call, in all executions that used this library.
+    pub fn memoized_hits(&self) -> usize {
+        self.memoized_hits.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_memoized_hit(&self) {
+        self.memoized_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns whether a named function was registered as a batchable function.
+    pub fn is_batchable(&self, name: &Identifier) -> bool {
+        self.functions
+            .get(name)
+            .is_some_and(|registered| registered.batch.is_some())
+    }
+
+    /// Returns whether a named function was registered with a signature that declares it to be
+    /// pure.
+    pub fn is_pure(&self, name: &Identifier) -> bool {
+        self.signature(name).is_some_and(Signature::is_pure)
+    }
+
+    /// Calls a named batchable function with several sets of arguments, returning one result for
+    /// each set.  Every set of arguments must match the function's signature.
+    pub(crate) fn call_batch(
+        &self,
+        name: &Identifier,
+        graph: &Graph,
+        source: &str,
+        arguments: Vec<Vec<Value>>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        let registered = self
+            .functions
+            .get(name)
+            .ok_or_else(|| undefined_function(name))?;
+        let batch = registered.batch.as_ref().ok_or_else(|| {
+            ExecutionError::FunctionFailed(name.to_string(), "Not batchable".to_string())
+        })?;
+        if let Some(signature) = &registered.signature {
+            for arguments in &arguments {
+                signature.check(name, arguments)?;
+            }
+        }
+        let expected = arguments.len();
+        single_result(name, batch.call_batch(graph, source, arguments)?, expected)
+    }
+
+    /// Returns the declared signature of a named function, if it was registered with one.
+    pub fn signature(&self, name: &Identifier) -> Option<&Signature> {
+        self.functions.get(name)?.signature.as_ref()
+    }
+
+    /// Calls a named function, returning an error if there is no function with that name, or if
+    /// the arguments do not match its declared signature.
+    pub fn call(
+        &self,
+        name: &Identifier,
+        graph: &mut Graph,
+        source: &str,
+        parameters: &mut dyn Parameters,
+    ) -> Result<Value, ExecutionError> {
+        let registered = self
+            .functions
+            .get(name)
+            .ok_or_else(|| undefined_function(name))?;
+        match &registered.signature {
+            Some(signature) => {
+                let mut arguments = Vec::new();
+                while let Ok(argument) = parameters.param() {
+                    arguments.push(argument);
+                }
+                signature.check(name, &arguments)?;
+                registered
+                    .function
+                    .call(graph, source, &mut arguments.into_iter())
+            }
+            None => registered.function.call(graph, source, parameters),
+        }
+    }
+}
+
+/// Implementations of the [standard library functions][`crate::reference::functions`]
+pub mod stdlib {
+    use regex::Regex;
+
     use crate::execution::error::ExecutionError;
     use crate::graph::Graph;
     use crate::graph::Value;
 
+    use super::Arguments;
     use super::Function;
     use super::Parameters;
 
@@ -184,9 +1344,10 @@ pub mod stdlib {
             _source: &str,
             parameters: &mut dyn Parameters,
         ) -> Result<Value, ExecutionError> {
-            let left = parameters.param()?;
-            let right = parameters.param()?;
-            parameters.finish()?;
+            let mut arguments = Arguments::new("eq", parameters);
+            let left = arguments.param()?;
+            let right = arguments.param()?;
+            arguments.finish()?;
 
             match &left {
                 Value::Null => match right {
@@ -203,6 +1364,11 @@ pub mod stdlib {
                     Value::Integer(right) => return Ok((left == right).into()),
                     _ => {}
                 },
+                Value::Float(left) => match &right {
+                    Value::Null => return Ok(false.into()),
+                    Value::Float(right) => return Ok((left == right).into()),
+                    _ => {}
+                },
                 Value::String(left) => match &right {
                     Value::Null => return Ok(false.into()),
                     Value::String(right) => return Ok((left == right).into()),
@@ -218,6 +1384,16 @@ pub mod stdlib {
                     Value::Set(right) => return Ok((left == right).into()),
                     _ => {}
                 },
+                Value::Map(left) => match &right {
+                    Value::Null => return Ok(false.into()),
+                    Value::Map(right) => return Ok((left == right).into()),
+                    _ => {}
+                },
+                Value::Location(left) => match &right {
+                    Value::Null => return Ok(false.into()),
+                    Value::Location(right) => return Ok((left == right).into()),
+                    _ => {}
+                },
                 Value::SyntaxNode(left) => match &right {
                     Value::Null => return Ok(false.into()),
                     Value::SyntaxNode(right) => return Ok((left == right).into()),
@@ -237,423 +1413,1810 @@ pub mod stdlib {
                 ),
             ))
         }
-    }
+    }
+
+    /// The implementation of the standard [`is-null`][`crate::reference::functions#is-null`] function.
+    pub struct IsNull;
+
+    impl Function for IsNull {
+        fn call(
+            &self,
+            _graph: &mut Graph,
+            _source: &str,
+            parameters: &mut dyn Parameters,
+        ) -> Result<Value, ExecutionError> {
+            let mut arguments = Arguments::new("is-null", parameters);
+            let parameter = arguments.param()?;
+            arguments.finish()?;
+            let result = if let Value::Null = parameter {
+                true
+            } else {
+                false
+            };
+            Ok(result.into())
+        }
+    }
+
+    pub mod syntax {
+        use std::convert::TryFrom;
+
+        use super::*;
+        use crate::graph::SourceLocation;
+
+        /// The implementation of the standard [`named-child-index`][`crate::reference::functions#named-child-index`]
+        /// function.
+        pub struct NamedChildIndex;
+
+        impl Function for NamedChildIndex {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("named-child-index", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                let parent = match node.parent() {
+                    Some(parent) => parent,
+                    None => {
+                        return Err(ExecutionError::FunctionFailed(
+                            "named-child-index".into(),
+                            format!("Cannot call named-child-index on the root node"),
+                        ))
+                    }
+                };
+                let mut tree_cursor = parent.walk();
+                let index = parent
+                    .named_children(&mut tree_cursor)
+                    .position(|child| child == node)
+                    .ok_or(ExecutionError::FunctionFailed(
+                        "named-child-index".into(),
+                        format!("Called named-child-index on a non-named child"),
+                    ))?;
+                Ok(Value::Integer(index as i64))
+            }
+        }
+
+        /// The implementation of the standard [`source-text`][`crate::reference::functions#source-text`]
+        /// function.
+        pub struct SourceText;
+
+        impl Function for SourceText {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("source-text", parameters);
+                let node = arguments.param_syntax_node()?;
+                arguments.finish()?;
+                let text = source.get(node.byte_range()).ok_or_else(|| {
+                    ExecutionError::TreeSourceMismatch(format!(
+                        "syntax node {} is outside the source text",
+                        node
+                    ))
+                })?;
+                Ok(Value::String(text.to_string()))
+            }
+        }
+
+        // The implementation of the standard [`start-row`][`crate::reference::functions#start-row`]
+        // function.
+        pub struct StartRow;
+
+        impl Function for StartRow {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("start-row", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::Integer(node.start_position().row as i64))
+            }
+        }
+
+        // The implementation of the standard
+        // [`start-column`][`crate::reference::functions#start-column`]
+        // function.
+        pub struct StartColumn;
+
+        impl Function for StartColumn {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("start-column", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::Integer(node.start_position().column as i64))
+            }
+        }
+
+        // The implementation of the standard [`end-row`][`crate::reference::functions#end-row`]
+        // function.
+        pub struct EndRow;
+
+        impl Function for EndRow {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("end-row", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::Integer(node.end_position().row as i64))
+            }
+        }
+
+        // The implementation of the standard [`end-column`][`crate::reference::functions#end-column`]
+        // function.
+        pub struct EndColumn;
+
+        impl Function for EndColumn {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("end-column", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::Integer(node.end_position().column as i64))
+            }
+        }
+
+        // The implementation of the standard
+        // [`location-of`][`crate::reference::functions#location-of`] function.
+        pub struct LocationOf;
+
+        impl Function for LocationOf {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("location-of", parameters);
+                let start = graph[arguments.param_syntax_node()?];
+                let mut location = SourceLocation::from(&start);
+                if let Some(end) = arguments.param_opt() {
+                    let end = graph[end.into_syntax_node_ref()?];
+                    location = location.span(&SourceLocation::from(&end));
+                }
+                arguments.finish()?;
+                Ok(location.into())
+            }
+        }
+
+        // The implementation of the standard [`node-type`][`crate::reference::functions#node-type`]
+        // function.
+        pub struct NodeType;
+
+        impl Function for NodeType {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("node-type", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::String(node.kind().to_string()))
+            }
+        }
+
+        /// The implementation of the standard [`is-named`][`crate::reference::functions#is-named`]
+        /// function.
+        pub struct IsNamed;
+
+        impl Function for IsNamed {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("is-named", parameters);
+                let node = arguments.param_syntax_node()?;
+                arguments.finish()?;
+                Ok(node.is_named().into())
+            }
+        }
+
+        /// The implementation of the standard [`node-range`][`crate::reference::functions#node-range`]
+        /// function.
+        pub struct NodeRange;
+
+        impl Function for NodeRange {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("node-range", parameters);
+                let node = arguments.param_syntax_node()?;
+                arguments.finish()?;
+                let (start, end) = (node.location(), node.end_location());
+                Ok(vec![
+                    Value::Integer(start.row as i64),
+                    Value::Integer(start.column as i64),
+                    Value::Integer(end.row as i64),
+                    Value::Integer(end.column as i64),
+                ]
+                .into())
+            }
+        }
+
+        // The implementation of the standard
+        // [`named-child-count`][`crate::reference::functions#named-child-count`] function.
+
+        pub struct NamedChildCount;
+
+        impl Function for NamedChildCount {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("named-child-count", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::Integer(node.named_child_count() as i64))
+            }
+        }
+
+        /// The implementation of the standard [`child-count`][`crate::reference::functions#child-count`]
+        /// function.
+        pub struct ChildCount;
+
+        impl Function for ChildCount {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("child-count", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                arguments.finish()?;
+                Ok(Value::Integer(node.child_count() as i64))
+            }
+        }
+
+        /// Calls a navigation function with a syntax node and its remaining parameters, and adds
+        /// the syntax node that it returns to the graph.  Returns null if there is no such node.
+        fn navigate<'tree>(
+            graph: &mut Graph<'tree>,
+            arguments: &mut Arguments,
+            navigation: impl FnOnce(
+                tree_sitter::Node<'tree>,
+                &mut Arguments,
+            )
+                -> Result<Option<tree_sitter::Node<'tree>>, ExecutionError>,
+        ) -> Result<Value, ExecutionError> {
+            let node = graph[arguments.param_syntax_node()?];
+            let related = navigation(node, arguments)?;
+            arguments.finish()?;
+            Ok(match related {
+                Some(related) => graph.add_syntax_node(related).into(),
+                None => Value::Null,
+            })
+        }
+
+        /// Returns a child index parameter, or `None` if it is negative.
+        fn child_index(arguments: &mut Arguments) -> Result<Option<usize>, ExecutionError> {
+            let index = arguments.param_int()?;
+            Ok(usize::try_from(index).ok())
+        }
+
+        /// The implementation of the standard [`parent`][`crate::reference::functions#parent`]
+        /// function.
+        pub struct Parent;
+
+        impl Function for Parent {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("parent", parameters);
+                navigate(graph, &mut arguments, |node, _| Ok(node.parent()))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`next-sibling`][`crate::reference::functions#next-sibling`] function.
+        pub struct NextSibling;
+
+        impl Function for NextSibling {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("next-sibling", parameters);
+                navigate(graph, &mut arguments, |node, _| Ok(node.next_sibling()))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`prev-sibling`][`crate::reference::functions#prev-sibling`] function.
+        pub struct PrevSibling;
+
+        impl Function for PrevSibling {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("prev-sibling", parameters);
+                navigate(graph, &mut arguments, |node, _| Ok(node.prev_sibling()))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`next-named-sibling`][`crate::reference::functions#next-named-sibling`] function.
+        pub struct NextNamedSibling;
+
+        impl Function for NextNamedSibling {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("next-named-sibling", parameters);
+                navigate(graph, &mut arguments, |node, _| {
+                    Ok(node.next_named_sibling())
+                })
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`prev-named-sibling`][`crate::reference::functions#prev-named-sibling`] function.
+        pub struct PrevNamedSibling;
+
+        impl Function for PrevNamedSibling {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("prev-named-sibling", parameters);
+                navigate(graph, &mut arguments, |node, _| {
+                    Ok(node.prev_named_sibling())
+                })
+            }
+        }
+
+        /// The implementation of the standard [`child`][`crate::reference::functions#child`]
+        /// function.
+        pub struct Child;
+
+        impl Function for Child {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("child", parameters);
+                navigate(graph, &mut arguments, |node, arguments| {
+                    Ok(child_index(arguments)?.and_then(|index| node.child(index)))
+                })
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`named-child`][`crate::reference::functions#named-child`] function.
+        pub struct NamedChild;
+
+        impl Function for NamedChild {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("named-child", parameters);
+                navigate(graph, &mut arguments, |node, arguments| {
+                    Ok(child_index(arguments)?.and_then(|index| node.named_child(index)))
+                })
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`child-by-field-name`][`crate::reference::functions#child-by-field-name`] function.
+        pub struct ChildByFieldName;
+
+        impl Function for ChildByFieldName {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("child-by-field-name", parameters);
+                navigate(graph, &mut arguments, |node, arguments| {
+                    let name = arguments.param_str()?;
+                    Ok(node.child_by_field_name(name))
+                })
+            }
+        }
+
+        /// The syntax node kinds that are considered comments, given as an optional list of kinds.
+        /// Without the list, every kind whose name contains `comment` is a comment.
+        struct CommentKinds(Option<Vec<String>>);
+
+        impl CommentKinds {
+            fn from_arguments(arguments: &mut Arguments) -> Result<Self, ExecutionError> {
+                let kinds = match arguments.param_opt() {
+                    Some(kinds) => Some(
+                        kinds
+                            .into_list()?
+                            .into_iter()
+                            .map(Value::into_string)
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    None => None,
+                };
+                Ok(Self(kinds))
+            }
+
+            fn contains(&self, node: &tree_sitter::Node) -> bool {
+                match &self.0 {
+                    Some(kinds) => kinds.iter().any(|kind| kind == node.kind()),
+                    None => node.kind().contains("comment"),
+                }
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`leading-comments`][`crate::reference::functions#leading-comments`] function.
+        pub struct LeadingComments;
+
+        impl Function for LeadingComments {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("leading-comments", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                let kinds = CommentKinds::from_arguments(&mut arguments)?;
+                arguments.finish()?;
+                let mut comments = Vec::new();
+                let mut sibling = node.prev_sibling();
+                while let Some(comment) = sibling.filter(|sibling| kinds.contains(sibling)) {
+                    sibling = comment.prev_sibling();
+                    // A comment on the same line as the end of the preceding code trails that code.
+                    if let Some(code) = sibling.filter(|sibling| !kinds.contains(sibling)) {
+                        if code.end_position().row == comment.start_position().row {
+                            break;
+                        }
+                    }
+                    comments.push(comment);
+                }
+                Ok(comments
+                    .into_iter()
+                    .rev()
+                    .map(|comment| graph.add_syntax_node(comment).into())
+                    .collect::<Vec<Value>>()
+                    .into())
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`trailing-comment`][`crate::reference::functions#trailing-comment`] function.
+        pub struct TrailingComment;
+
+        impl Function for TrailingComment {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("trailing-comment", parameters);
+                let node = graph[arguments.param_syntax_node()?];
+                let kinds = CommentKinds::from_arguments(&mut arguments)?;
+                arguments.finish()?;
+                let row = node.end_position().row;
+                // Comments are often attached to an ancestor, so climb to the nearest node that
+                // has a next sibling, and skip punctuation such as a statement terminator.
+                let mut current = node;
+                while current.next_sibling().is_none() {
+                    match current.parent() {
+                        Some(parent) => current = parent,
+                        None => return Ok(Value::Null),
+                    }
+                }
+                let mut sibling = current.next_sibling();
+                while let Some(next) = sibling {
+                    if next.start_position().row != row {
+                        break;
+                    }
+                    if kinds.contains(&next) {
+                        return Ok(graph.add_syntax_node(next).into());
+                    }
+                    if next.is_named() {
+                        break;
+                    }
+                    sibling = next.next_sibling();
+                }
+                Ok(Value::Null)
+            }
+        }
+    }
+
+    pub mod capability {
+        use std::time::SystemTime;
+        use std::time::UNIX_EPOCH;
+
+        use super::*;
+
+        /// The implementation of the standard [`now-iso8601`][`crate::reference::functions#now-iso8601`]
+        /// function, which needs the `time` [capability][crate::functions::Capabilities].
+        pub struct NowIso8601 {
+            /// The value to return instead of the current time
+            pub fixed: Option<String>,
+        }
+
+        impl Function for NowIso8601 {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("now-iso8601", parameters);
+                arguments.finish()?;
+                if let Some(fixed) = &self.fixed {
+                    return Ok(fixed.clone().into());
+                }
+                let seconds = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| {
+                        ExecutionError::FunctionFailed("now-iso8601".into(), e.to_string())
+                    })?
+                    .as_secs();
+                Ok(format_iso8601(seconds).into())
+            }
+        }
+
+        /// Formats a number of seconds since the Unix epoch as a UTC date and time.
+        fn format_iso8601(seconds: u64) -> String {
+            let (days, time) = (seconds / 86400, seconds % 86400);
+            // Converts days since the epoch to a proleptic Gregorian date, in eras of 400 years
+            // that start on March 1st, so that leap days are at the end of a year.
+            let days = days + 719468;
+            let era = days / 146097;
+            let day_of_era = days % 146097;
+            let year_of_era =
+                (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+            let day_of_year =
+                day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+            let month_index = (5 * day_of_year + 2) / 153;
+            let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+            let month = if month_index < 10 {
+                month_index + 3
+            } else {
+                month_index - 9
+            };
+            let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                year,
+                month,
+                day,
+                time / 3600,
+                time % 3600 / 60,
+                time % 60
+            )
+        }
+
+        /// The implementation of the standard [`env`][`crate::reference::functions#env`]
+        /// function, which needs the `env` [capability][crate::functions::Capabilities].
+        pub struct Env;
+
+        impl Function for Env {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("env", parameters);
+                let name = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(match std::env::var(name) {
+                    Ok(value) => value.into(),
+                    Err(_) => Value::Null,
+                })
+            }
+        }
+    }
+
+    pub mod location {
+        use super::*;
+        use crate::graph::SourceLocation;
+
+        /// Returns the source locations of a value, which is a source location, a syntax node, or
+        /// a list of them.
+        fn locations(graph: &Graph, value: Value) -> Result<Vec<SourceLocation>, ExecutionError> {
+            match value {
+                Value::List(values) => values
+                    .into_iter()
+                    .map(|value| location(graph, value))
+                    .collect(),
+                value => Ok(vec![location(graph, value)?]),
+            }
+        }
+
+        fn location(graph: &Graph, value: Value) -> Result<SourceLocation, ExecutionError> {
+            match value {
+                Value::SyntaxNode(node) => Ok(SourceLocation::from(&graph[node])),
+                value => value.into_location(),
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`range-contains`][`crate::reference::functions#range-contains`] function.
+        pub struct RangeContains;
+
+        impl Function for RangeContains {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("range-contains", parameters);
+                let ranges = locations(graph, arguments.param()?)?;
+                let location = location(graph, arguments.param()?)?;
+                arguments.finish()?;
+                Ok(ranges.iter().any(|range| range.contains(&location)).into())
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`ranges-overlap`][`crate::reference::functions#ranges-overlap`] function.
+        pub struct RangesOverlap;
+
+        impl Function for RangesOverlap {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("ranges-overlap", parameters);
+                let left = locations(graph, arguments.param()?)?;
+                let right = locations(graph, arguments.param()?)?;
+                arguments.finish()?;
+                Ok(left
+                    .iter()
+                    .any(|a| right.iter().any(|b| a.overlaps(b)))
+                    .into())
+            }
+        }
+    }
+
+    pub mod graph {
+        use super::*;
+        use crate::Identifier;
+
+        /// The implementation of the standard [`node`][`crate::reference::functions#node`] function.
+        pub struct Node;
+
+        impl Function for Node {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("node", parameters);
+                arguments.finish()?;
+                let node = graph.add_graph_node();
+                Ok(Value::GraphNode(node))
+            }
+        }
+
+        /// The implementation of the standard [`node-for`][`crate::reference::functions#node-for`]
+        /// function.
+        pub struct NodeFor;
+
+        impl Function for NodeFor {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("node-for", parameters);
+                let key = arguments.param_str()?;
+                arguments.finish()?;
+                let node = graph.add_keyed_graph_node(&key);
+                Ok(Value::GraphNode(node))
+            }
+        }
+
+        /// The implementation of the standard [`find-node`][`crate::reference::functions#find-node`]
+        /// function.
+        pub struct FindNode;
+
+        impl Function for FindNode {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("find-node", parameters);
+                let key = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(graph
+                    .graph_node_for_key(&key)
+                    .map_or(Value::Null, Value::GraphNode))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`find-nodes-by-attr`][`crate::reference::functions#find-nodes-by-attr`] function.
+        pub struct FindNodesByAttr;
+
+        impl Function for FindNodesByAttr {
+            fn call(
+                &self,
+                graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("find-nodes-by-attr", parameters);
+                let name = Identifier::from(arguments.param_str()?.as_str());
+                let value = arguments.param()?;
+                arguments.finish()?;
+                Ok(graph
+                    .find_graph_nodes_by_attribute(&name, &value)
+                    .into_iter()
+                    .map(Value::GraphNode)
+                    .collect::<Vec<_>>()
+                    .into())
+            }
+        }
+    }
+
+    pub mod bool {
+        use super::*;
+
+        /// The implementation of the standard [`not`][`crate::reference::functions#not`] function.
+        pub struct Not;
+
+        impl Function for Not {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("not", parameters);
+                let result = !arguments.param_bool()?;
+                arguments.finish()?;
+                Ok(result.into())
+            }
+        }
+
+        /// The implementation of the standard [`and`][`crate::reference::functions#and`] function.
+        pub struct And;
+
+        impl Function for And {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("and", parameters);
+                let mut result = true;
+                for parameter in arguments.rest() {
+                    result &= parameter.as_boolean()?;
+                }
+                Ok(result.into())
+            }
+        }
+
+        /// The implementation of the standard [`or`][`crate::reference::functions#or`] function.
+        pub struct Or;
+
+        impl Function for Or {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("or", parameters);
+                let mut result = false;
+                for parameter in arguments.rest() {
+                    result |= parameter.as_boolean()?;
+                }
+                Ok(result.into())
+            }
+        }
+    }
+
+    pub mod math {
+        use std::convert::TryFrom;
+
+        use super::*;
+
+        /// The implementation of the standard [`plus`][`crate::reference::functions#plus`] function.
+        pub struct Plus;
+
+        impl Function for Plus {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("plus", parameters);
+                let mut result = Value::Integer(0);
+                for parameter in arguments.rest() {
+                    result =
+                        arithmetic("plus", &result, &parameter, i64::checked_add, |l, r| l + r)?;
+                }
+                Ok(result)
+            }
+        }
+
+        /// The implementation of the standard [`minus`][`crate::reference::functions#minus`]
+        /// function.
+        pub struct Minus;
+
+        impl Function for Minus {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("minus", parameters);
+                let mut result = arguments.param()?;
+                let mut negate = true;
+                for parameter in arguments.rest() {
+                    result =
+                        arithmetic("minus", &result, &parameter, i64::checked_sub, |l, r| l - r)?;
+                    negate = false;
+                }
+                // with a single parameter, minus negates it
+                if negate {
+                    result = arithmetic(
+                        "minus",
+                        &Value::Integer(0),
+                        &result,
+                        i64::checked_sub,
+                        |l, r| l - r,
+                    )?;
+                }
+                Ok(result)
+            }
+        }
+
+        /// Applies an arithmetic operation to two numbers.  If both are integers, the result is an
+        /// integer, and overflow is an error.  Otherwise, both are promoted to floats.
+        fn arithmetic(
+            function: &str,
+            left: &Value,
+            right: &Value,
+            integer_op: fn(i64, i64) -> Option<i64>,
+            float_op: fn(f64, f64) -> f64,
+        ) -> Result<Value, ExecutionError> {
+            match (left, right) {
+                (Value::Integer(left), Value::Integer(right)) => integer_op(*left, *right)
+                    .map(Value::Integer)
+                    .ok_or_else(|| {
+                        ExecutionError::IntegerOverflow(format!(
+                            "in {} of {} and {}",
+                            function, left, right
+                        ))
+                    }),
+                _ => Ok(Value::try_from(float_op(
+                    left.as_float()?,
+                    right.as_float()?,
+                ))?),
+            }
+        }
+    }
+
+    pub mod conversion {
+        use std::convert::TryFrom;
+
+        use super::*;
+
+        /// The implementation of the standard [`parse-int`][`crate::reference::functions#parse-int`]
+        /// function.
+        pub struct ParseInt;
+
+        impl Function for ParseInt {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("parse-int", parameters);
+                let text = arguments.param_str()?;
+                let radix = match arguments.param_opt() {
+                    Some(radix) => radix.into_integer()?,
+                    None => 10,
+                };
+                arguments.finish()?;
+                if !(2..=36).contains(&radix) {
+                    return Err(ExecutionError::FunctionFailed(
+                        "parse-int".into(),
+                        format!("Radix {} is not between 2 and 36", radix),
+                    ));
+                }
+                let value = i64::from_str_radix(&text, radix as u32).map_err(|e| {
+                    ExecutionError::FunctionFailed(
+                        "parse-int".into(),
+                        format!(
+                            "Cannot parse {:?} as an integer in radix {}: {}",
+                            text, radix, e
+                        ),
+                    )
+                })?;
+                Ok(Value::Integer(value))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`int-to-string`][`crate::reference::functions#int-to-string`] function.
+        pub struct IntToString;
+
+        impl Function for IntToString {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("int-to-string", parameters);
+                let value = arguments.param_int()?;
+                let width = match arguments.param_opt() {
+                    Some(width) => width.into_integer()?,
+                    None => 0,
+                };
+                arguments.finish()?;
+                let width = usize::try_from(width).map_err(|_| {
+                    ExecutionError::FunctionFailed(
+                        "int-to-string".into(),
+                        format!("Width {} is negative", width),
+                    )
+                })?;
+                Ok(Value::String(format!("{:0width$}", value, width = width)))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`char-to-int`][`crate::reference::functions#char-to-int`] function.
+        pub struct CharToInt;
+
+        impl Function for CharToInt {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("char-to-int", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Value::Integer(c as i64)),
+                    _ => Err(ExecutionError::FunctionFailed(
+                        "char-to-int".into(),
+                        format!("Expected a single character, got {:?}", text),
+                    )),
+                }
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`int-to-char`][`crate::reference::functions#int-to-char`] function.
+        pub struct IntToChar;
+
+        impl Function for IntToChar {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("int-to-char", parameters);
+                let value = arguments.param_int()?;
+                arguments.finish()?;
+                let c = u32::try_from(value)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        ExecutionError::FunctionFailed(
+                            "int-to-char".into(),
+                            format!("{} is not a Unicode scalar value", value),
+                        )
+                    })?;
+                Ok(Value::String(c.to_string()))
+            }
+        }
+    }
+
+    pub mod string {
+        #[cfg(feature = "unicode-normalization")]
+        use unicode_normalization::UnicodeNormalization;
+
+        use super::*;
+
+        /// The implementation of the standard [`format`][`crate::reference::functions#format`] function.
+        pub struct Format;
+
+        impl Function for Format {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("format", parameters);
+                let format = arguments.param_str()?;
+                let mut result = String::new();
+                let mut it = format.chars().enumerate().into_iter();
+                while let Some((_, c)) = it.next() {
+                    match c {
+                        '{' => match it.next() {
+                            Some((_, '{')) => result.push('{'),
+                            Some((_, '}')) => {
+                                let value = arguments.param()?;
+                                result += &value.to_string();
+                            },
+                            Some((i, c)) => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected character `{}` after `{{` at position {} in format string `{}`. Expected `{{` or `}}`.", c, i + 1, format))),
+                            None => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected end of format string `{}` after `{{`. Expected `{{` or `}}`.", format))),
+                        },
+                        '}' => match it.next() {
+                            Some((_, '}')) => result.push('}'),
+                            Some((i, c)) => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected character `{}` after `}}` at position {} in format string `{}`. Expected `}}`.", c, i + 1, format))),
+                            None => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected end of format string `{}` after `{{. Expected `}}`.", format))),
+                        },
+                        c => result.push(c),
+                    }
+                }
+                arguments.finish()?;
+                Ok(result.into())
+            }
+        }
+
+        /// The implementation of the standard [`replace`][`crate::reference::functions#replace`] function.
+        pub struct Replace;
+
+        impl Function for Replace {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("replace", parameters);
+                let text = arguments.param_str()?;
+                let pattern = arguments.param_str()?;
+                let pattern = Regex::new(&pattern).map_err(|e| {
+                    ExecutionError::FunctionFailed("replace".into(), format!("{}", e))
+                })?;
+                let replacement = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(Value::String(
+                    pattern.replace_all(&text, replacement.as_str()).to_string(),
+                ))
+            }
+        }
+
+        /// The implementation of the standard [`split`][`crate::reference::functions#split`] function.
+        pub struct Split;
+
+        impl Function for Split {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("split", parameters);
+                let text = arguments.param_str()?;
+                let separator = arguments.param_str()?;
+                arguments.finish()?;
+                let parts = if separator.is_empty() {
+                    text.chars().map(|c| Value::from(c.to_string())).collect()
+                } else {
+                    text.split(separator.as_str()).map(Value::from).collect()
+                };
+                Ok(Value::List(parts))
+            }
+        }
+
+        /// The implementation of the standard [`trim`][`crate::reference::functions#trim`] function.
+        pub struct Trim;
+
+        impl Function for Trim {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("trim", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(text.trim().into())
+            }
+        }
+
+        /// The implementation of the standard [`to-lower`][`crate::reference::functions#to-lower`] function.
+        pub struct ToLower;
+
+        impl Function for ToLower {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("to-lower", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(text.to_lowercase().into())
+            }
+        }
+
+        /// The implementation of the standard [`to-upper`][`crate::reference::functions#to-upper`] function.
+        pub struct ToUpper;
+
+        impl Function for ToUpper {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("to-upper", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(text.to_uppercase().into())
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`starts-with`][`crate::reference::functions#starts-with`] function.
+        pub struct StartsWith;
+
+        impl Function for StartsWith {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("starts-with", parameters);
+                let text = arguments.param_str()?;
+                let prefix = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(text.starts_with(prefix.as_str()).into())
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`ends-with`][`crate::reference::functions#ends-with`] function.
+        pub struct EndsWith;
+
+        impl Function for EndsWith {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("ends-with", parameters);
+                let text = arguments.param_str()?;
+                let suffix = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(text.ends_with(suffix.as_str()).into())
+            }
+        }
+
+        /// The implementation of the standard [`contains`][`crate::reference::functions#contains`] function.
+        pub struct Contains;
+
+        impl Function for Contains {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("contains", parameters);
+                let text = arguments.param_str()?;
+                let needle = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(text.contains(needle.as_str()).into())
+            }
+        }
+
+        /// The implementation of the standard [`index-of`][`crate::reference::functions#index-of`] function.
+        pub struct IndexOf;
+
+        impl Function for IndexOf {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("index-of", parameters);
+                let text = arguments.param_str()?;
+                let needle = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(match text.find(needle.as_str()) {
+                    Some(byte_index) => Value::Integer(text[..byte_index].chars().count() as i64),
+                    None => Value::Null,
+                })
+            }
+        }
+
+        /// The implementation of the standard [`substring`][`crate::reference::functions#substring`] function.
+        pub struct Substring;
+
+        impl Function for Substring {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("substring", parameters);
+                let text = arguments.param_str()?;
+                let start = arguments.param_int()?;
+                let length = text.chars().count() as i64;
+                let end = match arguments.param_opt() {
+                    Some(end) => end.into_integer()?,
+                    None => length,
+                };
+                arguments.finish()?;
+                if start < 0 || start > end || end > length {
+                    return Err(ExecutionError::FunctionFailed(
+                        "substring".into(),
+                        format!(
+                            "Range {}..{} is not within the {} characters of {:?}",
+                            start, end, length, text
+                        ),
+                    ));
+                }
+                Ok(text
+                    .chars()
+                    .skip(start as usize)
+                    .take((end - start) as usize)
+                    .collect::<String>()
+                    .into())
+            }
+        }
+
+        /// The implementation of the standard [`nfc`][`crate::reference::functions#nfc`] function.
+        #[cfg(feature = "unicode-normalization")]
+        pub struct Nfc;
+
+        #[cfg(feature = "unicode-normalization")]
+        impl Function for Nfc {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("nfc", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(Value::String(text.nfc().collect()))
+            }
+        }
 
-    /// The implementation of the standard [`is-null`][`crate::reference::functions#is-null`] function.
-    pub struct IsNull;
+        /// The implementation of the standard [`nfkc`][`crate::reference::functions#nfkc`] function.
+        #[cfg(feature = "unicode-normalization")]
+        pub struct Nfkc;
 
-    impl Function for IsNull {
-        fn call(
-            &self,
-            _graph: &mut Graph,
-            _source: &str,
-            parameters: &mut dyn Parameters,
-        ) -> Result<Value, ExecutionError> {
-            let parameter = parameters.param()?;
-            parameters.finish()?;
-            let result = if let Value::Null = parameter {
-                true
-            } else {
-                false
-            };
-            Ok(result.into())
+        #[cfg(feature = "unicode-normalization")]
+        impl Function for Nfkc {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("nfkc", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(Value::String(text.nfkc().collect()))
+            }
+        }
+
+        /// The implementation of the standard
+        /// [`fold-identifier`][`crate::reference::functions#fold-identifier`] function.
+        #[cfg(feature = "unicode-normalization")]
+        pub struct FoldIdentifier;
+
+        #[cfg(feature = "unicode-normalization")]
+        impl Function for FoldIdentifier {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("fold-identifier", parameters);
+                let text = arguments.param_str()?;
+                arguments.finish()?;
+                // Lowercasing can produce strings that are not normalized, so normalize again.
+                let folded = text.nfkc().collect::<String>().to_lowercase();
+                Ok(Value::String(folded.nfkc().collect()))
+            }
         }
     }
 
-    pub mod syntax {
+    pub mod list {
+        use std::convert::TryFrom;
+
         use super::*;
 
-        /// The implementation of the standard [`named-child-index`][`crate::reference::functions#named-child-index`]
-        /// function.
-        pub struct NamedChildIndex;
+        /// The implementation of the standard [`concat`][`crate::reference::functions#concat`] function.
+        pub struct Concat;
 
-        impl Function for NamedChildIndex {
+        impl Function for Concat {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                let parent = match node.parent() {
-                    Some(parent) => parent,
-                    None => {
-                        return Err(ExecutionError::FunctionFailed(
-                            "named-child-index".into(),
-                            format!("Cannot call named-child-index on the root node"),
-                        ))
-                    }
-                };
-                let mut tree_cursor = parent.walk();
-                let index = parent
-                    .named_children(&mut tree_cursor)
-                    .position(|child| child == node)
-                    .ok_or(ExecutionError::FunctionFailed(
-                        "named-child-index".into(),
-                        format!("Called named-child-index on a non-named child"),
-                    ))?;
-                Ok(Value::Integer(index as u32))
+                let mut arguments = Arguments::new("concat", parameters);
+                let mut result = Vec::new();
+                for list in arguments.rest() {
+                    result.append(&mut list.into_list()?);
+                }
+                Ok(result.into())
             }
         }
 
-        /// The implementation of the standard [`source-text`][`crate::reference::functions#source-text`]
-        /// function.
-        pub struct SourceText;
+        /// The implementation of the standard [`is-empty`][`crate::reference::functions#is-empty`] function.
+        pub struct IsEmpty;
 
-        impl Function for SourceText {
+        impl Function for IsEmpty {
             fn call(
                 &self,
-                graph: &mut Graph,
-                source: &str,
+                _graph: &mut Graph,
+                _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::String(source[node.byte_range()].to_string()))
+                let mut arguments = Arguments::new("is-empty", parameters);
+                let list = arguments.param_list()?;
+                Ok(list.is_empty().into())
             }
         }
 
-        // The implementation of the standard [`start-row`][`crate::reference::functions#start-row`]
-        // function.
-        pub struct StartRow;
+        /// The implementation of the standard [`join`][`crate::reference::functions#join`] function.
+        pub struct Join;
 
-        impl Function for StartRow {
+        impl Function for Join {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::Integer(node.start_position().row as u32))
+                let mut arguments = Arguments::new("join", parameters);
+                let list = arguments.param_list()?;
+                let sep = match arguments.param_opt() {
+                    Some(sep) => sep.into_string()?,
+                    None => "".to_string(),
+                };
+                arguments.finish()?;
+                let result = list
+                    .into_iter()
+                    .map(|x| format!("{}", x))
+                    .collect::<Vec<_>>()
+                    .join(&sep);
+                Ok(result.into())
             }
         }
 
-        // The implementation of the standard
-        // [`start-column`][`crate::reference::functions#start-column`]
-        // function.
-        pub struct StartColumn;
+        /// The implementation of the standard [`length`][`crate::reference::functions#length`] function.
+        pub struct Length;
 
-        impl Function for StartColumn {
+        impl Function for Length {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::Integer(node.start_position().column as u32))
+                let mut arguments = Arguments::new("length", parameters);
+                let list = arguments.param_list()?;
+                Ok((list.len() as i64).into())
             }
         }
 
-        // The implementation of the standard [`end-row`][`crate::reference::functions#end-row`]
-        // function.
-        pub struct EndRow;
+        /// The implementation of the standard [`reverse`][`crate::reference::functions#reverse`] function.
+        pub struct Reverse;
 
-        impl Function for EndRow {
+        impl Function for Reverse {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::Integer(node.end_position().row as u32))
+                let mut arguments = Arguments::new("reverse", parameters);
+                let mut list = arguments.param_list()?;
+                arguments.finish()?;
+                list.reverse();
+                Ok(list.into())
             }
         }
 
-        // The implementation of the standard [`end-column`][`crate::reference::functions#end-column`]
-        // function.
-        pub struct EndColumn;
+        /// The implementation of the standard [`sort`][`crate::reference::functions#sort`] function.
+        pub struct Sort;
 
-        impl Function for EndColumn {
+        impl Function for Sort {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::Integer(node.end_position().column as u32))
+                let mut arguments = Arguments::new("sort", parameters);
+                let mut list = arguments.param_list()?;
+                arguments.finish()?;
+                list.sort();
+                Ok(list.into())
             }
         }
 
-        // The implementation of the standard [`node-type`][`crate::reference::functions#node-type`]
-        // function.
-        pub struct NodeType;
+        /// The implementation of the standard [`nth`][`crate::reference::functions#nth`] function.
+        pub struct Nth;
 
-        impl Function for NodeType {
+        impl Function for Nth {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::String(node.kind().to_string()))
+                let mut arguments = Arguments::new("nth", parameters);
+                let list = arguments.param_list()?;
+                let index = arguments.param_int()?;
+                arguments.finish()?;
+                let length = list.len();
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| list.into_iter().nth(index))
+                    .ok_or_else(|| {
+                        ExecutionError::FunctionFailed(
+                            "nth".into(),
+                            format!(
+                                "Index {} is not within the {} elements of the list",
+                                index, length
+                            ),
+                        )
+                    })
             }
         }
 
-        // The implementation of the standard
-        // [`named-child-count`][`crate::reference::functions#named-child-count`] function.
-
-        pub struct NamedChildCount;
+        /// The implementation of the standard [`first`][`crate::reference::functions#first`] function.
+        pub struct First;
 
-        impl Function for NamedChildCount {
+        impl Function for First {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let node = graph[parameters.param()?.into_syntax_node_ref()?];
-                parameters.finish()?;
-                Ok(Value::Integer(node.named_child_count() as u32))
+                let mut arguments = Arguments::new("first", parameters);
+                let list = arguments.param_list()?;
+                arguments.finish()?;
+                Ok(list.into_iter().next().unwrap_or(Value::Null))
             }
         }
-    }
-
-    pub mod graph {
-        use super::*;
 
-        /// The implementation of the standard [`node`][`crate::reference::functions#node`] function.
-        pub struct Node;
+        /// The implementation of the standard [`last`][`crate::reference::functions#last`] function.
+        pub struct Last;
 
-        impl Function for Node {
+        impl Function for Last {
             fn call(
                 &self,
-                graph: &mut Graph,
+                _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                parameters.finish()?;
-                let node = graph.add_graph_node();
-                Ok(Value::GraphNode(node))
+                let mut arguments = Arguments::new("last", parameters);
+                let list = arguments.param_list()?;
+                arguments.finish()?;
+                Ok(list.into_iter().last().unwrap_or(Value::Null))
             }
         }
-    }
-
-    pub mod bool {
-        use super::*;
 
-        /// The implementation of the standard [`not`][`crate::reference::functions#not`] function.
-        pub struct Not;
+        /// The implementation of the standard [`last?`][`crate::reference::functions#last-1`] function.
+        pub struct IsLast;
 
-        impl Function for Not {
+        impl Function for IsLast {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let result = !parameters.param()?.as_boolean()?;
-                parameters.finish()?;
-                Ok(result.into())
+                let mut arguments = Arguments::new("last?", parameters);
+                let list = arguments.param_list()?;
+                let index = arguments.param_int()?;
+                arguments.finish()?;
+                Ok((index >= 0 && index as usize + 1 == list.len()).into())
             }
         }
 
-        /// The implementation of the standard [`and`][`crate::reference::functions#and`] function.
-        pub struct And;
+        /// The implementation of the standard [`contains?`][`crate::reference::functions#contains-1`] function.
+        pub struct IsElement;
 
-        impl Function for And {
+        impl Function for IsElement {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let mut result = true;
-                while let Ok(parameter) = parameters.param() {
-                    result &= parameter.as_boolean()?;
+                let mut arguments = Arguments::new("contains?", parameters);
+                let collection = arguments.param()?;
+                let value = arguments.param()?;
+                arguments.finish()?;
+                match collection {
+                    Value::Set(set) => Ok(set.contains(&value).into()),
+                    collection => Ok(collection.into_list()?.contains(&value).into()),
                 }
-                Ok(result.into())
             }
         }
 
-        /// The implementation of the standard [`or`][`crate::reference::functions#or`] function.
-        pub struct Or;
+        /// The implementation of the standard [`to-list`][`crate::reference::functions#to-list`] function.
+        pub struct ToList;
 
-        impl Function for Or {
+        impl Function for ToList {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let mut result = false;
-                while let Ok(parameter) = parameters.param() {
-                    result |= parameter.as_boolean()?;
+                let mut arguments = Arguments::new("to-list", parameters);
+                let collection = arguments.param()?;
+                arguments.finish()?;
+                match collection {
+                    Value::Set(set) => Ok(set.into_iter().collect::<Vec<_>>().into()),
+                    collection => Ok(collection.into_list()?.into()),
                 }
-                Ok(result.into())
             }
         }
     }
 
-    pub mod math {
+    pub mod set {
+        use std::collections::BTreeSet;
+
         use super::*;
 
-        /// The implementation of the standard [`plus`][`crate::reference::functions#plus`] function.
-        pub struct Plus;
+        /// The implementation of the standard [`set-add`][`crate::reference::functions#set-add`] function.
+        pub struct Add;
 
-        impl Function for Plus {
+        impl Function for Add {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let mut result = 0;
-                while let Ok(parameter) = parameters.param() {
-                    result += parameter.as_integer()?;
+                let mut arguments = Arguments::new("set-add", parameters);
+                let mut set = arguments.param_set()?;
+                for value in arguments.rest() {
+                    set.insert(value);
                 }
-                Ok(Value::Integer(result))
+                Ok(set.into())
             }
         }
-    }
 
-    pub mod string {
-        use super::*;
+        /// The implementation of the standard [`set-union`][`crate::reference::functions#set-union`] function.
+        pub struct Union;
 
-        /// The implementation of the standard [`format`][`crate::reference::functions#format`] function.
-        pub struct Format;
+        impl Function for Union {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("set-union", parameters);
+                let mut result = BTreeSet::new();
+                for set in arguments.rest() {
+                    result.append(&mut set.into_set()?);
+                }
+                Ok(result.into())
+            }
+        }
 
-        impl Function for Format {
+        /// The implementation of the standard [`set-intersect`][`crate::reference::functions#set-intersect`] function.
+        pub struct Intersect;
+
+        impl Function for Intersect {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let format = parameters.param()?.into_string()?;
-                let mut result = String::new();
-                let mut it = format.chars().enumerate().into_iter();
-                while let Some((_, c)) = it.next() {
-                    match c {
-                        '{' => match it.next() {
-                            Some((_, '{')) => result.push('{'),
-                            Some((_, '}')) => {
-                                let value = parameters.param()?;
-                                result += &value.to_string();
-                            },
-                            Some((i, c)) => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected character `{}` after `{{` at position {} in format string `{}`. Expected `{{` or `}}`.", c, i + 1, format))),
-                            None => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected end of format string `{}` after `{{`. Expected `{{` or `}}`.", format))),
-                        },
-                        '}' => match it.next() {
-                            Some((_, '}')) => result.push('}'),
-                            Some((i, c)) => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected character `{}` after `}}` at position {} in format string `{}`. Expected `}}`.", c, i + 1, format))),
-                            None => return Err(ExecutionError::FunctionFailed("format".into(), format!("Unexpected end of format string `{}` after `{{. Expected `}}`.", format))),
-                        },
-                        c => result.push(c),
-                    }
+                let mut arguments = Arguments::new("set-intersect", parameters);
+                let mut result = arguments.param_set()?;
+                for set in arguments.rest() {
+                    let set = set.into_set()?;
+                    result.retain(|value| set.contains(value));
                 }
-                parameters.finish()?;
                 Ok(result.into())
             }
         }
 
-        /// The implementation of the standard [`replace`][`crate::reference::functions#replace`] function.
-        pub struct Replace;
+        /// The implementation of the standard [`set-difference`][`crate::reference::functions#set-difference`] function.
+        pub struct Difference;
 
-        impl Function for Replace {
+        impl Function for Difference {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let text = parameters.param()?.into_string()?;
-                let pattern = parameters.param()?.into_string()?;
-                let pattern = Regex::new(&pattern).map_err(|e| {
-                    ExecutionError::FunctionFailed("replace".into(), format!("{}", e))
-                })?;
-                let replacement = parameters.param()?.into_string()?;
-                parameters.finish()?;
-                Ok(Value::String(
-                    pattern.replace_all(&text, replacement.as_str()).to_string(),
-                ))
+                let mut arguments = Arguments::new("set-difference", parameters);
+                let mut result = arguments.param_set()?;
+                for set in arguments.rest() {
+                    let set = set.into_set()?;
+                    result.retain(|value| !set.contains(value));
+                }
+                Ok(result.into())
+            }
+        }
+
+        /// The implementation of the standard [`to-set`][`crate::reference::functions#to-set`] function.
+        pub struct ToSet;
+
+        impl Function for ToSet {
+            fn call(
+                &self,
+                _graph: &mut Graph,
+                _source: &str,
+                parameters: &mut dyn Parameters,
+            ) -> Result<Value, ExecutionError> {
+                let mut arguments = Arguments::new("to-set", parameters);
+                let collection = arguments.param()?;
+                arguments.finish()?;
+                match collection {
+                    Value::Set(set) => Ok(set.into()),
+                    collection => Ok(collection
+                        .into_list()?
+                        .into_iter()
+                        .collect::<BTreeSet<_>>()
+                        .into()),
+                }
             }
         }
     }
 
-    pub mod list {
+    pub mod map {
+        use crate::Identifier;
+
         use super::*;
 
-        /// The implementation of the standard [`concat`][`crate::reference::functions#concat`] function.
-        pub struct Concat;
+        /// The implementation of the standard [`map-get`][`crate::reference::functions#map-get`] function.
+        pub struct Get;
 
-        impl Function for Concat {
+        impl Function for Get {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let mut result = Vec::new();
-                while let Ok(list) = parameters.param() {
-                    result.append(&mut list.into_list()?);
-                }
-                Ok(result.into())
+                let mut arguments = Arguments::new("map-get", parameters);
+                let mut map = arguments.param_map()?;
+                let key = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(map.remove(key.as_str()).unwrap_or(Value::Null))
             }
         }
 
-        /// The implementation of the standard [`is-empty`][`crate::reference::functions#is-empty`] function.
-        pub struct IsEmpty;
+        /// The implementation of the standard [`map-set`][`crate::reference::functions#map-set`] function.
+        pub struct Set;
 
-        impl Function for IsEmpty {
+        impl Function for Set {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let list = parameters.param()?.into_list()?;
-                Ok(list.is_empty().into())
+                let mut arguments = Arguments::new("map-set", parameters);
+                let mut map = arguments.param_map()?;
+                let key = arguments.param_str()?;
+                let value = arguments.param()?;
+                arguments.finish()?;
+                map.insert(Identifier::from(key.as_str()), value);
+                Ok(map.into())
             }
         }
 
-        /// The implementation of the standard [`join`][`crate::reference::functions#join`] function.
-        pub struct Join;
+        /// The implementation of the standard [`map-keys`][`crate::reference::functions#map-keys`] function.
+        pub struct Keys;
 
-        impl Function for Join {
+        impl Function for Keys {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let list = parameters.param()?.into_list()?;
-                let sep = match parameters.param() {
-                    Ok(sep) => sep.into_string()?,
-                    Err(_) => "".to_string(),
-                };
-                parameters.finish()?;
-                let result = list
-                    .into_iter()
-                    .map(|x| format!("{}", x))
+                let mut arguments = Arguments::new("map-keys", parameters);
+                let map = arguments.param_map()?;
+                arguments.finish()?;
+                Ok(map
+                    .into_keys()
+                    .map(|key| Value::from(key.as_str()))
                     .collect::<Vec<_>>()
-                    .join(&sep);
-                Ok(result.into())
+                    .into())
             }
         }
 
-        /// The implementation of the standard [`length`][`crate::reference::functions#length`] function.
-        pub struct Length;
+        /// The implementation of the standard [`map-contains?`][`crate::reference::functions#map-contains`] function.
+        pub struct Contains;
 
-        impl Function for Length {
+        impl Function for Contains {
             fn call(
                 &self,
                 _graph: &mut Graph,
                 _source: &str,
                 parameters: &mut dyn Parameters,
             ) -> Result<Value, ExecutionError> {
-                let list = parameters.param()?.into_list()?;
-                Ok((list.len() as u32).into())
+                let mut arguments = Arguments::new("map-contains?", parameters);
+                let map = arguments.param_map()?;
+                let key = arguments.param_str()?;
+                arguments.finish()?;
+                Ok(map.contains_key(key.as_str()).into())
             }
         }
     }
@@ -8,23 +8,20 @@
 //! Defines data types for the graphs produced by the graph DSL
 
 use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fmt;
-use std::fs::File;
 use std::hash::Hash;
-use std::io::prelude::*;
-use std::io::stdout;
+use std::hash::Hasher;
 use std::ops::Index;
 use std::ops::IndexMut;
-use std::path::Path;
 
-use serde::ser::SerializeMap;
-use serde::ser::SerializeSeq;
-use serde::Serialize;
-use serde::Serializer;
-use serde_json;
 use smallvec::SmallVec;
 use tree_sitter::Node;
 
@@ -32,12 +29,125 @@ use crate::execution::error::ExecutionError;
 use crate::Identifier;
 use crate::Location;
 
+mod canonical;
+mod combine;
+#[cfg(feature = "serde")]
+mod deserialization;
+mod dot;
+mod edge_types;
+mod inheritance;
+mod lookup;
+mod memory;
+mod merge;
+mod mutation;
+mod orphans;
+#[cfg(feature = "petgraph")]
+mod petgraph_adapter;
+mod position;
+mod provenance;
+pub mod query;
+mod redaction;
+#[cfg(feature = "serde")]
+mod serialization;
+mod tag;
+mod visitor;
+
+pub use combine::CombinedFailure;
+pub use combine::CombinedFile;
+pub use combine::CombinedGraph;
+pub use combine::DEFAULT_SOURCE_PATH_ATTRIBUTE;
+#[cfg(feature = "serde")]
+pub use deserialization::MAX_MISSING_GRAPH_NODE_IDS;
+pub use edge_types::EdgeEndpoint;
+pub use edge_types::EdgeTypeHook;
+pub use edge_types::EdgeTypeRule;
+pub use edge_types::EdgeTypeSchema;
+#[cfg(feature = "serde")]
+pub use edge_types::EdgeTypeSchemaError;
+pub use edge_types::EdgeTypeViolation;
+pub use inheritance::AmbiguousInheritance;
+pub use memory::GraphMemoryUsage;
+pub use merge::AliasError;
+pub use merge::ConflictPolicy;
+pub use merge::GraphNodeRefMapping;
+pub use merge::SelfEdgePolicy;
+pub use mutation::GraphMutationError;
+pub use mutation::IntegrityViolation;
+pub use orphans::OrphanGroup;
+pub use orphans::OrphanReport;
+#[cfg(feature = "petgraph")]
+pub use petgraph_adapter::PetgraphAdapter;
+pub use position::PositionConverter;
+pub use position::PositionEncoding;
+pub use provenance::AttributeExplanation;
+pub use provenance::Origin;
+pub use provenance::ProvenanceStep;
+pub use provenance::ProvenanceStepRef;
+pub(crate) use provenance::StanzaOutput;
+pub use provenance::DEFAULT_EXPLANATION_DEPTH;
+pub use redaction::RedactionRules;
+#[cfg(feature = "serde")]
+pub use redaction::RedactionRulesError;
+#[cfg(feature = "serde")]
+pub use serialization::SerializationConfig;
+#[cfg(feature = "serde")]
+pub use serialization::SyntaxNodeRendering;
+pub use tag::Tag;
+pub use tag::TagError;
+pub use tag::MAX_TAG_DEPTH;
+pub use visitor::GraphVisitor;
+
 /// A graph produced by executing a graph DSL file.  Graphs include a lifetime parameter to ensure
 /// that they don't outlive the tree-sitter syntax tree that they are generated from.
+///
+/// # Mutating graphs after execution
+///
+/// Once a graph has been produced by execution, it belongs to the caller, who can change it
+/// freely.  The library assumes that every graph upholds the following, as graphs produced by
+/// execution do:
+///
+/// - Every graph node referred to by an edge or an attribute value exists in the graph.
+/// - Edges do not connect removed graph nodes.
+/// - Every syntax node referred to by an attribute value has been added to the graph using
+///   [`add_syntax_node`][Self::add_syntax_node], with a node of the syntax tree that the graph
+///   was built from.
+///
+/// Printing, serializing, merging, and pruning graphs that do not uphold these can panic or
+/// produce wrong results.  The following methods check them:
+///
+/// - [`add_graph_node`][Self::add_graph_node] and
+///   [`add_external_node`][Self::add_external_node] add graph nodes.
+/// - [`add_edge`][Self::add_edge] adds an edge between existing graph nodes.
+/// - [`set_attribute`][Self::set_attribute] adds or replaces an attribute of a graph node.
+///   Unlike during execution, replacing an attribute with a different value is allowed when
+///   asked for.
+/// - [`add_syntax_node`][Self::add_syntax_node] adds a syntax node.  Nodes of another syntax tree
+///   can have the same IDs as nodes of this one, which cannot be detected when they are added.
+///
+/// Mutating graph nodes directly, using the [`IndexMut`][] implementation, is deprecated, since
+/// it checks none of these.  [`check_integrity`][Self::check_integrity] finds every place where a
+/// graph does not uphold them.
 #[derive(Default)]
 pub struct Graph<'tree> {
     pub(crate) syntax_nodes: HashMap<SyntaxNodeID, Node<'tree>>,
     graph_nodes: Vec<GraphNode>,
+    owned_graph_nodes: HashMap<SyntaxNodeID, Vec<(Tag, GraphNodeRef)>>,
+    checkpoint: Option<Checkpoint>,
+    provenance: Option<provenance::Provenance>,
+    keyed_nodes: HashMap<String, GraphNodeRef>,
+    attribute_index: lookup::AttributeIndex,
+    orphans: Option<orphans::OrphanReport>,
+}
+
+/// The state needed to roll a graph back to an earlier point.  Graph nodes that existed when the
+/// checkpoint was taken are copied the first time they are mutably accessed; nodes created after
+/// the checkpoint are simply truncated away.  The same holds for the graph nodes owned by syntax
+/// nodes, and for the recorded provenance.
+struct Checkpoint {
+    graph_node_count: usize,
+    saved_nodes: HashMap<GraphNodeID, GraphNode>,
+    saved_owned_graph_nodes: HashMap<SyntaxNodeID, Option<Vec<(Tag, GraphNodeRef)>>>,
+    provenance: provenance::ProvenanceCheckpoint,
 }
 
 pub(crate) type SyntaxNodeID = u32;
@@ -53,17 +163,102 @@ impl<'tree> Graph<'tree> {
     ///
     /// The graph won't contain _every_ syntax node in the parsed syntax tree; it will only contain
     /// those nodes that are referenced at some point during the execution of the graph DSL file.
+    /// The node must belong to the syntax tree that the graph was built from.
     pub fn add_syntax_node(&mut self, node: Node<'tree>) -> SyntaxNodeRef {
-        let index = node.id() as SyntaxNodeID;
-        let node_ref = SyntaxNodeRef {
-            index,
-            kind: node.kind(),
-            position: node.start_position(),
-        };
-        self.syntax_nodes.entry(index).or_insert(node);
+        let node_ref = SyntaxNodeRef::from(&node);
+        self.syntax_nodes.entry(node_ref.index).or_insert(node);
         node_ref
     }
 
+    /// Returns a graph DSL reference to a syntax node, if the graph contains it.  Unlike
+    /// [`add_syntax_node`][Self::add_syntax_node], this does not add the node to the graph.
+    pub fn syntax_node_ref_for(&self, node: &Node) -> Option<SyntaxNodeRef> {
+        let node_ref = SyntaxNodeRef::from(node);
+        if self.syntax_nodes.contains_key(&node_ref.index) {
+            Some(node_ref)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a graph DSL reference to the syntax node with the given
+    /// [index][SyntaxNodeRef::index], if the graph contains it.  The kind, span, and position of
+    /// the syntax node are taken from the syntax node stored in the graph.
+    pub fn syntax_node_ref(&self, index: u32) -> Option<SyntaxNodeRef> {
+        self.syntax_nodes.get(&index).map(SyntaxNodeRef::from)
+    }
+
+    /// Records that a graph node is owned by a syntax node, under the given tag.  During
+    /// execution, every graph node stored in a scoped variable is owned by the variable's scope,
+    /// tagged with the variable's name.
+    pub(crate) fn add_owned_graph_node(
+        &mut self,
+        syntax_node: SyntaxNodeID,
+        tag: Tag,
+        graph_node: GraphNodeRef,
+    ) {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            let owned_graph_nodes = &self.owned_graph_nodes;
+            checkpoint
+                .saved_owned_graph_nodes
+                .entry(syntax_node)
+                .or_insert_with(|| owned_graph_nodes.get(&syntax_node).cloned());
+        }
+        let owned = self.owned_graph_nodes.entry(syntax_node).or_default();
+        let entry = (tag, graph_node);
+        if let Err(index) = owned.binary_search(&entry) {
+            owned.insert(index, entry);
+        }
+    }
+
+    /// Returns the graph nodes owned by a syntax node, together with their tags, ordered by tag.
+    /// A graph node is owned by a syntax node if it was stored in a scoped variable of that
+    /// syntax node; the tag has a single component, the name of the variable.
+    pub fn graph_nodes_for_syntax_node(
+        &self,
+        node: SyntaxNodeRef,
+    ) -> impl Iterator<Item = (&Tag, GraphNodeRef)> + '_ {
+        self.owned_graph_nodes
+            .get(&node.index)
+            .into_iter()
+            .flatten()
+            .map(|(tag, graph_node)| (tag, *graph_node))
+    }
+
+    /// Returns an index from syntax nodes to the graph nodes they own, together with their tags.
+    /// Syntax nodes that do not own any graph nodes are not included.
+    pub fn syntax_to_graph_index(&self) -> HashMap<SyntaxNodeRef, Vec<(Tag, GraphNodeRef)>> {
+        self.owned_graph_nodes
+            .iter()
+            .map(|(index, owned)| {
+                (
+                    SyntaxNodeRef::from(&self.syntax_nodes[index]),
+                    owned.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the syntax node that owns each graph node that has an owner.  If a graph node has
+    /// several owners, the one with the smallest span is used, and ties are broken by position.
+    pub(crate) fn graph_node_owners(&self) -> HashMap<GraphNodeID, SyntaxNodeRef> {
+        let mut owners = HashMap::<GraphNodeID, SyntaxNodeRef>::new();
+        for (syntax_node, owned) in &self.owned_graph_nodes {
+            let syntax_node = SyntaxNodeRef::from(&self.syntax_nodes[syntax_node]);
+            let key = |node: &SyntaxNodeRef| {
+                let range = node.byte_range();
+                (range.end - range.start, range.start)
+            };
+            for (_, graph_node) in owned {
+                let owner = owners.entry(graph_node.0).or_insert(syntax_node);
+                if key(&syntax_node) < key(owner) {
+                    *owner = syntax_node;
+                }
+            }
+        }
+        owners
+    }
+
     /// Adds a new graph node to the graph, returning a graph DSL reference to it.
     pub fn add_graph_node(&mut self) -> GraphNodeRef {
         let graph_node = GraphNode::new();
@@ -74,40 +269,162 @@ impl<'tree> Graph<'tree> {
 
     /// Pretty-prints the contents of this graph.
     pub fn pretty_print<'a>(&'a self) -> impl fmt::Display + 'a {
-        struct DisplayGraph<'a, 'tree>(&'a Graph<'tree>);
+        self.pretty_print_with(PrettyPrintOptions::default())
+    }
+
+    /// Pretty-prints the contents of this graph, with the given options.
+    pub fn pretty_print_with<'a>(&'a self, options: PrettyPrintOptions) -> impl fmt::Display + 'a {
+        struct DisplayGraph<'a, 'tree>(&'a Graph<'tree>, PrettyPrintOptions);
 
         impl<'a, 'tree> fmt::Display for DisplayGraph<'a, 'tree> {
             fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
                 let graph = self.0;
-                for (node_index, node) in graph.graph_nodes.iter().enumerate() {
-                    write!(f, "node {}\n{}", node_index, node.attributes)?;
-                    for (sink, edge) in &node.outgoing_edges {
-                        write!(f, "edge {} -> {}\n{}", node_index, *sink, edge.attributes)?;
+                let options = &self.1;
+                let nodes = graph
+                    .graph_nodes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, node)| !node.removed);
+                if options.header {
+                    let node_count = nodes.clone().count();
+                    let edge_count = nodes
+                        .clone()
+                        .map(|(_, node)| node.edge_count())
+                        .sum::<usize>();
+                    writeln!(
+                        f,
+                        "graph: {} nodes, {} edges, {} syntax nodes referenced",
+                        node_count,
+                        edge_count,
+                        graph.syntax_nodes.len()
+                    )?;
+                    if node_count == 0 {
+                        writeln!(f, "(empty graph)")?;
+                    }
+                }
+                graph.visit(&mut PrettyPrinter {
+                    f,
+                    node: None,
+                    include_hidden: options.include_hidden,
+                })?;
+                if options.syntax_nodes && !graph.syntax_nodes.is_empty() {
+                    writeln!(f, "syntax nodes")?;
+                    let mut nodes = graph.syntax_nodes.values().collect::<Vec<_>>();
+                    nodes.sort_by_key(|node| (node.start_byte(), Reverse(node.end_byte())));
+                    for node in nodes {
+                        writeln!(f, "  {}", SyntaxNodeRef::from(node))?;
                     }
                 }
                 Ok(())
             }
         }
 
-        DisplayGraph(self)
+        DisplayGraph(self, options)
     }
 
-    pub fn display_json(&self, path: Option<&Path>) -> std::io::Result<()> {
-        let s = serde_json::to_string_pretty(self).unwrap();
-        path.map_or(stdout().write_all(s.as_bytes()), |path| {
-            File::create(path)?.write_all(s.as_bytes())
-        })
-    }
-
-    // Returns an iterator of references to all of the nodes in the graph.
+    // Returns an iterator of references to all of the nodes in the graph, except removed ones.
     pub fn iter_nodes(&self) -> impl Iterator<Item = GraphNodeRef> {
-        (0..self.graph_nodes.len() as u32).map(GraphNodeRef)
+        self.graph_nodes_from(0)
     }
 
-    // Returns the number of nodes in the graph.
+    // Returns the number of nodes in the graph, including removed ones.
     pub fn node_count(&self) -> usize {
         self.graph_nodes.len()
     }
+
+    /// Returns a reference to the graph node with the given [index][GraphNodeRef::index], or None
+    /// if the graph has no such graph node, or it was removed.
+    pub fn node_ref(&self, index: usize) -> Option<GraphNodeRef> {
+        let node = GraphNodeRef(GraphNodeID::try_from(index).ok()?);
+        if self.contains(node) {
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether a graph node reference refers to a graph node of this graph that was not
+    /// removed.  References only record the index of their graph node, so a reference to a graph
+    /// node of another graph is contained in this graph if this graph has a graph node with the
+    /// same index.
+    pub fn contains(&self, node: GraphNodeRef) -> bool {
+        matches!(self.graph_nodes.get(node.index()), Some(node) if !node.removed)
+    }
+
+    /// Returns an iterator of references to the nodes in the graph, except removed ones, starting
+    /// at the given index.
+    pub(crate) fn graph_nodes_from(&self, first: usize) -> impl Iterator<Item = GraphNodeRef> {
+        // collect the removed nodes up front, so that the iterator does not borrow the graph
+        let removed = (first..self.graph_nodes.len())
+            .filter(|index| self.graph_nodes[*index].removed)
+            .map(|index| index as GraphNodeID)
+            .collect::<Vec<_>>();
+        (first as GraphNodeID..self.graph_nodes.len() as GraphNodeID)
+            .filter(move |index| removed.binary_search(index).is_err())
+            .map(GraphNodeRef)
+    }
+
+    /// Computes an attribute for every graph node that does not have it yet.  Nodes are visited
+    /// in order, and the computation can read the graph, including attribute values computed for
+    /// earlier nodes.  Nodes for which `compute` returns `None` are left without the attribute.
+    ///
+    /// This is useful for attributes that are expensive to compute, and which are therefore better
+    /// computed after execution, and only when they are actually needed.
+    pub fn compute_attribute<N, F>(&mut self, name: N, mut compute: F)
+    where
+        N: Into<Identifier>,
+        F: FnMut(GraphNodeRef, &Graph<'tree>) -> Option<Value>,
+    {
+        let name = name.into();
+        for node in self.iter_nodes() {
+            if self[node].attributes.get(&name).is_some() {
+                continue;
+            }
+            if let Some(value) = compute(node, self) {
+                // the attribute is missing, so adding it cannot fail
+                let _ = self[node].attributes.add(name.clone(), value);
+            }
+        }
+    }
+
+    /// Records the current state of the graph, so that any graph nodes, edges, and attributes
+    /// added afterwards, together with their owners and provenance, can be undone with
+    /// [`rollback`][Self::rollback].  Any previous checkpoint is discarded.
+    pub(crate) fn checkpoint(&mut self) {
+        self.checkpoint = Some(Checkpoint {
+            graph_node_count: self.graph_nodes.len(),
+            saved_nodes: HashMap::new(),
+            saved_owned_graph_nodes: HashMap::new(),
+            provenance: provenance::ProvenanceCheckpoint::new(self.provenance.as_ref()),
+        });
+    }
+
+    /// Discards the current checkpoint, keeping all changes made since it was taken.
+    pub(crate) fn commit(&mut self) {
+        self.checkpoint = None;
+    }
+
+    /// Restores the graph to the state it was in when the current checkpoint was taken.  Syntax
+    /// nodes that were added since then are kept, since they do not affect the graph's contents.
+    pub(crate) fn rollback(&mut self) {
+        if let Some(checkpoint) = self.checkpoint.take() {
+            self.graph_nodes.truncate(checkpoint.graph_node_count);
+            self.keyed_nodes
+                .retain(|_, node| node.index() < checkpoint.graph_node_count);
+            for (index, node) in checkpoint.saved_nodes {
+                self.graph_nodes[index as usize] = node;
+            }
+            for (syntax_node, owned) in checkpoint.saved_owned_graph_nodes {
+                match owned {
+                    Some(owned) => self.owned_graph_nodes.insert(syntax_node, owned),
+                    None => self.owned_graph_nodes.remove(&syntax_node),
+                };
+            }
+            if let Some(provenance) = &mut self.provenance {
+                provenance.rollback(checkpoint.provenance);
+            }
+        }
+    }
 }
 
 impl<'tree> Index<SyntaxNodeRef> for Graph<'tree> {
@@ -124,27 +441,81 @@ impl Index<GraphNodeRef> for Graph<'_> {
     }
 }
 
+/// Deprecated: mutating graph nodes directly does not check that edges and attribute values refer
+/// to nodes of the graph.  Use [`Graph::add_edge`][] and [`Graph::set_attribute`][] instead.
 impl<'tree> IndexMut<GraphNodeRef> for Graph<'_> {
     fn index_mut(&mut self, index: GraphNodeRef) -> &mut GraphNode {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            if (index.0 as usize) < checkpoint.graph_node_count {
+                let graph_nodes = &self.graph_nodes;
+                checkpoint
+                    .saved_nodes
+                    .entry(index.0)
+                    .or_insert_with(|| graph_nodes[index.0 as usize].clone());
+            }
+        }
         &mut self.graph_nodes[index.0 as usize]
     }
 }
 
-impl<'tree> Serialize for Graph<'tree> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut seq = serializer.serialize_seq(Some(self.graph_nodes.len()))?;
-        for (node_index, node) in self.graph_nodes.iter().enumerate() {
-            seq.serialize_element(&SerializeGraphNode(node_index, node))?;
+/// Options for [`Graph::pretty_print_with`][].  The default options print the graph exactly as
+/// [`Graph::pretty_print`][] does.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PrettyPrintOptions {
+    /// Print a summary line with the number of graph nodes, edges, and referenced syntax nodes
+    /// before the graph, followed by `(empty graph)` if the graph has no graph nodes.
+    pub header: bool,
+    /// Print the syntax nodes referenced by the graph after the graph, in the order in which they
+    /// appear in the source.
+    pub syntax_nodes: bool,
+    /// Print [hidden][Attributes::is_hidden] attributes, which are left out by default.
+    pub include_hidden: bool,
+}
+
+/// Pretty-prints the graph nodes and edges of a graph as they are visited
+struct PrettyPrinter<'f, 'g> {
+    f: &'f mut fmt::Formatter<'g>,
+    node: Option<GraphNodeRef>,
+    include_hidden: bool,
+}
+
+impl<'a> GraphVisitor<'a> for PrettyPrinter<'_, '_> {
+    type Error = fmt::Error;
+
+    fn include_hidden(&self) -> bool {
+        self.include_hidden
+    }
+
+    fn begin_node(&mut self, node: GraphNodeRef) -> fmt::Result {
+        self.node = Some(node);
+        writeln!(self.f, "node {}", node.0)
+    }
+
+    fn attribute(&mut self, name: &'a Identifier, value: &'a Value) -> fmt::Result {
+        writeln!(self.f, "  {}: {:?}", name, value)
+    }
+
+    fn edge(
+        &mut self,
+        sink: GraphNodeRef,
+        attributes: &[(&'a Identifier, &'a Value)],
+    ) -> fmt::Result {
+        let source = self.node.expect("edge outside of a graph node");
+        writeln!(self.f, "edge {} -> {}", source.0, sink.0)?;
+        for (name, value) in attributes {
+            self.attribute(name, value)?;
         }
-        seq.end()
+        Ok(())
     }
 }
 
 /// A node in a graph
+#[derive(Clone)]
 pub struct GraphNode {
     outgoing_edges: SmallVec<[(GraphNodeID, Edge); 8]>,
     /// The set of attributes associated with this graph node
     pub attributes: Attributes,
+    removed: bool,
 }
 
 impl GraphNode {
@@ -152,6 +523,7 @@ impl GraphNode {
         GraphNode {
             outgoing_edges: SmallVec::new(),
             attributes: Attributes::new(),
+            removed: false,
         }
     }
 
@@ -203,49 +575,8 @@ impl GraphNode {
     }
 }
 
-struct SerializeGraphNode<'a>(usize, &'a GraphNode);
-
-impl<'a> Serialize for SerializeGraphNode<'a> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let node_index = self.0;
-        let node = self.1;
-        // serializing as a map instead of a struct so we don't have to encode a struct name
-        let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry("id", &node_index)?;
-        map.serialize_entry("edges", &SerializeGraphNodeEdges(&node.outgoing_edges))?;
-        map.serialize_entry("attrs", &node.attributes)?;
-        map.end()
-    }
-}
-
-struct SerializeGraphNodeEdges<'a>(&'a SmallVec<[(GraphNodeID, Edge); 8]>);
-
-impl<'a> Serialize for SerializeGraphNodeEdges<'a> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let edges = self.0;
-        let mut seq = serializer.serialize_seq(Some(edges.len()))?;
-        for element in edges {
-            seq.serialize_element(&SerializeGraphNodeEdge(&element))?;
-        }
-        seq.end()
-    }
-}
-
-struct SerializeGraphNodeEdge<'a>(&'a (GraphNodeID, Edge));
-
-impl<'a> Serialize for SerializeGraphNodeEdge<'a> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let wrapped = &self.0;
-        let sink = &wrapped.0;
-        let edge = &wrapped.1;
-        let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry("sink", sink)?;
-        map.serialize_entry("attrs", &edge.attributes)?;
-        map.end()
-    }
-}
-
 /// An edge between two nodes in a graph
+#[derive(Clone)]
 pub struct Edge {
     /// The set of attributes associated with this edge
     pub attributes: Attributes,
@@ -263,6 +594,11 @@ impl Edge {
 #[derive(Clone, Debug)]
 pub struct Attributes {
     values: HashMap<Identifier, Value>,
+    // The attributes whose values are defaults, which explicit values replace without conflict
+    defaults: HashSet<Identifier>,
+    // The attributes that were added with `add_hidden`, apart from those whose names make them
+    // hidden anyway
+    hidden: HashSet<Identifier>,
 }
 
 impl Attributes {
@@ -270,12 +606,18 @@ impl Attributes {
     pub fn new() -> Attributes {
         Attributes {
             values: HashMap::new(),
+            defaults: HashSet::new(),
+            hidden: HashSet::new(),
         }
     }
 
     /// Adds an attribute to this attribute set.  If there was already an attribute with the same
-    /// name, replaces its value and returns `Err`.
+    /// name, replaces its value and returns `Err`, unless the existing value is a default.
     pub fn add<V: Into<Value>>(&mut self, name: Identifier, value: V) -> Result<(), Value> {
+        if self.defaults.remove(&name) {
+            self.values.insert(name, value.into());
+            return Ok(());
+        }
         match self.values.entry(name) {
             Entry::Occupied(mut o) => {
                 let value = value.into();
@@ -292,6 +634,100 @@ impl Attributes {
         }
     }
 
+    /// Adds a hidden attribute to this attribute set, in the same way as [`add`][Self::add],
+    /// including the detection of conflicting values.  Hidden attributes can be read like any
+    /// other attribute, but are left out of the pretty-printed and serialized graph, unless they
+    /// are asked for.  Attributes whose names start with `_` are always hidden, however they were
+    /// added.
+    pub fn add_hidden<V: Into<Value>>(&mut self, name: Identifier, value: V) -> Result<(), Value> {
+        self.add(name.clone(), value)?;
+        if !name.starts_with('_') {
+            self.hidden.insert(name);
+        }
+        Ok(())
+    }
+
+    /// Appends a value to a list-valued attribute, creating the attribute as a single-element
+    /// list if it does not exist yet.  If the attribute has a value that is not a list, leaves it
+    /// unchanged and returns `Err` with the value that could not be appended.
+    pub fn append<V: Into<Value>>(&mut self, name: Identifier, value: V) -> Result<(), Value> {
+        if self.defaults.remove(&name) {
+            self.values.insert(name, Value::List(vec![value.into()]));
+            return Ok(());
+        }
+        match self.values.entry(name) {
+            Entry::Occupied(mut o) => match o.get_mut() {
+                Value::List(values) => {
+                    values.push(value.into());
+                    Ok(())
+                }
+                _ => Err(value.into()),
+            },
+            Entry::Vacant(v) => {
+                v.insert(Value::List(vec![value.into()]));
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets an attribute to a value, replacing any value it already has, including a default.
+    /// Returns whether the attribute had a value before.  A hidden attribute stays hidden.
+    pub fn set<V: Into<Value>>(&mut self, name: Identifier, value: V) -> bool {
+        self.defaults.remove(&name);
+        self.values.insert(name, value.into()).is_some()
+    }
+
+    /// Removes an attribute from this attribute set, along with its default and hidden status.
+    /// Returns whether the attribute had a value.
+    pub fn remove<Q>(&mut self, name: &Q) -> bool
+    where
+        Q: ?Sized + Eq + Hash,
+        Identifier: Borrow<Q>,
+    {
+        self.defaults.remove(name);
+        self.hidden.remove(name);
+        self.values.remove(name).is_some()
+    }
+
+    /// Adds a default value for an attribute.  Defaults never conflict: an explicit value that is
+    /// already set is kept, a later default replaces an earlier one, and a later explicit value,
+    /// added using [`add`][Self::add] or [`append`][Self::append], replaces the default.
+    pub fn add_default<V: Into<Value>>(&mut self, name: Identifier, value: V) {
+        match self.values.entry(name) {
+            Entry::Occupied(mut o) => {
+                if self.defaults.contains(o.key()) {
+                    o.insert(value.into());
+                }
+            }
+            Entry::Vacant(v) => {
+                self.defaults.insert(v.key().clone());
+                v.insert(value.into());
+            }
+        }
+    }
+
+    /// Returns whether a particular attribute exists and has a default value.
+    pub fn is_default<Q>(&self, name: &Q) -> bool
+    where
+        Q: ?Sized + Eq + Hash,
+        Identifier: Borrow<Q>,
+    {
+        self.defaults.contains(name)
+    }
+
+    /// Returns whether an attribute is hidden, because its name starts with `_`, or because it was
+    /// added with [`add_hidden`][Self::add_hidden].
+    pub fn is_hidden(&self, name: &str) -> bool {
+        name.starts_with('_') || self.hidden.contains(name)
+    }
+
+    /// Returns the names of the attributes that were added with [`add_hidden`][Self::add_hidden]
+    /// and whose names do not start with `_`, in no particular order.
+    #[cfg(feature = "serde")]
+    pub(crate) fn explicitly_hidden(&self) -> impl Iterator<Item = &Identifier> {
+        self.hidden.iter()
+    }
+
     /// Returns the value of a particular attribute, if it exists.
     pub fn get<Q>(&self, name: &Q) -> Option<&Value>
     where
@@ -301,6 +737,7 @@ impl Attributes {
         self.values.get(name.borrow())
     }
 
+    /// Returns an iterator over the attributes, in no particular order, including hidden ones.
     pub fn iter(&self) -> impl Iterator<Item = (&Identifier, &Value)> {
         self.values.iter()
     }
@@ -308,7 +745,11 @@ impl Attributes {
 
 impl std::fmt::Display for Attributes {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut keys = self.values.keys().collect::<Vec<_>>();
+        let mut keys = self
+            .values
+            .keys()
+            .filter(|key| !self.is_hidden(key))
+            .collect::<Vec<_>>();
         keys.sort_by(|a, b| a.cmp(b));
         for key in &keys {
             let value = &self.values[*key];
@@ -318,27 +759,21 @@ impl std::fmt::Display for Attributes {
     }
 }
 
-impl Serialize for Attributes {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut map = serializer.serialize_map(None)?;
-        for (key, value) in &self.values {
-            map.serialize_entry(key, value)?;
-        }
-        map.end()
-    }
-}
-
 /// The value of an attribute
 #[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Value {
     // Scalar
     Null,
     Boolean(bool),
-    Integer(u32),
+    Integer(i64),
+    Float(Float),
     String(String),
     // Compound
     List(Vec<Value>),
     Set(BTreeSet<Value>),
+    Map(BTreeMap<Identifier, Value>),
+    // Source locations
+    Location(SourceLocation),
     // References
     SyntaxNode(SyntaxNodeRef),
     GraphNode(GraphNodeRef),
@@ -369,20 +804,34 @@ impl Value {
     }
 
     /// Coerces this value into an integer, returning an error if it's some other type of value.
-    pub fn into_integer(self) -> Result<u32, ExecutionError> {
+    pub fn into_integer(self) -> Result<i64, ExecutionError> {
         match self {
             Value::Integer(value) => Ok(value),
             _ => Err(ExecutionError::ExpectedInteger(format!("got {}", self))),
         }
     }
 
-    pub fn as_integer(&self) -> Result<u32, ExecutionError> {
+    pub fn as_integer(&self) -> Result<i64, ExecutionError> {
         match self {
             Value::Integer(value) => Ok(*value),
             _ => Err(ExecutionError::ExpectedInteger(format!("got {}", self))),
         }
     }
 
+    /// Coerces this value into a float, returning an error if it's some other type of value.
+    /// Integers are promoted to floats.
+    pub fn into_float(self) -> Result<f64, ExecutionError> {
+        self.as_float()
+    }
+
+    pub fn as_float(&self) -> Result<f64, ExecutionError> {
+        match self {
+            Value::Float(value) => Ok(value.get()),
+            Value::Integer(value) => Ok(*value as f64),
+            _ => Err(ExecutionError::ExpectedFloat(format!("got {}", self))),
+        }
+    }
+
     /// Coerces this value into a string, returning an error if it's some other type of value.
     pub fn into_string(self) -> Result<String, ExecutionError> {
         match self {
@@ -413,6 +862,36 @@ impl Value {
         }
     }
 
+    /// Coerces this value into a set, returning an error if it's some other type of value.
+    pub fn into_set(self) -> Result<BTreeSet<Value>, ExecutionError> {
+        match self {
+            Value::Set(values) => Ok(values),
+            _ => Err(ExecutionError::ExpectedSet(format!("got {}", self))),
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&BTreeSet<Value>, ExecutionError> {
+        match self {
+            Value::Set(values) => Ok(values),
+            _ => Err(ExecutionError::ExpectedSet(format!("got {}", self))),
+        }
+    }
+
+    /// Coerces this value into a map, returning an error if it's some other type of value.
+    pub fn into_map(self) -> Result<BTreeMap<Identifier, Value>, ExecutionError> {
+        match self {
+            Value::Map(values) => Ok(values),
+            _ => Err(ExecutionError::ExpectedMap(format!("got {}", self))),
+        }
+    }
+
+    pub fn as_map(&self) -> Result<&BTreeMap<Identifier, Value>, ExecutionError> {
+        match self {
+            Value::Map(values) => Ok(values),
+            _ => Err(ExecutionError::ExpectedMap(format!("got {}", self))),
+        }
+    }
+
     /// Coerces this value into a graph node reference, returning an error if it's some other type
     /// of value.
     pub fn into_graph_node_ref<'a, 'tree>(self) -> Result<GraphNodeRef, ExecutionError> {
@@ -454,6 +933,22 @@ impl Value {
             _ => Err(ExecutionError::ExpectedSyntaxNode(format!("got {}", self))),
         }
     }
+
+    /// Coerces this value into a source location, returning an error if it's some other type of
+    /// value.
+    pub fn into_location(self) -> Result<SourceLocation, ExecutionError> {
+        match self {
+            Value::Location(location) => Ok(location),
+            _ => Err(ExecutionError::ExpectedLocation(format!("got {}", self))),
+        }
+    }
+
+    pub fn as_location(&self) -> Result<SourceLocation, ExecutionError> {
+        match self {
+            Value::Location(location) => Ok(*location),
+            _ => Err(ExecutionError::ExpectedLocation(format!("got {}", self))),
+        }
+    }
 }
 
 impl From<bool> for Value {
@@ -462,9 +957,34 @@ impl From<bool> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Integer(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Value {
+        Value::Integer(value.into())
+    }
+}
+
 impl From<u32> for Value {
     fn from(value: u32) -> Value {
-        Value::Integer(value)
+        Value::Integer(value.into())
+    }
+}
+
+impl From<Float> for Value {
+    fn from(value: Float) -> Value {
+        Value::Float(value)
+    }
+}
+
+impl TryFrom<f64> for Value {
+    type Error = ExecutionError;
+    fn try_from(value: f64) -> Result<Value, ExecutionError> {
+        Ok(Value::Float(Float::try_from(value)?))
     }
 }
 
@@ -492,6 +1012,31 @@ impl From<BTreeSet<Value>> for Value {
     }
 }
 
+impl From<BTreeMap<Identifier, Value>> for Value {
+    fn from(value: BTreeMap<Identifier, Value>) -> Value {
+        Value::Map(value)
+    }
+}
+
+impl From<SourceLocation> for Value {
+    fn from(value: SourceLocation) -> Value {
+        Value::Location(value)
+    }
+}
+
+impl From<tree_sitter::Range> for Value {
+    fn from(value: tree_sitter::Range) -> Value {
+        Value::Location(value.into())
+    }
+}
+
+impl TryFrom<Value> for tree_sitter::Range {
+    type Error = ExecutionError;
+    fn try_from(value: Value) -> Result<tree_sitter::Range, ExecutionError> {
+        Ok(value.into_location()?.into())
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -504,6 +1049,7 @@ impl std::fmt::Display for Value {
                 }
             }
             Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => value.fmt(f),
             Value::String(value) => write!(f, "{}", value),
             Value::List(value) => {
                 write!(f, "[")?;
@@ -531,6 +1077,23 @@ impl std::fmt::Display for Value {
                 }
                 write!(f, "}}")
             }
+            Value::Map(value) => {
+                if value.is_empty() {
+                    return write!(f, "{{:}}");
+                }
+                write!(f, "{{")?;
+                let mut first = true;
+                for (key, element) in value {
+                    if first {
+                        write!(f, "{}: {}", key, element)?;
+                        first = false;
+                    } else {
+                        write!(f, ", {}: {}", key, element)?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::Location(location) => location.fmt(f),
             Value::SyntaxNode(node) => node.fmt(f),
             Value::GraphNode(node) => node.fmt(f),
         }
@@ -549,6 +1112,7 @@ impl std::fmt::Debug for Value {
                 }
             }
             Value::Integer(value) => write!(f, "{:?}", value),
+            Value::Float(value) => value.fmt(f),
             Value::String(value) => write!(f, "{:?}", value),
             Value::List(value) => {
                 write!(f, "[")?;
@@ -576,72 +1140,202 @@ impl std::fmt::Debug for Value {
                 }
                 write!(f, "}}")
             }
+            Value::Map(value) => {
+                if value.is_empty() {
+                    return write!(f, "{{:}}");
+                }
+                write!(f, "{{")?;
+                let mut first = true;
+                for (key, element) in value {
+                    if first {
+                        write!(f, "{}: {:?}", key, element)?;
+                        first = false;
+                    } else {
+                        write!(f, ", {}: {:?}", key, element)?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::Location(location) => location.fmt(f),
             Value::SyntaxNode(node) => node.fmt(f),
             Value::GraphNode(node) => node.fmt(f),
         }
     }
 }
 
-impl Serialize for Value {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        match self {
-            Value::Null => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "null")?;
-                map.end()
-            }
-            Value::Boolean(bool) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "bool")?;
-                map.serialize_entry("bool", bool)?;
-                map.end()
-            }
-            Value::Integer(int) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "int")?;
-                map.serialize_entry("int", int)?;
-                map.end()
-            }
-            Value::String(str) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "string")?;
-                map.serialize_entry("string", str)?;
-                map.end()
-            }
-            Value::List(list) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "list")?;
-                map.serialize_entry("values", list)?;
-                map.end()
-            }
-            Value::Set(set) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "set")?;
-                map.serialize_entry("values", set)?;
-                map.end()
-            }
-            Value::SyntaxNode(node) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "syntaxNode")?;
-                map.serialize_entry("id", &node.index)?;
-                map.end()
-            }
-            Value::GraphNode(node) => {
-                let mut map = serializer.serialize_map(None)?;
-                map.serialize_entry("type", "graphNode")?;
-                map.serialize_entry("id", &node.0)?;
-                map.end()
-            }
+/// A floating-point number that can be stored in a [`Value`][].  Floats are always finite: NaN and
+/// the infinities cannot be represented, so that floats are totally ordered, can be stored in
+/// sets, and are serialized as JSON numbers.  Negative zero is stored as zero.  Floats are always
+/// displayed with a fractional part or an exponent, so that they can be told apart from integers.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Float(f64);
+
+impl Float {
+    /// Creates a float, returning `None` if the value is NaN or infinite.
+    pub fn new(value: f64) -> Option<Float> {
+        if value.is_finite() {
+            // adding zero turns negative zero into zero, and leaves everything else unchanged
+            Some(Float(value + 0.0))
+        } else {
+            None
         }
     }
+
+    /// Returns the value of this float.
+    pub fn get(self) -> f64 {
+        self.0
+    }
 }
 
-/// A reference to a syntax node in a graph
+impl TryFrom<f64> for Float {
+    type Error = ExecutionError;
+    fn try_from(value: f64) -> Result<Float, ExecutionError> {
+        Float::new(value)
+            .ok_or_else(|| ExecutionError::ExpectedFloat(format!("that is finite, got {}", value)))
+    }
+}
+
+impl From<Float> for f64 {
+    fn from(value: Float) -> f64 {
+        value.0
+    }
+}
+
+impl Eq for Float {}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .expect("floats are finite and can always be compared")
+    }
+}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // equal floats have the same bits, because negative zero is never stored
+        self.0.to_bits().hash(state)
+    }
+}
+
+impl std::fmt::Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // unlike Display, Debug always includes a fractional part or an exponent
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::fmt::Debug for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// A range of source code, given by its byte offsets and its start and end points.  The end is
+/// exclusive.  Source locations are displayed as `ROW:COLUMN-ROW:COLUMN`, with one-based rows and
+/// columns.
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SourceLocation {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_point: tree_sitter::Point,
+    pub end_point: tree_sitter::Point,
+}
+
+impl SourceLocation {
+    /// Returns the smallest source location that contains both this location and another one.
+    pub fn span(&self, other: &SourceLocation) -> SourceLocation {
+        SourceLocation {
+            start_byte: self.start_byte.min(other.start_byte),
+            end_byte: self.end_byte.max(other.end_byte),
+            start_point: self.start_point.min(other.start_point),
+            end_point: self.end_point.max(other.end_point),
+        }
+    }
+
+    /// Returns whether this location contains another one.
+    pub fn contains(&self, other: &SourceLocation) -> bool {
+        self.start_byte <= other.start_byte && other.end_byte <= self.end_byte
+    }
+
+    /// Returns whether this location and another one have at least one byte in common.
+    pub fn overlaps(&self, other: &SourceLocation) -> bool {
+        self.start_byte < other.end_byte && other.start_byte < self.end_byte
+    }
+}
+
+impl From<tree_sitter::Range> for SourceLocation {
+    fn from(range: tree_sitter::Range) -> SourceLocation {
+        SourceLocation {
+            start_byte: range.start_byte,
+            end_byte: range.end_byte,
+            start_point: range.start_point,
+            end_point: range.end_point,
+        }
+    }
+}
+
+impl From<SourceLocation> for tree_sitter::Range {
+    fn from(location: SourceLocation) -> tree_sitter::Range {
+        tree_sitter::Range {
+            start_byte: location.start_byte,
+            end_byte: location.end_byte,
+            start_point: location.start_point,
+            end_point: location.end_point,
+        }
+    }
+}
+
+impl From<&Node<'_>> for SourceLocation {
+    fn from(node: &Node) -> SourceLocation {
+        SourceLocation {
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            start_point: node.start_position(),
+            end_point: node.end_position(),
+        }
+    }
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start_point.row + 1,
+            self.start_point.column + 1,
+            self.end_point.row + 1,
+            self.end_point.column + 1,
+        )
+    }
+}
+
+impl std::fmt::Debug for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+/// A reference to a syntax node in a graph.  The reference records the kind and span of the
+/// syntax node, and whether it is named, so that they are available without consulting the syntax
+/// tree.  References can refer to anonymous syntax nodes, such as operators and keywords, as well
+/// as named ones.  References can only be obtained from a graph, such as by adding a syntax node to
+/// it, or by looking up an index using [`Graph::syntax_node_ref`][].
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct SyntaxNodeRef {
     pub(crate) index: SyntaxNodeID,
     kind: &'static str,
+    named: bool,
     position: tree_sitter::Point,
+    end_position: tree_sitter::Point,
+    start_byte: usize,
+    end_byte: usize,
 }
 
 impl From<tree_sitter::Point> for Location {
@@ -654,9 +1348,67 @@ impl From<tree_sitter::Point> for Location {
 }
 
 impl SyntaxNodeRef {
+    /// Returns the index of the syntax node that this reference refers to, which identifies it
+    /// within its syntax tree.  Use [`Graph::syntax_node_ref`][] to turn an index back into a
+    /// reference.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
     pub fn location(&self) -> Location {
         Location::from(self.position)
     }
+
+    /// Returns the location of the end of this syntax node.
+    pub fn end_location(&self) -> Location {
+        Location::from(self.end_position)
+    }
+
+    /// Returns the kind of this syntax node.  The kind of an anonymous syntax node is its text, as
+    /// in `+`.
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    /// Returns whether this syntax node is named.  Anonymous syntax nodes, such as operators and
+    /// keywords, are matched by string patterns in queries, as in `"+" @op`.
+    pub fn is_named(&self) -> bool {
+        self.named
+    }
+
+    /// Displays this syntax node with the given location.  The kind of an anonymous syntax node
+    /// is quoted, as in queries.
+    pub(crate) fn fmt_at(
+        &self,
+        f: &mut std::fmt::Formatter,
+        location: Location,
+    ) -> std::fmt::Result {
+        if self.named {
+            write!(f, "[syntax node {} ", self.kind)?;
+        } else {
+            write!(f, "[syntax node {:?} ", self.kind)?;
+        }
+        write!(f, "({}, {})]", location.row + 1, location.column + 1)
+    }
+
+    /// Returns the range of bytes of the source text that this syntax node spans.
+    pub fn byte_range(&self) -> std::ops::Range<usize> {
+        self.start_byte..self.end_byte
+    }
+}
+
+impl From<&Node<'_>> for SyntaxNodeRef {
+    fn from(node: &Node) -> SyntaxNodeRef {
+        SyntaxNodeRef {
+            index: node.id() as SyntaxNodeID,
+            kind: node.kind(),
+            named: node.is_named(),
+            position: node.start_position(),
+            end_position: node.end_position(),
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+        }
+    }
 }
 
 impl From<SyntaxNodeRef> for Value {
@@ -667,29 +1419,19 @@ impl From<SyntaxNodeRef> for Value {
 
 impl std::fmt::Display for SyntaxNodeRef {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "[syntax node {} ({}, {})]",
-            self.kind,
-            self.position.row + 1,
-            self.position.column + 1,
-        )
+        self.fmt_at(f, self.location())
     }
 }
 
 impl std::fmt::Debug for SyntaxNodeRef {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "[syntax node {} ({}, {})]",
-            self.kind,
-            self.position.row + 1,
-            self.position.column + 1,
-        )
+        self.fmt_at(f, self.location())
     }
 }
 
-/// A reference to a graph node
+/// A reference to a graph node.  References can only be obtained from a graph, such as by adding a
+/// graph node to it, or by looking up an index using [`Graph::node_ref`][], which checks that the
+/// graph has a graph node with that index.
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct GraphNodeRef(GraphNodeID);
 
@@ -0,0 +1,250 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Canonical hashing of graphs
+
+use std::collections::HashMap;
+
+use sha2::Digest as _;
+use sha2::Sha256;
+
+use super::Attributes;
+use super::Graph;
+use super::GraphNodeRef;
+use super::SyntaxNodeRef;
+use super::Value;
+
+type Digest = [u8; 32];
+
+impl<'tree> Graph<'tree> {
+    /// Returns a SHA-256 digest of the contents of this graph that does not depend on the order
+    /// in which graph nodes were created.  Two graphs have the same hash if their graph nodes
+    /// can be matched up so that matching nodes have the same owners and attributes, and the
+    /// same edges between them.  This makes the hash suitable as a cache key for the results of
+    /// executing a graph DSL file, since reordering the stanzas of the file does not change it.
+    ///
+    /// The converse does not always hold.  The identity of a graph node only depends on its
+    /// owners and attributes, and on the nodes that its attributes refer to, and not on the rest
+    /// of the graph.  Graphs whose nodes cannot be told apart that way can have the same hash even
+    /// if their edges connect the nodes differently.  For instance, six graph nodes without owners
+    /// or attributes, connected as one cycle of six edges, have the same hash as when they are
+    /// connected as two cycles of three edges.
+    ///
+    /// The digest is computed as follows, so that other implementations can reproduce it.  All
+    /// integers are encoded as 8 bytes, big-endian, and _H_ is SHA-256.
+    ///
+    /// - A string is encoded as its length in bytes followed by its UTF-8 bytes.
+    ///
+    /// - A value is encoded as a type byte followed by its content: null is `0`; a boolean is
+    ///   `1` followed by a byte `0` or `1`; an integer is `2` followed by the integer; a string is
+    ///   `3` followed by the string; a list is `4` followed by the number of elements and the
+    ///   encoding of each element, in order; a set is `5` followed by the number of elements and
+    ///   the encodings of the elements, sorted bytewise; a location is `6` followed by its start
+    ///   and end byte, and the row and column of its start and end point; a syntax node is `7`
+    ///   followed by its kind as a string, and its start and end byte.  A graph node is `8`,
+    ///   followed by the _local hash_ of the node when computing node identities and edge hashes,
+    ///   and by nothing when computing local hashes.  A float is `9` followed by the bits of its
+    ///   IEEE 754 double-precision representation, as an integer.  Floats are never NaN or
+    ///   infinite, and negative zero is stored as zero, so it has the same encoding as zero.  A
+    ///   map is `10` followed by the number of entries, and the key, as a string, and the value
+    ///   of each entry, sorted bytewise by key.
+    ///
+    /// - A set of attributes is encoded as the number of attributes followed by the name, as a
+    ///   string, and the value of each attribute, sorted by name.  Hidden attributes are left
+    ///   out.
+    ///
+    /// - The owners of a graph node are the syntax nodes that own it, together with their tags
+    ///   (see [`graph_nodes_for_syntax_node`][Graph::graph_nodes_for_syntax_node]).  Each owner
+    ///   is encoded as the kind of the syntax node, its start and end byte, and the tag; the
+    ///   owners of a node are encoded as their number followed by their encodings, sorted
+    ///   bytewise.
+    ///
+    /// - The local hash of a graph node is _H_(`"node"` ‖ owners ‖ attributes), where graph node
+    ///   values are encoded without content.  Its identity is _H_(local hash ‖ attributes), where
+    ///   graph node values are encoded by the local hash of the node they refer to.
+    ///
+    /// - The hash of an edge is _H_(`"edge"` ‖ identity of the source ‖ identity of the sink ‖
+    ///   attributes), where graph node values are encoded by their local hash.
+    ///
+    /// - The hash of the graph is _H_(`"graph"` ‖ number of nodes ‖ node identities ‖ number of
+    ///   edges ‖ edge hashes), where the node identities and edge hashes are each sorted bytewise.
+    pub fn canonical_hash(&self) -> [u8; 32] {
+        let mut owners = HashMap::<GraphNodeRef, Vec<Vec<u8>>>::new();
+        for (syntax_node, owned) in &self.owned_graph_nodes {
+            let syntax_node = &self.syntax_nodes[syntax_node];
+            for (tag, graph_node) in owned {
+                let mut encoder = Encoder::default();
+                encoder.string(syntax_node.kind());
+                encoder.integer(syntax_node.start_byte() as u64);
+                encoder.integer(syntax_node.end_byte() as u64);
+                encoder.string(&tag.to_string());
+                owners.entry(*graph_node).or_default().push(encoder.0);
+            }
+        }
+
+        // Both are indexed by graph node index, and left empty for removed graph nodes.
+        let mut local_hashes = vec![Digest::default(); self.node_count()];
+        for node in self.iter_nodes() {
+            let mut encoder = Encoder::default();
+            encoder.bytes(b"node");
+            let mut node_owners = owners.remove(&node).unwrap_or_default();
+            node_owners.sort();
+            encoder.integer(node_owners.len() as u64);
+            node_owners.iter().for_each(|owner| encoder.bytes(owner));
+            encoder.attributes(self, &self[node].attributes, None);
+            local_hashes[node.index()] = sha256(&encoder.0);
+        }
+        let mut node_identities = vec![Digest::default(); self.node_count()];
+        for node in self.iter_nodes() {
+            let mut encoder = Encoder::default();
+            encoder.bytes(&local_hashes[node.index()]);
+            encoder.attributes(self, &self[node].attributes, Some(&local_hashes));
+            node_identities[node.index()] = sha256(&encoder.0);
+        }
+
+        let mut identities = self
+            .iter_nodes()
+            .map(|node| node_identities[node.index()])
+            .collect::<Vec<_>>();
+        let mut edges = Vec::new();
+        for node in self.iter_nodes() {
+            for (sink, edge) in self[node].iter_edges() {
+                let mut encoder = Encoder::default();
+                encoder.bytes(b"edge");
+                encoder.bytes(&node_identities[node.index()]);
+                encoder.bytes(&node_identities[sink.index()]);
+                encoder.attributes(self, &edge.attributes, Some(&local_hashes));
+                edges.push(sha256(&encoder.0));
+            }
+        }
+
+        identities.sort();
+        edges.sort();
+        let mut encoder = Encoder::default();
+        encoder.bytes(b"graph");
+        encoder.integer(identities.len() as u64);
+        identities
+            .iter()
+            .for_each(|identity| encoder.bytes(identity));
+        encoder.integer(edges.len() as u64);
+        edges.iter().for_each(|edge| encoder.bytes(edge));
+        sha256(&encoder.0)
+    }
+}
+
+/// Builds the canonical encoding of the parts of a graph
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn integer(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.integer(value.len() as u64);
+        self.bytes(value.as_bytes());
+    }
+
+    fn attributes(&mut self, graph: &Graph, attributes: &Attributes, nodes: Option<&[Digest]>) {
+        let mut attributes = attributes
+            .iter()
+            .filter(|(name, _)| !attributes.is_hidden(name))
+            .collect::<Vec<_>>();
+        attributes.sort_by_key(|(name, _)| *name);
+        self.integer(attributes.len() as u64);
+        for (name, value) in attributes {
+            self.string(name.as_str());
+            self.value(graph, value, nodes);
+        }
+    }
+
+    fn value(&mut self, graph: &Graph, value: &Value, nodes: Option<&[Digest]>) {
+        match value {
+            Value::Null => self.bytes(&[0]),
+            Value::Boolean(value) => self.bytes(&[1, *value as u8]),
+            Value::Integer(value) => {
+                self.bytes(&[2]);
+                self.integer(*value as u64);
+            }
+            Value::Float(value) => {
+                self.bytes(&[9]);
+                self.integer(value.get().to_bits());
+            }
+            Value::String(value) => {
+                self.bytes(&[3]);
+                self.string(value);
+            }
+            Value::List(values) => {
+                self.bytes(&[4]);
+                self.integer(values.len() as u64);
+                values
+                    .iter()
+                    .for_each(|value| self.value(graph, value, nodes));
+            }
+            Value::Set(values) => {
+                // The order of the elements of a set depends on graph node indices, so sort them
+                // by their encoding instead.
+                let mut elements = values
+                    .iter()
+                    .map(|value| {
+                        let mut encoder = Encoder::default();
+                        encoder.value(graph, value, nodes);
+                        encoder.0
+                    })
+                    .collect::<Vec<_>>();
+                elements.sort();
+                self.bytes(&[5]);
+                self.integer(elements.len() as u64);
+                elements.iter().for_each(|element| self.bytes(element));
+            }
+            Value::Map(values) => {
+                self.bytes(&[10]);
+                self.integer(values.len() as u64);
+                for (key, value) in values {
+                    self.string(key.as_str());
+                    self.value(graph, value, nodes);
+                }
+            }
+            Value::Location(location) => {
+                self.bytes(&[6]);
+                self.integer(location.start_byte as u64);
+                self.integer(location.end_byte as u64);
+                self.integer(location.start_point.row as u64);
+                self.integer(location.start_point.column as u64);
+                self.integer(location.end_point.row as u64);
+                self.integer(location.end_point.column as u64);
+            }
+            Value::SyntaxNode(node) => {
+                self.bytes(&[7]);
+                self.syntax_node(graph, *node);
+            }
+            Value::GraphNode(node) => {
+                self.bytes(&[8]);
+                if let Some(nodes) = nodes {
+                    self.bytes(&nodes[node.index()]);
+                }
+            }
+        }
+    }
+
+    fn syntax_node(&mut self, graph: &Graph, node: SyntaxNodeRef) {
+        let node = &graph[node];
+        self.string(node.kind());
+        self.integer(node.start_byte() as u64);
+        self.integer(node.end_byte() as u64);
+    }
+}
+
+/// Computes the SHA-256 digest of some data.
+pub(super) fn sha256(data: &[u8]) -> Digest {
+    Sha256::digest(data).into()
+}
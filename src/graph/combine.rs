@@ -0,0 +1,152 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Combining the graphs of several source files into one graph
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::execution::error::ExecutionError;
+use crate::Identifier;
+
+use super::Graph;
+use super::Value;
+
+/// The name of the attribute that records the source file of every graph node in a
+/// [`CombinedGraph`][], unless another name is given.
+pub const DEFAULT_SOURCE_PATH_ATTRIBUTE: &str = "source_path";
+
+/// A graph that combines the graphs of several source files, built with [`Graph::merge`][].
+/// Every graph node has an attribute with the path of the file it was built from, and the graph
+/// nodes of every file occupy a contiguous range of node indices, which is recorded in
+/// [`files`][Self::files].  Files whose graphs could not be built are left out of the graph, and
+/// recorded in [`failures`][Self::failures] instead.
+///
+/// Syntax nodes are identified by their ID, which is only unique among the trees that are alive
+/// at the same time, so the trees of all combined files must be kept alive until the combined
+/// graph is no longer needed.  This is enforced by the `'tree` lifetime.
+pub struct CombinedGraph<'tree> {
+    graph: Graph<'tree>,
+    path_attribute: Identifier,
+    files: Vec<CombinedFile>,
+    failures: Vec<CombinedFailure>,
+}
+
+/// A file whose graph is part of a [`CombinedGraph`][]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CombinedFile {
+    /// The path of the file
+    pub path: PathBuf,
+    /// The indices of the graph nodes that were built from the file
+    pub nodes: Range<usize>,
+}
+
+/// A file that was left out of a [`CombinedGraph`][], because its graph could not be built
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CombinedFailure {
+    /// The path of the file
+    pub path: PathBuf,
+    /// The error that prevented the graph of the file from being built
+    pub error: String,
+}
+
+impl<'tree> CombinedGraph<'tree> {
+    /// Creates an empty combined graph, which records the source files of graph nodes in the
+    /// [`source_path`][DEFAULT_SOURCE_PATH_ATTRIBUTE] attribute.
+    pub fn new() -> Self {
+        Self::with_path_attribute(Identifier::from(DEFAULT_SOURCE_PATH_ATTRIBUTE))
+    }
+
+    /// Creates an empty combined graph, which records the source files of graph nodes in the
+    /// given attribute.
+    pub fn with_path_attribute(path_attribute: Identifier) -> Self {
+        Self {
+            graph: Graph::new(),
+            path_attribute,
+            files: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Adds the graph of a file to the combined graph.  The path of the file is added to every
+    /// graph node of its graph, as a string.  If a graph node already has a different value for
+    /// the path attribute, or the graph cannot be [merged][Graph::merge], the file is recorded as
+    /// a failure instead, and the error is returned.
+    pub fn add_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mut graph: Graph<'tree>,
+    ) -> Result<&CombinedFile, ExecutionError> {
+        let path = path.as_ref();
+        let value = Value::String(path.display().to_string());
+        for node in &mut graph.graph_nodes {
+            let added = node
+                .attributes
+                .add(self.path_attribute.clone(), value.clone());
+            if added.is_err() {
+                let error = ExecutionError::DuplicateAttribute(format!(
+                    "{} on graph node built from {}",
+                    self.path_attribute,
+                    path.display()
+                ));
+                self.add_failure(path, &error);
+                return Err(error);
+            }
+        }
+        let start = self.graph.graph_nodes.len();
+        if let Err(error) = self.graph.merge(graph) {
+            self.add_failure(path, &error);
+            return Err(error);
+        }
+        self.files.push(CombinedFile {
+            path: path.to_path_buf(),
+            nodes: start..self.graph.graph_nodes.len(),
+        });
+        Ok(self.files.last().unwrap())
+    }
+
+    /// Records a file whose graph could not be built.
+    pub fn add_failure<P: AsRef<Path>, E: fmt::Display>(&mut self, path: P, error: E) {
+        self.failures.push(CombinedFailure {
+            path: path.as_ref().to_path_buf(),
+            error: error.to_string(),
+        });
+    }
+
+    /// Returns the combined graph.
+    pub fn graph(&self) -> &Graph<'tree> {
+        &self.graph
+    }
+
+    /// Returns the combined graph, discarding the file boundaries and failures.
+    pub fn into_graph(self) -> Graph<'tree> {
+        self.graph
+    }
+
+    /// Returns the name of the attribute that records the source files of graph nodes.
+    pub fn path_attribute(&self) -> &Identifier {
+        &self.path_attribute
+    }
+
+    /// Returns the files whose graphs were combined, in the order in which they were added.
+    pub fn files(&self) -> &[CombinedFile] {
+        &self.files
+    }
+
+    /// Returns the files that were left out, in the order in which they were recorded.
+    pub fn failures(&self) -> &[CombinedFailure] {
+        &self.failures
+    }
+}
+
+impl Default for CombinedGraph<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,670 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Deserialization of graphs from the JSON they are serialized to, available with the `serde`
+//! feature
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use serde::de;
+use serde::de::DeserializeSeed;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::Identifier;
+
+use super::Attributes;
+use super::Float;
+use super::Graph;
+use super::GraphNode;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::SourceLocation;
+use super::SyntaxNodeRef;
+use super::Value;
+
+/// Deserializes a graph from the format that graphs are serialized to, such as the output of
+/// [`Graph::display_json`][].  The deserialized graph is not tied to a syntax tree, so it can be
+/// given any lifetime, including `'static`.
+///
+/// Graph nodes keep the IDs they were serialized with, so that graph node references in
+/// attribute values remain valid.  The IDs of graph nodes that were removed before the graph was
+/// serialized are filled in with removed graph nodes.  At most the number of graph nodes plus
+/// [`MAX_MISSING_GRAPH_NODE_IDS`][] IDs can be missing, which keeps a small document from
+/// allocating a huge graph.
+///
+/// Syntax node values are restored from the kind and positions recorded in them.  Values that
+/// are serialized with their spans, using [`SyntaxNodeRendering::Full`][full] or
+/// [`SerializationConfig::include_spans`][include_spans], record all of them, and are restored exactly;
+/// otherwise, missing kinds are empty, and missing positions are zero.  Syntax nodes rendered as
+/// spans or source text cannot be restored, and are rejected.  The syntax nodes themselves, and
+/// which syntax nodes own which graph nodes, are not part of the deserialized graph, so the
+/// `span` entries of graph nodes are ignored, and [`Graph::check_integrity`][] reports every
+/// syntax node value.
+///
+/// Unknown entries are rejected, with an error that names the graph node that contains them.
+///
+/// [full]: super::SyntaxNodeRendering::Full
+/// [include_spans]: super::SerializationConfig::include_spans
+impl<'de, 'tree> Deserialize<'de> for Graph<'tree> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(GraphVisitor(PhantomData))
+    }
+}
+
+/// The number of graph node IDs, beyond the number of graph nodes, that can be missing from a
+/// deserialized graph
+pub const MAX_MISSING_GRAPH_NODE_IDS: usize = 1 << 16;
+
+struct GraphVisitor<'tree>(PhantomData<Graph<'tree>>);
+
+impl<'de, 'tree> Visitor<'de> for GraphVisitor<'tree> {
+    type Value = Graph<'tree>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of graph nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut nodes = Vec::new();
+        while let Some(node) = seq.next_element_seed(GraphNodeSeed(nodes.len()))? {
+            nodes.push(node);
+        }
+        let node_count = nodes
+            .iter()
+            .map(|node: &DeserializedGraphNode| node.id as usize + 1)
+            .max()
+            .unwrap_or(0);
+        if node_count > 2 * nodes.len() + MAX_MISSING_GRAPH_NODE_IDS {
+            return Err(de::Error::custom(format!(
+                "too many missing graph node IDs: {} graph nodes have IDs up to {}",
+                nodes.len(),
+                node_count - 1
+            )));
+        }
+
+        let mut graph = Graph::new();
+        graph.graph_nodes = (0..node_count)
+            .map(|_| GraphNode {
+                removed: true,
+                ..GraphNode::new()
+            })
+            .collect();
+        for node in nodes {
+            let slot = &mut graph.graph_nodes[node.id as usize];
+            if !slot.removed {
+                return Err(de::Error::custom(format!(
+                    "duplicate graph node {}",
+                    node.id
+                )));
+            }
+            *slot = node.node;
+        }
+        for (index, node) in graph.graph_nodes.iter().enumerate() {
+            let context = NodeContext {
+                id: Some(index as GraphNodeID),
+                position: index,
+            };
+            for (sink, edge) in &node.outgoing_edges {
+                match graph.graph_nodes.get(*sink as usize) {
+                    Some(sink_node) if !sink_node.removed => {}
+                    _ => {
+                        return Err(de::Error::custom(format!(
+                            "{}: edge to unknown graph node {}",
+                            context, sink
+                        )))
+                    }
+                }
+                check_graph_node_values(&edge.attributes, node_count, &context)?;
+            }
+            check_graph_node_values(&node.attributes, node_count, &context)?;
+        }
+        Ok(graph)
+    }
+}
+
+/// Checks that the graph node values of attributes refer to graph nodes of the graph.
+fn check_graph_node_values<E: de::Error>(
+    attributes: &Attributes,
+    node_count: usize,
+    context: &NodeContext,
+) -> Result<(), E> {
+    fn check(value: &Value, node_count: usize) -> Result<(), GraphNodeID> {
+        match value {
+            Value::GraphNode(node) if node.index() >= node_count => Err(node.0),
+            Value::List(values) => values.iter().try_for_each(|value| check(value, node_count)),
+            Value::Set(values) => values.iter().try_for_each(|value| check(value, node_count)),
+            Value::Map(values) => values
+                .values()
+                .try_for_each(|value| check(value, node_count)),
+            _ => Ok(()),
+        }
+    }
+    for (name, value) in attributes.iter() {
+        check(value, node_count).map_err(|node| {
+            de::Error::custom(format!(
+                "{}: attribute {} refers to unknown graph node {}",
+                context, name, node
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Identifies a graph node in error messages: by its ID, if it has been read, and by its
+/// position in the list of graph nodes otherwise
+struct NodeContext {
+    id: Option<GraphNodeID>,
+    position: usize,
+}
+
+impl fmt::Display for NodeContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "graph node {}", id),
+            None => write!(f, "graph node at position {}", self.position),
+        }
+    }
+}
+
+struct DeserializedGraphNode {
+    id: GraphNodeID,
+    node: GraphNode,
+}
+
+/// Deserializes the graph node at a position in the list of graph nodes
+struct GraphNodeSeed(usize);
+
+impl<'de> DeserializeSeed<'de> for GraphNodeSeed {
+    type Value = DeserializedGraphNode;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for GraphNodeSeed {
+    type Value = DeserializedGraphNode;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a graph node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        const FIELDS: &[&str] = &["id", "edges", "attrs", "hidden", "span"];
+        let mut context = NodeContext {
+            id: None,
+            position: self.0,
+        };
+        let mut edges: Option<Vec<DeserializedEdge>> = None;
+        let mut attributes: Option<Attributes> = None;
+        let mut hidden: Option<Vec<String>> = None;
+        let mut span = false;
+        let in_context = |context: &NodeContext, err: A::Error| {
+            de::Error::custom(format!("{}: {}", context, err))
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "id" if context.id.is_none() => {
+                    context.id = Some(map.next_value().map_err(|e| in_context(&context, e))?);
+                }
+                "edges" if edges.is_none() => {
+                    edges = Some(map.next_value().map_err(|e| in_context(&context, e))?);
+                }
+                "attrs" if attributes.is_none() => {
+                    attributes = Some(map.next_value().map_err(|e| in_context(&context, e))?);
+                }
+                "hidden" if hidden.is_none() => {
+                    hidden = Some(map.next_value().map_err(|e| in_context(&context, e))?);
+                }
+                // the span of the owner of the graph node, which is not part of the graph
+                "span" if !span => {
+                    map.next_value::<Option<DeserializedSpan>>()
+                        .map_err(|e| in_context(&context, e))?;
+                    span = true;
+                }
+                "id" | "edges" | "attrs" | "hidden" | "span" => {
+                    return Err(in_context(
+                        &context,
+                        de::Error::custom(format!("duplicate field `{}`", key)),
+                    ));
+                }
+                _ => {
+                    return Err(in_context(&context, de::Error::unknown_field(&key, FIELDS)));
+                }
+            }
+        }
+        let id = match context.id {
+            Some(id) => id,
+            None => return Err(in_context(&context, de::Error::missing_field("id"))),
+        };
+        let mut node = GraphNode::new();
+        node.attributes = attributes.unwrap_or_else(Attributes::new);
+        node.attributes
+            .hide(hidden.unwrap_or_default())
+            .map_err(|e| in_context(&context, e))?;
+        for edge in edges.unwrap_or_default() {
+            match node.add_edge(GraphNodeRef(edge.sink)) {
+                Ok(added) => added.attributes = edge.attributes,
+                Err(_) => {
+                    return Err(in_context(
+                        &context,
+                        de::Error::custom(format!("duplicate edge to graph node {}", edge.sink)),
+                    ));
+                }
+            }
+        }
+        Ok(DeserializedGraphNode { id, node })
+    }
+}
+
+struct DeserializedEdge {
+    sink: GraphNodeID,
+    attributes: Attributes,
+}
+
+impl<'de> Deserialize<'de> for DeserializedEdge {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EdgeVisitor;
+
+        impl<'de> Visitor<'de> for EdgeVisitor {
+            type Value = DeserializedEdge;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an edge")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut sink = None;
+                let mut attributes = None;
+                let mut hidden = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "sink" => set_field(&mut sink, map.next_value()?, "sink")?,
+                        "attrs" => set_field(&mut attributes, map.next_value()?, "attrs")?,
+                        "hidden" => set_field(&mut hidden, map.next_value()?, "hidden")?,
+                        _ => {
+                            return Err(de::Error::unknown_field(
+                                &key,
+                                &["sink", "attrs", "hidden"],
+                            ))
+                        }
+                    }
+                }
+                let mut attributes = attributes.unwrap_or_else(Attributes::new);
+                attributes.hide(hidden.unwrap_or_default())?;
+                Ok(DeserializedEdge {
+                    sink: sink.ok_or_else(|| de::Error::missing_field("sink"))?,
+                    attributes,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(EdgeVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Attributes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AttributesVisitor;
+
+        impl<'de> Visitor<'de> for AttributesVisitor {
+            type Value = Attributes;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map of attributes")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut attributes = Attributes::new();
+                while let Some(name) = map.next_key::<String>()? {
+                    let value = map
+                        .next_value::<Value>()
+                        .map_err(|err| de::Error::custom(format!("attribute {}: {}", name, err)))?;
+                    let name = Identifier::from(name.as_str());
+                    if attributes.values.insert(name.clone(), value).is_some() {
+                        return Err(de::Error::custom(format!("duplicate attribute {}", name)));
+                    }
+                }
+                Ok(attributes)
+            }
+        }
+
+        deserializer.deserialize_map(AttributesVisitor)
+    }
+}
+
+impl Attributes {
+    /// Marks the attributes with the given names as hidden, failing if one of them does not
+    /// exist.
+    fn hide<E: de::Error>(&mut self, names: Vec<String>) -> Result<(), E> {
+        for name in names {
+            let name = Identifier::from(name.as_str());
+            if !self.values.contains_key(&name) {
+                return Err(E::custom(format!(
+                    "hidden attribute {} does not exist",
+                    name
+                )));
+            }
+            if !name.starts_with('_') {
+                self.hidden.insert(name);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        const FIELDS: &[&str] = &[
+            "type", "bool", "int", "float", "string", "values", "entries", "start", "end", "id",
+            "row", "column", "span", "text",
+        ];
+        let mut fields = Vec::new();
+        let mut type_ = None::<String>;
+        let mut bool = None;
+        let mut int = None;
+        let mut float = None::<f64>;
+        let mut string = None::<String>;
+        let mut values = None::<Vec<Value>>;
+        let mut entries = None::<BTreeMap<String, Value>>;
+        let mut start = None::<DeserializedPosition>;
+        let mut end = None::<DeserializedPosition>;
+        let mut id = None::<u32>;
+        let mut row = None::<usize>;
+        let mut column = None::<usize>;
+        let mut span = None::<DeserializedSpan>;
+        while let Some(key) = map.next_key::<String>()? {
+            let field = match FIELDS.iter().find(|field| **field == key) {
+                Some(field) => *field,
+                None => return Err(de::Error::unknown_field(&key, FIELDS)),
+            };
+            if fields.contains(&field) {
+                return Err(de::Error::duplicate_field(field));
+            }
+            fields.push(field);
+            match field {
+                "type" => type_ = Some(map.next_value()?),
+                "bool" => bool = Some(map.next_value()?),
+                "int" => int = Some(map.next_value()?),
+                "float" => float = Some(map.next_value()?),
+                "string" => string = Some(map.next_value()?),
+                "values" => values = Some(map.next_value()?),
+                "entries" => entries = Some(map.next_value()?),
+                "start" => start = Some(map.next_value()?),
+                "end" => end = Some(map.next_value()?),
+                "id" => id = Some(map.next_value()?),
+                "row" => row = Some(map.next_value()?),
+                "column" => column = Some(map.next_value()?),
+                "span" => span = Some(map.next_value()?),
+                // the source text of a syntax node, which is not part of the graph
+                _ => {
+                    map.next_value::<String>()?;
+                }
+            }
+        }
+
+        let type_ = type_.ok_or_else(|| de::Error::missing_field("type"))?;
+        let allowed: &[&str] = match type_.as_str() {
+            "null" => &[],
+            "bool" => &["bool"],
+            "int" => &["int"],
+            "float" => &["float"],
+            "string" => &["string"],
+            "list" | "set" => &["values"],
+            "map" => &["entries"],
+            "location" => &["start", "end"],
+            "syntaxNode" => &["id", "row", "column", "span", "text"],
+            "graphNode" => &["id"],
+            _ => {
+                return Err(de::Error::unknown_variant(
+                    &type_,
+                    &[
+                        "null",
+                        "bool",
+                        "int",
+                        "float",
+                        "string",
+                        "list",
+                        "set",
+                        "map",
+                        "location",
+                        "syntaxNode",
+                        "graphNode",
+                    ],
+                ))
+            }
+        };
+        if let Some(field) = fields
+            .iter()
+            .find(|field| **field != "type" && !allowed.contains(field))
+        {
+            return Err(de::Error::custom(format!(
+                "unexpected field `{}` for type {}",
+                field, type_
+            )));
+        }
+
+        Ok(match type_.as_str() {
+            "null" => Value::Null,
+            "bool" => Value::Boolean(bool.ok_or_else(|| de::Error::missing_field("bool"))?),
+            "int" => Value::Integer(int.ok_or_else(|| de::Error::missing_field("int"))?),
+            "float" => {
+                let float = float.ok_or_else(|| de::Error::missing_field("float"))?;
+                Value::Float(
+                    Float::new(float).ok_or_else(|| de::Error::custom("floats must be finite"))?,
+                )
+            }
+            "string" => Value::String(string.ok_or_else(|| de::Error::missing_field("string"))?),
+            "list" => Value::List(values.ok_or_else(|| de::Error::missing_field("values"))?),
+            "set" => Value::Set(
+                values
+                    .ok_or_else(|| de::Error::missing_field("values"))?
+                    .into_iter()
+                    .collect::<BTreeSet<_>>(),
+            ),
+            "map" => Value::Map(
+                entries
+                    .ok_or_else(|| de::Error::missing_field("entries"))?
+                    .into_iter()
+                    .map(|(key, value)| (Identifier::from(key.as_str()), value))
+                    .collect(),
+            ),
+            "location" => {
+                let start = start.ok_or_else(|| de::Error::missing_field("start"))?;
+                let end = end.ok_or_else(|| de::Error::missing_field("end"))?;
+                Value::Location(SourceLocation {
+                    start_byte: start.byte,
+                    end_byte: end.byte,
+                    start_point: start.point(),
+                    end_point: end.point(),
+                })
+            }
+            "syntaxNode" => {
+                let id = id.ok_or_else(|| de::Error::missing_field("id"))?;
+                let position = tree_sitter::Point {
+                    row: row.unwrap_or_default(),
+                    column: column.unwrap_or_default(),
+                };
+                Value::SyntaxNode(match span {
+                    Some(span) => SyntaxNodeRef {
+                        index: id,
+                        kind: intern_kind(&span.kind),
+                        named: span.named,
+                        position: span.start.point(),
+                        end_position: span.end.point(),
+                        start_byte: span.start.byte,
+                        end_byte: span.end.byte,
+                    },
+                    None => SyntaxNodeRef {
+                        index: id,
+                        kind: "",
+                        named: true,
+                        position,
+                        end_position: position,
+                        start_byte: 0,
+                        end_byte: 0,
+                    },
+                })
+            }
+            _ => Value::GraphNode(GraphNodeRef(
+                id.ok_or_else(|| de::Error::missing_field("id"))?,
+            )),
+        })
+    }
+}
+
+/// A position, as serialized in locations and spans
+struct DeserializedPosition {
+    byte: usize,
+    row: usize,
+    column: usize,
+}
+
+impl DeserializedPosition {
+    fn point(&self) -> tree_sitter::Point {
+        tree_sitter::Point {
+            row: self.row,
+            column: self.column,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DeserializedPosition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PositionVisitor;
+
+        impl<'de> Visitor<'de> for PositionVisitor {
+            type Value = DeserializedPosition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a position")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                const FIELDS: &[&str] = &["byte", "row", "column"];
+                let mut position = [None; 3];
+                while let Some(key) = map.next_key::<String>()? {
+                    let index = FIELDS
+                        .iter()
+                        .position(|field| *field == key)
+                        .ok_or_else(|| de::Error::unknown_field(&key, FIELDS))?;
+                    set_field(&mut position[index], map.next_value()?, FIELDS[index])?;
+                }
+                let [byte, row, column] = position;
+                Ok(DeserializedPosition {
+                    byte: byte.ok_or_else(|| de::Error::missing_field("byte"))?,
+                    row: row.ok_or_else(|| de::Error::missing_field("row"))?,
+                    column: column.ok_or_else(|| de::Error::missing_field("column"))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(PositionVisitor)
+    }
+}
+
+/// The span of a syntax node, as serialized in syntax node values and graph nodes
+struct DeserializedSpan {
+    kind: String,
+    named: bool,
+    start: DeserializedPosition,
+    end: DeserializedPosition,
+}
+
+impl<'de> Deserialize<'de> for DeserializedSpan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpanVisitor;
+
+        impl<'de> Visitor<'de> for SpanVisitor {
+            type Value = DeserializedSpan;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a span")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut kind = None;
+                let mut named = None;
+                let mut start = None;
+                let mut end = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "kind" => set_field(&mut kind, map.next_value()?, "kind")?,
+                        "named" => set_field(&mut named, map.next_value()?, "named")?,
+                        "start" => set_field(&mut start, map.next_value()?, "start")?,
+                        "end" => set_field(&mut end, map.next_value()?, "end")?,
+                        _ => {
+                            return Err(de::Error::unknown_field(
+                                &key,
+                                &["kind", "named", "start", "end"],
+                            ))
+                        }
+                    }
+                }
+                Ok(DeserializedSpan {
+                    kind: kind.ok_or_else(|| de::Error::missing_field("kind"))?,
+                    // only anonymous syntax nodes have a `named` entry
+                    named: named.unwrap_or(true),
+                    start: start.ok_or_else(|| de::Error::missing_field("start"))?,
+                    end: end.ok_or_else(|| de::Error::missing_field("end"))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(SpanVisitor)
+    }
+}
+
+/// Stores the value of a field, which must not have been seen before.
+fn set_field<T, E: de::Error>(
+    slot: &mut Option<T>,
+    value: T,
+    field: &'static str,
+) -> Result<(), E> {
+    if slot.is_some() {
+        return Err(de::Error::duplicate_field(field));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Returns a static copy of the kind of a deserialized syntax node.  Each distinct kind is
+/// allocated once and never freed; there are only as many kinds as the grammar defines.
+fn intern_kind(kind: &str) -> &'static str {
+    static KINDS: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+    let mut kinds = KINDS.lock().unwrap_or_else(|err| err.into_inner());
+    let kinds = kinds.get_or_insert_with(HashSet::new);
+    if let Some(kind) = kinds.get(kind) {
+        return kind;
+    }
+    let kind: &'static str = Box::leak(kind.to_string().into_boxed_str());
+    kinds.insert(kind);
+    kind
+}
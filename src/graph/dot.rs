@@ -0,0 +1,112 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Rendering graphs in the DOT language of Graphviz
+
+use std::fmt;
+
+use crate::Identifier;
+
+use super::Graph;
+use super::GraphNodeRef;
+use super::GraphVisitor;
+use super::Value;
+
+impl<'tree> Graph<'tree> {
+    /// Renders this graph in the [DOT language][dot] of Graphviz, as a directed graph.  Every
+    /// graph node is labeled with its index and its attributes, and every edge with its
+    /// attributes, in the same form as [`pretty_print`][Self::pretty_print] uses.  Hidden
+    /// attributes are left out.
+    ///
+    /// [dot]: https://graphviz.org/doc/info/lang.html
+    pub fn display_dot<'a>(&'a self) -> impl fmt::Display + 'a {
+        struct DisplayDot<'a, 'tree>(&'a Graph<'tree>);
+
+        impl fmt::Display for DisplayDot<'_, '_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                writeln!(f, "digraph {{")?;
+                self.0.visit(&mut DotPrinter { f, node: None })?;
+                writeln!(f, "}}")
+            }
+        }
+
+        DisplayDot(self)
+    }
+}
+
+/// Prints the graph nodes and edges of a graph as DOT statements as they are visited.  The label
+/// of a graph node is only complete once all of its attributes have been visited, so it is
+/// written when its first edge, or the next graph node, is visited.
+struct DotPrinter<'f, 'g> {
+    f: &'f mut fmt::Formatter<'g>,
+    node: Option<(GraphNodeRef, String)>,
+}
+
+impl DotPrinter<'_, '_> {
+    fn end_label(&mut self) -> fmt::Result {
+        if let Some((node, label)) = &mut self.node {
+            if !label.is_empty() {
+                writeln!(self.f, "  {} [label=\"{}\"];", node.index(), escape(label))?;
+                label.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> GraphVisitor<'a> for DotPrinter<'_, '_> {
+    type Error = fmt::Error;
+
+    fn begin_node(&mut self, node: GraphNodeRef) -> fmt::Result {
+        self.node = Some((node, format!("node {}", node.index())));
+        Ok(())
+    }
+
+    fn attribute(&mut self, name: &'a Identifier, value: &'a Value) -> fmt::Result {
+        if let Some((_, label)) = &mut self.node {
+            label.push_str(&format!("\n{}: {:?}", name, value));
+        }
+        Ok(())
+    }
+
+    fn edge(
+        &mut self,
+        sink: GraphNodeRef,
+        attributes: &[(&'a Identifier, &'a Value)],
+    ) -> fmt::Result {
+        self.end_label()?;
+        let (source, _) = self.node.as_ref().expect("edge outside of a graph node");
+        write!(self.f, "  {} -> {}", source.index(), sink.index())?;
+        if !attributes.is_empty() {
+            let label = attributes
+                .iter()
+                .map(|(name, value)| format!("{}: {:?}", name, value))
+                .collect::<Vec<_>>()
+                .join("\n");
+            write!(self.f, " [label=\"{}\"]", escape(&label))?;
+        }
+        writeln!(self.f, ";")
+    }
+
+    fn end_node(&mut self, _node: GraphNodeRef) -> fmt::Result {
+        self.end_label()
+    }
+}
+
+/// Escapes a string for use in a quoted DOT string.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,385 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Validation of the kinds of the graph nodes that edges connect
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use thiserror::Error;
+
+use crate::execution::hook::Decision;
+use crate::execution::hook::GraphMutationHook;
+use crate::execution::hook::MutationContext;
+use crate::execution::hook::MutationTarget;
+use crate::Identifier;
+use crate::Location;
+
+use super::Graph;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::SyntaxNodeRef;
+use super::Value;
+
+/// A schema that constrains the kinds of the graph nodes that edges connect.  The kind of a graph
+/// node or edge is the value of its kind attribute, which is `kind` by default.  A rule for an
+/// edge kind lists the kinds that the source and the sink of edges of that kind may have.  Edges
+/// without a kind, or with a kind that has no rule, are not constrained.
+///
+/// A schema can be checked against a graph after execution using
+/// [`Graph::validate_edge_types`][], or during execution by installing the hook returned by
+/// [`as_hook`][Self::as_hook].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EdgeTypeSchema {
+    kind_attr: Identifier,
+    rules: BTreeMap<String, EdgeTypeRule>,
+}
+
+impl EdgeTypeSchema {
+    /// Creates a new schema without rules, which uses the `kind` attribute.
+    pub fn new() -> Self {
+        Self {
+            kind_attr: Identifier::from("kind"),
+            rules: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the attribute that holds the kinds of graph nodes and edges.
+    pub fn kind_attr(&self) -> &Identifier {
+        &self.kind_attr
+    }
+
+    /// Sets the attribute that holds the kinds of graph nodes and edges.
+    pub fn set_kind_attr(&mut self, kind_attr: Identifier) {
+        self.kind_attr = kind_attr;
+    }
+
+    /// Adds a rule that edges of the given kind must go from a graph node with one of the source
+    /// kinds to a graph node with one of the sink kinds, replacing any earlier rule for that edge
+    /// kind.
+    pub fn add_rule<S, I, J>(&mut self, edge_kind: S, sources: I, sinks: J)
+    where
+        S: Into<String>,
+        I: IntoIterator,
+        I::Item: Into<String>,
+        J: IntoIterator,
+        J::Item: Into<String>,
+    {
+        self.rules.insert(
+            edge_kind.into(),
+            EdgeTypeRule {
+                sources: sources.into_iter().map(Into::into).collect(),
+                sinks: sinks.into_iter().map(Into::into).collect(),
+            },
+        );
+    }
+
+    /// Returns the rule for edges of the given kind, if there is one.
+    pub fn rule(&self, edge_kind: &str) -> Option<&EdgeTypeRule> {
+        self.rules.get(edge_kind)
+    }
+
+    /// Returns the rules of this schema, ordered by edge kind.
+    pub fn rules(&self) -> impl Iterator<Item = (&str, &EdgeTypeRule)> {
+        self.rules
+            .iter()
+            .map(|(edge_kind, rule)| (edge_kind.as_str(), rule))
+    }
+
+    /// Parses a schema from JSON.  The JSON is an object whose `edges` entry maps edge kinds to
+    /// objects with `sources` and `sinks` entries, each a list of graph node kinds.  An optional
+    /// `kind_attr` entry names the attribute that holds the kinds:
+    ///
+    /// ``` json
+    /// {
+    ///   "kind_attr": "kind",
+    ///   "edges": {
+    ///     "defines": { "sources": ["scope"], "sinks": ["definition"] }
+    ///   }
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, EdgeTypeSchemaError> {
+        let json = serde_json::from_str::<serde_json::Value>(json)?;
+        let json = json
+            .as_object()
+            .ok_or_else(|| EdgeTypeSchemaError::Invalid("expected an object".to_string()))?;
+        let mut schema = EdgeTypeSchema::new();
+        for (key, value) in json {
+            match key.as_str() {
+                "kind_attr" => {
+                    let kind_attr = value.as_str().ok_or_else(|| {
+                        EdgeTypeSchemaError::Invalid("expected a string for kind_attr".to_string())
+                    })?;
+                    schema.set_kind_attr(Identifier::from(kind_attr));
+                }
+                "edges" => {
+                    let edges = value.as_object().ok_or_else(|| {
+                        EdgeTypeSchemaError::Invalid("expected an object for edges".to_string())
+                    })?;
+                    for (edge_kind, rule) in edges {
+                        let sources = json_kinds(rule, edge_kind, "sources")?;
+                        let sinks = json_kinds(rule, edge_kind, "sinks")?;
+                        schema.add_rule(edge_kind.as_str(), sources, sinks);
+                    }
+                }
+                _ => {
+                    return Err(EdgeTypeSchemaError::Invalid(format!(
+                        "unexpected entry {}",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Returns a mutation hook that rejects attributes that give an edge a kind whose rule the
+    /// kinds of the edge's source and sink violate.  Install it using
+    /// [`ExecutionConfig::mutation_hook`][crate::ExecutionConfig::mutation_hook].
+    ///
+    /// The hook can only check the kinds of graph nodes that have been set when the kind of the
+    /// edge is set.  Violations that involve graph nodes whose kind is set later, which is common
+    /// in lazy execution, are only found by [`Graph::validate_edge_types`][].  The owners of graph
+    /// nodes are only recorded at the end of execution, so the rejections of the hook do not
+    /// include the locations of the endpoints, but the error includes the location of the
+    /// statement that sets the kind of the edge.
+    pub fn as_hook(&self) -> EdgeTypeHook<'_> {
+        EdgeTypeHook { schema: self }
+    }
+
+    /// Checks an edge of the given kind against its rule, returning the violation if there is
+    /// one.  If `allow_unknown` is set, endpoints without a kind are not checked.
+    fn check_edge(
+        &self,
+        graph: &Graph,
+        source: GraphNodeRef,
+        sink: GraphNodeRef,
+        edge_kind: &str,
+        allow_unknown: bool,
+        owners: &mut Option<HashMap<GraphNodeID, SyntaxNodeRef>>,
+    ) -> Option<EdgeTypeViolation> {
+        let rule = self.rule(edge_kind)?;
+        let source_kind = graph[source].attributes.get(&self.kind_attr).map(kind_of);
+        let sink_kind = graph[sink].attributes.get(&self.kind_attr).map(kind_of);
+        let allows = |kinds: &BTreeSet<String>, kind: &Option<String>| match kind {
+            Some(kind) => kinds.contains(kind),
+            None => allow_unknown,
+        };
+        if allows(&rule.sources, &source_kind) && allows(&rule.sinks, &sink_kind) {
+            return None;
+        }
+        let owners = owners.get_or_insert_with(|| graph.graph_node_owners());
+        let endpoint = |node: GraphNodeRef, kind: Option<String>| EdgeEndpoint {
+            node,
+            kind,
+            location: owners.get(&node.0).map(|owner| owner.location()),
+        };
+        Some(EdgeTypeViolation {
+            edge_kind: edge_kind.to_string(),
+            source: endpoint(source, source_kind),
+            sink: endpoint(sink, sink_kind),
+            rule: rule.clone(),
+        })
+    }
+}
+
+impl Default for EdgeTypeSchema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the kinds of graph nodes listed in an entry of the rule for an edge kind.
+#[cfg(feature = "serde")]
+fn json_kinds<'a>(
+    rule: &'a serde_json::Value,
+    edge_kind: &str,
+    entry: &str,
+) -> Result<Vec<&'a str>, EdgeTypeSchemaError> {
+    let invalid = || {
+        EdgeTypeSchemaError::Invalid(format!(
+            "expected a list of strings for {} of edge kind {}",
+            entry, edge_kind
+        ))
+    };
+    rule.get(entry)
+        .and_then(|kinds| kinds.as_array())
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|kind| kind.as_str().ok_or_else(invalid))
+        .collect()
+}
+
+/// Returns the kind given by the value of a kind attribute.
+fn kind_of(value: &Value) -> String {
+    match value {
+        Value::String(kind) => kind.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// The kinds of graph nodes that the source and the sink of edges of some kind may have
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EdgeTypeRule {
+    /// The allowed kinds of the source graph node
+    pub sources: BTreeSet<String>,
+    /// The allowed kinds of the sink graph node
+    pub sinks: BTreeSet<String>,
+}
+
+impl fmt::Display for EdgeTypeRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds = |kinds: &BTreeSet<String>| {
+            kinds
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" or ")
+        };
+        write!(f, "from {} to {}", kinds(&self.sources), kinds(&self.sinks))
+    }
+}
+
+/// An edge whose source or sink has a kind that the rule for the edge's kind does not allow
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EdgeTypeViolation {
+    /// The kind of the edge
+    pub edge_kind: String,
+    /// The source of the edge
+    pub source: EdgeEndpoint,
+    /// The sink of the edge
+    pub sink: EdgeEndpoint,
+    /// The rule that the edge violates
+    pub rule: EdgeTypeRule,
+}
+
+impl EdgeTypeViolation {
+    /// Returns whether the kind of the source is allowed by the rule.
+    pub fn source_allowed(&self) -> bool {
+        self.source.is_one_of(&self.rule.sources)
+    }
+
+    /// Returns whether the kind of the sink is allowed by the rule.
+    pub fn sink_allowed(&self) -> bool {
+        self.sink.is_one_of(&self.rule.sinks)
+    }
+}
+
+impl fmt::Display for EdgeTypeViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} edge {} -> {} goes from {} to {}, but must go {}",
+            self.edge_kind,
+            self.source.node.index(),
+            self.sink.node.index(),
+            self.source,
+            self.sink,
+            self.rule
+        )
+    }
+}
+
+/// The source or sink of an edge that violates an [`EdgeTypeRule`][]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EdgeEndpoint {
+    /// The graph node
+    pub node: GraphNodeRef,
+    /// The kind of the graph node, or `None` if it does not have one
+    pub kind: Option<String>,
+    /// The location of the syntax node that owns the graph node, or `None` if it has no owner.
+    /// The smallest owner is used if there are several.
+    pub location: Option<Location>,
+}
+
+impl EdgeEndpoint {
+    fn is_one_of(&self, kinds: &BTreeSet<String>) -> bool {
+        matches!(&self.kind, Some(kind) if kinds.contains(kind))
+    }
+}
+
+impl fmt::Display for EdgeEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            Some(kind) => write!(f, "{} node {}", kind, self.node.index())?,
+            None => write!(f, "node {} without kind", self.node.index())?,
+        }
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+/// An error that can occur while parsing an [`EdgeTypeSchema`][] from JSON
+#[cfg(feature = "serde")]
+#[derive(Debug, Error)]
+pub enum EdgeTypeSchemaError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid edge type schema: {0}")]
+    Invalid(String),
+}
+
+/// A mutation hook that enforces an [`EdgeTypeSchema`][] during execution, created by
+/// [`EdgeTypeSchema::as_hook`][]
+pub struct EdgeTypeHook<'a> {
+    schema: &'a EdgeTypeSchema,
+}
+
+impl GraphMutationHook for EdgeTypeHook<'_> {
+    fn before_attr(
+        &self,
+        target: MutationTarget,
+        name: &Identifier,
+        value: &Value,
+        ctx: &MutationContext,
+    ) -> Decision {
+        let (source, sink) = match target {
+            MutationTarget::Edge(source, sink) if *name == self.schema.kind_attr => (source, sink),
+            _ => return Decision::Allow,
+        };
+        match self
+            .schema
+            .check_edge(ctx.graph(), source, sink, &kind_of(value), true, &mut None)
+        {
+            Some(violation) => Decision::Reject(violation.to_string()),
+            None => Decision::Allow,
+        }
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Checks the edges of this graph against a schema, returning the edges whose source or sink
+    /// has a kind that the rule for the edge's kind does not allow, ordered by source and sink.
+    /// Graph nodes without a kind violate every rule.
+    pub fn validate_edge_types(&self, schema: &EdgeTypeSchema) -> Vec<EdgeTypeViolation> {
+        let mut owners = None;
+        let mut violations = Vec::new();
+        for source in self.iter_nodes() {
+            for (sink, edge) in self[source].iter_edges() {
+                let edge_kind = match edge.attributes.get(&schema.kind_attr) {
+                    Some(edge_kind) => kind_of(edge_kind),
+                    None => continue,
+                };
+                violations.extend(schema.check_edge(
+                    self,
+                    source,
+                    sink,
+                    &edge_kind,
+                    false,
+                    &mut owners,
+                ));
+            }
+        }
+        violations
+    }
+}
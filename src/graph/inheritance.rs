@@ -0,0 +1,127 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Inheritance of attributes along edges
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::Identifier;
+
+use super::Graph;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::Value;
+
+/// A graph node that could inherit an attribute from several nearest ancestors, which have
+/// different values for it.  The node inherits the value of the first of these ancestors.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AmbiguousInheritance {
+    /// The graph node that inherited the attribute
+    pub node: GraphNodeRef,
+    /// The name of the inherited attribute
+    pub attribute: Identifier,
+    /// The nearest ancestors that have the attribute, in order of their graph node index, with
+    /// their values
+    pub ancestors: Vec<(GraphNodeRef, Value)>,
+}
+
+impl fmt::Display for AmbiguousInheritance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Ambiguous inherited attribute {} on {}:",
+            self.attribute, self.node
+        )?;
+        for (ancestor, value) in &self.ancestors {
+            write!(f, " {} has {:?}", ancestor, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Copies an attribute to every graph node that does not have it, from its nearest ancestor
+    /// that does.  The ancestors of a node are found by following incoming edges whose
+    /// `edge_attribute` has the value `edge_value`, such as containment edges.  Only values that
+    /// were set before this call are inherited, so the result does not depend on the order in
+    /// which nodes are visited.
+    ///
+    /// If a node has several nearest ancestors with different values, it inherits the value of
+    /// the ancestor with the lowest graph node index, and the ambiguity is reported in the
+    /// returned list.
+    pub fn inherit_attribute<N, E, V>(
+        &mut self,
+        name: N,
+        edge_attribute: E,
+        edge_value: V,
+    ) -> Vec<AmbiguousInheritance>
+    where
+        N: Into<Identifier>,
+        E: Into<Identifier>,
+        V: Into<Value>,
+    {
+        let name = name.into();
+        let edge_attribute = edge_attribute.into();
+        let edge_value = edge_value.into();
+
+        // Sources are added in increasing order, so every list of parents is sorted.
+        let mut parents = vec![Vec::new(); self.graph_nodes.len()];
+        for (source, node) in self.graph_nodes.iter().enumerate() {
+            for (sink, edge) in &node.outgoing_edges {
+                if edge.attributes.get(&edge_attribute) == Some(&edge_value) {
+                    parents[*sink as usize].push(source as GraphNodeID);
+                }
+            }
+        }
+
+        let mut inherited = Vec::new();
+        let mut ambiguities = Vec::new();
+        for (index, node) in self.graph_nodes.iter().enumerate() {
+            if node.attributes.get(&name).is_some() {
+                continue;
+            }
+            let mut visited = HashSet::new();
+            visited.insert(index as GraphNodeID);
+            let mut frontier = parents[index].clone();
+            while !frontier.is_empty() {
+                frontier.retain(|parent| visited.insert(*parent));
+                frontier.sort_unstable();
+                let ancestors = frontier
+                    .iter()
+                    .filter_map(|parent| {
+                        let value = self.graph_nodes[*parent as usize].attributes.get(&name)?;
+                        Some((GraphNodeRef(*parent), value.clone()))
+                    })
+                    .collect::<Vec<_>>();
+                if let Some((_, value)) = ancestors.first() {
+                    inherited.push((index, value.clone()));
+                    if ancestors.iter().any(|(_, other)| other != value) {
+                        ambiguities.push(AmbiguousInheritance {
+                            node: GraphNodeRef(index as GraphNodeID),
+                            attribute: name.clone(),
+                            ancestors,
+                        });
+                    }
+                    break;
+                }
+                frontier = frontier
+                    .iter()
+                    .flat_map(|parent| parents[*parent as usize].iter().copied())
+                    .collect();
+            }
+        }
+
+        for (index, value) in inherited {
+            self[GraphNodeRef(index as GraphNodeID)]
+                .attributes
+                .add(name.clone(), value)
+                .expect("inherited attribute already set");
+        }
+        ambiguities
+    }
+}
@@ -0,0 +1,108 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Lookup of graph nodes by key and by attribute value
+
+use std::collections::HashMap;
+
+use crate::Identifier;
+
+use super::Graph;
+use super::GraphNodeRef;
+use super::Value;
+
+/// An index from attribute values to the graph nodes that have them, for the attributes that have
+/// been looked up during the current execution.  Entries are only ever added, so the index can
+/// contain nodes whose value has since changed, or that were rolled back; lookups check every
+/// node against the graph before returning it.
+#[derive(Default)]
+pub(crate) struct AttributeIndex {
+    attributes: HashMap<Identifier, HashMap<Value, Vec<GraphNodeRef>>>,
+}
+
+impl AttributeIndex {
+    fn insert(&mut self, name: &Identifier, value: &Value, node: GraphNodeRef) {
+        if let Some(values) = self.attributes.get_mut(name) {
+            let nodes = values.entry(value.clone()).or_default();
+            if let Err(index) = nodes.binary_search(&node) {
+                nodes.insert(index, node);
+            }
+        }
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Returns the graph node registered under a key, if there is one.
+    pub fn graph_node_for_key(&self, key: &str) -> Option<GraphNodeRef> {
+        self.keyed_nodes.get(key).copied()
+    }
+
+    /// Returns the graph node registered under a key, adding a new graph node and registering it
+    /// if there is none.
+    pub fn add_keyed_graph_node(&mut self, key: &str) -> GraphNodeRef {
+        if let Some(node) = self.graph_node_for_key(key) {
+            return node;
+        }
+        let node = self.add_graph_node();
+        self.keyed_nodes.insert(key.to_string(), node);
+        node
+    }
+
+    /// Returns the graph nodes whose attribute `name` is equal to `value`, in the order in which
+    /// they were created.  The first lookup of an attribute scans all graph nodes; afterwards,
+    /// the attribute is kept up to date as execution adds attributes to graph nodes.
+    pub(crate) fn find_graph_nodes_by_attribute(
+        &mut self,
+        name: &Identifier,
+        value: &Value,
+    ) -> Vec<GraphNodeRef> {
+        if !self.attribute_index.attributes.contains_key(name) {
+            let mut values = HashMap::<Value, Vec<GraphNodeRef>>::new();
+            for node in self.iter_nodes() {
+                if let Some(value) = self[node].attributes.get(name) {
+                    values.entry(value.clone()).or_default().push(node);
+                }
+            }
+            self.attribute_index.attributes.insert(name.clone(), values);
+        }
+        let nodes = match self.attribute_index.attributes[name].get(value) {
+            Some(nodes) => nodes,
+            None => return Vec::new(),
+        };
+        nodes
+            .iter()
+            .copied()
+            .filter(|node| {
+                node.index() < self.graph_nodes.len()
+                    && self[*node].attributes.get(name) == Some(value)
+            })
+            .collect()
+    }
+
+    /// Records the current value of an attribute of a graph node in the attribute index.
+    pub(crate) fn index_attribute(&mut self, node: GraphNodeRef, name: &Identifier) {
+        if let Some(value) = self.graph_nodes[node.index()].attributes.get(name) {
+            self.attribute_index.insert(name, value, node);
+        }
+    }
+
+    /// Records the current values of all attributes of a graph node in the attribute index.
+    pub(crate) fn index_attributes(&mut self, node: GraphNodeRef) {
+        if self.attribute_index.attributes.is_empty() {
+            return;
+        }
+        for (name, value) in self.graph_nodes[node.index()].attributes.iter() {
+            self.attribute_index.insert(name, value, node);
+        }
+    }
+
+    /// Discards the attribute index.  The index only reflects the changes made by a single
+    /// execution, and is reset at the start of every execution.
+    pub(crate) fn reset_attribute_index(&mut self) {
+        self.attribute_index = AttributeIndex::default();
+    }
+}
@@ -0,0 +1,213 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Introspection of the memory used by graphs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::size_of;
+
+use crate::Identifier;
+
+use super::Attributes;
+use super::Edge;
+use super::Graph;
+use super::GraphNode;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::Tag;
+use super::Value;
+
+/// The memory used by a graph, in bytes, broken down by component.  The numbers are computed by
+/// walking the graph's data structures, and are estimates: hash tables are assumed to use one
+/// control byte per entry, and the allocator's own overhead is not included.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct GraphMemoryUsage {
+    /// The bytes allocated for the vector of graph nodes, including edges that are stored inline
+    pub graph_nodes: usize,
+    /// The bytes allocated for edge lists that no longer fit inline in their graph node
+    pub edges: usize,
+    /// The number of graph nodes whose edges are stored inline
+    pub inline_edge_lists: usize,
+    /// The number of graph nodes whose edges are stored in a separate allocation
+    pub spilled_edge_lists: usize,
+    /// The bytes allocated for the attribute maps of graph nodes and edges, including the
+    /// elements of list and set values, but not the contents of strings
+    pub attributes: usize,
+    /// The bytes allocated for string values
+    pub string_capacity: usize,
+    /// The total length of string values, in bytes
+    pub string_length: usize,
+    /// The number of string values that have an allocation
+    pub string_allocations: usize,
+    /// The bytes allocated for the table of syntax nodes
+    pub syntax_nodes: usize,
+    /// The bytes allocated for the graph nodes owned by syntax nodes
+    pub owned_graph_nodes: usize,
+}
+
+impl GraphMemoryUsage {
+    /// Returns the total number of bytes used by the graph.
+    pub fn total(&self) -> usize {
+        self.graph_nodes
+            + self.edges
+            + self.attributes
+            + self.string_capacity
+            + self.syntax_nodes
+            + self.owned_graph_nodes
+    }
+
+    fn add_attributes(&mut self, attributes: &Attributes) {
+        self.attributes += hash_table_size::<_, Value>(&attributes.values);
+        self.attributes += attributes.defaults.capacity() * (size_of::<Identifier>() + 1);
+        self.attributes += attributes.hidden.capacity() * (size_of::<Identifier>() + 1);
+        for value in attributes.values.values() {
+            self.add_value(value);
+        }
+    }
+
+    fn add_value(&mut self, value: &Value) {
+        match value {
+            Value::String(value) => {
+                if value.capacity() > 0 {
+                    self.string_allocations += 1;
+                }
+                self.string_capacity += value.capacity();
+                self.string_length += value.len();
+            }
+            Value::List(values) => {
+                self.attributes += values.capacity() * size_of::<Value>();
+                values.iter().for_each(|value| self.add_value(value));
+            }
+            Value::Set(values) => {
+                self.attributes += values.len() * size_of::<Value>();
+                values.iter().for_each(|value| self.add_value(value));
+            }
+            Value::Map(values) => {
+                self.attributes += values.len() * size_of::<(Identifier, Value)>();
+                values.values().for_each(|value| self.add_value(value));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for GraphMemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "graph nodes: {} bytes", self.graph_nodes)?;
+        writeln!(
+            f,
+            "edges: {} bytes ({} inline, {} spilled edge lists)",
+            self.edges, self.inline_edge_lists, self.spilled_edge_lists
+        )?;
+        writeln!(f, "attributes: {} bytes", self.attributes)?;
+        writeln!(
+            f,
+            "strings: {} bytes in {} allocations ({} bytes used)",
+            self.string_capacity, self.string_allocations, self.string_length
+        )?;
+        writeln!(f, "syntax nodes: {} bytes", self.syntax_nodes)?;
+        writeln!(f, "owned graph nodes: {} bytes", self.owned_graph_nodes)?;
+        writeln!(f, "total: {} bytes", self.total())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for GraphMemoryUsage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut report = serializer.serialize_struct("GraphMemoryUsage", 11)?;
+        report.serialize_field("graph_nodes", &self.graph_nodes)?;
+        report.serialize_field("edges", &self.edges)?;
+        report.serialize_field("inline_edge_lists", &self.inline_edge_lists)?;
+        report.serialize_field("spilled_edge_lists", &self.spilled_edge_lists)?;
+        report.serialize_field("attributes", &self.attributes)?;
+        report.serialize_field("string_capacity", &self.string_capacity)?;
+        report.serialize_field("string_length", &self.string_length)?;
+        report.serialize_field("string_allocations", &self.string_allocations)?;
+        report.serialize_field("syntax_nodes", &self.syntax_nodes)?;
+        report.serialize_field("owned_graph_nodes", &self.owned_graph_nodes)?;
+        report.serialize_field("total", &self.total())?;
+        report.end()
+    }
+}
+
+/// Estimates the bytes allocated for a hash table, assuming one control byte per entry.
+fn hash_table_size<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (size_of::<K>() + size_of::<V>() + 1)
+}
+
+impl<'tree> Graph<'tree> {
+    /// Returns the memory used by this graph, broken down by component.
+    pub fn memory_usage(&self) -> GraphMemoryUsage {
+        let mut usage = GraphMemoryUsage {
+            graph_nodes: self.graph_nodes.capacity() * size_of::<GraphNode>(),
+            syntax_nodes: hash_table_size(&self.syntax_nodes),
+            owned_graph_nodes: hash_table_size(&self.owned_graph_nodes),
+            ..GraphMemoryUsage::default()
+        };
+        for owned in self.owned_graph_nodes.values() {
+            usage.owned_graph_nodes += owned.capacity() * size_of::<(Tag, GraphNodeRef)>();
+        }
+        for node in &self.graph_nodes {
+            if node.outgoing_edges.spilled() {
+                usage.spilled_edge_lists += 1;
+                usage.edges += node.outgoing_edges.capacity() * size_of::<(GraphNodeID, Edge)>();
+            } else {
+                usage.inline_edge_lists += 1;
+            }
+            usage.add_attributes(&node.attributes);
+            for (_, edge) in &node.outgoing_edges {
+                usage.add_attributes(&edge.attributes);
+            }
+        }
+        usage
+    }
+
+    /// Shrinks all of the graph's containers to fit their contents.  This is useful once execution
+    /// has completed, and no more nodes, edges, or attributes are added to the graph.
+    pub fn shrink_to_fit(&mut self) {
+        self.graph_nodes.shrink_to_fit();
+        for node in &mut self.graph_nodes {
+            node.outgoing_edges.shrink_to_fit();
+            node.attributes.shrink_to_fit();
+            for (_, edge) in &mut node.outgoing_edges {
+                edge.attributes.shrink_to_fit();
+            }
+        }
+        self.syntax_nodes.shrink_to_fit();
+        self.owned_graph_nodes.shrink_to_fit();
+        for owned in self.owned_graph_nodes.values_mut() {
+            owned.shrink_to_fit();
+        }
+    }
+}
+
+impl Attributes {
+    fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+        self.defaults.shrink_to_fit();
+        self.hidden.shrink_to_fit();
+        for value in self.values.values_mut() {
+            value.shrink_to_fit();
+        }
+    }
+}
+
+impl Value {
+    fn shrink_to_fit(&mut self) {
+        match self {
+            Value::String(value) => value.shrink_to_fit(),
+            Value::List(values) => {
+                values.shrink_to_fit();
+                values.iter_mut().for_each(Value::shrink_to_fit);
+            }
+            Value::Map(values) => values.values_mut().for_each(Value::shrink_to_fit),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,535 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Merging of graphs, and of graph nodes within a graph
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ops::Index;
+
+use thiserror::Error;
+
+use crate::execution::error::ExecutionError;
+use crate::Identifier;
+
+use super::Attributes;
+use super::Edge;
+use super::Graph;
+use super::GraphNode;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::SyntaxNodeRef;
+use super::Value;
+
+/// Determines what happens when merging two graph nodes or edges that both have an attribute with
+/// the same name, but with different values.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Keep the value of the graph that is merged into.
+    KeepExisting,
+    /// Use the value of the graph that is merged.
+    Overwrite,
+    /// Fail the merge with an error.
+    Error,
+}
+
+/// Determines what happens to the edges between two graph nodes that are
+/// [aliased][Graph::alias], which end up connecting the kept node to itself.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SelfEdgePolicy {
+    /// Keep them, as a single edge from the kept node to itself.
+    Keep,
+    /// Drop them.  An edge from the kept node to itself that existed before is kept.
+    Drop,
+}
+
+/// An error that can occur when aliasing graph nodes
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum AliasError {
+    #[error("Unknown graph node {0}")]
+    UnknownGraphNode(GraphNodeRef),
+    #[error("Removed graph node {0}")]
+    RemovedGraphNode(GraphNodeRef),
+    #[error("Cannot alias graph node {0} to itself")]
+    SameGraphNode(GraphNodeRef),
+    #[error("Duplicate attribute {0}")]
+    DuplicateAttribute(String),
+}
+
+/// A mapping from the graph node references of a merged graph to the references of the
+/// corresponding nodes in the graph it was merged into.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GraphNodeRefMapping(Vec<GraphNodeRef>);
+
+impl GraphNodeRefMapping {
+    /// Returns the new reference for a graph node of the merged graph, if it exists.
+    pub fn get(&self, node: GraphNodeRef) -> Option<GraphNodeRef> {
+        self.0.get(node.index()).copied()
+    }
+
+    /// Returns an iterator over all pairs of old and new graph node references.
+    pub fn iter(&self) -> impl Iterator<Item = (GraphNodeRef, GraphNodeRef)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (GraphNodeRef(index as GraphNodeID), *node))
+    }
+
+    /// Returns the number of graph nodes in the mapping.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the mapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Index<GraphNodeRef> for GraphNodeRefMapping {
+    type Output = GraphNodeRef;
+    fn index(&self, node: GraphNodeRef) -> &GraphNodeRef {
+        &self.0[node.index()]
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Merges another graph into this one.  The nodes of the other graph are appended to this
+    /// graph, and any graph node references in their edges and attribute values are updated.
+    /// Returns the mapping from the other graph's node references to the new ones.  Provenance
+    /// recorded for the other graph is not kept, and neither are the keys of its [keyed graph
+    /// nodes][Self::add_keyed_graph_node].
+    ///
+    /// Syntax nodes are identified by their ID, which is only unique among the trees that are
+    /// alive at the same time.  The merge fails if the other graph contains a syntax node with the
+    /// same ID as a different syntax node of this graph, in which case this graph is left
+    /// unchanged.
+    pub fn merge(&mut self, other: Graph<'tree>) -> Result<GraphNodeRefMapping, ExecutionError> {
+        self.merge_nodes(other, None, ConflictPolicy::Error)
+    }
+
+    /// Merges another graph into this one, unifying graph nodes that have the same value for the
+    /// `key` attribute.  A node of the other graph is unified with the first node in this graph,
+    /// or the first node added by this merge, that has an equal `key` value.  The attributes and
+    /// outgoing edges of unified nodes are combined, and edges that end up connecting the same
+    /// nodes are combined as well.  Conflicting attribute values are resolved according to
+    /// `on_conflict`.  Nodes without the `key` attribute are appended, as in
+    /// [`merge`][Self::merge].
+    ///
+    /// Removed graph nodes are never unified.  Returns the mapping from the other graph's node
+    /// references to the new ones, where unified nodes map to the node they were unified with.
+    /// The merge fails for conflicting syntax nodes, as [`merge`][Self::merge] does, and for
+    /// conflicting attribute values under [`ConflictPolicy::Error`][].  If the merge fails, this
+    /// graph is left unchanged.
+    pub fn merge_keyed(
+        &mut self,
+        other: Graph<'tree>,
+        key: &Identifier,
+        on_conflict: ConflictPolicy,
+    ) -> Result<GraphNodeRefMapping, ExecutionError> {
+        self.merge_nodes(other, Some(key), on_conflict)
+    }
+
+    fn merge_nodes(
+        &mut self,
+        other: Graph<'tree>,
+        key: Option<&Identifier>,
+        on_conflict: ConflictPolicy,
+    ) -> Result<GraphNodeRefMapping, ExecutionError> {
+        for (index, node) in &other.syntax_nodes {
+            if let Some(existing) = self.syntax_nodes.get(index) {
+                if existing.id() != node.id() || existing.range() != node.range() {
+                    return Err(ExecutionError::ConflictingSyntaxNodes(format!(
+                        "{} and {} with the same ID",
+                        SyntaxNodeRef::from(existing),
+                        SyntaxNodeRef::from(node),
+                    )));
+                }
+            }
+        }
+        let base = self.graph_nodes.len();
+
+        // Decide which node each of the other graph's nodes ends up as.
+        let mut keyed_nodes = HashMap::new();
+        if let Some(key) = key {
+            for (index, node) in self.graph_nodes.iter().enumerate() {
+                if node.removed {
+                    continue;
+                }
+                if let Some(value) = node.attributes.get(key) {
+                    keyed_nodes
+                        .entry(value.clone())
+                        .or_insert(GraphNodeRef(index as GraphNodeID));
+                }
+            }
+        }
+        let mut next_index = base;
+        let mut mapping = Vec::with_capacity(other.graph_nodes.len());
+        for node in &other.graph_nodes {
+            let key_value = key
+                .filter(|_| !node.removed)
+                .and_then(|key| node.attributes.get(key));
+            let target = match key_value.and_then(|value| keyed_nodes.get(value)) {
+                Some(target) => *target,
+                None => {
+                    let target = GraphNodeRef(next_index as GraphNodeID);
+                    next_index += 1;
+                    if let Some(value) = key_value {
+                        keyed_nodes.insert(value.clone(), target);
+                    }
+                    target
+                }
+            };
+            mapping.push(target);
+        }
+        let mapping = GraphNodeRefMapping(mapping);
+
+        // Build the merged nodes before changing this graph, so that a conflict leaves it intact.
+        let mut updated_nodes = HashMap::new();
+        let mut appended_nodes: Vec<GraphNode> = Vec::new();
+        for (index, node) in other.graph_nodes.into_iter().enumerate() {
+            let target = mapping.0[index];
+            let node = node.remap(&mapping, on_conflict)?;
+            if target.index() == base + appended_nodes.len() {
+                appended_nodes.push(node);
+                continue;
+            }
+            let existing = if target.index() >= base {
+                &mut appended_nodes[target.index() - base]
+            } else {
+                match updated_nodes.entry(target.0) {
+                    Entry::Occupied(o) => o.into_mut(),
+                    Entry::Vacant(v) => v.insert(self.graph_nodes[target.index()].clone()),
+                }
+            };
+            existing.combine(node, target, on_conflict)?;
+        }
+
+        for (index, node) in updated_nodes {
+            self.graph_nodes[index as usize] = node;
+        }
+        self.graph_nodes.extend(appended_nodes);
+        self.syntax_nodes.extend(other.syntax_nodes);
+        for (index, owned) in other.owned_graph_nodes {
+            for (tag, node) in owned {
+                self.add_owned_graph_node(index, tag, mapping[node]);
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Unifies two graph nodes of this graph that turn out to stand for the same entity, keeping
+    /// `kept` and removing `removed`.  The attributes of `removed` are added to `kept`, its
+    /// outgoing and incoming edges are redirected to `kept`, and every graph node reference to
+    /// `removed`, in the attributes of all graph nodes and edges, and in the keys and owners of
+    /// graph nodes, is replaced by a reference to `kept`.  Conflicting attribute values, of the
+    /// nodes themselves and of edges that end up connecting the same nodes, are resolved
+    /// according to `on_conflict`, where the values of `kept` and of its edges are the existing
+    /// ones.  Edges between the two nodes end up connecting `kept` to itself, and are kept or
+    /// dropped according to `self_edges`.
+    ///
+    /// `removed` is left as a removed graph node, as [`prune_orphans`][Self::prune_orphans] does.
+    /// Provenance recorded for `removed` is not moved to `kept`.  If aliasing fails, this graph is
+    /// left unchanged.
+    pub fn alias(
+        &mut self,
+        kept: GraphNodeRef,
+        removed: GraphNodeRef,
+        on_conflict: ConflictPolicy,
+        self_edges: SelfEdgePolicy,
+    ) -> Result<(), AliasError> {
+        for node in &[kept, removed] {
+            match self.graph_nodes.get(node.index()) {
+                None => return Err(AliasError::UnknownGraphNode(*node)),
+                Some(graph_node) if graph_node.removed => {
+                    return Err(AliasError::RemovedGraphNode(*node))
+                }
+                Some(_) => {}
+            }
+        }
+        if kept == removed {
+            return Err(AliasError::SameGraphNode(kept));
+        }
+        let mut mapping = (0..self.graph_nodes.len())
+            .map(|index| GraphNodeRef(index as GraphNodeID))
+            .collect::<Vec<_>>();
+        mapping[removed.index()] = kept;
+        let mapping = GraphNodeRefMapping(mapping);
+
+        // Build the changed nodes before changing this graph, so that a conflict leaves it intact.
+        let kept_node = &self.graph_nodes[kept.index()];
+        let removed_node = &self.graph_nodes[removed.index()];
+        let mut new_kept = GraphNode::new();
+        new_kept.attributes = kept_node.attributes.clone().remap(&mapping);
+        new_kept
+            .attributes
+            .combine(
+                removed_node.attributes.clone().remap(&mapping),
+                on_conflict,
+                || format!("{}", kept),
+            )
+            .map_err(AliasError::DuplicateAttribute)?;
+        // The edges of kept come first, so that their attributes are the existing ones.  Only
+        // the edges that did not connect kept to itself before are self-edges created here.
+        let edges = kept_node
+            .outgoing_edges
+            .iter()
+            .map(|(sink, edge)| (*sink, edge, *sink == kept.0))
+            .chain(
+                removed_node
+                    .outgoing_edges
+                    .iter()
+                    .map(|(sink, edge)| (*sink, edge, false)),
+            );
+        for (sink, edge, existing_self_edge) in edges {
+            let sink = mapping[GraphNodeRef(sink)];
+            if sink == kept && !existing_self_edge && self_edges == SelfEdgePolicy::Drop {
+                continue;
+            }
+            new_kept
+                .add_remapped_edge(sink, edge, &mapping, on_conflict, kept)
+                .map_err(AliasError::DuplicateAttribute)?;
+        }
+
+        let mut updated_nodes = Vec::new();
+        for (index, node) in self.graph_nodes.iter().enumerate() {
+            if index == kept.index() || index == removed.index() || !node.refers_to(removed) {
+                continue;
+            }
+            let source = GraphNodeRef(index as GraphNodeID);
+            let mut new_node = GraphNode::new();
+            new_node.attributes = node.attributes.clone().remap(&mapping);
+            // The edge to removed comes last, so that an edge to kept is the existing one.
+            let mut edges = node.outgoing_edges.iter().collect::<Vec<_>>();
+            edges.sort_by_key(|(sink, _)| *sink == removed.0);
+            for (sink, edge) in edges {
+                new_node
+                    .add_remapped_edge(
+                        mapping[GraphNodeRef(*sink)],
+                        edge,
+                        &mapping,
+                        on_conflict,
+                        source,
+                    )
+                    .map_err(AliasError::DuplicateAttribute)?;
+            }
+            updated_nodes.push((index, new_node));
+        }
+
+        for (index, node) in updated_nodes {
+            self.graph_nodes[index] = node;
+        }
+        self.graph_nodes[kept.index()] = new_kept;
+        self.graph_nodes[removed.index()] = GraphNode {
+            removed: true,
+            ..GraphNode::new()
+        };
+        for node in self.keyed_nodes.values_mut() {
+            *node = mapping[*node];
+        }
+        for owned in self.owned_graph_nodes.values_mut() {
+            for (_, node) in owned.iter_mut() {
+                *node = mapping[*node];
+            }
+        }
+        self.reset_attribute_index();
+        Ok(())
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Returns a copy of the graph nodes, edges, and syntax nodes of this graph, and the owners
+    /// of its graph nodes.  Provenance and the keys of keyed graph nodes are not copied.
+    pub(crate) fn copy_contents(&self) -> Graph<'tree> {
+        Graph {
+            syntax_nodes: self.syntax_nodes.clone(),
+            graph_nodes: self.graph_nodes.clone(),
+            owned_graph_nodes: self.owned_graph_nodes.clone(),
+            ..Graph::default()
+        }
+    }
+}
+
+impl GraphNode {
+    /// Updates all graph node references in this node's edges and attributes.
+    fn remap(
+        self,
+        mapping: &GraphNodeRefMapping,
+        on_conflict: ConflictPolicy,
+    ) -> Result<GraphNode, ExecutionError> {
+        let mut node = GraphNode::new();
+        node.attributes = self.attributes.remap(mapping);
+        node.removed = self.removed;
+        for (sink, edge) in self.outgoing_edges {
+            let sink = mapping[GraphNodeRef(sink)];
+            let attributes = edge.attributes.remap(mapping);
+            match node.add_edge(sink) {
+                Ok(edge) => edge.attributes = attributes,
+                Err(edge) => edge
+                    .attributes
+                    .combine(attributes, on_conflict, || format!("edge to {}", sink))
+                    .map_err(ExecutionError::DuplicateAttribute)?,
+            }
+        }
+        Ok(node)
+    }
+
+    /// Combines the attributes and edges of another node into this one.
+    fn combine(
+        &mut self,
+        other: GraphNode,
+        node: GraphNodeRef,
+        on_conflict: ConflictPolicy,
+    ) -> Result<(), ExecutionError> {
+        self.attributes
+            .combine(other.attributes, on_conflict, || format!("{}", node))
+            .map_err(ExecutionError::DuplicateAttribute)?;
+        for (sink, edge) in other.outgoing_edges {
+            let sink = GraphNodeRef(sink);
+            match self.add_edge(sink) {
+                Ok(new_edge) => *new_edge = edge,
+                Err(existing_edge) => existing_edge
+                    .attributes
+                    .combine(edge.attributes, on_conflict, || {
+                        format!("edge ({} -> {})", node, sink)
+                    })
+                    .map_err(ExecutionError::DuplicateAttribute)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a copy of an edge to this node, with its sink and attributes remapped, combining it
+    /// with an existing edge to the same sink.
+    fn add_remapped_edge(
+        &mut self,
+        sink: GraphNodeRef,
+        edge: &Edge,
+        mapping: &GraphNodeRefMapping,
+        on_conflict: ConflictPolicy,
+        source: GraphNodeRef,
+    ) -> Result<(), String> {
+        let attributes = edge.attributes.clone().remap(mapping);
+        match self.add_edge(sink) {
+            Ok(new_edge) => new_edge.attributes = attributes,
+            Err(existing_edge) => {
+                existing_edge
+                    .attributes
+                    .combine(attributes, on_conflict, || {
+                        format!("edge ({} -> {})", source, sink)
+                    })?
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether this node has an edge to the given graph node, or refers to it in the
+    /// attributes of the node or its edges.
+    fn refers_to(&self, node: GraphNodeRef) -> bool {
+        self.attributes.refers_to(node)
+            || self
+                .outgoing_edges
+                .iter()
+                .any(|(sink, edge)| *sink == node.0 || edge.attributes.refers_to(node))
+    }
+}
+
+impl Attributes {
+    fn remap(self, mapping: &GraphNodeRefMapping) -> Attributes {
+        Attributes {
+            values: self
+                .values
+                .into_iter()
+                .map(|(name, value)| (name, value.remap(mapping)))
+                .collect(),
+            defaults: self.defaults,
+            hidden: self.hidden,
+        }
+    }
+
+    fn refers_to(&self, node: GraphNodeRef) -> bool {
+        self.values.values().any(|value| value.refers_to(node))
+    }
+
+    /// Combines the attributes of another attribute set into this one, returning a description
+    /// of the first conflicting attribute that is not resolved by `on_conflict`.
+    fn combine<F>(
+        &mut self,
+        other: Attributes,
+        on_conflict: ConflictPolicy,
+        describe: F,
+    ) -> Result<(), String>
+    where
+        F: Fn() -> String,
+    {
+        self.hidden.extend(other.hidden);
+        // sort the attributes so that the first conflict that is reported is deterministic
+        let mut values = other.values.into_iter().collect::<Vec<_>>();
+        values.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, value) in values {
+            match self.values.entry(name) {
+                Entry::Vacant(v) => {
+                    v.insert(value);
+                }
+                Entry::Occupied(mut o) => {
+                    if *o.get() == value {
+                        continue;
+                    }
+                    match on_conflict {
+                        ConflictPolicy::KeepExisting => {}
+                        ConflictPolicy::Overwrite => {
+                            o.insert(value);
+                        }
+                        ConflictPolicy::Error => {
+                            return Err(format!(
+                                "{} on {} with values {} and {}",
+                                o.key(),
+                                describe(),
+                                o.get(),
+                                value,
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    fn remap(self, mapping: &GraphNodeRefMapping) -> Value {
+        match self {
+            Value::List(values) => {
+                Value::List(values.into_iter().map(|v| v.remap(mapping)).collect())
+            }
+            Value::Set(values) => {
+                Value::Set(values.into_iter().map(|v| v.remap(mapping)).collect())
+            }
+            Value::Map(values) => Value::Map(
+                values
+                    .into_iter()
+                    .map(|(k, v)| (k, v.remap(mapping)))
+                    .collect(),
+            ),
+            Value::GraphNode(node) => Value::GraphNode(mapping[node]),
+            value => value,
+        }
+    }
+
+    fn refers_to(&self, node: GraphNodeRef) -> bool {
+        match self {
+            Value::List(values) => values.iter().any(|v| v.refers_to(node)),
+            Value::Set(values) => values.iter().any(|v| v.refers_to(node)),
+            Value::Map(values) => values.values().any(|v| v.refers_to(node)),
+            Value::GraphNode(other) => *other == node,
+            _ => false,
+        }
+    }
+}
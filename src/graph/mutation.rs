@@ -0,0 +1,237 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Checked mutation of graphs after execution, and checking the integrity of graphs
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::Identifier;
+
+use super::Attributes;
+use super::Edge;
+use super::Graph;
+use super::GraphNodeRef;
+use super::SyntaxNodeRef;
+use super::Value;
+
+/// An error that can occur when mutating a graph using its checked methods
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum GraphMutationError {
+    #[error("Unknown graph node {0}")]
+    UnknownGraphNode(GraphNodeRef),
+    #[error("Removed graph node {0}")]
+    RemovedGraphNode(GraphNodeRef),
+    #[error("Syntax node {0} has not been added to the graph")]
+    UnknownSyntaxNode(SyntaxNodeRef),
+    #[error("Duplicate attribute {1} on graph node {0}")]
+    DuplicateAttribute(GraphNodeRef, Identifier),
+    #[error("Duplicate edge {0} -> {1}")]
+    DuplicateEdge(GraphNodeRef, GraphNodeRef),
+}
+
+/// A place where a graph breaks one of the assumptions that the library makes about graphs
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IntegrityViolation {
+    /// An edge whose sink does not exist
+    DanglingEdge(GraphNodeRef, GraphNodeRef),
+    /// An edge from or to a removed graph node
+    RemovedEdgeEndpoint(GraphNodeRef, GraphNodeRef),
+    /// An attribute of a graph node whose value refers to a graph node that does not exist
+    DanglingGraphNode(GraphNodeRef, Identifier, GraphNodeRef),
+    /// An attribute of a graph node whose value refers to a syntax node that has not been added
+    /// to the graph, or that differs from the syntax node added to the graph with the same ID
+    UnknownSyntaxNode(GraphNodeRef, Identifier, SyntaxNodeRef),
+}
+
+impl fmt::Display for IntegrityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DanglingEdge(source, sink) => {
+                write!(
+                    f,
+                    "edge {} -> {} leads to an unknown graph node",
+                    source, sink
+                )
+            }
+            Self::RemovedEdgeEndpoint(source, sink) => {
+                write!(
+                    f,
+                    "edge {} -> {} connects a removed graph node",
+                    source, sink
+                )
+            }
+            Self::DanglingGraphNode(node, name, value) => write!(
+                f,
+                "attribute {} of {} refers to unknown graph node {}",
+                name, node, value
+            ),
+            Self::UnknownSyntaxNode(node, name, value) => write!(
+                f,
+                "attribute {} of {} refers to syntax node {}, which is not part of the graph",
+                name, node, value
+            ),
+        }
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Adds a new graph node with the given attributes, which is not tied to any syntax node, and
+    /// returns a reference to it.  This is useful for synthesized nodes, such as nodes that stand
+    /// for definitions in other files.
+    pub fn add_external_node(
+        &mut self,
+        metadata: Attributes,
+    ) -> Result<GraphNodeRef, GraphMutationError> {
+        for (_, value) in metadata.iter() {
+            self.check_value(value)?;
+        }
+        let node = self.add_graph_node();
+        self[node].attributes = metadata;
+        self.index_attributes(node);
+        Ok(node)
+    }
+
+    /// Adds an edge between two existing graph nodes, returning a mutable reference to it.  There
+    /// can be at most one edge connecting any two graph nodes, so adding an edge that already
+    /// exists is an error.
+    pub fn add_edge(
+        &mut self,
+        source: GraphNodeRef,
+        sink: GraphNodeRef,
+    ) -> Result<&mut Edge, GraphMutationError> {
+        self.check_graph_node(source)?;
+        self.check_graph_node(sink)?;
+        self[source]
+            .add_edge(sink)
+            .map_err(|_| GraphMutationError::DuplicateEdge(source, sink))
+    }
+
+    /// Sets an attribute of an existing graph node.  If the graph node already has an attribute
+    /// with a different value, the value is replaced if `overwrite` is set, and the attribute is
+    /// left unchanged and an error is returned otherwise.  Default values are always replaced.
+    /// The value can only refer to existing graph nodes, and to syntax nodes that have been
+    /// added to the graph.
+    pub fn set_attribute<N, V>(
+        &mut self,
+        node: GraphNodeRef,
+        name: N,
+        value: V,
+        overwrite: bool,
+    ) -> Result<(), GraphMutationError>
+    where
+        N: Into<Identifier>,
+        V: Into<Value>,
+    {
+        let name = name.into();
+        let value = value.into();
+        self.check_graph_node(node)?;
+        self.check_value(&value)?;
+        let attributes = &self[node].attributes;
+        if !overwrite && !attributes.is_default(&name) {
+            if let Some(existing) = attributes.get(&name) {
+                if *existing != value {
+                    return Err(GraphMutationError::DuplicateAttribute(node, name));
+                }
+            }
+        }
+        // any conflict has been ruled out above, or is meant to replace the value
+        let _ = self[node].attributes.add(name.clone(), value);
+        self.index_attribute(node, &name);
+        Ok(())
+    }
+
+    /// Checks that this graph upholds the assumptions described in the [`Graph`][]
+    /// documentation, returning every place where it does not, ordered by graph node.
+    pub fn check_integrity(&self) -> Vec<IntegrityViolation> {
+        let mut violations = Vec::new();
+        for index in 0..self.graph_nodes.len() {
+            let node = GraphNodeRef(index as _);
+            let removed = self.graph_nodes[index].removed;
+            for (sink, _) in self[node].iter_edges() {
+                if sink.index() >= self.graph_nodes.len() {
+                    violations.push(IntegrityViolation::DanglingEdge(node, sink));
+                } else if removed || self.graph_nodes[sink.index()].removed {
+                    violations.push(IntegrityViolation::RemovedEdgeEndpoint(node, sink));
+                }
+            }
+            let mut attributes = self[node].attributes.iter().collect::<Vec<_>>();
+            attributes.sort_by_key(|(name, _)| *name);
+            for (name, value) in attributes {
+                self.check_value_integrity(node, name, value, &mut violations);
+            }
+        }
+        violations
+    }
+
+    fn check_value_integrity(
+        &self,
+        node: GraphNodeRef,
+        name: &Identifier,
+        value: &Value,
+        violations: &mut Vec<IntegrityViolation>,
+    ) {
+        match value {
+            Value::GraphNode(value) if value.index() >= self.graph_nodes.len() => violations.push(
+                IntegrityViolation::DanglingGraphNode(node, name.clone(), *value),
+            ),
+            Value::SyntaxNode(value) if !self.contains_syntax_node(value) => violations.push(
+                IntegrityViolation::UnknownSyntaxNode(node, name.clone(), *value),
+            ),
+            Value::List(values) => values
+                .iter()
+                .for_each(|value| self.check_value_integrity(node, name, value, violations)),
+            Value::Set(values) => values
+                .iter()
+                .for_each(|value| self.check_value_integrity(node, name, value, violations)),
+            Value::Map(values) => values
+                .values()
+                .for_each(|value| self.check_value_integrity(node, name, value, violations)),
+            _ => {}
+        }
+    }
+
+    /// Checks that a graph node exists and has not been removed.
+    fn check_graph_node(&self, node: GraphNodeRef) -> Result<(), GraphMutationError> {
+        match self.graph_nodes.get(node.index()) {
+            None => Err(GraphMutationError::UnknownGraphNode(node)),
+            Some(graph_node) if graph_node.removed => {
+                Err(GraphMutationError::RemovedGraphNode(node))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Checks that all graph nodes and syntax nodes that a value refers to are part of the graph.
+    fn check_value(&self, value: &Value) -> Result<(), GraphMutationError> {
+        match value {
+            Value::GraphNode(node) if node.index() >= self.graph_nodes.len() => {
+                Err(GraphMutationError::UnknownGraphNode(*node))
+            }
+            Value::SyntaxNode(node) if !self.contains_syntax_node(node) => {
+                Err(GraphMutationError::UnknownSyntaxNode(*node))
+            }
+            Value::List(values) => values.iter().try_for_each(|value| self.check_value(value)),
+            Value::Set(values) => values.iter().try_for_each(|value| self.check_value(value)),
+            Value::Map(values) => values
+                .values()
+                .try_for_each(|value| self.check_value(value)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether a syntax node has been added to the graph.  The reference must agree with
+    /// the added node, which rules out most nodes of other syntax trees that happen to have the
+    /// same ID.
+    fn contains_syntax_node(&self, node: &SyntaxNodeRef) -> bool {
+        match self.syntax_nodes.get(&node.index) {
+            Some(syntax_node) => SyntaxNodeRef::from(syntax_node) == *node,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Detection and removal of graph nodes that nothing connects or annotates
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::Location;
+
+use super::Attributes;
+use super::Graph;
+use super::GraphNodeRef;
+use super::Value;
+
+/// The orphan graph nodes of a graph, grouped by the stanza that created them.  A graph node is an
+/// orphan if it has no attributes, no outgoing or incoming edges, and is not referred to by any
+/// attribute value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OrphanReport {
+    /// The groups of orphan graph nodes, ordered by the location of their stanza.  Orphans that
+    /// were not created by a known stanza come last.
+    pub groups: Vec<OrphanGroup>,
+}
+
+impl OrphanReport {
+    /// Returns the total number of orphan graph nodes.
+    pub fn count(&self) -> usize {
+        self.groups.iter().map(OrphanGroup::count).sum()
+    }
+
+    /// Returns whether there are no orphan graph nodes.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+/// The orphan graph nodes created by one stanza
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OrphanGroup {
+    /// The location of the stanza that created the graph nodes, or `None` if it is not known,
+    /// because provenance was not recorded, or because the nodes were not created by a stanza
+    pub stanza: Option<Location>,
+    /// The orphan graph nodes, in order of creation
+    pub nodes: Vec<GraphNodeRef>,
+}
+
+impl OrphanGroup {
+    /// Returns the number of orphan graph nodes in this group.
+    pub fn count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+impl fmt::Display for OrphanGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.count() == 1 { "" } else { "s" };
+        write!(f, "{} orphan graph node{}", self.count(), plural)?;
+        match self.stanza {
+            Some(stanza) => write!(f, " created by stanza at {}", stanza),
+            None => write!(f, " not created by a known stanza"),
+        }
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Finds the orphan graph nodes of this graph: graph nodes that have no attributes, no
+    /// outgoing or incoming edges, and are not referred to by any attribute value.  The orphans
+    /// are grouped by the stanza that created them, which is only known if provenance was
+    /// recorded during execution, as enabled using
+    /// [`ExecutionConfig::provenance`][crate::ExecutionConfig::provenance].  Removed graph nodes
+    /// are not reported.
+    pub fn find_orphans(&self) -> OrphanReport {
+        let mut referenced = vec![false; self.graph_nodes.len()];
+        for node in &self.graph_nodes {
+            mark_referenced(&node.attributes, &mut referenced);
+            for (sink, edge) in &node.outgoing_edges {
+                referenced[*sink as usize] = true;
+                mark_referenced(&edge.attributes, &mut referenced);
+            }
+        }
+
+        let creators = self.creating_stanzas();
+        let mut groups = Vec::<OrphanGroup>::new();
+        let mut group_indices = HashMap::new();
+        for node_ref in self.iter_nodes() {
+            let node = &self[node_ref];
+            if referenced[node_ref.index()]
+                || !node.outgoing_edges.is_empty()
+                || node.attributes.iter().next().is_some()
+            {
+                continue;
+            }
+            let stanza = creators.get(&node_ref).copied();
+            let index = *group_indices.entry(stanza).or_insert_with(|| {
+                groups.push(OrphanGroup {
+                    stanza,
+                    nodes: Vec::new(),
+                });
+                groups.len() - 1
+            });
+            groups[index].nodes.push(node_ref);
+        }
+        groups.sort_by_key(|group| match group.stanza {
+            Some(stanza) => (false, stanza.row, stanza.column),
+            None => (true, 0, 0),
+        });
+        OrphanReport { groups }
+    }
+
+    /// Finds the orphan graph nodes of this graph, as [`find_orphans`][Self::find_orphans] does,
+    /// and removes them.  Removed graph nodes keep their index, so that references to other graph
+    /// nodes stay valid, but they are no longer returned by [`iter_nodes`][Self::iter_nodes], and
+    /// are left out when the graph is printed or serialized.
+    pub fn prune_orphans(&mut self) -> OrphanReport {
+        let report = self.find_orphans();
+        for group in &report.groups {
+            for node in &group.nodes {
+                self[*node].removed = true;
+            }
+        }
+        report
+    }
+
+    /// Returns whether a graph node was removed from this graph.
+    pub fn is_removed(&self, node: GraphNodeRef) -> bool {
+        self[node].removed
+    }
+
+    /// Returns the orphans found at the end of execution, if enabled using
+    /// [`ExecutionConfig::report_orphans`][crate::ExecutionConfig::report_orphans] or
+    /// [`ExecutionConfig::prune_orphans`][crate::ExecutionConfig::prune_orphans].
+    pub fn orphan_report(&self) -> Option<&OrphanReport> {
+        self.orphans.as_ref()
+    }
+
+    pub(crate) fn set_orphan_report(&mut self, report: OrphanReport) {
+        self.orphans = Some(report);
+    }
+}
+
+/// Marks the graph nodes referred to by attribute values.
+fn mark_referenced(attributes: &Attributes, referenced: &mut [bool]) {
+    fn mark_value(value: &Value, referenced: &mut [bool]) {
+        match value {
+            Value::GraphNode(node) => referenced[node.index()] = true,
+            Value::List(values) => values.iter().for_each(|v| mark_value(v, referenced)),
+            Value::Set(values) => values.iter().for_each(|v| mark_value(v, referenced)),
+            Value::Map(values) => values.values().for_each(|v| mark_value(v, referenced)),
+            _ => {}
+        }
+    }
+    for (_, value) in attributes.iter() {
+        mark_value(value, referenced);
+    }
+}
@@ -0,0 +1,79 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Viewing graphs as [`petgraph`][] graphs, to run graph algorithms on them
+
+use petgraph::graph::DiGraph;
+use petgraph::graph::NodeIndex;
+
+use super::Edge;
+use super::Graph;
+use super::GraphNode;
+use super::GraphNodeRef;
+
+/// A [`Graph`][] copied into a petgraph [`DiGraph`][], created by [`Graph::to_petgraph`][].  The
+/// weights of the petgraph nodes and edges are references to the graph nodes and edges of the
+/// graph, so that their attributes remain accessible.  Petgraph node indices can be mapped back
+/// to graph node references, and the other way around.
+pub struct PetgraphAdapter<'a> {
+    graph: DiGraph<&'a GraphNode, &'a Edge>,
+    graph_node_refs: Vec<GraphNodeRef>,
+    node_indices: Vec<Option<NodeIndex>>,
+}
+
+impl<'a> PetgraphAdapter<'a> {
+    /// Returns the petgraph graph.
+    pub fn graph(&self) -> &DiGraph<&'a GraphNode, &'a Edge> {
+        &self.graph
+    }
+
+    /// Returns the petgraph graph, discarding the mapping between node indices and graph node
+    /// references.
+    pub fn into_graph(self) -> DiGraph<&'a GraphNode, &'a Edge> {
+        self.graph
+    }
+
+    /// Returns the graph node reference of a petgraph node index.  Panics if the index does not
+    /// belong to the petgraph graph.
+    pub fn graph_node_ref(&self, index: NodeIndex) -> GraphNodeRef {
+        self.graph_node_refs[index.index()]
+    }
+
+    /// Returns the petgraph node index of a graph node reference, or None if the graph node was
+    /// removed from the graph, or does not belong to it.
+    pub fn node_index(&self, node: GraphNodeRef) -> Option<NodeIndex> {
+        self.node_indices.get(node.index()).copied().flatten()
+    }
+}
+
+impl Graph<'_> {
+    /// Copies the structure of this graph into a petgraph [`DiGraph`][], to run graph algorithms
+    /// on it.  The graph nodes are added in order, leaving out removed ones, so petgraph node
+    /// indices are the same as graph node indices if no graph node was removed.  Use the returned
+    /// adapter to map between the two in general.
+    pub fn to_petgraph(&self) -> PetgraphAdapter<'_> {
+        let mut graph = DiGraph::new();
+        let mut graph_node_refs = Vec::new();
+        let mut node_indices = vec![None; self.node_count()];
+        for node in self.iter_nodes() {
+            node_indices[node.index()] = Some(graph.add_node(&self[node]));
+            graph_node_refs.push(node);
+        }
+        for source in self.iter_nodes() {
+            let source_index = node_indices[source.index()].unwrap();
+            for (sink, edge) in self[source].iter_edges() {
+                let sink_index = node_indices[sink.index()].expect("edge to removed graph node");
+                graph.add_edge(source_index, sink_index, edge);
+            }
+        }
+        PetgraphAdapter {
+            graph,
+            graph_node_refs,
+            node_indices,
+        }
+    }
+}
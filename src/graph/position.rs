@@ -0,0 +1,142 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Conversion of syntax node positions between encodings
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::Location;
+
+use super::SyntaxNodeRef;
+
+/// The unit in which the columns of positions are counted
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PositionEncoding {
+    /// Columns count UTF-8 bytes, as in tree-sitter points.
+    Utf8,
+    /// Columns count UTF-16 code units, as in the Language Server Protocol.
+    Utf16,
+    /// Columns count Unicode characters.
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn len(self, ch: char) -> usize {
+        match self {
+            Self::Utf8 => ch.len_utf8(),
+            Self::Utf16 => ch.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Converts the byte-based positions of syntax nodes into positions in another encoding, using
+/// the source text they were parsed from.  Lines are only scanned the first time a position on
+/// them is converted, so converting many positions takes time linear in the size of the source.
+pub struct PositionConverter<'a> {
+    source: &'a str,
+    encoding: PositionEncoding,
+    line_starts: Vec<usize>,
+    // For each line that has been scanned, the byte and encoded columns following every non-ASCII
+    // character on it.  Lines without non-ASCII characters have identical columns in all
+    // encodings, and get an empty list.
+    lines: RefCell<HashMap<usize, Vec<(usize, usize)>>>,
+}
+
+impl<'a> PositionConverter<'a> {
+    /// Creates a converter for positions in the given source text.
+    pub fn new(source: &'a str, encoding: PositionEncoding) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self {
+            source,
+            encoding,
+            line_starts,
+            lines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the encoding that positions are converted into.
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /// Converts a tree-sitter point, whose column counts bytes, into a location whose column is
+    /// counted in this converter's encoding.  Rows are not changed.
+    pub fn convert(&self, point: tree_sitter::Point) -> Location {
+        Location {
+            row: point.row,
+            column: self.convert_column(point.row, point.column),
+        }
+    }
+
+    fn convert_column(&self, row: usize, column: usize) -> usize {
+        if self.encoding == PositionEncoding::Utf8 {
+            return column;
+        }
+        let start = match self.line_starts.get(row) {
+            Some(start) => *start,
+            None => return column,
+        };
+        let mut lines = self.lines.borrow_mut();
+        let offsets = lines.entry(row).or_insert_with(|| self.scan_line(start));
+        // Find the last non-ASCII character that ends at or before the column; every byte after
+        // it is a single ASCII character in all encodings.
+        match offsets.partition_point(|(byte, _)| *byte <= column) {
+            0 => column,
+            index => {
+                let (byte, encoded) = offsets[index - 1];
+                encoded + (column - byte)
+            }
+        }
+    }
+
+    fn scan_line(&self, start: usize) -> Vec<(usize, usize)> {
+        let line = &self.source[start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut offsets = Vec::new();
+        let mut encoded = 0;
+        for (byte, ch) in line.char_indices() {
+            encoded += self.encoding.len(ch);
+            if !ch.is_ascii() {
+                offsets.push((byte + ch.len_utf8(), encoded));
+            }
+        }
+        offsets
+    }
+}
+
+impl SyntaxNodeRef {
+    /// Returns the location of this syntax node, with its column counted in the encoding of the
+    /// given converter.
+    pub fn location_in(&self, converter: &PositionConverter) -> Location {
+        converter.convert(self.position)
+    }
+
+    /// Returns the location of the end of this syntax node, with its column counted in the
+    /// encoding of the given converter.
+    pub fn end_location_in(&self, converter: &PositionConverter) -> Location {
+        converter.convert(self.end_position)
+    }
+
+    /// Returns a displayable version of this syntax node, with its column counted in the encoding
+    /// of the given converter.
+    pub fn display_with<'a>(&'a self, converter: &'a PositionConverter) -> impl fmt::Display + 'a {
+        struct DisplaySyntaxNode<'a>(&'a SyntaxNodeRef, Location);
+
+        impl fmt::Display for DisplaySyntaxNode<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.fmt_at(f, self.1)
+            }
+        }
+
+        DisplaySyntaxNode(self, self.location_in(converter))
+    }
+}
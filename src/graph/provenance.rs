@@ -0,0 +1,459 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Provenance of attribute values
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+use std::ops::Range;
+
+use crate::ast;
+use crate::Identifier;
+use crate::Location;
+use crate::MutationTarget;
+
+use super::Graph;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+
+/// The default number of steps shown when explaining an attribute value
+pub const DEFAULT_EXPLANATION_DEPTH: usize = 5;
+
+/// A reference to a step in the provenance of a value
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ProvenanceStepRef(u32);
+
+/// One step in the provenance of a value: the expression or statement that produced it, and the
+/// steps that produced the inputs it was computed from.
+#[derive(Clone, Debug)]
+pub struct ProvenanceStep {
+    /// What produced the value
+    pub origin: Origin,
+    /// The location in the graph DSL file
+    pub location: Location,
+    /// The steps that produced the inputs of this step
+    pub inputs: Vec<ProvenanceStepRef>,
+}
+
+/// What produced a value during execution
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+    /// A literal value
+    Literal,
+    /// A query capture
+    Capture(Identifier),
+    /// A regular expression capture in a `scan` statement
+    RegexCapture(usize),
+    /// A global variable
+    Global(Identifier),
+    /// A read of a local or scoped variable
+    Variable(String),
+    /// A declaration of, or assignment to, a local or scoped variable
+    Assignment(String),
+    /// A function call
+    Call(Identifier),
+    /// A graph node created by a `node` statement
+    GraphNode,
+    /// A list literal or comprehension
+    List,
+    /// A set literal or comprehension
+    Set,
+    /// A map literal
+    Map,
+    /// An attribute statement
+    Attribute(Identifier),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Literal => write!(f, "literal"),
+            Self::Capture(name) => write!(f, "capture @{}", name),
+            Self::RegexCapture(index) => write!(f, "regex capture ${}", index),
+            Self::Global(name) => write!(f, "global variable {}", name),
+            Self::Variable(variable) => write!(f, "read of variable {}", variable),
+            Self::Assignment(variable) => write!(f, "assignment to variable {}", variable),
+            Self::Call(function) => write!(f, "call to function {}", function),
+            Self::GraphNode => write!(f, "new graph node"),
+            Self::List => write!(f, "list"),
+            Self::Set => write!(f, "set"),
+            Self::Map => write!(f, "map"),
+            Self::Attribute(name) => write!(f, "attribute {}", name),
+        }
+    }
+}
+
+/// The provenance recorded during an execution.  Steps are stored in a side table, so that values
+/// themselves carry no extra cost.
+#[derive(Default)]
+pub(crate) struct Provenance {
+    steps: Vec<ProvenanceStep>,
+    attributes: HashMap<(GraphNodeID, Identifier), ProvenanceStepRef>,
+    stanzas: HashMap<Location, StanzaOutput>,
+    /// The graph nodes created by each match, keyed by the location of the stanza and the byte
+    /// range of the syntax node that the match matched
+    matches: HashMap<(Location, Range<usize>), Vec<GraphNodeRef>>,
+}
+
+/// The graph nodes and edges created, and the attributes set, by the matches of a stanza
+#[derive(Default)]
+pub(crate) struct StanzaOutput {
+    pub(crate) nodes: Vec<GraphNodeRef>,
+    pub(crate) edges: Vec<(GraphNodeRef, GraphNodeRef)>,
+    pub(crate) attributes: Vec<(MutationTarget, Identifier)>,
+}
+
+impl StanzaOutput {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.edges.is_empty() && self.attributes.is_empty()
+    }
+
+    fn len(&self) -> (usize, usize, usize) {
+        (self.nodes.len(), self.edges.len(), self.attributes.len())
+    }
+
+    fn truncate(&mut self, (nodes, edges, attributes): (usize, usize, usize)) {
+        self.nodes.truncate(nodes);
+        self.edges.truncate(edges);
+        self.attributes.truncate(attributes);
+    }
+}
+
+/// The state needed to roll the provenance of a graph back to an earlier point.  Steps are only
+/// ever appended, and the original values of attribute steps and the original lengths of
+/// recorded outputs are saved the first time they change.
+#[derive(Default)]
+pub(crate) struct ProvenanceCheckpoint {
+    step_count: usize,
+    saved_attributes: HashMap<(GraphNodeID, Identifier), Option<ProvenanceStepRef>>,
+    saved_stanzas: HashMap<Location, (usize, usize, usize)>,
+    saved_matches: HashMap<(Location, Range<usize>), usize>,
+}
+
+impl ProvenanceCheckpoint {
+    pub(crate) fn new(provenance: Option<&Provenance>) -> Self {
+        Self {
+            step_count: provenance.map_or(0, |provenance| provenance.steps.len()),
+            ..Self::default()
+        }
+    }
+}
+
+impl Provenance {
+    /// Restores the provenance to the state it was in when the checkpoint was taken.
+    pub(crate) fn rollback(&mut self, checkpoint: ProvenanceCheckpoint) {
+        self.steps.truncate(checkpoint.step_count);
+        for (key, step) in checkpoint.saved_attributes {
+            match step {
+                Some(step) => self.attributes.insert(key, step),
+                None => self.attributes.remove(&key),
+            };
+        }
+        for (stanza, len) in checkpoint.saved_stanzas {
+            if len == (0, 0, 0) {
+                self.stanzas.remove(&stanza);
+            } else if let Some(output) = self.stanzas.get_mut(&stanza) {
+                output.truncate(len);
+            }
+        }
+        for (key, len) in checkpoint.saved_matches {
+            if len == 0 {
+                self.matches.remove(&key);
+            } else if let Some(nodes) = self.matches.get_mut(&key) {
+                nodes.truncate(len);
+            }
+        }
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Records a provenance step, returning a reference to it.
+    pub(crate) fn add_provenance_step(&mut self, step: ProvenanceStep) -> ProvenanceStepRef {
+        let provenance = self.provenance.get_or_insert_with(Provenance::default);
+        let step_ref = ProvenanceStepRef(provenance.steps.len() as u32);
+        provenance.steps.push(step);
+        step_ref
+    }
+
+    /// Records the provenance step that set an attribute of a graph node.
+    pub(crate) fn set_attribute_provenance(
+        &mut self,
+        node: GraphNodeRef,
+        name: Identifier,
+        step: ProvenanceStepRef,
+    ) {
+        let provenance = self.provenance.get_or_insert_with(Provenance::default);
+        if let Some(checkpoint) = &mut self.checkpoint {
+            let key = (node.0, name.clone());
+            let attributes = &provenance.attributes;
+            checkpoint
+                .provenance
+                .saved_attributes
+                .entry(key)
+                .or_insert_with_key(|key| attributes.get(key).copied());
+        }
+        provenance.attributes.insert((node.0, name), step);
+    }
+
+    /// Records the output of a successful match of the stanza at the given location, which
+    /// matched the syntax node with the given byte range.
+    pub(crate) fn add_stanza_output(
+        &mut self,
+        stanza: Location,
+        matched: Range<usize>,
+        output: StanzaOutput,
+    ) {
+        if output.is_empty() {
+            return;
+        }
+        let provenance = self.provenance.get_or_insert_with(Provenance::default);
+        if let Some(checkpoint) = &mut self.checkpoint {
+            let saved = &mut checkpoint.provenance;
+            saved.saved_stanzas.entry(stanza).or_insert_with(|| {
+                provenance
+                    .stanzas
+                    .get(&stanza)
+                    .map_or((0, 0, 0), StanzaOutput::len)
+            });
+            if !output.nodes.is_empty() {
+                saved
+                    .saved_matches
+                    .entry((stanza, matched.clone()))
+                    .or_insert_with_key(|key| provenance.matches.get(key).map_or(0, Vec::len));
+            }
+        }
+        if !output.nodes.is_empty() {
+            provenance
+                .matches
+                .entry((stanza, matched))
+                .or_default()
+                .extend(output.nodes.iter().copied());
+        }
+        let recorded = provenance.stanzas.entry(stanza).or_default();
+        recorded.nodes.extend(output.nodes);
+        recorded.edges.extend(output.edges);
+        recorded.attributes.extend(output.attributes);
+    }
+
+    /// Returns the location of the stanza that created each graph node, for the graph nodes whose
+    /// creation was recorded.
+    pub(crate) fn creating_stanzas(&self) -> HashMap<GraphNodeRef, Location> {
+        let mut creators = HashMap::new();
+        if let Some(provenance) = &self.provenance {
+            for (stanza, output) in &provenance.stanzas {
+                for node in &output.nodes {
+                    creators.insert(*node, *stanza);
+                }
+            }
+        }
+        creators
+    }
+
+    /// Returns the graph nodes created by the matches of the stanza at the given location that
+    /// matched the syntax node with the given byte range, or None if provenance was not recorded.
+    pub(crate) fn nodes_created_by_match(
+        &self,
+        stanza: Location,
+        matched: Range<usize>,
+    ) -> Option<&[GraphNodeRef]> {
+        let provenance = self.provenance.as_ref()?;
+        Some(
+            provenance
+                .matches
+                .get(&(stanza, matched))
+                .map(|nodes| nodes.as_slice())
+                .unwrap_or(&[]),
+        )
+    }
+
+    fn stanza_output(&self, stanza: Location) -> Option<&StanzaOutput> {
+        self.provenance.as_ref()?.stanzas.get(&stanza)
+    }
+
+    /// Returns the graph nodes created by the matches of the stanza at the given location, in the
+    /// order in which they were created.  This includes graph nodes created by functions called
+    /// from the stanza.  The location of a stanza is the location of the start of its query, as
+    /// found in [`Stanza::range`][crate::ast::Stanza::range].  Like provenance, this is only
+    /// recorded if enabled using [`ExecutionConfig::provenance`][crate::ExecutionConfig::provenance].
+    pub fn nodes_created_by(&self, stanza: Location) -> &[GraphNodeRef] {
+        self.stanza_output(stanza)
+            .map(|output| output.nodes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the edges created by the matches of the stanza at the given location, as pairs of
+    /// source and sink, in the order in which they were created.  Edge statements for edges that
+    /// already existed are not included.
+    pub fn edges_created_by(&self, stanza: Location) -> &[(GraphNodeRef, GraphNodeRef)] {
+        self.stanza_output(stanza)
+            .map(|output| output.edges.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the attributes of graph nodes and edges set by the matches of the stanza at the
+    /// given location, in the order in which they were set.
+    pub fn attrs_set_by(&self, stanza: Location) -> &[(MutationTarget, Identifier)] {
+        self.stanza_output(stanza)
+            .map(|output| output.attributes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Displays the output of each stanza of a file, under a heading for the stanza, in the order
+    /// in which the stanzas are executed.  Each stanza lists the graph nodes and edges it created,
+    /// followed by the attributes it set, with their final values.  Stanzas that produced nothing
+    /// are included, so that it is visible that they did not match.  The output is only recorded
+    /// if provenance is enabled.
+    pub fn pretty_print_by_stanza<'a>(&'a self, file: &'a ast::File) -> impl fmt::Display + 'a {
+        DisplayByStanza { graph: self, file }
+    }
+
+    /// Returns the provenance step that set an attribute of a graph node.  Provenance is only
+    /// recorded if enabled using [`ExecutionConfig::provenance`][crate::ExecutionConfig::provenance].
+    pub fn attribute_provenance(
+        &self,
+        node: GraphNodeRef,
+        name: &str,
+    ) -> Option<ProvenanceStepRef> {
+        self[node].attributes.get(name)?;
+        let provenance = self.provenance.as_ref()?;
+        provenance
+            .attributes
+            .get(&(node.0, Identifier::from(name)))
+            .copied()
+    }
+
+    /// Explains how the value of an attribute of a graph node was computed, if provenance was
+    /// recorded for it.  The result can be displayed, and shows the chain of steps that produced
+    /// the value, up to [`DEFAULT_EXPLANATION_DEPTH`][] steps deep.
+    pub fn explain_attribute<'a>(
+        &'a self,
+        node: GraphNodeRef,
+        name: &str,
+    ) -> Option<AttributeExplanation<'a, 'tree>> {
+        let step = self.attribute_provenance(node, name)?;
+        Some(AttributeExplanation {
+            graph: self,
+            node,
+            name: Identifier::from(name),
+            step,
+            max_depth: DEFAULT_EXPLANATION_DEPTH,
+        })
+    }
+}
+
+impl Index<ProvenanceStepRef> for Graph<'_> {
+    type Output = ProvenanceStep;
+    fn index(&self, index: ProvenanceStepRef) -> &ProvenanceStep {
+        &self
+            .provenance
+            .as_ref()
+            .expect("graph has no provenance")
+            .steps[index.0 as usize]
+    }
+}
+
+/// An explanation of how the value of an attribute was computed
+pub struct AttributeExplanation<'a, 'tree> {
+    graph: &'a Graph<'tree>,
+    node: GraphNodeRef,
+    name: Identifier,
+    step: ProvenanceStepRef,
+    max_depth: usize,
+}
+
+impl<'a, 'tree> AttributeExplanation<'a, 'tree> {
+    /// Sets the maximum number of steps shown for each chain of steps.
+    pub fn max_depth(self, max_depth: usize) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    fn fmt_step(
+        &self,
+        f: &mut fmt::Formatter,
+        step: ProvenanceStepRef,
+        depth: usize,
+    ) -> fmt::Result {
+        let indent = 2 * (depth + 1);
+        if depth == self.max_depth {
+            return writeln!(f, "{:indent$}...", "", indent = indent);
+        }
+        let step = &self.graph[step];
+        writeln!(
+            f,
+            "{:indent$}{} at {}",
+            "",
+            step.origin,
+            step.location,
+            indent = indent
+        )?;
+        for input in &step.inputs {
+            self.fmt_step(f, *input, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AttributeExplanation<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.graph[self.node]
+            .attributes
+            .get(&self.name)
+            .expect("missing explained attribute");
+        writeln!(
+            f,
+            "attribute {} of graph node {} = {:?}",
+            self.name,
+            self.node.index(),
+            value
+        )?;
+        self.fmt_step(f, self.step, 0)
+    }
+}
+
+struct DisplayByStanza<'a, 'tree> {
+    graph: &'a Graph<'tree>,
+    file: &'a ast::File,
+}
+
+impl fmt::Display for DisplayByStanza<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let graph = self.graph;
+        for stanza in &self.file.stanzas {
+            let location = stanza.range.start;
+            match &stanza.name {
+                Some(name) => writeln!(f, "stanza {} at {}", name, location)?,
+                None => writeln!(f, "stanza at {}", location)?,
+            }
+            for node in graph.nodes_created_by(location) {
+                writeln!(f, "  node {}", node.index())?;
+            }
+            for (source, sink) in graph.edges_created_by(location) {
+                writeln!(f, "  edge {} -> {}", source.index(), sink.index())?;
+            }
+            for (target, name) in graph.attrs_set_by(location) {
+                let (target, value) = match target {
+                    MutationTarget::GraphNode(node) => (
+                        format!("{}", node.index()),
+                        graph[*node].attributes.get(name),
+                    ),
+                    MutationTarget::Edge(source, sink) => (
+                        format!("{} -> {}", source.index(), sink.index()),
+                        graph[*source]
+                            .get_edge(*sink)
+                            .and_then(|edge| edge.attributes.get(name)),
+                    ),
+                };
+                match value {
+                    Some(value) => writeln!(f, "  attr ({}) {} = {:?}", target, name, value)?,
+                    None => writeln!(f, "  attr ({}) {}", target, name)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Queries that find graph nodes by their attributes and edges
+//!
+//! A query is built from [`Graph::query`][], by adding conditions on the attributes of graph
+//! nodes, and on their outgoing edges and the graph nodes that those edges lead to:
+//!
+//! ```
+//! # use tree_sitter_graph::graph::Graph;
+//! use tree_sitter_graph::graph::query::eq;
+//! use tree_sitter_graph::graph::query::gt;
+//!
+//! # let graph = Graph::new();
+//! let exported_definitions = graph
+//!     .query()
+//!     .nodes()
+//!     .where_attr("kind", eq("definition"))
+//!     .where_attr("is_exported", eq(true))
+//!     .with_edge(|e| {
+//!         e.where_attr("precedence", gt(5))
+//!             .to_node(|n| n.where_attr("kind", eq("scope")))
+//!     })
+//!     .collect();
+//! # assert!(exported_definitions.is_empty());
+//! ```
+//!
+//! Attribute predicates never fail.  An attribute that is missing, or whose value has a different
+//! type than the value it is compared to, does not match.
+
+use std::collections::BTreeSet;
+use std::mem::discriminant;
+
+use crate::Identifier;
+
+use super::Attributes;
+use super::Graph;
+use super::GraphNodeRef;
+use super::Value;
+
+/// A predicate on the value of an attribute
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AttrPredicate {
+    /// The attribute exists, with any value
+    Exists,
+    /// The attribute is equal to the value
+    Eq(Value),
+    /// The attribute has the same type as the value, and is not equal to it
+    Ne(Value),
+    /// The attribute has the same type as the value, and is greater than it
+    Gt(Value),
+    /// The attribute has the same type as the value, and is less than it
+    Lt(Value),
+    /// The attribute is equal to one of the values
+    In(BTreeSet<Value>),
+}
+
+impl AttrPredicate {
+    /// Returns whether an attribute value, or a missing attribute, satisfies this predicate.
+    /// Integers, floats, strings, and source locations are ordered as expected; values of other
+    /// types are ordered consistently with [`Value`][]'s `Ord` implementation.
+    pub fn matches(&self, value: Option<&Value>) -> bool {
+        let value = match value {
+            Some(value) => value,
+            None => return false,
+        };
+        let same_type = |other: &Value| discriminant(value) == discriminant(other);
+        match self {
+            Self::Exists => true,
+            Self::Eq(other) => value == other,
+            Self::Ne(other) => same_type(other) && value != other,
+            Self::Gt(other) => same_type(other) && value > other,
+            Self::Lt(other) => same_type(other) && value < other,
+            Self::In(values) => values.contains(value),
+        }
+    }
+}
+
+/// Returns a predicate that matches attributes that exist, with any value.
+pub fn exists() -> AttrPredicate {
+    AttrPredicate::Exists
+}
+
+/// Returns a predicate that matches attributes equal to a value.
+pub fn eq<V: Into<Value>>(value: V) -> AttrPredicate {
+    AttrPredicate::Eq(value.into())
+}
+
+/// Returns a predicate that matches attributes of the same type as a value, that are not equal
+/// to it.
+pub fn ne<V: Into<Value>>(value: V) -> AttrPredicate {
+    AttrPredicate::Ne(value.into())
+}
+
+/// Returns a predicate that matches attributes of the same type as a value, that are greater
+/// than it.
+pub fn gt<V: Into<Value>>(value: V) -> AttrPredicate {
+    AttrPredicate::Gt(value.into())
+}
+
+/// Returns a predicate that matches attributes of the same type as a value, that are less than
+/// it.
+pub fn lt<V: Into<Value>>(value: V) -> AttrPredicate {
+    AttrPredicate::Lt(value.into())
+}
+
+/// Returns a predicate that matches attributes equal to one of the values.
+pub fn in_set<I, V>(values: I) -> AttrPredicate
+where
+    I: IntoIterator<Item = V>,
+    V: Into<Value>,
+{
+    AttrPredicate::In(values.into_iter().map(Into::into).collect())
+}
+
+/// The conditions that a graph node must satisfy: predicates on its attributes, and outgoing
+/// edges that it must have.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodePattern {
+    attrs: Vec<(Identifier, AttrPredicate)>,
+    edges: Vec<EdgePattern>,
+}
+
+impl NodePattern {
+    /// Creates a pattern that matches every graph node.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the attribute `name` to satisfy a predicate.
+    pub fn where_attr<N: Into<Identifier>>(mut self, name: N, predicate: AttrPredicate) -> Self {
+        self.attrs.push((name.into(), predicate));
+        self
+    }
+
+    /// Requires an outgoing edge that matches the pattern built by `build`.  If this is used
+    /// several times, each edge pattern must be matched, possibly by the same edge.
+    pub fn with_edge<F>(mut self, build: F) -> Self
+    where
+        F: FnOnce(EdgePattern) -> EdgePattern,
+    {
+        self.edges.push(build(EdgePattern::new()));
+        self
+    }
+
+    /// Returns whether a graph node matches this pattern.  Removed graph nodes never match.
+    pub fn matches(&self, graph: &Graph, node: GraphNodeRef) -> bool {
+        self.match_edges(graph, node).is_some()
+    }
+
+    /// Returns the sinks of the matching edges of a graph node for each edge pattern, if the
+    /// node matches.
+    fn match_edges(&self, graph: &Graph, node: GraphNodeRef) -> Option<Vec<Vec<GraphNodeRef>>> {
+        if graph.is_removed(node) || !attrs_match(&self.attrs, &graph[node].attributes) {
+            return None;
+        }
+        let mut edges = Vec::with_capacity(self.edges.len());
+        for pattern in &self.edges {
+            let sinks = graph[node]
+                .iter_edges()
+                .filter(|(sink, edge)| {
+                    attrs_match(&pattern.attrs, &edge.attributes)
+                        && pattern.sink.matches(graph, *sink)
+                })
+                .map(|(sink, _)| sink)
+                .collect::<Vec<_>>();
+            if sinks.is_empty() {
+                return None;
+            }
+            edges.push(sinks);
+        }
+        Some(edges)
+    }
+}
+
+/// The conditions that an edge must satisfy: predicates on its attributes, and a pattern for the
+/// graph node it leads to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgePattern {
+    attrs: Vec<(Identifier, AttrPredicate)>,
+    sink: NodePattern,
+}
+
+impl EdgePattern {
+    /// Creates a pattern that matches every edge.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the attribute `name` of the edge to satisfy a predicate.
+    pub fn where_attr<N: Into<Identifier>>(mut self, name: N, predicate: AttrPredicate) -> Self {
+        self.attrs.push((name.into(), predicate));
+        self
+    }
+
+    /// Requires the graph node that the edge leads to to match the pattern built by `build`.
+    pub fn to_node<F>(mut self, build: F) -> Self
+    where
+        F: FnOnce(NodePattern) -> NodePattern,
+    {
+        self.sink = build(self.sink);
+        self
+    }
+}
+
+fn attrs_match(predicates: &[(Identifier, AttrPredicate)], attributes: &Attributes) -> bool {
+    predicates
+        .iter()
+        .all(|(name, predicate)| predicate.matches(attributes.get(name)))
+}
+
+/// The starting point of a query over a graph, returned by [`Graph::query`][]
+pub struct GraphQuery<'a, 'tree> {
+    graph: &'a Graph<'tree>,
+}
+
+impl<'a, 'tree> GraphQuery<'a, 'tree> {
+    /// Starts a query for graph nodes, which matches every graph node that has not been removed.
+    pub fn nodes(self) -> NodeQuery<'a, 'tree> {
+        NodeQuery {
+            graph: self.graph,
+            pattern: NodePattern::new(),
+        }
+    }
+}
+
+/// A query for the graph nodes of a graph that match a [`NodePattern`][]
+pub struct NodeQuery<'a, 'tree> {
+    graph: &'a Graph<'tree>,
+    pattern: NodePattern,
+}
+
+/// A graph node that matches a query, together with the sinks of its matching edges
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NodeMatch {
+    /// The matching graph node
+    pub node: GraphNodeRef,
+    /// For every edge pattern of the query, in the order in which they were added, the sinks of
+    /// the outgoing edges of the node that match it, ordered by sink
+    pub edges: Vec<Vec<GraphNodeRef>>,
+}
+
+impl<'a, 'tree> NodeQuery<'a, 'tree> {
+    /// Requires the attribute `name` to satisfy a predicate.
+    pub fn where_attr<N: Into<Identifier>>(self, name: N, predicate: AttrPredicate) -> Self {
+        Self {
+            graph: self.graph,
+            pattern: self.pattern.where_attr(name, predicate),
+        }
+    }
+
+    /// Requires an outgoing edge that matches the pattern built by `build`.  If this is used
+    /// several times, each edge pattern must be matched, possibly by the same edge.
+    pub fn with_edge<F>(self, build: F) -> Self
+    where
+        F: FnOnce(EdgePattern) -> EdgePattern,
+    {
+        Self {
+            graph: self.graph,
+            pattern: self.pattern.with_edge(build),
+        }
+    }
+
+    /// Returns the pattern that this query matches graph nodes against.
+    pub fn pattern(&self) -> &NodePattern {
+        &self.pattern
+    }
+
+    /// Returns the matching graph nodes, in the order in which they were created.
+    pub fn collect(self) -> Vec<GraphNodeRef> {
+        self.candidates()
+            .filter(|node| self.pattern.matches(self.graph, *node))
+            .collect()
+    }
+
+    /// Returns the matching graph nodes, together with the sinks of their matching edges, in
+    /// the order in which the nodes were created.
+    pub fn matches(self) -> Vec<NodeMatch> {
+        self.candidates()
+            .filter_map(|node| {
+                let edges = self.pattern.match_edges(self.graph, node)?;
+                Some(NodeMatch { node, edges })
+            })
+            .collect()
+    }
+
+    /// Returns the graph nodes that can match the query, in the order in which they were
+    /// created.  This scans all graph nodes; it is the place to use an index of attribute
+    /// values, once graphs maintain one after execution.
+    fn candidates(&self) -> impl Iterator<Item = GraphNodeRef> {
+        self.graph.iter_nodes()
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Starts a query over the graph nodes of this graph.  See the [`query`][crate::graph::query]
+    /// module for an example.
+    pub fn query(&self) -> GraphQuery<'_, 'tree> {
+        GraphQuery { graph: self }
+    }
+}
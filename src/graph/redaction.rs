@@ -0,0 +1,281 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Redaction of attribute values, for graphs that are shared with others
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use regex::Regex;
+#[cfg(feature = "serde")]
+use thiserror::Error;
+
+use crate::Identifier;
+
+use super::canonical::sha256;
+use super::Attributes;
+use super::Graph;
+use super::Value;
+
+/// Rules that determine which attributes of graph nodes and edges are removed or rewritten by
+/// [`Graph::redact`][].  Rules apply to attributes by name, and to every string in their value,
+/// including the strings in lists and sets.  Each attribute is handled by the first of the
+/// following that applies to it:
+///
+/// - A dropped attribute is removed.
+/// - The strings of a replaced attribute are replaced by a fixed placeholder.
+/// - The strings of a hashed attribute are replaced by a salted hash, so that equal strings still
+///   have equal values, without revealing their content.
+/// - The strings of all other attributes are rewritten by the scrubbers, in order.
+///
+/// Values other than strings, such as syntax nodes, are kept.
+#[derive(Clone, Debug, Default)]
+pub struct RedactionRules {
+    dropped: BTreeSet<Identifier>,
+    replaced: BTreeMap<Identifier, StringRedaction>,
+    salt: String,
+    scrubbers: Vec<(Regex, String)>,
+}
+
+/// How the strings of an attribute are redacted
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum StringRedaction {
+    Placeholder(String),
+    Hash,
+}
+
+impl RedactionRules {
+    /// Creates rules that leave every attribute unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes the attribute `name`.
+    pub fn drop_attribute<N: Into<Identifier>>(&mut self, name: N) {
+        self.dropped.insert(name.into());
+    }
+
+    /// Replaces the strings of the attribute `name` by a placeholder.
+    pub fn replace_attribute<N: Into<Identifier>>(&mut self, name: N, placeholder: &str) {
+        self.replaced.insert(
+            name.into(),
+            StringRedaction::Placeholder(placeholder.to_string()),
+        );
+    }
+
+    /// Replaces the strings of the attribute `name` by their salted hash: the lowercase
+    /// hexadecimal SHA-256 digest of the length of the salt in bytes, as 8 bytes big-endian,
+    /// followed by the salt and the string.
+    pub fn hash_attribute<N: Into<Identifier>>(&mut self, name: N) {
+        self.replaced.insert(name.into(), StringRedaction::Hash);
+    }
+
+    /// Sets the salt of hashed attributes.  Graphs redacted with the same salt hash equal strings
+    /// to the same value.  The salt is empty by default.
+    pub fn set_salt(&mut self, salt: &str) {
+        self.salt = salt.to_string();
+    }
+
+    /// Adds a scrubber, which replaces every match of `regex` in the strings of attributes that
+    /// are not dropped, replaced, or hashed.  The replacement can refer to the capture groups of
+    /// the match, as in [`Regex::replace_all`][].
+    pub fn add_scrubber(&mut self, regex: Regex, replacement: &str) {
+        self.scrubbers.push((regex, replacement.to_string()));
+    }
+
+    /// Parses redaction rules from JSON, in the following format, where every entry is
+    /// optional.  Scrubbers without a `replacement` replace matches with `[redacted]`.
+    ///
+    /// ```json
+    /// {
+    ///   "drop": ["text"],
+    ///   "replace": { "path": "[path]" },
+    ///   "hash": ["name"],
+    ///   "salt": "partner",
+    ///   "scrub": [{ "pattern": "/home/[^/]+", "replacement": "/home/user" }]
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, RedactionRulesError> {
+        let json = serde_json::from_str::<serde_json::Value>(json)?;
+        let json = json
+            .as_object()
+            .ok_or_else(|| RedactionRulesError::Invalid("expected an object".to_string()))?;
+        let mut rules = RedactionRules::new();
+        for (key, value) in json {
+            match key.as_str() {
+                "drop" => {
+                    for name in json_strings(value, "drop")? {
+                        rules.drop_attribute(name);
+                    }
+                }
+                "replace" => {
+                    let replace = value.as_object().ok_or_else(|| {
+                        RedactionRulesError::Invalid("expected an object for replace".to_string())
+                    })?;
+                    for (name, placeholder) in replace {
+                        let placeholder = placeholder.as_str().ok_or_else(|| {
+                            RedactionRulesError::Invalid(format!(
+                                "expected a string placeholder for {}",
+                                name
+                            ))
+                        })?;
+                        rules.replace_attribute(name.as_str(), placeholder);
+                    }
+                }
+                "hash" => {
+                    for name in json_strings(value, "hash")? {
+                        rules.hash_attribute(name);
+                    }
+                }
+                "salt" => {
+                    let salt = value.as_str().ok_or_else(|| {
+                        RedactionRulesError::Invalid("expected a string for salt".to_string())
+                    })?;
+                    rules.set_salt(salt);
+                }
+                "scrub" => {
+                    let invalid = || {
+                        RedactionRulesError::Invalid(
+                            "expected a list of objects with a pattern for scrub".to_string(),
+                        )
+                    };
+                    for scrubber in value.as_array().ok_or_else(invalid)? {
+                        let pattern = scrubber
+                            .get("pattern")
+                            .and_then(|pattern| pattern.as_str())
+                            .ok_or_else(invalid)?;
+                        let replacement = match scrubber.get("replacement") {
+                            Some(replacement) => replacement.as_str().ok_or_else(invalid)?,
+                            None => "[redacted]",
+                        };
+                        let regex = Regex::new(pattern).map_err(|err| {
+                            RedactionRulesError::Invalid(format!(
+                                "invalid pattern {}: {}",
+                                pattern, err
+                            ))
+                        })?;
+                        rules.add_scrubber(regex, replacement);
+                    }
+                }
+                _ => {
+                    return Err(RedactionRulesError::Invalid(format!(
+                        "unexpected entry {}",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    fn redact_attributes(&self, attributes: &mut Attributes) {
+        attributes
+            .values
+            .retain(|name, _| !self.dropped.contains(name));
+        attributes
+            .defaults
+            .retain(|name| !self.dropped.contains(name));
+        attributes
+            .hidden
+            .retain(|name| !self.dropped.contains(name));
+        for (name, value) in attributes.values.iter_mut() {
+            match self.replaced.get(name) {
+                Some(StringRedaction::Placeholder(placeholder)) => {
+                    map_strings(value, &|_| placeholder.clone())
+                }
+                Some(StringRedaction::Hash) => map_strings(value, &|string| self.hash(string)),
+                None if !self.scrubbers.is_empty() => {
+                    map_strings(value, &|string| self.scrub(string))
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn hash(&self, string: &str) -> String {
+        let mut data = Vec::with_capacity(8 + self.salt.len() + string.len());
+        data.extend_from_slice(&(self.salt.len() as u64).to_be_bytes());
+        data.extend_from_slice(self.salt.as_bytes());
+        data.extend_from_slice(string.as_bytes());
+        sha256(&data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn scrub(&self, string: &str) -> String {
+        let mut string = string.to_string();
+        for (regex, replacement) in &self.scrubbers {
+            string = regex
+                .replace_all(&string, replacement.as_str())
+                .into_owned();
+        }
+        string
+    }
+}
+
+/// Replaces every string in a value, including the strings in lists, sets, and maps.  The keys
+/// of maps are left unchanged.
+fn map_strings(value: &mut Value, f: &dyn Fn(&str) -> String) {
+    match value {
+        Value::String(string) => *string = f(string),
+        Value::List(values) => values.iter_mut().for_each(|value| map_strings(value, f)),
+        Value::Set(values) => {
+            *values = std::mem::take(values)
+                .into_iter()
+                .map(|mut value| {
+                    map_strings(&mut value, f);
+                    value
+                })
+                .collect()
+        }
+        Value::Map(values) => values.values_mut().for_each(|value| map_strings(value, f)),
+        _ => {}
+    }
+}
+
+#[cfg(feature = "serde")]
+fn json_strings<'a>(
+    value: &'a serde_json::Value,
+    key: &str,
+) -> Result<Vec<&'a str>, RedactionRulesError> {
+    let invalid =
+        || RedactionRulesError::Invalid(format!("expected a list of strings for {}", key));
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|name| name.as_str().ok_or_else(invalid))
+        .collect()
+}
+
+/// An error that can occur when parsing [`RedactionRules`][] from JSON
+#[cfg(feature = "serde")]
+#[derive(Debug, Error)]
+pub enum RedactionRulesError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid redaction rules: {0}")]
+    Invalid(String),
+}
+
+impl<'tree> Graph<'tree> {
+    /// Redacts the attributes of all graph nodes and edges of this graph, according to the
+    /// given rules.  Redaction is meant to happen once the graph is complete, right before it is
+    /// written out or shared.
+    pub fn redact(&mut self, rules: &RedactionRules) {
+        for node in &mut self.graph_nodes {
+            rules.redact_attributes(&mut node.attributes);
+            for (_, edge) in node.outgoing_edges.iter_mut() {
+                rules.redact_attributes(&mut edge.attributes);
+            }
+        }
+        // the index refers to the values from before redaction
+        self.reset_attribute_index();
+    }
+}
@@ -0,0 +1,668 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2021, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Serialization of graphs, available with the `serde` feature
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::stdout;
+use std::ops::Range;
+use std::path::Path;
+
+use serde::ser::SerializeMap;
+use serde::ser::SerializeSeq;
+use serde::Serialize;
+use serde::Serializer;
+
+use crate::Identifier;
+use crate::Location;
+
+use super::Attributes;
+use super::CombinedFailure;
+use super::CombinedFile;
+use super::CombinedGraph;
+use super::Graph;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::GraphVisitor;
+use super::PositionConverter;
+use super::PositionEncoding;
+use super::SyntaxNodeRef;
+use super::Value;
+
+/// Configures how a graph is serialized.  Syntax nodes are serialized with their positions in the
+/// source text, with columns counted in the configured encoding.
+pub struct SerializationConfig<'a> {
+    source: &'a str,
+    position_encoding: PositionEncoding,
+    include_spans: bool,
+    syntax_node_rendering: SyntaxNodeRendering,
+    max_text_length: Option<usize>,
+    include_hidden: bool,
+}
+
+/// Determines how syntax node values are serialized, wherever they appear in attribute values,
+/// including inside lists and sets.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SyntaxNodeRendering {
+    /// A `syntaxNode` value with the node's `id`, and its `row` and `column`.  This is the
+    /// default.
+    #[default]
+    Id,
+    /// The span of the node: an object with the `kind` of the node, and its `start` and `end`
+    /// positions, each with a `byte` offset, `row`, and `column`.
+    Span,
+    /// A `string` value with the source text of the node.
+    SourceText,
+    /// A `syntaxNode` value with the node's `id`, `row`, and `column`, its `span`, and its source
+    /// `text`.
+    Full,
+}
+
+impl<'a> SerializationConfig<'a> {
+    /// Creates a configuration for serializing a graph that was built from the given source text.
+    /// Positions are serialized with columns counted in UTF-8 bytes by default.
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position_encoding: PositionEncoding::Utf8,
+            include_spans: false,
+            syntax_node_rendering: SyntaxNodeRendering::Id,
+            max_text_length: None,
+            include_hidden: false,
+        }
+    }
+
+    /// Sets the encoding in which the columns of syntax node positions are counted.
+    pub fn position_encoding(self, position_encoding: PositionEncoding) -> Self {
+        Self {
+            position_encoding,
+            ..self
+        }
+    }
+
+    /// Sets whether the spans of syntax nodes are included.  If set, every graph node has a
+    /// `span` entry with the span of the syntax node that owns it, or `null` if it has no owner,
+    /// and every syntax node value has a `span` entry with its own span.  A span is an object
+    /// with the `kind` of the syntax node, and its `start` and `end` positions, each with a
+    /// `byte` offset, `row`, and `column`.  A graph node is owned by the syntax nodes in whose
+    /// scoped variables it is stored; if there are several, the one with the smallest span is
+    /// used.  The span of an anonymous syntax node, such as an operator, also has a `named` entry
+    /// that is `false`.
+    ///
+    /// The spans are recorded in the graph, so they are available without the syntax tree.
+    pub fn include_spans(self, include_spans: bool) -> Self {
+        Self {
+            include_spans,
+            ..self
+        }
+    }
+
+    /// Sets how syntax node values are serialized.
+    pub fn syntax_node_rendering(self, syntax_node_rendering: SyntaxNodeRendering) -> Self {
+        Self {
+            syntax_node_rendering,
+            ..self
+        }
+    }
+
+    /// Sets the maximum length, in bytes, of the source text of syntax nodes that is serialized.
+    /// Longer texts are cut at the last character boundary within the limit, and end with `…`.
+    /// By default, texts are not truncated.
+    pub fn max_text_length(self, max_text_length: usize) -> Self {
+        Self {
+            max_text_length: Some(max_text_length),
+            ..self
+        }
+    }
+
+    /// Sets whether [hidden][super::Attributes::is_hidden] attributes are serialized, which is
+    /// useful for debugging.  They are left out by default.  If set, every graph node and edge
+    /// with attributes that were hidden with [`add_hidden`][super::Attributes::add_hidden] has a
+    /// `hidden` entry with a list of their names, so that deserializing the graph restores them
+    /// as hidden attributes.  Attributes whose names start with `_` are hidden without being
+    /// listed.
+    pub fn include_hidden(self, include_hidden: bool) -> Self {
+        Self {
+            include_hidden,
+            ..self
+        }
+    }
+
+    fn context(&self) -> SerializationContext<'a> {
+        SerializationContext {
+            source: self.source,
+            converter: PositionConverter::new(self.source, self.position_encoding),
+            include_spans: self.include_spans,
+            syntax_node_rendering: self.syntax_node_rendering,
+            max_text_length: self.max_text_length,
+            include_hidden: self.include_hidden,
+        }
+    }
+}
+
+/// The parts of a serialization configuration that are needed while serializing
+struct SerializationContext<'a> {
+    source: &'a str,
+    converter: PositionConverter<'a>,
+    include_spans: bool,
+    syntax_node_rendering: SyntaxNodeRendering,
+    max_text_length: Option<usize>,
+    include_hidden: bool,
+}
+
+impl SerializationContext<'_> {
+    /// Returns the source text of a syntax node, truncated to the maximum length.
+    fn source_text(&self, node: &SyntaxNodeRef) -> String {
+        let text = self.source.get(node.byte_range()).unwrap_or_default();
+        match self.max_text_length {
+            Some(max_length) if text.len() > max_length => {
+                let mut end = max_length;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                format!("{}…", &text[..end])
+            }
+            _ => text.to_string(),
+        }
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    pub fn display_json(&self, path: Option<&Path>) -> std::io::Result<()> {
+        let s = serde_json::to_string_pretty(self).unwrap();
+        write_json(path, s)
+    }
+
+    /// Writes the graph as JSON, including the positions of syntax nodes as configured.
+    pub fn display_json_with(
+        &self,
+        path: Option<&Path>,
+        config: &SerializationConfig,
+    ) -> std::io::Result<()> {
+        let context = config.context();
+        let s = serde_json::to_string_pretty(&SerializeGraph(self, Some(&context))).unwrap();
+        write_json(path, s)
+    }
+
+    /// Returns a serializable version of the graph, which includes the positions of syntax nodes
+    /// as configured.
+    pub fn serialize_with<'a>(&'a self, config: &SerializationConfig<'a>) -> impl Serialize + 'a {
+        struct SerializeWithConfig<'a, 'tree>(&'a Graph<'tree>, SerializationContext<'a>);
+
+        impl Serialize for SerializeWithConfig<'_, '_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                SerializeGraph(self.0, Some(&self.1)).serialize(serializer)
+            }
+        }
+
+        SerializeWithConfig(self, config.context())
+    }
+}
+
+impl CombinedGraph<'_> {
+    /// Writes the combined graph as JSON, as an object with the serialized `graph`, the `files`
+    /// it was built from, each with its `path` and the `start` and `end` of the range of its
+    /// graph node indices, and the `failures`, each with the `path` of a file that was left out
+    /// and its `error`.
+    pub fn display_json(&self, path: Option<&Path>) -> std::io::Result<()> {
+        let s = serde_json::to_string_pretty(self).unwrap();
+        write_json(path, s)
+    }
+}
+
+impl Serialize for CombinedGraph<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("graph", self.graph())?;
+        map.serialize_entry("files", self.files())?;
+        map.serialize_entry("failures", self.failures())?;
+        map.end()
+    }
+}
+
+impl Serialize for CombinedFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("path", &self.path.display().to_string())?;
+        map.serialize_entry("start", &self.nodes.start)?;
+        map.serialize_entry("end", &self.nodes.end)?;
+        map.end()
+    }
+}
+
+impl Serialize for CombinedFailure {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("path", &self.path.display().to_string())?;
+        map.serialize_entry("error", &self.error)?;
+        map.end()
+    }
+}
+
+fn write_json(path: Option<&Path>, s: String) -> std::io::Result<()> {
+    path.map_or(stdout().write_all(s.as_bytes()), |path| {
+        File::create(path)?.write_all(s.as_bytes())
+    })
+}
+
+impl<'tree> Serialize for Graph<'tree> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeGraph(self, None).serialize(serializer)
+    }
+}
+
+struct SerializeGraph<'a, 'tree>(&'a Graph<'tree>, Option<&'a SerializationContext<'a>>);
+
+impl<'a, 'tree> Serialize for SerializeGraph<'a, 'tree> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let graph = self.0;
+        let owners = match self.1 {
+            Some(context) if context.include_spans => Some(graph.graph_node_owners()),
+            _ => None,
+        };
+        let include_hidden = self.1.is_some_and(|context| context.include_hidden);
+        let mut visitor = SerializingVisitor {
+            seq: serializer.serialize_seq(Some(graph.iter_nodes().count()))?,
+            owners,
+            context: self.1,
+            hidden_from: include_hidden.then_some(graph),
+            node: None,
+            attributes: Vec::new(),
+            hidden: Vec::new(),
+            node_hidden: 0..0,
+            edges: Vec::new(),
+            edge_attributes: Vec::new(),
+        };
+        graph.visit(&mut visitor)?;
+        visitor.seq.end()
+    }
+}
+
+/// Serializes each graph node as it is visited, once its attributes and edges have been
+/// collected.  The buffers are reused for every graph node.
+struct SerializingVisitor<'a, S> {
+    seq: S,
+    owners: Option<HashMap<GraphNodeID, SyntaxNodeRef>>,
+    context: Option<&'a SerializationContext<'a>>,
+    // the graph whose explicitly hidden attributes are listed, if hidden attributes are included
+    hidden_from: Option<&'a Graph<'a>>,
+    node: Option<GraphNodeRef>,
+    attributes: Vec<(&'a Identifier, &'a Value)>,
+    // the names of the explicitly hidden attributes of the node and its edges, and the range of
+    // those of the node
+    hidden: Vec<&'a Identifier>,
+    node_hidden: Range<usize>,
+    // the sink of each edge, the range of its attributes in `edge_attributes`, and the range of
+    // its explicitly hidden attributes in `hidden`
+    edges: Vec<(GraphNodeID, Range<usize>, Range<usize>)>,
+    edge_attributes: Vec<(&'a Identifier, &'a Value)>,
+}
+
+impl<'a, S> SerializingVisitor<'a, S> {
+    /// Adds the sorted names of the explicitly hidden attributes to `hidden`, and returns their
+    /// range.
+    fn add_hidden(&mut self, attributes: Option<&'a Attributes>) -> Range<usize> {
+        let start = self.hidden.len();
+        if let Some(attributes) = attributes {
+            self.hidden.extend(attributes.explicitly_hidden());
+            self.hidden[start..].sort_unstable();
+        }
+        start..self.hidden.len()
+    }
+}
+
+impl<'a, S: SerializeSeq> GraphVisitor<'a> for SerializingVisitor<'a, S> {
+    type Error = S::Error;
+
+    fn include_hidden(&self) -> bool {
+        self.hidden_from.is_some()
+    }
+
+    fn begin_node(&mut self, node: GraphNodeRef) -> Result<(), S::Error> {
+        self.node = Some(node);
+        self.attributes.clear();
+        self.hidden.clear();
+        self.edges.clear();
+        self.edge_attributes.clear();
+        let attributes = self.hidden_from.map(|graph| &graph[node].attributes);
+        self.node_hidden = self.add_hidden(attributes);
+        Ok(())
+    }
+
+    fn attribute(&mut self, name: &'a Identifier, value: &'a Value) -> Result<(), S::Error> {
+        self.attributes.push((name, value));
+        Ok(())
+    }
+
+    fn edge(
+        &mut self,
+        sink: GraphNodeRef,
+        attributes: &[(&'a Identifier, &'a Value)],
+    ) -> Result<(), S::Error> {
+        let start = self.edge_attributes.len();
+        self.edge_attributes.extend_from_slice(attributes);
+        let edge = match (self.hidden_from, self.node) {
+            (Some(graph), Some(node)) => graph[node].get_edge(sink),
+            _ => None,
+        };
+        let hidden = self.add_hidden(edge.map(|edge| &edge.attributes));
+        self.edges
+            .push((sink.0, start..self.edge_attributes.len(), hidden));
+        Ok(())
+    }
+
+    fn end_node(&mut self, node: GraphNodeRef) -> Result<(), S::Error> {
+        let owner = self.owners.as_ref().map(|owners| owners.get(&node.0));
+        self.seq.serialize_element(&SerializeGraphNode {
+            node,
+            edges: &self.edges,
+            edge_attributes: &self.edge_attributes,
+            attributes: &self.attributes,
+            hidden: &self.hidden,
+            node_hidden: self.node_hidden.clone(),
+            owner,
+            context: self.context,
+        })
+    }
+}
+
+struct SerializeGraphNode<'a, 'v> {
+    node: GraphNodeRef,
+    edges: &'v [(GraphNodeID, Range<usize>, Range<usize>)],
+    edge_attributes: &'v [(&'a Identifier, &'a Value)],
+    attributes: &'v [(&'a Identifier, &'a Value)],
+    hidden: &'v [&'a Identifier],
+    node_hidden: Range<usize>,
+    owner: Option<Option<&'v SyntaxNodeRef>>,
+    context: Option<&'a SerializationContext<'a>>,
+}
+
+impl Serialize for SerializeGraphNode<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // serializing as a map instead of a struct so we don't have to encode a struct name
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("id", &self.node.0)?;
+        map.serialize_entry("edges", &SerializeGraphNodeEdges(self))?;
+        map.serialize_entry(
+            "attrs",
+            &SerializeSortedAttributes(self.attributes, self.context),
+        )?;
+        let hidden = &self.hidden[self.node_hidden.clone()];
+        if !hidden.is_empty() {
+            map.serialize_entry("hidden", hidden)?;
+        }
+        if let (Some(owner), Some(context)) = (self.owner, self.context) {
+            map.serialize_entry("span", &owner.map(|owner| SerializeSpan(owner, context)))?;
+        }
+        map.end()
+    }
+}
+
+struct SerializeGraphNodeEdges<'a, 'v, 'n>(&'n SerializeGraphNode<'a, 'v>);
+
+impl Serialize for SerializeGraphNodeEdges<'_, '_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = self.0;
+        let mut seq = serializer.serialize_seq(Some(node.edges.len()))?;
+        for (sink, attributes, hidden) in node.edges {
+            let attributes = &node.edge_attributes[attributes.clone()];
+            let hidden = &node.hidden[hidden.clone()];
+            seq.serialize_element(&SerializeGraphNodeEdge(
+                *sink,
+                attributes,
+                hidden,
+                node.context,
+            ))?;
+        }
+        seq.end()
+    }
+}
+
+struct SerializeGraphNodeEdge<'a, 'v>(
+    GraphNodeID,
+    &'v [(&'a Identifier, &'a Value)],
+    &'v [&'a Identifier],
+    Option<&'a SerializationContext<'a>>,
+);
+
+impl Serialize for SerializeGraphNodeEdge<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("sink", &self.0)?;
+        map.serialize_entry("attrs", &SerializeSortedAttributes(self.1, self.3))?;
+        if !self.2.is_empty() {
+            map.serialize_entry("hidden", self.2)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Attributes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeAttributes(self, None).serialize(serializer)
+    }
+}
+
+struct SerializeAttributes<'a>(&'a Attributes, Option<&'a SerializationContext<'a>>);
+
+impl<'a> Serialize for SerializeAttributes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // sort the attributes so that the serialized graph does not depend on hash map order
+        let mut attributes = self
+            .0
+            .values
+            .iter()
+            .filter(|(name, _)| !self.0.is_hidden(name))
+            .collect::<Vec<_>>();
+        attributes.sort_by_key(|(name, _)| *name);
+        SerializeSortedAttributes(&attributes, self.1).serialize(serializer)
+    }
+}
+
+struct SerializeSortedAttributes<'a, 'v>(
+    &'v [(&'a Identifier, &'a Value)],
+    Option<&'a SerializationContext<'a>>,
+);
+
+impl Serialize for SerializeSortedAttributes<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            map.serialize_entry(key, &SerializeValue(value, self.1))?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeValue(self, None).serialize(serializer)
+    }
+}
+
+impl Value {
+    /// Returns a serializable version of the value, which serializes syntax nodes as configured,
+    /// including those nested inside lists and sets.
+    pub fn serialize_with<'a>(&'a self, config: &SerializationConfig<'a>) -> impl Serialize + 'a {
+        struct SerializeWithConfig<'a>(&'a Value, SerializationContext<'a>);
+
+        impl Serialize for SerializeWithConfig<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                SerializeValue(self.0, Some(&self.1)).serialize(serializer)
+            }
+        }
+
+        SerializeWithConfig(self, config.context())
+    }
+}
+
+struct SerializeValue<'a>(&'a Value, Option<&'a SerializationContext<'a>>);
+
+impl<'a> SerializeValue<'a> {
+    fn values<I>(&self, values: I) -> Vec<SerializeValue<'a>>
+    where
+        I: IntoIterator<Item = &'a Value>,
+    {
+        values
+            .into_iter()
+            .map(|value| SerializeValue(value, self.1))
+            .collect()
+    }
+}
+
+impl<'a> Serialize for SerializeValue<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Null => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "null")?;
+                map.end()
+            }
+            Value::Boolean(bool) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "bool")?;
+                map.serialize_entry("bool", bool)?;
+                map.end()
+            }
+            Value::Integer(int) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "int")?;
+                map.serialize_entry("int", int)?;
+                map.end()
+            }
+            Value::Float(float) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "float")?;
+                map.serialize_entry("float", &float.get())?;
+                map.end()
+            }
+            Value::String(str) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "string")?;
+                map.serialize_entry("string", str)?;
+                map.end()
+            }
+            Value::List(list) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "list")?;
+                map.serialize_entry("values", &self.values(list))?;
+                map.end()
+            }
+            Value::Set(set) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "set")?;
+                map.serialize_entry("values", &self.values(set))?;
+                map.end()
+            }
+            Value::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), SerializeValue(value, self.1)))
+                    .collect::<BTreeMap<_, _>>();
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "map")?;
+                map.serialize_entry("entries", &entries)?;
+                map.end()
+            }
+            Value::Location(location) => {
+                let convert = |point| match self.1 {
+                    Some(context) => context.converter.convert(point),
+                    None => Location::from(point),
+                };
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "location")?;
+                map.serialize_entry(
+                    "start",
+                    &SerializePosition(location.start_byte, convert(location.start_point)),
+                )?;
+                map.serialize_entry(
+                    "end",
+                    &SerializePosition(location.end_byte, convert(location.end_point)),
+                )?;
+                map.end()
+            }
+            Value::SyntaxNode(node) => {
+                let rendering = self.1.map_or(SyntaxNodeRendering::Id, |context| {
+                    context.syntax_node_rendering
+                });
+                match (rendering, self.1) {
+                    (SyntaxNodeRendering::Span, Some(context)) => {
+                        return SerializeSpan(node, context).serialize(serializer);
+                    }
+                    (SyntaxNodeRendering::SourceText, Some(context)) => {
+                        let mut map = serializer.serialize_map(None)?;
+                        map.serialize_entry("type", "string")?;
+                        map.serialize_entry("string", &context.source_text(node))?;
+                        return map.end();
+                    }
+                    _ => {}
+                }
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "syntaxNode")?;
+                map.serialize_entry("id", &node.index)?;
+                if let Some(context) = self.1 {
+                    let location = node.location_in(&context.converter);
+                    map.serialize_entry("row", &location.row)?;
+                    map.serialize_entry("column", &location.column)?;
+                    let full = rendering == SyntaxNodeRendering::Full;
+                    if context.include_spans || full {
+                        map.serialize_entry("span", &SerializeSpan(node, context))?;
+                    }
+                    if full {
+                        map.serialize_entry("text", &context.source_text(node))?;
+                    }
+                }
+                map.end()
+            }
+            Value::GraphNode(node) => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "graphNode")?;
+                map.serialize_entry("id", &node.0)?;
+                map.end()
+            }
+        }
+    }
+}
+
+struct SerializePosition(usize, Location);
+
+impl Serialize for SerializePosition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("byte", &self.0)?;
+        map.serialize_entry("row", &self.1.row)?;
+        map.serialize_entry("column", &self.1.column)?;
+        map.end()
+    }
+}
+
+struct SerializeSpan<'a>(&'a SyntaxNodeRef, &'a SerializationContext<'a>);
+
+impl Serialize for SerializeSpan<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = self.0;
+        let converter = &self.1.converter;
+        let range = node.byte_range();
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", node.kind())?;
+        if !node.is_named() {
+            map.serialize_entry("named", &false)?;
+        }
+        map.serialize_entry(
+            "start",
+            &SerializePosition(range.start, node.location_in(converter)),
+        )?;
+        map.serialize_entry(
+            "end",
+            &SerializePosition(range.end, node.end_location_in(converter)),
+        )?;
+        map.end()
+    }
+}
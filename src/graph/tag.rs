@@ -0,0 +1,150 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Tags, under which syntax nodes own graph nodes
+
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::parser::is_ident;
+use crate::parser::is_ident_start;
+use crate::Identifier;
+
+/// The default maximum number of components of a [`Tag`][], see
+/// [`Tag::parse_with_max_depth`][]
+pub const MAX_TAG_DEPTH: usize = 64;
+
+/// A tag under which a syntax node owns a graph node, as returned by
+/// [`Graph::graph_nodes_for_syntax_node`][super::Graph::graph_nodes_for_syntax_node].  A tag is a
+/// non-empty sequence of components, written separated by dots, as in `definition.class.method`.
+/// Every component is a name as the graph DSL parses it.  Graph nodes stored in scoped variables
+/// are owned under a tag with a single component, the name of the variable.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Tag {
+    components: Vec<Identifier>,
+}
+
+/// An error parsing a tag
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum TagError {
+    #[error("Empty tag")]
+    Empty,
+    #[error("Empty tag component at byte {0}")]
+    EmptyComponent(usize),
+    #[error("Unexpected character {0:?} in tag at byte {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("Tag has more than the maximum of {0} components")]
+    TooDeep(usize),
+}
+
+impl Tag {
+    /// Parses a tag from its dotted form.  Every component must be a name that the graph DSL
+    /// accepts: it starts with a letter or `_`, followed by letters, digits, `_`, or `-`.  A tag
+    /// can have at most [`MAX_TAG_DEPTH`][] components.
+    pub fn parse(tag: &str) -> Result<Tag, TagError> {
+        Self::parse_with_max_depth(tag, MAX_TAG_DEPTH)
+    }
+
+    /// Parses a tag from its dotted form, allowing at most the given number of components,
+    /// instead of [`MAX_TAG_DEPTH`][].  Deeper tags fail with [`TagError::TooDeep`][].
+    pub fn parse_with_max_depth(tag: &str, max_depth: usize) -> Result<Tag, TagError> {
+        if tag.is_empty() {
+            return Err(TagError::Empty);
+        }
+        if tag.split('.').count() > max_depth {
+            return Err(TagError::TooDeep(max_depth));
+        }
+        let mut components = Vec::new();
+        let mut offset = 0;
+        for component in tag.split('.') {
+            let mut chars = component.char_indices();
+            match chars.next() {
+                None => return Err(TagError::EmptyComponent(offset)),
+                Some((index, ch)) if !is_ident_start(ch) => {
+                    return Err(TagError::UnexpectedCharacter(ch, offset + index))
+                }
+                Some(_) => {}
+            }
+            if let Some((index, ch)) = chars.find(|(_, ch)| !is_ident(*ch)) {
+                return Err(TagError::UnexpectedCharacter(ch, offset + index));
+            }
+            components.push(Identifier::from(component));
+            offset += component.len() + 1;
+        }
+        Ok(Tag { components })
+    }
+
+    /// Returns the components of this tag.
+    pub fn components(&self) -> &[Identifier] {
+        &self.components
+    }
+
+    /// Returns the number of components of this tag.
+    pub fn depth(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Returns whether the components of this tag start with the components of another tag.
+    /// Every tag starts with itself.
+    pub fn starts_with(&self, prefix: &Tag) -> bool {
+        self.components.starts_with(&prefix.components)
+    }
+
+    /// Returns the tag with the components of this tag, followed by another component.  The
+    /// component is not checked; use [`Tag::parse`][] for names that do not come from the graph
+    /// DSL.  Fails if the child would have more than [`MAX_TAG_DEPTH`][] components.
+    pub fn child(&self, component: Identifier) -> Result<Tag, TagError> {
+        self.child_with_max_depth(component, MAX_TAG_DEPTH)
+    }
+
+    /// Returns the tag with the components of this tag, followed by another component, allowing
+    /// the child to have at most the given number of components, instead of
+    /// [`MAX_TAG_DEPTH`][].  Deeper tags fail with [`TagError::TooDeep`][].
+    pub fn child_with_max_depth(
+        &self,
+        component: Identifier,
+        max_depth: usize,
+    ) -> Result<Tag, TagError> {
+        if self.components.len() >= max_depth {
+            return Err(TagError::TooDeep(max_depth));
+        }
+        let mut components = self.components.clone();
+        components.push(component);
+        Ok(Tag { components })
+    }
+}
+
+impl From<Identifier> for Tag {
+    fn from(component: Identifier) -> Tag {
+        Tag {
+            components: vec![component],
+        }
+    }
+}
+
+impl FromStr for Tag {
+    type Err = TagError;
+    fn from_str(tag: &str) -> Result<Tag, TagError> {
+        Tag::parse(tag)
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for component in &self.components {
+            if !first {
+                write!(f, ".")?;
+            }
+            first = false;
+            write!(f, "{}", component)?;
+        }
+        Ok(())
+    }
+}
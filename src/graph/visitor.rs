@@ -0,0 +1,141 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Visiting the contents of a graph, for consumers that build their own structures from it
+
+use crate::Identifier;
+
+use super::Attributes;
+use super::Graph;
+use super::GraphNodeID;
+use super::GraphNodeRef;
+use super::Value;
+
+/// Receives the contents of a graph from [`Graph::visit`][], as borrowed data.  All methods do
+/// nothing by default, so a visitor only needs to implement the ones it is interested in.
+/// Returning an error from any method stops the visit, and [`Graph::visit`][] returns the error.
+///
+/// For every graph node that has not been removed, in the order in which the nodes were created,
+/// the visitor receives:
+///
+/// 1. a call to [`begin_node`][Self::begin_node],
+/// 2. a call to [`attribute`][Self::attribute] for every attribute of the node, ordered by name,
+/// 3. a call to [`edge`][Self::edge] for every outgoing edge of the node, ordered by sink, with
+///    the attributes of the edge ordered by name, and
+/// 4. a call to [`end_node`][Self::end_node].
+///
+/// [Hidden][super::Attributes::is_hidden] attributes of graph nodes and edges are left out, unless
+/// [`include_hidden`][Self::include_hidden] returns true.
+///
+/// ```
+/// # use tree_sitter_graph::graph::Graph;
+/// # use tree_sitter_graph::graph::GraphNodeRef;
+/// # use tree_sitter_graph::graph::GraphVisitor;
+/// # use tree_sitter_graph::graph::Value;
+/// # use tree_sitter_graph::Identifier;
+/// #[derive(Default)]
+/// struct CountEdges(usize);
+///
+/// impl<'a> GraphVisitor<'a> for CountEdges {
+///     type Error = std::convert::Infallible;
+///
+///     fn edge(
+///         &mut self,
+///         _sink: GraphNodeRef,
+///         _attributes: &[(&'a Identifier, &'a Value)],
+///     ) -> Result<(), Self::Error> {
+///         self.0 += 1;
+///         Ok(())
+///     }
+/// }
+///
+/// let mut graph = Graph::new();
+/// let a = graph.add_graph_node();
+/// let b = graph.add_graph_node();
+/// graph.add_edge(a, b).unwrap();
+/// let mut visitor = CountEdges::default();
+/// graph.visit(&mut visitor).unwrap();
+/// assert_eq!(visitor.0, 1);
+/// ```
+pub trait GraphVisitor<'a> {
+    /// The type of the errors that stop the visit
+    type Error;
+
+    /// Returns whether the visitor receives hidden attributes.  It does not by default.
+    fn include_hidden(&self) -> bool {
+        false
+    }
+
+    /// Called before the attributes and edges of a graph node.
+    fn begin_node(&mut self, _node: GraphNodeRef) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called for every attribute of the current graph node.
+    fn attribute(&mut self, _name: &'a Identifier, _value: &'a Value) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called for every outgoing edge of the current graph node, with the attributes of the edge.
+    fn edge(
+        &mut self,
+        _sink: GraphNodeRef,
+        _attributes: &[(&'a Identifier, &'a Value)],
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called after the attributes and edges of a graph node.
+    fn end_node(&mut self, _node: GraphNodeRef) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'tree> Graph<'tree> {
+    /// Visits the graph nodes and edges of this graph, and their attributes, in the order
+    /// described by [`GraphVisitor`][].  This is how the graph is pretty-printed and serialized,
+    /// so a visitor sees everything that those outputs contain, without going through them.
+    /// Apart from a buffer for sorting attributes, which is reused for every graph node and edge,
+    /// the visit does not allocate.
+    pub fn visit<'a, V: GraphVisitor<'a>>(&'a self, visitor: &mut V) -> Result<(), V::Error> {
+        let mut attributes = Vec::new();
+        let include_hidden = visitor.include_hidden();
+        for (index, node) in self.graph_nodes.iter().enumerate() {
+            if node.removed {
+                continue;
+            }
+            let node_ref = GraphNodeRef(index as GraphNodeID);
+            visitor.begin_node(node_ref)?;
+            sorted_attributes(&node.attributes, include_hidden, &mut attributes);
+            for (name, value) in &attributes {
+                visitor.attribute(name, value)?;
+            }
+            for (sink, edge) in &node.outgoing_edges {
+                sorted_attributes(&edge.attributes, include_hidden, &mut attributes);
+                visitor.edge(GraphNodeRef(*sink), &attributes)?;
+            }
+            visitor.end_node(node_ref)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the contents of a buffer with the attributes, ordered by name, leaving out hidden
+/// attributes unless they are included.
+fn sorted_attributes<'a>(
+    attributes: &'a Attributes,
+    include_hidden: bool,
+    buffer: &mut Vec<(&'a Identifier, &'a Value)>,
+) {
+    buffer.clear();
+    buffer.extend(
+        attributes
+            .iter()
+            .filter(|(name, _)| include_hidden || !attributes.is_hidden(name)),
+    );
+    buffer.sort_unstable_by_key(|(name, _)| *name);
+}
@@ -24,37 +24,126 @@
 //! There are no limitations on what graph structure you create: you are not limited to creating a
 //! tree, and in particular, you are not limited to creating a tree that "lines" up with the parsed
 //! syntax tree.
+//!
+//! # Features
+//!
+//! The library is split into the following Cargo features.  The core functionality, parsing,
+//! checking, and executing graph DSL files, is always available, even with
+//! `default-features = false`.
+//!
+//! | Feature       | Default | Description                                                        |
+//! |---------------|---------|--------------------------------------------------------------------|
+//! | `serde`       | yes     | `Serialize` and `Deserialize` implementations for graphs, `Graph::display_json`, and `Variables::from_json` |
+//! | `unicode-normalization` | yes | The `nfc`, `nfkc`, and `fold-identifier` functions of the standard library |
+//! | `resource-usage` | yes | The counters of [`ResourceUsage`][]; without it they are always zero |
+//! | `term-colors` | no      | Colored output when displaying errors and graph diffs              |
+//! | `lite-regex`  | no      | Compiles `scan` arms with the minimal engine in [`regex_engine`][] |
+//! | `petgraph`    | no      | `Graph::to_petgraph`, to run petgraph's algorithms on graphs        |
+//! | `rayon`       | no      | `bulk::execute_many`, to execute a file against many source files in parallel |
+//! | `cli`         | no      | Dependencies of the `tree-sitter-graph` binary; implies `serde` and `term-colors` |
+//!
+//! Regular expressions, which are used by the DSL's `scan` statement, are part of the core and
+//! always available.  By default, `scan` arms are compiled with the `regex` crate.  The
+//! `lite-regex` feature compiles them with [`LiteRegex`][regex_engine::LiteRegex] instead, which
+//! supports a documented subset of the syntax, and rejects other patterns when the DSL file is
+//! parsed.  The `regex` crate remains a dependency, as tree-sitter itself depends on it, and it
+//! is still used by the `replace` function and the `#match-global?` predicate.
+//!
+//! # Embedding
+//!
+//! Programs that embed this library should import the types they need from the [`prelude`][]
+//! module, which is the supported import path, and use the re-exported [`tree_sitter`][] crate
+//! to parse source code, so that their syntax trees have the same types as the library's.
+//!
+//! # Determinism
+//!
+//! Executing a graph DSL file is deterministic: given the same file, source, globals, and
+//! functions, strict and lazy execution each always create the same graph, with the same graph
+//! node indices, and the graph is always displayed and serialized to the same text.  This makes
+//! the output of an execution suitable for caching.  The only exception is the `id` of syntax
+//! nodes in serialized graphs, which is assigned by tree-sitter, and is the same only for
+//! executions over the same syntax tree.  Functions with side effects, such as functions that
+//! create graph nodes, must themselves be deterministic for this to hold.
 
 #[cfg(doc)]
 pub mod reference;
 
 pub mod ast;
+#[cfg(feature = "rayon")]
+pub mod bulk;
 mod checker;
+pub mod diagnostics;
 mod execution;
 pub mod functions;
 pub mod graph;
+mod overlay;
 pub mod parse_error;
 mod parser;
+pub mod prelude;
+pub mod regex_engine;
+pub mod testing;
 mod variables;
 
+pub use checker::CheckWarning;
+pub use execution::error::Context as ErrorContext;
+pub use execution::error::DependencyCycleStep;
 pub use execution::error::ExecutionError;
+pub use execution::error::ExecutionProgress;
+pub use execution::error::StatementContext;
+pub use execution::hook::Decision;
+pub use execution::hook::GraphMutationHook;
+pub use execution::hook::GraphObserver;
+pub use execution::hook::MutationContext;
+pub use execution::hook::MutationTarget;
+pub use execution::query_cache::QueryCache;
+pub use execution::report::CaptureBinding;
+pub use execution::report::MatchReport;
+pub use execution::report::StanzaMatch;
+pub use execution::report::StanzaMatches;
+pub use execution::report::MATCH_REPORT_SCHEMA_VERSION;
+pub use execution::session::ExecutionSession;
+pub use execution::session::SessionStats;
+pub use execution::source::source_hash;
+pub use execution::source::ParsedSource;
+pub use execution::usage::ExecutionStatistics;
+pub use execution::usage::ResourceUsage;
+pub use execution::usage::StanzaStatistics;
+pub use execution::CancelAfter;
 pub use execution::CancellationError;
 pub use execution::CancellationFlag;
+pub use execution::ErrorPolicy;
 pub use execution::ExecutionConfig;
+pub use execution::ExternalMatch;
 pub use execution::Match;
+pub use execution::MatchError;
 pub use execution::NoCancellation;
+pub use execution::DEFAULT_MAX_DEPTH;
+pub use overlay::OverlayPolicy;
 pub use parser::Location;
 pub use parser::ParseError;
+pub use parser::ParseStats;
+pub use parser::DSL_VERSION;
+pub use parser::MAX_NESTING_DEPTH;
 pub use variables::Globals as Variables;
 pub use variables::Iter as VariableIter;
+#[cfg(feature = "serde")]
+pub use variables::JsonValueError;
 pub use variables::VariableError;
 
+/// The version of the [tree-sitter][] crate that this library uses.  Parse syntax trees with it
+/// to make sure that they have the same types as the ones this library expects.
+///
+/// [tree-sitter]: https://docs.rs/tree-sitter/
+pub use tree_sitter;
+
 use std::borrow::Borrow;
 use std::hash::Hash;
 use std::ops::Deref;
 use std::sync::Arc;
 
+#[cfg(feature = "serde")]
 use serde::Serialize;
+#[cfg(feature = "serde")]
 use serde::Serializer;
 
 /// An identifier that appears in a graph DSL file or in the graph that is produced as an output.
@@ -115,6 +204,7 @@ impl<'a> PartialEq<&'a str> for Identifier {
     }
 }
 
+#[cfg(feature = "serde")]
 impl Serialize for Identifier {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         serializer.serialize_str(self.as_str())
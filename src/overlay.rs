@@ -0,0 +1,203 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Overlays, which override individual stanzas of a graph DSL file
+
+use tree_sitter::Language;
+use tree_sitter::Query;
+
+use crate::ast;
+use crate::parser::suggest_similar_name;
+use crate::parser::FULL_MATCH;
+use crate::Location;
+use crate::ParseError;
+
+/// Determines what happens to a stanza that is overridden by a stanza of an overlay
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OverlayPolicy {
+    /// The overlay stanza replaces the stanza it overrides.
+    Replace,
+    /// The overlay stanza is executed right after the stanza it overrides, which is kept.
+    Append,
+}
+
+impl ast::File {
+    /// Applies an overlay to this file.  An overlay is a graph DSL file whose named stanzas
+    /// override the stanzas of this file with the same name, as determined by the policy.  Unnamed
+    /// stanzas of the overlay are added after all stanzas of this file.  Stanzas keep their
+    /// names, so with [`OverlayPolicy::Append`], the file can end up with several stanzas with the
+    /// same name, of which later overlays override the first one.
+    ///
+    /// The globals, inherited variables and attributes, and phases of the overlay are added to
+    /// this file, and its attribute shorthands and node types replace those of this file with the
+    /// same name.  The invalid stanzas of an overlay that was parsed with recovery are recorded
+    /// as invalid stanzas of this file.  The merged file is checked again, so that the stanzas of
+    /// the overlay can use the scoped variables of the stanzas of this file, and vice versa.  The
+    /// locations of stanzas and statements that come from the overlay refer to the overlay's
+    /// source.
+    ///
+    /// An error is returned if a named stanza of the overlay does not override any stanza of this
+    /// file, if a stanza query of the overlay does not compile for the language of this file, or
+    /// if the merged file does not pass the checks.  The overlay is applied to a copy of this
+    /// file, which replaces this file only if it is valid, so this file is left unchanged if an
+    /// error is returned.
+    pub fn apply_overlay(
+        &mut self,
+        overlay: &ast::File,
+        policy: OverlayPolicy,
+    ) -> Result<(), ParseError> {
+        let mut file = self.duplicate()?;
+        file.add_overlay(overlay, policy)?;
+        *self = file;
+        Ok(())
+    }
+
+    /// Adds the stanzas and declarations of an overlay to this file, and checks the result.  This
+    /// file must not be used if an error is returned.
+    fn add_overlay(
+        &mut self,
+        overlay: &ast::File,
+        policy: OverlayPolicy,
+    ) -> Result<(), ParseError> {
+        let mut overrides = self.stanzas.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        let mut appended = Vec::new();
+        for overlay_stanza in &overlay.stanzas {
+            let stanza = overlay_stanza.duplicate(self.language)?;
+            let name = match &overlay_stanza.name {
+                Some(name) => name,
+                None => {
+                    appended.push(stanza);
+                    continue;
+                }
+            };
+            let index = self
+                .stanzas
+                .iter()
+                .position(|stanza| stanza.name.as_ref() == Some(name))
+                .ok_or_else(|| {
+                    ParseError::UnknownOverlayStanza(
+                        name.to_string(),
+                        self.stanza_names(),
+                        overlay_stanza.range.start,
+                    )
+                })?;
+            overrides[index].push(stanza);
+        }
+
+        for global in &overlay.globals {
+            if !self.globals.iter().any(|g| g.name == global.name) {
+                self.globals.push(global.clone());
+            }
+        }
+        self.inherited_variables
+            .extend(overlay.inherited_variables.iter().cloned());
+        self.inherited_attributes
+            .extend(overlay.inherited_attributes.iter().cloned());
+        for shorthand in overlay.shorthands.iter() {
+            self.shorthands.add(shorthand.clone());
+        }
+        for node_type in overlay.node_types.iter() {
+            self.node_types.add(node_type.clone());
+        }
+        for phase in &overlay.phases {
+            if !self.phases.contains(phase) {
+                self.phases.push(phase.clone());
+            }
+        }
+        self.invalid_stanzas
+            .extend(overlay.invalid_stanzas.iter().copied());
+
+        let mut stanzas = Vec::new();
+        for (stanza, overrides) in std::mem::take(&mut self.stanzas).into_iter().zip(overrides) {
+            if overrides.is_empty() || policy == OverlayPolicy::Append {
+                stanzas.push(stanza);
+            }
+            stanzas.extend(overrides);
+        }
+        stanzas.extend(appended);
+        self.stanzas = stanzas;
+        self.compile_query().map_err(|error| {
+            let location = self.stanza_location_for_query_row(error.row);
+            ParseError::QueryError(error, location)
+        })?;
+        self.check()?;
+        Ok(())
+    }
+
+    /// Returns a copy of this file, with the queries of its stanzas compiled again.
+    fn duplicate(&self) -> Result<ast::File, ParseError> {
+        Ok(ast::File {
+            language: self.language,
+            globals: self.globals.clone(),
+            inherited_variables: self.inherited_variables.clone(),
+            inherited_attributes: self.inherited_attributes.clone(),
+            query: None,
+            stanzas: self
+                .stanzas
+                .iter()
+                .map(|stanza| stanza.duplicate(self.language))
+                .collect::<Result<_, _>>()?,
+            shorthands: self.shorthands.clone(),
+            node_types: self.node_types.clone(),
+            phases: self.phases.clone(),
+            declared_version: self.declared_version,
+            shadowing_allowed: self.shadowing_allowed.clone(),
+            invalid_stanzas: self.invalid_stanzas.clone(),
+        })
+    }
+
+    /// Returns the location of the stanza whose query contains the given row of the combined
+    /// query of this file, as built by [`compile_query`][Self::compile_query].
+    fn stanza_location_for_query_row(&self, row: usize) -> Location {
+        let mut end = 0;
+        for stanza in &self.stanzas {
+            end += stanza.query_source.matches('\n').count() + 1;
+            if row < end {
+                return stanza.range.start;
+            }
+        }
+        Location::default()
+    }
+
+    /// Returns the names of the named stanzas of this file, separated by commas.
+    fn stanza_names(&self) -> String {
+        let names = self
+            .stanzas
+            .iter()
+            .filter_map(|stanza| stanza.name.as_ref().map(|name| name.to_string()))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            "(none)".to_string()
+        } else {
+            names.join(", ")
+        }
+    }
+}
+
+impl ast::Stanza {
+    /// Returns a copy of this stanza, with its query compiled for the given language.
+    fn duplicate(&self, language: Language) -> Result<ast::Stanza, ParseError> {
+        let query = Query::new(language, &self.query_source).map_err(|mut error| {
+            suggest_similar_name(language, &mut error);
+            ParseError::QueryError(error, self.range.start)
+        })?;
+        let full_match_stanza_capture_index = query
+            .capture_index_for_name(FULL_MATCH)
+            .expect("missing capture index for full match")
+            as usize;
+        Ok(ast::Stanza {
+            query,
+            statements: self.statements.clone(),
+            full_match_stanza_capture_index,
+            full_match_file_capture_index: usize::MAX, // set in checker
+            phase: self.phase.clone(),
+            name: self.name.clone(),
+            range: self.range,
+            query_source: self.query_source.clone(),
+        })
+    }
+}
@@ -5,12 +5,14 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::iter::Peekable;
 use std::path::Path;
 use std::str::Chars;
+use std::time::Duration;
+use std::time::Instant;
 
-use regex::Regex;
 use thiserror::Error;
 use tree_sitter::CaptureQuantifier;
 use tree_sitter::CaptureQuantifier::One;
@@ -21,13 +23,26 @@ use tree_sitter::CaptureQuantifier::ZeroOrOne;
 use tree_sitter::Language;
 use tree_sitter::Query;
 use tree_sitter::QueryError;
+use tree_sitter::QueryErrorKind;
 
 use crate::ast;
+use crate::execution::predicates::check_predicates;
+use crate::functions::Functions;
+use crate::graph::Float;
 use crate::parse_error::Excerpt;
+use crate::regex_engine::RegexEngine;
+use crate::regex_engine::RegexError;
 use crate::Identifier;
 
 pub const FULL_MATCH: &str = "__tsg__full_match";
 
+/// The latest version of the graph DSL that this library supports
+pub const DSL_VERSION: u32 = 2;
+
+/// The default maximum nesting depth of blocks and expressions in a graph DSL file, see
+/// [`File::from_str_with_max_nesting_depth`][ast::File::from_str_with_max_nesting_depth]
+pub const MAX_NESTING_DEPTH: usize = 64;
+
 impl ast::File {
     /// Parses a graph DSL file, returning a new `File` instance.
     pub fn from_str(language: Language, source: &str) -> Result<Self, ParseError> {
@@ -38,6 +53,90 @@ impl ast::File {
         Ok(file)
     }
 
+    /// Parses a graph DSL file, returning a new `File` instance.  Calls to functions of the given
+    /// library that have a declared signature are checked to have the right number of arguments.
+    pub fn from_str_with_functions(
+        language: Language,
+        source: &str,
+        functions: &Functions,
+    ) -> Result<Self, ParseError> {
+        let mut file = ast::File::new(language);
+        #[allow(deprecated)]
+        file.parse(source)?;
+        file.check_with_functions(functions)?;
+        Ok(file)
+    }
+
+    /// Parses a graph DSL file, allowing blocks and expressions to be nested at most the given
+    /// number of levels deep, instead of [`MAX_NESTING_DEPTH`][].  Deeper nesting fails with
+    /// [`ParseError::NestingTooDeep`][].  Parsing and executing a file is recursive, so a large
+    /// limit requires a correspondingly large stack.
+    pub fn from_str_with_max_nesting_depth(
+        language: Language,
+        source: &str,
+        max_nesting_depth: usize,
+    ) -> Result<Self, ParseError> {
+        let mut file = ast::File::new(language);
+        let mut parser = Parser::new(source);
+        parser.max_depth = max_nesting_depth;
+        parser.parse_into_file(&mut file)?;
+        file.check()?;
+        Ok(file)
+    }
+
+    /// Parses a graph DSL file, recovering from syntax errors in stanzas, for files that are
+    /// being edited.  A stanza that fails to parse, or whose query fails to compile, is skipped up
+    /// to its closing brace, and parsing continues with the next stanza.  The returned file
+    /// contains the other stanzas, and records the ranges of the skipped ones in
+    /// [`invalid_stanzas`][ast::File::invalid_stanzas].  The errors of the skipped stanzas are
+    /// returned together with the file.  The other stanzas are checked as usual.
+    ///
+    /// Errors outside of stanzas, errors that leave the end of a stanza unclear, such as a missing
+    /// closing brace, and errors found by the checker still fail the whole file.
+    ///
+    /// A file with invalid stanzas is only executed if the execution configuration
+    /// [allows it][crate::ExecutionConfig::allow_partial].
+    pub fn from_str_with_recovery(
+        language: Language,
+        source: &str,
+    ) -> Result<(Self, Vec<ParseError>), ParseError> {
+        let mut file = ast::File::new(language);
+        let mut parser = Parser::new(source);
+        parser.recover = true;
+        parser.parse_into_file(&mut file)?;
+        file.check()?;
+        Ok((file, parser.recovered_errors))
+    }
+
+    /// Parses a graph DSL file, returning a new `File` instance together with the time spent in
+    /// each phase of parsing.
+    pub fn from_source_with_stats(
+        language: Language,
+        source: &str,
+    ) -> Result<(Self, ParseStats), ParseError> {
+        let mut file = ast::File::new(language);
+        let mut parser = Parser::new(source);
+        let start = Instant::now();
+        parser.parse_into_file(&mut file)?;
+        let parsed = start.elapsed();
+        let start = Instant::now();
+        file.check()?;
+        let check = start.elapsed();
+        let query_compile = parser
+            .query_compile_times
+            .iter()
+            .map(|(_, duration)| *duration)
+            .sum::<Duration>()
+            + parser.file_query_compile;
+        let stats = ParseStats {
+            lex_parse: parsed.saturating_sub(query_compile),
+            check,
+            per_stanza_query_compile: parser.query_compile_times,
+            file_query_compile: parser.file_query_compile,
+        };
+        Ok((file, stats))
+    }
+
     /// Parses a graph DSL file, adding its content to an existing `File` instance.
     #[deprecated(
         note = "Parsing multiple times into the same `File` instance is unsound. Use `File::from_str` instead."
@@ -45,6 +144,40 @@ impl ast::File {
     pub fn parse(&mut self, content: &str) -> Result<(), ParseError> {
         Parser::new(content).parse_into_file(self)
     }
+
+    /// Compiles the file query from the queries of the stanzas.  Stanzas are executed phase by
+    /// phase, in the order in which the phases were first declared, so the stanzas, and the
+    /// patterns of the file query, are first ordered the same way.
+    pub(crate) fn compile_query(&mut self) -> Result<(), QueryError> {
+        let mut stanzas = std::mem::take(&mut self.stanzas);
+        stanzas.sort_by_key(|stanza| self.phase_index(stanza));
+        self.stanzas = stanzas;
+        let mut query_source = String::new();
+        for stanza in &self.stanzas {
+            query_source += &stanza.query_source;
+            query_source += "\n";
+        }
+        self.query = Some(Query::new(self.language, &query_source)?);
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Parse statistics
+
+/// The time spent in each phase of parsing a graph DSL file, as returned by
+/// [`File::from_source_with_stats`][ast::File::from_source_with_stats]
+#[derive(Clone, Debug, Default)]
+pub struct ParseStats {
+    /// The time spent parsing the DSL itself, excluding the compilation of queries
+    pub lex_parse: Duration,
+    /// The time spent checking the parsed file
+    pub check: Duration,
+    /// The time spent compiling the query of each stanza, together with the stanza's location,
+    /// in the order in which the stanzas appear in the file
+    pub per_stanza_query_compile: Vec<(Location, Duration)>,
+    /// The time spent compiling the combined query of all stanzas
+    pub file_query_compile: Duration,
 }
 
 // ----------------------------------------------------------------------------
@@ -53,6 +186,10 @@ impl ast::File {
 /// An error that can occur while parsing a graph DSL file
 #[derive(Debug, Error)]
 pub enum ParseError {
+    #[error("Duplicate map key {0} at {1}")]
+    DuplicateMapKey(String, Location),
+    #[error("Duplicate stanza name {0} at {1}")]
+    DuplicateStanzaName(String, Location),
     #[error("Expected quantifier at {0}")]
     ExpectedQuantifier(Location),
     #[error("Expected '{0}' at {1}")]
@@ -63,10 +200,23 @@ pub enum ParseError {
     ExpectedUnscopedVariable(Location),
     #[error("Invalid regular expression /{0}/ at {1}")]
     InvalidRegex(String, Location),
-    #[error("Expected integer constant in regex capture at {0}")]
+    #[error("Unsupported regular expression /{0}/ at {2}: {1}")]
+    UnsupportedRegex(String, String, Location),
+    #[error("Expected integer constant or group name in regex capture at {0}")]
     InvalidRegexCapture(Location),
-    #[error("Invalid query pattern: {}", _0.message)]
-    QueryError(#[from] QueryError),
+    #[error("Float constant {0} is out of range at {1}")]
+    InvalidFloatConstant(String, Location),
+    #[error("Integer constant {0} is out of range at {1}")]
+    InvalidIntegerConstant(String, Location),
+    #[error("Invalid query predicate in stanza at {1}: {0}")]
+    InvalidPredicate(String, Location),
+    /// The query of the stanza at the given location does not compile.  For unknown node types
+    /// and fields, the message of the query error is the unknown name, followed on a second line
+    /// by the most similar name of the grammar, if there is one.
+    #[error("{}", DisplayQueryError(_0, _1))]
+    QueryError(QueryError, Location),
+    #[error("{}", _0.iter().map(|(e, stanza)| DisplayQueryError(e, stanza).to_string()).collect::<Vec<_>>().join("\n"))]
+    QueryErrors(Vec<(QueryError, Location)>),
     #[error("Unexpected character '{0}' in {1} at {2}")]
     UnexpectedCharacter(char, &'static str, Location),
     #[error("Unexpected end of file at {0}")]
@@ -75,13 +225,97 @@ pub enum ParseError {
     UnexpectedKeyword(String, Location),
     #[error("Unexpected literal '#{0}' at {1}")]
     UnexpectedLiteral(String, Location),
+    #[error("Nesting deeper than {0} levels at {1}")]
+    NestingTooDeep(usize, Location),
     #[error("Query contains multiple patterns at {0}")]
     UnexpectedQueryPatterns(Location),
+    #[error(
+        "Overlay stanza {0} at {2} does not override any stanza, available stanza names are: {1}"
+    )]
+    UnknownOverlayStanza(String, String, Location),
+    #[error("Unsupported DSL version {0} at {2}, supported versions are 1 to {1}")]
+    UnsupportedVersion(u32, u32, Location),
     #[error(transparent)]
     Check(#[from] crate::checker::CheckError),
 }
 
+/// Displays the error of a stanza query, classified by its kind.  The messages of tree-sitter's
+/// query errors only contain the offending name, or the offending line for syntax and structure
+/// errors.
+struct DisplayQueryError<'a>(&'a QueryError, &'a Location);
+
+impl std::fmt::Display for DisplayQueryError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self(error, stanza) = self;
+        write!(
+            f,
+            "Invalid query pattern at ({}, {}) in stanza at {}: ",
+            error.row + 1,
+            error.column + 1,
+            stanza
+        )?;
+        write!(f, "{}", DisplayQueryErrorKind(error))
+    }
+}
+
+/// Displays the kind of a query error, with the offending name or line.
+pub(crate) struct DisplayQueryErrorKind<'a>(pub(crate) &'a QueryError);
+
+impl std::fmt::Display for DisplayQueryErrorKind<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = self.0;
+        let mut lines = error.message.lines();
+        let name = lines.next().unwrap_or_default();
+        let suggestion = match lines.next() {
+            Some(suggestion) => format!(", did you mean `{}`?", suggestion),
+            None => String::new(),
+        };
+        match error.kind {
+            QueryErrorKind::NodeType => write!(f, "unknown node type `{}`{}", name, suggestion),
+            QueryErrorKind::Field => write!(f, "unknown field `{}`{}", name, suggestion),
+            QueryErrorKind::Capture => write!(f, "unknown capture `{}`", error.message),
+            QueryErrorKind::Predicate => write!(f, "invalid predicate: {}", error.message),
+            QueryErrorKind::Structure => write!(f, "impossible pattern\n{}", error.message),
+            QueryErrorKind::Syntax => write!(f, "syntax error\n{}", error.message),
+            QueryErrorKind::Language => write!(f, "incompatible language: {}", error.message),
+        }
+    }
+}
+
 impl ParseError {
+    /// Returns the location in the graph DSL file that the error is reported at, or None for
+    /// errors in the queries of several stanzas, which each have their own location.
+    pub fn location(&self) -> Option<Location> {
+        Some(match self {
+            ParseError::DuplicateMapKey(_, location) => *location,
+            ParseError::DuplicateStanzaName(_, location) => *location,
+            ParseError::ExpectedQuantifier(location) => *location,
+            ParseError::ExpectedToken(_, location) => *location,
+            ParseError::ExpectedVariable(location) => *location,
+            ParseError::ExpectedUnscopedVariable(location) => *location,
+            ParseError::InvalidRegex(_, location) => *location,
+            ParseError::UnsupportedRegex(_, _, location) => *location,
+            ParseError::InvalidRegexCapture(location) => *location,
+            ParseError::InvalidFloatConstant(_, location) => *location,
+            ParseError::InvalidIntegerConstant(_, location) => *location,
+            ParseError::InvalidPredicate(_, location) => *location,
+            ParseError::QueryError(err, _) => Location {
+                row: err.row,
+                column: err.column,
+            },
+            ParseError::QueryErrors(_) => return None,
+            ParseError::UnexpectedCharacter(_, _, location) => *location,
+            ParseError::UnexpectedEOF(location) => *location,
+            ParseError::UnexpectedKeyword(_, location) => *location,
+            ParseError::UnexpectedLiteral(_, location) => *location,
+            ParseError::NestingTooDeep(_, location) => *location,
+            ParseError::UnexpectedQueryPatterns(location) => *location,
+            ParseError::UnknownOverlayStanza(_, _, location) => *location,
+            ParseError::UnsupportedVersion(_, _, location) => *location,
+            ParseError::Check(err) => err.location(),
+        })
+    }
+
     pub fn display_pretty<'a>(
         &'a self,
         path: &'a Path,
@@ -104,25 +338,28 @@ struct DisplayParseErrorPretty<'a> {
 impl std::fmt::Display for DisplayParseErrorPretty<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let location = match self.error {
-            ParseError::ExpectedQuantifier(location) => *location,
-            ParseError::ExpectedToken(_, location) => *location,
-            ParseError::ExpectedVariable(location) => *location,
-            ParseError::ExpectedUnscopedVariable(location) => *location,
-            ParseError::InvalidRegex(_, location) => *location,
-            ParseError::InvalidRegexCapture(location) => *location,
-            ParseError::QueryError(err) => Location {
-                row: err.row,
-                column: err.column,
-            },
-            ParseError::UnexpectedCharacter(_, _, location) => *location,
-            ParseError::UnexpectedEOF(location) => *location,
-            ParseError::UnexpectedKeyword(_, location) => *location,
-            ParseError::UnexpectedLiteral(_, location) => *location,
-            ParseError::UnexpectedQueryPatterns(location) => *location,
+            ParseError::QueryErrors(errors) => {
+                for (err, stanza) in errors {
+                    writeln!(f, "{}", DisplayQueryError(err, stanza))?;
+                    write!(
+                        f,
+                        "{}",
+                        Excerpt::from_source(
+                            self.path,
+                            self.source,
+                            err.row,
+                            err.column..err.column + 1,
+                            0
+                        )
+                    )?;
+                }
+                return Ok(());
+            }
             ParseError::Check(err) => {
                 write!(f, "{}", err.display_pretty(self.path, self.source))?;
                 return Ok(());
             }
+            error => error.location().expect("error with a single location"),
         };
         writeln!(f, "{}", self.error)?;
         write!(
@@ -140,11 +377,65 @@ impl std::fmt::Display for DisplayParseErrorPretty<'_> {
     }
 }
 
+/// Adds the most similar name of the grammar to the message of a query error for an unknown
+/// node type or field, on a second line, if there is a name that is similar enough.
+pub(crate) fn suggest_similar_name(language: Language, error: &mut QueryError) {
+    let names = match error.kind {
+        QueryErrorKind::NodeType => (0..language.node_kind_count() as u16)
+            .filter(|id| language.node_kind_is_named(*id))
+            .filter_map(|id| language.node_kind_for_id(id))
+            .collect::<Vec<_>>(),
+        QueryErrorKind::Field => (1..=language.field_count() as u16)
+            .filter_map(|id| language.field_name_for_id(id))
+            .collect::<Vec<_>>(),
+        _ => return,
+    };
+    if let Some(name) = most_similar_name(&error.message, names) {
+        error.message = format!("{}\n{}", error.message, name);
+    }
+}
+
+/// Returns the name that is closest to the given one by edit distance, if it differs in at most a
+/// third of its characters.  Ties are broken in favor of the earliest name.
+fn most_similar_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = std::cmp::max(1, name.chars().count() / 3);
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(name, candidate);
+        if distance == 0 || distance > max_distance {
+            continue;
+        }
+        if !matches!(best, Some((best_distance, _)) if best_distance <= distance) {
+            best = Some((distance, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+/// Returns the Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
 // ----------------------------------------------------------------------------
 // Location
 
 /// The location of a graph DSL entity within its file
-#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Location {
     pub row: usize,
     pub column: usize,
@@ -195,27 +486,69 @@ struct Parser<'a> {
     chars: Peekable<Chars<'a>>,
     offset: usize,
     location: Location,
-    query_source: String,
+    // The errors of the stanza queries that failed to compile, with the locations of the stanzas
+    query_errors: Vec<(QueryError, Location)>,
+    // The time spent compiling the query of each stanza, and the query of the file
+    query_compile_times: Vec<(Location, Duration)>,
+    file_query_compile: Duration,
+    // The version of the DSL whose grammar is used to parse the file
+    version: u32,
+    // The rows of `; #allow(shadow)` comments
+    shadowing_allowed: BTreeSet<usize>,
+    // The number of blocks and expressions that are currently being parsed, one inside the other
+    depth: usize,
+    // The maximum value of `depth`
+    max_depth: usize,
+    // Whether stanzas that fail to parse are skipped, instead of failing the whole file
+    recover: bool,
+    // The errors of the stanzas that were skipped
+    recovered_errors: Vec<ParseError>,
 }
 
-fn is_ident_start(c: char) -> bool {
+pub(crate) fn is_ident_start(c: char) -> bool {
     c == '_' || c.is_alphabetic()
 }
 
-fn is_ident(c: char) -> bool {
+pub(crate) fn is_ident(c: char) -> bool {
     c == '_' || c == '-' || c.is_alphanumeric()
 }
 
+/// Returns the version number of a comment line of the form `;; tsg N`, which starts with one or
+/// more semicolons, or `None` if the line is another comment.
+fn comment_version_directive(line: &str) -> Option<&str> {
+    let is_blank = |c: char| c == ' ' || c == '\t';
+    let rest = line
+        .strip_prefix(';')?
+        .trim_start_matches(';')
+        .trim_start_matches(is_blank)
+        .strip_prefix("tsg")?;
+    let version = rest.trim_start_matches(is_blank).trim_end_matches(is_blank);
+    if version.len() == rest.len()
+        || version.is_empty()
+        || !version.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    Some(version)
+}
+
 impl<'a> Parser<'a> {
     fn new(source: &'a str) -> Parser<'a> {
         let chars = source.chars().peekable();
-        let query_source = String::with_capacity(source.len());
         Parser {
             source,
             chars,
             offset: 0,
             location: Location::default(),
-            query_source,
+            query_errors: Vec::new(),
+            query_compile_times: Vec::new(),
+            file_query_compile: Duration::ZERO,
+            version: DSL_VERSION,
+            shadowing_allowed: BTreeSet::new(),
+            depth: 0,
+            max_depth: MAX_NESTING_DEPTH,
+            recover: false,
+            recovered_errors: Vec::new(),
         }
     }
 }
@@ -256,6 +589,14 @@ impl<'a> Parser<'a> {
             } else {
                 if ch == ';' {
                     in_comment = true;
+                    let comment = &self.source[self.offset..];
+                    if comment
+                        .trim_start_matches(';')
+                        .trim_start_matches([' ', '\t'])
+                        .starts_with("#allow(shadow)")
+                    {
+                        self.shadowing_allowed.insert(self.location.row);
+                    }
                 } else if !ch.is_whitespace() {
                     return;
                 }
@@ -288,33 +629,157 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Consumes a keyword, which unlike a token cannot be followed by further identifier
+    /// characters.
+    fn consume_keyword(&mut self, keyword: &'static str) -> Result<(), ParseError> {
+        let rest = &self.source[self.offset..];
+        if rest.starts_with(keyword) && !rest[keyword.len()..].starts_with(is_ident) {
+            self.consume_n(keyword.len())
+        } else {
+            Err(ParseError::ExpectedToken(keyword, self.location))
+        }
+    }
+
     fn parse_into_file(&mut self, file: &mut ast::File) -> Result<(), ParseError> {
+        let mut phase = None;
+        if let Some(version) = self.parse_version_directive()? {
+            file.declared_version = Some(version);
+            self.version = version;
+        }
         self.consume_whitespace();
         while self.try_peek().is_some() {
             if let Ok(_) = self.consume_token("attribute") {
                 self.consume_whitespace();
                 let shorthand = self.parse_shorthand()?;
                 file.shorthands.add(shorthand);
+            } else if self.consume_token("nodetype").is_ok() {
+                self.consume_whitespace();
+                let node_type = self.parse_node_type()?;
+                file.node_types.add(node_type);
             } else if let Ok(_) = self.consume_token("global") {
                 self.consume_whitespace();
                 let global = self.parse_global()?;
                 file.globals.push(global);
             } else if let Ok(_) = self.consume_token("inherit") {
                 self.consume_whitespace();
-                self.consume_token(".")?;
-                let name = self.parse_identifier("inherit")?;
-                file.inherited_variables.insert(name);
-            } else {
-                let stanza = self.parse_stanza(file.language)?;
+                if self.consume_token(".").is_ok() {
+                    let name = self.parse_identifier("inherit")?;
+                    file.inherited_variables.insert(name);
+                } else {
+                    let inherited = self.parse_inherited_attribute()?;
+                    file.inherited_attributes.push(inherited);
+                }
+            } else if self.consume_token("phase").is_ok() {
+                self.consume_whitespace();
+                let name = self.parse_identifier("phase name")?;
+                if !file.phases.contains(&name) {
+                    file.phases.push(name.clone());
+                }
+                phase = Some(name);
+            } else if self.consume_keyword("stanza").is_ok() {
+                self.consume_whitespace();
+                let location = self.location;
+                let name = self.parse_identifier("stanza name")?;
+                if file
+                    .stanzas
+                    .iter()
+                    .any(|stanza| stanza.name.as_ref() == Some(&name))
+                {
+                    return Err(ParseError::DuplicateStanzaName(name.to_string(), location));
+                }
+                self.consume_whitespace();
+                if let Some(mut stanza) = self.parse_stanza_or_recover(file)? {
+                    stanza.phase = phase.clone();
+                    stanza.name = Some(name);
+                    file.stanzas.push(stanza);
+                }
+            } else if let Some(mut stanza) = self.parse_stanza_or_recover(file)? {
+                stanza.phase = phase.clone();
                 file.stanzas.push(stanza);
             }
             self.consume_whitespace();
         }
+        // Report the queries of all stanzas that failed to compile together, so that they can
+        // all be fixed at once, for example after upgrading the grammar.
+        match self.query_errors.len() {
+            0 => {}
+            1 => {
+                let (error, stanza) = self.query_errors.pop().unwrap();
+                return Err(ParseError::QueryError(error, stanza));
+            }
+            _ => {
+                return Err(ParseError::QueryErrors(std::mem::take(
+                    &mut self.query_errors,
+                )))
+            }
+        }
+        file.shadowing_allowed.append(&mut self.shadowing_allowed);
         // we can unwrap here because all queries have already been parsed before
-        file.query = Some(Query::new(file.language, &self.query_source).unwrap());
+        let start = Instant::now();
+        file.compile_query().unwrap();
+        self.file_query_compile = start.elapsed();
         Ok(())
     }
 
+    /// Parses the optional version directive, which is either a `;; tsg N` comment or a
+    /// `version N` declaration, and can only be preceded by whitespace and other comments.
+    fn parse_version_directive(&mut self) -> Result<Option<u32>, ParseError> {
+        loop {
+            self.consume_while(char::is_whitespace);
+            let location = self.location;
+            let version = if self.try_peek() == Some(';') {
+                let rest = &self.source[self.offset..];
+                let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                let version =
+                    comment_version_directive(line.trim_end_matches('\r')).map(str::to_string);
+                self.consume_while(|ch| ch != '\n');
+                match version {
+                    Some(version) => version,
+                    None => continue,
+                }
+            } else if self.consume_keyword("version").is_ok() {
+                self.consume_whitespace();
+                let start = self.offset;
+                self.consume_while(|ch| ch.is_ascii_digit());
+                if start == self.offset {
+                    return Err(ParseError::ExpectedToken("version number", self.location));
+                }
+                self.source[start..self.offset].to_string()
+            } else {
+                return Ok(None);
+            };
+            return match version.parse::<u32>() {
+                Ok(version) if (1..=DSL_VERSION).contains(&version) => Ok(Some(version)),
+                _ => Err(ParseError::UnsupportedVersion(
+                    version.parse().unwrap_or(u32::MAX),
+                    DSL_VERSION,
+                    location,
+                )),
+            };
+        }
+    }
+
+    fn parse_inherited_attribute(&mut self) -> Result<ast::InheritedAttribute, ParseError> {
+        let location = self.location;
+        let name = self.parse_identifier("inherit")?;
+        self.consume_whitespace();
+        self.consume_token("along")?;
+        self.consume_whitespace();
+        self.consume_token("edge")?;
+        self.consume_whitespace();
+        let edge_attribute = self.parse_identifier("inherit")?;
+        self.consume_whitespace();
+        self.consume_token("=")?;
+        self.consume_whitespace();
+        let edge_value = self.parse_string()?;
+        Ok(ast::InheritedAttribute {
+            name,
+            edge_attribute,
+            edge_value,
+            location,
+        })
+    }
+
     fn parse_global(&mut self) -> Result<ast::Global, ParseError> {
         let location = self.location;
         let name = self.parse_identifier("global variable")?;
@@ -337,11 +802,16 @@ impl<'a> Parser<'a> {
         let location = self.location;
         let name = self.parse_identifier("shorthand name")?;
         self.consume_whitespace();
-        self.consume_token("=")?;
-        self.consume_whitespace();
-        let variable = self.parse_unscoped_variable()?;
-        self.consume_whitespace();
-        self.consume_token("=>")?;
+        let variable = if self.consume_token("=>").is_ok() {
+            None
+        } else {
+            self.consume_token("=")?;
+            self.consume_whitespace();
+            let variable = self.parse_unscoped_variable()?;
+            self.consume_whitespace();
+            self.consume_token("=>")?;
+            Some(variable)
+        };
         self.consume_whitespace();
         let attributes = self.parse_attributes()?;
         Ok(ast::AttributeShorthand {
@@ -352,6 +822,35 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn parse_node_type(&mut self) -> Result<ast::NodeType, ParseError> {
+        let location = self.location;
+        let name = self.parse_identifier("node type name")?;
+        self.consume_whitespace();
+        self.consume_token("{")?;
+        self.consume_whitespace();
+        let mut attributes = Vec::new();
+        while self.peek()? != '}' {
+            if !attributes.is_empty() {
+                self.consume_token(",")?;
+                self.consume_whitespace();
+            }
+            let attribute_location = self.location;
+            let attribute = self.parse_attribute()?;
+            if attribute.operation != ast::AttributeOperation::Set {
+                // Defaults are replaced rather than appended to or overridden.
+                return Err(ParseError::ExpectedToken("=", attribute_location));
+            }
+            attributes.push(attribute);
+            self.consume_whitespace();
+        }
+        self.consume_token("}")?;
+        Ok(ast::NodeType {
+            name,
+            attributes,
+            location,
+        })
+    }
+
     fn parse_quantifier(&mut self) -> Result<CaptureQuantifier, ParseError> {
         let mut quantifier = One;
         if let Some(c) = self.try_peek() {
@@ -369,51 +868,119 @@ impl<'a> Parser<'a> {
         Ok(quantifier)
     }
 
-    fn parse_stanza(&mut self, language: Language) -> Result<ast::Stanza, ParseError> {
+    /// Parses a stanza.  If its query does not compile, the error is recorded, and the rest of
+    /// the stanza is parsed but not returned.
+    /// Parses a stanza.  When recovering from errors, a stanza that fails to parse is skipped and
+    /// recorded as invalid, and its error is kept, instead of being returned.  The error is only
+    /// returned if the end of the stanza cannot be found.
+    fn parse_stanza_or_recover(
+        &mut self,
+        file: &mut ast::File,
+    ) -> Result<Option<ast::Stanza>, ParseError> {
+        if !self.recover {
+            return self.parse_stanza(file.language);
+        }
+        let start = self.location;
+        let offset = self.offset;
+        let error = match self.parse_stanza(file.language) {
+            Ok(stanza) => return Ok(stanza),
+            Err(error) => error,
+        };
+        // Rewind to the start of the stanza, and skip over it without parsing its contents.
+        self.chars = self.source[offset..].chars().peekable();
+        self.offset = offset;
+        self.location = start;
+        self.depth = 0;
+        if self.skip_query().is_err() || self.skip_block().is_err() {
+            return Err(error);
+        }
+        file.invalid_stanzas.push(Range {
+            start,
+            end: self.location,
+        });
+        self.recovered_errors.push(error);
+        Ok(None)
+    }
+
+    fn parse_stanza(&mut self, language: Language) -> Result<Option<ast::Stanza>, ParseError> {
         let start = self.location;
-        let (query, full_match_stanza_capture_index) = self.parse_query(language)?;
+        let query = self.parse_query(language)?;
         self.consume_whitespace();
         let statements = self.parse_statements()?;
+        let (query, full_match_stanza_capture_index, query_source) = match query {
+            Some(query) => query,
+            None => return Ok(None),
+        };
         let end = self.location;
         let range = Range { start, end };
-        Ok(ast::Stanza {
+        Ok(Some(ast::Stanza {
             query,
             statements,
             full_match_stanza_capture_index,
+            phase: None,
+            name: None,
             full_match_file_capture_index: usize::MAX, // set in checker
             range,
-        })
+            query_source,
+        }))
     }
 
-    fn parse_query(&mut self, language: Language) -> Result<(Query, usize), ParseError> {
+    /// Parses and compiles the query of a stanza, returning it together with the index of its
+    /// full match capture and its source.  If the query does not compile, the error is recorded
+    /// and `None` is returned.
+    fn parse_query(
+        &mut self,
+        language: Language,
+    ) -> Result<Option<(Query, usize, String)>, ParseError> {
         let location = self.location;
         let query_start = self.offset;
         self.skip_query()?;
         let query_end = self.offset;
         let query_source = self.source[query_start..query_end].to_owned() + "@" + FULL_MATCH;
-        // If tree-sitter allowed us to incrementally add patterns to a query, we wouldn't need
-        // the global query_source.
-        self.query_source += &query_source;
-        self.query_source += "\n";
-        let query = Query::new(language, &query_source).map_err(|mut e| {
-            // the column of the first row of a query pattern must be shifted by the whitespace
-            // that was already consumed
-            if e.row == 0 {
-                // must come before we update e.row!
-                e.column += location.column;
-            }
-            e.row += location.row;
-            e.offset += query_start;
-            e
-        })?;
+        let start = Instant::now();
+        let query = Query::new(language, &query_source);
+        self.query_compile_times.push((location, start.elapsed()));
+        let query = match query {
+            Ok(query) => query,
+            Err(mut e) => {
+                // the column of the first row of a query pattern must be shifted by the whitespace
+                // that was already consumed
+                if e.row == 0 {
+                    // must come before we update e.row!
+                    e.column += location.column;
+                }
+                e.row += location.row;
+                e.offset += query_start;
+                // syntax errors quote the offending line of the query, with a marker under the
+                // error, which must be shifted in the same way
+                let quotes_line =
+                    matches!(e.kind, QueryErrorKind::Syntax | QueryErrorKind::Structure)
+                        && e.message.contains('\n');
+                if quotes_line {
+                    if let Some(line) = self.source.lines().nth(e.row) {
+                        e.message = format!("{}\n{}^", line, " ".repeat(e.column));
+                    }
+                }
+                suggest_similar_name(language, &mut e);
+                if self.recover {
+                    return Err(ParseError::QueryError(e, location));
+                }
+                self.query_errors.push((e, location));
+                return Ok(None);
+            }
+        };
         if query.pattern_count() > 1 {
             return Err(ParseError::UnexpectedQueryPatterns(location));
         }
+        check_predicates(&query, 0)
+            .map_err(|message| ParseError::InvalidPredicate(message, location))?;
         let full_match_capture_index = query
             .capture_index_for_name(FULL_MATCH)
             .expect("missing capture index for full match")
             as usize;
-        Ok((query, full_match_capture_index))
+        // If tree-sitter allowed us to incrementally add patterns to a query, we wouldn't need
+        // to keep the query sources around to build the file query.
+        Ok(Some((query, full_match_capture_index, query_source)))
     }
 
     fn skip_query(&mut self) -> Result<(), ParseError> {
@@ -457,7 +1024,42 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Skips a block enclosed in braces, including the braces, without parsing it.
+    fn skip_block(&mut self) -> Result<(), ParseError> {
+        self.consume_token("{")?;
+        let mut brace_depth = 1;
+        let mut in_string = false;
+        let mut in_escape = false;
+        let mut in_comment = false;
+        while brace_depth > 0 {
+            let ch = self.next()?;
+            if in_escape {
+                in_escape = false;
+            } else if in_string {
+                match ch {
+                    '\\' => in_escape = true,
+                    '"' | '\n' => in_string = false,
+                    _ => {}
+                }
+            } else if in_comment {
+                if ch == '\n' {
+                    in_comment = false;
+                }
+            } else {
+                match ch {
+                    '"' => in_string = true,
+                    '{' => brace_depth += 1,
+                    '}' => brace_depth -= 1,
+                    ';' => in_comment = true,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn parse_statements(&mut self) -> Result<Vec<ast::Statement>, ParseError> {
+        self.enter_nested()?;
         self.consume_token("{")?;
         let mut statements = Vec::new();
         self.consume_whitespace();
@@ -467,9 +1069,21 @@ impl<'a> Parser<'a> {
             self.consume_whitespace();
         }
         self.consume_token("}")?;
+        self.depth -= 1;
         Ok(statements)
     }
 
+    /// Records that parsing descends one level into a nested block or expression, failing if
+    /// that is too deep.  Parsing is recursive, so this protects against running out of stack
+    /// space on deeply nested input.
+    fn enter_nested(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(ParseError::NestingTooDeep(self.max_depth, self.location));
+        }
+        Ok(())
+    }
+
     fn parse_name(&mut self, within: &'static str) -> Result<&'a str, ParseError> {
         let start = self.offset;
         let ch = self.next()?;
@@ -523,58 +1137,92 @@ impl<'a> Parser<'a> {
             .into())
         } else if keyword == "node" {
             let node = self.parse_variable()?;
+            self.consume_whitespace();
+            let node_type = if self.consume_token(":").is_ok() {
+                self.consume_whitespace();
+                Some(self.parse_identifier("node type name")?)
+            } else {
+                None
+            };
+            let attributes = self.parse_inline_attributes()?;
             Ok(ast::CreateGraphNode {
                 node,
+                node_type,
+                defaults: Vec::new(),
+                attributes,
                 location: keyword_location,
             }
             .into())
         } else if keyword == "edge" {
-            let source = self.parse_expression()?;
+            let left = self.parse_expression()?;
             self.consume_whitespace();
-            self.consume_token("->")?;
+            let reversed = self.parse_edge_arrow()?;
             self.consume_whitespace();
-            let sink = self.parse_expression()?;
+            let right = self.parse_expression()?;
+            let (source, sink) = if reversed {
+                (right, left)
+            } else {
+                (left, right)
+            };
+            let attributes = self.parse_inline_attributes()?;
             Ok(ast::CreateEdge {
                 source,
                 sink,
+                attributes,
                 location: keyword_location,
             }
             .into())
         } else if keyword == "attr" {
             self.consume_token("(")?;
             self.consume_whitespace();
-            let node_or_source = self.parse_expression()?;
-            self.consume_whitespace();
-
-            if self.peek()? == '-' {
-                let source = node_or_source;
-                self.consume_token("->")?;
-                self.consume_whitespace();
-                let sink = self.parse_expression()?;
+            let mut targets = vec![self.parse_attribute_target()?];
+            while self.consume_token(",").is_ok() {
                 self.consume_whitespace();
-                self.consume_token(")")?;
-                self.consume_whitespace();
-                let attributes = self.parse_attributes()?;
-                Ok(ast::AddEdgeAttribute {
+                targets.push(self.parse_attribute_target()?);
+            }
+            self.consume_token(")")?;
+            self.consume_whitespace();
+            let attributes = self.parse_attributes()?;
+            if targets.len() > 1 {
+                return Ok(ast::AddAttributes {
+                    targets,
+                    attributes,
+                    location: keyword_location,
+                }
+                .into());
+            }
+            match targets.pop().unwrap() {
+                ast::AttributeTarget::Edge { source, sink } => Ok(ast::AddEdgeAttribute {
                     source,
                     sink,
                     attributes,
                     location: keyword_location,
                 }
-                .into())
-            } else {
-                let node = node_or_source;
-                self.consume_whitespace();
-                self.consume_token(")")?;
-                self.consume_whitespace();
-                let attributes = self.parse_attributes()?;
-                Ok(ast::AddGraphNodeAttribute {
+                .into()),
+                ast::AttributeTarget::GraphNode(node) => Ok(ast::AddGraphNodeAttribute {
                     node,
                     attributes,
                     location: keyword_location,
                 }
-                .into())
+                .into()),
+            }
+        } else if keyword == "delattr" {
+            self.consume_token("(")?;
+            self.consume_whitespace();
+            let mut targets = vec![self.parse_attribute_target()?];
+            while self.consume_token(",").is_ok() {
+                self.consume_whitespace();
+                targets.push(self.parse_attribute_target()?);
+            }
+            self.consume_token(")")?;
+            self.consume_whitespace();
+            let names = self.parse_attribute_names()?;
+            Ok(ast::RemoveAttributes {
+                targets,
+                names,
+                location: keyword_location,
             }
+            .into())
         } else if keyword == "print" {
             let mut values = vec![self.parse_expression()?];
             self.consume_whitespace();
@@ -599,8 +1247,14 @@ impl<'a> Parser<'a> {
             while self.peek()? != '}' {
                 let pattern_location = self.location;
                 let pattern = self.parse_string()?;
-                let regex = Regex::new(&pattern)
-                    .map_err(|_| ParseError::InvalidRegex(pattern.into(), pattern_location))?;
+                let regex = RegexEngine::compile(&pattern).map_err(|err| match err {
+                    RegexError::Invalid => {
+                        ParseError::InvalidRegex(pattern.clone(), pattern_location)
+                    }
+                    RegexError::Unsupported(construct) => {
+                        ParseError::UnsupportedRegex(pattern.clone(), construct, pattern_location)
+                    }
+                })?;
                 self.consume_whitespace();
                 let statements = self.parse_statements()?;
                 arms.push(ast::ScanArm {
@@ -671,21 +1325,7 @@ impl<'a> Parser<'a> {
             }
             .into())
         } else if keyword == "for" {
-            self.consume_whitespace();
-            let variable = self.parse_unscoped_variable()?;
-            self.consume_whitespace();
-            self.consume_token("in")?;
-            self.consume_whitespace();
-            let value = self.parse_expression()?;
-            self.consume_whitespace();
-            let statements = self.parse_statements()?;
-            Ok(ast::ForIn {
-                variable,
-                value,
-                statements,
-                location: keyword_location,
-            }
-            .into())
+            self.parse_for_in(keyword_location)
         } else {
             Err(ParseError::UnexpectedKeyword(
                 keyword.into(),
@@ -694,6 +1334,65 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the rest of a `for` statement, after the keyword.  The loop variable can be
+    /// preceded by an index variable.
+    fn parse_for_in(&mut self, location: Location) -> Result<ast::Statement, ParseError> {
+        self.consume_whitespace();
+        let mut variable = self.parse_unscoped_variable()?;
+        self.consume_whitespace();
+        let mut index = None;
+        if self.try_peek() == Some(',') {
+            self.consume_token(",")?;
+            self.consume_whitespace();
+            index = Some(variable);
+            variable = self.parse_unscoped_variable()?;
+            self.consume_whitespace();
+        }
+        self.consume_token("in")?;
+        self.consume_whitespace();
+        let value = self.parse_expression()?;
+        self.consume_whitespace();
+        let statements = self.parse_statements()?;
+        Ok(ast::ForIn {
+            variable,
+            index,
+            value,
+            statements,
+            location,
+        }
+        .into())
+    }
+
+    /// Parses the target of an `attr` statement, which is either a graph node or an edge.
+    fn parse_attribute_target(&mut self) -> Result<ast::AttributeTarget, ParseError> {
+        let left = self.parse_expression()?;
+        self.consume_whitespace();
+        if !matches!(self.peek()?, '-' | '<') {
+            return Ok(ast::AttributeTarget::GraphNode(left));
+        }
+        let reversed = self.parse_edge_arrow()?;
+        self.consume_whitespace();
+        let right = self.parse_expression()?;
+        self.consume_whitespace();
+        let (source, sink) = if reversed {
+            (right, left)
+        } else {
+            (left, right)
+        };
+        Ok(ast::AttributeTarget::Edge { source, sink })
+    }
+
+    /// Parses the arrow between the two graph nodes of an edge, returning whether it is a `<-`
+    /// arrow, which points from the right graph node to the left one.  Edges are always stored
+    /// with their source first, so the parser swaps the graph nodes of reversed edges.
+    fn parse_edge_arrow(&mut self) -> Result<bool, ParseError> {
+        if self.consume_token("<-").is_ok() {
+            return Ok(true);
+        }
+        self.consume_token("->")?;
+        Ok(false)
+    }
+
     fn parse_conditions(&mut self) -> Result<Vec<ast::Condition>, ParseError> {
         let mut conditions = Vec::new();
         let mut has_next = true;
@@ -713,22 +1412,35 @@ impl<'a> Parser<'a> {
 
     fn parse_condition(&mut self) -> Result<ast::Condition, ParseError> {
         let location = self.location;
-        let condition = if let Ok(_) = self.consume_token("some") {
+        // Version 1 of the DSL accepts the `some` and `none` keywords as a prefix of an identifier,
+        // so that `none_found` is parsed as `none _found`.
+        let consume_keyword = if self.version < 2 {
+            Self::consume_token
+        } else {
+            Self::consume_keyword
+        };
+        let condition = if consume_keyword(self, "some").is_ok() {
             self.consume_whitespace();
             let value = self.parse_expression()?;
             ast::Condition::Some { value, location }
-        } else if let Ok(_) = self.consume_token("none") {
+        } else if consume_keyword(self, "none").is_ok() {
             self.consume_whitespace();
             let value = self.parse_expression()?;
             ast::Condition::None { value, location }
-        } else if let Ok(value) = self.parse_expression() {
-            self.consume_whitespace();
-            ast::Condition::Bool { value, location }
         } else {
-            return Err(ParseError::ExpectedToken(
-                "(some|none)? EXPRESSION",
-                location,
-            ));
+            match self.parse_expression() {
+                Ok(value) => {
+                    self.consume_whitespace();
+                    ast::Condition::Bool { value, location }
+                }
+                Err(err @ ParseError::NestingTooDeep(..)) => return Err(err),
+                Err(_) => {
+                    return Err(ParseError::ExpectedToken(
+                        "(some|none)? EXPRESSION",
+                        location,
+                    ))
+                }
+            }
         };
         self.consume_whitespace();
         Ok(condition)
@@ -765,15 +1477,17 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_expression(&mut self) -> Result<ast::Expression, ParseError> {
+        self.enter_nested()?;
+        let mut levels = 1;
         let mut expression = match self.peek()? {
             '#' => self.parse_literal()?,
             '"' => self.parse_string()?.into(),
             '@' => self.parse_capture()?.into(),
-            '$' => self.parse_regex_capture()?.into(),
+            '$' => self.parse_regex_capture()?,
             '(' => self.parse_call()?,
             '[' => self.parse_list()?,
             '{' => self.parse_set()?,
-            ch if ch.is_ascii_digit() => self.parse_integer_constant()?,
+            ch if ch == '-' || ch.is_ascii_digit() => self.parse_number_constant()?,
             ch if is_ident_start(ch) => {
                 let location = self.location;
                 let name = self.parse_identifier("variable name")?;
@@ -789,6 +1503,9 @@ impl<'a> Parser<'a> {
         };
         self.consume_whitespace();
         while self.try_peek() == Some('.') {
+            // every scoped variable adds a level to the expression
+            self.enter_nested()?;
+            levels += 1;
             self.skip().unwrap();
             self.consume_whitespace();
             let location = self.location;
@@ -802,13 +1519,15 @@ impl<'a> Parser<'a> {
             }
             .into();
         }
+        self.depth -= levels;
         Ok(expression)
     }
 
     fn parse_call(&mut self) -> Result<ast::Expression, ParseError> {
+        let location = self.location;
         self.consume_token("(")?;
         self.consume_whitespace();
-        let function = self.parse_identifier("function name")?;
+        let function = self.parse_function_name()?;
         self.consume_whitespace();
         let mut parameters = Vec::new();
         while self.peek()? != ')' {
@@ -816,13 +1535,38 @@ impl<'a> Parser<'a> {
             self.consume_whitespace();
         }
         self.consume_token(")")?;
+        // count, first, and last calls on a capture are computed from the query match directly
+        if let Some(aggregate) = ast::Aggregate::from_function_name(function.as_str()) {
+            if let [ast::Expression::Capture(_)] = parameters.as_slice() {
+                if let Some(ast::Expression::Capture(capture)) = parameters.pop() {
+                    return Ok(ast::CaptureAggregate {
+                        aggregate,
+                        capture,
+                        location,
+                    }
+                    .into());
+                }
+            }
+        }
         Ok(ast::Call {
             function,
             parameters,
+            location,
         }
         .into())
     }
 
+    /// Parses the name of a function, which, unlike other identifiers, can end with a `?`, as
+    /// in `contains?`.
+    fn parse_function_name(&mut self) -> Result<Identifier, ParseError> {
+        let start = self.offset;
+        self.parse_name("function name")?;
+        if self.try_peek() == Some('?') {
+            self.skip()?;
+        }
+        Ok(Identifier::from(&self.source[start..self.offset]))
+    }
+
     fn parse_sequence(&mut self, end_marker: char) -> Result<Vec<ast::Expression>, ParseError> {
         let mut elements = Vec::new();
         while self.peek()? != end_marker {
@@ -836,6 +1580,46 @@ impl<'a> Parser<'a> {
         Ok(elements)
     }
 
+    /// Returns whether the parser is at the first entry of a map literal, which is a name followed
+    /// by a colon, or at the colon of the empty map literal `{:}`.
+    fn at_map_entry(&self) -> bool {
+        let rest = &self.source[self.offset..];
+        let rest = match rest.chars().next() {
+            Some(ch) if is_ident_start(ch) => rest.trim_start_matches(is_ident),
+            _ => rest,
+        };
+        rest.trim_start().starts_with(':')
+    }
+
+    /// Parses the entries of a map literal, after its opening brace.
+    fn parse_map_entries(&mut self) -> Result<ast::Expression, ParseError> {
+        let mut entries: Vec<(Identifier, ast::Expression)> = Vec::new();
+        if self.consume_token(":").is_ok() {
+            self.consume_whitespace();
+            self.consume_token("}")?;
+            return Ok(ast::MapLiteral { entries }.into());
+        }
+        while self.peek()? != '}' {
+            let location = self.location;
+            let key = self.parse_identifier("map key")?;
+            if entries.iter().any(|(other, _)| *other == key) {
+                return Err(ParseError::DuplicateMapKey(key.to_string(), location));
+            }
+            self.consume_whitespace();
+            self.consume_token(":")?;
+            self.consume_whitespace();
+            let value = self.parse_expression()?;
+            entries.push((key, value));
+            self.consume_whitespace();
+            if self.peek()? != '}' {
+                self.consume_token(",")?;
+                self.consume_whitespace();
+            }
+        }
+        self.consume_token("}")?;
+        Ok(ast::MapLiteral { entries }.into())
+    }
+
     fn parse_list(&mut self) -> Result<ast::Expression, ParseError> {
         let location = self.location;
         self.consume_token("[")?;
@@ -882,6 +1666,9 @@ impl<'a> Parser<'a> {
         if let Ok(_) = self.consume_token("}") {
             return Ok(ast::SetLiteral { elements: vec![] }.into());
         }
+        if self.at_map_entry() {
+            return self.parse_map_entries();
+        }
         let first_element = self.parse_expression()?;
         self.consume_whitespace();
         if let Ok(_) = self.consume_token("}") {
@@ -939,12 +1726,35 @@ impl<'a> Parser<'a> {
         .into())
     }
 
-    fn parse_integer_constant(&mut self) -> Result<ast::Expression, ParseError> {
-        // We'll have already verified that the next digit is an integer.
+    fn parse_number_constant(&mut self) -> Result<ast::Expression, ParseError> {
+        // We'll have already verified that the next character is a sign or a digit.
+        let location = self.location;
         let start = self.offset;
+        if self.peek()? == '-' {
+            self.skip()?;
+            let ch = self.peek()?;
+            if !ch.is_ascii_digit() {
+                return Err(ParseError::UnexpectedCharacter(ch, "number", self.location));
+            }
+        }
         self.consume_while(|ch| ch.is_ascii_digit());
-        let end = self.offset;
-        let value = u32::from_str_radix(&self.source[start..end], 10).unwrap();
+        // A float constant has a fractional part, which must start with a digit.
+        let mut rest = self.source[self.offset..].chars();
+        if rest.next() == Some('.') && matches!(rest.next(), Some(ch) if ch.is_ascii_digit()) {
+            self.skip()?;
+            self.consume_while(|ch| ch.is_ascii_digit());
+            let text = &self.source[start..self.offset];
+            let value = text
+                .parse::<f64>()
+                .ok()
+                .and_then(Float::new)
+                .ok_or_else(|| ParseError::InvalidFloatConstant(text.to_string(), location))?;
+            return Ok(ast::FloatConstant { value }.into());
+        }
+        let text = &self.source[start..self.offset];
+        let value = text
+            .parse::<i64>()
+            .map_err(|_| ParseError::InvalidIntegerConstant(text.to_string(), location))?;
         Ok(ast::IntegerConstant { value }.into())
     }
 
@@ -966,9 +1776,25 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_regex_capture(&mut self) -> Result<ast::RegexCapture, ParseError> {
+    fn parse_regex_capture(&mut self) -> Result<ast::Expression, ParseError> {
         let regex_capture_location = self.location;
         self.consume_token("$")?;
+        if self.try_peek().filter(|c| is_ident_start(*c)).is_some() {
+            let name = self.parse_name("regex capture")?;
+            return Ok(match name {
+                "match_start" | "match_end" => ast::MatchOffset {
+                    end: name == "match_end",
+                    location: regex_capture_location,
+                }
+                .into(),
+                _ => ast::RegexCapture {
+                    match_index: usize::MAX, // set in checker
+                    name: Some(Identifier::from(name)),
+                    location: regex_capture_location,
+                }
+                .into(),
+            });
+        }
         let start = self.offset;
         self.consume_while(|ch| ch.is_ascii_digit());
         let end = self.offset;
@@ -976,7 +1802,12 @@ impl<'a> Parser<'a> {
             return Err(ParseError::InvalidRegexCapture(regex_capture_location));
         }
         let match_index = usize::from_str_radix(&self.source[start..end], 10).unwrap();
-        Ok(ast::RegexCapture { match_index }.into())
+        Ok(ast::RegexCapture {
+            match_index,
+            name: None,
+            location: regex_capture_location,
+        }
+        .into())
     }
 
     fn parse_attributes(&mut self) -> Result<Vec<ast::Attribute>, ParseError> {
@@ -991,17 +1822,61 @@ impl<'a> Parser<'a> {
         Ok(attributes)
     }
 
+    /// Parses the inline attributes of a `node` or `edge` statement, which follow a comma, if
+    /// there is one.
+    fn parse_inline_attributes(&mut self) -> Result<Vec<ast::Attribute>, ParseError> {
+        self.consume_whitespace();
+        if self.try_peek() != Some(',') {
+            return Ok(Vec::new());
+        }
+        self.skip().unwrap();
+        self.consume_whitespace();
+        self.parse_attributes()
+    }
+
     fn parse_attribute(&mut self) -> Result<ast::Attribute, ParseError> {
         let name = self.parse_identifier("attribute name")?;
         self.consume_whitespace();
-        let value = if self.try_peek() == Some('=') {
-            self.consume_token("=")?;
-            self.consume_whitespace();
-            self.parse_expression()?
-        } else {
-            ast::Expression::TrueLiteral
+        let operation = match self.try_peek() {
+            Some('=') => {
+                self.consume_token("=")?;
+                ast::AttributeOperation::Set
+            }
+            Some('+') => {
+                self.consume_token("+=")?;
+                ast::AttributeOperation::Append
+            }
+            Some(':') => {
+                self.consume_token(":=")?;
+                ast::AttributeOperation::Override
+            }
+            _ => {
+                return Ok(ast::Attribute {
+                    name,
+                    value: ast::Expression::TrueLiteral,
+                    operation: ast::AttributeOperation::Set,
+                })
+            }
         };
-        Ok(ast::Attribute { name, value })
+        self.consume_whitespace();
+        let value = self.parse_expression()?;
+        Ok(ast::Attribute {
+            name,
+            value,
+            operation,
+        })
+    }
+
+    fn parse_attribute_names(&mut self) -> Result<Vec<Identifier>, ParseError> {
+        let mut names = vec![self.parse_identifier("attribute name")?];
+        self.consume_whitespace();
+        while self.try_peek() == Some(',') {
+            self.skip().unwrap();
+            self.consume_whitespace();
+            names.push(self.parse_identifier("attribute name")?);
+            self.consume_whitespace();
+        }
+        Ok(names)
     }
 
     fn parse_variable(&mut self) -> Result<ast::Variable, ParseError> {
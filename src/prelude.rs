@@ -0,0 +1,44 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! The types that most programs embedding this library need.
+//!
+//! ```
+//! use tree_sitter_graph::prelude::*;
+//! ```
+//!
+//! This is the supported way to import the library's embedding API.  The names re-exported here
+//! stay available from this module across minor releases, even if the modules that define them
+//! are reorganized.  Types that are not re-exported here are more likely to move.
+
+pub use crate::ast::File;
+pub use crate::execution::error::ExecutionError;
+pub use crate::execution::error::ExecutionProgress;
+pub use crate::execution::CancelAfter;
+pub use crate::execution::CancellationError;
+pub use crate::execution::CancellationFlag;
+pub use crate::execution::ErrorPolicy;
+pub use crate::execution::ExecutionConfig;
+pub use crate::execution::NoCancellation;
+pub use crate::functions::Arguments;
+pub use crate::functions::Function;
+pub use crate::functions::Functions;
+pub use crate::functions::ParameterType;
+pub use crate::functions::Parameters;
+pub use crate::functions::Signature;
+pub use crate::functions::ValueFunction;
+pub use crate::graph::Edge;
+pub use crate::graph::Graph;
+pub use crate::graph::GraphNode;
+pub use crate::graph::GraphNodeRef;
+pub use crate::graph::SyntaxNodeRef;
+pub use crate::graph::Value;
+pub use crate::parser::Location;
+pub use crate::parser::ParseError;
+pub use crate::variables::Globals as Variables;
+pub use crate::variables::VariableError;
+pub use crate::Identifier;
@@ -40,6 +40,40 @@
 //!   - Input parameters: none
 //!   - Output value: a reference to the new graph node
 //!
+//! ## `node-for`
+//!
+//! Returns the graph node registered under a key, creating a new graph node and registering it
+//! the first time the key is used.  This allows stanzas for different syntax nodes to refer to
+//! the same graph node, using a name they can both compute.
+//!
+//!   - Input parameters:
+//!     - `key`: A string
+//!   - Output value: a reference to the graph node registered under `key`
+//!
+//! ## `find-node`
+//!
+//! Returns the graph node registered under a key by [`node-for`](#node-for), without creating
+//! one.  Since the result can be null, it is typically used as an edge endpoint that is guarded
+//! by a check such as `if (not (is-null def))`.
+//!
+//!   - Input parameters:
+//!     - `key`: A string
+//!   - Output value: a reference to the graph node registered under `key`, or `#null` if no graph
+//!     node was registered under it yet
+//!
+//! ## `find-nodes-by-attr`
+//!
+//! Returns the graph nodes that have an attribute with the given value.  The graph nodes are
+//! returned in the order in which they were created.  Only attributes that have been added when
+//! the function is called are taken into account, so in lazy execution, where attributes are
+//! added in an unspecified order, the result can be incomplete.
+//!
+//!   - Input parameters:
+//!     - `name`: A string, the name of the attribute
+//!     - `value`: The value of the attribute
+//!   - Output value: a list of references to the graph nodes whose attribute `name` is equal to
+//!     `value`
+//!
 //! # Logical functions
 //!
 //! ## `not`
@@ -69,10 +103,60 @@
 //!
 //! ## `plus`
 //!
-//! Adds integers together.
+//! Adds numbers together.  If any of the numbers is a float, the integers are converted to
+//! floats, and the sum is a float.  Otherwise, the sum is an integer.  It is an error for the sum
+//! to be too large to be represented.
+//!
+//!   - Input parameters: zero or more integers or floats
+//!   - Output value: the sum of all of the input numbers
+//!
+//! ## `minus`
+//!
+//! Subtracts numbers from the first one, or negates a single number.  Integers and floats are
+//! combined in the same way as by [`plus`](#plus).
+//!
+//!   - Input parameters: one or more integers or floats
+//!   - Output value: the first number minus all of the others, or the negation of the first
+//!     number if there are no others
+//!
+//! # Conversion functions
 //!
-//!   - Input parameters: zero or more integers
-//!   - Output value: the sum of all of the input integers
+//! ## `parse-int`
+//!
+//! Parses an integer from a string.  The string must consist of the digits of the integer, with
+//! an optional leading `+` or `-` sign, and nothing else.  Parsing an empty string, a string with
+//! any other characters, or an integer that does not fit in 64 bits is an error.
+//!
+//!   - Input parameters:
+//!     - `text`: the string to parse
+//!     - `radix`: an optional integer between 2 and 36, the base of the integer (default: 10)
+//!   - Output value: the parsed integer
+//!
+//! ## `int-to-string`
+//!
+//! Formats an integer as a decimal string.
+//!
+//!   - Input parameters:
+//!     - `value`: the integer to format
+//!     - `width`: an optional non-negative integer; the string is padded with zeros after the
+//!       sign, if any, until it is at least this long (default: 0)
+//!   - Output value: the formatted string
+//!
+//! ## `char-to-int`
+//!
+//! Returns the Unicode code point of a character.  Calling it with a string that does not contain
+//! exactly one character is an error.
+//!
+//!   - Input parameters: a string containing a single character
+//!   - Output value: the code point of the character, as an integer
+//!
+//! ## `int-to-char`
+//!
+//! Returns the character with a Unicode code point.  Calling it with an integer that is not a
+//! Unicode scalar value, such as a negative integer or a surrogate, is an error.
+//!
+//!   - Input parameters: an integer
+//!   - Output value: a string containing the character with that code point
 //!
 //! # String functions
 //!
@@ -105,6 +189,136 @@
 //! [`Regex::new`]: https://docs.rs/regex/*/regex/struct.Regex.html#method.new
 //! [`Regex::replace_all`]: https://docs.rs/regex/*/regex/struct.Regex.html#method.replace_all
 //!
+//! ## `split`
+//!
+//! Splits a string at every occurrence of a separator.  Splitting with an empty separator splits
+//! the string into its characters.
+//!
+//!   - Input parameters:
+//!     - `text`: the string to split
+//!     - `separator`: the string that separates the parts
+//!   - Output value: a list of the parts of the string, including empty parts between adjacent
+//!     separators and at either end.  Splitting an empty string with an empty separator results
+//!     in an empty list.
+//!
+//! ## `trim`
+//!
+//! Removes whitespace from both ends of a string.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!   - Output value: the string without leading and trailing whitespace
+//!
+//! ## `to-lower`
+//!
+//! Converts a string to lowercase, according to the Unicode lowercase mapping.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!   - Output value: the lowercase string
+//!
+//! ## `to-upper`
+//!
+//! Converts a string to uppercase, according to the Unicode uppercase mapping.  The result can be
+//! longer than the input; for instance, `ß` becomes `SS`.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!   - Output value: the uppercase string
+//!
+//! ## `starts-with`
+//!
+//! Tests whether a string starts with a prefix.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!     - `prefix`: the prefix to look for
+//!   - Output value: `#true` if `text` starts with `prefix`, `#false` otherwise
+//!
+//! ## `ends-with`
+//!
+//! Tests whether a string ends with a suffix.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!     - `suffix`: the suffix to look for
+//!   - Output value: `#true` if `text` ends with `suffix`, `#false` otherwise
+//!
+//! ## `contains`
+//!
+//! Tests whether a string contains another string.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!     - `needle`: the string to look for
+//!   - Output value: `#true` if `needle` occurs in `text`, `#false` otherwise
+//!
+//! ## `index-of`
+//!
+//! Finds the first occurrence of a string in another string.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!     - `needle`: the string to look for
+//!   - Output value: the index, in characters, of the first occurrence of `needle` in `text`, or
+//!     `#null` if it does not occur
+//!
+//! ## `substring`
+//!
+//! Returns the characters of a string between two indices.  Indices count characters, not bytes,
+//! so a substring never splits a character.  It is an error if the start index is negative, or
+//! greater than the end index, or if the end index is greater than the number of characters.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!     - `start`: the index of the first character of the substring
+//!     - `end`: an optional index of the character after the substring (default: the number of
+//!       characters of `text`)
+//!   - Output value: the substring
+//!
+//! ## `nfc`
+//!
+//! Normalizes a string to Unicode Normalization Form C, in which accented characters are
+//! composed wherever possible.  Use this to compare names that might be written with either
+//! composed or decomposed accents.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!   - Output value: the normalized string
+//!
+//! Only available if the library is built with the `unicode-normalization` feature, which is
+//! enabled by default.
+//!
+//! ## `nfkc`
+//!
+//! Normalizes a string to Unicode Normalization Form KC.  Like `nfc`, but also replaces
+//! compatibility characters, such as ligatures and full-width letters, with their ordinary
+//! equivalents.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!   - Output value: the normalized string
+//!
+//! Only available if the library is built with the `unicode-normalization` feature, which is
+//! enabled by default.
+//!
+//! ## `fold-identifier`
+//!
+//! Normalizes a string to Unicode Normalization Form KC and converts it to lowercase.  Two
+//! identifiers that fold to the same string are equivalent for languages that compare identifiers
+//! ignoring case, as described in [Unicode Standard Annex #31][uax31].  Folding uses lowercase
+//! mapping instead of full case folding, so a few characters, such as `ß`, do not fold to the
+//! same string as their uppercase counterparts.
+//!
+//!   - Input parameters:
+//!     - `text`: a string
+//!   - Output value: the folded string
+//!
+//! Only available if the library is built with the `unicode-normalization` feature, which is
+//! enabled by default.
+//!
+//! [uax31]: https://www.unicode.org/reports/tr31/
+//!
 //! # List functions
 //!
 //! ## `concat`
@@ -139,6 +353,171 @@
 //!   - Input parameters: a list value
 //!   - Output value: an integer indicating the length of the list
 //!
+//! ## `concat-lists`
+//!
+//! Concatenate list arguments, like [`concat`](#concat).
+//!
+//!  - Input parameters: list values
+//!  - Output value: the concatenation of the input lists
+//!
+//! ## `reverse`
+//!
+//! Reverses a list.
+//!
+//!   - Input parameters: a list value
+//!   - Output value: a list with the same elements in reverse order
+//!
+//! ## `sort`
+//!
+//! Sorts a list.  Values of the same type are ordered as expected: `#false` before `#true`,
+//! numbers by value, strings lexicographically by their bytes, and lists element by element.
+//! Values of different types are ordered by type, in this order: null, booleans, integers,
+//! floats, strings, lists, sets, locations, syntax nodes, and graph nodes.  Integers and floats
+//! are different types, so all integers come before all floats.
+//!
+//!   - Input parameters: a list value
+//!   - Output value: a list with the same elements in ascending order
+//!
+//! ## `nth`
+//!
+//! Returns an element of a list.
+//!
+//!   - Input parameters:
+//!     - `list`: a list value
+//!     - `index`: the index of the element, starting at 0
+//!   - Output value: the element at `index`
+//!
+//! It is an error if `index` is negative, or not smaller than the length of the list.
+//!
+//! ## `first`
+//!
+//! Returns the first element of a list.
+//!
+//!   - Input parameters: a list value
+//!   - Output value: the first element of the list, or `#null` if it is empty
+//!
+//! When the argument is a capture, as in `(first @params)`, the first captured node is returned
+//! instead, or `#null` if there is none.
+//!
+//! ## `last`
+//!
+//! Returns the last element of a list.
+//!
+//!   - Input parameters: a list value
+//!   - Output value: the last element of the list, or `#null` if it is empty
+//!
+//! When the argument is a capture, as in `(last @params)`, the last captured node is returned
+//! instead, or `#null` if there is none.
+//!
+//! ## `last?`
+//!
+//! Tests whether an index is the index of the last element of a list.  This is useful in a `for`
+//! statement with an index variable, to treat the last element differently.
+//!
+//!   - Input parameters:
+//!     - `list`: a list value
+//!     - `index`: an index into the list, starting at 0
+//!   - Output value: `#true` if `index` is one less than the length of the list, `#false`
+//!     otherwise, including when the list is empty
+//!
+//! ## `contains?`
+//!
+//! Test whether a list or set contains a value.  Unlike other identifiers, the names of functions
+//! can end with a `?`.
+//!
+//!   - Input parameters:
+//!     - `collection`: a list or set value
+//!     - `value`: any value
+//!   - Output value: a boolean indicating whether `value` is an element of `collection`
+//!
+//! ## `to-list`
+//!
+//! Converts a set into a list.
+//!
+//!   - Input parameters: a list or set value
+//!   - Output value: a list with the elements of the set, in ascending order, as
+//!     [`sort`](#sort) orders them.  A list is returned unchanged.
+//!
+//! # Set functions
+//!
+//! ## `set-add`
+//!
+//! Adds values to a set.
+//!
+//!   - Input parameters:
+//!     - `set`: a set value
+//!     - zero or more values to add
+//!   - Output value: a set with the elements of `set` and the added values
+//!
+//! ## `set-union`
+//!
+//! Combines sets.
+//!
+//!   - Input parameters: set values
+//!   - Output value: a set with the elements of any of the input sets
+//!
+//! ## `set-intersect`
+//!
+//! Intersects sets.
+//!
+//!   - Input parameters: one or more set values
+//!   - Output value: a set with the elements of the first set that are in all of the other sets
+//!
+//! ## `set-difference`
+//!
+//! Subtracts sets from a set.
+//!
+//!   - Input parameters: one or more set values
+//!   - Output value: a set with the elements of the first set that are in none of the other sets
+//!
+//! ## `to-set`
+//!
+//! Converts a list into a set.
+//!
+//!   - Input parameters: a list or set value
+//!   - Output value: a set with the elements of the list, without duplicates.  A set is returned
+//!     unchanged.
+//!
+//! # Map functions
+//!
+//! ## `map-get`
+//!
+//! Looks up an entry of a map.
+//!
+//!   - Input parameters:
+//!     - `map`: a map value
+//!     - `key`: a string value
+//!   - Output value: the value of the entry of `map` named `key`, or `#null` if there is no such
+//!     entry
+//!
+//! ## `map-set`
+//!
+//! Sets an entry of a map.
+//!
+//!   - Input parameters:
+//!     - `map`: a map value
+//!     - `key`: a string value
+//!     - `value`: any value
+//!   - Output value: a map with the entries of `map`, and an entry named `key` with `value`,
+//!     which replaces the existing entry with that name.  The input map is unchanged.
+//!
+//! ## `map-keys`
+//!
+//! Returns the names of the entries of a map.
+//!
+//!   - Input parameters:
+//!     - `map`: a map value
+//!   - Output value: a list of strings with the names of the entries of `map`, in sorted order
+//!
+//! ## `map-contains?`
+//!
+//! Tests whether a map has an entry.
+//!
+//!   - Input parameters:
+//!     - `map`: a map value
+//!     - `key`: a string value
+//!   - Output value: `#true` if `map` has an entry named `key`, `#false` otherwise
+//!
 //! # Syntax manipulation functions
 //!
 //! ## `named-child-index`
@@ -160,6 +539,135 @@
 //!   - Output value:
 //!     - The number of _named_ children in `node`
 //!
+//! ## `child-count`
+//!
+//! Returns the number of children of a syntax node, including anonymous ones.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - The number of children in `node`
+//!
+//! ## `parent`
+//!
+//! Returns the parent of a syntax node.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - The parent of `node`, or `#null` if it is the root node
+//!
+//! Like all of the navigation functions below, this adds the syntax node it returns to the graph,
+//! so that it can have scoped variables and graph nodes attached to it, just like a captured
+//! node.  The navigation functions return `#null` instead of failing when there is no such node,
+//! so that they can be combined with [`is-null`](#is-null).
+//!
+//! ``` tsg
+//! (identifier) @id
+//! {
+//!   let parent = (parent @id)
+//!   if (not (is-null parent)) {
+//!     node n
+//!     attr (n) parent_type = (node-type parent)
+//!   }
+//! }
+//! ```
+//!
+//! ## `next-sibling`
+//!
+//! Returns the next sibling of a syntax node, including anonymous ones.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - The next sibling of `node`, or `#null` if it is the last child of its parent
+//!
+//! ## `prev-sibling`
+//!
+//! Returns the previous sibling of a syntax node, including anonymous ones.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - The previous sibling of `node`, or `#null` if it is the first child of its parent
+//!
+//! ## `next-named-sibling`
+//!
+//! Returns the next _named_ sibling of a syntax node.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - The next named sibling of `node`, or `#null` if there is none
+//!
+//! ## `prev-named-sibling`
+//!
+//! Returns the previous _named_ sibling of a syntax node.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - The previous named sibling of `node`, or `#null` if there is none
+//!
+//! ## `child`
+//!
+//! Returns a child of a syntax node, including anonymous ones.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!     - `index`: The index of the child, starting at 0
+//!   - Output value:
+//!     - The child of `node` at `index`, or `#null` if `index` is negative or `node` does not
+//!       have that many children
+//!
+//! ## `named-child`
+//!
+//! Returns a _named_ child of a syntax node.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!     - `index`: The index of the child among the named children of `node`, starting at 0
+//!   - Output value:
+//!     - The named child of `node` at `index`, or `#null` if `index` is negative or `node` does
+//!       not have that many named children
+//!
+//! ## `child-by-field-name`
+//!
+//! Returns the child of a syntax node in a field.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!     - `field`: The name of the field, as a string
+//!   - Output value:
+//!     - The first child of `node` in `field`, or `#null` if there is none, or the language has
+//!       no such field
+//!
+//! ## `leading-comments`
+//!
+//! Returns the comments immediately preceding a syntax node, such as the comment block
+//! documenting a definition.  The previous siblings of the node are collected while they are
+//! comments, which may be separated by blank lines.  A comment on the same line as the end of
+//! preceding code trails that code, and is not included.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!     - `kinds`: An optional list of the syntax node types that are comments.  By default, every
+//!       type whose name contains `comment` is a comment.
+//!   - Output value:
+//!     - A list of the comment syntax nodes, in source order
+//!
+//! ## `trailing-comment`
+//!
+//! Returns the comment that follows a syntax node on the same line as the node ends, if any.
+//! Comments that the grammar attaches to an ancestor of the node are found as well.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!     - `kinds`: An optional list of the syntax node types that are comments, as for
+//!       `leading-comments`
+//!   - Output value:
+//!     - The comment syntax node, or `#null` if there is no trailing comment
+//!
 //! ## `source-text`
 //!
 //! Returns the source text represented by a syntax node.
@@ -169,6 +677,56 @@
 //!   - Output value:
 //!     - A string containing the source text represented by `node`
 //!
+//! The source text is the text that the syntax tree was parsed from, which is passed to every
+//! function.  Multi-byte characters are never split.  It is an error if the syntax node is not
+//! within the source text, which happens if the tree was not parsed from it.
+//!
+//! ## `location-of`
+//!
+//! Returns the source location of a syntax node.  If a second syntax node is given, the location
+//! spans from the start of the first node to the end of the second.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!     - `end`: An optional syntax node
+//!   - Output value:
+//!     - The source location of `node`, or of the range from `node` to `end`
+//!
+//! ## `range-contains`
+//!
+//! Checks whether a source location lies within a range, or within any of a list of ranges.  This
+//! allows a stanza to only act on matches within ranges provided by the host, such as the ranges
+//! of a file that changed since it was last processed:
+//!
+//! ``` tsg
+//! global changed_ranges
+//!
+//! (function_definition) @def
+//! {
+//!   if (range-contains changed_ranges @def) {
+//!     node n
+//!   }
+//! }
+//! ```
+//!
+//!   - Input parameters:
+//!     - `ranges`: A source location, or a list of source locations
+//!     - `node`: A syntax node or a source location
+//!   - Output value:
+//!     - A boolean indicating whether `node` lies entirely within one of the `ranges`
+//!
+//! ## `ranges-overlap`
+//!
+//! Checks whether two source locations have at least one byte in common.  Either parameter can also
+//! be a list, in which case the function checks whether any of its elements overlaps the other
+//! parameter.
+//!
+//!   - Input parameters:
+//!     - `a`: A syntax node, a source location, or a list of them
+//!     - `b`: A syntax node, a source location, or a list of them
+//!   - Output value:
+//!     - A boolean indicating whether `a` and `b` overlap
+//!
 //! ## `node-type`
 //!
 //! Returns a syntax node's type as a string.  (The type is the name of the node's grammar rule in
@@ -179,6 +737,32 @@
 //!   - Output value:
 //!     - A string containing the type of `node`
 //!
+//! The type of an anonymous syntax node, such as an operator, is its text, as in `+`.
+//!
+//! ## `node-kind`
+//!
+//! Returns a syntax node's type as a string, like [`node-type`](#node-type).
+//!
+//! ## `is-named`
+//!
+//! Tests whether a syntax node is named.  Anonymous syntax nodes, such as operators and keywords,
+//! are matched by string patterns in queries.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - A boolean indicating whether `node` is named
+//!
+//! ## `node-range`
+//!
+//! Returns the start and end positions of a syntax node.
+//!
+//!   - Input parameters:
+//!     - `node`: A syntax node
+//!   - Output value:
+//!     - A list of four integers: the zero-based start row, start column, end row, and end column
+//!       of `node`.  Columns count bytes, as in [`start-column`](#start-column).
+//!
 //! ## `start-column`
 //!
 //! Returns the zero-based start column of a syntax node.
@@ -214,3 +798,32 @@
 //!     - `node`: A syntax node
 //!   - Output value:
 //!     - The zero-based end row of `node`
+//!
+//! # Capability functions
+//!
+//! These functions give graph DSL files access to the environment of the process that executes
+//! them, so they are not part of the standard library by default.  The process must enable the
+//! capability that each of them needs with
+//! [`Functions::add_standard_functions_with`][crate::functions::Functions::add_standard_functions_with].
+//! Calling one of them without its capability is an error that names the capability.
+//!
+//! ## `now-iso8601`
+//!
+//! Returns the current time.  Needs the `time` capability.
+//!
+//!   - Input parameters: none
+//!   - Output value:
+//!     - The current UTC time, formatted as in `2024-01-02T03:04:05Z`
+//!
+//! For reproducible builds, the process can provide a fixed value to return instead, with
+//! [`Capabilities::fixed_time`][crate::functions::Capabilities::fixed_time].
+//!
+//! ## `env`
+//!
+//! Returns the value of an environment variable.  Needs the `env` capability.
+//!
+//!   - Input parameters:
+//!     - `name`: The name of the environment variable, as a string
+//!   - Output value:
+//!     - The value of the environment variable, or `#null` if it is not set, or its value is not
+//!       valid Unicode
@@ -80,6 +80,10 @@
 //! Query patterns can be suffixed by quantification operators. If a pattern with a quantification
 //! suffix is captured, the suffix determines the capture value. In the case of `?`, the capture value
 //! is a null value, or a syntax node. In the case of `+` and `*`, the value is a list of syntax nodes.
+//! The list contains every node matched by the quantified pattern, in the order in which they appear
+//! in the source, and is empty if a `*` pattern matches nothing.  The quantifiers of captures are
+//! known when a file is checked, so it is an error to use a capture that is not a list where a list
+//! is expected, such as in a `for` statement.
 //!
 //! [quantification]: https://tree-sitter.github.io/tree-sitter/using-parsers#quantification-operators
 //!
@@ -115,6 +119,127 @@
 //! }
 //! ```
 //!
+//! ## Versions
+//!
+//! A graph DSL file can declare the version of the DSL that it is written for, using a version
+//! directive at the top of the file.  The directive is either a `version` declaration, or a comment
+//! of the form `;; tsg N`, and can only be preceded by whitespace and other comments:
+//!
+//! ``` tsg
+//! ;; tsg 2
+//!
+//! (identifier) @id
+//! {
+//! }
+//! ```
+//!
+//! It is an error to declare a version that is newer than the library supports.  Files that do not
+//! declare a version are parsed using the latest version.  Files that declare version 1 are parsed
+//! using the grammar of that version where later versions changed it:
+//!
+//!   - In version 1, the `some` and `none` keywords of [conditionals](#conditionals) are
+//!     recognized at the start of an identifier, so that `if none_found` is parsed as `if none
+//!     _found`.  From version 2 on, `none_found` is a variable name.
+//!
+//! ## Query predicates
+//!
+//! Query patterns can use tree-sitter's [predicates][] to only match if the text of a capture
+//! satisfies a condition.  Matches that do not satisfy the predicates of their stanza are skipped
+//! before the stanza is executed.
+//!
+//!   - `(#eq? @capture "text")` only matches if the text of `@capture` is equal to the string.
+//!     The second argument can also be another capture, whose text must be equal.
+//!
+//!   - `(#match? @capture "regex")` only matches if the text of `@capture` matches the regular
+//!     expression.
+//!
+//!   - `(#any-of? @capture "text" ...)` only matches if the text of `@capture` is equal to one of
+//!     the strings.
+//!
+//!   - `#not-eq?`, `#not-match?`, and `#not-any-of?` only match if the corresponding predicate
+//!     does not.
+//!
+//! If a capture matches several syntax nodes, `#eq?`, `#match?`, and their negations only check
+//! the text of the first one, while the other predicates check the text of every one of them.
+//! It is an error to use any other predicate, such as tree-sitter's `#is?` property
+//! predicates, which are not supported.
+//!
+//! ``` tsg
+//! ((identifier) @id (#any-of? @id "self" "cls"))
+//! {
+//!   ; Only executed for identifiers whose text is self or cls.
+//! }
+//! ```
+//!
+//! In addition, the graph DSL supports predicates that compare the text of a capture against the
+//! value of a [global variable](#variables):
+//!
+//!   - `(#eq-global? @capture name)` only matches if the text of `@capture` is equal to the value
+//!     of the global variable `name`, which must be a string.
+//!
+//!   - `(#match-global? @capture name)` only matches if the text of `@capture` matches the regular
+//!     expression that is the value of the global variable `name`.
+//!
+//! It is an error if the global variable is not defined, is not a string, or is not a valid regular
+//! expression.  These errors are reported when execution starts, before any stanza is executed.
+//!
+//! ``` tsg
+//! global module_name
+//!
+//! ((identifier) @id (#eq-global? @id module_name))
+//! {
+//!   ; Only executed for identifiers whose text is the module name.
+//! }
+//! ```
+//!
+//! [predicates]: https://tree-sitter.github.io/tree-sitter/using-parsers#predicates
+//!
+//! ## Phases
+//!
+//! Stanzas can be grouped into named **_phases_**.  A `phase` declaration, at the same level as
+//! stanzas, assigns all stanzas that follow it to the named phase, up to the next `phase`
+//! declaration.  Phases are executed in the order in which they are first declared, and all
+//! matches of the stanzas in one phase are executed before any stanza of the next phase, in both
+//! regular and lazy execution.  Within a phase, stanzas are executed as usual.  Stanzas that precede
+//! the first `phase` declaration are executed before all phases.
+//!
+//! ``` tsg
+//! phase declare
+//!
+//! (function_definition name: (identifier) @name) @def
+//! {
+//!   node @def.node
+//! }
+//!
+//! phase connect
+//!
+//! (call function: (identifier) @name)
+//! {
+//!   ; All definitions have been created at this point.
+//! }
+//! ```
+//!
+//! A phase can be declared again later in the file, to add more stanzas to it.  It is an error to
+//! read a scoped variable in a phase before any phase in which a variable with that name is set,
+//! since the read could never see the value.
+//!
+//! ## Stanza names
+//!
+//! A stanza can be given a name by preceding it with a `stanza` declaration.  Names must be unique
+//! within a file.  They do not change how the stanza is executed, but allow an _overlay_ to
+//! override the stanza: an overlay is another graph DSL file, applied to a file with
+//! [`File::apply_overlay`][crate::ast::File::apply_overlay], whose stanzas replace, or are added
+//! after, the stanzas of the file with the same name.
+//!
+//! ``` tsg
+//! stanza imports
+//! (import_statement name: (dotted_name) @name)
+//! {
+//!   node @name.def
+//!   attr (@name.def) kind = "import"
+//! }
+//! ```
+//!
 //! # Expressions
 //!
 //! The value of an expression in the graph DSL can be any of the following:
@@ -122,13 +247,16 @@
 //!   - null
 //!   - a boolean
 //!   - a string
-//!   - an integer (unsigned, 32 bits)
+//!   - an integer (signed, 64 bits)
+//!   - a float (64 bits, finite)
+//!   - a source location
 //!   - a reference to a syntax node
 //!   - a reference to a graph node
 //!   - an ordered list of values
 //!   - a list comprehension
 //!   - an unordered set of values
 //!   - a set comprehension
+//!   - a map from names to values
 //!
 //! The null value is spelled `#null`.
 //!
@@ -141,11 +269,20 @@
 //!   - `"a string with\na newline"`
 //!   - `"a string with\\a backslash"`
 //!
-//! Integer constants are encoded in ASCII decimal:
+//! Integer constants are encoded in ASCII decimal, with an optional leading minus sign:
 //!
 //!   - `0`
 //!   - `10`
-//!   - `42`
+//!   - `-42`
+//!
+//! Float constants are encoded the same way, with a decimal point followed by at least one digit:
+//!
+//!   - `0.25`
+//!   - `1.5`
+//!   - `10.0`
+//!
+//! Floats are always finite.  An integer and a float are never equal, even if they have the same
+//! numeric value, so `1` and `1.0` are different elements of a set.
 //!
 //! Lists consist of zero or more expressions, separated by commas, enclosed in square brackets.
 //! The elements of a list do not have to have the same type:
@@ -172,6 +309,26 @@
 //! ]
 //! ```
 //!
+//! Maps consist of zero or more entries, separated by commas, enclosed in curly braces.  Every
+//! entry is a name, followed by a colon and an expression.  The names of the entries of a map
+//! must be different, and their values do not have to have the same type:
+//!
+//! ``` tsg
+//! {name: (source-text @id), arity: 2, exported: #true}
+//! ```
+//!
+//! Since `{}` is the empty set, the empty map is spelled `{:}`.  Maps also allow a trailing comma
+//! after the final entry.  Their entries are read and updated with the [map functions][].
+//!
+//! [map functions]: functions/index.html#map-functions
+//!
+//! Source locations are ranges of the source code, with the byte offsets and the rows and columns
+//! of their start and end.  They are created by the [`location-of`][] function, and
+//! displayed as `ROW:COLUMN-ROW:COLUMN` with one-based rows and columns, such as `3:7-3:12`.
+//! Unlike syntax nodes, locations can span several nodes.
+//!
+//! [`location-of`]: functions/index.html#location-of
+//!
 //! List comprehensions allow mapping over a list and producing a new list with elements based on the
 //! given element expression:
 //!
@@ -197,9 +354,43 @@
 //! example stanza, whose query is `(identifier) @id`, `@id` would refer to the `identifier` syntax
 //! node that the stanza matched against.
 //!
+//! Captures can refer to anonymous syntax nodes, such as operators and keywords, which queries
+//! match with string patterns, as well as to named ones.  Anonymous syntax nodes are supported
+//! everywhere that named ones are: they can have scoped variables and graph nodes attached to
+//! them, they can be used as attribute values, and functions such as `source-text` and
+//! `start-column` work on them.  When displayed, the kind of an anonymous syntax node is quoted,
+//! as in `[syntax node "+" (1, 7)]`.
+//!
+//! ``` tsg
+//! (binary_operator left: (_) @left operator: "+" @op right: (_) @right)
+//! {
+//!   node @op.node
+//!   attr (@op.node) operator = (source-text @op)
+//!   edge @op.node -> @left.node
+//!   edge @op.node -> @right.node
+//! }
+//! ```
+//!
 //! Unused query captures are considered errors, unless they start with an underscode. For example,
 //! a capture `@id` must be used within the stanza, but `@_id` does not.
 //!
+//! The nodes bound to a capture with a `*` or `+` quantifier can be summarized without building a
+//! list of them.  `(count @params)` is the number of nodes bound to `@params` in the current match,
+//! and `(first @params)` and `(last @params)` are its first and last node, or null if it is empty.
+//! These forms are only allowed on captures with a `*` or `+` quantifier; it is an error to use
+//! them on any other capture.
+//!
+//! ``` tsg
+//! (function_definition parameters: (parameters (_)* @params)) @def
+//! {
+//!   node @def.node
+//!   attr (@def.node) param_count = (count @params)
+//!   if (not (is-null (first @params))) {
+//!     attr (@def.node) first_param = (source-text (first @params))
+//!   }
+//! }
+//! ```
+//!
 //! # Variables
 //!
 //! You can use variables to pass information between different stanzas and statements in a graph
@@ -222,6 +413,18 @@
 //! be suffixed by a quantifier: '*' and '+' for lists, and '?' for optional values, which allows them to
 //! be used in iteration and conditional statements, respectively.
 //!
+//! A global variable can be given a default string value, which is used if the external process does
+//! not provide one:
+//!
+//! ``` tsg
+//! global FILE_PATH
+//! global LANG = "python"
+//! ```
+//!
+//! Execution fails before any stanza is executed if any declared global variable without a default
+//! is not provided, and the error lists all of the missing variables.  Referring to a global variable
+//! that is not declared is an error when the file is checked.
+//!
 //! Local and scoped variables are created using `var` or `let` statements.  A `let` statement
 //! creates an **_immutable variable_**, whose value cannot be changed.  A `var` statement creates
 //! a **_mutable variable_**.  You use a `set` statement to change the value of a mutable variable.
@@ -258,6 +461,100 @@
 //! Variables can be referenced anywhere that you can provide an expression.  It's an error if you
 //! try to reference a variable that hasn't been defined.
 //!
+//! ## Mutable scoped variables
+//!
+//! A scoped variable created with `var` can be changed with `set` by any later stanza, which lets
+//! a file classify syntax nodes in two passes: early stanzas make a guess, and later ones refine
+//! it.  The change is visible to every stanza and match that is executed after it.  Stanzas are
+//! executed in the order in which they appear in the file (phase by phase, if the file has
+//! [phases](#phases)), and all matches of a stanza are executed before the next stanza, so a
+//! stanza sees the value set by the last earlier stanza that matched.  Lazy execution, which
+//! visits matches in a different order, gives reads the same values.
+//!
+//! ``` tsg
+//! ; first pass: every identifier starts out unclassified
+//! (identifier) @id
+//! {
+//!   var @id.kind = "unknown"
+//! }
+//!
+//! ; second pass: refine the guess for the identifiers we know more about
+//! (assignment left: (identifier) @id)
+//! {
+//!   set @id.kind = "variable"
+//! }
+//!
+//! (function_definition name: (identifier) @id)
+//! {
+//!   set @id.kind = "function"
+//! }
+//!
+//! ; this stanza sees the refined values
+//! (identifier) @id
+//! {
+//!   node n
+//!   attr (n) name = (source-text @id), kind = @id.kind
+//! }
+//! ```
+//!
+//! It's an error to `set` a scoped variable that was created with `let`, and a different error to
+//! `set` a scoped variable that was never created on that syntax node.  If no stanza creates a
+//! scoped variable with that name at all, or all of them use `let`, the file is rejected when it
+//! is parsed; otherwise, the error is reported during execution.
+//!
+//! ## Evaluation order
+//!
+//! Strict execution evaluates every expression when its statement is executed, so a value can
+//! only depend on values that were computed before it, and cycles cannot occur.  Reading a scoped
+//! variable that a later stanza defines is an error instead.
+//!
+//! Lazy execution defers the values of variables and attributes until all stanzas have been
+//! executed, and then evaluates each value once, after the values it depends on.  Which values a
+//! stanza can see does not depend on the order of evaluation: a read of a scoped variable sees the
+//! value described in [Mutable scoped variables](#mutable-scoped-variables).  The only thing
+//! evaluation order cannot resolve is a value that depends on itself, as in the following stanzas,
+//! where `@root.a` is computed from `@root.b`, and `@root.b` from `@root.a`:
+//!
+//! ``` tsg
+//! (module) @root
+//! {
+//!   let @root.a = @root.b
+//! }
+//!
+//! (module) @root
+//! {
+//!   let @root.b = @root.a
+//! }
+//! ```
+//!
+//! Executing this file lazily fails with a cyclic dependency error, which lists every variable in
+//! the cycle, in the order in which they depend on each other, each with the syntax node it
+//! belongs to and the location of the statement that defines it.  A file whose variables are only
+//! computed from variables defined by earlier statements, as strict execution requires, is free of
+//! cycles by construction.
+//!
+//! ## Shadowing
+//!
+//! A local variable can have the same name as a global variable that is not declared by the file,
+//! but provided by the process executing it, and a local variable in a `scan`, `if`, or `for`
+//! block can have the same name as a local variable of an enclosing block.  Captures can also have
+//! the same name as a global variable.  These are not errors, but since the meaning of the name
+//! silently changes when a global is added later, they are reported as warnings by
+//! `File::check_warnings`.  A `; #allow(shadow)` comment at the end of a statement's line, or on
+//! the line before it, suppresses the warnings for that statement.  Before a stanza, it
+//! suppresses the warnings for the stanza's captures.
+//!
+//! ``` tsg
+//! (identifier) @id
+//! {
+//!   let name = (source-text @id)
+//!   if (eq name "self") {
+//!     ; #allow(shadow)
+//!     let name = "this"
+//!   }
+//! }
+//! ```
+//!
 //! # Functions
 //!
 //! The process executing a graph DSL file can provide **_functions_** that can be called from
@@ -358,6 +655,11 @@
 //! }
 //! ```
 //!
+//! An edge can also be written with a `<-` arrow, which points from the graph node on the right to
+//! the graph node on the left.  `edge @name.sink <- @name.source` creates the same edge as the
+//! statement above.  The `<-` arrow can be used wherever edges are written, including the targets
+//! of `attr` and `delattr` statements.
+//!
 //! There can be at most one edge connecting any particular source and sink graph node in the
 //! graph.  If multiple stanzas create edges between the same graph nodes, those are "collapsed"
 //! into a single edge.
@@ -380,7 +682,10 @@
 //! ```
 //!
 //! Note that you have to have already created the graph node or edge, and the graph node or edge
-//! must not already have an attribute with the same name.
+//! must not already have an attribute with the same name and a different value.  Setting an
+//! attribute to a value that is equal to the one it already has does nothing, so that a stanza
+//! whose patterns overlap can match the same syntax node more than once.  Setting it to a
+//! different value is an error, which reports both statements that set the attribute.
 //!
 //! (Attributes might seem similar to scoped variables, but they are quite different.  Attributes
 //! are attached to graph nodes and edges, while scoped variables are attached to syntax nodes.
@@ -388,6 +693,120 @@
 //! execution has completed, the variables disappear.  Attributes, on the other hand, are part of
 //! the output produced by the graph DSL file, and live on after execution has finished.)
 //!
+//! ## Several targets
+//!
+//! An `attr` statement can add the same attributes to several graph nodes, or to several edges,
+//! by listing them separated by commas:
+//!
+//! ``` tsg
+//! (import_from_statement module_name: (_) @module name: (_) @name)
+//! {
+//!   node @module.def
+//!   node @name.def
+//!   attr (@module.def, @name.def) is_import = #true, lang = "python"
+//! }
+//! ```
+//!
+//! The attribute values are evaluated once.  The targets then receive all of the attributes in
+//! order, one target after the other.  The statement is not atomic: if adding an attribute to a
+//! target fails, for instance because the target already has the attribute, the targets before it
+//! keep the attributes they received.  A statement cannot mix graph nodes and edges.
+//!
+//! ## Inline attributes
+//!
+//! A `node` or `edge` statement can add attributes to the graph node or edge that it creates,
+//! listed after a comma:
+//!
+//! ``` tsg
+//! (import_statement name: (_) @name)
+//! {
+//!   node @name.source
+//!   node @name.sink, kind = "module"
+//!   edge @name.source -> @name.sink, precedence = 10
+//! }
+//! ```
+//!
+//! This is the same as an `attr` statement that directly follows the `node` or `edge` statement.
+//! In particular, the inline attributes conflict with attributes that are later set to a
+//! different value, and an edge that already exists receives the inline attributes as well.
+//! The inline attributes of a `node` statement can refer to the graph node that it creates.
+//!
+//! ## Appending to attributes
+//!
+//! An attribute can collect values from several statements, or several stanzas, by using `+=`
+//! instead of `=`.  The first append creates a list containing the value, and each later append
+//! adds its value to the end of that list.  Duplicate values are kept.
+//!
+//! ``` tsg
+//! (function_definition name: (identifier) @name) @def
+//! {
+//!   node @def.node
+//!   attr (@def.node) tags += "function"
+//! }
+//!
+//! (decorated_definition definition: (_) @def)
+//! {
+//!   attr (@def.node) tags += "decorated"
+//! }
+//! ```
+//!
+//! Values are appended in the order of the stanzas in the file, and, within a stanza, in the order
+//! of its matches, in both strict and lazy execution.  It is an error to append to an attribute
+//! that was set to a value that is not a list; the error points at both statements.
+//!
+//! ## Overriding and removing attributes
+//!
+//! A later, more specific stanza can replace the value of an attribute that an earlier stanza
+//! set, by using `:=` instead of `=`:
+//!
+//! ``` tsg
+//! (identifier) @id
+//! {
+//!   node @id.node
+//!   attr (@id.node) kind = "reference"
+//! }
+//!
+//! (call function: (identifier) @id)
+//! {
+//!   attr (@id.node) kind := "call"
+//! }
+//! ```
+//!
+//! Overriding must be explicit: setting an attribute with `=` still fails if it has a different
+//! value.  It is an error to override an attribute that is not set, since that usually means that
+//! the stanza that should have set it did not match.
+//!
+//! A `delattr` statement removes attributes from graph nodes or edges.  It takes the same targets
+//! as an `attr` statement, followed by the names of the attributes to remove.  Removing an
+//! attribute that is not set does nothing, and a removed attribute can be set again with `=`.
+//!
+//! ``` tsg
+//! (decorated_definition definition: (_) @def)
+//! {
+//!   delattr (@def.node) tags, is_exported
+//! }
+//! ```
+//!
+//! Attributes are overridden and removed in the order of the stanzas in the file, in both strict
+//! and lazy execution, so a stanza sees the values set by the stanzas before it.
+//!
+//! ## Hidden attributes
+//!
+//! Attributes whose names begin with an underscore are **_hidden_**.  They are useful for
+//! bookkeeping that later stanzas need, but that consumers of the graph should not see:
+//!
+//! ``` tsg
+//! (identifier) @id
+//! {
+//!   node @id.node
+//!   attr (@id.node) name = (source-text @id), _seen = #true
+//! }
+//! ```
+//!
+//! Hidden attributes behave like any other attribute during execution: they can be read, and
+//! setting them twice to different values is an error.  They are left out when the graph is
+//! printed or serialized, unless the output is asked to include them.
+//!
 //! ## Attribute shorthands
 //!
 //! Commonly used combinations of attributes can be captured in **_shorthands_**.  Each shorthand defines
@@ -405,6 +824,63 @@
 //! }
 //! ```
 //!
+//! A shorthand that does not need a value leaves out the variable, and is used like an attribute
+//! without a value:
+//!
+//! ``` tsg
+//! attribute def_attrs => kind = "definition", is_exported = #true
+//!
+//! (function_definition name: (identifier) @name) {
+//!   node @name.def
+//!   attr (@name.def) def_attrs
+//! }
+//! ```
+//!
+//! The attributes of a shorthand are evaluated every time the shorthand is used.  Since a shorthand
+//! can be used in any stanza, its attributes can only refer to its variable and to global
+//! variables, and not to captures.  A shorthand can use other shorthands, but not itself.  It is an
+//! error for the attributes of a shorthand to set an attribute that is also set by the other
+//! attributes of the same `attr` statement, unless both of them append to it.
+//!
+//! ## Node types
+//!
+//! Graph nodes that are always created with the same attributes can be declared once, as a
+//! **_node type_**.  Node types are defined at the same level as stanzas, and give a default value
+//! for each of their attributes.  A `node` statement can then name the node type after a colon, to
+//! create a graph node that has those attributes:
+//!
+//! ``` tsg
+//! nodetype definition { kind = "definition", is_exported = #false }
+//!
+//! (function_definition name: (identifier) @name)
+//! {
+//!   node @name.def : definition
+//!   attr (@name.def) is_exported = #true
+//! }
+//! ```
+//!
+//! Default values are replaced by later `attr` statements without causing a conflict, so the graph
+//! node above ends up with `is_exported` set to `#true`.  Setting an attribute explicitly twice, to
+//! different values, is still an error.  The default values must be literals, or lists or sets of
+//! literals.  Using a node type that is not defined in the file is an error.
+//!
+//! ## Attribute inheritance
+//!
+//! Graph nodes can inherit attributes from their ancestors, which is useful when the value of an
+//! attribute is not in scope in the stanza that creates a node.  An `inherit` directive, defined at
+//! the same level as stanzas, names the attribute, and the edge attribute and value that identify
+//! the edges to follow:
+//!
+//! ``` tsg
+//! inherit module_path along edge kind = "contains"
+//! ```
+//!
+//! After execution, every graph node that does not have the `module_path` attribute gets the value
+//! of its nearest ancestor that does, where the ancestors of a node are found by following incoming
+//! edges whose `kind` attribute is `"contains"`.  Cycles are only visited once.  If a node has
+//! several nearest ancestors with different values, it inherits the value of the ancestor that was
+//! created first, and a warning is logged.
+//!
 //! # Regular expressions
 //!
 //! You can use a `scan` statement to match the content of a string value against a set of regular
@@ -421,10 +897,36 @@
 //! string, or none of the regular expressions match.
 //!
 //! Within each regular expression's block, you can use `$0`, `$1`, etc., to refer to any capture
-//! groups in the regular expression.
+//! groups in the regular expression.  Named groups, such as `(?P<dir>[^/]+)`, can also be referred
+//! to by name, as in `$dir`.  A group that did not take part in the match is an empty string.  It
+//! is an error to refer to a group that the block's regular expression does not have, or to use
+//! `$` references outside of a `scan` block.
+//!
+//! The block can also use `$match_start` and `$match_end`, which are the offsets of the start and
+//! the end of the match in the scanned string.  Like the indices of the
+//! [`substring`][`crate::reference::functions#substring`] function, offsets count characters,
+//! not bytes.  These names take precedence over named groups with the same names.
 //!
 //! The value being scanned must be local, which means it cannot be derived from scoped variables.
 //!
+//! Besides strings, you can scan a syntax node, which matches against the node's source text
+//! without first copying it into a string with [`source-text`][`crate::reference::functions#source-text`].
+//! Scanning a list scans each of its elements in turn, each of which must be a string or a syntax
+//! node; this is useful for quantified captures:
+//!
+//! ``` tsg
+//! (string) @str
+//! {
+//!   scan @str {
+//!     "\\\\u([0-9a-fA-F]{4})"
+//!     {
+//!       node escape
+//!       attr (escape) code_point = $1
+//!     }
+//!   }
+//! }
+//! ```
+//!
 //! For example, if `filepath` is a global variable containing the path of a Python source file,
 //! you could use the following `scan` statement to construct graph nodes for the name of the
 //! module defined in the file:
@@ -499,6 +1001,14 @@
 //! }
 //! ```
 //!
+//! A bare expression can be any local expression that evaluates to a boolean, including function
+//! calls.  For instance, `if (is-null @type) { ... }` has the same effect as `if none @type { ... }`.
+//!
+//! Only the body of the first arm whose conditions are all true is executed.  The conditions of
+//! later arms, and the bodies of the other arms, are not evaluated, so they cannot cause errors.
+//! Variables defined in a body are local to it, but a body can `set` mutable variables defined
+//! before the `if` statement, and the new values are visible after it.
+//!
 //! # List iteration
 //!
 //! You can use a `for` statement to execute blocks of statements for every element in list
@@ -513,6 +1023,60 @@
 //! }
 //! ```
 //!
+//! The list value can be any expression that evaluates to a list, such as a quantified capture, a
+//! list literal, or a function call.  Iterating over any other value is an error, and iterating
+//! over an empty list executes the block zero times.  The loop variable is an immutable local
+//! variable of the block.  Mutable variables defined outside of the loop can be read and `set`
+//! in the block, which lets you thread a value through the iterations:
+//!
+//! ```tsg
+//! (module)
+//! {
+//!   var current_node = (node)
+//!   for part in ["a", "b", "c"] {
+//!     let new_node = (node)
+//!     attr (new_node) name = part
+//!     edge current_node -> new_node
+//!     set current_node = new_node
+//!   }
+//! }
+//! ```
+//!
+//! A `for` statement can also name a variable for the index of each element, before the loop
+//! variable.  The index is an integer that starts at 0.  Together with the
+//! [`last?`][`crate::reference::functions#last-1`] function, this lets you treat the last element
+//! of a list differently.  For instance, the example in the section on
+//! [regular expressions](#regular-expressions), which constructs graph nodes for the name of the
+//! module defined in a Python source file, can be written without any regular expressions:
+//!
+//! ```tsg
+//! global filepath
+//!
+//! (module) @mod
+//! {
+//!   var current_node = (node)
+//!   let parts = (split filepath "/")
+//!
+//!   for i, part in parts {
+//!     if (not (last? parts i)) {
+//!       ; A directory component of the file path
+//!       let new_node = (node)
+//!       attr (new_node) name = part
+//!       edge current_node -> new_node
+//!       set current_node = new_node
+//!     } elif (eq part "__init__.py") {
+//!       ; The module's name comes from the last directory component.
+//!       let @mod.root = current_node
+//!     } elif (ends-with part ".py") {
+//!       let new_node = (node)
+//!       attr (new_node) name = (substring part 0 (index-of part ".py"))
+//!       edge current_node -> new_node
+//!       let @mod.root = new_node
+//!     }
+//!   }
+//! }
+//! ```
+//!
 //! # Debugging
 //!
 //! To support members of the Ancient and Harmonious Order of Printf Debuggers, you can use `print`
@@ -526,5 +1090,122 @@
 //!    print "Hi! x = ", x
 //! }
 //! ```
+//!
+//! To find out why an attribute ended up with a particular value, you can ask the `tree-sitter-graph`
+//! program to explain it, by passing the index of the graph node and the name of the attribute:
+//!
+//! ``` text
+//! $ tree-sitter-graph --explain-attr 3 qualified_name rules.tsg source.py
+//! ```
+//!
+//! This prints the chain of steps that produced the value: the `attr` statement that set it, the
+//! variables it was read from and where they were assigned, and the function calls, captures, and
+//! literals it was computed from.  Library users can enable this with
+//! `ExecutionConfig::provenance` and call `Graph::explain_attribute`.  Recording provenance only
+//! works with strict execution.
+//!
+//! ## Match reports
+//!
+//! Editors and other tools can ask which parts of a source file each stanza matched, and what the
+//! matches produced, with the `--match-report` option:
+//!
+//! ``` text
+//! $ tree-sitter-graph --match-report rules.tsg source.py
+//! ```
+//!
+//! This prints a JSON object instead of the graph.  For the following file, executed against a
+//! Python file that contains `def f():` and, on the next line, `    g()`:
+//!
+//! ``` tsg
+//! (function_definition name: (identifier) @name) {
+//!   node @name.def
+//!   attr (@name.def) kind = "definition", name = (source-text @name)
+//! }
+//!
+//! (call function: (identifier) @callee) {
+//!   node ref
+//!   attr (ref) kind = "reference", name = (source-text @callee)
+//! }
+//!
+//! (class_definition) {
+//! }
+//! ```
+//!
+//! the report is the following, where some positions have been put on one line to save space:
+//!
+//! ``` json
+//! {
+//!   "schema_version": 1,
+//!   "stanzas": [
+//!     {
+//!       "index": 0,
+//!       "name": null,
+//!       "range": { "start": { "row": 0, "column": 0 }, "end": { "row": 3, "column": 1 } },
+//!       "matches": [
+//!         {
+//!           "range": {
+//!             "start": { "byte": 0, "row": 0, "column": 0 },
+//!             "end": { "byte": 16, "row": 1, "column": 7 }
+//!           },
+//!           "captures": [
+//!             {
+//!               "name": "name",
+//!               "kind": "identifier",
+//!               "range": {
+//!                 "start": { "byte": 4, "row": 0, "column": 4 },
+//!                 "end": { "byte": 5, "row": 0, "column": 5 }
+//!               }
+//!             }
+//!           ],
+//!           "nodes": [0]
+//!         }
+//!       ]
+//!     },
+//!     {
+//!       "index": 1,
+//!       "name": null,
+//!       "range": { "start": { "row": 5, "column": 0 }, "end": { "row": 8, "column": 1 } },
+//!       "matches": [
+//!         {
+//!           "range": {
+//!             "start": { "byte": 13, "row": 1, "column": 4 },
+//!             "end": { "byte": 16, "row": 1, "column": 7 }
+//!           },
+//!           "captures": [
+//!             {
+//!               "name": "callee",
+//!               "kind": "identifier",
+//!               "range": {
+//!                 "start": { "byte": 13, "row": 1, "column": 4 },
+//!                 "end": { "byte": 14, "row": 1, "column": 5 }
+//!               }
+//!             }
+//!           ],
+//!           "nodes": [1]
+//!         }
+//!       ]
+//!     },
+//!     {
+//!       "index": 2,
+//!       "name": null,
+//!       "range": { "start": { "row": 10, "column": 0 }, "end": { "row": 11, "column": 1 } },
+//!       "matches": []
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! The report lists every stanza of the file, in order, with its `index`, its `name` if it has a
+//! [stanza name](#stanza-names), and its `range` in the graph DSL file.  Each of the `matches` of a
+//! stanza has the `range` of the syntax node matched by the stanza's query, and its `captures`, in
+//! the order of the captures in the query, with one entry for every syntax node bound to a
+//! quantified capture.  The `nodes` of a match are the IDs of the graph nodes it created, as they
+//! appear in the graph output of the same file and source.  Rows and columns count from zero, and
+//! columns count bytes.  The schema is versioned by `schema_version`, which is increased when it
+//! changes.
+//!
+//! Library users can create the report with `File::match_report`, which leaves out the `nodes` of
+//! matches unless it is given a graph whose provenance was recorded.  The example above is checked
+//! by the tests in `tests/fixtures/match_report`.
 
 pub mod functions;
@@ -0,0 +1,100 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Defines the regular expression engines used by the `scan` statement.
+//!
+//! By default, `scan` arms are compiled with the [`regex`][] crate.  With the `lite-regex`
+//! feature, they are compiled with [`LiteRegex`][], a minimal backtracking engine that supports
+//! a documented subset of the same syntax.  Patterns that use unsupported constructs are
+//! rejected when the DSL file is parsed, with the location of the offending `scan` arm.
+//!
+//! The engine is only used for `scan` statements.  The `replace` function and the
+//! `#match-global?` predicate always use the [`regex`][] crate.
+//!
+//! [`regex`]: https://docs.rs/regex/
+
+use std::ops::Range;
+
+mod lite;
+
+pub use lite::LiteRegex;
+pub use lite::LiteRegexError;
+
+/// The type of the compiled regular expressions of `scan` arms
+#[cfg(not(feature = "lite-regex"))]
+pub type Regex = regex::Regex;
+
+/// The type of the compiled regular expressions of `scan` arms
+#[cfg(feature = "lite-regex")]
+pub type Regex = LiteRegex;
+
+/// The operations that `scan` statements need from a regular expression engine
+pub(crate) trait RegexEngine: Sized {
+    /// Compiles a pattern.
+    fn compile(pattern: &str) -> Result<Self, RegexError>;
+
+    /// Returns the byte range of the first match in the text.
+    fn find_range(&self, text: &str) -> Option<Range<usize>>;
+
+    /// Returns the byte ranges of the capture groups of the first match in the text, starting
+    /// with the whole match.
+    fn capture_ranges(&self, text: &str) -> Option<Vec<Option<Range<usize>>>>;
+
+    /// Returns the names of the capture groups, starting with the whole match, which has no name.
+    fn group_names(&self) -> Vec<Option<String>>;
+}
+
+/// An error that can occur when compiling a pattern
+pub(crate) enum RegexError {
+    /// The pattern is not a valid regular expression
+    Invalid,
+    /// The pattern uses a construct that the engine does not support
+    Unsupported(String),
+}
+
+impl RegexEngine for regex::Regex {
+    fn compile(pattern: &str) -> Result<Self, RegexError> {
+        regex::Regex::new(pattern).map_err(|_| RegexError::Invalid)
+    }
+
+    fn find_range(&self, text: &str) -> Option<Range<usize>> {
+        self.find(text).map(|m| m.range())
+    }
+
+    fn capture_ranges(&self, text: &str) -> Option<Vec<Option<Range<usize>>>> {
+        let captures = self.captures(text)?;
+        Some(captures.iter().map(|m| m.map(|m| m.range())).collect())
+    }
+
+    fn group_names(&self) -> Vec<Option<String>> {
+        self.capture_names()
+            .map(|name| name.map(String::from))
+            .collect()
+    }
+}
+
+impl RegexEngine for LiteRegex {
+    fn compile(pattern: &str) -> Result<Self, RegexError> {
+        LiteRegex::new(pattern).map_err(|err| match err {
+            LiteRegexError::Invalid(..) => RegexError::Invalid,
+            LiteRegexError::Unsupported(..) => RegexError::Unsupported(err.to_string()),
+        })
+    }
+
+    fn find_range(&self, text: &str) -> Option<Range<usize>> {
+        self.find(text)
+    }
+
+    fn capture_ranges(&self, text: &str) -> Option<Vec<Option<Range<usize>>>> {
+        self.captures(text)
+    }
+
+    fn group_names(&self) -> Vec<Option<String>> {
+        // named groups are not supported
+        vec![None; self.captures_len()]
+    }
+}
@@ -0,0 +1,782 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! A minimal regular expression engine, which supports a subset of the syntax of the [`regex`][]
+//! crate
+//!
+//! [`regex`]: https://docs.rs/regex/
+
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
+
+use thiserror::Error;
+
+/// The maximum count of a counted repetition
+const MAX_REPETITION: u32 = 1000;
+
+/// The maximum number of instructions of a compiled pattern
+const MAX_PROGRAM_SIZE: usize = 10_000;
+
+/// The maximum nesting depth of groups
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// A regular expression compiled by the minimal engine.  It supports the following subset of the
+/// syntax of the [`regex`][] crate, with the same meaning:
+///
+/// - literal characters, and `\` followed by a punctuation character to match it literally
+/// - the escapes `\n`, `\r`, `\t`, `\f`, and `\v`
+/// - `.`, which matches any character except a newline
+/// - character classes such as `[a-z_]` and `[^"]`, which can contain ranges and escapes
+/// - the classes `\d`, `\w`, and `\s`, and their negations `\D`, `\W`, and `\S`
+/// - `^` and `$`, which match at the start and end of the text
+/// - capturing groups `(...)` and non-capturing groups `(?:...)`
+/// - alternation `a|b`
+/// - the repetitions `*`, `+`, `?`, `{n}`, `{n,}`, and `{n,m}`, and their lazy variants
+///   followed by `?`
+///
+/// Unlike in the [`regex`][] crate, `\d`, `\w`, and `\s` only match ASCII characters.  Flags,
+/// named groups, word boundaries, Unicode classes such as `\p{L}`, nested classes and class set
+/// operations, and character codes such as `\x41` are not supported, nor are counted repetitions
+/// above 1000.
+///
+/// Matches are found by backtracking, which remembers the states that failed, so that a search
+/// takes time and memory proportional to the length of the pattern times the length of the text
+/// that it looks at.  A search looks at the text up to the end of the match, or further when
+/// parts of the pattern fail later in the text, and at most at the whole text.
+///
+/// [`regex`]: https://docs.rs/regex/
+#[derive(Clone)]
+pub struct LiteRegex {
+    pattern: String,
+    program: Vec<Inst>,
+    captures_len: usize,
+    anchored: bool,
+}
+
+/// An error that can occur when compiling a [`LiteRegex`][]
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum LiteRegexError {
+    /// The pattern is not a valid regular expression
+    #[error("{0} at offset {1}")]
+    Invalid(&'static str, usize),
+    /// The pattern uses a construct that the minimal engine does not support
+    #[error("{0} at offset {1} is not supported")]
+    Unsupported(&'static str, usize),
+}
+
+impl LiteRegex {
+    /// Compiles a regular expression.
+    pub fn new(pattern: &str) -> Result<Self, LiteRegexError> {
+        let mut parser = PatternParser {
+            chars: pattern.char_indices().peekable(),
+            len: pattern.len(),
+            captures_len: 1,
+            depth: 0,
+        };
+        let node = parser.parse_alternation()?;
+        if let Some((offset, _)) = parser.chars.next() {
+            return Err(LiteRegexError::Invalid("unopened group", offset));
+        }
+        let anchored = node.is_anchored();
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        compiler.push(Inst::Save(0))?;
+        compiler.compile(&node)?;
+        compiler.push(Inst::Save(1))?;
+        compiler.push(Inst::Match)?;
+        Ok(LiteRegex {
+            pattern: pattern.to_string(),
+            program: compiler.program,
+            captures_len: parser.captures_len,
+            anchored,
+        })
+    }
+
+    /// Returns the pattern of this regular expression.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns the number of capture groups, including the implicit group of the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.captures_len
+    }
+
+    /// Returns whether the regular expression matches somewhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Returns the byte range of the leftmost-first match in the text.
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        let slots = self.search(text)?;
+        Some(slots[0].unwrap()..slots[1].unwrap())
+    }
+
+    /// Returns the byte ranges of the capture groups of the leftmost-first match in the text,
+    /// starting with the whole match.  Groups that did not participate in the match are `None`.
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<Range<usize>>>> {
+        let slots = self.search(text)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|slot| match slot {
+                    [Some(start), Some(end)] => Some(*start..*end),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the capture slots of the leftmost-first match in the text.
+    fn search(&self, text: &str) -> Option<Vec<Option<usize>>> {
+        let mut slots = vec![None; self.captures_len * 2];
+        let mut visited = Visited::new(self.program.len());
+        let mut stack = Vec::new();
+        for (start, _) in text
+            .char_indices()
+            .chain(std::iter::once((text.len(), ' ')))
+        {
+            if self.backtrack(text, start, &mut slots, &mut visited, &mut stack) {
+                return Some(slots);
+            }
+            if self.anchored {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Looks for a match that starts at the given position.  States that are visited again
+    /// failed before, because the search stops at the first match, so they are not explored
+    /// again.  Captures do not influence matching, so this holds across start positions as well.
+    fn backtrack(
+        &self,
+        text: &str,
+        start: usize,
+        slots: &mut [Option<usize>],
+        visited: &mut Visited,
+        stack: &mut Vec<Job>,
+    ) -> bool {
+        stack.clear();
+        stack.push(Job::Explore(0, start));
+        while let Some(job) = stack.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Explore(pc, pos) => (pc, pos),
+                Job::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            loop {
+                if !visited.insert(pc, pos) {
+                    break;
+                }
+                match &self.program[pc] {
+                    Inst::Match => return true,
+                    Inst::Char(ch) => match text[pos..].chars().next() {
+                        Some(next) if next == *ch => {
+                            pc += 1;
+                            pos += next.len_utf8();
+                        }
+                        _ => break,
+                    },
+                    Inst::Any => match text[pos..].chars().next() {
+                        Some(next) if next != '\n' => {
+                            pc += 1;
+                            pos += next.len_utf8();
+                        }
+                        _ => break,
+                    },
+                    Inst::Class(class) => match text[pos..].chars().next() {
+                        Some(next) if class.matches(next) => {
+                            pc += 1;
+                            pos += next.len_utf8();
+                        }
+                        _ => break,
+                    },
+                    Inst::Start => {
+                        if pos != 0 {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::End => {
+                        if pos != text.len() {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::Split(first, second) => {
+                        stack.push(Job::Explore(*second, pos));
+                        pc = *first;
+                    }
+                    Inst::Jump(target) => pc = *target,
+                    Inst::Save(slot) => {
+                        stack.push(Job::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(pos);
+                        pc += 1;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+impl fmt::Display for LiteRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl fmt::Debug for LiteRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("LiteRegex").field(&self.pattern).finish()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Parsing patterns
+
+/// The syntax tree of a pattern
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Group(Option<usize>, Box<Node>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+impl Node {
+    /// Returns whether every match of the node starts at the start of the text.
+    fn is_anchored(&self) -> bool {
+        match self {
+            Node::Start => true,
+            Node::Group(_, node) => node.is_anchored(),
+            Node::Concat(nodes) => matches!(nodes.first(), Some(node) if node.is_anchored()),
+            Node::Alternate(nodes) => nodes.iter().all(Node::is_anchored),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, ch: char) -> bool {
+        match self {
+            Perl::Digit => ch.is_ascii_digit(),
+            Perl::Word => ch.is_ascii_alphanumeric() || ch == '_',
+            Perl::Space => matches!(ch, ' ' | '\t' | '\n' | '\r' | '\x0B' | '\x0C'),
+        }
+    }
+}
+
+impl Class {
+    fn perl(perl: Perl, negated: bool) -> Self {
+        Class {
+            negated: false,
+            items: vec![ClassItem::Perl(perl, negated)],
+        }
+    }
+
+    fn matches(&self, ch: char) -> bool {
+        let matches = self.items.iter().any(|item| match item {
+            ClassItem::Range(from, to) => *from <= ch && ch <= *to,
+            ClassItem::Perl(perl, negated) => perl.matches(ch) != *negated,
+        });
+        matches != self.negated
+    }
+}
+
+/// The result of parsing an escape sequence
+enum Escape {
+    Char(char),
+    Perl(Perl, bool),
+}
+
+struct PatternParser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+    captures_len: usize,
+    depth: usize,
+}
+
+impl PatternParser<'_> {
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.len, |(offset, _)| *offset)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, ch)| *ch)
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, LiteRegexError> {
+        let mut alternatives = vec![self.parse_concat()?];
+        while self.eat('|') {
+            alternatives.push(self.parse_concat()?);
+        }
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Node::Alternate(alternatives))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, LiteRegexError> {
+        let mut nodes = Vec::new();
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            let mut node = self.parse_atom()?;
+            while let Some((min, max)) = self.parse_quantifier()? {
+                let greedy = !self.eat('?');
+                node = Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                    greedy,
+                };
+            }
+            nodes.push(node);
+        }
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_quantifier(&mut self) -> Result<Option<(u32, Option<u32>)>, LiteRegexError> {
+        let quantifier = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let offset = self.offset();
+                self.chars.next();
+                let min = self.parse_count(offset)?;
+                let max = if self.eat(',') {
+                    if self.peek() == Some('}') {
+                        None
+                    } else {
+                        Some(self.parse_count(offset)?)
+                    }
+                } else {
+                    Some(min)
+                };
+                if !self.eat('}') {
+                    return Err(LiteRegexError::Invalid(
+                        "unclosed counted repetition",
+                        offset,
+                    ));
+                }
+                if matches!(max, Some(max) if max < min) {
+                    return Err(LiteRegexError::Invalid(
+                        "invalid counted repetition",
+                        offset,
+                    ));
+                }
+                return Ok(Some((min, max)));
+            }
+            _ => return Ok(None),
+        };
+        self.chars.next();
+        Ok(Some(quantifier))
+    }
+
+    fn parse_count(&mut self, offset: usize) -> Result<u32, LiteRegexError> {
+        let mut count: Option<u32> = None;
+        while let Some(digit) = self.peek().and_then(|ch| ch.to_digit(10)) {
+            self.chars.next();
+            count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        }
+        match count {
+            None => Err(LiteRegexError::Invalid(
+                "invalid counted repetition",
+                offset,
+            )),
+            Some(count) if count > MAX_REPETITION => Err(LiteRegexError::Unsupported(
+                "counted repetition above 1000",
+                offset,
+            )),
+            Some(count) => Ok(count),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, LiteRegexError> {
+        let (offset, ch) = self.chars.next().unwrap();
+        match ch {
+            '(' => self.parse_group(offset),
+            '[' => Ok(Node::Class(self.parse_class(offset)?)),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '\\' => match self.parse_escape(offset)? {
+                Escape::Char(ch) => Ok(Node::Char(ch)),
+                Escape::Perl(perl, negated) => Ok(Node::Class(Class::perl(perl, negated))),
+            },
+            '*' | '+' | '?' => Err(LiteRegexError::Invalid(
+                "repetition operator missing expression",
+                offset,
+            )),
+            '{' => Err(LiteRegexError::Invalid(
+                "repetition operator missing expression",
+                offset,
+            )),
+            ch => Ok(Node::Char(ch)),
+        }
+    }
+
+    fn parse_group(&mut self, offset: usize) -> Result<Node, LiteRegexError> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(LiteRegexError::Unsupported(
+                "nesting groups more than 64 levels deep",
+                offset,
+            ));
+        }
+        let index = if self.eat('?') {
+            if !self.eat(':') {
+                return Err(LiteRegexError::Unsupported(
+                    "a group with flags or a name",
+                    offset,
+                ));
+            }
+            None
+        } else {
+            self.captures_len += 1;
+            Some(self.captures_len - 1)
+        };
+        self.depth += 1;
+        let node = self.parse_alternation()?;
+        self.depth -= 1;
+        if !self.eat(')') {
+            return Err(LiteRegexError::Invalid("unclosed group", offset));
+        }
+        Ok(Node::Group(index, Box::new(node)))
+    }
+
+    fn parse_escape(&mut self, offset: usize) -> Result<Escape, LiteRegexError> {
+        let ch = match self.chars.next() {
+            Some((_, ch)) => ch,
+            None => {
+                return Err(LiteRegexError::Invalid(
+                    "incomplete escape sequence",
+                    offset,
+                ))
+            }
+        };
+        Ok(match ch {
+            'n' => Escape::Char('\n'),
+            'r' => Escape::Char('\r'),
+            't' => Escape::Char('\t'),
+            'f' => Escape::Char('\x0C'),
+            'v' => Escape::Char('\x0B'),
+            'd' => Escape::Perl(Perl::Digit, false),
+            'D' => Escape::Perl(Perl::Digit, true),
+            'w' => Escape::Perl(Perl::Word, false),
+            'W' => Escape::Perl(Perl::Word, true),
+            's' => Escape::Perl(Perl::Space, false),
+            'S' => Escape::Perl(Perl::Space, true),
+            'b' | 'B' | 'A' | 'z' | '<' | '>' => {
+                return Err(LiteRegexError::Unsupported("a boundary assertion", offset))
+            }
+            'p' | 'P' => return Err(LiteRegexError::Unsupported("a Unicode class", offset)),
+            'x' | 'u' | 'U' | '0'..='7' => {
+                return Err(LiteRegexError::Unsupported("a character code", offset))
+            }
+            ch if ch.is_ascii_punctuation() => Escape::Char(ch),
+            _ => {
+                return Err(LiteRegexError::Invalid(
+                    "unrecognized escape sequence",
+                    offset,
+                ))
+            }
+        })
+    }
+
+    fn parse_class(&mut self, offset: usize) -> Result<Class, LiteRegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let (item_offset, ch) = match self.chars.next() {
+                Some(next) => next,
+                None => return Err(LiteRegexError::Invalid("unclosed character class", offset)),
+            };
+            let from = match ch {
+                ']' if !first => break,
+                '[' => {
+                    return Err(LiteRegexError::Unsupported(
+                        "a nested character class",
+                        item_offset,
+                    ))
+                }
+                '&' | '-' | '~' if self.peek() == Some(ch) => {
+                    return Err(LiteRegexError::Unsupported(
+                        "a class set operation",
+                        item_offset,
+                    ))
+                }
+                '\\' => match self.parse_escape(item_offset)? {
+                    Escape::Char(ch) => ch,
+                    Escape::Perl(perl, negated) => {
+                        items.push(ClassItem::Perl(perl, negated));
+                        first = false;
+                        continue;
+                    }
+                },
+                ch => ch,
+            };
+            first = false;
+            let mut lookahead = self.chars.clone();
+            let is_range = matches!(lookahead.next(), Some((_, '-')))
+                && !matches!(lookahead.peek(), Some((_, ']')) | None);
+            if !is_range {
+                items.push(ClassItem::Range(from, from));
+                continue;
+            }
+            self.chars.next();
+            let (to_offset, to) = self.chars.next().unwrap();
+            let to = match to {
+                '\\' => match self.parse_escape(to_offset)? {
+                    Escape::Char(ch) => ch,
+                    Escape::Perl(..) => {
+                        return Err(LiteRegexError::Invalid("invalid range", to_offset))
+                    }
+                },
+                '[' => {
+                    return Err(LiteRegexError::Unsupported(
+                        "a nested character class",
+                        to_offset,
+                    ))
+                }
+                ch => ch,
+            };
+            if to < from {
+                return Err(LiteRegexError::Invalid("invalid range", item_offset));
+            }
+            items.push(ClassItem::Range(from, to));
+        }
+        Ok(Class { negated, items })
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Compiling patterns
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Match,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    /// Continue at the first target, and backtrack to the second
+    Split(usize, usize),
+    Jump(usize),
+    Save(usize),
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, LiteRegexError> {
+        if self.program.len() == MAX_PROGRAM_SIZE {
+            return Err(LiteRegexError::Unsupported("a pattern this large", 0));
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), LiteRegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Char(ch) => {
+                self.push(Inst::Char(*ch))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()))?;
+            }
+            Node::Start => {
+                self.push(Inst::Start)?;
+            }
+            Node::End => {
+                self.push(Inst::End)?;
+            }
+            Node::Group(index, node) => {
+                if let Some(index) = index {
+                    self.push(Inst::Save(index * 2))?;
+                }
+                self.compile(node)?;
+                if let Some(index) = index {
+                    self.push(Inst::Save(index * 2 + 1))?;
+                }
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(nodes) => {
+                let mut jumps = Vec::new();
+                for (index, node) in nodes.iter().enumerate() {
+                    if index + 1 == nodes.len() {
+                        self.compile(node)?;
+                        break;
+                    }
+                    let split = self.push(Inst::Split(0, 0))?;
+                    self.compile(node)?;
+                    jumps.push(self.push(Inst::Jump(0))?);
+                    let next = self.program.len();
+                    self.program[split] = Inst::Split(split + 1, next);
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                match max {
+                    // An unbounded repetition is compiled as `(?:x+)?`, so that an empty
+                    // iteration of `x` can still complete the match, as it does in the `regex`
+                    // crate.
+                    None if *min == 0 => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        let end = self.program.len() + 1;
+                        self.push(self.split(split + 1, end, *greedy))?;
+                        self.program[split] = self.split(split + 1, end, *greedy);
+                    }
+                    None => {
+                        for _ in 1..*min {
+                            self.compile(node)?;
+                        }
+                        let start = self.program.len();
+                        self.compile(node)?;
+                        let end = self.program.len() + 1;
+                        self.push(self.split(start, end, *greedy))?;
+                    }
+                    Some(max) => {
+                        for _ in 0..*min {
+                            self.compile(node)?;
+                        }
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = self.split(split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a split that prefers the first target if greedy, and the second target otherwise.
+    fn split(&self, first: usize, second: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(first, second)
+        } else {
+            Inst::Split(second, first)
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Matching
+
+enum Job {
+    Explore(usize, usize),
+    Restore(usize, Option<usize>),
+}
+
+/// The set of visited states, each a pair of an instruction and a position in the text.  The
+/// states are ordered by position, and the set only grows as far into the text as the search
+/// goes, so that a search that looks at a small part of a long text does not need to allocate a
+/// set for the whole text.
+struct Visited {
+    bits: Vec<u64>,
+    instructions: usize,
+}
+
+impl Visited {
+    fn new(instructions: usize) -> Self {
+        Visited {
+            bits: Vec::new(),
+            instructions,
+        }
+    }
+
+    /// Adds a state to the set, returning whether it was not in the set yet.
+    fn insert(&mut self, pc: usize, pos: usize) -> bool {
+        let index = pos * self.instructions + pc;
+        let (word, bit) = (index / 64, 1 << (index % 64));
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        let inserted = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        inserted
+    }
+}
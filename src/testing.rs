@@ -0,0 +1,193 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Assertions for comparing graphs in test suites
+//!
+//! [`assert_graph`][] compares the [pretty-printed][crate::graph::Graph::pretty_print] form of a
+//! graph against an expectation, which is either a string, or the contents of a file:
+//!
+//! ``` ignore
+//! use tree_sitter_graph::assert_graph_eq;
+//! use tree_sitter_graph::expect_file;
+//!
+//! assert_graph_eq!(graph, indoc! {r#"
+//!     node 0
+//!       kind: "definition"
+//! "#});
+//! assert_graph_eq!(graph, expect_file!("tests/expected/definitions.graph"));
+//! ```
+//!
+//! Trailing whitespace is ignored.  If the graph does not match, the assertion panics with a line
+//! diff between the expected and actual graphs.  Expectations that are read from a file are
+//! rewritten with the actual graph instead when the [`UPDATE_EXPECTATIONS`][] environment variable
+//! is set to `1`.
+//!
+//! [`run_corpus`][] runs a directory of corpus cases, each of which is a Markdown file with a graph
+//! DSL file, a source file to execute it against, and the expected graph or error.  Rule
+//! repositories can use it to test their own rules in the same format as this crate does.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+#[cfg(feature = "term-colors")]
+use colored::Colorize;
+
+use crate::graph::Graph;
+
+mod corpus;
+
+pub use corpus::run_corpus;
+pub use corpus::CORPUS_CASE_EXTENSION;
+
+/// The environment variable that, when set to `1`, rewrites file expectations that do not match
+pub const UPDATE_EXPECTATIONS: &str = "TSG_UPDATE_EXPECTATIONS";
+
+/// The expected contents of a pretty-printed graph
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expectation {
+    /// The expected graph, given as a string
+    Inline(String),
+    /// A file containing the expected graph.  A missing file is treated as empty.
+    File(PathBuf),
+}
+
+impl From<&str> for Expectation {
+    fn from(expected: &str) -> Expectation {
+        Expectation::Inline(expected.to_string())
+    }
+}
+
+impl From<String> for Expectation {
+    fn from(expected: String) -> Expectation {
+        Expectation::Inline(expected)
+    }
+}
+
+/// Asserts that a graph matches an expectation, ignoring trailing whitespace.  Panics with a diff
+/// of the expected and actual graphs if it does not.  File expectations are rewritten instead if
+/// the [`UPDATE_EXPECTATIONS`][] environment variable is set to `1`.
+pub fn assert_graph<E: Into<Expectation>>(graph: &Graph, expected: E) {
+    let actual = graph.pretty_print().to_string();
+    match expected.into() {
+        Expectation::Inline(expected) => {
+            if let Some(diff) = diff_graphs(&expected, &actual) {
+                panic!("graph does not match expectation\n{}", diff);
+            }
+        }
+        Expectation::File(path) => {
+            let expected = std::fs::read_to_string(&path).unwrap_or_default();
+            let diff = match diff_graphs(&expected, &actual) {
+                Some(diff) => diff,
+                None => return,
+            };
+            if update_expectations() {
+                std::fs::write(&path, actual).unwrap_or_else(|error| {
+                    panic!("cannot update expectation {}: {}", path.display(), error)
+                });
+                return;
+            }
+            panic!(
+                "graph does not match expectation {}\n{}\nRerun with {}=1 to update the expectation.",
+                path.display(),
+                diff,
+                UPDATE_EXPECTATIONS,
+            );
+        }
+    }
+}
+
+/// Asserts that a graph matches an expectation.  See [`assert_graph`][crate::testing::assert_graph].
+#[macro_export]
+macro_rules! assert_graph_eq {
+    ($graph:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_graph(&$graph, $expected)
+    };
+}
+
+/// Creates an expectation that is read from a file, whose path is relative to the manifest
+/// directory of the crate that uses the macro.
+#[macro_export]
+macro_rules! expect_file {
+    ($path:expr $(,)?) => {
+        $crate::testing::Expectation::File(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($path),
+        )
+    };
+}
+
+/// Returns whether the [`UPDATE_EXPECTATIONS`][] environment variable is set to `1`.
+fn update_expectations() -> bool {
+    std::env::var(UPDATE_EXPECTATIONS).is_ok_and(|value| value == "1")
+}
+
+/// Returns a line diff of the expected and actual graphs, if they differ in more than trailing
+/// whitespace.
+fn diff_graphs(expected: &str, actual: &str) -> Option<String> {
+    let expected = trimmed_lines(expected);
+    let actual = trimmed_lines(actual);
+    if expected == actual {
+        return None;
+    }
+
+    // Compute the longest common subsequence of lines, which tests graphs are small enough for.
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            writeln!(diff, "  {}", expected[i]).unwrap();
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || common[i][j + 1] >= common[i + 1][j])
+        {
+            writeln!(diff, "{}", added_style(&format!("+ {}", actual[j]))).unwrap();
+            j += 1;
+        } else {
+            writeln!(diff, "{}", removed_style(&format!("- {}", expected[i]))).unwrap();
+            i += 1;
+        }
+    }
+    Some(diff)
+}
+
+fn trimmed_lines(graph: &str) -> Vec<&str> {
+    let mut lines = graph.lines().map(str::trim_end).collect::<Vec<_>>();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines
+}
+
+// coloring functions
+
+#[cfg(feature = "term-colors")]
+fn added_style(str: &str) -> impl std::fmt::Display {
+    str.green()
+}
+#[cfg(not(feature = "term-colors"))]
+fn added_style<'a>(str: &'a str) -> impl std::fmt::Display + 'a {
+    str
+}
+
+#[cfg(feature = "term-colors")]
+fn removed_style(str: &str) -> impl std::fmt::Display {
+    str.red()
+}
+#[cfg(not(feature = "term-colors"))]
+fn removed_style<'a>(str: &'a str) -> impl std::fmt::Display + 'a {
+    str
+}
@@ -0,0 +1,289 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+//! Corpus tests, which execute graph DSL files described in Markdown files
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+
+use tree_sitter::Language;
+use tree_sitter::Parser;
+
+use crate::ast::File;
+use crate::functions::Functions;
+use crate::ExecutionConfig;
+use crate::NoCancellation;
+use crate::Variables;
+
+use super::diff_graphs;
+use super::update_expectations;
+use super::UPDATE_EXPECTATIONS;
+
+/// The file extension of corpus cases
+pub const CORPUS_CASE_EXTENSION: &str = "md";
+
+/// Runs the corpus cases in a directory and its subdirectories, panicking with a report of all
+/// failing cases if any of them fail.  Returns the number of cases that were run.
+///
+/// Every Markdown file (with an `.md` extension) in the directory is a case, which consists of
+/// three fenced sections, in order:
+///
+///  - a `tsg` section with the graph DSL file,
+///  - a section with the source to execute the file against, whose info string is the name of its
+///    language, which `languages` resolves to a tree-sitter language, and
+///  - a `graph` section with the expected pretty-printed graph, compared ignoring trailing
+///    whitespace, or an `error` section with a substring of the expected error message, which
+///    can be an error parsing the graph DSL file or executing it.
+///
+/// Text outside of the fenced sections describes the case, and is ignored.  For example:
+///
+/// ````markdown
+/// Every identifier gets a graph node.
+///
+/// ```tsg
+/// (identifier) @id {
+///   node @id.def
+///   attr (@id.def) name = (source-text @id)
+/// }
+/// ```
+///
+/// ```python
+/// x = 1
+/// ```
+///
+/// ```graph
+/// node 0
+///   name: "x"
+/// ```
+/// ````
+///
+/// Files are executed strictly, with the standard library functions and no global variables
+/// other than the defaults declared by the file.  When the [`UPDATE_EXPECTATIONS`][] environment
+/// variable is set to `1`, the expectations of failing cases are rewritten with the actual graph or
+/// error message instead.
+pub fn run_corpus<P, F>(dir: P, languages: F) -> usize
+where
+    P: AsRef<Path>,
+    F: Fn(&str) -> Option<Language>,
+{
+    let dir = dir.as_ref();
+    let mut paths = Vec::new();
+    find_cases(dir, &mut paths)
+        .unwrap_or_else(|error| panic!("cannot read corpus {}: {}", dir.display(), error));
+    paths.sort();
+
+    let update = update_expectations();
+    let mut failures = String::new();
+    let mut failure_count = 0;
+    for path in &paths {
+        if let Err(failure) = run_case(path, &languages, update) {
+            failure_count += 1;
+            writeln!(failures, "{}: {}", path.display(), failure).unwrap();
+        }
+    }
+    if failure_count > 0 {
+        panic!(
+            "{} of {} corpus cases failed\n\n{}\nRerun with {}=1 to update the expectations.",
+            failure_count,
+            paths.len(),
+            failures,
+            UPDATE_EXPECTATIONS,
+        );
+    }
+    paths.len()
+}
+
+fn find_cases(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_cases(&path, paths)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == CORPUS_CASE_EXTENSION)
+        {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs a corpus case, returning a description of the failure if it fails.
+fn run_case<F>(path: &Path, languages: &F, update: bool) -> Result<(), String>
+where
+    F: Fn(&str) -> Option<Language>,
+{
+    let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let case = Case::parse(&contents)?;
+    let language =
+        languages(&case.language).ok_or_else(|| format!("unknown language {}", case.language))?;
+    let actual = execute(&case.tsg, &case.source, language);
+
+    let failure = match (&case.expected, &actual) {
+        (Outcome::Graph(expected), Outcome::Graph(actual)) => match diff_graphs(expected, actual) {
+            Some(diff) => format!("graph does not match expectation\n{}", diff),
+            None => return Ok(()),
+        },
+        (Outcome::Error(expected), Outcome::Error(actual)) => {
+            if !expected.trim().is_empty() && actual.contains(expected.trim()) {
+                return Ok(());
+            }
+            format!(
+                "expected an error containing\n  {}\nbut got\n  {}\n",
+                expected.trim(),
+                actual
+            )
+        }
+        (Outcome::Graph(_), Outcome::Error(actual)) => {
+            format!("expected a graph, but got an error\n  {}\n", actual)
+        }
+        (Outcome::Error(expected), Outcome::Graph(actual)) => format!(
+            "expected an error containing\n  {}\nbut got a graph\n{}",
+            expected.trim(),
+            actual
+        ),
+    };
+    if update {
+        let updated = case.with_expected(&contents, &actual);
+        return std::fs::write(path, updated)
+            .map_err(|error| format!("cannot update expectation: {}", error));
+    }
+    Err(failure)
+}
+
+/// Executes a graph DSL file against a source, returning the pretty-printed graph or the error.
+fn execute(tsg: &str, source: &str, language: Language) -> Outcome {
+    let file = match File::from_str(language, tsg) {
+        Ok(file) => file,
+        Err(error) => return Outcome::Error(error.to_string()),
+    };
+    let mut parser = Parser::new();
+    if let Err(error) = parser.set_language(language) {
+        return Outcome::Error(error.to_string());
+    }
+    let tree = match parser.parse(source, None) {
+        Some(tree) => tree,
+        None => return Outcome::Error("cannot parse source".to_string()),
+    };
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    match file.execute(&tree, source, &config, &NoCancellation) {
+        Ok(graph) => Outcome::Graph(graph.pretty_print().to_string()),
+        Err(error) => Outcome::Error(error.to_string()),
+    }
+}
+
+/// The result of executing a corpus case
+enum Outcome {
+    Graph(String),
+    Error(String),
+}
+
+/// A corpus case, parsed from its Markdown file
+struct Case {
+    tsg: String,
+    language: String,
+    source: String,
+    expected: Outcome,
+    /// The byte range of the expectation section in the file, including its fences
+    expected_range: std::ops::Range<usize>,
+}
+
+impl Case {
+    fn parse(contents: &str) -> Result<Case, String> {
+        let sections = Section::find_all(contents)?;
+        if sections.len() != 3 {
+            return Err(format!(
+                "expected 3 fenced sections, found {}",
+                sections.len()
+            ));
+        }
+        if sections[0].info != "tsg" {
+            return Err(format!(
+                "expected the first section to be tsg, found {}",
+                sections[0].info
+            ));
+        }
+        let expected = match sections[2].info.as_str() {
+            "graph" => Outcome::Graph(sections[2].text.clone()),
+            "error" => Outcome::Error(sections[2].text.clone()),
+            info => {
+                return Err(format!(
+                    "expected the third section to be graph or error, found {}",
+                    info
+                ))
+            }
+        };
+        Ok(Case {
+            tsg: sections[0].text.clone(),
+            language: sections[1].info.clone(),
+            source: sections[1].text.clone(),
+            expected,
+            expected_range: sections[2].range.clone(),
+        })
+    }
+
+    /// Returns the contents of the case file, with the expectation replaced by an actual outcome.
+    fn with_expected(&self, contents: &str, actual: &Outcome) -> String {
+        let (info, text) = match actual {
+            Outcome::Graph(graph) => ("graph", graph.to_string()),
+            Outcome::Error(error) => ("error", format!("{}\n", error)),
+        };
+        format!(
+            "{}```{}\n{}```\n{}",
+            &contents[..self.expected_range.start],
+            info,
+            text,
+            &contents[self.expected_range.end..]
+        )
+    }
+}
+
+/// A fenced section of a Markdown file
+struct Section {
+    info: String,
+    text: String,
+    range: std::ops::Range<usize>,
+}
+
+impl Section {
+    fn find_all(contents: &str) -> Result<Vec<Section>, String> {
+        let mut sections = Vec::new();
+        let mut open: Option<(String, usize, String)> = None;
+        let mut offset = 0;
+        for line in contents.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let trimmed = line.trim_end();
+            match &mut open {
+                None => {
+                    if let Some(info) = trimmed.strip_prefix("```") {
+                        open = Some((info.trim().to_string(), start, String::new()));
+                    }
+                }
+                Some((info, section_start, text)) => {
+                    if trimmed == "```" {
+                        sections.push(Section {
+                            info: std::mem::take(info),
+                            text: std::mem::take(text),
+                            range: *section_start..offset,
+                        });
+                        open = None;
+                    } else {
+                        text.push_str(line);
+                    }
+                }
+            }
+        }
+        if open.is_some() {
+            return Err("unterminated fenced section".to_string());
+        }
+        Ok(sections)
+    }
+}
@@ -10,6 +10,8 @@ use std::collections::hash_map::Entry::Vacant;
 use std::collections::HashMap;
 use thiserror::Error;
 
+#[cfg(feature = "serde")]
+use crate::graph::Float;
 use crate::graph::Value;
 use crate::Identifier;
 
@@ -44,6 +46,7 @@ pub(crate) struct VariableMap<'a, V> {
     values: HashMap<Identifier, Variable<V>>,
 }
 
+#[derive(Clone)]
 struct Variable<V> {
     value: V,
     mutable: bool,
@@ -71,6 +74,23 @@ impl<'a, V> VariableMap<'a, V> {
     pub(crate) fn clear(&mut self) {
         self.values.clear();
     }
+
+    /// Returns an iterator over the variables defined in this environment, excluding those of its
+    /// context.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Identifier, &V)> {
+        self.values.iter().map(|(name, v)| (name, &v.value))
+    }
+}
+
+impl<'a, V: Clone> VariableMap<'a, V> {
+    /// Creates a copy of the variables defined in this environment.  The copy does not inherit
+    /// from this environment's context.
+    pub(crate) fn clone_detached(&self) -> Self {
+        Self {
+            context: None,
+            values: self.values.clone(),
+        }
+    }
 }
 
 impl<V> Variables<V> for VariableMap<'_, V> {
@@ -178,6 +198,29 @@ impl<'a> Globals<'a> {
     pub fn clear(&mut self) {
         self.values.clear();
     }
+
+    /// Creates a variable environment from a JSON object, with a variable for every entry of the
+    /// object.  The values are converted as [`Value::from_json`][] does.
+    ///
+    /// ```json
+    /// { "FILE_PATH": "src/main.py", "max_depth": 3, "exports": ["main", "run"] }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, JsonValueError> {
+        let json = serde_json::from_str::<serde_json::Value>(json)?;
+        let json = json
+            .as_object()
+            .ok_or_else(|| JsonValueError::Invalid("expected an object".to_string()))?;
+        let mut globals = Globals::new();
+        for (name, value) in json {
+            let value = json_value(value)
+                .map_err(|e| JsonValueError::Invalid(format!("global variable {}: {}", name, e)))?;
+            globals
+                .values
+                .insert(Identifier::from(name.as_str()), value);
+        }
+        Ok(globals)
+    }
 }
 
 pub struct Iter<'a>(std::collections::hash_map::Iter<'a, Identifier, Value>);
@@ -195,3 +238,51 @@ impl Variables<Value> for Globals<'_> {
         self.get(name)
     }
 }
+
+impl Value {
+    /// Converts a JSON value to a value.  JSON null, booleans, strings, and arrays become
+    /// [`Null`][Value::Null], [`Boolean`][Value::Boolean], [`String`][Value::String], and
+    /// [`List`][Value::List] values.  Numbers without a fraction or exponent become
+    /// [`Integer`][Value::Integer] values, and must fit in 64 bits, and other numbers become
+    /// [`Float`][Value::Float] values.  JSON objects cannot be converted.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Value, JsonValueError> {
+        let json = serde_json::from_str::<serde_json::Value>(json)?;
+        json_value(&json).map_err(JsonValueError::Invalid)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn json_value(json: &serde_json::Value) -> Result<Value, String> {
+    use serde_json::Value as Json;
+    Ok(match json {
+        Json::Null => Value::Null,
+        Json::Bool(value) => Value::Boolean(*value),
+        Json::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                Value::Integer(value)
+            } else if number.is_u64() {
+                return Err(format!("integer {} is out of range", number));
+            } else {
+                let value = number.as_f64().and_then(Float::new);
+                Value::Float(value.ok_or_else(|| format!("number {} is out of range", number))?)
+            }
+        }
+        Json::String(value) => Value::String(value.clone()),
+        Json::Array(values) => {
+            Value::List(values.iter().map(json_value).collect::<Result<_, _>>()?)
+        }
+        Json::Object(_) => return Err("objects cannot be converted to values".to_string()),
+    })
+}
+
+/// An error that can occur while converting JSON to [`Value`][]s or [global
+/// variables][Globals::from_json]
+#[cfg(feature = "serde")]
+#[derive(Debug, Error)]
+pub enum JsonValueError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid value: {0}")]
+    Invalid(String),
+}
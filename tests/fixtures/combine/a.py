@@ -0,0 +1,5 @@
+def alpha():
+    pass
+
+def beta():
+    pass
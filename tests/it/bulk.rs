@@ -0,0 +1,133 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use std::time::Instant;
+
+use indoc::indoc;
+use tree_sitter::Parser;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::bulk::execute_many;
+use tree_sitter_graph::bulk::BulkError;
+use tree_sitter_graph::bulk::SourceInput;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::CancelAfter;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::ExecutionError;
+use tree_sitter_graph::Identifier;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
+
+const DSL_SOURCE: &str = indoc! {r#"
+  global FILE_PATH
+
+  (function_definition name: (identifier) @name) @def
+  {
+    node @def.node
+    attr (@def.node) name = (source-text @name), path = FILE_PATH
+  }
+
+  (call function: (identifier) @name) @call
+  {
+    node @call.node
+    attr (@call.node) callee = (source-text @name)
+  }
+"#};
+
+fn inputs() -> Vec<SourceInput> {
+    (0..8)
+        .map(|i| {
+            let source = (0..=i)
+                .map(|j| format!("def f{}():\n  g{}()\n", j, i))
+                .collect::<String>();
+            SourceInput::new(format!("file{}.py", i), source)
+        })
+        .collect()
+}
+
+fn add_file_path(input: &SourceInput, globals: &mut Variables) {
+    globals
+        .add(
+            Identifier::from("FILE_PATH"),
+            input.path.to_str().unwrap().into(),
+        )
+        .unwrap();
+}
+
+#[test]
+fn can_execute_many_files_in_parallel() {
+    let file =
+        File::from_str(tree_sitter_python::language(), DSL_SOURCE).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    for lazy in [false, true] {
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_python::language()).unwrap();
+        let expected = inputs()
+            .iter()
+            .map(|input| {
+                let tree = parser.parse(&input.source, None).unwrap();
+                let mut globals = Variables::new();
+                add_file_path(input, &mut globals);
+                let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+                let graph = file
+                    .execute(&tree, &input.source, &config, &NoCancellation)
+                    .expect("Cannot execute file");
+                let printed = graph.pretty_print().to_string();
+                (input.path.clone(), printed)
+            })
+            .collect::<Vec<_>>();
+
+        let results = execute_many(
+            &file,
+            tree_sitter_python::language(),
+            inputs(),
+            &functions,
+            |input, functions, globals| {
+                add_file_path(input, globals);
+                ExecutionConfig::new(functions, globals).lazy(lazy)
+            },
+            &NoCancellation,
+            |input, graph| (input.path.clone(), graph.pretty_print().to_string()),
+        )
+        .expect("Cannot use language");
+        let actual = results
+            .into_iter()
+            .map(|result| result.expect("Cannot execute file"))
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected, "lazy: {}", lazy);
+    }
+}
+
+#[test]
+fn cancelled_bulk_execution_does_not_start_files() {
+    let file =
+        File::from_str(tree_sitter_python::language(), DSL_SOURCE).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let results = execute_many(
+        &file,
+        tree_sitter_python::language(),
+        inputs(),
+        &functions,
+        |input, functions, globals| {
+            add_file_path(input, globals);
+            ExecutionConfig::new(functions, globals)
+        },
+        &CancelAfter::at(Instant::now()),
+        |_, graph| graph.node_count(),
+    )
+    .expect("Cannot use language");
+    assert_eq!(results.len(), 8);
+    for result in results {
+        assert!(
+            matches!(
+                result,
+                Err(BulkError::Execution(ExecutionError::Cancelled(_)))
+            ),
+            "unexpected result: {:?}",
+            result
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use tree_sitter::Parser;
+use tree_sitter::Tree;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::Identifier;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
+
+const RUNS: usize = 50;
+
+const PYTHON_SOURCE: &str = r#"
+import a, b
+from c import d
+
+def f(x, y):
+    z = x + y
+    return g(z, d)
+
+class C:
+    def m(self, w):
+        return f(w, a) + b
+"#;
+
+const TSG_SOURCE: &str = r#"
+global filename
+
+(module) @mod {
+  node @mod.root
+  attr (@mod.root) kind = "module", file = filename, line = (start-row @mod)
+}
+
+(identifier) @id {
+  let @id.def = (node)
+  let @id.ref = (node)
+  let @id.key = (node-for (source-text @id))
+  attr (@id.def) kind = "definition", name = (source-text @id), line = (start-row @id), column = (start-column @id)
+  attr (@id.ref) kind = "reference", name = (source-text @id), line = (start-row @id), column = (start-column @id)
+  edge @id.ref -> @id.def
+  edge @id.def -> @id.key
+}
+
+(block) @block {
+  node @block.scope
+  attr (@block.scope) kind = "block", statements = (named-child-count @block)
+}
+
+(function_definition name: (identifier) @name body: (_) @body) @fn {
+  node @fn.scope
+  attr (@fn.scope) kind = "scope", name = (source-text @name)
+  edge @name.def -> @fn.scope
+  edge @fn.scope -> @body.scope
+}
+
+(call function: (identifier) @callee) @call {
+  node @call.node
+  attr (@call.node) kind = "call", callee = (source-text @callee), args = [(source-text @callee), (start-row @call)]
+  edge @call.node -> @callee.ref
+}
+"#;
+
+fn parse() -> Tree {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    parser.parse(PYTHON_SOURCE, None).unwrap()
+}
+
+/// Executes the file and returns the graph pretty-printed, and serialized as JSON.  Every run
+/// creates fresh hash maps, each with its own random hash keys, so iteration order over them
+/// differs between runs.
+fn execute(file: &File, tree: &Tree, lazy: bool) -> Vec<String> {
+    let functions = Functions::stdlib();
+    let mut globals = Variables::new();
+    globals
+        .add(Identifier::from("filename"), "test.py".into())
+        .unwrap();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+    let graph = file
+        .execute(tree, PYTHON_SOURCE, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    #[allow(unused_mut)]
+    let mut outputs = vec![graph.pretty_print().to_string()];
+    #[cfg(feature = "serde")]
+    outputs.push(serde_json::to_string(&graph).unwrap());
+    outputs
+}
+
+fn check_determinism(lazy: bool) {
+    let tree = parse();
+    let file = File::from_str(tree_sitter_python::language(), TSG_SOURCE).unwrap();
+    let expected = execute(&file, &tree, lazy);
+    let mut perturbations = Vec::new();
+    for run in 0..RUNS {
+        // Allocate hash maps of varying capacities between runs, so that the maps created during
+        // execution land in different places and get different hash keys.
+        perturbations.push(HashMap::<usize, usize>::with_capacity(run * 7));
+        assert_eq!(
+            execute(&file, &tree, lazy),
+            expected,
+            "run {} differs from the first run",
+            run
+        );
+    }
+}
+
+#[test]
+fn strict_execution_is_deterministic() {
+    check_determinism(false);
+}
+
+#[test]
+fn lazy_execution_is_deterministic() {
+    check_determinism(true);
+}
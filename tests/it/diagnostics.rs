@@ -0,0 +1,147 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use std::path::Path;
+
+use indoc::indoc;
+use tree_sitter::Parser;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::diagnostics::Diagnostic;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
+
+fn render_parse_error(dsl_source: &str) -> String {
+    let error = match File::from_str(tree_sitter_python::language(), dsl_source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(error) => error,
+    };
+    Diagnostic::from_parse_error(&error, dsl_source)
+        .tsg_path(Path::new("test.tsg"))
+        .to_string()
+}
+
+fn render_execution_error(python_source: &str, dsl_source: &str, lazy: bool) -> String {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+    let error = match file.execute(&tree, python_source, &config, &NoCancellation) {
+        Ok(_) => panic!("Execution succeeded unexpectedly"),
+        Err(error) => error,
+    };
+    Diagnostic::from_execution_error(&error, dsl_source, python_source)
+        .tsg_path(Path::new("test.tsg"))
+        .source_path(Path::new("test.py"))
+        .to_string()
+}
+
+#[test]
+fn can_render_parse_errors() {
+    let rendered = render_parse_error(indoc! {r#"
+      (module) @root
+      {
+        node n
+        attr (n) name = (source-text @missing)
+      }
+    "#});
+    assert_eq!(
+        rendered,
+        concat!(
+            "error: Undefined syntax capture @missing at (4, 32)\n",
+            " --> test.tsg:4:32\n",
+            "  |\n",
+            "4 |   attr (n) name = (source-text @missing)\n",
+            "  |                                ^^^^^^^^\n",
+        )
+    );
+}
+
+#[test]
+fn can_render_query_errors() {
+    let rendered = render_parse_error(indoc! {r#"
+      (module (unknown_node) @n) @root
+      {
+        node n
+      }
+    "#});
+    assert_eq!(
+        rendered,
+        concat!(
+            "error: Invalid query pattern at (1, 10) in stanza at (1, 1): unknown node type `unknown_node`\n",
+            " --> test.tsg:1:10\n",
+            "  |\n",
+            "1 | (module (unknown_node) @n) @root\n",
+            "  |          ^^^^^^^^^^^^ unknown node type `unknown_node`\n",
+        )
+    );
+}
+
+#[test]
+fn can_render_execution_errors() {
+    let python_source = indoc! {r#"
+      import os
+      x = 1
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (_) @left)
+      {
+        node n
+        attr (n) name = (plus (source-text @left) 1)
+      }
+    "#};
+    for lazy in [false, true] {
+        assert_eq!(
+            render_execution_error(python_source, dsl_source, lazy),
+            concat!(
+                "error: Function plus expects argument 1 to be number, got string\n",
+                " --> test.tsg:4:19\n",
+                "  |\n",
+                "1 | (assignment left: (_) @left)\n",
+                "  | ---------------------------- in this stanza\n",
+                "...\n",
+                "4 |   attr (n) name = (plus (source-text @left) 1)\n",
+                "  |                   ^^^^^^^^^^^^^^^^^^^^^^^^^^^^ in this call to plus\n",
+                "  |   -------------------------------------------- while executing this statement\n",
+                "  |\n",
+                " ::: test.py:2:1\n",
+                "  |\n",
+                "2 | x = 1\n",
+                "  | ----- matching this (assignment) node\n",
+            ),
+            "lazy: {}",
+            lazy
+        );
+    }
+}
+
+#[test]
+fn can_render_colored_diagnostics() {
+    let dsl_source = "(module) @root {\n  node @missing.n\n}\n";
+    let error = match File::from_str(tree_sitter_python::language(), dsl_source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(error) => error,
+    };
+    let diagnostic = Diagnostic::from_parse_error(&error, dsl_source);
+    assert!(!diagnostic.to_string().contains('\x1b'));
+    let rendered = diagnostic.colored(true).to_string();
+    assert!(
+        rendered.starts_with("\x1b[1;31merror\x1b[0m"),
+        "{:?}",
+        rendered
+    );
+    assert!(
+        rendered.contains("\x1b[1;31m^^^^^^^^\x1b[0m"),
+        "{:?}",
+        rendered
+    );
+}
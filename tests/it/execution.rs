@@ -5,14 +5,46 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
 use indoc::indoc;
 use tree_sitter::Parser;
+use tree_sitter::Query;
+use tree_sitter::QueryCursor;
 use tree_sitter_graph::ast::File;
 use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::graph::ConflictPolicy;
+use tree_sitter_graph::graph::EdgeTypeSchema;
+use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::graph::GraphNodeRef;
+use tree_sitter_graph::graph::OrphanGroup;
+use tree_sitter_graph::graph::SelfEdgePolicy;
+use tree_sitter_graph::graph::SyntaxNodeRef;
+use tree_sitter_graph::graph::Value;
+use tree_sitter_graph::source_hash;
+use tree_sitter_graph::CancelAfter;
+use tree_sitter_graph::Decision;
+use tree_sitter_graph::ErrorPolicy;
 use tree_sitter_graph::ExecutionConfig;
 use tree_sitter_graph::ExecutionError;
+use tree_sitter_graph::ExecutionProgress;
+use tree_sitter_graph::ExecutionSession;
+use tree_sitter_graph::ExternalMatch;
+use tree_sitter_graph::GraphMutationHook;
+use tree_sitter_graph::GraphObserver;
 use tree_sitter_graph::Identifier;
+use tree_sitter_graph::Location;
+use tree_sitter_graph::MutationContext;
+use tree_sitter_graph::MutationTarget;
 use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::ParsedSource;
+use tree_sitter_graph::QueryCache;
+use tree_sitter_graph::SessionStats;
 use tree_sitter_graph::Variables;
 
 fn init_log() {
@@ -25,6 +57,17 @@ fn init_log() {
 }
 
 fn execute(python_source: &str, dsl_source: &str) -> Result<String, ExecutionError> {
+    execute_in_mode(python_source, dsl_source, false, |graph| {
+        graph.pretty_print().to_string()
+    })
+}
+
+fn execute_in_mode<R>(
+    python_source: &str,
+    dsl_source: &str,
+    lazy: bool,
+    output: impl FnOnce(&Graph) -> R,
+) -> Result<R, ExecutionError> {
     init_log();
     let mut parser = Parser::new();
     parser.set_language(tree_sitter_python::language()).unwrap();
@@ -36,10 +79,9 @@ fn execute(python_source: &str, dsl_source: &str) -> Result<String, ExecutionErr
     globals
         .add(Identifier::from("filename"), "test.py".into())
         .map_err(|_| ExecutionError::DuplicateVariable("filename".into()))?;
-    let mut config = ExecutionConfig::new(&functions, &globals);
+    let mut config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
     let graph = file.execute(&tree, python_source, &mut config, &NoCancellation)?;
-    let result = graph.pretty_print().to_string();
-    Ok(result)
+    Ok(output(&graph))
 }
 
 fn check_execution(python_source: &str, dsl_source: &str, expected_graph: &str) {
@@ -47,6 +89,41 @@ fn check_execution(python_source: &str, dsl_source: &str, expected_graph: &str)
         Ok(actual_graph) => assert_eq!(actual_graph, expected_graph),
         Err(e) => panic!("Could not execute file: {}", e),
     }
+    // Where lazy execution can run the program too, both modes must build the same graph, up to
+    // the numbering of graph nodes.
+    let canonical_hash = |lazy| {
+        execute_in_mode(python_source, dsl_source, lazy, |graph| {
+            graph.canonical_hash()
+        })
+    };
+    match canonical_hash(true) {
+        Ok(lazy_hash) => assert_eq!(
+            canonical_hash(false).unwrap(),
+            lazy_hash,
+            "lazy execution builds a different graph"
+        ),
+        Err(e) => panic!("Could not execute file lazily: {}", e),
+    }
+}
+
+fn execute_partial(
+    python_source: &str,
+    dsl_source: &str,
+    on_error: ErrorPolicy,
+) -> Result<(String, Vec<Location>), ExecutionError> {
+    init_log();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).on_error(on_error);
+    let (graph, errors) = file.execute_partial(&tree, python_source, &config, &NoCancellation)?;
+    let result = graph.pretty_print().to_string();
+    let match_locations = errors.iter().map(|e| e.match_location).collect();
+    Ok((result, match_locations))
 }
 
 fn fail_execution(python_source: &str, dsl_source: &str) {
@@ -305,6 +382,75 @@ fn cannot_omit_global_variable() {
     );
 }
 
+#[test]
+fn can_override_default_of_global_variable() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          global filename = "default.py"
+
+          (module)
+          {
+            node n
+            attr (n) filename = filename
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            filename: "test.py"
+    "#},
+    );
+}
+
+#[test]
+fn cannot_omit_several_global_variables() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          global root
+          global filename
+          global pkgname = ""
+          global lang
+
+          (module)
+          {
+            node n
+            attr (n) root = root, lang = lang
+          }
+        "#},
+    )
+    .expect_err("execution to fail");
+    assert_eq!(error.to_string(), "Missing global variables root, lang");
+}
+
+#[test]
+fn can_find_missing_global_variables() {
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          global root
+          global filename
+          global pkgname = ""
+        "#},
+    )
+    .unwrap();
+    let mut globals = Variables::new();
+    let missing = file
+        .missing_globals(&globals)
+        .into_iter()
+        .map(|global| global.name.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(missing, vec!["root", "filename"]);
+    assert!(!file.globals[0].has_default());
+    assert!(file.globals[2].has_default());
+
+    globals.add(Identifier::from("root"), "/".into()).unwrap();
+    globals
+        .add(Identifier::from("filename"), "test.py".into())
+        .unwrap();
+    assert!(file.missing_globals(&globals).is_empty());
+}
+
 #[test]
 fn cannot_pass_string_to_global_list_variable() {
     fail_execution(
@@ -351,6 +497,9 @@ fn can_nest_function_calls() {
     );
 }
 
+// The minimal regex engine does not support `\b`, and cannot express regexes that only match
+// empty strings within a larger string.
+#[cfg(not(feature = "lite-regex"))]
 #[test]
 fn cannot_use_nullable_regex() {
     fail_execution(
@@ -368,6 +517,80 @@ fn cannot_use_nullable_regex() {
     );
 }
 
+#[test]
+fn can_create_quantified_captures_in_order() {
+    check_execution(
+        indoc! {r#"
+          def f():
+            x = 1
+            pass
+            return x
+          def g():
+            pass
+        "#},
+        indoc! {r#"
+          (function_definition body: (block (_)* @stmts))
+          {
+            node n
+            attr (n) stmts = @stmts, count = (length @stmts)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            count: 3
+            stmts: [[syntax node expression_statement (2, 3)], [syntax node pass_statement (3, 3)], [syntax node return_statement (4, 3)]]
+          node 1
+            count: 1
+            stmts: [[syntax node pass_statement (6, 3)]]
+        "#},
+    );
+}
+
+#[test]
+fn can_create_one_graph_node_per_unique_imported_module() {
+    check_execution(
+        indoc! {r#"
+          import os
+          import sys
+          from os import path
+          import json
+          from sys import argv
+        "#},
+        indoc! {r#"
+          (module [
+            (import_statement name: (dotted_name) @names)
+            (import_from_statement module_name: (dotted_name) @names)
+          ]* ) @mod
+          {
+            ; the names are captured, so they are local values that a for loop can iterate over
+            let modules = (to-set [(source-text name) for name in @names])
+            let @mod.modules = modules
+            for module in (to-list modules) {
+              node n
+              attr (n) module = module
+            }
+          }
+
+          (module) @mod
+          {
+            node @mod.node
+            attr (@mod.node) modules = @mod.modules, count = (length (to-list @mod.modules))
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            module: "json"
+          node 1
+            module: "os"
+          node 2
+            module: "sys"
+          node 3
+            count: 3
+            modules: {"json", "os", "sys"}
+        "#},
+    );
+}
+
 #[test]
 fn can_create_present_optional_capture() {
     check_execution(
@@ -445,6 +668,35 @@ fn can_create_nonempty_list_capture() {
     );
 }
 
+#[test]
+fn can_execute_capture_aggregates() {
+    check_execution(
+        "pass\npass\npass\n",
+        indoc! {r#"
+          (module (pass_statement)* @xs)
+          {
+            node node0
+            attr (node0) count = (count @xs), first = (first @xs), last = (last @xs)
+          }
+          (module (import_statement)* @xs)
+          {
+            node node1
+            attr (node1) count = (count @xs), first = (first @xs), last = (last @xs)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            count: 3
+            first: [syntax node pass_statement (1, 1)]
+            last: [syntax node pass_statement (3, 1)]
+          node 1
+            count: 0
+            first: #null
+            last: #null
+        "#},
+    );
+}
+
 #[test]
 fn can_execute_if_some() {
     check_execution(
@@ -577,6 +829,54 @@ fn can_execute_if_literal() {
     );
 }
 
+#[test]
+fn can_execute_if_function_call() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module (import_statement)? @x (pass_statement)? @y)
+          {
+            node node0
+            if (is-null @x) {
+              attr (node0) x = #false
+            }
+            if (not (is-null @y)) {
+              attr (node0) y = #true
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            x: #false
+            y: #true
+        "#},
+    );
+}
+
+#[test]
+fn skip_errors_in_branches_not_taken() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module (import_statement)? @x)
+          {
+            node node0
+            if some @x {
+              attr (node0) val = (parse-int "not a number")
+            } elif #false {
+              attr (node0) val = (int-to-char -1)
+            } else {
+              attr (node0) val = 1
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            val: 1
+        "#},
+    );
+}
+
 #[test]
 fn skip_if_without_true_conditions() {
     check_execution(
@@ -819,130 +1119,439 @@ fn variables_are_inherited_in_for_in_body() {
 }
 
 #[test]
-fn can_execute_list_comprehension() {
+fn can_execute_for_in_function_result() {
     check_execution(
         r#"
           pass
-          pass
-          pass
         "#,
         indoc! {r#"
-          (module (pass_statement)* @xs)
+          (module)
           {
-            node node0
-            attr (node0) val = [ (named-child-index x) for x in @xs ]
+            var current = (node)
+            attr (current) name = "root"
+            for part in (concat ["a", "b"] ["c"]) {
+              let next = (node)
+              attr (next) name = part
+              edge current -> next
+              set current = next
+            }
           }
         "#},
         indoc! {r#"
           node 0
-            val: [0, 1, 2]
+            name: "root"
+          edge 0 -> 1
+          node 1
+            name: "a"
+          edge 1 -> 2
+          node 2
+            name: "b"
+          edge 2 -> 3
+          node 3
+            name: "c"
         "#},
     );
 }
 
 #[test]
-fn can_execute_set_comprehension() {
+fn can_execute_for_in_empty_function_result() {
     check_execution(
         r#"
           pass
-          pass
-          pass
         "#,
         indoc! {r#"
-          (module (pass_statement)* @xs)
+          (module)
           {
+            var n = 0
+            for x in (concat) {
+              set n = (plus n 1)
+            }
             node node0
-            attr (node0) val = { (source-text x) for x in @xs }
+            attr (node0) val = n
           }
         "#},
         indoc! {r#"
           node 0
-            val: {"pass"}
+            val: 0
         "#},
     );
 }
 
 #[test]
-fn can_execute_scan_of_local_call_expression() {
+fn can_execute_for_in_with_index() {
     check_execution(
         r#"
-          def get_f():
-            pass
+          pass
         "#,
         indoc! {r#"
-          (function_definition
-            name: (identifier) @name)
+          (module) @mod
           {
-            node n
-            scan (source-text @name) {
-              "get_.*" {
-                attr (n) is_getter = #true
+            var current = (node)
+            attr (current) name = "root"
+            let parts = (split "pkg/sub/mod.py" "/")
+            for i, part in parts {
+              if (not (last? parts i)) {
+                let next = (node)
+                attr (next) name = part, index = i
+                edge current -> next
+                set current = next
+              } elif (ends-with part ".py") {
+                let next = (node)
+                attr (next) name = (substring part 0 (index-of part ".py")), index = i
+                edge current -> next
+                let @mod.root = next
               }
             }
           }
+
+          (module) @mod
+          {
+            attr (@mod.root) is_root = #true
+          }
         "#},
         indoc! {r#"
           node 0
-            is_getter: #true
+            name: "root"
+          edge 0 -> 1
+          node 1
+            index: 0
+            name: "pkg"
+          edge 1 -> 2
+          node 2
+            index: 1
+            name: "sub"
+          edge 2 -> 3
+          node 3
+            index: 2
+            is_root: #true
+            name: "mod"
         "#},
     );
 }
 
 #[test]
-fn can_execute_scan_of_local_variable() {
-    check_execution(
+fn cannot_execute_for_in_non_list() {
+    fail_execution(
         r#"
-          def get_f():
-            pass
+          pass
         "#,
         indoc! {r#"
-          (function_definition
-            name: (identifier) @name)
+          (module)
           {
-            node n
-            let val = (source-text @name)
-            scan val {
-              "get_.*" {
-                attr (n) is_getter = #true
-              }
+            for x in "abc" {
+              node n
             }
           }
         "#},
-        indoc! {r#"
-          node 0
-            is_getter: #true
-        "#},
     );
 }
 
 #[test]
-fn can_execute_shorthand() {
+fn can_execute_hidden_attributes() {
     check_execution(
-        indoc! { r#"
-          def get_f():
-            pass
+        indoc! {r#"
+          a = 1
+          b = "x"
         "#},
         indoc! {r#"
-            attribute def = x => source_node = x, symbol = (source-text x)
-            (function_definition name: (identifier) @name) {
-              node n
-              attr (n) def = @name
-            }
+          (assignment left: (identifier) @name right: (_) @value)
+          {
+            node n
+            attr (n) name = (source-text @name), _kind = (node-type @value)
+          }
+
+          (module)
+          {
+            node integers
+            attr (integers) members = (find-nodes-by-attr "_kind" "integer")
+          }
         "#},
         indoc! {r#"
           node 0
-            source_node: [syntax node identifier (1, 5)]
-            symbol: "get_f"
+            name: "a"
+          node 1
+            name: "b"
+          node 2
+            members: [[graph node 0]]
         "#},
     );
 }
 
 #[test]
-fn can_access_inherited_attribute() {
-    check_execution(
-        indoc! { r#"
-          def get_f():
-            pass
+fn cannot_execute_conflicting_hidden_attributes() {
+    fail_execution(
+        indoc! {r#"
+          pass
+        "#},
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) _seen = #true
+            attr (n) _seen = #false
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_list_comprehension() {
+    check_execution(
+        r#"
+          pass
+          pass
+          pass
+        "#,
+        indoc! {r#"
+          (module (pass_statement)* @xs)
+          {
+            node node0
+            attr (node0) val = [ (named-child-index x) for x in @xs ]
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            val: [0, 1, 2]
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_set_comprehension() {
+    check_execution(
+        r#"
+          pass
+          pass
+          pass
+        "#,
+        indoc! {r#"
+          (module (pass_statement)* @xs)
+          {
+            node node0
+            attr (node0) val = { (source-text x) for x in @xs }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            val: {"pass"}
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_scan_of_local_call_expression() {
+    check_execution(
+        r#"
+          def get_f():
+            pass
+        "#,
+        indoc! {r#"
+          (function_definition
+            name: (identifier) @name)
+          {
+            node n
+            scan (source-text @name) {
+              "get_.*" {
+                attr (n) is_getter = #true
+              }
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            is_getter: #true
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_scan_of_local_variable() {
+    check_execution(
+        r#"
+          def get_f():
+            pass
+        "#,
+        indoc! {r#"
+          (function_definition
+            name: (identifier) @name)
+          {
+            node n
+            let val = (source-text @name)
+            scan val {
+              "get_.*" {
+                attr (n) is_getter = #true
+              }
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            is_getter: #true
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_scan_of_syntax_node() {
+    check_execution(
+        r#"
+          def get_f():
+            pass
+        "#,
+        indoc! {r#"
+          (function_definition
+            name: (identifier) @name)
+          {
+            node n
+            scan @name {
+              "get_(.*)" {
+                attr (n) suffix = $1
+              }
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            suffix: "f"
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_scan_of_list_of_syntax_nodes() {
+    check_execution(
+        "get_a\nb\nget_c\n",
+        indoc! {r#"
+          (module (_)* @stmts)
+          {
+            scan @stmts {
+              "get_(.*)" {
+                node n
+                attr (n) name = $1
+              }
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            name: "a"
+          node 1
+            name: "c"
+        "#},
+    );
+}
+
+// The minimal regex engine does not support named groups.
+#[cfg(not(feature = "lite-regex"))]
+#[test]
+fn can_execute_scan_with_named_groups_and_match_offsets() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            scan "dé/ñ/mañana.rs" {
+              "(?P<dir>[^/]+)/" {
+                node n
+                attr (n) dir = $dir, group = $1, start = $match_start, end = $match_end
+              }
+              "([^/.]+)\\.(?P<ext>\\w+)$" {
+                node n
+                attr (n) stem = $1, ext = $ext, start = $match_start, end = $match_end
+              }
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            dir: "dé"
+            end: 3
+            group: "dé"
+            start: 0
+          node 1
+            dir: "ñ"
+            end: 5
+            group: "ñ"
+            start: 3
+          node 2
+            end: 14
+            ext: "rs"
+            start: 5
+            stem: "mañana"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_scan_non_string_values() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            scan [1] {
+              "1" {
+                node n
+              }
+            }
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_shorthand() {
+    check_execution(
+        indoc! { r#"
+          def get_f():
+            pass
+        "#},
+        indoc! {r#"
+            attribute def = x => source_node = x, symbol = (source-text x)
+            (function_definition name: (identifier) @name) {
+              node n
+              attr (n) def = @name
+            }
+        "#},
+        indoc! {r#"
+          node 0
+            source_node: [syntax node identifier (1, 5)]
+            symbol: "get_f"
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_shorthand_without_value() {
+    check_execution(
+        indoc! { r#"
+          def get_f():
+            pass
+        "#},
+        indoc! {r#"
+            attribute def_attrs => kind = "definition", is_exported = #true
+            attribute pos_attrs = n => row = (start-row n), def_attrs
+            (function_definition name: (identifier) @name) {
+              node n
+              attr (n) pos_attrs = @name, name = (source-text @name)
+            }
+        "#},
+        indoc! {r#"
+          node 0
+            is_exported: #true
+            kind: "definition"
+            name: "get_f"
+            row: 0
+        "#},
+    );
+}
+
+#[test]
+fn can_access_inherited_attribute() {
+    check_execution(
+        indoc! { r#"
+          def get_f():
+            pass
         "#},
         indoc! {r#"
             inherit .test
@@ -1072,3 +1681,2907 @@ fn cannot_change_attribute_value() {
         "#},
     );
 }
+
+const ROLLBACK_PYTHON: &str = indoc! {r#"
+    good
+    bad
+    fine
+"#};
+
+const ROLLBACK_DSL: &str = indoc! {r#"
+    inherit .root
+    (module) @mod {
+      node @mod.root
+    }
+    (expression_statement (identifier) @id) @stmt {
+      node n
+      edge @stmt.root -> n
+      attr (n) name = (source-text @id)
+      scan (source-text @id) {
+        "^bad$" { attr (n) name = "changed" }
+      }
+    }
+"#};
+
+#[test]
+fn cannot_execute_failing_match_by_default() {
+    fail_execution(ROLLBACK_PYTHON, ROLLBACK_DSL);
+}
+
+#[test]
+fn can_skip_failing_match() {
+    let (graph, match_locations) =
+        execute_partial(ROLLBACK_PYTHON, ROLLBACK_DSL, ErrorPolicy::SkipMatch)
+            .expect("Could not execute file");
+    assert_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+          edge 0 -> 1
+          edge 0 -> 2
+          node 1
+            name: "good"
+          node 2
+            name: "fine"
+        "#}
+    );
+    assert_eq!(match_locations, vec![Location { row: 1, column: 0 }]);
+}
+
+#[test]
+fn can_skip_failing_stanza() {
+    let (graph, match_locations) =
+        execute_partial(ROLLBACK_PYTHON, ROLLBACK_DSL, ErrorPolicy::SkipStanza)
+            .expect("Could not execute file");
+    assert_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+        "#}
+    );
+    assert_eq!(match_locations, vec![Location { row: 1, column: 0 }]);
+}
+
+#[test]
+fn can_roll_back_provenance_of_skipped_match() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(ROLLBACK_PYTHON, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), ROLLBACK_DSL).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let stanza = file.stanzas[1].range.start;
+    for (on_error, created) in [(ErrorPolicy::SkipMatch, 2), (ErrorPolicy::SkipStanza, 0)] {
+        let config = ExecutionConfig::new(&functions, &globals)
+            .provenance(true)
+            .on_error(on_error);
+        let (graph, errors) = file
+            .execute_partial(&tree, ROLLBACK_PYTHON, &config, &NoCancellation)
+            .expect("Could not execute file");
+        assert_eq!(errors.len(), 1);
+        // The rolled back nodes and attributes are not attributed to the stanza.
+        let nodes = graph.nodes_created_by(stanza);
+        assert_eq!(nodes, &graph.iter_nodes().skip(1).collect::<Vec<_>>()[..]);
+        assert_eq!(nodes.len(), created);
+        assert_eq!(graph.attrs_set_by(stanza).len(), created);
+        if let Some(node) = nodes.last() {
+            let explanation = graph.explain_attribute(*node, "name").unwrap().to_string();
+            assert!(
+                explanation.starts_with("attribute name of graph node 2 = \"fine\""),
+                "{}",
+                explanation
+            );
+        }
+    }
+}
+
+#[test]
+fn can_roll_back_scoped_graph_nodes_of_skipped_match() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(ROLLBACK_PYTHON, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (expression_statement (identifier) @id) @stmt {
+            node @stmt.node
+            attr (@stmt.node) name = (source-text @id)
+            scan (source-text @id) {
+              "^bad$" { attr (@stmt.node) name = "changed" }
+            }
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).on_error(ErrorPolicy::SkipMatch);
+    let (graph, errors) = file
+        .execute_partial(&tree, ROLLBACK_PYTHON, &config, &NoCancellation)
+        .expect("Could not execute file");
+    assert_eq!(errors.len(), 1);
+    // The statement of the skipped match does not own the graph node that was rolled back.
+    let mut owners = graph
+        .syntax_to_graph_index()
+        .into_iter()
+        .map(|(syntax_node, owned)| (syntax_node.location().row, owned.len()))
+        .collect::<Vec<_>>();
+    owners.sort();
+    assert_eq!(owners, vec![(0, 1), (2, 1)]);
+}
+
+#[test]
+fn can_report_conflict_with_attribute_that_was_not_rolled_back() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let python_source = indoc! {r#"
+        bad
+        good
+        fine
+    "#};
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          inherit .root
+          (module) @mod {
+            node @mod.root
+          }
+          (expression_statement (identifier) @id) @stmt {
+            attr (@stmt.root) kind = (source-text @id)
+            scan (source-text @id) {
+              "^bad$" { attr (@stmt.root) kind = "changed" }
+            }
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).on_error(ErrorPolicy::SkipMatch);
+    let (_, errors) = file
+        .execute_partial(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    let locations = errors
+        .iter()
+        .map(|e| e.match_location.row)
+        .collect::<Vec<_>>();
+    assert_eq!(locations, vec![0, 2]);
+    // The conflict of the last match is with the attribute set by the second match, and not with
+    // the one set by the skipped first match.
+    let message = format!("{}", errors[1].error);
+    assert!(message.contains("Duplicate attribute"), "{}", message);
+    assert!(message.contains("node at (2, 1)"), "{}", message);
+    assert!(!message.contains("node at (1, 1)"), "{}", message);
+}
+
+#[test]
+fn can_execute_valid_stanzas_of_recovered_file() {
+    init_log();
+    let python_source = "x = 1";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let (file, errors) = File::from_str_with_recovery(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) {
+            node n
+            attr (n) kind = "module"
+          }
+
+          (identifier) @id {
+            if some @id {
+              node @id.def
+              attr (@id.def) name = = "{broken}"
+            }
+          }
+
+          (integer) @int {
+            node @int.lit
+            attr (@int.lit) kind = "integer"
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(file.stanzas.len(), 2);
+    assert_eq!(file.invalid_stanzas.len(), 1);
+    assert_eq!(
+        file.invalid_stanzas[0].start,
+        Location { row: 5, column: 0 }
+    );
+    assert_eq!(file.invalid_stanzas[0].end, Location { row: 10, column: 1 });
+
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    match file.execute(&tree, python_source, &config, &NoCancellation) {
+        Err(ExecutionError::InvalidStanzas(message)) => assert_eq!(message, "at (6, 1)"),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+
+    for lazy in [false, true] {
+        let config = ExecutionConfig::new(&functions, &globals)
+            .lazy(lazy)
+            .allow_partial(true);
+        let graph = file
+            .execute(&tree, python_source, &config, &NoCancellation)
+            .expect("Could not execute file");
+        assert_eq!(
+            graph.pretty_print().to_string(),
+            indoc! {r#"
+              node 0
+                kind: "module"
+              node 1
+                kind: "integer"
+            "#}
+        );
+    }
+}
+
+#[test]
+fn can_find_graph_nodes_for_syntax_nodes() {
+    let python_source = "x";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) @mod {
+            node n
+            attr (n) source = @mod
+          }
+          (identifier) @id {
+            node @id.ref
+            node @id.def
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+
+    let module = tree.root_node();
+    let module_ref = graph.syntax_node_ref_for(&module).unwrap();
+    assert_eq!(graph.graph_nodes_for_syntax_node(module_ref).count(), 0);
+
+    let identifier = module.child(0).unwrap().child(0).unwrap();
+    let identifier_ref = graph.syntax_node_ref_for(&identifier).unwrap();
+    let owned = graph
+        .graph_nodes_for_syntax_node(identifier_ref)
+        .map(|(tag, node)| (tag.to_string(), node.index()))
+        .collect::<Vec<_>>();
+    assert_eq!(owned, vec![("def".to_string(), 2), ("ref".to_string(), 1)]);
+
+    let index = graph.syntax_to_graph_index();
+    assert_eq!(index.len(), 1);
+    assert_eq!(index[&identifier_ref].len(), 2);
+    assert!(!index.contains_key(&module_ref));
+
+    let statement = module.child(0).unwrap();
+    assert!(graph.syntax_node_ref_for(&statement).is_none());
+}
+
+#[test]
+fn can_filter_matches_with_global_predicates() {
+    check_execution(
+        indoc! {r#"
+          test.py
+          other.py
+        "#},
+        indoc! {r#"
+          global filename
+          global prefix = "^o"
+          ((attribute) @attr (#eq-global? @attr filename)) {
+            node n
+            attr (n) text = (source-text @attr)
+          }
+          ((identifier) @id (#match-global? @id prefix)) {
+            node n
+            attr (n) text = (source-text @id)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            text: "test.py"
+          node 1
+            text: "other"
+        "#},
+    );
+}
+
+#[test]
+fn can_filter_matches_with_text_predicates() {
+    check_execution(
+        indoc! {r#"
+          def f(self, x, cls, y):
+            pass
+        "#},
+        indoc! {r#"
+          ((identifier) @id (#any-of? @id "self" "cls")) {
+            node n
+            attr (n) receiver = (source-text @id)
+          }
+          ((identifier) @id (#not-any-of? @id "self" "cls" "f")) {
+            node n
+            attr (n) parameter = (source-text @id)
+          }
+          ((identifier) @id (#eq? @id "x")) {
+            node n
+            attr (n) eq = (source-text @id)
+          }
+          ((identifier) @id (#match? @id "^[fy]$") (#not-eq? @id "f")) {
+            node n
+            attr (n) match = (source-text @id)
+          }
+          ((identifier) @id (#not-match? @id "[a-z]{2}")) {
+            node n
+            attr (n) short = (source-text @id)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            receiver: "self"
+          node 1
+            receiver: "cls"
+          node 2
+            parameter: "x"
+          node 3
+            parameter: "y"
+          node 4
+            eq: "x"
+          node 5
+            match: "y"
+          node 6
+            short: "f"
+          node 7
+            short: "x"
+          node 8
+            short: "y"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_use_undefined_global_in_predicate() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          ((module) @mod (#eq-global? @mod undefined)) {
+            node n
+            attr (n) source = @mod
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_explain_attribute_values() {
+    let python_source = "def f(): pass";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          global prefix = "pkg"
+          (module) @mod {
+            let @mod.prefix = prefix
+          }
+          (module (function_definition name: (identifier) @name)) @mod {
+            node def
+            let name = (source-text @name)
+            attr (def) qualified_name = (format "{}.{}" @mod.prefix name)
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).provenance(true);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+
+    let node = graph.iter_nodes().next().unwrap();
+    assert_eq!(
+        graph
+            .explain_attribute(node, "qualified_name")
+            .unwrap()
+            .to_string(),
+        indoc! {r#"
+          attribute qualified_name of graph node 0 = "pkg.f"
+            attribute qualified_name at (8, 3)
+              call to function format at (8, 3)
+                literal at (8, 3)
+                read of variable @mod.prefix at (8, 52)
+                  assignment to variable @mod.prefix at (3, 3)
+                    global variable prefix at (3, 21)
+                read of variable name at (8, 59)
+                  assignment to variable name at (7, 3)
+                    call to function source-text at (7, 3)
+                      ...
+        "#}
+    );
+    assert!(graph.explain_attribute(node, "missing").is_none());
+}
+
+#[test]
+fn cannot_explain_attribute_values_by_default() {
+    let python_source = "pass";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) {
+            node n
+            attr (n) kind = "module"
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    let node = graph.iter_nodes().next().unwrap();
+    assert!(graph.explain_attribute(node, "kind").is_none());
+}
+
+#[test]
+fn can_query_output_by_stanza() {
+    let python_source = "def f(): pass\ndef g(): pass\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) @mod {
+            node @mod.root
+          }
+          stanza definitions
+          (function_definition name: (identifier) @name) @def {
+            node @def.node
+            attr (@def.node) name = (source-text @name)
+            edge @def.node -> (node)
+          }
+          (module (function_definition) @def) @mod {
+            edge @mod.root -> @def.node
+            attr (@mod.root -> @def.node) kind = "defines"
+          }
+          (class_definition) {
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).provenance(true);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+
+    let node = |index| graph.iter_nodes().nth(index).unwrap();
+    let definitions = file.stanzas[1].range.start;
+    assert_eq!(definitions, Location { row: 4, column: 0 });
+    assert_eq!(
+        graph.nodes_created_by(definitions),
+        &[node(1), node(2), node(3), node(4)]
+    );
+    assert_eq!(
+        graph.edges_created_by(definitions),
+        &[(node(1), node(2)), (node(3), node(4))]
+    );
+    assert_eq!(
+        graph.attrs_set_by(definitions),
+        &[
+            (MutationTarget::GraphNode(node(1)), Identifier::from("name")),
+            (MutationTarget::GraphNode(node(3)), Identifier::from("name")),
+        ]
+    );
+    assert!(graph
+        .nodes_created_by(file.stanzas[3].range.start)
+        .is_empty());
+    assert_eq!(
+        graph.pretty_print_by_stanza(&file).to_string(),
+        indoc! {r#"
+          stanza at (1, 1)
+            node 0
+          stanza definitions at (5, 1)
+            node 1
+            node 2
+            node 3
+            node 4
+            edge 1 -> 2
+            edge 3 -> 4
+            attr (1) name = "f"
+            attr (3) name = "g"
+          stanza at (10, 1)
+            edge 0 -> 1
+            edge 0 -> 3
+            attr (0 -> 1) kind = "defines"
+            attr (0 -> 3) kind = "defines"
+          stanza at (14, 1)
+        "#}
+    );
+
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    assert!(graph.nodes_created_by(definitions).is_empty());
+}
+
+#[test]
+fn stanza_output_is_discarded_with_skipped_matches() {
+    let python_source = "x = 1\ny = 2\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (assignment left: (identifier) @name right: (_) @value) {
+            node n
+            attr (n) name = (source-text @name)
+            attr (n) value = (source-text @value)
+            if (eq (source-text @name) "y") {
+              attr (n) value = "conflict"
+            }
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals)
+        .provenance(true)
+        .on_error(ErrorPolicy::SkipMatch);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    let stanza = file.stanzas[0].range.start;
+    let node = graph.iter_nodes().next().unwrap();
+    assert_eq!(graph.node_count(), 1);
+    assert_eq!(graph.nodes_created_by(stanza), &[node]);
+    assert_eq!(graph.attrs_set_by(stanza).len(), 2);
+}
+
+#[test]
+fn can_execute_external_matches() {
+    let python_source = indoc! {r#"
+      def f(): pass
+      def g(): pass
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) {
+            node n
+            attr (n) kind = "module"
+          }
+          (function_definition name: (identifier) @name) {
+            node n
+            attr (n) name = (source-text @name)
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+
+    // Produce the matches with a different query, and supply them out of order.
+    let query = Query::new(
+        tree_sitter_python::language(),
+        "(function_definition name: (identifier) @function.name) @function",
+    )
+    .unwrap();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor
+        .matches(&query, tree.root_node(), python_source.as_bytes())
+        .map(|mat| ExternalMatch {
+            stanza_index: 1,
+            node: mat.captures[0].node,
+            captures: vec![("name".to_string(), mat.captures[1].node)],
+        })
+        .collect::<Vec<_>>();
+    matches.reverse();
+    matches.push(ExternalMatch {
+        stanza_index: 0,
+        node: tree.root_node(),
+        captures: vec![],
+    });
+
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute_with_matches(python_source, matches, &config, &NoCancellation)
+        .expect("Could not execute file");
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            kind: "module"
+          node 1
+            name: "f"
+          node 2
+            name: "g"
+        "#}
+    );
+}
+
+#[test]
+fn cannot_execute_external_matches_with_invalid_captures() {
+    let python_source = "def f(): pass";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (function_definition name: (identifier) @name) {
+            node n
+            attr (n) name = (source-text @name)
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let definition = tree.root_node().child(0).unwrap();
+    let name = definition.child_by_field_name("name").unwrap();
+    let execute = |captures| {
+        file.execute_with_matches(
+            python_source,
+            vec![ExternalMatch {
+                stanza_index: 0,
+                node: definition,
+                captures,
+            }],
+            &config,
+            &NoCancellation,
+        )
+    };
+
+    assert!(matches!(
+        execute(vec![]),
+        Err(ExecutionError::InvalidMatch(_))
+    ));
+    assert!(matches!(
+        execute(vec![("name".to_string(), name), ("name".to_string(), name)]),
+        Err(ExecutionError::InvalidMatch(_))
+    ));
+    assert!(matches!(
+        execute(vec![
+            ("name".to_string(), name),
+            ("other".to_string(), name)
+        ]),
+        Err(ExecutionError::UndefinedCapture(_))
+    ));
+    assert!(execute(vec![("name".to_string(), name)]).is_ok());
+}
+
+#[test]
+fn can_inherit_attributes_after_execution() {
+    check_execution(
+        indoc! {r#"
+          class A:
+            def f(self): pass
+        "#},
+        indoc! {r#"
+          inherit module_path along edge kind = "contains"
+
+          (module) @mod {
+            node @mod.node
+            attr (@mod.node) module_path = "pkg"
+          }
+          (class_definition) @class {
+            node @class.node
+          }
+          (function_definition) @fn {
+            node @fn.node
+          }
+          (module (class_definition) @class) @mod {
+            edge @mod.node -> @class.node
+            attr (@mod.node -> @class.node) kind = "contains"
+          }
+          (class_definition body: (block (function_definition) @fn)) @class {
+            edge @class.node -> @fn.node
+            attr (@class.node -> @fn.node) kind = "contains"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            module_path: "pkg"
+          edge 0 -> 1
+            kind: "contains"
+          node 1
+            module_path: "pkg"
+          edge 1 -> 2
+            kind: "contains"
+          node 2
+            module_path: "pkg"
+        "#},
+    );
+}
+
+#[test]
+fn can_append_to_attributes() {
+    check_execution(
+        indoc! {r#"
+          a = 1
+          b = 2
+        "#},
+        indoc! {r#"
+          (module) @m
+          {
+            node @m.node
+          }
+          (module (expression_statement (assignment left: (identifier) @name))) @m
+          {
+            attr (@m.node) tags += (source-text @name)
+          }
+          (module) @m
+          {
+            attr (@m.node) tags += "last"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            tags: ["a", "b", "last"]
+        "#},
+    );
+}
+
+#[test]
+fn cannot_append_to_non_list_attribute() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) tags = "first"
+            attr (n) tags += "second"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(
+        message.contains("Cannot append to non-list attribute tags on"),
+        "{}",
+        message
+    );
+    assert!(message.contains("(4, 3)"), "{}", message);
+    assert!(message.contains("(5, 3)"), "{}", message);
+}
+
+#[test]
+fn can_override_attributes() {
+    check_execution(
+        indoc! {r#"
+          f(x)
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) kind = "reference", name = (source-text @id)
+            edge @id.node -> @id.node
+            attr (@id.node -> @id.node) precedence = 0
+          }
+          (call function: (identifier) @id)
+          {
+            attr (@id.node) kind := "call"
+            attr (@id.node -> @id.node) precedence := 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "call"
+            name: "f"
+          edge 0 -> 0
+            precedence: 1
+          node 1
+            kind: "reference"
+            name: "x"
+          edge 1 -> 1
+            precedence: 0
+        "#},
+    );
+}
+
+#[test]
+fn can_set_attributes_to_equal_values() {
+    check_execution(
+        indoc! {r#"
+          f(x)
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) kind = "name", parts = [1, "two"], self = @id.node
+            edge @id.node -> @id.node
+            attr (@id.node -> @id.node) precedence = 0
+          }
+          (call function: (identifier) @id)
+          {
+            attr (@id.node) kind = "name", parts = [1, "two"], self = @id.node
+            attr (@id.node -> @id.node) precedence = 0
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "name"
+            parts: [1, "two"]
+            self: [graph node 0]
+          edge 0 -> 0
+            precedence: 0
+          node 1
+            kind: "name"
+            parts: [1, "two"]
+            self: [graph node 1]
+          edge 1 -> 1
+            precedence: 0
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_attributes_to_different_values() {
+    let error = execute(
+        indoc! {r#"
+          f(x)
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) kind = "name"
+          }
+          (call function: (identifier) @id)
+          {
+            attr (@id.node) kind = "call"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(message.contains("Duplicate attribute"), "{}", message);
+    assert!(message.contains("(4, 3)"), "{}", message);
+    assert!(message.contains("(8, 3)"), "{}", message);
+}
+
+#[test]
+fn cannot_override_attribute_that_is_not_set() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) kind := "call"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(
+        message.contains("Cannot override attribute that is not set kind on"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn can_remove_and_re_add_attributes() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module) @m
+          {
+            node @m.node
+            attr (@m.node) kind = "reference", name = "m"
+            edge @m.node -> @m.node
+            attr (@m.node -> @m.node) precedence = 0
+          }
+          (module) @m
+          {
+            delattr (@m.node) kind, name
+            delattr (@m.node -> @m.node) precedence
+          }
+          (module) @m
+          {
+            attr (@m.node) kind = "definition"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "definition"
+          edge 0 -> 0
+        "#},
+    );
+}
+
+#[test]
+fn can_instantiate_node_types() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          nodetype definition { kind = "definition", is_exported = #false }
+
+          (module)
+          {
+            node n : definition
+            attr (n) is_exported = #true
+            node m : definition
+            attr (m) kind = "reference", kind = "reference"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            is_exported: #true
+            kind: "definition"
+          node 1
+            is_exported: #false
+            kind: "reference"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_conflicting_attributes_on_node_type_instances() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          nodetype definition { kind = "definition" }
+
+          (module)
+          {
+            node n : definition
+            attr (n) kind = "reference"
+            attr (n) kind = "import"
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_phases_in_order() {
+    check_execution(
+        indoc! {r#"
+          a = 1
+          b = 2
+        "#},
+        indoc! {r#"
+          phase declare
+          (identifier) @id
+          {
+            node n
+            attr (n) name = (source-text @id)
+          }
+
+          phase connect
+          (module)
+          {
+            node n
+            attr (n) kind = "module"
+          }
+
+          phase declare
+          (module)
+          {
+            node n
+            attr (n) kind = "declared module"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            name: "a"
+          node 1
+            name: "b"
+          node 2
+            kind: "declared module"
+          node 3
+            kind: "module"
+        "#},
+    );
+}
+
+#[test]
+fn can_add_attributes_to_several_targets() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node a
+            node b
+            edge a -> b
+            edge b -> a
+            attr (a, b) kind = "definition", lang = "python"
+            attr (a -> b, b -> a) precedence = 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "definition"
+            lang: "python"
+          edge 0 -> 1
+            precedence: 1
+          node 1
+            kind: "definition"
+            lang: "python"
+          edge 1 -> 0
+            precedence: 1
+        "#},
+    );
+}
+
+#[test]
+fn can_add_inline_attributes_to_nodes_and_edges() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node a, kind = "definition", names = ["a"]
+            node b, kind = "reference"
+            edge a -> b, precedence = 10, kind = "member"
+            attr (a) names += "b"
+            attr (a -> b) precedence := 11
+            edge b -> a,
+              precedence = 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "definition"
+            names: ["a", "b"]
+          edge 0 -> 1
+            kind: "member"
+            precedence: 11
+          node 1
+            kind: "reference"
+          edge 1 -> 0
+            precedence: 1
+        "#},
+    );
+}
+
+#[test]
+fn can_create_reversed_edges() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node scope
+            node ref
+            edge scope <- ref, kind = "parent"
+            attr (scope <- ref) precedence = 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+          node 1
+          edge 1 -> 0
+            kind: "parent"
+            precedence: 1
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_inline_attributes_again() {
+    for (statement, name) in [
+        (r#"attr (a) kind = "reference""#, "kind"),
+        (r#"attr (a -> b) precedence = 2"#, "precedence"),
+        (r#"edge a -> b, precedence = 2"#, "precedence"),
+    ] {
+        let error = execute(
+            "pass",
+            &format!(
+                "(module) {{ node a, kind = \"definition\" node b edge a -> b, precedence = 1 {} }}",
+                statement
+            ),
+        )
+        .unwrap_err();
+        let message = error.to_string();
+        assert!(
+            message.contains("Duplicate attribute") && message.contains(name),
+            "unexpected error: {}",
+            message
+        );
+    }
+}
+
+#[test]
+fn cannot_add_conflicting_attributes_to_several_targets() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module)
+          {
+            node a
+            node b
+            attr (b) lang = "rust"
+            attr (a, b) kind = "definition", lang = "python"
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let mut graph = Graph::new();
+    let error = file
+        .execute_into(&mut graph, &tree, "pass", &config, &NoCancellation)
+        .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(message.contains("lang on"), "{}", message);
+    assert!(message.contains("Adding attributes to b"), "{}", message);
+    // Targets are updated in order, and the first target keeps its attributes.
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            kind: "definition"
+            lang: "python"
+          node 1
+            kind: "definition"
+            lang: "python"
+        "#}
+    );
+}
+
+/// Rejects edges to graph nodes without a kind, and drops internal attributes.
+struct RequireKind;
+
+impl GraphMutationHook for RequireKind {
+    fn before_edge(
+        &self,
+        _source: GraphNodeRef,
+        sink: GraphNodeRef,
+        ctx: &MutationContext,
+    ) -> Decision {
+        match ctx.graph()[sink].attributes.get("kind") {
+            Some(_) => Decision::Allow,
+            None => Decision::Reject(format!("{} has no kind", sink)),
+        }
+    }
+
+    fn before_attr(
+        &self,
+        _target: MutationTarget,
+        name: &Identifier,
+        _value: &Value,
+        _ctx: &MutationContext,
+    ) -> Decision {
+        if name.as_str() == "internal" {
+            Decision::Skip
+        } else {
+            Decision::Allow
+        }
+    }
+}
+
+fn execute_with_hook(dsl_source: &str) -> Result<String, ExecutionError> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let hook = RequireKind;
+    let config = ExecutionConfig::new(&functions, &globals).mutation_hook(&hook);
+    let graph = file.execute(&tree, "pass", &config, &NoCancellation)?;
+    let result = graph.pretty_print().to_string();
+    Ok(result)
+}
+
+#[test]
+fn can_skip_mutations_with_hook() {
+    let graph = execute_with_hook(indoc! {r#"
+      nodetype definition { kind = "definition" }
+
+      (module)
+      {
+        node a
+        node b : definition
+        edge a -> b
+        attr (a) internal = #true, name = "a"
+      }
+    "#})
+    .expect("Could not execute file");
+    assert_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+            name: "a"
+          edge 0 -> 1
+          node 1
+            kind: "definition"
+        "#}
+    );
+}
+
+#[test]
+fn cannot_violate_mutation_hook() {
+    let error = execute_with_hook(indoc! {r#"
+      (module)
+      {
+        node a
+        node b
+        edge a -> b
+      }
+    "#})
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(
+        message.contains("Graph mutation rejected: [graph node 1] has no kind"),
+        "{}",
+        message
+    );
+    assert!(message.contains("(5, 3)"), "{}", message);
+}
+
+/// Records the mutations of the graph as strings.
+#[derive(Default)]
+struct RecordMutations(Vec<String>);
+
+impl GraphObserver for RecordMutations {
+    fn on_node_created(
+        &mut self,
+        node: GraphNodeRef,
+        syntax_node: SyntaxNodeRef,
+        ctx: &MutationContext,
+    ) {
+        self.0.push(format!(
+            "node {} for {} in stanza {}",
+            node.index(),
+            ctx.graph()[syntax_node].kind(),
+            ctx.stanza_location().row + 1,
+        ));
+    }
+
+    fn on_edge_created(&mut self, source: GraphNodeRef, sink: GraphNodeRef, ctx: &MutationContext) {
+        self.0.push(format!(
+            "edge {} -> {} at line {}",
+            source.index(),
+            sink.index(),
+            ctx.location().row + 1,
+        ));
+    }
+
+    fn on_attr_added(
+        &mut self,
+        target: MutationTarget,
+        name: &Identifier,
+        value: &Value,
+        ctx: &MutationContext,
+    ) {
+        let target = match target {
+            MutationTarget::GraphNode(node) => format!("{}", node.index()),
+            MutationTarget::Edge(source, sink) => format!("{} -> {}", source.index(), sink.index()),
+        };
+        self.0.push(format!(
+            "attr ({}) {} = {} at line {}",
+            target,
+            name,
+            value,
+            ctx.location().row + 1,
+        ));
+    }
+}
+
+#[test]
+fn can_observe_mutations_in_statement_order() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let source = "pass";
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) @m
+          {
+            node a
+            node b
+            attr (a) name = "a"
+            edge a -> b
+            attr (a -> b) precedence = 1
+            attr (b) name = "b"
+            attr (a) name := "c"
+            let @m.b = b
+          }
+          (module (_) @s) @m
+          {
+            node @s.n
+            edge @m.b -> @s.n
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let observer = RefCell::new(RecordMutations::default());
+    let config = ExecutionConfig::new(&functions, &globals).observer(&observer);
+    file.execute(&tree, source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    // The observer is called in the order of the statements.
+    assert_eq!(
+        observer.into_inner().0,
+        vec![
+            "node 0 for module in stanza 1",
+            "node 1 for module in stanza 1",
+            "attr (0) name = a at line 5",
+            "edge 0 -> 1 at line 6",
+            "attr (0 -> 1) precedence = 1 at line 7",
+            "attr (1) name = b at line 8",
+            "attr (0) name = c at line 9",
+            "node 2 for module in stanza 12",
+            "edge 1 -> 2 at line 15",
+        ]
+    );
+}
+
+#[test]
+fn can_observe_mutations_of_skipped_match() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(ROLLBACK_PYTHON, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), ROLLBACK_DSL).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let observer = RefCell::new(RecordMutations::default());
+    let config = ExecutionConfig::new(&functions, &globals)
+        .observer(&observer)
+        .on_error(ErrorPolicy::SkipMatch);
+    let (graph, errors) = file
+        .execute_partial(&tree, ROLLBACK_PYTHON, &config, &NoCancellation)
+        .expect("Could not execute file");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(graph.node_count(), 3);
+    // The observer also sees the mutations of the skipped match, which are not in the graph.
+    assert_eq!(
+        observer.into_inner().0,
+        vec![
+            "node 0 for module in stanza 2",
+            "node 1 for expression_statement in stanza 5",
+            "edge 0 -> 1 at line 7",
+            "attr (1) name = good at line 8",
+            "node 2 for expression_statement in stanza 5",
+            "edge 0 -> 2 at line 7",
+            "attr (2) name = bad at line 8",
+            "node 2 for expression_statement in stanza 5",
+            "edge 0 -> 2 at line 7",
+            "attr (2) name = fine at line 8",
+        ]
+    );
+}
+
+fn canonical_hash(python_source: &str, dsl_source: &str) -> [u8; 32] {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    graph.canonical_hash()
+}
+
+#[test]
+fn reordering_stanzas_does_not_change_canonical_hash() {
+    let python_source = "def f(): pass\nclass C: pass\n";
+    let functions = indoc! {r#"
+        (module (function_definition name: (_) @name) @def) @mod {
+          node @def.node
+          attr (@def.node) name = (source-text @name), kind = "function"
+          edge @def.node -> @mod.root
+        }
+    "#};
+    let classes = indoc! {r#"
+        (module (class_definition name: (_) @name) @def) @mod {
+          node @def.node
+          attr (@def.node) name = (source-text @name), kind = "class"
+          edge @def.node -> @mod.root
+        }
+    "#};
+    let module = indoc! {r#"
+        (module) @mod {
+          node @mod.root
+          attr (@mod.root) kind = "root"
+        }
+    "#};
+    let hash = canonical_hash(
+        python_source,
+        &format!("{}{}{}", module, functions, classes),
+    );
+    assert_eq!(
+        hash,
+        canonical_hash(
+            python_source,
+            &format!("{}{}{}", module, classes, functions)
+        )
+    );
+    let changed_classes = classes.replace("\"class\"", "\"type\"");
+    assert_ne!(
+        hash,
+        canonical_hash(
+            python_source,
+            &format!("{}{}{}", module, changed_classes, functions)
+        )
+    );
+}
+
+#[test]
+fn can_re_execute_only_stanzas_affected_by_changed_globals() {
+    let python_source = "def f(): pass\nclass C: pass\n";
+    let dsl_source = indoc! {r#"
+        global ROOT_NAME
+        global CLASS_PREFIX
+
+        (module) @mod {
+          node @mod.root
+          attr (@mod.root) name = ROOT_NAME
+        }
+
+        (module (function_definition name: (_) @name) @def) @mod {
+          node @def.node
+          attr (@def.node) name = (source-text @name)
+          edge @def.node -> @mod.root
+        }
+
+        (class_definition name: (_) @name) {
+          node class
+          attr (class) name = (format "{}{}" CLASS_PREFIX (source-text @name))
+        }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let no_globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &no_globals);
+    let mut session = ExecutionSession::new(&file, &tree, python_source, &config);
+    assert!(session.is_selective());
+
+    let globals = |root: &str, prefix: &str| {
+        let mut globals = Variables::new();
+        globals.add("ROOT_NAME".into(), root.into()).unwrap();
+        globals.add("CLASS_PREFIX".into(), prefix.into()).unwrap();
+        globals
+    };
+    let mut check = |globals: &Variables, stats: SessionStats| {
+        let graph = session
+            .re_execute_with_globals(globals, &NoCancellation)
+            .expect("Cannot re-execute file");
+        let config = ExecutionConfig::new(&functions, globals);
+        let expected = file
+            .execute(&tree, python_source, &config, &NoCancellation)
+            .expect("Cannot execute file");
+        assert_eq!(graph.canonical_hash(), expected.canonical_hash());
+        assert_eq!(session.stats(), stats);
+    };
+
+    let stats =
+        |full_executions, selective_executions, stanzas_executed, stanzas_reused| SessionStats {
+            full_executions,
+            selective_executions,
+            stanzas_executed,
+            stanzas_reused,
+        };
+    check(&globals("root", "class "), stats(1, 0, 3, 0));
+    // Only the class stanza refers to CLASS_PREFIX.
+    check(&globals("root", "type "), stats(1, 1, 4, 2));
+    // The function stanza uses the root node of the module stanza, so both are re-executed.
+    check(&globals("top", "type "), stats(1, 2, 6, 3));
+    check(&globals("top", "type "), stats(1, 3, 6, 6));
+}
+
+#[test]
+fn re_executes_whole_file_if_stanzas_are_not_independent() {
+    let python_source = "def f(): pass\n";
+    let dsl_source = indoc! {r#"
+        global NAME
+
+        (function_definition) {
+          let def = (node-for "def")
+          attr (def) name = NAME
+        }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let mut globals = Variables::new();
+    globals.add("NAME".into(), "f".into()).unwrap();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let mut session = ExecutionSession::new(&file, &tree, python_source, &config);
+    assert!(!session.is_selective());
+    for _ in 0..2 {
+        let graph = session
+            .re_execute_with_globals(&globals, &NoCancellation)
+            .expect("Cannot re-execute file");
+        assert_eq!(graph.node_count(), 1);
+    }
+    assert_eq!(session.stats().full_executions, 2);
+    assert_eq!(session.stats().selective_executions, 0);
+}
+
+#[test]
+fn sessions_use_the_maximum_depth_of_the_configuration() {
+    let python_source = "pass";
+    let dsl_source = indoc! {r#"
+        (module) {
+          node n
+          if #true {
+            if #true {
+              attr (n) value = 1
+            }
+          }
+        }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).max_depth(2);
+    let mut session = ExecutionSession::new(&file, &tree, python_source, &config);
+    let err = match session.re_execute_with_globals(&globals, &NoCancellation) {
+        Ok(_) => panic!("Expected execution to fail"),
+        Err(err) => err,
+    };
+    assert!(
+        err.to_string()
+            .ends_with("Evaluation exceeded the maximum depth of 2 at (4, 5)"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn can_set_mutable_scoped_variables_in_later_stanzas() {
+    check_execution(
+        indoc! {r#"
+          x = 1
+          def f(): pass
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            var @id.kind = "unknown"
+          }
+
+          (identifier) @id
+          {
+            node guess
+            attr (guess) name = (source-text @id), guess = @id.kind
+          }
+
+          (assignment left: (identifier) @id)
+          {
+            set @id.kind = "variable"
+          }
+
+          (function_definition name: (identifier) @id)
+          {
+            set @id.kind = "function"
+          }
+
+          (identifier) @id
+          {
+            node result
+            attr (result) name = (source-text @id), kind = @id.kind
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            guess: "unknown"
+            name: "x"
+          node 1
+            guess: "unknown"
+            name: "f"
+          node 2
+            kind: "variable"
+            name: "x"
+          node 3
+            kind: "function"
+            name: "f"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_scoped_variable_that_is_not_declared_on_its_scope() {
+    let error = execute(
+        indoc! {r#"
+          x = 1
+          def f(): pass
+        "#},
+        indoc! {r#"
+          (assignment left: (identifier) @id)
+          {
+            var @id.kind = "variable"
+          }
+
+          (function_definition name: (identifier) @id)
+          {
+            set @id.kind = "function"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    assert!(
+        error.to_string().contains("Undefined variable"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn cannot_set_immutable_scoped_variable() {
+    let error = execute(
+        indoc! {r#"
+          x = 1
+        "#},
+        indoc! {r#"
+          (module) @mod
+          {
+            var @mod.kind = "module"
+          }
+
+          (identifier) @id
+          {
+            let @id.kind = "variable"
+          }
+
+          (identifier) @id
+          {
+            set @id.kind = "constant"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    assert!(
+        error
+            .to_string()
+            .contains("Cannot assign immutable variable"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn cannot_execute_with_stale_tree() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let old_source = "x = 1\n";
+    let new_source = "x = 1\ny = 2\n";
+    let tree = parser.parse(old_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        "(identifier) @id { node n attr (n) name = (source-text @id) }",
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let err = match file.execute(&tree, new_source, &config, &NoCancellation) {
+        Ok(_) => panic!("Expected execution to fail"),
+        Err(err) => err,
+    };
+    assert!(matches!(err, ExecutionError::TreeSourceMismatch(_)));
+    assert_eq!(
+        err.to_string(),
+        "Syntax tree does not match source text: syntax tree ends at byte 6, but source text has 12 bytes"
+    );
+}
+
+#[test]
+fn cannot_execute_with_unexpected_source_hash() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let old_source = "x = 1\n";
+    // same length as the old source, so only the hash can tell them apart
+    let new_source = "y = 2\n";
+    let tree = parser.parse(new_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), "(module) {}").expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config =
+        ExecutionConfig::new(&functions, &globals).expect_source_hash(source_hash(old_source));
+    let err = match file.execute(&tree, new_source, &config, &NoCancellation) {
+        Ok(_) => panic!("Expected execution to fail"),
+        Err(err) => err,
+    };
+    assert!(matches!(err, ExecutionError::TreeSourceMismatch(_)));
+
+    let config =
+        ExecutionConfig::new(&functions, &globals).expect_source_hash(source_hash(new_source));
+    file.execute(&tree, new_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+}
+
+#[test]
+fn can_execute_parsed_source() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let parsed = ParsedSource::parse(&mut parser, "x = 1\n".to_string()).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        "(identifier) @id { node n attr (n) name = (source-text @id) }",
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config =
+        ExecutionConfig::new(&functions, &globals).expect_source_hash(parsed.source_hash());
+    let graph = file
+        .execute_parsed(&parsed, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            name: "x"
+        "#}
+    );
+}
+
+const ORPHANS_PYTHON_SOURCE: &str = "x = 1\ny = 2\n";
+
+const ORPHANS_DSL_SOURCE: &str = indoc! {r#"
+  (module) @mod {
+    node @mod.root
+  }
+  (identifier) @id {
+    node @id.def
+    node @id.unused
+  }
+  (module (expression_statement (assignment left: (identifier) @id))) @mod {
+    edge @mod.root -> @id.def
+  }
+"#};
+
+#[test]
+fn can_report_orphans() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(ORPHANS_PYTHON_SOURCE, None).unwrap();
+    let file = File::from_str(tree_sitter_python::language(), ORPHANS_DSL_SOURCE)
+        .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals)
+        .provenance(true)
+        .report_orphans(true);
+    let graph = file
+        .execute(&tree, ORPHANS_PYTHON_SOURCE, &config, &NoCancellation)
+        .expect("Could not execute file");
+    let node = |index| graph.iter_nodes().nth(index).unwrap();
+    // the @id.def nodes only get an incoming edge from a later stanza, and are not orphans
+    let report = graph.orphan_report().expect("Missing orphan report");
+    assert_eq!(
+        report.groups,
+        vec![OrphanGroup {
+            stanza: Some(Location { row: 3, column: 0 }),
+            nodes: vec![node(2), node(4)],
+        }]
+    );
+    assert_eq!(report.count(), 2);
+    assert_eq!(
+        report.groups[0].to_string(),
+        "2 orphan graph nodes created by stanza at (4, 1)"
+    );
+    // reporting does not remove the orphans
+    assert_eq!(graph.iter_nodes().count(), 5);
+}
+
+#[test]
+fn can_prune_orphans() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(ORPHANS_PYTHON_SOURCE, None).unwrap();
+    let file = File::from_str(tree_sitter_python::language(), ORPHANS_DSL_SOURCE)
+        .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).prune_orphans(true);
+    let graph = file
+        .execute(&tree, ORPHANS_PYTHON_SOURCE, &config, &NoCancellation)
+        .expect("Could not execute file");
+    // without provenance, the stanza that created the orphans is not known
+    let report = graph.orphan_report().expect("Missing orphan report");
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.groups[0].stanza, None);
+    assert!(report.groups[0]
+        .nodes
+        .iter()
+        .all(|node| graph.is_removed(*node)));
+    assert_eq!(
+        graph
+            .iter_nodes()
+            .map(|node| node.index())
+            .collect::<Vec<_>>(),
+        vec![0, 1, 3]
+    );
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+          edge 0 -> 1
+          edge 0 -> 3
+          node 1
+          node 3
+        "#}
+    );
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_value(&graph).unwrap();
+        let ids = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["id"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 1, 3]);
+    }
+}
+
+const NESTED_PARENS_DSL_SOURCE: &str = indoc! {r#"
+  (assignment right: (parenthesized_expression) @paren) {
+    let @paren.depth = 1
+  }
+
+  (parenthesized_expression (parenthesized_expression) @inner) @outer {
+    let @inner.depth = (plus @outer.depth 1)
+  }
+
+  (parenthesized_expression (integer)) @paren {
+    node n
+    attr (n) depth = @paren.depth
+  }
+"#};
+
+fn nested_parens_source(depth: usize) -> String {
+    format!("x = {}1{}\n", "(".repeat(depth), ")".repeat(depth))
+}
+
+fn execute_nested_parens(depth: usize, config: ExecutionConfig) -> Result<String, ExecutionError> {
+    let python_source = nested_parens_source(depth);
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(&python_source, None).unwrap();
+    let file = File::from_str(tree_sitter_python::language(), NESTED_PARENS_DSL_SOURCE)
+        .expect("Cannot parse file");
+    let graph = file.execute(&tree, &python_source, &config, &NoCancellation)?;
+    let result = graph.pretty_print().to_string();
+    Ok(result)
+}
+
+#[test]
+fn can_execute_deeply_nested_syntax_trees() {
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    for lazy in [false, true] {
+        let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+        assert_eq!(
+            execute_nested_parens(100, config).expect("Could not execute file"),
+            "node 0\n  depth: 100\n",
+        );
+    }
+    // strict execution evaluates the chain of scoped variables one match at a time
+    let config = ExecutionConfig::new(&functions, &globals);
+    assert_eq!(
+        execute_nested_parens(2_000, config).expect("Could not execute file"),
+        "node 0\n  depth: 2000\n",
+    );
+}
+
+#[test]
+fn can_execute_deeply_nested_expressions() {
+    // the stanza block, the call, and its arguments account for one level each
+    let lists = tree_sitter_graph::MAX_NESTING_DEPTH - 3;
+    let mut expected = "2".to_string();
+    for _ in 0..lists {
+        expected = format!("[{}]", expected);
+    }
+    let nested_lists = format!(
+        "(module) {{\n  node n\n  attr (n) value = {}(plus 1 1){}\n}}\n",
+        "[".repeat(lists),
+        "]".repeat(lists),
+    );
+    // the stanza block and the innermost expression account for one level each
+    let blocks = tree_sitter_graph::MAX_NESTING_DEPTH - 2;
+    let nested_blocks = format!(
+        "(module) {{\n  node n\n{}attr (n) value = 1\n{}}}\n",
+        "if #true {\n".repeat(blocks),
+        "}\n".repeat(blocks),
+    );
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    for (dsl_source, expected) in [(nested_lists, expected), (nested_blocks, "1".into())] {
+        let file =
+            File::from_str(tree_sitter_python::language(), &dsl_source).expect("Cannot parse file");
+        for lazy in [false, true] {
+            let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+            let graph = file
+                .execute(&tree, "pass", &config, &NoCancellation)
+                .expect("Could not execute file");
+            assert_eq!(
+                graph.pretty_print().to_string(),
+                format!("node 0\n  value: {}\n", expected)
+            );
+        }
+    }
+}
+
+#[test]
+fn strict_execution_fails_cleanly_on_too_deeply_nested_files() {
+    let lists = 100;
+    let nested_lists = format!(
+        "(module) {{\n  node n\n  attr (n) value = {}(plus 1 1){}\n}}\n",
+        "[".repeat(lists),
+        "]".repeat(lists),
+    );
+    let blocks = 40;
+    let nested_blocks = format!(
+        "(module) {{\n  node n\n{}attr (n) value = 1\n{}}}\n",
+        "if #true {\n".repeat(blocks),
+        "}\n".repeat(blocks),
+    );
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    File::from_str(tree_sitter_python::language(), &nested_lists)
+        .expect_err("Parse succeeded unexpectedly");
+    // the innermost statement that was being executed is the one that sets the attribute, or the
+    // deepest `if` that can be entered
+    for (dsl_source, max_depth, location) in
+        [(nested_lists, 50, "(3, 3)"), (nested_blocks, 20, "(22, 1)")]
+    {
+        let file =
+            File::from_str_with_max_nesting_depth(tree_sitter_python::language(), &dsl_source, 200)
+                .expect("Cannot parse file");
+        let config = ExecutionConfig::new(&functions, &globals);
+        file.execute(&tree, "pass", &config, &NoCancellation)
+            .expect("Could not execute file");
+        let config = ExecutionConfig::new(&functions, &globals).max_depth(max_depth);
+        let err = match file.execute(&tree, "pass", &config, &NoCancellation) {
+            Ok(_) => panic!("Expected execution to fail"),
+            Err(err) => err,
+        };
+        let expected = format!(
+            "Caused by: Evaluation exceeded the maximum depth of {} at {}",
+            max_depth, location
+        );
+        assert!(err.to_string().ends_with(&expected), "{}", err);
+    }
+}
+
+#[test]
+fn lazy_execution_fails_cleanly_on_too_deep_evaluation() {
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(true);
+    let err = execute_nested_parens(2_000, config).expect_err("Expected execution to fail");
+    assert!(
+        err.to_string()
+            .contains("Evaluation exceeded the maximum depth of 512"),
+        "{}",
+        err
+    );
+    let config = ExecutionConfig::new(&functions, &globals)
+        .lazy(true)
+        .max_depth(50);
+    let err = execute_nested_parens(100, config).expect_err("Expected execution to fail");
+    assert!(
+        err.to_string()
+            .contains("Evaluation exceeded the maximum depth of 50 at (6, 3)"),
+        "{}",
+        err
+    );
+}
+
+const EDGE_TYPES_PYTHON_SOURCE: &str = "def f():\n  pass\nf()\n";
+
+const EDGE_TYPES_DSL_SOURCE: &str = indoc! {r#"
+  (module) @mod {
+    node @mod.scope
+    attr (@mod.scope) kind = "scope"
+  }
+
+  (module (function_definition) @def) @mod {
+    node @def.node
+    attr (@def.node) kind = "definition"
+    edge @mod.scope -> @def.node
+    attr (@mod.scope -> @def.node) kind = "defines"
+  }
+
+  (module (expression_statement) @ref) @mod {
+    node @ref.node
+    attr (@ref.node) kind = "reference"
+    edge @mod.scope -> @ref.node
+    attr (@mod.scope -> @ref.node) kind = "defines"
+  }
+"#};
+
+fn defines_schema() -> EdgeTypeSchema {
+    let mut schema = EdgeTypeSchema::new();
+    schema.add_rule("defines", ["scope"], ["definition"]);
+    schema
+}
+
+#[test]
+fn can_validate_edge_types() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(EDGE_TYPES_PYTHON_SOURCE, None).unwrap();
+    let file = File::from_str(tree_sitter_python::language(), EDGE_TYPES_DSL_SOURCE)
+        .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, EDGE_TYPES_PYTHON_SOURCE, &config, &NoCancellation)
+        .expect("Could not execute file");
+
+    let violations = graph.validate_edge_types(&defines_schema());
+    assert_eq!(violations.len(), 1);
+    let violation = &violations[0];
+    assert_eq!(violation.source.kind.as_deref(), Some("scope"));
+    assert_eq!(violation.sink.kind.as_deref(), Some("reference"));
+    assert_eq!(
+        violation.sink.location,
+        Some(Location { row: 2, column: 0 })
+    );
+    assert!(violation.source_allowed());
+    assert!(!violation.sink_allowed());
+    assert_eq!(
+        violation.to_string(),
+        "defines edge 0 -> 2 goes from scope node 0 at (1, 1) to reference node 2 at (3, 1), but must go from scope to definition"
+    );
+
+    // edges with kinds that have no rule are not constrained
+    let mut schema = EdgeTypeSchema::new();
+    schema.add_rule("references", ["reference"], ["definition"]);
+    assert_eq!(graph.validate_edge_types(&schema), vec![]);
+
+    #[cfg(feature = "serde")]
+    {
+        let schema = EdgeTypeSchema::from_json(
+            r#"{ "edges": { "defines": { "sources": ["scope"], "sinks": ["definition"] } } }"#,
+        )
+        .expect("Cannot parse schema");
+        assert_eq!(schema, defines_schema());
+        assert!(
+            EdgeTypeSchema::from_json(r#"{ "edges": { "defines": { "sources": [] } } }"#).is_err()
+        );
+    }
+}
+
+#[test]
+fn can_enforce_edge_types_with_hook() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(EDGE_TYPES_PYTHON_SOURCE, None).unwrap();
+    let file = File::from_str(tree_sitter_python::language(), EDGE_TYPES_DSL_SOURCE)
+        .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let schema = defines_schema();
+    let hook = schema.as_hook();
+    let config = ExecutionConfig::new(&functions, &globals).mutation_hook(&hook);
+    let message = match file.execute(&tree, EDGE_TYPES_PYTHON_SOURCE, &config, &NoCancellation) {
+        Ok(_) => panic!("Execution succeeded unexpectedly"),
+        Err(error) => error.to_string(),
+    };
+    assert!(
+        message.contains("Graph mutation rejected: defines edge 0 -> 2 goes from scope node 0 to reference node 2, but must go from scope to definition"),
+        "{}",
+        message
+    );
+    assert!(message.contains("(17, 3)"), "{}", message);
+}
+
+const QUERY_CACHE_PYTHON_SOURCE: &str = indoc! {r#"
+    import a
+    x = 1
+    y = "s"
+    def f(z):
+        return x + z
+"#};
+
+const QUERY_CACHE_DSL_SOURCES: [&str; 3] = [
+    indoc! {r#"
+        (identifier) @id {
+          node n
+          attr (n) name = (source-text @id), file = "first"
+        }
+        (assignment left: (identifier) @left) {
+          node n
+          attr (n) assigned = (source-text @left)
+        }
+        (string) @s {
+          node n
+          attr (n) string = (source-text @s)
+        }
+    "#},
+    indoc! {r#"
+        ; the same query as in the first file, formatted differently
+        (assignment
+          left: (identifier) @left) {
+          node n
+          attr (n) assigned_again = (source-text @left)
+        }
+        ((identifier) @id
+         (#eq? @id "x")) {
+          node n
+          attr (n) x = (source-text @id)
+        }
+    "#},
+    indoc! {r#"
+        (identifier) @id {
+          node n
+          attr (n) name = (source-text @id), file = "third"
+        }
+        ((identifier) @id
+         (#eq? @id "x")) {
+          node n
+          attr (n) x_again = (source-text @id)
+        }
+        (function_definition name: (identifier) @name) {
+          node n
+          attr (n) function = (source-text @name)
+        }
+    "#},
+];
+
+fn execute_with_query_cache(
+    tree: &tree_sitter::Tree,
+    source: &str,
+    lazy: bool,
+    cache: Option<&QueryCache>,
+) -> Vec<String> {
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    QUERY_CACHE_DSL_SOURCES
+        .iter()
+        .map(|dsl_source| {
+            let file = File::from_str(tree_sitter_python::language(), dsl_source)
+                .expect("Cannot parse file");
+            let mut config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+            if let Some(cache) = cache {
+                config = config.query_cache(cache);
+            }
+            file.execute(tree, source, &config, &NoCancellation)
+                .expect("Could not execute file")
+                .pretty_print()
+                .to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn can_share_query_matches_between_files() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(QUERY_CACHE_PYTHON_SOURCE, None).unwrap();
+    let expected = execute_with_query_cache(&tree, QUERY_CACHE_PYTHON_SOURCE, false, None);
+
+    let cache = QueryCache::new();
+    let graphs = execute_with_query_cache(&tree, QUERY_CACHE_PYTHON_SOURCE, false, Some(&cache));
+    assert_eq!(graphs, expected);
+    assert_eq!((cache.hits(), cache.misses()), (3, 5));
+
+    // executing again only uses cached matches
+    let graphs = execute_with_query_cache(&tree, QUERY_CACHE_PYTHON_SOURCE, false, Some(&cache));
+    assert_eq!(graphs, expected);
+    assert_eq!((cache.hits(), cache.misses()), (11, 5));
+
+    // lazy execution does not use the cache
+    let expected = execute_with_query_cache(&tree, QUERY_CACHE_PYTHON_SOURCE, true, None);
+    let graphs = execute_with_query_cache(&tree, QUERY_CACHE_PYTHON_SOURCE, true, Some(&cache));
+    assert_eq!(graphs, expected);
+    assert_eq!((cache.hits(), cache.misses()), (11, 5));
+}
+
+#[test]
+fn query_cache_does_not_share_matches_between_trees() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(QUERY_CACHE_PYTHON_SOURCE, None).unwrap();
+    let cache = QueryCache::new();
+    execute_with_query_cache(&tree, QUERY_CACHE_PYTHON_SOURCE, false, Some(&cache));
+
+    let other_source = "x = 2\nb = x\n";
+    let other_tree = parser.parse(other_source, None).unwrap();
+    let expected = execute_with_query_cache(&other_tree, other_source, false, None);
+    let graphs = execute_with_query_cache(&other_tree, other_source, false, Some(&cache));
+    assert_eq!(graphs, expected);
+    assert_eq!((cache.hits(), cache.misses()), (6, 10));
+}
+
+#[test]
+fn query_cache_does_not_share_matches_with_reparsed_trees() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let source = "aa = 1\npass\npass\n";
+    let mut tree = parser.parse(source, None).unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let cache = QueryCache::new();
+    let config = ExecutionConfig::new(&functions, &globals).query_cache(&cache);
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        "(identifier) @n { node n attr (n) name = (source-text @n) }",
+    )
+    .expect("Cannot parse file");
+    let names = |tree: &tree_sitter::Tree, source: &str| {
+        let graph = file
+            .execute(tree, source, &config, &NoCancellation)
+            .expect("Could not execute file");
+        graph
+            .iter_nodes()
+            .map(|node| {
+                graph[node]
+                    .attributes
+                    .get(&Identifier::from("name"))
+                    .unwrap()
+                    .clone()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&tree, source), vec![Value::from("aa")]);
+
+    // the reparsed tree shares the subtree of the first statement with the original one
+    let edited_source = "aa = 1\npass\npass\ndd = 4\n";
+    tree.edit(&tree_sitter::InputEdit {
+        start_byte: 17,
+        old_end_byte: 17,
+        new_end_byte: 24,
+        start_position: tree_sitter::Point::new(3, 0),
+        old_end_position: tree_sitter::Point::new(3, 0),
+        new_end_position: tree_sitter::Point::new(4, 0),
+    });
+    let edited_tree = parser.parse(edited_source, Some(&tree)).unwrap();
+    assert_eq!(
+        names(&edited_tree, edited_source),
+        vec![Value::from("aa"), Value::from("dd")]
+    );
+    assert_eq!((cache.hits(), cache.misses()), (0, 2));
+}
+
+#[test]
+fn query_cache_distinguishes_whitespace_in_strings() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let source = "a = \"x  y\"\nb = \"x y\"\n";
+    let tree = parser.parse(source, None).unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let cache = QueryCache::new();
+    let config = ExecutionConfig::new(&functions, &globals).query_cache(&cache);
+    let count = |pattern: &str| {
+        let dsl_source = format!(
+            "((string) @s (#eq? @s \"{}\")) {{ node n attr (n) text = (source-text @s) }}",
+            pattern.replace('"', "\\\"")
+        );
+        let file =
+            File::from_str(tree_sitter_python::language(), &dsl_source).expect("Cannot parse file");
+        file.execute(&tree, source, &config, &NoCancellation)
+            .expect("Could not execute file")
+            .node_count()
+    };
+    assert_eq!(count("\"x  y\""), 1);
+    assert_eq!(count("\"x y\""), 1);
+    assert_eq!(count("\"x   y\""), 0);
+    assert_eq!(cache.misses(), 3);
+}
+
+#[test]
+fn can_attach_graph_nodes_to_anonymous_syntax_nodes() {
+    let python_source = "x = a + b\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (binary_operator left: (_) @left operator: "+" @op right: (_) @right)
+          {
+            node @left.node
+            node @right.node
+            node @op.node
+            let @op.text = (source-text @op)
+            attr (@op.node) operator = @op.text, syntax = @op, kind = (node-type @op)
+            attr (@op.node) start_column = (start-column @op)
+            edge @op.node -> @left.node
+            edge @op.node -> @right.node
+            attr (@op.node -> @left.node) side = "left"
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+          node 1
+          node 2
+            kind: "+"
+            operator: "+"
+            start_column: 6
+            syntax: [syntax node "+" (1, 7)]
+          edge 2 -> 0
+            side: "left"
+          edge 2 -> 1
+        "#}
+    );
+    #[cfg(feature = "serde")]
+    {
+        use tree_sitter_graph::graph::SerializationConfig;
+
+        let config = SerializationConfig::new(python_source).include_spans(true);
+        let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+        let span = serde_json::json!({
+            "kind": "+",
+            "named": false,
+            "start": { "byte": 6, "row": 0, "column": 6 },
+            "end": { "byte": 7, "row": 0, "column": 7 },
+        });
+        assert_eq!(json[2]["span"], span);
+        assert_eq!(json[2]["attrs"]["syntax"]["span"], span);
+        assert_eq!(json[0]["span"]["kind"], "identifier");
+        assert!(json[0]["span"].get("named").is_none());
+        let deserialized = serde_json::from_value::<Graph>(json).unwrap();
+        assert_eq!(
+            deserialized.pretty_print().to_string(),
+            graph.pretty_print().to_string()
+        );
+    }
+}
+
+fn match_report_fixture(name: &str) -> String {
+    let path = format!(
+        "{}/tests/fixtures/match_report/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn can_report_matches_of_stanzas() {
+    let python_source = match_report_fixture("source.py");
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(&python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        &match_report_fixture("rules.tsg"),
+    )
+    .unwrap();
+
+    let report = file.match_report(&tree, &python_source, None);
+    let matches = report
+        .stanzas
+        .iter()
+        .map(|stanza| {
+            stanza
+                .matches
+                .iter()
+                .map(|mat| {
+                    let captures = mat
+                        .captures
+                        .iter()
+                        .map(|capture| {
+                            format!("@{} {} {}", capture.name, capture.kind, capture.location)
+                        })
+                        .collect::<Vec<_>>();
+                    (mat.location.to_string(), captures, mat.nodes.clone())
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        matches,
+        vec![
+            vec![(
+                "1:1-2:8".to_string(),
+                vec!["@name identifier 1:5-1:6".to_string()],
+                None
+            )],
+            vec![(
+                "2:5-2:8".to_string(),
+                vec!["@callee identifier 2:5-2:6".to_string()],
+                None
+            )],
+            vec![],
+        ]
+    );
+}
+
+#[test]
+fn can_report_graph_nodes_created_by_matches() {
+    let python_source = match_report_fixture("source.py");
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(&python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        &match_report_fixture("rules.tsg"),
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).provenance(true);
+    let graph = file
+        .execute(&tree, &python_source, &config, &NoCancellation)
+        .unwrap();
+
+    let report = file.match_report(&tree, &python_source, Some(&graph));
+    let nodes = report
+        .stanzas
+        .iter()
+        .flat_map(|stanza| stanza.matches.iter().map(|mat| mat.nodes.clone()))
+        .collect::<Vec<_>>();
+    let node = |index| graph.iter_nodes().nth(index).unwrap();
+    assert_eq!(nodes, vec![Some(vec![node(0)]), Some(vec![node(1)])]);
+
+    #[cfg(feature = "serde")]
+    {
+        let expected: serde_json::Value =
+            serde_json::from_str(&match_report_fixture("expected.json")).unwrap();
+        assert_eq!(serde_json::to_value(&report).unwrap(), expected);
+    }
+}
+
+#[test]
+#[cfg(feature = "resource-usage")]
+fn can_account_for_resource_usage() {
+    let source = "f(xy)\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) name = (source-text @id), parts = [1, 2, 3]
+            scan (source-text @id) {
+              "[a-z]" {
+                attr (@id.node) letter = #true
+              }
+            }
+          }
+          (call function: (identifier) @f arguments: (argument_list (identifier) @x))
+          {
+            edge @f.node -> @x.node
+            edge @f.node -> @x.node
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let (_, usage) = file
+        .execute_with_usage(&tree, source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    assert_eq!(usage.matches, 3);
+    assert_eq!(usage.statements, 11);
+    assert_eq!(usage.graph_nodes, 2);
+    assert_eq!(usage.edges, 1);
+    assert_eq!(usage.attributes, 7);
+    assert_eq!(usage.scan_iterations, 3);
+    assert_eq!(usage.regex_bytes_scanned, 4);
+    assert_eq!(
+        usage.function_calls.into_iter().collect::<Vec<_>>(),
+        vec![(Identifier::from("source-text"), 4)]
+    );
+    assert_eq!(usage.peak_list_length, 3);
+}
+
+#[test]
+#[cfg(feature = "resource-usage")]
+fn can_collect_statistics_per_stanza() {
+    let source = "f(xy)\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            scan (source-text @id) {
+              "[a-z]" {
+                attr (@id.node) letter = #true
+              }
+            }
+          }
+          (call function: (identifier) @f arguments: (argument_list (identifier) @x))
+          {
+            edge @f.node -> @x.node
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let (_, usage) = file
+        .execute_with_usage(&tree, source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    let stanzas = &usage.statistics.stanzas;
+    assert_eq!(stanzas.len(), 2);
+    assert_eq!(stanzas[0].index, 0);
+    assert_eq!(stanzas[0].location, Location { row: 0, column: 0 });
+    assert_eq!(
+        (
+            stanzas[0].matches,
+            stanzas[0].graph_nodes,
+            stanzas[0].edges,
+            stanzas[0].scan_iterations
+        ),
+        (2, 2, 0, 3)
+    );
+    assert_eq!(stanzas[1].index, 1);
+    assert_eq!(stanzas[1].location, Location { row: 9, column: 0 });
+    assert_eq!(
+        (
+            stanzas[1].matches,
+            stanzas[1].graph_nodes,
+            stanzas[1].edges,
+            stanzas[1].scan_iterations
+        ),
+        (1, 0, 1, 0)
+    );
+    let table = usage.statistics.to_string();
+    let mut lines = table.lines();
+    assert!(lines.next().unwrap().starts_with("stanza"));
+    assert_eq!(lines.count(), 2);
+}
+
+#[test]
+#[cfg(feature = "resource-usage")]
+fn can_account_for_resource_usage_of_failed_execution() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) kind = "module"
+            attr (n) kind = "file"
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let mut graph = Graph::new();
+    let mut usage = Default::default();
+    file.execute_into_with_usage(
+        &mut graph,
+        &tree,
+        "pass",
+        &config,
+        &NoCancellation,
+        &mut usage,
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    assert_eq!(usage.matches, 1);
+    assert_eq!(usage.statements, 3);
+    assert_eq!(usage.graph_nodes, 1);
+    assert_eq!(usage.attributes, 1);
+}
+
+#[test]
+fn can_locate_failing_statements() {
+    let python_source = indoc! {r#"
+      x = 1
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (_) @left)
+      {
+        node n
+        attr (n) name = (plus (source-text @left) 1)
+      }
+    "#};
+    let error = execute(python_source, dsl_source).expect_err("Execution succeeded unexpectedly");
+    assert!(
+        matches!(error.root_cause(), ExecutionError::WrongArgumentType { .. }),
+        "unexpected error: {}",
+        error
+    );
+    let statement = error
+        .statement_context()
+        .expect("Missing statement context");
+    assert_eq!(statement.statement_location, Location { row: 3, column: 2 });
+    assert_eq!(statement.stanza_location, Location { row: 0, column: 0 });
+    assert_eq!(statement.source_location, Location { row: 0, column: 0 });
+    assert_eq!(
+        error
+            .display_pretty(
+                Path::new("test.py"),
+                python_source,
+                Path::new("test.tsg"),
+                dsl_source
+            )
+            .to_string(),
+        concat!(
+            "    0: Error executing statement attr (n) name = (plus (source-text @left) 1) at (4, 3)\n",
+            "       test.tsg:4:3:\n",
+            "       4 |   attr (n) name = (plus (source-text @left) 1)\n",
+            "         |   ^\n",
+            "       in stanza\n",
+            "       test.tsg:1:1:\n",
+            "       1 | (assignment left: (_) @left)\n",
+            "         | ^\n",
+            "       matching (assignment) node\n",
+            "       test.py:1:1:\n",
+            "       1 | x = 1\n",
+            "         | ^\n",
+            "    1: Error calling function plus\n",
+            "       test.tsg:4:19:\n",
+            "       4 |   attr (n) name = (plus (source-text @left) 1)\n",
+            "         |                   ^\n",
+            "    2: Function plus expects argument 1 to be number, got string\n",
+        )
+    );
+}
+
+#[test]
+fn can_cancel_execution_after_deadline() {
+    let python_source = format!("x = \"{}\"\n", "a".repeat(1_000_000));
+    let dsl_source = indoc! {r#"
+      (module) @m
+      {
+        node @m.node
+      }
+
+      (module) @m
+      {
+        scan (source-text @m) {
+          "a" {
+            node n
+            edge @m.node -> n
+          }
+        }
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(&python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let deadline = Duration::from_millis(50);
+    for lazy in [false, true] {
+        let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+        let start = Instant::now();
+        let result = file.execute(&tree, &python_source, &config, &CancelAfter::new(deadline));
+        let elapsed = start.elapsed();
+        let error = match result {
+            Ok(_) => panic!("Execution succeeded unexpectedly"),
+            Err(error) => error,
+        };
+        assert!(
+            matches!(error.root_cause(), ExecutionError::Cancelled(_)),
+            "unexpected error: {}",
+            error
+        );
+        assert!(
+            elapsed < deadline * 10,
+            "cancelled after {:?}, lazy: {}",
+            elapsed,
+            lazy
+        );
+        // lazy execution processes the matches of both stanzas before completing either
+        let expected = ExecutionProgress {
+            stanzas_completed: if lazy { 0 } else { 1 },
+            matches_processed: 1,
+        };
+        assert_eq!(error.progress(), Some(&expected), "lazy: {}", lazy);
+    }
+}
+
+#[test]
+fn can_combine_executions_against_several_sources() {
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          global ROOT
+
+          (function_definition name: (identifier) @name) @def
+          {
+            node @def.node
+            attr (@def.node) name = (source-text @name), root = ROOT, self = @def.node
+            edge ROOT -> @def.node
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let sources = ["def f():\n  pass\n", "def g():\n  pass\n"];
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let trees = sources
+        .iter()
+        .map(|source| parser.parse(source, None).unwrap())
+        .collect::<Vec<_>>();
+    let functions = Functions::stdlib();
+    let expected = indoc! {r#"
+      node 0
+        kind: "root"
+      edge 0 -> 1
+      edge 0 -> 2
+      node 1
+        name: "f"
+        root: [graph node 0]
+        self: [graph node 1]
+      node 2
+        name: "g"
+        root: [graph node 0]
+        self: [graph node 2]
+    "#};
+
+    // Executing into one graph, which shares a root node
+    let mut graph = Graph::new();
+    let root = graph.add_graph_node();
+    graph[root]
+        .attributes
+        .add(Identifier::from("kind"), "root")
+        .unwrap();
+    let mut globals = Variables::new();
+    globals.add(Identifier::from("ROOT"), root.into()).unwrap();
+    let config = ExecutionConfig::new(&functions, &globals);
+    for (tree, source) in trees.iter().zip(sources) {
+        file.execute_into(&mut graph, tree, source, &config, &NoCancellation)
+            .expect("Cannot execute file");
+    }
+    assert_eq!(graph.pretty_print().to_string(), expected);
+
+    // Merging graphs that were executed separately, which each have their own root node
+    let mut merged = Graph::new();
+    let root = merged.add_graph_node();
+    merged[root]
+        .attributes
+        .add(Identifier::from("kind"), "root")
+        .unwrap();
+    for (tree, source) in trees.iter().zip(sources) {
+        let mut graph = Graph::new();
+        let other_root = graph.add_graph_node();
+        let mut globals = Variables::new();
+        globals
+            .add(Identifier::from("ROOT"), other_root.into())
+            .unwrap();
+        let config = ExecutionConfig::new(&functions, &globals);
+        file.execute_into(&mut graph, tree, source, &config, &NoCancellation)
+            .expect("Cannot execute file");
+        let mapping = merged.merge(graph).expect("Cannot merge graphs");
+        merged
+            .alias(
+                root,
+                mapping[other_root],
+                ConflictPolicy::Error,
+                SelfEdgePolicy::Drop,
+            )
+            .expect("Cannot alias root nodes");
+    }
+    // The aliased root nodes are left as removed nodes, so the merged graph is only the same up
+    // to the numbering of its nodes.
+    assert_eq!(merged.canonical_hash(), graph.canonical_hash());
+}
+
+#[test]
+fn can_execute_file_from_several_threads() {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<File>();
+
+    let dsl_source = indoc! {r#"
+      (function_definition name: (identifier) @name) @def
+      {
+        node @def.node
+        attr (@def.node) name = (source-text @name)
+        scan (source-text @name) {
+          "^f([0-9]+)$" {
+            attr (@def.node) number = $1
+          }
+        }
+      }
+    "#};
+    let file = Arc::new(
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file"),
+    );
+    let threads = (0..4)
+        .map(|i| {
+            let file = file.clone();
+            thread::spawn(move || {
+                let python_source = format!("def f{}():\n  pass\n", i);
+                let mut parser = Parser::new();
+                parser.set_language(tree_sitter_python::language()).unwrap();
+                let tree = parser.parse(&python_source, None).unwrap();
+                let functions = Functions::stdlib();
+                let globals = Variables::new();
+                let mut graphs = Vec::new();
+                for lazy in [false, true] {
+                    let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+                    let graph = file
+                        .execute(&tree, &python_source, &config, &NoCancellation)
+                        .expect("Cannot execute file");
+                    graphs.push(graph.pretty_print().to_string());
+                }
+                graphs
+            })
+        })
+        .collect::<Vec<_>>();
+    for (i, thread) in threads.into_iter().enumerate() {
+        let expected = format!(
+            indoc! {r#"
+              node 0
+                name: "f{0}"
+                number: "{0}"
+            "#},
+            i
+        );
+        assert_eq!(thread.join().unwrap(), vec![expected.clone(), expected]);
+    }
+}
@@ -0,0 +1,20 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use std::process::Command;
+
+/// Ensures that the core of the library keeps building without any optional features.
+#[test]
+fn can_build_without_default_features() {
+    let status = Command::new(env!("CARGO"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["check", "--lib", "--no-default-features", "--target-dir"])
+        .arg(concat!(env!("CARGO_TARGET_TMPDIR"), "/no-default-features"))
+        .status()
+        .expect("Cannot run cargo");
+    assert!(status.success(), "cargo check --no-default-features failed");
+}
@@ -8,7 +8,18 @@
 use indoc::indoc;
 use tree_sitter::Parser;
 use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::stdlib;
+use tree_sitter_graph::functions::Arguments;
+use tree_sitter_graph::functions::BatchFunction;
+use tree_sitter_graph::functions::Capabilities;
+use tree_sitter_graph::functions::Function;
 use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::functions::Parameters;
+use tree_sitter_graph::functions::ValueFunction;
+use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::graph::Value;
+use tree_sitter_graph::sig;
+use tree_sitter_graph::ErrorPolicy;
 use tree_sitter_graph::ExecutionConfig;
 use tree_sitter_graph::ExecutionError;
 use tree_sitter_graph::Identifier;
@@ -105,6 +116,458 @@ fn cannot_eq_bool_and_string() {
     );
 }
 
+#[test]
+fn can_eq_floats() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) eq = (eq 0.5 0.50)
+            attr (n) ne = (eq 0.5 0.25)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            eq: #true
+            ne: #false
+        "#},
+    );
+}
+
+#[test]
+fn cannot_eq_integer_and_float() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) eq = (eq 1 1.0)
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_add_integers_and_floats() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) floats = (plus 0.25 0.5)
+            attr (n) integers = (plus 1 2)
+            attr (n) mixed = (plus 1 0.5 2)
+            attr (n) whole = (plus 1.0 2)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            floats: 0.75
+            integers: 3
+            mixed: 3.5
+            whole: 3.0
+        "#},
+    );
+}
+
+#[test]
+fn cannot_add_non_numbers() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) sum = (plus 0.5 "1")
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_subtract_below_zero() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) difference = (minus 1 2)
+            attr (n) float = (minus 1 1.5)
+            attr (n) negated = (minus -3)
+            attr (n) repeated = (minus 10 4 8)
+            attr (n) sum = (plus -2 1)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            difference: -1
+            float: -0.5
+            negated: 3
+            repeated: -2
+            sum: -1
+        "#},
+    );
+}
+
+#[test]
+fn can_eq_integers_with_mixed_signs() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) eq = (eq -1 (minus 1 2))
+            attr (n) ne = (eq -1 1)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            eq: #true
+            ne: #false
+        "#},
+    );
+}
+
+#[test]
+fn cannot_overflow_integers() {
+    for (expression, message) in [
+        (
+            "(plus 9223372036854775807 1)",
+            "Integer overflow in plus of 9223372036854775807 and 1",
+        ),
+        (
+            "(minus -9223372036854775808 1)",
+            "Integer overflow in minus of -9223372036854775808 and 1",
+        ),
+        (
+            "(minus -9223372036854775808)",
+            "Integer overflow in minus of 0 and -9223372036854775808",
+        ),
+    ] {
+        let error = execute(
+            "pass",
+            &format!("(module) {{ node n attr (n) value = {} }}", expression),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains(message),
+            "unexpected error: {}",
+            error
+        );
+    }
+}
+
+#[test]
+fn can_convert_between_integers_and_strings() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) decimal = (parse-int "-42")
+            attr (n) hex = (parse-int "ff" 16)
+            attr (n) max = (parse-int "9223372036854775807")
+            attr (n) padded = (int-to-string 7 3)
+            attr (n) padded_negative = (int-to-string -7 3)
+            attr (n) unpadded = (int-to-string 1234 2)
+            attr (n) code_point = (char-to-int "é")
+            attr (n) character = (int-to-char (plus (char-to-int "a") 1))
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            character: "b"
+            code_point: 233
+            decimal: -42
+            hex: 255
+            max: 9223372036854775807
+            padded: "007"
+            padded_negative: "-07"
+            unpadded: "1234"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_convert_invalid_integers_and_strings() {
+    for (expression, message) in [
+        (
+            r#"(parse-int "")"#,
+            r#"Cannot parse "" as an integer in radix 10"#,
+        ),
+        (
+            r#"(parse-int "12a")"#,
+            r#"Cannot parse "12a" as an integer in radix 10"#,
+        ),
+        (
+            r#"(parse-int " 12")"#,
+            r#"Cannot parse " 12" as an integer in radix 10"#,
+        ),
+        (
+            r#"(parse-int "9223372036854775808")"#,
+            r#"Cannot parse "9223372036854775808" as an integer in radix 10"#,
+        ),
+        (r#"(parse-int "12" 37)"#, "Radix 37 is not between 2 and 36"),
+        ("(int-to-string 1 -1)", "Width -1 is negative"),
+        (
+            r#"(char-to-int "")"#,
+            r#"Expected a single character, got """#,
+        ),
+        (
+            r#"(char-to-int "ab")"#,
+            r#"Expected a single character, got "ab""#,
+        ),
+        ("(int-to-char -1)", "-1 is not a Unicode scalar value"),
+        ("(int-to-char 55296)", "55296 is not a Unicode scalar value"),
+    ] {
+        let error = execute(
+            "pass",
+            &format!("(module) {{ node n attr (n) value = {} }}", expression),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains(message),
+            "unexpected error: {}",
+            error
+        );
+    }
+}
+
+#[test]
+fn can_manipulate_strings() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) split = (split "a,b,,c" ",")
+            attr (n) split_chars = (split "héllo" "")
+            attr (n) split_empty = (split "" "")
+            attr (n) split_unicode = (split "α→β→γ" "→")
+            attr (n) trimmed = (trim "  \tspaced\n ")
+            attr (n) lower = (to-lower "ÀBC")
+            attr (n) upper = (to-upper "straße")
+            attr (n) starts = (starts-with "éclair" "é")
+            attr (n) ends = (ends-with "éclair" "ir")
+            attr (n) contains = (contains "éclair" "cla")
+            attr (n) not_contains = (contains "éclair" "x")
+            attr (n) index = (index-of "αβγβ" "β")
+            attr (n) missing_index = (index-of "αβγ" "x")
+            attr (n) substring = (substring "αβγδ" 1 3)
+            attr (n) suffix = (substring "αβγδ" 2)
+            attr (n) empty_substring = (substring "αβγδ" 4 4)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            contains: #true
+            empty_substring: ""
+            ends: #true
+            index: 1
+            lower: "àbc"
+            missing_index: #null
+            not_contains: #false
+            split: ["a", "b", "", "c"]
+            split_chars: ["h", "é", "l", "l", "o"]
+            split_empty: []
+            split_unicode: ["α", "β", "γ"]
+            starts: #true
+            substring: "βγ"
+            suffix: "γδ"
+            trimmed: "spaced"
+            upper: "STRASSE"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_manipulate_strings_with_invalid_arguments() {
+    for (expression, message) in [
+        (
+            r#"(substring "αβγ" 1 4)"#,
+            r#"Range 1..4 is not within the 3 characters of "αβγ""#,
+        ),
+        (
+            r#"(substring "αβγ" -1)"#,
+            r#"Range -1..3 is not within the 3 characters of "αβγ""#,
+        ),
+        (
+            r#"(substring "αβγ" 2 1)"#,
+            r#"Range 2..1 is not within the 3 characters of "αβγ""#,
+        ),
+        (r#"(split "abc")"#, "split"),
+        (r#"(split "abc" 1)"#, "split"),
+        (r#"(starts-with 1 "a")"#, "starts-with"),
+        (r#"(substring "abc" "1")"#, "substring"),
+    ] {
+        let error = execute(
+            "pass",
+            &format!("(module) {{ node n attr (n) value = {} }}", expression),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains(message),
+            "unexpected error: {}",
+            error
+        );
+    }
+}
+
+#[test]
+fn can_manipulate_lists_and_sets() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) concat = (concat-lists [1, 2] [] [3])
+            attr (n) reverse = (reverse [1, "a", #true])
+            attr (n) sort = (sort [2.5, "b", 3, #null, "a", [1], 1, #false])
+            attr (n) nth = (nth ["a", "b", "c"] 1)
+            attr (n) first = (first ["a", "b", "c"])
+            attr (n) last = (last ["a", "b", "c"])
+            attr (n) first_of_empty = (first [])
+            attr (n) last_of_empty = (last [])
+            attr (n) is_last = (last? ["a", "b", "c"] 2)
+            attr (n) is_not_last = (last? ["a", "b", "c"] 1)
+            attr (n) is_last_of_empty = (last? [] -1)
+            attr (n) list_contains = (contains? [1, "a"] "a")
+            attr (n) list_not_contains = (contains? [1, "a"] "b")
+            attr (n) set_contains = (contains? {1, 2} 2)
+            attr (n) length = (length ["a", "b"])
+            attr (n) set_add = (set-add {2, 3} 1 3)
+            attr (n) set_union = (set-union {1, 2} {2, 3} {})
+            attr (n) set_intersect = (set-intersect {1, 2, 3} {2, 3, 4} {3, 2})
+            attr (n) set_difference = (set-difference {1, 2, 3} {2} {4})
+            attr (n) to_set = (to-set ["b", "a", "b"])
+            attr (n) to_list = (to-list {"b", "a"})
+            attr (n) list_to_list = (to-list ["b", "a"])
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            concat: [1, 2, 3]
+            first: "a"
+            first_of_empty: #null
+            is_last: #true
+            is_last_of_empty: #false
+            is_not_last: #false
+            last: "c"
+            last_of_empty: #null
+            length: 2
+            list_contains: #true
+            list_not_contains: #false
+            list_to_list: ["b", "a"]
+            nth: "b"
+            reverse: [#true, "a", 1]
+            set_add: {1, 2, 3}
+            set_contains: #true
+            set_difference: {1, 3}
+            set_intersect: {2, 3}
+            set_union: {1, 2, 3}
+            sort: [#null, #false, 1, 3, 2.5, "a", "b", [1]]
+            to_list: ["a", "b"]
+            to_set: {"a", "b"}
+        "#},
+    );
+}
+
+#[test]
+fn cannot_manipulate_lists_and_sets_with_invalid_arguments() {
+    for (expression, message) in [
+        (
+            r#"(nth ["a", "b"] 2)"#,
+            "Index 2 is not within the 2 elements of the list",
+        ),
+        (
+            r#"(nth ["a", "b"] -1)"#,
+            "Index -1 is not within the 2 elements of the list",
+        ),
+        (r#"(nth {"a"} 0)"#, "nth"),
+        (r#"(last? ["a"] "0")"#, "last?"),
+        (r#"(set-add ["a"] "b")"#, "set-add"),
+        (r#"(set-union {"a"} ["b"])"#, "set-union"),
+        (r#"(to-set "ab")"#, "Expected a list"),
+    ] {
+        let error = execute(
+            "pass",
+            &format!("(module) {{ node n attr (n) value = {} }}", expression),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains(message),
+            "unexpected error: {}",
+            error
+        );
+    }
+}
+
+#[test]
+fn can_manipulate_maps() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            let map = {name: "f", arity: 2}
+            attr (n) map = map
+            attr (n) empty = {:}
+            attr (n) get = (map-get map "name")
+            attr (n) get_missing = (map-get map "kind")
+            attr (n) set = (map-set map "arity" 3)
+            attr (n) set_new = (map-set {:} "kind" "function")
+            attr (n) keys = (map-keys map)
+            attr (n) contains = (map-contains? map "arity")
+            attr (n) not_contains = (map-contains? map "kind")
+            attr (n) eq = (eq map {arity: 2, name: "f"})
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            contains: #true
+            empty: {:}
+            eq: #true
+            get: "f"
+            get_missing: #null
+            keys: ["arity", "name"]
+            map: {arity: 2, name: "f"}
+            not_contains: #false
+            set: {arity: 3, name: "f"}
+            set_new: {kind: "function"}
+        "#},
+    );
+}
+
+#[test]
+fn cannot_manipulate_maps_with_invalid_arguments() {
+    for (expression, message) in [
+        (r#"(map-get {"a"} "a")"#, "map-get"),
+        (r#"(map-set {a: 1} 1 2)"#, "map-set"),
+        (r#"(map-keys [])"#, "map-keys"),
+    ] {
+        let error = execute(
+            "pass",
+            &format!("(module) {{ node n attr (n) value = {} }}", expression),
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains(message),
+            "unexpected error: {}",
+            error
+        );
+    }
+}
+
 #[test]
 fn can_format_string_null_and_escaped_braces() {
     check_execution(
@@ -232,3 +695,1053 @@ fn can_join_list_without_separator() {
         "#},
     );
 }
+
+#[test]
+fn can_declare_function_signatures() {
+    assert_eq!(
+        "(string, string, string) -> string",
+        sig!(string, string, string -> string).to_string()
+    );
+    assert_eq!(
+        "(list, string?) -> string",
+        sig!(list, string? -> string).to_string()
+    );
+    assert_eq!(
+        "(string, any...) -> string",
+        sig!(string, any... -> string).to_string()
+    );
+    assert_eq!("() -> graph_node", sig!(-> graph_node).to_string());
+    assert_eq!(
+        "(number, float...) -> number",
+        sig!(number, float... -> number).to_string()
+    );
+
+    let signature = sig!(int, bool?, string? -> null);
+    assert!(!signature.accepts_count(0));
+    assert!(signature.accepts_count(3));
+    assert!(!signature.accepts_count(4));
+    assert_eq!("1 to 3", signature.expected_count());
+}
+
+#[test]
+fn cannot_call_function_with_wrong_arity() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) str = (replace "abc" "b")
+          }
+        "#},
+    )
+    .unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Function replace expects 3 arguments, got 2 at (4, 18)"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn cannot_call_function_with_wrong_argument_type() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) str = (replace "abc" 1 "d")
+          }
+        "#},
+    )
+    .unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Function replace expects argument 2 to be string, got int"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn can_call_function_with_variadic_signature() {
+    struct Sum;
+
+    impl Function for Sum {
+        fn call(
+            &self,
+            _graph: &mut Graph,
+            _source: &str,
+            parameters: &mut dyn Parameters,
+        ) -> Result<Value, ExecutionError> {
+            let mut sum = parameters.param()?.as_integer()?;
+            while let Ok(parameter) = parameters.param() {
+                sum += parameter.as_integer()?;
+            }
+            Ok(Value::Integer(sum))
+        }
+    }
+
+    let mut functions = Functions::new();
+    functions.add_with_signature(Identifier::from("sum"), sig!(int, int... -> int), Sum);
+    let mut graph = Graph::new();
+    let sum = Identifier::from("sum");
+    let values = vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)];
+    let result = functions.call(&sum, &mut graph, "", &mut values.into_iter());
+    assert_eq!(Value::Integer(6), result.unwrap());
+    let result = functions.call(&sum, &mut graph, "", &mut std::iter::empty());
+    assert!(matches!(
+        result,
+        Err(ExecutionError::WrongArity { got: 0, .. })
+    ));
+    let values = vec![Value::Integer(1), Value::from("2")];
+    let result = functions.call(&sum, &mut graph, "", &mut values.into_iter());
+    assert!(matches!(
+        result,
+        Err(ExecutionError::WrongArgumentType { index: 1, .. })
+    ));
+}
+
+#[test]
+fn can_check_arity_of_calls_when_parsing() {
+    let source = indoc! {r#"
+      (module)
+      {
+        node n
+        attr (n) str = (replace "abc" "b")
+      }
+    "#};
+    let functions = Functions::stdlib();
+    let error = File::from_str_with_functions(tree_sitter_python::language(), source, &functions)
+        .unwrap_err();
+    assert_eq!(
+        "Function replace expects 3 arguments, got 2 at (4, 18)",
+        error.to_string()
+    );
+    assert!(File::from_str(tree_sitter_python::language(), source).is_ok());
+}
+
+#[test]
+fn can_navigate_syntax_nodes() {
+    check_execution(
+        indoc! {r#"
+          def f(a, b):
+            return a
+        "#},
+        indoc! {r#"
+          (parameters) @params
+          {
+            node n
+            attr (n) parent = (parent @params)
+            attr (n) next_sibling = (next-sibling @params)
+            attr (n) prev_sibling = (prev-sibling @params)
+            attr (n) next_named_sibling = (next-named-sibling @params)
+            attr (n) prev_named_sibling = (prev-named-sibling @params)
+            attr (n) first_child = (child @params 0)
+            attr (n) second_named_child = (named-child @params 1)
+            attr (n) missing_child = (child @params 5)
+            attr (n) negative_child = (named-child @params -1)
+            attr (n) name = (child-by-field-name (parent @params) "name")
+            attr (n) missing_field = (child-by-field-name @params "name")
+            attr (n) unknown_field = (child-by-field-name @params "no-such-field")
+            attr (n) child_count = (child-count @params)
+            attr (n) named_child_count = (named-child-count @params)
+            let def = (parent @params)
+            node def.node
+          }
+
+          (module) @mod
+          {
+            node n
+            attr (n) parent = (parent @mod)
+            attr (n) next_sibling = (next-sibling @mod)
+          }
+
+          (function_definition) @def
+          {
+            attr (@def.node) defines = (source-text (child-by-field-name @def "name"))
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            child_count: 5
+            first_child: [syntax node "(" (1, 6)]
+            missing_child: #null
+            missing_field: #null
+            name: [syntax node identifier (1, 5)]
+            named_child_count: 2
+            negative_child: #null
+            next_named_sibling: [syntax node block (2, 3)]
+            next_sibling: [syntax node ":" (1, 12)]
+            parent: [syntax node function_definition (1, 1)]
+            prev_named_sibling: [syntax node identifier (1, 5)]
+            prev_sibling: [syntax node identifier (1, 5)]
+            second_named_child: [syntax node identifier (1, 10)]
+            unknown_field: #null
+          node 1
+            defines: "f"
+          node 2
+            next_sibling: #null
+            parent: #null
+        "#},
+    );
+}
+
+#[test]
+fn can_find_leading_comments() {
+    check_execution(
+        indoc! {r##"
+          # unrelated
+          x = 1  # trailing x
+
+          # first
+          # second
+
+          # third
+          def f():
+              pass
+        "##},
+        indoc! {r##"
+          (function_definition name: (identifier) @name) @def
+          {
+            node n
+            attr (n) name = (source-text @name)
+            attr (n) comments = (leading-comments @def)
+            attr (n) other = (leading-comments @def ["other_comment"])
+          }
+        "##},
+        indoc! {r##"
+          node 0
+            comments: [[syntax node comment (4, 1)], [syntax node comment (5, 1)], [syntax node comment (7, 1)]]
+            name: "f"
+            other: []
+        "##},
+    );
+}
+
+#[test]
+fn can_get_locations() {
+    check_execution(
+        indoc! {r#"
+          pass
+          x = foo
+        "#},
+        indoc! {r#"
+          (assignment left: (identifier) @left right: (identifier) @right)
+          {
+            node n
+            attr (n) left = (location-of @left)
+            attr (n) span = (location-of @left @right)
+            attr (n) locations = {(location-of @left), (location-of @left)}
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            left: 2:1-2:2
+            locations: {2:1-2:2}
+            span: 2:1-2:8
+        "#},
+    );
+}
+
+#[test]
+fn can_find_trailing_comments() {
+    check_execution(
+        indoc! {r##"
+          x = 1  # trailing x
+          y = 2
+          # not trailing
+        "##},
+        indoc! {r##"
+          (expression_statement (assignment left: (identifier) @name)) @stmt
+          {
+            node n
+            attr (n) name = (source-text @name)
+            attr (n) comment = (trailing-comment @stmt)
+          }
+        "##},
+        indoc! {r##"
+          node 0
+            comment: [syntax node comment (1, 8)]
+            name: "x"
+          node 1
+            comment: #null
+            name: "y"
+        "##},
+    );
+}
+
+#[test]
+fn can_find_keyed_nodes() {
+    check_execution(
+        indoc! {r#"
+          x = 1
+          y = x
+          z = w
+        "#},
+        indoc! {r#"
+          (assignment left: (identifier) @name)
+          {
+            let def = (node-for (format "def:{}" (source-text @name)))
+            attr (def) name = (source-text @name)
+          }
+
+          (assignment right: (identifier) @ref)
+          {
+            node ref
+            attr (ref) ref = (source-text @ref)
+            let def = (find-node (format "def:{}" (source-text @ref)))
+            if (not (is-null def)) {
+              edge ref -> def
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            name: "x"
+          node 1
+            name: "y"
+          node 2
+            name: "z"
+          node 3
+            ref: "x"
+          edge 3 -> 0
+          node 4
+            ref: "w"
+        "#},
+    );
+}
+
+#[test]
+fn can_find_nodes_by_attribute() {
+    check_execution(
+        indoc! {r#"
+          a = 1
+          b = "x"
+          c = 2
+        "#},
+        indoc! {r#"
+          (assignment left: (identifier) @name right: (_) @value)
+          {
+            node n
+            attr (n) name = (source-text @name), kind = (node-type @value)
+          }
+
+          (module)
+          {
+            node before
+            attr (before) integers = (find-nodes-by-attr "kind" "integer")
+            node extra
+            attr (extra) kind = "integer"
+            node after
+            attr (after) integers = (find-nodes-by-attr "kind" "integer")
+            attr (after) strings = (find-nodes-by-attr "kind" "string")
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "integer"
+            name: "a"
+          node 1
+            kind: "string"
+            name: "b"
+          node 2
+            kind: "integer"
+            name: "c"
+          node 3
+            integers: [[graph node 0], [graph node 2]]
+          node 4
+            kind: "integer"
+          node 5
+            integers: [[graph node 0], [graph node 2], [graph node 4]]
+            strings: [[graph node 1]]
+        "#},
+    );
+}
+
+#[test]
+fn can_check_ranges() {
+    init_log();
+    let python_source = "x = 1\ny = 2\nz = 3\n";
+    let dsl_source = indoc! {r#"
+      global changed_ranges
+
+      (assignment left: (identifier) @name) @stmt
+      {
+        node n
+        attr (n) name = (source-text @name)
+        attr (n) changed = (range-contains changed_ranges @stmt)
+        attr (n) overlaps = (ranges-overlap changed_ranges (location-of @name))
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let changed_ranges = vec![tree_sitter::Range {
+        start_byte: 4,
+        end_byte: 11,
+        start_point: tree_sitter::Point::new(0, 4),
+        end_point: tree_sitter::Point::new(1, 5),
+    }];
+    let mut globals = Variables::new();
+    globals
+        .add(
+            Identifier::from("changed_ranges"),
+            changed_ranges
+                .into_iter()
+                .map(Value::from)
+                .collect::<Vec<_>>()
+                .into(),
+        )
+        .unwrap();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            changed: #false
+            name: "x"
+            overlaps: #false
+          node 1
+            changed: #true
+            name: "y"
+            overlaps: #true
+          node 2
+            changed: #false
+            name: "z"
+            overlaps: #false
+        "#}
+    );
+}
+
+/// A batchable function that records the argument sets of every batch it is called with.
+#[derive(Clone, Default)]
+struct RecordingLookup {
+    batches: std::sync::Arc<std::sync::Mutex<Vec<Vec<Vec<Value>>>>>,
+}
+
+impl BatchFunction for RecordingLookup {
+    fn call_batch(
+        &self,
+        _graph: &Graph,
+        _source: &str,
+        arguments: Vec<Vec<Value>>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        let results = arguments
+            .iter()
+            .map(|arguments| Ok(Value::from(format!("def {}", arguments[0].as_str()?))))
+            .collect::<Result<_, ExecutionError>>()?;
+        self.batches.lock().unwrap().push(arguments);
+        Ok(results)
+    }
+}
+
+fn execute_batched(lazy: bool) -> (String, Vec<Vec<Vec<Value>>>) {
+    init_log();
+    let python_source = indoc! {r#"
+      a = b
+      c = a
+      d = b
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (identifier) @left right: (identifier) @right)
+      {
+        node n
+        attr (n) left = (lookup (source-text @left))
+        attr (n) right = (lookup (source-text @right))
+        let name = (source-text @left)
+        attr (n) shouted = (lookup (format "{}!" name))
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let lookup = RecordingLookup::default();
+    let mut functions = Functions::stdlib();
+    functions.add_batchable(
+        Identifier::from("lookup"),
+        sig!(string -> string),
+        lookup.clone(),
+    );
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    let batches = lookup.batches.lock().unwrap().clone();
+    let result = graph.pretty_print().to_string();
+    (result, batches)
+}
+
+#[test]
+fn can_batch_calls() {
+    let expected_graph = indoc! {r#"
+      node 0
+        left: "def a"
+        right: "def b"
+        shouted: "def a!"
+      node 1
+        left: "def c"
+        right: "def a"
+        shouted: "def c!"
+      node 2
+        left: "def d"
+        right: "def b"
+        shouted: "def d!"
+    "#};
+    let batch = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| vec![Value::from(*name)])
+            .collect::<Vec<_>>()
+    };
+
+    let (graph, batches) = execute_batched(false);
+    assert_eq!(graph, expected_graph);
+    assert_eq!(
+        batches,
+        vec![
+            batch(&["a", "b", "c", "d"]),
+            batch(&["a!"]),
+            batch(&["c!"]),
+            batch(&["d!"]),
+        ]
+    );
+
+    let (graph, batches) = execute_batched(true);
+    assert_eq!(graph, expected_graph);
+    assert_eq!(batches[0], batch(&["a", "b", "c", "d"]));
+    assert_eq!(batches.len(), 4);
+}
+
+/// A batchable function that fails for every batch that contains the argument `c`.
+struct FailingLookup;
+
+impl BatchFunction for FailingLookup {
+    fn call_batch(
+        &self,
+        _graph: &Graph,
+        _source: &str,
+        arguments: Vec<Vec<Value>>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        arguments
+            .iter()
+            .map(|arguments| match arguments[0].as_str()? {
+                "c" => Err(ExecutionError::FunctionFailed(
+                    "lookup".into(),
+                    "no definition for c".into(),
+                )),
+                name => Ok(Value::from(format!("def {}", name))),
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn failing_batched_calls_fail_execution() {
+    init_log();
+    let python_source = indoc! {r#"
+      a = b
+      c = a
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (identifier) @left)
+      {
+        node n
+        attr (n) def = (lookup (source-text @left))
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let mut functions = Functions::stdlib();
+    functions.add_batchable(
+        Identifier::from("lookup"),
+        sig!(string -> string),
+        FailingLookup,
+    );
+    let globals = Variables::new();
+    for &lazy in &[false, true] {
+        let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+        let error = match file.execute(&tree, python_source, &config, &NoCancellation) {
+            Ok(_) => panic!("Expected batched call to fail"),
+            Err(error) => error,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Calling function lookup at (4, 18). Caused by: Function lookup failed: no definition for c"
+        );
+    }
+}
+
+#[test]
+fn failing_batched_calls_only_skip_their_matches() {
+    init_log();
+    let python_source = indoc! {r#"
+      a = b
+      c = a
+      d = b
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (identifier) @left)
+      {
+        node n
+        attr (n) def = (lookup (source-text @left))
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let mut functions = Functions::stdlib();
+    functions.add_batchable(
+        Identifier::from("lookup"),
+        sig!(string -> string),
+        FailingLookup,
+    );
+    let globals = Variables::new();
+    let execute = |on_error| {
+        let config = ExecutionConfig::new(&functions, &globals).on_error(on_error);
+        let (graph, errors) = file
+            .execute_partial(&tree, python_source, &config, &NoCancellation)
+            .expect("Cannot execute file");
+        let match_locations = errors
+            .iter()
+            .map(|error| error.match_location.to_string())
+            .collect::<Vec<_>>();
+        let graph = graph.pretty_print().to_string();
+        (graph, match_locations)
+    };
+
+    let (graph, match_locations) = execute(ErrorPolicy::SkipMatch);
+    assert_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+            def: "def a"
+          node 1
+            def: "def d"
+        "#}
+    );
+    assert_eq!(match_locations, vec!["(2, 1)"]);
+
+    let (graph, match_locations) = execute(ErrorPolicy::SkipStanza);
+    assert_eq!(graph, "");
+    assert_eq!(match_locations, vec!["(2, 1)"]);
+}
+
+/// A function that counts how often it is called, and returns its argument with the count.
+#[derive(Clone, Default)]
+struct CountingFunction {
+    calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl Function for CountingFunction {
+    fn call(
+        &self,
+        _graph: &mut Graph,
+        _source: &str,
+        parameters: &mut dyn Parameters,
+    ) -> Result<Value, ExecutionError> {
+        let name = parameters.param()?.into_string()?;
+        parameters.finish()?;
+        let calls = self
+            .calls
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(format!("{} {}", name, calls + 1).into())
+    }
+}
+
+fn execute_memoized(lazy: bool) -> (String, usize, usize) {
+    init_log();
+    let python_source = indoc! {r#"
+      a = b
+      c = c
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (identifier) @left right: (identifier) @right)
+      {
+        node n
+        attr (n) first = (expensive (source-text @left))
+        attr (n) second = (expensive (source-text @left))
+        attr (n) third = (expensive (source-text @left))
+        attr (n) right = (expensive (source-text @right))
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let expensive = CountingFunction::default();
+    let mut functions = Functions::stdlib();
+    functions.add_memoized(Identifier::from("expensive"), expensive.clone());
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    let calls = expensive.calls.load(std::sync::atomic::Ordering::Relaxed);
+    let result = graph.pretty_print().to_string();
+    (result, calls, functions.memoized_hits())
+}
+
+#[test]
+fn can_memoize_calls_within_a_match() {
+    // Each match calls the function once for each distinct argument.  The second match calls it
+    // with the same argument as the first match did, which is not reused.
+    let (graph, calls, hits) = execute_memoized(false);
+    assert_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+            first: "a 1"
+            right: "b 2"
+            second: "a 1"
+            third: "a 1"
+          node 1
+            first: "c 3"
+            right: "c 3"
+            second: "c 3"
+            third: "c 3"
+        "#}
+    );
+    assert_eq!((calls, hits), (3, 5));
+
+    let (graph, calls, hits) = execute_memoized(true);
+    assert_eq!(graph.matches("a ").count(), 3);
+    assert_eq!((calls, hits), (3, 5));
+}
+
+#[test]
+#[cfg(feature = "unicode-normalization")]
+fn can_unify_keys_of_normalized_identifiers() {
+    // The first assignment uses a composed é, the second an e followed by a combining accent.
+    check_execution(
+        "caf\u{e9} = 1\ncafe\u{301} = 2\n",
+        indoc! {r#"
+          (assignment left: (identifier) @name)
+          {
+            let def = (node-for (nfc (source-text @name)))
+            attr (def) name = (nfc (source-text @name))
+          }
+        "#},
+        "node 0\n  name: \"caf\u{e9}\"\n",
+    );
+}
+
+#[test]
+#[cfg(feature = "unicode-normalization")]
+fn can_normalize_compatibility_characters() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) nfc = (nfc "ﬁle")
+            attr (n) nfkc = (nfkc "ﬁle")
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            nfc: "ﬁle"
+            nfkc: "file"
+        "#},
+    );
+}
+
+#[test]
+#[cfg(feature = "unicode-normalization")]
+fn can_fold_identifiers() {
+    check_execution(
+        "CAFE\u{301} = 1\n\u{ff23}af\u{e9} = 2\n",
+        indoc! {r#"
+          (assignment left: (identifier) @name)
+          {
+            let def = (node-for (fold-identifier (source-text @name)))
+            attr (def) name = (fold-identifier (source-text @name))
+          }
+        "#},
+        "node 0\n  name: \"caf\u{e9}\"\n",
+    );
+}
+
+fn execute_with_capabilities(
+    dsl_source: &str,
+    capabilities: Capabilities,
+) -> Result<String, ExecutionError> {
+    init_log();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let mut functions = Functions::new();
+    functions.add_standard_functions_with(capabilities);
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file.execute(&tree, "pass", &config, &NoCancellation)?;
+    let result = graph.pretty_print().to_string();
+    Ok(result)
+}
+
+#[test]
+fn can_call_capability_functions_when_enabled() {
+    std::env::set_var("TREE_SITTER_GRAPH_TEST_ENV", "from the environment");
+    let graph = execute_with_capabilities(
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) now = (now-iso8601)
+            attr (n) set = (env "TREE_SITTER_GRAPH_TEST_ENV")
+            attr (n) unset = (env "TREE_SITTER_GRAPH_TEST_UNSET_ENV")
+            attr (n) length = (length (split (now-iso8601) ""))
+          }
+        "#},
+        Capabilities {
+            time: true,
+            env: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(graph.contains("length: 20\n"), "{}", graph);
+    assert!(
+        graph.contains("set: \"from the environment\"\n"),
+        "{}",
+        graph
+    );
+    assert!(graph.contains("unset: #null\n"), "{}", graph);
+    let now = graph.split("now: \"").nth(1).unwrap();
+    assert!(
+        now.starts_with("20") && now[10..].starts_with('T'),
+        "{}",
+        now
+    );
+}
+
+#[test]
+fn can_fix_the_time_for_reproducible_builds() {
+    let dsl_source = indoc! {r#"
+      (module)
+      {
+        node n
+        attr (n) now = (now-iso8601)
+      }
+    "#};
+    let capabilities = Capabilities {
+        time: true,
+        fixed_time: Some("2024-01-02T03:04:05Z".to_string()),
+        ..Default::default()
+    };
+    let expected = indoc! {r#"
+      node 0
+        now: "2024-01-02T03:04:05Z"
+    "#};
+    assert_eq!(
+        execute_with_capabilities(dsl_source, capabilities.clone()).unwrap(),
+        expected
+    );
+    assert_eq!(
+        execute_with_capabilities(dsl_source, capabilities).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn cannot_call_capability_functions_when_disabled() {
+    let capabilities = Capabilities {
+        time: true,
+        ..Default::default()
+    };
+    let error = execute_with_capabilities(
+        r#"(module) { node n attr (n) home = (env "HOME") }"#,
+        capabilities,
+    )
+    .unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .ends_with("Undefined function env (requires the env capability)"),
+        "unexpected error: {}",
+        error
+    );
+    let error = execute(
+        "pass",
+        r#"(module) { node n attr (n) now = (now-iso8601) }"#,
+    )
+    .unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .ends_with("Undefined function now-iso8601 (requires the time capability)"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn can_access_source_text_and_positions_of_syntax_nodes() {
+    check_execution(
+        "x = \"😀é\" + y\n",
+        indoc! {r#"
+          (binary_operator left: (_) @left operator: _ @op)
+          {
+            node n
+            attr (n) text = (source-text @left), op = (source-text @op)
+            attr (n) kind = (node-kind @left), op_kind = (node-kind @op)
+            attr (n) named = (is-named @left), op_named = (is-named @op)
+            attr (n) range = (node-range @left), op_range = (node-range @op)
+            attr (n) start = [(start-row @op), (start-column @op)]
+            attr (n) end = [(end-row @op), (end-column @op)]
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            end: [0, 14]
+            kind: "string"
+            named: #true
+            op: "+"
+            op_kind: "+"
+            op_named: #false
+            op_range: [0, 13, 0, 14]
+            range: [0, 4, 0, 12]
+            start: [0, 13]
+            text: "\"😀é\""
+        "#},
+    );
+}
+
+#[test]
+fn cannot_get_source_text_outside_of_the_source() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("long_name = 1\n", None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        "(integer) @i { node n attr (n) text = (source-text @i) }",
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let error = match file.execute(&tree, "x = 1\n", &config, &NoCancellation) {
+        Ok(_) => panic!("Execution succeeded unexpectedly"),
+        Err(error) => error,
+    };
+    assert!(
+        error.to_string().contains("does not match source text"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn can_call_closures_that_create_graph_nodes() {
+    init_log();
+    let python_source = indoc! {r#"
+      x = y
+    "#};
+    let dsl_source = indoc! {r#"
+      (identifier) @id
+      {
+        let n = (make-node @id)
+        attr (n) arguments = (count @id "a" "b")
+      }
+    "#};
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let prefix = String::from("made from ");
+    let mut functions = Functions::stdlib();
+    functions.add(
+        Identifier::from("make-node"),
+        move |graph: &mut Graph, source: &str, parameters: &mut dyn Parameters| {
+            let syntax_node = parameters.param()?.into_syntax_node_ref()?;
+            parameters.finish()?;
+            let text = format!("{}{}", prefix, &source[syntax_node.byte_range()]);
+            let node = graph.add_graph_node();
+            graph[node]
+                .attributes
+                .add(Identifier::from("text"), text)
+                .expect("new node has no attributes");
+            Ok(Value::from(node))
+        },
+    );
+    functions.add(
+        Identifier::from("count"),
+        ValueFunction(|arguments: Vec<Value>| Ok(Value::Integer(arguments.len() as i64))),
+    );
+    let globals = Variables::new();
+    for lazy in [false, true] {
+        let config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
+        let graph = file
+            .execute(&tree, python_source, &config, &NoCancellation)
+            .expect("Cannot execute file");
+        assert_eq!(
+            graph.pretty_print().to_string(),
+            indoc! {r#"
+              node 0
+                arguments: 3
+                text: "made from x"
+              node 1
+                arguments: 3
+                text: "made from y"
+            "#},
+            "lazy: {}",
+            lazy
+        );
+    }
+}
+
+#[test]
+fn arguments_report_function_and_position() {
+    let mut graph = Graph::new();
+    let values = vec![Value::from("abc"), Value::Integer(42), Value::from("x")];
+    let error = stdlib::string::Replace
+        .call(&mut graph, "", &mut values.into_iter())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Function replace expects argument 2 to be string, got int"
+    );
+
+    let values = vec![Value::from("abc"), Value::Integer(1), Value::Integer(2)];
+    let parameters = &mut values.into_iter();
+    let mut arguments = Arguments::new("repeat", parameters);
+    assert_eq!(arguments.param_str().unwrap(), "abc");
+    assert_eq!(arguments.param_opt(), Some(Value::Integer(1)));
+    assert_eq!(arguments.position(), 2);
+    assert_eq!(
+        arguments.finish().unwrap_err().to_string(),
+        "Function repeat expects 2 arguments, got 3"
+    );
+
+    let parameters = &mut std::iter::empty();
+    let mut arguments = Arguments::new("repeat", parameters);
+    assert_eq!(arguments.param_opt(), None);
+    assert!(arguments.rest().is_empty());
+    assert_eq!(
+        arguments.param_str().unwrap_err().to_string(),
+        "Function repeat expects at least 1 arguments, got 0"
+    );
+}
@@ -5,11 +5,52 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "serde")]
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
 use indoc::indoc;
+#[cfg(feature = "serde")]
+use serde_json::json;
+use tree_sitter::InputEdit;
 use tree_sitter::Parser;
+use tree_sitter::Point;
+use tree_sitter::Tree;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::graph::AliasError;
+use tree_sitter_graph::graph::AmbiguousInheritance;
+use tree_sitter_graph::graph::Attributes;
+use tree_sitter_graph::graph::CombinedGraph;
+use tree_sitter_graph::graph::ConflictPolicy;
+use tree_sitter_graph::graph::Float;
 use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::graph::GraphMutationError;
+use tree_sitter_graph::graph::GraphNodeRef;
+use tree_sitter_graph::graph::GraphVisitor;
+use tree_sitter_graph::graph::PositionConverter;
+use tree_sitter_graph::graph::PositionEncoding;
+use tree_sitter_graph::graph::PrettyPrintOptions;
+use tree_sitter_graph::graph::RedactionRules;
+use tree_sitter_graph::graph::SelfEdgePolicy;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::graph::SerializationConfig;
+use tree_sitter_graph::graph::SourceLocation;
+use tree_sitter_graph::graph::SyntaxNodeRef;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::graph::SyntaxNodeRendering;
+use tree_sitter_graph::graph::Tag;
+use tree_sitter_graph::graph::TagError;
 use tree_sitter_graph::graph::Value;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::graph::DEFAULT_SOURCE_PATH_ATTRIBUTE;
+use tree_sitter_graph::graph::MAX_TAG_DEPTH;
+use tree_sitter_graph::parse_error::ParseError;
+use tree_sitter_graph::ExecutionConfig;
 use tree_sitter_graph::Identifier;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::Variables;
 
 #[test]
 fn can_overwrite_attributes() {
@@ -22,6 +63,23 @@ fn can_overwrite_attributes() {
     assert_eq!(*attrs.get(&name).unwrap(), Value::from("overwritten"));
 }
 
+#[test]
+fn can_replace_default_attributes() {
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    let attrs = &mut graph[node].attributes;
+    let name = Identifier::from("name");
+    attrs.add_default(name.clone(), "default");
+    attrs.add_default(name.clone(), "later default");
+    assert_eq!(*attrs.get(&name).unwrap(), Value::from("later default"));
+    assert!(attrs.is_default(&name));
+    attrs.add(name.clone(), "explicit").unwrap();
+    assert!(!attrs.is_default(&name));
+    attrs.add_default(name.clone(), "ignored default");
+    assert_eq!(*attrs.get(&name).unwrap(), Value::from("explicit"));
+    attrs.add(name.clone(), "conflicting").unwrap_err();
+}
+
 #[test]
 fn can_iterate_graph_nodes() {
     let mut graph = Graph::new();
@@ -47,6 +105,67 @@ fn can_iterate_graph_edges() {
     assert_eq!(edges, vec![node1, node2]);
 }
 
+#[test]
+fn can_look_up_graph_node_refs_by_index() {
+    let mut graph = Graph::new();
+    let node0 = graph.add_graph_node();
+    let node1 = graph.add_graph_node();
+    graph[node1]
+        .attributes
+        .add(Identifier::from("name"), "node1")
+        .unwrap();
+    assert_eq!(graph.node_ref(node0.index()), Some(node0));
+    assert_eq!(graph.node_ref(node1.index()), Some(node1));
+    assert_eq!(graph.node_ref(2), None);
+    assert_eq!(graph.node_ref(usize::MAX), None);
+    assert!(graph.contains(node1));
+
+    // node 0 has no attributes or edges, so it is removed as an orphan
+    graph.prune_orphans();
+    assert_eq!(graph.node_ref(node0.index()), None);
+    assert!(!graph.contains(node0));
+    assert!(graph.contains(node1));
+}
+
+#[test]
+fn cannot_look_up_graph_node_refs_of_larger_graphs() {
+    let mut graph = Graph::new();
+    graph.add_graph_node();
+    let mut other = Graph::new();
+    other.add_graph_node();
+    let other_node1 = other.add_graph_node();
+    assert!(other.contains(other_node1));
+    assert!(!graph.contains(other_node1));
+    assert_eq!(graph.node_ref(other_node1.index()), None);
+}
+
+#[test]
+fn can_look_up_syntax_node_refs_by_index() {
+    let python_source = "x = 1";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let module = tree.root_node();
+    let statement = module.named_child(0).unwrap();
+
+    let mut graph = Graph::new();
+    let module_ref = graph.add_syntax_node(module);
+    let found = graph.syntax_node_ref(module_ref.index()).unwrap();
+    assert_eq!(found, module_ref);
+    assert_eq!(found.kind(), "module");
+    assert_eq!(found.byte_range(), 0..5);
+    assert_eq!(graph.syntax_node_ref(statement.id() as u32), None);
+
+    // a syntax node that was only added to another graph is not found
+    let mut other = Graph::new();
+    let statement_ref = other.add_syntax_node(statement);
+    assert_eq!(
+        other.syntax_node_ref(statement_ref.index()),
+        Some(statement_ref)
+    );
+    assert_eq!(graph.syntax_node_ref(statement_ref.index()), None);
+}
+
 #[test]
 fn can_display_graph() {
     let python_source = "pass";
@@ -102,3 +221,2264 @@ fn can_display_graph() {
         "#}
     );
 }
+
+#[test]
+fn can_display_graph_as_dot() {
+    let mut graph = Graph::new();
+    let node0 = graph.add_graph_node();
+    graph[node0]
+        .attributes
+        .add(Identifier::from("name"), "say \"hi\"")
+        .unwrap();
+    graph[node0]
+        .attributes
+        .add_hidden(Identifier::from("secret"), 1)
+        .unwrap();
+    let node1 = graph.add_graph_node();
+    let edge01 = graph[node0]
+        .add_edge(node1)
+        .unwrap_or_else(|_| unreachable!());
+    edge01
+        .attributes
+        .add(Identifier::from("precedence"), 14)
+        .unwrap();
+    graph[node1]
+        .add_edge(node0)
+        .unwrap_or_else(|_| unreachable!());
+    assert_eq!(
+        graph.display_dot().to_string(),
+        indoc! {r#"
+          digraph {
+            0 [label="node 0\nname: \"say \\\"hi\\\"\""];
+            0 -> 1 [label="precedence: 14"];
+            1 [label="node 1"];
+            1 -> 0;
+          }
+        "#}
+    );
+}
+
+#[test]
+fn can_compute_missing_attributes() {
+    let mut graph = Graph::new();
+    let node0 = graph.add_graph_node();
+    let node1 = graph.add_graph_node();
+    let node2 = graph.add_graph_node();
+    let name = Identifier::from("name");
+    let qualified_name = Identifier::from("qualified_name");
+    graph[node0].attributes.add(name.clone(), "a").unwrap();
+    graph[node1].attributes.add(name.clone(), "b").unwrap();
+    graph[node1]
+        .attributes
+        .add(Identifier::from("parent"), node0)
+        .unwrap();
+    graph[node2]
+        .attributes
+        .add(qualified_name.clone(), "precomputed")
+        .unwrap();
+    graph.compute_attribute("qualified_name", |node, graph| {
+        let name = graph[node].attributes.get("name")?.as_str().ok()?;
+        match graph[node].attributes.get("parent") {
+            Some(parent) => {
+                let parent = parent.as_graph_node_ref().ok()?;
+                let prefix = graph[parent].attributes.get("qualified_name")?;
+                Some(format!("{}.{}", prefix.as_str().ok()?, name).into())
+            }
+            None => Some(name.into()),
+        }
+    });
+    assert_eq!(
+        graph[node0].attributes.get(&qualified_name),
+        Some(&Value::from("a"))
+    );
+    assert_eq!(
+        graph[node1].attributes.get(&qualified_name),
+        Some(&Value::from("a.b"))
+    );
+    assert_eq!(
+        graph[node2].attributes.get(&qualified_name),
+        Some(&Value::from("precomputed"))
+    );
+}
+
+#[test]
+fn can_merge_graphs() {
+    let mut graph = Graph::new();
+    let node0 = graph.add_graph_node();
+    graph[node0]
+        .attributes
+        .add(Identifier::from("name"), "node0")
+        .unwrap();
+
+    let mut other = Graph::new();
+    let other0 = other.add_graph_node();
+    let other1 = other.add_graph_node();
+    other[other0]
+        .attributes
+        .add(Identifier::from("parent"), other1)
+        .unwrap();
+    other[other0]
+        .attributes
+        .add(
+            Identifier::from("siblings"),
+            vec![other0.into(), other1.into()],
+        )
+        .unwrap();
+    let _ = other[other1].add_edge(other0);
+
+    let mapping = graph.merge(other).expect("Cannot merge graphs");
+    assert_eq!(mapping.len(), 2);
+    assert_eq!(mapping[other0].index(), 1);
+    assert_eq!(mapping[other1].index(), 2);
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            name: "node0"
+          node 1
+            parent: [graph node 2]
+            siblings: [[graph node 1], [graph node 2]]
+          node 2
+          edge 2 -> 1
+        "#}
+    );
+}
+
+fn keyed_graphs() -> (Graph<'static>, Graph<'static>) {
+    let key = Identifier::from("qualified_name");
+    let kind = Identifier::from("kind");
+    let visibility = Identifier::from("visibility");
+
+    let mut graph = Graph::new();
+    let module = graph.add_graph_node();
+    let function = graph.add_graph_node();
+    graph[module].attributes.add(key.clone(), "module").unwrap();
+    graph[module]
+        .attributes
+        .add(visibility.clone(), "public")
+        .unwrap();
+    graph[function]
+        .attributes
+        .add(key.clone(), "function")
+        .unwrap();
+    let edge = graph[module]
+        .add_edge(function)
+        .unwrap_or_else(|_| unreachable!());
+    edge.attributes.add(kind.clone(), "call").unwrap();
+
+    let mut other = Graph::new();
+    let reference = other.add_graph_node();
+    let module = other.add_graph_node();
+    let function = other.add_graph_node();
+    other[module].attributes.add(key.clone(), "module").unwrap();
+    other[module]
+        .attributes
+        .add(visibility.clone(), "private")
+        .unwrap();
+    other[function]
+        .attributes
+        .add(key.clone(), "function")
+        .unwrap();
+    let _ = other[reference].add_edge(module);
+    let edge = other[module]
+        .add_edge(function)
+        .unwrap_or_else(|_| unreachable!());
+    edge.attributes.add(kind.clone(), "ref").unwrap();
+
+    (graph, other)
+}
+
+#[test]
+fn can_merge_keyed_graphs_keeping_existing_attributes() {
+    let (mut graph, other) = keyed_graphs();
+    let mapping = graph
+        .merge_keyed(
+            other,
+            &Identifier::from("qualified_name"),
+            ConflictPolicy::KeepExisting,
+        )
+        .unwrap();
+    assert_eq!(
+        mapping.iter().map(|(_, n)| n.index()).collect::<Vec<_>>(),
+        vec![2, 0, 1]
+    );
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            qualified_name: "module"
+            visibility: "public"
+          edge 0 -> 1
+            kind: "call"
+          node 1
+            qualified_name: "function"
+          node 2
+          edge 2 -> 0
+        "#}
+    );
+}
+
+#[test]
+fn can_merge_keyed_graphs_overwriting_attributes() {
+    let (mut graph, other) = keyed_graphs();
+    graph
+        .merge_keyed(
+            other,
+            &Identifier::from("qualified_name"),
+            ConflictPolicy::Overwrite,
+        )
+        .unwrap();
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            qualified_name: "module"
+            visibility: "private"
+          edge 0 -> 1
+            kind: "ref"
+          node 1
+            qualified_name: "function"
+          node 2
+          edge 2 -> 0
+        "#}
+    );
+}
+
+#[test]
+fn cannot_merge_keyed_graphs_with_conflicting_attributes() {
+    let (mut graph, other) = keyed_graphs();
+    let before = graph.pretty_print().to_string();
+    graph
+        .merge_keyed(
+            other,
+            &Identifier::from("qualified_name"),
+            ConflictPolicy::Error,
+        )
+        .unwrap_err();
+    assert_eq!(graph.pretty_print().to_string(), before);
+}
+
+#[test]
+fn can_merge_keyed_edges_into_one() {
+    let key = Identifier::from("qualified_name");
+    let mut graph = Graph::new();
+    let mut other = Graph::new();
+    let source = other.add_graph_node();
+    let sink0 = other.add_graph_node();
+    let sink1 = other.add_graph_node();
+    other[sink0].attributes.add(key.clone(), "module").unwrap();
+    other[sink1].attributes.add(key.clone(), "module").unwrap();
+    let _ = other[source].add_edge(sink0);
+    let _ = other[source].add_edge(sink1);
+    graph
+        .merge_keyed(other, &key, ConflictPolicy::Error)
+        .unwrap();
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+          edge 0 -> 1
+          node 1
+            qualified_name: "module"
+        "#}
+    );
+}
+
+#[test]
+fn cannot_merge_keyed_graphs_into_removed_nodes() {
+    let key = Identifier::from("qualified_name");
+    let mut graph = Graph::new();
+    let removed = graph.add_graph_node();
+    graph.prune_orphans();
+    graph[removed]
+        .attributes
+        .add(key.clone(), "module")
+        .unwrap();
+    let mut other = Graph::new();
+    let module = other.add_graph_node();
+    other[module].attributes.add(key.clone(), "module").unwrap();
+    let mapping = graph
+        .merge_keyed(other, &key, ConflictPolicy::Error)
+        .unwrap();
+    assert_eq!(mapping[module].index(), 1);
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 1
+            qualified_name: "module"
+        "#}
+    );
+}
+
+#[test]
+fn cannot_merge_graphs_with_conflicting_syntax_nodes() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let old_tree = parser
+        .parse("a = [1, 2, 3]\nb = [1, 2, 3]\n", None)
+        .unwrap();
+    let mut edited_tree = old_tree.clone();
+    edited_tree.edit(&InputEdit {
+        start_byte: 6,
+        old_end_byte: 6,
+        new_end_byte: 7,
+        start_position: Point::new(0, 6),
+        old_end_position: Point::new(0, 6),
+        new_end_position: Point::new(0, 7),
+    });
+    let new_tree = parser
+        .parse("a = [12, 2, 3]\nb = [1, 2, 3]\n", Some(&edited_tree))
+        .unwrap();
+    // The reparsed tree reuses the list of the unchanged statement, which keeps its ID but moves.
+    let old_node = old_tree
+        .root_node()
+        .descendant_for_byte_range(18, 27)
+        .unwrap();
+    let new_node = new_tree
+        .root_node()
+        .descendant_for_byte_range(19, 28)
+        .unwrap();
+    assert_eq!(new_node.kind(), "list");
+    assert_eq!(old_node.id(), new_node.id());
+
+    let mut graph = Graph::new();
+    let old_ref = graph.add_syntax_node(old_node);
+    let node = graph.add_graph_node();
+    graph[node]
+        .attributes
+        .add(Identifier::from("syntax"), old_ref)
+        .unwrap();
+    let before = graph.pretty_print().to_string();
+    let mut other = Graph::new();
+    let new_ref = other.add_syntax_node(new_node);
+    let node = other.add_graph_node();
+    other[node]
+        .attributes
+        .add(Identifier::from("syntax"), new_ref)
+        .unwrap();
+    let error = graph.merge(other).unwrap_err();
+    assert!(
+        error.to_string().starts_with("Conflicting syntax nodes"),
+        "{}",
+        error
+    );
+    assert_eq!(graph.pretty_print().to_string(), before);
+}
+
+/// Builds a graph with a forward declaration (node 1) and a definition (node 2) of the same
+/// function, which refer to each other, and are both referenced by node 0.  Node 3 refers to the
+/// declaration in an attribute.
+fn aliased_graph() -> (Graph<'static>, Vec<GraphNodeRef>) {
+    let mut graph = Graph::new();
+    let nodes = (0..4).map(|_| graph.add_graph_node()).collect::<Vec<_>>();
+    graph.set_attribute(nodes[1], "name", "f", false).unwrap();
+    graph
+        .set_attribute(nodes[1], "kind", "declaration", false)
+        .unwrap();
+    graph.set_attribute(nodes[2], "name", "f", false).unwrap();
+    graph
+        .set_attribute(nodes[2], "kind", "definition", false)
+        .unwrap();
+    graph
+        .set_attribute(nodes[3], "target", vec![Value::from(nodes[1])], false)
+        .unwrap();
+    for (source, sink, kind) in &[
+        (0, 1, "call"),
+        (0, 2, "ref"),
+        (1, 2, "defined-by"),
+        (2, 1, "declared-by"),
+    ] {
+        graph
+            .add_edge(nodes[*source], nodes[*sink])
+            .unwrap()
+            .attributes
+            .add(Identifier::from("kind"), *kind)
+            .unwrap();
+    }
+    (graph, nodes)
+}
+
+#[test]
+fn can_alias_graph_nodes() {
+    let (mut graph, nodes) = aliased_graph();
+    graph
+        .alias(
+            nodes[2],
+            nodes[1],
+            ConflictPolicy::KeepExisting,
+            SelfEdgePolicy::Drop,
+        )
+        .unwrap();
+    assert!(graph.is_removed(nodes[1]));
+    assert!(graph.check_integrity().is_empty());
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+          edge 0 -> 2
+            kind: "ref"
+          node 2
+            kind: "definition"
+            name: "f"
+          node 3
+            target: [[graph node 2]]
+        "#}
+    );
+    assert_eq!(
+        graph.alias(
+            nodes[2],
+            nodes[1],
+            ConflictPolicy::Error,
+            SelfEdgePolicy::Drop
+        ),
+        Err(AliasError::RemovedGraphNode(nodes[1]))
+    );
+}
+
+#[test]
+fn can_alias_graph_nodes_keeping_self_edges() {
+    let (mut graph, nodes) = aliased_graph();
+    graph
+        .alias(
+            nodes[2],
+            nodes[1],
+            ConflictPolicy::Overwrite,
+            SelfEdgePolicy::Keep,
+        )
+        .unwrap();
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+          edge 0 -> 2
+            kind: "call"
+          node 2
+            kind: "declaration"
+            name: "f"
+          edge 2 -> 2
+            kind: "defined-by"
+          node 3
+            target: [[graph node 2]]
+        "#}
+    );
+}
+
+#[test]
+fn cannot_alias_graph_nodes_with_conflicting_attributes() {
+    let (mut graph, nodes) = aliased_graph();
+    let before = graph.pretty_print().to_string();
+    assert!(matches!(
+        graph.alias(
+            nodes[2],
+            nodes[1],
+            ConflictPolicy::Error,
+            SelfEdgePolicy::Drop
+        ),
+        Err(AliasError::DuplicateAttribute(_))
+    ));
+    assert_eq!(graph.pretty_print().to_string(), before);
+    assert_eq!(
+        graph.alias(
+            nodes[2],
+            nodes[2],
+            ConflictPolicy::Error,
+            SelfEdgePolicy::Drop
+        ),
+        Err(AliasError::SameGraphNode(nodes[2]))
+    );
+}
+
+#[test]
+fn can_combine_graphs_of_files() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/combine");
+    let mut paths = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    let sources = paths
+        .iter()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect::<Vec<_>>();
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let trees = sources
+        .iter()
+        .map(|source| parser.parse(source, None).unwrap())
+        .collect::<Vec<_>>();
+
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (function_definition name: (identifier) @name) {
+            node @name.def
+            attr (@name.def) name = (source-text @name)
+          }
+        "#},
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let mut combined = CombinedGraph::with_path_attribute(Identifier::from("file"));
+    for ((path, source), tree) in paths.iter().zip(&sources).zip(&trees) {
+        if let Some(error) = ParseError::first(tree) {
+            combined.add_failure(path, error.display(path, source));
+            continue;
+        }
+        let graph = file
+            .execute(tree, source, &config, &NoCancellation)
+            .unwrap();
+        combined.add_file(path, graph).unwrap();
+    }
+
+    let ranges = combined
+        .files()
+        .iter()
+        .map(|file| (file.path.file_name().unwrap(), file.nodes.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ranges,
+        vec![("a.py".as_ref(), 0..2), ("c.py".as_ref(), 2..3)]
+    );
+    let failures = combined.failures();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].path.ends_with("b.py"));
+    for file in combined.files() {
+        for index in file.nodes.clone() {
+            let node = combined.graph().iter_nodes().nth(index).unwrap();
+            assert_eq!(
+                combined.graph()[node].attributes.get("file"),
+                Some(&Value::from(file.path.display().to_string()))
+            );
+        }
+    }
+    let names = combined
+        .graph()
+        .iter_nodes()
+        .map(|node| {
+            combined.graph()[node]
+                .attributes
+                .get("name")
+                .unwrap()
+                .clone()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec!["alpha".into(), "beta".into(), Value::from("delta")]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_combined_graphs() {
+    let mut first = Graph::new();
+    first.add_graph_node();
+    let mut second = Graph::new();
+    let node = second.add_graph_node();
+    second[node]
+        .attributes
+        .add(
+            Identifier::from(DEFAULT_SOURCE_PATH_ATTRIBUTE),
+            "elsewhere.py",
+        )
+        .unwrap();
+
+    let mut combined = CombinedGraph::new();
+    combined.add_file("first.py", first).unwrap();
+    assert!(combined.add_file("second.py", second).is_err());
+    combined.add_failure("third.py", "Cannot parse third.py");
+    assert_eq!(
+        serde_json::to_value(&combined).unwrap(),
+        json!({
+            "graph": [
+                {
+                    "id": 0,
+                    "edges": [],
+                    "attrs": {
+                        "source_path": { "type": "string", "string": "first.py" },
+                    },
+                },
+            ],
+            "files": [{ "path": "first.py", "start": 0, "end": 1 }],
+            "failures": [
+                {
+                    "path": "second.py",
+                    "error": "Duplicate attribute source_path on graph node built from second.py",
+                },
+                { "path": "third.py", "error": "Cannot parse third.py" },
+            ],
+        })
+    );
+}
+
+fn containment_graph() -> (Graph<'static>, Vec<GraphNodeRef>) {
+    let mut graph = Graph::new();
+    let nodes = (0..5).map(|_| graph.add_graph_node()).collect::<Vec<_>>();
+    let add_edge = |graph: &mut Graph, source: GraphNodeRef, sink: GraphNodeRef, kind: &str| {
+        let edge = graph[source]
+            .add_edge(sink)
+            .unwrap_or_else(|_| unreachable!());
+        edge.attributes.add(Identifier::from("kind"), kind).unwrap();
+    };
+    // 0 contains 1 contains 2, 3 contains 2, 4 references 2
+    add_edge(&mut graph, nodes[0], nodes[1], "contains");
+    add_edge(&mut graph, nodes[1], nodes[2], "contains");
+    add_edge(&mut graph, nodes[3], nodes[2], "contains");
+    add_edge(&mut graph, nodes[4], nodes[2], "references");
+    (graph, nodes)
+}
+
+#[test]
+fn can_inherit_attributes_along_edges() {
+    let (mut graph, nodes) = containment_graph();
+    let module_path = Identifier::from("module_path");
+    graph[nodes[0]]
+        .attributes
+        .add(module_path.clone(), "pkg")
+        .unwrap();
+    graph[nodes[4]]
+        .attributes
+        .add(module_path.clone(), "other")
+        .unwrap();
+    let ambiguities = graph.inherit_attribute(module_path.clone(), "kind", "contains");
+    assert!(ambiguities.is_empty());
+    let values = nodes
+        .iter()
+        .map(|node| graph[*node].attributes.get(&module_path).cloned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        vec![
+            Some("pkg".into()),
+            Some("pkg".into()),
+            Some("pkg".into()),
+            None,
+            Some("other".into()),
+        ]
+    );
+}
+
+#[test]
+fn can_report_ambiguous_attribute_inheritance() {
+    let (mut graph, nodes) = containment_graph();
+    let module_path = Identifier::from("module_path");
+    graph[nodes[1]]
+        .attributes
+        .add(module_path.clone(), "b")
+        .unwrap();
+    graph[nodes[3]]
+        .attributes
+        .add(module_path.clone(), "a")
+        .unwrap();
+    let ambiguities = graph.inherit_attribute(module_path.clone(), "kind", "contains");
+    assert_eq!(
+        ambiguities,
+        vec![AmbiguousInheritance {
+            node: nodes[2],
+            attribute: module_path.clone(),
+            ancestors: vec![(nodes[1], "b".into()), (nodes[3], "a".into())],
+        }]
+    );
+    assert_eq!(
+        graph[nodes[2]].attributes.get(&module_path),
+        Some(&"b".into())
+    );
+}
+
+#[test]
+fn can_inherit_attributes_along_cycles() {
+    let mut graph = Graph::new();
+    let nodes = (0..3).map(|_| graph.add_graph_node()).collect::<Vec<_>>();
+    for (source, sink) in [(0, 1), (1, 2), (2, 1)] {
+        let edge = graph[nodes[source]]
+            .add_edge(nodes[sink])
+            .unwrap_or_else(|_| unreachable!());
+        edge.attributes
+            .add(Identifier::from("kind"), "contains")
+            .unwrap();
+    }
+    let ambiguities = graph.inherit_attribute("module_path", "kind", "contains");
+    assert!(ambiguities.is_empty());
+    assert_eq!(
+        graph
+            .pretty_print()
+            .to_string()
+            .matches("module_path")
+            .count(),
+        0
+    );
+
+    graph[nodes[0]]
+        .attributes
+        .add(Identifier::from("module_path"), "pkg")
+        .unwrap();
+    graph.inherit_attribute("module_path", "kind", "contains");
+    assert_eq!(
+        graph
+            .pretty_print()
+            .to_string()
+            .matches("module_path")
+            .count(),
+        3
+    );
+}
+
+const MULTIBYTE_SOURCE: &str = "a = 1\nb = \"😀é\" + c\n";
+
+#[test]
+fn can_convert_positions_between_encodings() {
+    let point = Point::new(1, 15);
+    let utf8 = PositionConverter::new(MULTIBYTE_SOURCE, PositionEncoding::Utf8);
+    assert_eq!(15, utf8.convert(point).column);
+    let utf16 = PositionConverter::new(MULTIBYTE_SOURCE, PositionEncoding::Utf16);
+    assert_eq!(12, utf16.convert(point).column);
+    assert_eq!(4, utf16.convert(Point::new(0, 4)).column);
+    // the opening quote comes before the surrogate pair, the closing one after it
+    assert_eq!(4, utf16.convert(Point::new(1, 4)).column);
+    assert_eq!(8, utf16.convert(Point::new(1, 11)).column);
+    let utf32 = PositionConverter::new(MULTIBYTE_SOURCE, PositionEncoding::Utf32);
+    assert_eq!(11, utf32.convert(point).column);
+    assert_eq!(1, utf32.convert(point).row);
+}
+
+#[test]
+fn can_display_syntax_nodes_with_position_encoding() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MULTIBYTE_SOURCE, None).unwrap();
+    let offset = MULTIBYTE_SOURCE.find('c').unwrap();
+    let identifier = tree
+        .root_node()
+        .descendant_for_byte_range(offset, offset + 1)
+        .unwrap();
+
+    let mut graph = Graph::new();
+    let node = graph.add_syntax_node(identifier);
+    assert_eq!("[syntax node identifier (2, 16)]", format!("{}", node));
+    let utf16 = PositionConverter::new(MULTIBYTE_SOURCE, PositionEncoding::Utf16);
+    assert_eq!(
+        "[syntax node identifier (2, 13)]",
+        format!("{}", node.display_with(&utf16))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_positions_with_position_encoding() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MULTIBYTE_SOURCE, None).unwrap();
+    let offset = MULTIBYTE_SOURCE.find('c').unwrap();
+    let identifier = tree
+        .root_node()
+        .descendant_for_byte_range(offset, offset + 1)
+        .unwrap();
+
+    let mut graph = Graph::new();
+    let syntax_node = graph.add_syntax_node(identifier);
+    let node = graph.add_graph_node();
+    graph[node]
+        .attributes
+        .add(Identifier::from("source"), syntax_node)
+        .unwrap();
+
+    let json = serde_json::to_value(&graph).unwrap();
+    let source = &json[0]["attrs"]["source"];
+    assert_eq!(source["type"], "syntaxNode");
+    assert!(source.get("column").is_none());
+
+    let config =
+        SerializationConfig::new(MULTIBYTE_SOURCE).position_encoding(PositionEncoding::Utf16);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    let source = &json[0]["attrs"]["source"];
+    assert_eq!(source["row"], 1);
+    assert_eq!(source["column"], 12);
+
+    let config = SerializationConfig::new(MULTIBYTE_SOURCE);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    assert_eq!(json[0]["attrs"]["source"]["column"], 15);
+}
+
+#[test]
+fn can_compare_and_display_locations() {
+    let location = |start: usize, end: usize| SourceLocation {
+        start_byte: start,
+        end_byte: end,
+        start_point: Point::new(2, start),
+        end_point: Point::new(2, end),
+    };
+    let first = Value::from(location(6, 11));
+    let second = Value::from(location(20, 22));
+    assert_eq!(first.to_string(), "3:7-3:12");
+    assert_eq!(format!("{:?}", first), "3:7-3:12");
+    assert_eq!(first.as_location().unwrap(), location(6, 11));
+    assert!(Value::from("3:7-3:12").as_location().is_err());
+    assert!(first < second);
+
+    let set = Value::Set(
+        vec![second.clone(), first.clone(), first]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(set.to_string(), "{3:7-3:12, 3:21-3:23}");
+    assert_eq!(location(6, 11).span(&location(20, 22)), location(6, 22),);
+}
+
+#[test]
+fn can_convert_ranges_to_and_from_values() {
+    let range = tree_sitter::Range {
+        start_byte: 6,
+        end_byte: 11,
+        start_point: Point::new(1, 0),
+        end_point: Point::new(1, 5),
+    };
+    let value = Value::from(range);
+    assert_eq!(value.to_string(), "2:1-2:6");
+    assert_eq!(tree_sitter::Range::try_from(value).unwrap(), range);
+    assert!(tree_sitter::Range::try_from(Value::from(6)).is_err());
+
+    let location = SourceLocation::from(range);
+    assert!(location.contains(&location));
+    let inner = SourceLocation {
+        start_byte: 7,
+        end_byte: 9,
+        ..location
+    };
+    let after = SourceLocation {
+        start_byte: 11,
+        end_byte: 14,
+        ..location
+    };
+    assert!(location.contains(&inner));
+    assert!(!inner.contains(&location));
+    assert!(location.overlaps(&inner));
+    assert!(!location.overlaps(&after));
+    assert!(!location.contains(&after));
+}
+
+#[test]
+fn can_compare_and_display_floats() {
+    let float = |value: f64| Value::try_from(value).unwrap();
+    assert!(Float::new(f64::NAN).is_none());
+    assert!(Float::new(f64::INFINITY).is_none());
+    assert!(Value::try_from(f64::NEG_INFINITY).is_err());
+    assert_eq!(float(-0.0), float(0.0));
+    assert_ne!(float(1.0), Value::from(1));
+
+    assert_eq!(float(1.0).to_string(), "1.0");
+    assert_eq!(format!("{:?}", float(0.25)), "0.25");
+    assert_eq!(float(0.25).as_float().unwrap(), 0.25);
+    assert_eq!(Value::from(3).as_float().unwrap(), 3.0);
+    assert!(Value::from("0.25").as_float().is_err());
+
+    let set = Value::Set(
+        vec![float(2.5), float(-1.0), float(0.0), float(-0.0), float(2.5)]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(set.to_string(), "{-1.0, 0.0, 2.5}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_floats() {
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    graph
+        .set_attribute(node, "weight", Value::try_from(0.25).unwrap(), false)
+        .unwrap();
+
+    let json = serde_json::to_value(&graph).unwrap();
+    assert_eq!(
+        json[0]["attrs"]["weight"],
+        json!({ "type": "float", "float": 0.25 })
+    );
+
+    let deserialized = serde_json::from_value::<Graph>(json).unwrap();
+    assert_eq!(
+        deserialized[node].attributes.get("weight"),
+        Some(&Value::try_from(0.25).unwrap())
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_negative_integers() {
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    graph.set_attribute(node, "offset", -3, false).unwrap();
+    graph
+        .set_attribute(node, "sentinel", i64::MIN, false)
+        .unwrap();
+
+    let json = serde_json::to_value(&graph).unwrap();
+    assert_eq!(
+        json[0]["attrs"]["offset"],
+        json!({ "type": "int", "int": -3 })
+    );
+    assert_eq!(json[0]["attrs"]["sentinel"]["int"], i64::MIN);
+
+    let deserialized = serde_json::from_value::<Graph>(json).unwrap();
+    assert_eq!(
+        deserialized[node].attributes.get("offset"),
+        Some(&Value::from(-3))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_maps() {
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    let map = vec![
+        (Identifier::from("name"), Value::from("f")),
+        (Identifier::from("arity"), Value::from(2)),
+    ]
+    .into_iter()
+    .collect::<BTreeMap<_, _>>();
+    graph
+        .set_attribute(node, "signature", map.clone(), false)
+        .unwrap();
+
+    let json = serde_json::to_value(&graph).unwrap();
+    assert_eq!(
+        json[0]["attrs"]["signature"],
+        json!({
+            "type": "map",
+            "entries": {
+                "arity": { "type": "int", "int": 2 },
+                "name": { "type": "string", "string": "f" },
+            },
+        })
+    );
+
+    let deserialized = serde_json::from_value::<Graph>(json).unwrap();
+    assert_eq!(
+        deserialized[node].attributes.get("signature"),
+        Some(&Value::from(map))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_locations() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MULTIBYTE_SOURCE, None).unwrap();
+    let offset = MULTIBYTE_SOURCE.find('c').unwrap();
+    let identifier = tree
+        .root_node()
+        .descendant_for_byte_range(offset, offset + 1)
+        .unwrap();
+
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    graph[node]
+        .attributes
+        .add(
+            Identifier::from("location"),
+            SourceLocation::from(&identifier),
+        )
+        .unwrap();
+
+    let json = serde_json::to_value(&graph).unwrap();
+    let location = &json[0]["attrs"]["location"];
+    assert_eq!(location["type"], "location");
+    assert_eq!(location["start"]["byte"], offset);
+    assert_eq!(location["start"]["row"], 1);
+    assert_eq!(location["start"]["column"], 15);
+    assert_eq!(location["end"]["byte"], offset + 1);
+    assert_eq!(location["end"]["column"], 16);
+
+    let config =
+        SerializationConfig::new(MULTIBYTE_SOURCE).position_encoding(PositionEncoding::Utf16);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    assert_eq!(json[0]["attrs"]["location"]["start"]["column"], 12);
+    assert_eq!(json[0]["attrs"]["location"]["start"]["byte"], offset);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_spans_of_syntax_nodes() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MULTIBYTE_SOURCE, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) {
+            node unowned
+          }
+          (binary_operator right: (identifier) @id) {
+            node @id.def
+            attr (@id.def) source = @id
+          }
+        "#},
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, MULTIBYTE_SOURCE, &config, &NoCancellation)
+        .unwrap();
+
+    let config = SerializationConfig::new(MULTIBYTE_SOURCE)
+        .position_encoding(PositionEncoding::Utf16)
+        .include_spans(true);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    // syntax node IDs are assigned by tree-sitter, so they cannot be part of the golden output
+    let syntax_node_id = json[1]["attrs"]["source"]["id"].clone();
+    let span = json!({
+        "kind": "identifier",
+        "start": { "byte": 21, "row": 1, "column": 12 },
+        "end": { "byte": 22, "row": 1, "column": 13 },
+    });
+    assert_eq!(
+        json,
+        json!([
+            {
+                "id": 0,
+                "edges": [],
+                "attrs": {},
+                "span": null,
+            },
+            {
+                "id": 1,
+                "edges": [],
+                "attrs": {
+                    "source": {
+                        "type": "syntaxNode",
+                        "id": syntax_node_id,
+                        "row": 1,
+                        "column": 12,
+                        "span": span,
+                    },
+                },
+                "span": span,
+            },
+        ])
+    );
+
+    let config = SerializationConfig::new(MULTIBYTE_SOURCE);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    assert!(json[1].get("span").is_none());
+    assert!(json[1]["attrs"]["source"].get("span").is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_render_syntax_nodes_in_serialization() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MULTIBYTE_SOURCE, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (assignment left: (identifier) @name right: (_) @value) {
+            node @name.def
+            attr (@name.def) value = @value, nested = [@name, {@value}]
+          }
+        "#},
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, MULTIBYTE_SOURCE, &config, &NoCancellation)
+        .unwrap();
+    let attrs = |rendering| {
+        let config = SerializationConfig::new(MULTIBYTE_SOURCE).syntax_node_rendering(rendering);
+        let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+        json[0]["attrs"].clone()
+    };
+
+    let id_attrs = attrs(SyntaxNodeRendering::Id);
+    // syntax node IDs are assigned by tree-sitter, so they cannot be part of the golden output
+    let name_id = id_attrs["nested"]["values"][0]["id"].clone();
+    let value_id = id_attrs["value"]["id"].clone();
+    assert_eq!(
+        id_attrs,
+        json!({
+            "nested": {
+                "type": "list",
+                "values": [
+                    { "type": "syntaxNode", "id": name_id, "row": 0, "column": 0 },
+                    {
+                        "type": "set",
+                        "values": [
+                            { "type": "syntaxNode", "id": value_id, "row": 0, "column": 4 },
+                        ],
+                    },
+                ],
+            },
+            "value": { "type": "syntaxNode", "id": value_id, "row": 0, "column": 4 },
+        })
+    );
+
+    let name_span = json!({
+        "kind": "identifier",
+        "start": { "byte": 0, "row": 0, "column": 0 },
+        "end": { "byte": 1, "row": 0, "column": 1 },
+    });
+    let value_span = json!({
+        "kind": "integer",
+        "start": { "byte": 4, "row": 0, "column": 4 },
+        "end": { "byte": 5, "row": 0, "column": 5 },
+    });
+    assert_eq!(
+        attrs(SyntaxNodeRendering::Span),
+        json!({
+            "nested": {
+                "type": "list",
+                "values": [name_span, { "type": "set", "values": [value_span] }],
+            },
+            "value": value_span,
+        })
+    );
+
+    assert_eq!(
+        attrs(SyntaxNodeRendering::SourceText),
+        json!({
+            "nested": {
+                "type": "list",
+                "values": [
+                    { "type": "string", "string": "a" },
+                    { "type": "set", "values": [{ "type": "string", "string": "1" }] },
+                ],
+            },
+            "value": { "type": "string", "string": "1" },
+        })
+    );
+
+    let full_value = json!({
+        "type": "syntaxNode",
+        "id": value_id,
+        "row": 0,
+        "column": 4,
+        "span": value_span,
+        "text": "1",
+    });
+    assert_eq!(
+        attrs(SyntaxNodeRendering::Full),
+        json!({
+            "nested": {
+                "type": "list",
+                "values": [
+                    {
+                        "type": "syntaxNode",
+                        "id": name_id,
+                        "row": 0,
+                        "column": 0,
+                        "span": name_span,
+                        "text": "a",
+                    },
+                    { "type": "set", "values": [full_value] },
+                ],
+            },
+            "value": full_value,
+        })
+    );
+
+    // texts are truncated at a character boundary
+    let config = SerializationConfig::new(MULTIBYTE_SOURCE)
+        .syntax_node_rendering(SyntaxNodeRendering::SourceText)
+        .max_text_length(6);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    assert_eq!(json[1]["attrs"]["value"]["string"], "\"😀…");
+    let value = graph[graph.iter_nodes().nth(1).unwrap()]
+        .attributes
+        .get("value")
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(value.serialize_with(&config)).unwrap(),
+        json!({ "type": "string", "string": "\"😀…" })
+    );
+}
+
+fn graph_with_nodes(count: usize) -> Graph<'static> {
+    let mut graph = Graph::new();
+    for index in 0..count {
+        let node = graph.add_graph_node();
+        graph[node]
+            .attributes
+            .add(Identifier::from("name"), format!("node{:04}", index))
+            .unwrap();
+    }
+    graph
+}
+
+#[test]
+fn can_report_memory_usage() {
+    let mut small = graph_with_nodes(10);
+    let mut large = graph_with_nodes(100);
+    small.shrink_to_fit();
+    large.shrink_to_fit();
+    let small = small.memory_usage();
+    let large = large.memory_usage();
+    assert_eq!(large.graph_nodes, 10 * small.graph_nodes);
+    assert_eq!(large.string_length, 10 * small.string_length);
+    assert_eq!(large.string_allocations, 100);
+    assert!(large.attributes >= 9 * small.attributes);
+    assert!(large.total() > 9 * small.total());
+    assert_eq!(large.spilled_edge_lists, 0);
+    assert_eq!(large.inline_edge_lists, 100);
+}
+
+#[test]
+fn can_report_memory_usage_of_spilled_edges() {
+    let mut graph = graph_with_nodes(20);
+    let nodes = graph.iter_nodes().collect::<Vec<_>>();
+    for sink in &nodes[1..] {
+        let _ = graph[nodes[0]].add_edge(*sink);
+    }
+    let usage = graph.memory_usage();
+    assert_eq!(usage.spilled_edge_lists, 1);
+    assert_eq!(usage.inline_edge_lists, 19);
+    assert!(usage.edges > 0);
+    assert!(format!("{}", usage).contains("(19 inline, 1 spilled edge lists)"));
+}
+
+#[test]
+fn shrinking_graph_does_not_change_contents() {
+    let mut graph = graph_with_nodes(10);
+    let before = graph.pretty_print().to_string();
+    let usage = graph.memory_usage();
+    graph.shrink_to_fit();
+    assert_eq!(graph.pretty_print().to_string(), before);
+    assert!(graph.memory_usage().total() <= usage.total());
+}
+
+#[test]
+fn canonical_hash_does_not_depend_on_node_order() {
+    let build = |names: &[&str]| {
+        let mut graph = Graph::new();
+        let nodes = names
+            .iter()
+            .map(|name| {
+                let node = graph.add_graph_node();
+                graph[node]
+                    .attributes
+                    .add(Identifier::from("name"), *name)
+                    .unwrap();
+                (*name, node)
+            })
+            .collect::<Vec<_>>();
+        let node = |name| nodes.iter().find(|(n, _)| *n == name).unwrap().1;
+        let edge = match graph[node("a")].add_edge(node("b")) {
+            Ok(edge) => edge,
+            Err(_) => unreachable!(),
+        };
+        edge.attributes
+            .add(Identifier::from("precedence"), 1)
+            .unwrap();
+        graph[node("c")]
+            .attributes
+            .add(
+                Identifier::from("refs"),
+                Value::List(vec![node("a").into(), node("b").into()]),
+            )
+            .unwrap();
+        graph
+    };
+    let graph = build(&["a", "b", "c"]);
+    assert_eq!(
+        graph.canonical_hash(),
+        build(&["c", "b", "a"]).canonical_hash()
+    );
+    assert_eq!(
+        graph.canonical_hash(),
+        build(&["b", "c", "a"]).canonical_hash()
+    );
+
+    let mut changed = build(&["a", "b", "c"]);
+    let a = changed.iter_nodes().next().unwrap();
+    let b = changed.iter_nodes().nth(1).unwrap();
+    changed[a]
+        .get_edge_mut(b)
+        .unwrap()
+        .attributes
+        .add(Identifier::from("label"), "x")
+        .unwrap();
+    assert_ne!(graph.canonical_hash(), changed.canonical_hash());
+    assert_ne!(graph.canonical_hash(), Graph::new().canonical_hash());
+}
+
+#[test]
+fn canonical_hash_follows_documented_encoding() {
+    let hex = |graph: &Graph| {
+        graph
+            .canonical_hash()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    };
+    let mut graph = Graph::new();
+    // SHA-256 of "graph", no nodes, and no edges
+    assert_eq!(
+        hex(&graph),
+        "b0538425656765bb74f7f1a1a7fc97a8d072b873cff04da47e5ceb5d719db926"
+    );
+    graph.add_graph_node();
+    assert_eq!(
+        hex(&graph),
+        "0e8011d540c541366d332cf7aaaed54598851fa7aa72bba2ac09beeeaff9ac23"
+    );
+}
+
+#[test]
+fn default_pretty_print_options_do_not_change_output() {
+    assert_eq!(Graph::new().pretty_print().to_string(), "");
+    let graph = graph_with_nodes(2);
+    let options = PrettyPrintOptions::default();
+    assert_eq!(
+        graph.pretty_print_with(options).to_string(),
+        graph.pretty_print().to_string()
+    );
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            name: "node0000"
+          node 1
+            name: "node0001"
+        "#}
+    );
+}
+
+#[test]
+fn can_pretty_print_header_and_syntax_nodes() {
+    let options = PrettyPrintOptions {
+        header: true,
+        syntax_nodes: true,
+        include_hidden: false,
+    };
+    assert_eq!(
+        Graph::new().pretty_print_with(options).to_string(),
+        "graph: 0 nodes, 0 edges, 0 syntax nodes referenced\n(empty graph)\n"
+    );
+
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass\n", None).unwrap();
+    let mut graph = Graph::new();
+    graph.add_syntax_node(tree.root_node().child(0).unwrap());
+    graph.add_syntax_node(tree.root_node());
+    assert_eq!(
+        graph.pretty_print_with(options).to_string(),
+        indoc! {r#"
+          graph: 0 nodes, 0 edges, 2 syntax nodes referenced
+          (empty graph)
+          syntax nodes
+            [syntax node module (1, 1)]
+            [syntax node pass_statement (1, 1)]
+        "#}
+    );
+
+    let mut graph = graph_with_nodes(2);
+    let nodes = graph.iter_nodes().collect::<Vec<_>>();
+    let _ = graph[nodes[0]].add_edge(nodes[1]);
+    let options = PrettyPrintOptions {
+        header: true,
+        syntax_nodes: false,
+        include_hidden: false,
+    };
+    assert_eq!(
+        graph.pretty_print_with(options).to_string(),
+        indoc! {r#"
+          graph: 2 nodes, 1 edges, 0 syntax nodes referenced
+          node 0
+            name: "node0000"
+          edge 0 -> 1
+          node 1
+            name: "node0001"
+        "#}
+    );
+}
+
+#[test]
+fn can_set_and_remove_attributes() {
+    let mut attributes = Attributes::new();
+    let kind = Identifier::from("kind");
+    assert!(!attributes.set(kind.clone(), "reference"));
+    assert!(attributes.set(kind.clone(), "call"));
+    assert_eq!(attributes.get("kind"), Some(&Value::from("call")));
+    assert!(attributes.add(kind.clone(), "reference").is_err());
+
+    assert!(attributes.remove("kind"));
+    assert!(!attributes.remove("kind"));
+    assert_eq!(attributes.get("kind"), None);
+    attributes.add(kind.clone(), "reference").unwrap();
+    assert_eq!(attributes.get("kind"), Some(&Value::from("reference")));
+
+    attributes.add_default(Identifier::from("exported"), false);
+    assert!(attributes.set(Identifier::from("exported"), true));
+    assert!(!attributes.is_default("exported"));
+}
+
+#[test]
+fn hidden_attributes_are_not_pretty_printed() {
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    let attributes = &mut graph[node].attributes;
+    attributes.add(Identifier::from("name"), "x").unwrap();
+    attributes.add(Identifier::from("_seen"), true).unwrap();
+    attributes
+        .add_hidden(Identifier::from("scratch"), 1)
+        .unwrap();
+    assert!(attributes.is_hidden("_seen"));
+    assert!(attributes.is_hidden("scratch"));
+    assert!(!attributes.is_hidden("name"));
+    assert_eq!(attributes.get("scratch"), Some(&Value::from(1)));
+
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            name: "x"
+        "#}
+    );
+    let options = PrettyPrintOptions {
+        include_hidden: true,
+        ..PrettyPrintOptions::default()
+    };
+    assert_eq!(
+        graph.pretty_print_with(options).to_string(),
+        indoc! {r#"
+          node 0
+            _seen: #true
+            name: "x"
+            scratch: 1
+        "#}
+    );
+}
+
+#[test]
+fn hidden_attributes_conflict_like_other_attributes() {
+    let mut attributes = Attributes::new();
+    attributes
+        .add_hidden(Identifier::from("scratch"), 1)
+        .unwrap();
+    assert_eq!(
+        attributes.add(Identifier::from("scratch"), 2),
+        Err(Value::from(1))
+    );
+    attributes.add(Identifier::from("name"), "x").unwrap();
+    assert_eq!(
+        attributes.add_hidden(Identifier::from("name"), "y"),
+        Err(Value::from("x"))
+    );
+    assert!(!attributes.is_hidden("name"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_serialize_hidden_attributes() {
+    let mut graph = Graph::new();
+    let source = graph.add_graph_node();
+    let sink = graph.add_graph_node();
+    let attributes = &mut graph[source].attributes;
+    attributes.add(Identifier::from("_seen"), true).unwrap();
+    attributes
+        .add_hidden(Identifier::from("scratch"), 1)
+        .unwrap();
+    graph
+        .add_edge(source, sink)
+        .unwrap()
+        .attributes
+        .add_hidden(Identifier::from("weight"), 2)
+        .unwrap();
+
+    let json = serde_json::to_value(&graph).unwrap();
+    assert_eq!(json[0]["attrs"], json!({}));
+    assert!(json[0].get("hidden").is_none());
+    assert_eq!(json[0]["edges"][0]["attrs"], json!({}));
+
+    let config = SerializationConfig::new("").include_hidden(true);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    assert_eq!(
+        json[0]["attrs"]["_seen"],
+        json!({ "type": "bool", "bool": true })
+    );
+    assert_eq!(json[0]["hidden"], json!(["scratch"]));
+    assert_eq!(json[0]["edges"][0]["hidden"], json!(["weight"]));
+
+    let deserialized = serde_json::from_value::<Graph>(json).unwrap();
+    let attributes = &deserialized[source].attributes;
+    assert!(attributes.is_hidden("_seen"));
+    assert!(attributes.is_hidden("scratch"));
+    assert_eq!(attributes.get("scratch"), Some(&Value::from(1)));
+    let edge = deserialized[source].get_edge(sink).unwrap();
+    assert!(edge.attributes.is_hidden("weight"));
+    assert_eq!(
+        deserialized.pretty_print().to_string(),
+        graph.pretty_print().to_string()
+    );
+}
+
+#[test]
+fn can_parse_tags() {
+    let tag = Tag::parse("definition.class.method").unwrap();
+    assert_eq!(
+        tag.components(),
+        &[
+            Identifier::from("definition"),
+            Identifier::from("class"),
+            Identifier::from("method"),
+        ]
+    );
+    assert_eq!(tag.depth(), 3);
+    assert_eq!(tag.to_string(), "definition.class.method");
+
+    let prefix = Tag::parse("definition.class").unwrap();
+    assert!(tag.starts_with(&prefix));
+    assert!(tag.starts_with(&tag));
+    assert!(!prefix.starts_with(&tag));
+    assert!(!tag.starts_with(&Tag::parse("definition.cl").unwrap()));
+    assert_eq!(prefix.child(Identifier::from("method")), Ok(tag));
+    assert_eq!(Tag::from(Identifier::from("def")).to_string(), "def");
+
+    assert_eq!(Tag::parse(""), Err(TagError::Empty));
+    assert_eq!(Tag::parse("a..b"), Err(TagError::EmptyComponent(2)));
+    assert_eq!(Tag::parse("a."), Err(TagError::EmptyComponent(2)));
+    assert_eq!(
+        Tag::parse("a.1b"),
+        Err(TagError::UnexpectedCharacter('1', 2))
+    );
+    assert_eq!(
+        Tag::parse("a.b c"),
+        Err(TagError::UnexpectedCharacter(' ', 3))
+    );
+}
+
+#[test]
+fn cannot_exceed_maximum_tag_depth() {
+    let deepest = vec!["a"; MAX_TAG_DEPTH].join(".");
+    let tag = Tag::parse(&deepest).unwrap();
+    assert_eq!(tag.depth(), MAX_TAG_DEPTH);
+    assert_eq!(
+        Tag::parse(&format!("{}.a", deepest)),
+        Err(TagError::TooDeep(MAX_TAG_DEPTH))
+    );
+    assert_eq!(
+        tag.child(Identifier::from("a")),
+        Err(TagError::TooDeep(MAX_TAG_DEPTH))
+    );
+
+    let tag = Tag::parse_with_max_depth("a.b", 2).unwrap();
+    assert_eq!(
+        Tag::parse_with_max_depth("a.b.c", 2),
+        Err(TagError::TooDeep(2))
+    );
+    assert_eq!(
+        tag.child_with_max_depth(Identifier::from("c"), 2),
+        Err(TagError::TooDeep(2))
+    );
+    assert_eq!(
+        tag.child_with_max_depth(Identifier::from("c"), 3),
+        Tag::parse("a.b.c")
+    );
+}
+
+/// Returns the names of a chain of scoped variables, as in `@capture.a.b`, in order.
+fn scoped_variable_names(variable: &tree_sitter_graph::ast::ScopedVariable) -> Vec<Identifier> {
+    use tree_sitter_graph::ast::Expression;
+    use tree_sitter_graph::ast::Variable;
+    let mut names = match &*variable.scope {
+        Expression::Variable(Variable::Scoped(scope)) => scoped_variable_names(scope),
+        _ => Vec::new(),
+    };
+    names.push(variable.name.clone());
+    names
+}
+
+#[test]
+fn tags_accepted_by_the_parser_round_trip() {
+    use tree_sitter_graph::ast::Statement;
+    use tree_sitter_graph::ast::Variable;
+
+    const ALPHABET: &[char] = &['a', 'Z', '_', '-', '0', 'é', '.', '@', '('];
+    // a fixed xorshift generator, so that failures can be reproduced
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut next = |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    let mut accepted = 0;
+    for _ in 0..400 {
+        let len = 1 + next(8);
+        let tag = (0..len)
+            .map(|_| ALPHABET[next(ALPHABET.len())])
+            .collect::<String>();
+        let source = format!("(module) @mod {{\n  let @mod.{} = 1\n}}\n", tag);
+        let file = File::from_str(tree_sitter_python::language(), &source);
+        let parsed = Tag::parse(&tag);
+        assert_eq!(file.is_ok(), parsed.is_ok(), "{:?}", tag);
+        let (file, parsed) = match (file, parsed) {
+            (Ok(file), Ok(parsed)) => (file, parsed),
+            _ => continue,
+        };
+        accepted += 1;
+        assert_eq!(parsed.to_string(), tag);
+        let names = match &file.stanzas[0].statements[0] {
+            Statement::DeclareImmutable(declare) => match &declare.variable {
+                Variable::Scoped(variable) => scoped_variable_names(variable),
+                _ => panic!("expected a scoped variable"),
+            },
+            _ => panic!("expected a let statement"),
+        };
+        assert_eq!(parsed.components(), names.as_slice(), "{:?}", tag);
+    }
+    assert!(accepted > 20, "only {} tags were accepted", accepted);
+}
+
+const MUTATION_SOURCE: &str = "x = 1\n";
+
+fn executed_graph(tree: &Tree) -> Graph<'_> {
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) @mod {
+            node @mod.node
+            attr (@mod.node) kind = "module", source = @mod
+          }
+        "#},
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    file.execute(tree, MUTATION_SOURCE, &config, &NoCancellation)
+        .unwrap()
+}
+
+#[test]
+fn can_mutate_graph_after_execution() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MUTATION_SOURCE, None).unwrap();
+    let mut graph = executed_graph(&tree);
+    let module = graph.iter_nodes().next().unwrap();
+
+    let mut metadata = Attributes::new();
+    metadata.add(Identifier::from("kind"), "external").unwrap();
+    metadata.add(Identifier::from("file"), "lib.py").unwrap();
+    let external = graph.add_external_node(metadata).unwrap();
+    graph
+        .add_edge(module, external)
+        .unwrap()
+        .attributes
+        .add(Identifier::from("kind"), "import")
+        .unwrap();
+    assert_eq!(
+        graph.set_attribute(module, "kind", "package", false),
+        Err(GraphMutationError::DuplicateAttribute(
+            module,
+            Identifier::from("kind")
+        ))
+    );
+    graph
+        .set_attribute(module, "kind", "module", false)
+        .unwrap();
+    graph
+        .set_attribute(module, "kind", "package", true)
+        .unwrap();
+    graph
+        .set_attribute(external, "importer", module, false)
+        .unwrap();
+    assert!(graph.check_integrity().is_empty());
+
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            kind: "package"
+            source: [syntax node module (1, 1)]
+          edge 0 -> 1
+            kind: "import"
+          node 1
+            file: "lib.py"
+            importer: [graph node 0]
+            kind: "external"
+        "#}
+    );
+    #[cfg(feature = "serde")]
+    assert_eq!(
+        serde_json::to_value(&graph).unwrap(),
+        json!([
+            {
+                "id": 0,
+                "edges": [{ "sink": 1, "attrs": { "kind": { "type": "string", "string": "import" } } }],
+                "attrs": {
+                    "kind": { "type": "string", "string": "package" },
+                    "source": { "type": "syntaxNode", "id": tree.root_node().id() as u32 },
+                },
+            },
+            {
+                "id": 1,
+                "edges": [],
+                "attrs": {
+                    "file": { "type": "string", "string": "lib.py" },
+                    "importer": { "type": "graphNode", "id": 0 },
+                    "kind": { "type": "string", "string": "external" },
+                },
+            },
+        ])
+    );
+}
+
+#[test]
+fn checked_mutators_reject_unknown_nodes() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MUTATION_SOURCE, None).unwrap();
+    let other_tree = parser.parse("y = 22\n", None).unwrap();
+    let mut graph = executed_graph(&tree);
+    let module = graph.iter_nodes().next().unwrap();
+    let unknown = graph_with_nodes(5).iter_nodes().last().unwrap();
+    let other_module = SyntaxNodeRef::from(&other_tree.root_node());
+
+    let external = graph.add_external_node(Attributes::new()).unwrap();
+    graph.add_edge(module, external).unwrap();
+    assert_eq!(
+        graph.add_edge(module, external).err(),
+        Some(GraphMutationError::DuplicateEdge(module, external))
+    );
+    assert_eq!(
+        graph.add_edge(module, unknown).err(),
+        Some(GraphMutationError::UnknownGraphNode(unknown))
+    );
+    assert_eq!(
+        graph.set_attribute(unknown, "name", "unknown", false),
+        Err(GraphMutationError::UnknownGraphNode(unknown))
+    );
+    assert_eq!(
+        graph.set_attribute(module, "targets", vec![Value::from(unknown)], false),
+        Err(GraphMutationError::UnknownGraphNode(unknown))
+    );
+    assert_eq!(
+        graph.set_attribute(module, "other", other_module, false),
+        Err(GraphMutationError::UnknownSyntaxNode(other_module))
+    );
+    let mut metadata = Attributes::new();
+    metadata.add(Identifier::from("target"), unknown).unwrap();
+    assert_eq!(
+        graph.add_external_node(metadata),
+        Err(GraphMutationError::UnknownGraphNode(unknown))
+    );
+    assert_eq!(graph.node_count(), 2);
+    assert!(graph.check_integrity().is_empty());
+}
+
+#[test]
+fn can_check_graph_integrity() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MUTATION_SOURCE, None).unwrap();
+    let other_tree = parser.parse("y = 22\n", None).unwrap();
+    let mut graph = executed_graph(&tree);
+    let module = graph.iter_nodes().next().unwrap();
+    let unknown = graph_with_nodes(5).iter_nodes().last().unwrap();
+    let other_module = SyntaxNodeRef::from(&other_tree.root_node());
+
+    let _ = graph[module].add_edge(unknown);
+    let attrs = &mut graph[module].attributes;
+    attrs.add(Identifier::from("target"), unknown).unwrap();
+    attrs
+        .add(Identifier::from("others"), vec![Value::from(other_module)])
+        .unwrap();
+    assert_eq!(
+        graph
+            .check_integrity()
+            .iter()
+            .map(|violation| violation.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "edge [graph node 0] -> [graph node 4] leads to an unknown graph node",
+            "attribute others of [graph node 0] refers to syntax node [syntax node module (1, 1)], which is not part of the graph",
+            "attribute target of [graph node 0] refers to unknown graph node [graph node 4]",
+        ]
+    );
+}
+
+/// Builds a graph of three nodes with a `name`, a `path`, and a `text` attribute, where the
+/// first and last node have the same name, and an edge from the first to the second node.
+fn graph_to_redact() -> Graph<'static> {
+    let mut graph = Graph::new();
+    for (name, path) in [
+        ("secret", "/home/alice/src/a.py"),
+        ("other", "/home/bob/src/b.py"),
+        ("secret", "/home/carol/src/c.py"),
+    ] {
+        let node = graph.add_graph_node();
+        let attrs = &mut graph[node].attributes;
+        attrs.add(Identifier::from("name"), name).unwrap();
+        attrs.add(Identifier::from("path"), path).unwrap();
+        attrs.add(Identifier::from("text"), "x = 1").unwrap();
+    }
+    let (first, second) = (nth_node(&graph, 0), nth_node(&graph, 1));
+    graph
+        .add_edge(first, second)
+        .unwrap()
+        .attributes
+        .add(Identifier::from("name"), "secret")
+        .unwrap();
+    graph
+}
+
+fn nth_node(graph: &Graph, index: usize) -> GraphNodeRef {
+    graph.iter_nodes().nth(index).unwrap()
+}
+
+fn attribute(graph: &Graph, node: usize, name: &str) -> Option<Value> {
+    graph[nth_node(graph, node)]
+        .attributes
+        .get(&Identifier::from(name))
+        .cloned()
+}
+
+#[test]
+fn hashed_attributes_keep_equal_strings_equal() {
+    let mut graph = graph_to_redact();
+    let mut rules = RedactionRules::new();
+    rules.hash_attribute("name");
+    rules.set_salt("partner");
+    graph.redact(&rules);
+
+    let first = attribute(&graph, 0, "name").unwrap();
+    let second = attribute(&graph, 1, "name").unwrap();
+    let third = attribute(&graph, 2, "name").unwrap();
+    assert_eq!(first, third);
+    assert_ne!(first, second);
+    assert_ne!(first, Value::from("secret"));
+    assert!(!graph.pretty_print().to_string().contains("secret"));
+    let edge = graph[nth_node(&graph, 0)]
+        .get_edge(nth_node(&graph, 1))
+        .unwrap();
+    assert_eq!(edge.attributes.get(&Identifier::from("name")), Some(&first));
+
+    // a different salt gives different hashes
+    let mut other_graph = graph_to_redact();
+    rules.set_salt("other partner");
+    other_graph.redact(&rules);
+    assert_ne!(attribute(&other_graph, 0, "name").unwrap(), first);
+}
+
+#[test]
+fn can_drop_replace_and_scrub_attributes() {
+    let mut graph = graph_to_redact();
+    let mut rules = RedactionRules::new();
+    rules.drop_attribute("text");
+    rules.replace_attribute("name", "[name]");
+    rules.add_scrubber(regex::Regex::new("/home/[^/]+").unwrap(), "/home/user");
+    rules.add_scrubber(regex::Regex::new(r"/src/(\w+)\.py").unwrap(), "/$1");
+    graph.redact(&rules);
+
+    for node in 0..3 {
+        assert_eq!(attribute(&graph, node, "text"), None);
+        assert_eq!(attribute(&graph, node, "name"), Some(Value::from("[name]")));
+    }
+    assert_eq!(
+        attribute(&graph, 1, "path"),
+        Some(Value::from("/home/user/b"))
+    );
+}
+
+#[test]
+fn redaction_recurses_into_lists_and_sets() {
+    let mut graph = Graph::new();
+    let node = graph.add_graph_node();
+    let attrs = &mut graph[node].attributes;
+    attrs
+        .add(
+            Identifier::from("names"),
+            vec![
+                Value::from("a"),
+                Value::from(vec![Value::from("b"), Value::from(1)]),
+            ],
+        )
+        .unwrap();
+    attrs
+        .add(
+            Identifier::from("paths"),
+            vec![Value::from("/home/alice"), Value::from("/home/bob")]
+                .into_iter()
+                .collect::<BTreeSet<_>>(),
+        )
+        .unwrap();
+    let mut rules = RedactionRules::new();
+    rules.replace_attribute("names", "?");
+    rules.add_scrubber(regex::Regex::new("/home/.*").unwrap(), "[home]");
+    graph.redact(&rules);
+
+    assert_eq!(
+        attribute(&graph, 0, "names"),
+        Some(Value::from(vec![
+            Value::from("?"),
+            Value::from(vec![Value::from("?"), Value::from(1)]),
+        ]))
+    );
+    // equal strings in a set collapse into one
+    assert_eq!(
+        attribute(&graph, 0, "paths"),
+        Some(Value::from(
+            vec![Value::from("[home]")]
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+        ))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_load_redaction_rules_from_json() {
+    let rules = RedactionRules::from_json(
+        r#"{
+            "drop": ["text"],
+            "hash": ["name"],
+            "salt": "partner",
+            "scrub": [{ "pattern": "/home/[^/]+" }]
+        }"#,
+    )
+    .unwrap();
+    let mut graph = graph_to_redact();
+    graph.redact(&rules);
+    assert_eq!(attribute(&graph, 0, "text"), None);
+    assert_eq!(attribute(&graph, 0, "name"), attribute(&graph, 2, "name"));
+    assert_eq!(
+        attribute(&graph, 0, "path"),
+        Some(Value::from("[redacted]/src/a.py"))
+    );
+
+    let mut expected = RedactionRules::new();
+    expected.hash_attribute("name");
+    expected.set_salt("partner");
+    let mut expected_graph = graph_to_redact();
+    expected_graph.redact(&expected);
+    assert_eq!(
+        attribute(&graph, 0, "name"),
+        attribute(&expected_graph, 0, "name")
+    );
+
+    for (json, message) in [
+        (r#"{"drop": "text"}"#, "expected a list of strings for drop"),
+        (
+            r#"{"replace": {"name": 1}}"#,
+            "expected a string placeholder for name",
+        ),
+        (r#"{"scrub": [{"pattern": "("}]}"#, "invalid pattern ("),
+        (r#"{"keep": []}"#, "unexpected entry keep"),
+    ] {
+        let err = RedactionRules::from_json(json).unwrap_err().to_string();
+        assert!(err.contains(message), "{}", err);
+    }
+}
+
+#[cfg(feature = "serde")]
+fn sorted_attributes(attributes: &Attributes) -> Vec<(Identifier, Value)> {
+    let mut attributes = attributes
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<Vec<_>>();
+    attributes.sort();
+    attributes
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_deserialize_serialized_graphs() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(MULTIBYTE_SOURCE, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) {
+            node unowned
+          }
+          (binary_operator left: (_) @left right: (_) @right) @op {
+            node @op.node
+            node @right.node
+            edge @op.node -> @right.node
+            attr (@op.node -> @right.node) precedence = 1
+            attr (@op.node)
+              null = #null,
+              bool = #true,
+              int = 42,
+              string = "😀é",
+              list = [1, "two", @left, [#false]],
+              set = {@left, @right},
+              map = {left: @left, count: 2},
+              location = (location-of @op),
+              syntax = @op,
+              graph = @right.node
+          }
+        "#},
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let mut graph = file
+        .execute(&tree, MULTIBYTE_SOURCE, &config, &NoCancellation)
+        .unwrap();
+    // the removed graph node leaves a gap in the serialized IDs
+    assert_eq!(graph.prune_orphans().count(), 1);
+
+    let config =
+        SerializationConfig::new(MULTIBYTE_SOURCE).syntax_node_rendering(SyntaxNodeRendering::Full);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    let deserialized: Graph<'static> = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(
+        serde_json::to_value(deserialized.serialize_with(&config)).unwrap(),
+        json
+    );
+    let nodes = graph.iter_nodes().collect::<Vec<_>>();
+    assert_eq!(deserialized.iter_nodes().collect::<Vec<_>>(), nodes);
+    for node in graph.iter_nodes() {
+        assert_eq!(
+            sorted_attributes(&deserialized[node].attributes),
+            sorted_attributes(&graph[node].attributes)
+        );
+        let edges = |graph: &Graph, node: GraphNodeRef| {
+            graph[node]
+                .iter_edges()
+                .map(|(sink, edge)| (sink, sorted_attributes(&edge.attributes)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(edges(&deserialized, node), edges(&graph, node));
+    }
+
+    // the spans of the owners of graph nodes are accepted, but not restored
+    let config = SerializationConfig::new(MULTIBYTE_SOURCE).include_spans(true);
+    let json = serde_json::to_value(graph.serialize_with(&config)).unwrap();
+    let deserialized: Graph<'static> = serde_json::from_value(json).unwrap();
+    assert_eq!(
+        sorted_attributes(&deserialized[nodes[0]].attributes),
+        sorted_attributes(&graph[nodes[0]].attributes)
+    );
+
+    // without spans, syntax nodes only keep their IDs, and the graph still round-trips
+    let json = serde_json::to_string(&graph).unwrap();
+    let deserialized: Graph<'static> = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&deserialized).unwrap(), json);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialization_rejects_invalid_graphs() {
+    for (json, message) in [
+        (
+            r#"[{"id": 0, "edges": [], "attrs": {}}, {"id": 3, "attrs": {}, "color": "red"}]"#,
+            "graph node 3: unknown field `color`",
+        ),
+        (
+            r#"[{"id": 2, "attrs": {"x": {"type": "int", "int": 1, "double": 2}}}]"#,
+            "graph node 2: attribute x: unknown field `double`",
+        ),
+        (
+            r#"[{"id": 2, "attrs": {"x": {"type": "int", "string": "1"}}}]"#,
+            "graph node 2: attribute x: unexpected field `string` for type int",
+        ),
+        (
+            r#"[{"attrs": {"x": {"type": "double"}}, "id": 0}]"#,
+            "graph node at position 0: attribute x: unknown variant `double`",
+        ),
+        (
+            r#"[{"id": 0, "edges": [{"sink": 1, "attrs": {}}], "attrs": {}}]"#,
+            "graph node 0: edge to unknown graph node 1",
+        ),
+        (
+            r#"[{"id": 0, "attrs": {"x": {"type": "graphNode", "id": 7}}}]"#,
+            "graph node 0: attribute x refers to unknown graph node 7",
+        ),
+        (r#"[{"id": 0}, {"id": 0}]"#, "duplicate graph node 0"),
+        (r#"[{"id": 4000000000}]"#, "too many missing graph node IDs"),
+    ] {
+        let err = serde_json::from_str::<Graph>(json)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains(message), "{}", err);
+    }
+}
+
+/// Records the calls it receives, stopping after a given number of graph nodes
+struct RecordingVisitor {
+    calls: Vec<String>,
+    max_nodes: usize,
+}
+
+impl<'a> GraphVisitor<'a> for RecordingVisitor {
+    type Error = String;
+
+    fn begin_node(&mut self, node: GraphNodeRef) -> Result<(), String> {
+        self.calls.push(format!("begin {}", node));
+        Ok(())
+    }
+
+    fn attribute(&mut self, name: &'a Identifier, value: &'a Value) -> Result<(), String> {
+        self.calls.push(format!("attr {} = {}", name, value));
+        Ok(())
+    }
+
+    fn edge(
+        &mut self,
+        sink: GraphNodeRef,
+        attributes: &[(&'a Identifier, &'a Value)],
+    ) -> Result<(), String> {
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>();
+        self.calls
+            .push(format!("edge {} [{}]", sink, attributes.join(", ")));
+        Ok(())
+    }
+
+    fn end_node(&mut self, node: GraphNodeRef) -> Result<(), String> {
+        self.calls.push(format!("end {}", node));
+        if self
+            .calls
+            .iter()
+            .filter(|call| call.starts_with("end"))
+            .count()
+            == self.max_nodes
+        {
+            return Err(format!("stopped at {}", node));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn can_visit_graphs_in_order() {
+    let mut graph = Graph::new();
+    let nodes = (0..4).map(|_| graph.add_graph_node()).collect::<Vec<_>>();
+    for (name, value) in [("zeta", 1), ("alpha", 2), ("mu", 3)] {
+        graph.set_attribute(nodes[0], name, value, false).unwrap();
+    }
+    let edge = graph.add_edge(nodes[0], nodes[3]).unwrap();
+    edge.attributes.add(Identifier::from("z"), true).unwrap();
+    edge.attributes.add(Identifier::from("a"), false).unwrap();
+    graph.add_edge(nodes[0], nodes[1]).unwrap();
+    graph
+        .set_attribute(nodes[3], "name", "last", false)
+        .unwrap();
+    // nodes[2] is an orphan, and is not visited once it is removed
+    graph.prune_orphans();
+
+    let mut visitor = RecordingVisitor {
+        calls: Vec::new(),
+        max_nodes: usize::MAX,
+    };
+    graph.visit(&mut visitor).unwrap();
+    assert_eq!(
+        visitor.calls,
+        vec![
+            "begin [graph node 0]",
+            "attr alpha = 2",
+            "attr mu = 3",
+            "attr zeta = 1",
+            "edge [graph node 1] []",
+            "edge [graph node 3] [a = #false, z = #true]",
+            "end [graph node 0]",
+            "begin [graph node 1]",
+            "end [graph node 1]",
+            "begin [graph node 3]",
+            "attr name = last",
+            "end [graph node 3]",
+        ]
+    );
+
+    // an error stops the visit
+    let mut visitor = RecordingVisitor {
+        calls: Vec::new(),
+        max_nodes: 2,
+    };
+    assert_eq!(
+        graph.visit(&mut visitor),
+        Err("stopped at [graph node 1]".to_string())
+    );
+    assert_eq!(visitor.calls.last().unwrap(), "end [graph node 1]");
+}
+
+#[test]
+#[cfg(feature = "petgraph")]
+fn can_find_strongly_connected_components_with_petgraph() {
+    let source = "f(x)\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) @mod {
+            node @mod.node
+            attr (@mod.node) name = "module"
+          }
+          (identifier) @id {
+            node @id.node
+            attr (@id.node) name = (source-text @id)
+          }
+          (module (expression_statement (call function: (_) @f arguments: (argument_list (_) @x)))) @mod {
+            edge @mod.node -> @f.node
+            edge @f.node -> @x.node
+            attr (@f.node -> @x.node) kind = "argument"
+            edge @x.node -> @f.node
+          }
+        "#},
+    )
+    .unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, source, &config, &NoCancellation)
+        .unwrap();
+
+    let adapter = graph.to_petgraph();
+    assert_eq!(adapter.graph().node_count(), 3);
+    assert_eq!(adapter.graph().edge_count(), 3);
+    let name = |node: GraphNodeRef| graph[node].attributes.get("name").unwrap().to_string();
+    let mut components = petgraph::algo::tarjan_scc(adapter.graph())
+        .into_iter()
+        .map(|component| {
+            let mut names = component
+                .into_iter()
+                .map(|index| {
+                    // the weights are the graph nodes themselves
+                    let node = adapter.graph_node_ref(index);
+                    assert!(std::ptr::eq(adapter.graph()[index], &graph[node]));
+                    assert_eq!(adapter.node_index(node), Some(index));
+                    name(node)
+                })
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        })
+        .collect::<Vec<_>>();
+    components.sort();
+    assert_eq!(components, vec![vec!["f", "x"], vec!["module"]]);
+
+    let edge = adapter
+        .graph()
+        .find_edge(
+            adapter
+                .node_index(graph.iter_nodes().nth(1).unwrap())
+                .unwrap(),
+            adapter
+                .node_index(graph.iter_nodes().nth(2).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(
+        adapter.graph()[edge].attributes.get("kind"),
+        Some(&Value::from("argument"))
+    );
+}
@@ -0,0 +1,223 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use tree_sitter_graph::graph::query::eq;
+use tree_sitter_graph::graph::query::exists;
+use tree_sitter_graph::graph::query::gt;
+use tree_sitter_graph::graph::query::in_set;
+use tree_sitter_graph::graph::query::lt;
+use tree_sitter_graph::graph::query::ne;
+use tree_sitter_graph::graph::query::NodeMatch;
+use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::graph::GraphNodeRef;
+use tree_sitter_graph::graph::Value;
+use tree_sitter_graph::Identifier;
+
+const NODE_COUNT: usize = 300;
+const KINDS: [&str; 3] = ["definition", "reference", "scope"];
+
+/// Builds a graph where node `i` has kind `KINDS[i % 3]` and an `index` attribute, definitions
+/// are exported if `i` is even, and every definition has an edge with precedence `i % 10` to the
+/// scope that follows it.  References have an edge to the preceding definition, without
+/// attributes.  The graph ends with a few nodes without attributes.
+fn synthetic_graph() -> (Graph<'static>, Vec<GraphNodeRef>) {
+    let mut graph = Graph::new();
+    let nodes = (0..NODE_COUNT)
+        .map(|_| graph.add_graph_node())
+        .collect::<Vec<_>>();
+    for (i, node) in nodes.iter().enumerate() {
+        let kind = KINDS[i % 3];
+        graph.set_attribute(*node, "kind", kind, false).unwrap();
+        graph
+            .set_attribute(*node, "index", i as u32, false)
+            .unwrap();
+        match kind {
+            "definition" => {
+                graph
+                    .set_attribute(*node, "is_exported", i % 2 == 0, false)
+                    .unwrap();
+                graph
+                    .add_edge(*node, nodes[i + 2])
+                    .unwrap()
+                    .attributes
+                    .add(Identifier::from("precedence"), (i % 10) as u32)
+                    .unwrap();
+            }
+            "reference" => {
+                graph.add_edge(*node, nodes[i - 1]).unwrap();
+            }
+            _ => {}
+        }
+    }
+    for _ in 0..3 {
+        graph.add_graph_node();
+    }
+    (graph, nodes)
+}
+
+fn manual_query<F: Fn(usize) -> bool>(nodes: &[GraphNodeRef], f: F) -> Vec<GraphNodeRef> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| f(*i))
+        .map(|(_, node)| *node)
+        .collect()
+}
+
+#[test]
+fn can_query_nodes_by_attributes() {
+    let (graph, nodes) = synthetic_graph();
+    let result = graph
+        .query()
+        .nodes()
+        .where_attr("kind", eq("definition"))
+        .where_attr("is_exported", eq(true))
+        .collect();
+    assert_eq!(result.len(), 50);
+    assert_eq!(result, manual_query(&nodes, |i| i % 6 == 0));
+
+    let result = graph
+        .query()
+        .nodes()
+        .where_attr("kind", eq("scope"))
+        .collect();
+    assert_eq!(result, manual_query(&nodes, |i| i % 3 == 2));
+
+    let result = graph.query().nodes().collect();
+    assert_eq!(result.len(), NODE_COUNT + 3);
+}
+
+#[test]
+fn can_query_nodes_by_edges() {
+    let (graph, nodes) = synthetic_graph();
+    let query = graph
+        .query()
+        .nodes()
+        .where_attr("kind", eq("definition"))
+        .where_attr("is_exported", eq(true))
+        .with_edge(|e| {
+            e.where_attr("precedence", gt(5))
+                .to_node(|n| n.where_attr("kind", eq("scope")))
+        });
+    let expected = manual_query(&nodes, |i| i % 6 == 0 && i % 10 > 5);
+    assert_eq!(expected.len(), 20);
+    assert_eq!(
+        query.matches(),
+        expected
+            .iter()
+            .map(|node| NodeMatch {
+                node: *node,
+                edges: vec![vec![nodes[node.index() + 2]]],
+            })
+            .collect::<Vec<_>>()
+    );
+
+    // the sink of an edge can itself be required to have edges
+    let result = graph
+        .query()
+        .nodes()
+        .where_attr("kind", eq("reference"))
+        .with_edge(|e| {
+            e.to_node(|n| {
+                n.where_attr("is_exported", eq(false))
+                    .with_edge(|e| e.where_attr("precedence", lt(2)))
+            })
+        })
+        .collect();
+    assert_eq!(
+        result,
+        manual_query(&nodes, |i| i % 3 == 1
+            && (i - 1) % 2 == 1
+            && (i - 1) % 10 < 2)
+    );
+
+    // every edge pattern must be matched
+    let result = graph
+        .query()
+        .nodes()
+        .with_edge(|e| e.where_attr("precedence", exists()))
+        .with_edge(|e| e.to_node(|n| n.where_attr("kind", eq("definition"))))
+        .collect();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn can_query_nodes_with_all_predicates() {
+    let (graph, nodes) = synthetic_graph();
+    let query = |name: &str, predicate| graph.query().nodes().where_attr(name, predicate).collect();
+    assert_eq!(query("index", lt(10)), manual_query(&nodes, |i| i < 10));
+    assert_eq!(query("index", gt(290)), manual_query(&nodes, |i| i > 290));
+    assert_eq!(
+        query("kind", ne("scope")),
+        manual_query(&nodes, |i| i % 3 != 2)
+    );
+    assert_eq!(
+        query("kind", gt("definition")),
+        manual_query(&nodes, |i| i % 3 != 0)
+    );
+    assert_eq!(
+        query("index", in_set(vec![3u32, 5, 1000])),
+        vec![nodes[3], nodes[5]]
+    );
+    assert_eq!(
+        query("is_exported", exists()),
+        manual_query(&nodes, |i| i % 3 == 0)
+    );
+}
+
+#[test]
+fn can_query_negative_integers() {
+    let mut graph = Graph::new();
+    let nodes = [-5, -1, 0, 3]
+        .iter()
+        .map(|offset| {
+            let node = graph.add_graph_node();
+            graph.set_attribute(node, "offset", *offset, false).unwrap();
+            node
+        })
+        .collect::<Vec<_>>();
+    let query = |predicate| {
+        graph
+            .query()
+            .nodes()
+            .where_attr("offset", predicate)
+            .collect()
+    };
+    assert_eq!(query(lt(0)), vec![nodes[0], nodes[1]]);
+    assert_eq!(query(gt(-2)), vec![nodes[1], nodes[2], nodes[3]]);
+    assert_eq!(query(eq(-1)), vec![nodes[1]]);
+}
+
+#[test]
+fn predicates_do_not_match_values_of_other_types() {
+    let (graph, _) = synthetic_graph();
+    let query = |name: &str, predicate| {
+        graph
+            .query()
+            .nodes()
+            .where_attr(name, predicate)
+            .collect()
+            .len()
+    };
+    assert_eq!(query("kind", gt(5)), 0);
+    assert_eq!(query("kind", lt(5)), 0);
+    assert_eq!(query("kind", ne(5)), 0);
+    assert_eq!(query("kind", eq(Value::Null)), 0);
+    assert_eq!(query("index", ne("definition")), 0);
+    assert_eq!(query("index", in_set(vec!["1", "2"])), 0);
+    assert_eq!(query("is_exported", gt(0)), 0);
+    assert_eq!(query("missing", ne(0)), 0);
+    assert_eq!(query("missing", exists()), 0);
+}
+
+#[test]
+fn removed_nodes_do_not_match_queries() {
+    let (mut graph, _) = synthetic_graph();
+    let report = graph.prune_orphans();
+    assert_eq!(report.count(), 3);
+    assert_eq!(graph.query().nodes().collect().len(), NODE_COUNT);
+}
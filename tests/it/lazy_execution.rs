@@ -5,12 +5,27 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+use std::cell::RefCell;
+use std::path::Path;
+
 use indoc::indoc;
 use tree_sitter::Parser;
 use tree_sitter_graph::ast::File;
 use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::graph::GraphNodeRef;
+use tree_sitter_graph::graph::SyntaxNodeRef;
+use tree_sitter_graph::graph::Value;
+use tree_sitter_graph::Decision;
+use tree_sitter_graph::DependencyCycleStep;
 use tree_sitter_graph::ExecutionConfig;
 use tree_sitter_graph::ExecutionError;
+use tree_sitter_graph::GraphMutationHook;
+use tree_sitter_graph::GraphObserver;
+use tree_sitter_graph::Identifier;
+use tree_sitter_graph::Location;
+use tree_sitter_graph::MutationContext;
+use tree_sitter_graph::MutationTarget;
 use tree_sitter_graph::NoCancellation;
 use tree_sitter_graph::Variables;
 
@@ -24,6 +39,17 @@ fn init_log() {
 }
 
 fn execute(python_source: &str, dsl_source: &str) -> Result<String, ExecutionError> {
+    execute_in_mode(python_source, dsl_source, true, |graph| {
+        graph.pretty_print().to_string()
+    })
+}
+
+fn execute_in_mode<R>(
+    python_source: &str,
+    dsl_source: &str,
+    lazy: bool,
+    output: impl FnOnce(&Graph) -> R,
+) -> Result<R, ExecutionError> {
     init_log();
     let mut parser = Parser::new();
     parser.set_language(tree_sitter_python::language()).unwrap();
@@ -35,19 +61,46 @@ fn execute(python_source: &str, dsl_source: &str) -> Result<String, ExecutionErr
     globals
         .add("filename".into(), "test.py".into())
         .map_err(|_| ExecutionError::DuplicateVariable("filename".into()))?;
-    let mut config = ExecutionConfig::new(&functions, &globals).lazy(true);
+    let mut config = ExecutionConfig::new(&functions, &globals).lazy(lazy);
     let graph = file.execute(&tree, python_source, &mut config, &NoCancellation)?;
-    let result = graph.pretty_print().to_string();
-    Ok(result)
+    Ok(output(&graph))
 }
 
-fn check_execution(python_source: &str, dsl_source: &str, expected_graph: &str) {
+fn check_lazy_execution(python_source: &str, dsl_source: &str, expected_graph: &str) {
     match execute(python_source, dsl_source) {
         Ok(actual_graph) => assert_eq!(actual_graph, expected_graph),
         Err(e) => panic!("Could not execute file: {}", e),
     }
 }
 
+/// Checks a program that relies on lazy evaluation, such as using a scoped variable before it is
+/// defined, which strict execution cannot run.
+fn check_lazy_only_execution(python_source: &str, dsl_source: &str, expected_graph: &str) {
+    check_lazy_execution(python_source, dsl_source, expected_graph);
+    if execute_in_mode(python_source, dsl_source, false, |_| ()).is_ok() {
+        panic!("Strict execution succeeded unexpectedly");
+    }
+}
+
+fn check_execution(python_source: &str, dsl_source: &str, expected_graph: &str) {
+    check_lazy_execution(python_source, dsl_source, expected_graph);
+    // Where strict execution can run the program too, both modes must build the same graph, up to
+    // the numbering of graph nodes.
+    let canonical_hash = |lazy| {
+        execute_in_mode(python_source, dsl_source, lazy, |graph| {
+            graph.canonical_hash()
+        })
+    };
+    match canonical_hash(false) {
+        Ok(strict_hash) => assert_eq!(
+            strict_hash,
+            canonical_hash(true).unwrap(),
+            "strict execution builds a different graph"
+        ),
+        Err(e) => panic!("Could not execute file strictly: {}", e),
+    }
+}
+
 fn fail_execution(python_source: &str, dsl_source: &str) {
     if let Ok(_) = execute(python_source, dsl_source) {
         panic!("Execution succeeded unexpectedly");
@@ -347,6 +400,9 @@ fn can_nest_function_calls() {
     );
 }
 
+// The minimal regex engine does not support `\b`, and cannot express regexes that only match
+// empty strings within a larger string.
+#[cfg(not(feature = "lite-regex"))]
 #[test]
 fn cannot_use_nullable_regex() {
     fail_execution(
@@ -364,6 +420,108 @@ fn cannot_use_nullable_regex() {
     );
 }
 
+#[test]
+fn can_create_quantified_captures_in_order() {
+    check_execution(
+        indoc! {r#"
+          def f():
+            x = 1
+            pass
+            return x
+          def g():
+            pass
+        "#},
+        indoc! {r#"
+          (function_definition body: (block (_)* @stmts))
+          {
+            node n
+            attr (n) stmts = @stmts, count = (length @stmts)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            count: 3
+            stmts: [[syntax node expression_statement (2, 3)], [syntax node pass_statement (3, 3)], [syntax node return_statement (4, 3)]]
+          node 1
+            count: 1
+            stmts: [[syntax node pass_statement (6, 3)]]
+        "#},
+    );
+}
+
+#[test]
+fn can_create_one_graph_node_per_unique_imported_module() {
+    check_execution(
+        indoc! {r#"
+          import os
+          import sys
+          from os import path
+          import json
+          from sys import argv
+        "#},
+        indoc! {r#"
+          (module [
+            (import_statement name: (dotted_name) @names)
+            (import_from_statement module_name: (dotted_name) @names)
+          ]* ) @mod
+          {
+            ; the names are captured, so they are local values that a for loop can iterate over
+            let modules = (to-set [(source-text name) for name in @names])
+            let @mod.modules = modules
+            for module in (to-list modules) {
+              node n
+              attr (n) module = module
+            }
+          }
+
+          (module) @mod
+          {
+            node @mod.node
+            attr (@mod.node) modules = @mod.modules, count = (length (to-list @mod.modules))
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            count: 3
+            modules: {"json", "os", "sys"}
+          node 1
+            module: "json"
+          node 2
+            module: "os"
+          node 3
+            module: "sys"
+        "#},
+    );
+}
+
+#[test]
+fn can_attach_graph_nodes_to_navigated_syntax_nodes() {
+    check_execution(
+        indoc! {r#"
+          def f(a, b):
+            return a
+        "#},
+        indoc! {r#"
+          (parameters) @params
+          {
+            let def = (parent @params)
+            node def.node
+            attr (def.node) parameter_count = (named-child-count @params)
+          }
+
+          (function_definition) @def
+          {
+            attr (@def.node) defines = (source-text (child-by-field-name @def "name"))
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            defines: "f"
+            parameter_count: 2
+        "#},
+    );
+}
+
 #[test]
 fn can_create_present_optional_capture() {
     check_execution(
@@ -441,6 +599,35 @@ fn can_create_nonempty_list_capture() {
     );
 }
 
+#[test]
+fn can_execute_capture_aggregates() {
+    check_execution(
+        "pass\npass\npass\n",
+        indoc! {r#"
+          (module (pass_statement)* @xs)
+          {
+            node node0
+            attr (node0) count = (count @xs), first = (first @xs), last = (last @xs)
+          }
+          (module (import_statement)* @xs)
+          {
+            node node1
+            attr (node1) count = (count @xs), first = (first @xs), last = (last @xs)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            count: 0
+            first: #null
+            last: #null
+          node 1
+            count: 3
+            first: [syntax node pass_statement (1, 1)]
+            last: [syntax node pass_statement (3, 1)]
+        "#},
+    );
+}
+
 #[test]
 fn can_execute_if_some() {
     check_execution(
@@ -573,6 +760,54 @@ fn can_execute_if_literal() {
     );
 }
 
+#[test]
+fn can_execute_if_function_call() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module (import_statement)? @x (pass_statement)? @y)
+          {
+            node node0
+            if (is-null @x) {
+              attr (node0) x = #false
+            }
+            if (not (is-null @y)) {
+              attr (node0) y = #true
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            x: #false
+            y: #true
+        "#},
+    );
+}
+
+#[test]
+fn skip_errors_in_branches_not_taken() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module (import_statement)? @x)
+          {
+            node node0
+            if some @x {
+              attr (node0) val = (parse-int "not a number")
+            } elif #false {
+              attr (node0) val = (int-to-char -1)
+            } else {
+              attr (node0) val = 1
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            val: 1
+        "#},
+    );
+}
+
 #[test]
 fn skip_if_without_true_conditions() {
     check_execution(
@@ -814,6 +1049,134 @@ fn variables_are_inherited_in_for_in_body() {
     );
 }
 
+#[test]
+fn can_execute_for_in_function_result() {
+    check_execution(
+        r#"
+          pass
+        "#,
+        indoc! {r#"
+          (module)
+          {
+            var current = (node)
+            attr (current) name = "root"
+            for part in (concat ["a", "b"] ["c"]) {
+              let next = (node)
+              attr (next) name = part
+              edge current -> next
+              set current = next
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            name: "root"
+          edge 0 -> 1
+          node 1
+            name: "a"
+          edge 1 -> 2
+          node 2
+            name: "b"
+          edge 2 -> 3
+          node 3
+            name: "c"
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_for_in_empty_function_result() {
+    check_execution(
+        r#"
+          pass
+        "#,
+        indoc! {r#"
+          (module)
+          {
+            var n = 0
+            for x in (concat) {
+              set n = (plus n 1)
+            }
+            node node0
+            attr (node0) val = n
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            val: 0
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_for_in_with_index() {
+    check_execution(
+        r#"
+          pass
+        "#,
+        indoc! {r#"
+          (module) @mod
+          {
+            var current = (node)
+            attr (current) name = "root"
+            let parts = (split "pkg/sub/mod.py" "/")
+            for i, part in parts {
+              if (not (last? parts i)) {
+                let next = (node)
+                attr (next) name = part, index = i
+                edge current -> next
+                set current = next
+              } elif (ends-with part ".py") {
+                let next = (node)
+                attr (next) name = (substring part 0 (index-of part ".py")), index = i
+                edge current -> next
+                let @mod.root = next
+              }
+            }
+          }
+
+          (module) @mod
+          {
+            attr (@mod.root) is_root = #true
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            name: "root"
+          edge 0 -> 1
+          node 1
+            index: 0
+            name: "pkg"
+          edge 1 -> 2
+          node 2
+            index: 1
+            name: "sub"
+          edge 2 -> 3
+          node 3
+            index: 2
+            is_root: #true
+            name: "mod"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_execute_for_in_non_list() {
+    fail_execution(
+        r#"
+          pass
+        "#,
+        indoc! {r#"
+          (module)
+          {
+            for x in "abc" {
+              node n
+            }
+          }
+        "#},
+    );
+}
+
 #[test]
 fn can_execute_list_comprehension() {
     check_execution(
@@ -1018,7 +1381,7 @@ fn can_set_mutable_local_variables() {
 
 #[test]
 fn scoped_variables_can_appear_out_of_order() {
-    check_execution(
+    check_lazy_only_execution(
         indoc! {r#"
           import a
           from b import c
@@ -1056,7 +1419,7 @@ fn scoped_variables_can_appear_out_of_order() {
 
 #[test]
 fn variables_can_be_scoped_in_arbitrary_expressions() {
-    check_execution(
+    check_lazy_only_execution(
         indoc! {r#"
           import a
           from b import c
@@ -1435,54 +1798,187 @@ fn variable_set_executed_once() {
 }
 
 #[test]
-fn can_execute_shorthand() {
+fn can_execute_scan_of_syntax_node() {
     check_execution(
-        indoc! { r#"
+        r#"
           def get_f():
             pass
-        "#},
+        "#,
         indoc! {r#"
-            attribute def = x => source_node = x, symbol = (source-text x)
-            (function_definition name: (identifier) @name) {
-              node n
-              attr (n) def = @name
+          (function_definition
+            name: (identifier) @name)
+          {
+            node n
+            scan @name {
+              "get_(.*)" {
+                attr (n) suffix = $1
+              }
             }
+          }
         "#},
         indoc! {r#"
           node 0
-            source_node: [syntax node identifier (1, 5)]
-            symbol: "get_f"
+            suffix: "f"
         "#},
     );
 }
 
 #[test]
-fn can_access_inherited_attribute() {
+fn can_execute_scan_of_list_of_syntax_nodes() {
     check_execution(
-        indoc! { r#"
-          def get_f():
-            pass
-        "#},
+        "get_a\nb\nget_c\n",
         indoc! {r#"
-            inherit .test
-            (function_definition)@def {
-              node @def.test
-              attr (@def.test) in_def
-            }
-            (pass_statement)@pass {
-              attr (@pass.test) in_pass
+          (module (_)* @stmts)
+          {
+            scan @stmts {
+              "get_(.*)" {
+                node n
+                attr (n) name = $1
+              }
             }
+          }
         "#},
         indoc! {r#"
           node 0
-            in_def: #true
-            in_pass: #true
+            name: "a"
+          node 1
+            name: "c"
         "#},
     );
 }
 
+// The minimal regex engine does not support named groups.
+#[cfg(not(feature = "lite-regex"))]
 #[test]
-fn can_overwrite_inherited_attribute() {
+fn can_execute_scan_with_named_groups_and_match_offsets() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            scan "dé/ñ/mañana.rs" {
+              "(?P<dir>[^/]+)/" {
+                node n
+                attr (n) dir = $dir, group = $1, start = $match_start, end = $match_end
+              }
+              "([^/.]+)\\.(?P<ext>\\w+)$" {
+                node n
+                attr (n) stem = $1, ext = $ext, start = $match_start, end = $match_end
+              }
+            }
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            dir: "dé"
+            end: 3
+            group: "dé"
+            start: 0
+          node 1
+            dir: "ñ"
+            end: 5
+            group: "ñ"
+            start: 3
+          node 2
+            end: 14
+            ext: "rs"
+            start: 5
+            stem: "mañana"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_scan_non_string_values() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            scan [1] {
+              "1" {
+                node n
+              }
+            }
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_shorthand() {
+    check_execution(
+        indoc! { r#"
+          def get_f():
+            pass
+        "#},
+        indoc! {r#"
+            attribute def = x => source_node = x, symbol = (source-text x)
+            (function_definition name: (identifier) @name) {
+              node n
+              attr (n) def = @name
+            }
+        "#},
+        indoc! {r#"
+          node 0
+            source_node: [syntax node identifier (1, 5)]
+            symbol: "get_f"
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_shorthand_without_value() {
+    check_execution(
+        indoc! { r#"
+          def get_f():
+            pass
+        "#},
+        indoc! {r#"
+            attribute def_attrs => kind = "definition", is_exported = #true
+            attribute pos_attrs = n => row = (start-row n), def_attrs
+            (function_definition name: (identifier) @name) {
+              node n
+              attr (n) pos_attrs = @name, name = (source-text @name)
+            }
+        "#},
+        indoc! {r#"
+          node 0
+            is_exported: #true
+            kind: "definition"
+            name: "get_f"
+            row: 0
+        "#},
+    );
+}
+
+#[test]
+fn can_access_inherited_attribute() {
+    check_execution(
+        indoc! { r#"
+          def get_f():
+            pass
+        "#},
+        indoc! {r#"
+            inherit .test
+            (function_definition)@def {
+              node @def.test
+              attr (@def.test) in_def
+            }
+            (pass_statement)@pass {
+              attr (@pass.test) in_pass
+            }
+        "#},
+        indoc! {r#"
+          node 0
+            in_def: #true
+            in_pass: #true
+        "#},
+    );
+}
+
+#[test]
+fn can_overwrite_inherited_attribute() {
     check_execution(
         indoc! { r#"
           def get_f():
@@ -1591,3 +2087,1049 @@ fn cannot_change_attribute_value() {
         "#},
     );
 }
+
+#[test]
+fn can_filter_matches_with_global_predicates() {
+    check_execution(
+        indoc! {r#"
+          test.py
+          other.py
+        "#},
+        indoc! {r#"
+          global filename
+          global prefix = "^o"
+          ((attribute) @attr (#eq-global? @attr filename)) {
+            node n
+            attr (n) text = (source-text @attr)
+          }
+          ((identifier) @id (#match-global? @id prefix)) {
+            node n
+            attr (n) text = (source-text @id)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            text: "test.py"
+          node 1
+            text: "other"
+        "#},
+    );
+}
+
+#[test]
+fn can_filter_matches_with_text_predicates() {
+    check_execution(
+        indoc! {r#"
+          def f(self, x, cls, y):
+            pass
+        "#},
+        indoc! {r#"
+          ((identifier) @id (#any-of? @id "self" "cls")) {
+            node n
+            attr (n) receiver = (source-text @id)
+          }
+          ((identifier) @id (#not-any-of? @id "self" "cls" "f")) {
+            node n
+            attr (n) parameter = (source-text @id)
+          }
+          ((identifier) @id (#eq? @id "x")) {
+            node n
+            attr (n) eq = (source-text @id)
+          }
+          ((identifier) @id (#match? @id "^[fy]$") (#not-eq? @id "f")) {
+            node n
+            attr (n) match = (source-text @id)
+          }
+          ((identifier) @id (#not-match? @id "[a-z]{2}")) {
+            node n
+            attr (n) short = (source-text @id)
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            short: "f"
+          node 1
+            receiver: "self"
+          node 2
+            parameter: "x"
+          node 3
+            eq: "x"
+          node 4
+            short: "x"
+          node 5
+            receiver: "cls"
+          node 6
+            parameter: "y"
+          node 7
+            match: "y"
+          node 8
+            short: "y"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_use_undefined_global_in_predicate() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          ((module) @mod (#eq-global? @mod undefined)) {
+            node n
+            attr (n) source = @mod
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_append_to_attributes() {
+    check_execution(
+        indoc! {r#"
+          a = 1
+          b = 2
+        "#},
+        indoc! {r#"
+          (module) @m
+          {
+            node @m.node
+          }
+          (module (expression_statement (assignment left: (identifier) @name))) @m
+          {
+            attr (@m.node) tags += (source-text @name)
+          }
+          (module) @m
+          {
+            attr (@m.node) tags += "last"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            tags: ["a", "b", "last"]
+        "#},
+    );
+}
+
+#[test]
+fn cannot_append_to_non_list_attribute() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) tags = "first"
+            attr (n) tags += "second"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(
+        message.contains("Cannot append to non-list attribute tags on"),
+        "{}",
+        message
+    );
+    assert!(message.contains("(4, 3)"), "{}", message);
+    assert!(message.contains("(5, 3)"), "{}", message);
+}
+
+#[test]
+fn can_override_attributes() {
+    check_execution(
+        indoc! {r#"
+          f(x)
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) kind = "reference", name = (source-text @id)
+            edge @id.node -> @id.node
+            attr (@id.node -> @id.node) precedence = 0
+          }
+          (call function: (identifier) @id)
+          {
+            attr (@id.node) kind := "call"
+            attr (@id.node -> @id.node) precedence := 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "call"
+            name: "f"
+          edge 0 -> 0
+            precedence: 1
+          node 1
+            kind: "reference"
+            name: "x"
+          edge 1 -> 1
+            precedence: 0
+        "#},
+    );
+}
+
+#[test]
+fn can_set_attributes_to_equal_values() {
+    check_execution(
+        indoc! {r#"
+          f(x)
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) kind = "name", parts = [1, "two"], self = @id.node
+            edge @id.node -> @id.node
+            attr (@id.node -> @id.node) precedence = 0
+          }
+          (call function: (identifier) @id)
+          {
+            attr (@id.node) kind = "name", parts = [1, "two"], self = @id.node
+            attr (@id.node -> @id.node) precedence = 0
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "name"
+            parts: [1, "two"]
+            self: [graph node 0]
+          edge 0 -> 0
+            precedence: 0
+          node 1
+            kind: "name"
+            parts: [1, "two"]
+            self: [graph node 1]
+          edge 1 -> 1
+            precedence: 0
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_attributes_to_different_values() {
+    let error = execute(
+        indoc! {r#"
+          f(x)
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) kind = "name"
+          }
+          (call function: (identifier) @id)
+          {
+            attr (@id.node) kind = "call"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(message.contains("Duplicate attribute"), "{}", message);
+    assert!(message.contains("(4, 3)"), "{}", message);
+    assert!(message.contains("(8, 3)"), "{}", message);
+}
+
+#[test]
+fn cannot_override_attribute_that_is_not_set() {
+    let error = execute(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node n
+            attr (n) kind := "call"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(
+        message.contains("Cannot override attribute that is not set kind on"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn can_remove_and_re_add_attributes() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module) @m
+          {
+            node @m.node
+            attr (@m.node) kind = "reference", name = "m"
+            edge @m.node -> @m.node
+            attr (@m.node -> @m.node) precedence = 0
+          }
+          (module) @m
+          {
+            delattr (@m.node) kind, name
+            delattr (@m.node -> @m.node) precedence
+          }
+          (module) @m
+          {
+            attr (@m.node) kind = "definition"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "definition"
+          edge 0 -> 0
+        "#},
+    );
+}
+
+#[test]
+fn can_instantiate_node_types() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          nodetype definition { kind = "definition", is_exported = #false }
+
+          (module)
+          {
+            node n : definition
+            attr (n) is_exported = #true
+            node m : definition
+            attr (m) kind = "reference", kind = "reference"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            is_exported: #true
+            kind: "definition"
+          node 1
+            is_exported: #false
+            kind: "reference"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_conflicting_attributes_on_node_type_instances() {
+    fail_execution(
+        "pass",
+        indoc! {r#"
+          nodetype definition { kind = "definition" }
+
+          (module)
+          {
+            node n : definition
+            attr (n) kind = "reference"
+            attr (n) kind = "import"
+          }
+        "#},
+    );
+}
+
+#[test]
+fn can_execute_phases_in_order() {
+    check_execution(
+        indoc! {r#"
+          a = 1
+          b = 2
+        "#},
+        indoc! {r#"
+          phase declare
+          (identifier) @id
+          {
+            node n
+            attr (n) name = (source-text @id)
+          }
+
+          phase connect
+          (module)
+          {
+            node n
+            attr (n) kind = "module"
+          }
+
+          phase declare
+          (module)
+          {
+            node n
+            attr (n) kind = "declared module"
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "declared module"
+          node 1
+            name: "a"
+          node 2
+            name: "b"
+          node 3
+            kind: "module"
+        "#},
+    );
+}
+
+#[test]
+fn can_add_attributes_to_several_targets() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node a
+            node b
+            edge a -> b
+            edge b -> a
+            attr (a, b) kind = "definition", lang = "python"
+            attr (a -> b, b -> a) precedence = 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "definition"
+            lang: "python"
+          edge 0 -> 1
+            precedence: 1
+          node 1
+            kind: "definition"
+            lang: "python"
+          edge 1 -> 0
+            precedence: 1
+        "#},
+    );
+}
+
+#[test]
+fn can_add_inline_attributes_to_nodes_and_edges() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node a, kind = "definition", names = ["a"]
+            node b, kind = "reference"
+            edge a -> b, precedence = 10, kind = "member"
+            attr (a) names += "b"
+            attr (a -> b) precedence := 11
+            edge b -> a,
+              precedence = 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            kind: "definition"
+            names: ["a", "b"]
+          edge 0 -> 1
+            kind: "member"
+            precedence: 11
+          node 1
+            kind: "reference"
+          edge 1 -> 0
+            precedence: 1
+        "#},
+    );
+}
+
+#[test]
+fn can_create_reversed_edges() {
+    check_execution(
+        "pass",
+        indoc! {r#"
+          (module)
+          {
+            node scope
+            node ref
+            edge scope <- ref, kind = "parent"
+            attr (scope <- ref) precedence = 1
+          }
+        "#},
+        indoc! {r#"
+          node 0
+          node 1
+          edge 1 -> 0
+            kind: "parent"
+            precedence: 1
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_inline_attributes_again() {
+    for (statement, name) in [
+        (r#"attr (a) kind = "reference""#, "kind"),
+        (r#"attr (a -> b) precedence = 2"#, "precedence"),
+        (r#"edge a -> b, precedence = 2"#, "precedence"),
+    ] {
+        let error = execute(
+            "pass",
+            &format!(
+                "(module) {{ node a, kind = \"definition\" node b edge a -> b, precedence = 1 {} }}",
+                statement
+            ),
+        )
+        .unwrap_err();
+        let message = error.to_string();
+        assert!(
+            message.contains("Duplicate attribute") && message.contains(name),
+            "unexpected error: {}",
+            message
+        );
+    }
+}
+
+#[test]
+fn cannot_add_conflicting_attributes_to_several_targets() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module)
+          {
+            node a
+            node b
+            attr (b) lang = "rust"
+            attr (a, b) kind = "definition", lang = "python"
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(true);
+    let mut graph = Graph::new();
+    let error = file
+        .execute_into(&mut graph, &tree, "pass", &config, &NoCancellation)
+        .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(message.contains("lang on"), "{}", message);
+    // Targets are updated in order, and the first target keeps its attributes.
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            kind: "definition"
+            lang: "python"
+          node 1
+            kind: "definition"
+            lang: "python"
+        "#}
+    );
+}
+
+/// Rejects edges to graph nodes without a kind, and drops internal attributes.
+struct RequireKind;
+
+impl GraphMutationHook for RequireKind {
+    fn before_edge(
+        &self,
+        _source: GraphNodeRef,
+        sink: GraphNodeRef,
+        ctx: &MutationContext,
+    ) -> Decision {
+        match ctx.graph()[sink].attributes.get("kind") {
+            Some(_) => Decision::Allow,
+            None => Decision::Reject(format!("{} has no kind", sink)),
+        }
+    }
+
+    fn before_attr(
+        &self,
+        _target: MutationTarget,
+        name: &Identifier,
+        _value: &Value,
+        _ctx: &MutationContext,
+    ) -> Decision {
+        if name.as_str() == "internal" {
+            Decision::Skip
+        } else {
+            Decision::Allow
+        }
+    }
+}
+
+fn execute_with_hook(dsl_source: &str) -> Result<String, ExecutionError> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse("pass", None).unwrap();
+    let file =
+        File::from_str(tree_sitter_python::language(), dsl_source).expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let hook = RequireKind;
+    let config = ExecutionConfig::new(&functions, &globals)
+        .lazy(true)
+        .mutation_hook(&hook);
+    let graph = file.execute(&tree, "pass", &config, &NoCancellation)?;
+    let result = graph.pretty_print().to_string();
+    Ok(result)
+}
+
+#[test]
+fn can_skip_mutations_with_hook() {
+    let graph = execute_with_hook(indoc! {r#"
+      nodetype definition { kind = "definition" }
+
+      (module)
+      {
+        node a
+        node b : definition
+        edge a -> b
+        attr (a) internal = #true, name = "a"
+      }
+    "#})
+    .expect("Could not execute file");
+    assert_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+            name: "a"
+          edge 0 -> 1
+          node 1
+            kind: "definition"
+        "#}
+    );
+}
+
+#[test]
+fn cannot_violate_mutation_hook() {
+    let error = execute_with_hook(indoc! {r#"
+      (module)
+      {
+        node a
+        node b
+        edge a -> b
+      }
+    "#})
+    .expect_err("Execution succeeded unexpectedly");
+    let message = format!("{}", error);
+    assert!(
+        message.contains("Graph mutation rejected: [graph node 1] has no kind"),
+        "{}",
+        message
+    );
+    assert!(message.contains("(5, 3)"), "{}", message);
+}
+
+/// Records the mutations of the graph as strings.
+#[derive(Default)]
+struct RecordMutations(Vec<String>);
+
+impl GraphObserver for RecordMutations {
+    fn on_node_created(
+        &mut self,
+        node: GraphNodeRef,
+        syntax_node: SyntaxNodeRef,
+        ctx: &MutationContext,
+    ) {
+        self.0.push(format!(
+            "node {} for {} in stanza {}",
+            node.index(),
+            ctx.graph()[syntax_node].kind(),
+            ctx.stanza_location().row + 1,
+        ));
+    }
+
+    fn on_edge_created(&mut self, source: GraphNodeRef, sink: GraphNodeRef, ctx: &MutationContext) {
+        self.0.push(format!(
+            "edge {} -> {} at line {}",
+            source.index(),
+            sink.index(),
+            ctx.location().row + 1,
+        ));
+    }
+
+    fn on_attr_added(
+        &mut self,
+        target: MutationTarget,
+        name: &Identifier,
+        value: &Value,
+        ctx: &MutationContext,
+    ) {
+        let target = match target {
+            MutationTarget::GraphNode(node) => format!("{}", node.index()),
+            MutationTarget::Edge(source, sink) => format!("{} -> {}", source.index(), sink.index()),
+        };
+        self.0.push(format!(
+            "attr ({}) {} = {} at line {}",
+            target,
+            name,
+            value,
+            ctx.location().row + 1,
+        ));
+    }
+}
+
+#[test]
+fn can_observe_mutations_in_statement_order() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let source = "pass";
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (module) @m
+          {
+            node a
+            node b
+            attr (a) name = "a"
+            edge a -> b
+            attr (a -> b) precedence = 1
+            attr (b) name = "b"
+            attr (a) name := "c"
+            let @m.b = b
+          }
+          (module (_) @s) @m
+          {
+            node @s.n
+            edge @m.b -> @s.n
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let observer = RefCell::new(RecordMutations::default());
+    let config = ExecutionConfig::new(&functions, &globals)
+        .lazy(true)
+        .observer(&observer);
+    file.execute(&tree, source, &config, &NoCancellation)
+        .expect("Could not execute file");
+    // Nodes are created first, then edges, and then attributes, each in the order of their
+    // statements.
+    assert_eq!(
+        observer.into_inner().0,
+        vec![
+            "node 0 for module in stanza 1",
+            "node 1 for module in stanza 1",
+            "node 2 for module in stanza 12",
+            "edge 0 -> 1 at line 6",
+            "edge 1 -> 2 at line 15",
+            "attr (0) name = a at line 5",
+            "attr (0 -> 1) precedence = 1 at line 7",
+            "attr (1) name = b at line 8",
+            "attr (0) name = c at line 9",
+        ]
+    );
+}
+
+#[test]
+fn can_set_mutable_scoped_variables_in_later_stanzas() {
+    check_execution(
+        indoc! {r#"
+          x = 1
+          def f(): pass
+        "#},
+        indoc! {r#"
+          (identifier) @id
+          {
+            var @id.kind = "unknown"
+          }
+
+          (identifier) @id
+          {
+            node guess
+            attr (guess) name = (source-text @id), guess = @id.kind
+          }
+
+          (assignment left: (identifier) @id)
+          {
+            set @id.kind = "variable"
+          }
+
+          (function_definition name: (identifier) @id)
+          {
+            set @id.kind = "function"
+          }
+
+          (identifier) @id
+          {
+            node result
+            attr (result) name = (source-text @id), kind = @id.kind
+          }
+        "#},
+        indoc! {r#"
+          node 0
+            guess: "unknown"
+            name: "x"
+          node 1
+            kind: "variable"
+            name: "x"
+          node 2
+            guess: "unknown"
+            name: "f"
+          node 3
+            kind: "function"
+            name: "f"
+        "#},
+    );
+}
+
+#[test]
+fn cannot_set_scoped_variable_that_is_not_declared_on_its_scope() {
+    let error = execute(
+        indoc! {r#"
+          x = 1
+          def f(): pass
+        "#},
+        indoc! {r#"
+          (assignment left: (identifier) @id)
+          {
+            var @id.kind = "variable"
+          }
+
+          (function_definition name: (identifier) @id)
+          {
+            set @id.kind = "function"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    assert!(
+        error.to_string().contains("Undefined scoped variable"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn cannot_set_immutable_scoped_variable() {
+    let error = execute(
+        indoc! {r#"
+          x = 1
+        "#},
+        indoc! {r#"
+          (module) @mod
+          {
+            var @mod.kind = "module"
+          }
+
+          (identifier) @id
+          {
+            let @id.kind = "variable"
+          }
+
+          (identifier) @id
+          {
+            set @id.kind = "constant"
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    assert!(
+        error
+            .to_string()
+            .contains("Cannot assign immutable variable"),
+        "unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn cannot_execute_cyclic_scoped_variables() {
+    let mut error = execute(
+        indoc! {r#"
+          pass
+        "#},
+        indoc! {r#"
+          (module) @root
+          {
+            let @root.a = @root.b
+          }
+
+          (module (pass_statement)) @root
+          {
+            let @root.b = @root.a
+          }
+        "#},
+    )
+    .expect_err("Execution succeeded unexpectedly");
+    while let ExecutionError::InContext(_, cause) = error {
+        error = *cause;
+    }
+    let steps = match error {
+        ExecutionError::DependencyCycle(steps) => steps,
+        error => panic!("unexpected error: {}", error),
+    };
+    assert_eq!(
+        steps,
+        vec![
+            DependencyCycleStep {
+                variable: "@root.a".into(),
+                statement_location: Location { row: 2, column: 2 },
+                node_kind: "module".into(),
+                node_location: Location { row: 0, column: 0 },
+            },
+            DependencyCycleStep {
+                variable: "@root.b".into(),
+                statement_location: Location { row: 7, column: 2 },
+                node_kind: "module".into(),
+                node_location: Location { row: 0, column: 0 },
+            },
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "resource-usage")]
+fn can_account_for_resource_usage() {
+    let source = "f(xy)\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            attr (@id.node) name = (source-text @id), parts = [1, 2, 3]
+            scan (source-text @id) {
+              "[a-z]" {
+                attr (@id.node) letter = #true
+              }
+            }
+          }
+          (call function: (identifier) @f arguments: (argument_list (identifier) @x))
+          {
+            edge @f.node -> @x.node
+            edge @f.node -> @x.node
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(true);
+    let (_, usage) = file
+        .execute_with_usage(&tree, source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    assert_eq!(usage.matches, 3);
+    assert_eq!(usage.statements, 11);
+    assert_eq!(usage.graph_nodes, 2);
+    assert_eq!(usage.edges, 1);
+    assert_eq!(usage.attributes, 7);
+    assert_eq!(usage.scan_iterations, 3);
+    assert_eq!(usage.regex_bytes_scanned, 4);
+    assert_eq!(
+        usage.function_calls.into_iter().collect::<Vec<_>>(),
+        vec![(Identifier::from("source-text"), 4)]
+    );
+    assert_eq!(usage.peak_list_length, 3);
+}
+
+#[test]
+#[cfg(feature = "resource-usage")]
+fn can_collect_statistics_per_stanza() {
+    let source = "f(xy)\n";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          (identifier) @id
+          {
+            node @id.node
+            scan (source-text @id) {
+              "[a-z]" {
+                attr (@id.node) letter = #true
+              }
+            }
+          }
+          (call function: (identifier) @f arguments: (argument_list (identifier) @x))
+          {
+            edge @f.node -> @x.node
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals).lazy(true);
+    let (_, usage) = file
+        .execute_with_usage(&tree, source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    let stanzas = &usage.statistics.stanzas;
+    assert_eq!(stanzas.len(), 2);
+    assert_eq!(stanzas[0].index, 0);
+    assert_eq!(stanzas[0].location, Location { row: 0, column: 0 });
+    assert_eq!(
+        (
+            stanzas[0].matches,
+            stanzas[0].graph_nodes,
+            stanzas[0].edges,
+            stanzas[0].scan_iterations
+        ),
+        (2, 2, 0, 3)
+    );
+    assert_eq!(stanzas[1].index, 1);
+    assert_eq!(stanzas[1].location, Location { row: 9, column: 0 });
+    assert_eq!(
+        (
+            stanzas[1].matches,
+            stanzas[1].graph_nodes,
+            stanzas[1].edges,
+            stanzas[1].scan_iterations
+        ),
+        (1, 0, 1, 0)
+    );
+    let table = usage.statistics.to_string();
+    let mut lines = table.lines();
+    assert!(lines.next().unwrap().starts_with("stanza"));
+    assert_eq!(lines.count(), 2);
+}
+
+#[test]
+fn can_locate_failing_statements() {
+    let python_source = indoc! {r#"
+      x = 1
+    "#};
+    let dsl_source = indoc! {r#"
+      (assignment left: (_) @left)
+      {
+        node n
+        attr (n) name = (plus (source-text @left) 1)
+      }
+    "#};
+    let error = execute(python_source, dsl_source).expect_err("Execution succeeded unexpectedly");
+    assert!(
+        matches!(error.root_cause(), ExecutionError::WrongArgumentType { .. }),
+        "unexpected error: {}",
+        error
+    );
+    let statement = error
+        .statement_context()
+        .expect("Missing statement context");
+    assert_eq!(statement.statement_location, Location { row: 3, column: 2 });
+    assert_eq!(statement.stanza_location, Location { row: 0, column: 0 });
+    assert_eq!(statement.source_location, Location { row: 0, column: 0 });
+    assert_eq!(
+        error
+            .display_pretty(
+                Path::new("test.py"),
+                python_source,
+                Path::new("test.tsg"),
+                dsl_source
+            )
+            .to_string(),
+        concat!(
+            "    0: Error executing statement attr (n) name = (plus (source-text @left) 1) at (4, 3)\n",
+            "       test.tsg:4:3:\n",
+            "       4 |   attr (n) name = (plus (source-text @left) 1)\n",
+            "         |   ^\n",
+            "       in stanza\n",
+            "       test.tsg:1:1:\n",
+            "       1 | (assignment left: (_) @left)\n",
+            "         | ^\n",
+            "       matching (assignment) node\n",
+            "       test.py:1:1:\n",
+            "       1 | x = 1\n",
+            "         | ^\n",
+            "    1: Error calling function plus\n",
+            "       test.tsg:4:19:\n",
+            "       4 |   attr (n) name = (plus (source-text @left) 1)\n",
+            "         |                   ^\n",
+            "    2: Function plus expects argument 1 to be number, got string\n",
+        )
+    );
+}
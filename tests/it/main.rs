@@ -5,10 +5,20 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "rayon")]
+mod bulk;
+mod determinism;
+mod diagnostics;
 mod execution;
+mod features;
 mod functions;
 mod graph;
+mod graph_query;
 mod lazy_execution;
+mod overlay;
 mod parse_errors;
 mod parser;
+mod prelude;
+mod regex_engine;
+mod testing;
 mod variables;
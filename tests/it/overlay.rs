@@ -0,0 +1,195 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use indoc::indoc;
+use tree_sitter::Parser;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::functions::Functions;
+use tree_sitter_graph::ExecutionConfig;
+use tree_sitter_graph::NoCancellation;
+use tree_sitter_graph::OverlayPolicy;
+use tree_sitter_graph::ParseError;
+use tree_sitter_graph::Variables;
+
+const PYTHON_SOURCE: &str = "import a\nx = 1\n";
+
+const BASE: &str = indoc! {r#"
+  stanza imports
+  (import_statement name: (dotted_name) @name) {
+    node @name.def
+    attr (@name.def) kind = "import"
+  }
+
+  stanza assignments
+  (assignment left: (identifier) @name) {
+    node @name.def
+    attr (@name.def) kind = "variable"
+  }
+
+  (module) @mod {
+    node @mod.root
+  }
+"#};
+
+fn parse(source: &str) -> File {
+    File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file")
+}
+
+fn execute(file: &File) -> String {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(PYTHON_SOURCE, None).unwrap();
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, PYTHON_SOURCE, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    let result = graph.pretty_print().to_string();
+    result
+}
+
+#[test]
+fn can_replace_stanzas_with_overlay() {
+    let mut file = parse(BASE);
+    assert_eq!(
+        execute(&file),
+        indoc! {r#"
+          node 0
+            kind: "import"
+          node 1
+            kind: "variable"
+          node 2
+        "#}
+    );
+
+    let overlay = parse(indoc! {r#"
+      stanza imports
+      (import_statement name: (dotted_name) @name) {
+        node @name.def
+        attr (@name.def) kind = "module-import", module = (source-text @name)
+      }
+
+      (module) @mod {
+        attr (@mod.root) imports = 1
+      }
+    "#});
+    file.apply_overlay(&overlay, OverlayPolicy::Replace)
+        .expect("Cannot apply overlay");
+    assert_eq!(
+        execute(&file),
+        indoc! {r#"
+          node 0
+            kind: "module-import"
+            module: "a"
+          node 1
+            kind: "variable"
+          node 2
+            imports: 1
+        "#}
+    );
+}
+
+#[test]
+fn can_append_stanzas_with_overlay() {
+    let mut file = parse(BASE);
+    let overlay = parse(indoc! {r#"
+      stanza imports
+      (import_statement name: (dotted_name) @name) {
+        attr (@name.def) module = (source-text @name)
+      }
+    "#});
+    file.apply_overlay(&overlay, OverlayPolicy::Append)
+        .expect("Cannot apply overlay");
+    assert_eq!(
+        execute(&file),
+        indoc! {r#"
+          node 0
+            kind: "import"
+            module: "a"
+          node 1
+            kind: "variable"
+          node 2
+        "#}
+    );
+}
+
+#[test]
+fn cannot_apply_overlay_with_unknown_stanza_name() {
+    let mut file = parse(BASE);
+    let overlay = parse(indoc! {r#"
+      stanza calls
+      (call) @call {
+        node @call.node
+      }
+    "#});
+    let err = file
+        .apply_overlay(&overlay, OverlayPolicy::Replace)
+        .expect_err("Expected overlay to fail");
+    assert!(matches!(err, ParseError::UnknownOverlayStanza(..)));
+    assert_eq!(
+        err.to_string(),
+        "Overlay stanza calls at (2, 1) does not override any stanza, available stanza names are: imports, assignments"
+    );
+    // the file is left unchanged
+    assert_eq!(file.stanzas.len(), 3);
+}
+
+#[test]
+fn overlay_is_checked_together_with_file() {
+    let mut file = parse(indoc! {r#"
+      stanza counter
+      (module) @mod {
+        var @mod.count = 0
+      }
+
+      (module) @mod {
+        set @mod.count = 1
+      }
+    "#});
+    let overlay = parse(indoc! {r#"
+      stanza counter
+      (module) @mod {
+        let @mod.count = 0
+      }
+    "#});
+    let err = file
+        .apply_overlay(&overlay, OverlayPolicy::Replace)
+        .expect_err("Expected overlay to fail");
+    assert!(matches!(err, ParseError::Check(_)), "{}", err);
+}
+
+#[test]
+fn file_is_unchanged_by_overlay_that_fails_check() {
+    let mut file = parse(indoc! {r#"
+      stanza counter
+      (module) @mod {
+        var @mod.count = 0
+      }
+
+      (module) @mod {
+        set @mod.count = 1
+        node n
+        attr (n) kind = "module"
+      }
+    "#});
+    let before = execute(&file);
+    let overlay = parse(indoc! {r#"
+      stanza counter
+      (module) @mod {
+        let @mod.count = 0
+      }
+    "#});
+    let err = file
+        .apply_overlay(&overlay, OverlayPolicy::Replace)
+        .expect_err("Expected overlay to fail");
+    assert!(matches!(err, ParseError::Check(_)), "{}", err);
+    // the file is left unchanged, and can still be executed
+    assert_eq!(file.stanzas.len(), 2);
+    assert_eq!(file.stanzas[0].statements.len(), 1);
+    assert_eq!(execute(&file), before);
+}
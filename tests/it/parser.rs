@@ -8,9 +8,14 @@
 use tree_sitter::CaptureQuantifier::*;
 
 use tree_sitter_graph::ast::*;
+use tree_sitter_graph::graph::Float;
+use tree_sitter_graph::graph::Value;
+use tree_sitter_graph::CheckWarning;
 use tree_sitter_graph::Identifier;
 use tree_sitter_graph::Location;
 use tree_sitter_graph::ParseError;
+use tree_sitter_graph::Variables;
+use tree_sitter_graph::MAX_NESTING_DEPTH;
 
 #[test]
 fn can_parse_blocks() {
@@ -51,6 +56,9 @@ fn can_parse_blocks() {
                     location: Location { row: 4, column: 15 }
                 }
                 .into(),
+                node_type: None,
+                defaults: vec![],
+                attributes: vec![],
                 location: Location { row: 4, column: 10 }
             }
             .into(),
@@ -70,6 +78,9 @@ fn can_parse_blocks() {
                     location: Location { row: 5, column: 21 }
                 }
                 .into(),
+                node_type: None,
+                defaults: vec![],
+                attributes: vec![],
                 location: Location { row: 5, column: 10 },
             }
             .into(),
@@ -94,6 +105,7 @@ fn can_parse_blocks() {
                     location: Location { row: 6, column: 30 },
                 }
                 .into(),
+                attributes: vec![],
                 location: Location { row: 6, column: 10 },
             }
             .into(),
@@ -120,7 +132,8 @@ fn can_parse_blocks() {
                 .into(),
                 attributes: vec![Attribute {
                     name: precedence,
-                    value: Expression::TrueLiteral
+                    value: Expression::TrueLiteral,
+                    operation: AttributeOperation::Set,
                 }],
                 location: Location { row: 7, column: 10 },
             }
@@ -145,10 +158,12 @@ fn can_parse_blocks() {
                     Attribute {
                         name: push.clone(),
                         value: String::from("str2").into(),
+                        operation: AttributeOperation::Set,
                     },
                     Attribute {
                         name: pop.clone(),
                         value: Expression::TrueLiteral,
+                        operation: AttributeOperation::Set,
                     },
                 ],
                 location: Location { row: 8, column: 10 },
@@ -462,6 +477,199 @@ fn can_parse_sets() {
     );
 }
 
+#[test]
+fn can_parse_maps() {
+    let source = r#"
+        (identifier)
+        {
+          let map1 = {name: "f", arity : 2,}
+          let map2 = {:}
+          let set = {}
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+
+    let values = file
+        .stanzas
+        .into_iter()
+        .flat_map(|s| s.statements)
+        .map(|s| match s {
+            Statement::DeclareImmutable(statement) => statement.value,
+            statement => panic!("unexpected statement {}", statement),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        vec![
+            MapLiteral {
+                entries: vec![
+                    (
+                        Identifier::from("name"),
+                        StringConstant {
+                            value: String::from("f")
+                        }
+                        .into()
+                    ),
+                    (
+                        Identifier::from("arity"),
+                        IntegerConstant { value: 2 }.into()
+                    ),
+                ],
+            }
+            .into(),
+            MapLiteral { entries: vec![] }.into(),
+            SetLiteral { elements: vec![] }.into(),
+        ]
+    );
+}
+
+#[test]
+fn cannot_parse_duplicate_map_keys() {
+    let source = r#"
+        (identifier)
+        {
+          let map = {name: "f", name: "g"}
+        }
+    "#;
+    match File::from_str(tree_sitter_python::language(), source) {
+        Err(ParseError::DuplicateMapKey(key, location)) => {
+            assert_eq!(key, "name");
+            assert_eq!(location, Location { row: 3, column: 32 });
+        }
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn can_parse_inline_attributes() {
+    let source = r#"
+        (identifier)
+        {
+          node a, kind = "definition"
+          node b
+          edge a -> b, precedence = 10, member
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+
+    let statements = file
+        .stanzas
+        .into_iter()
+        .flat_map(|s| s.statements)
+        .collect::<Vec<_>>();
+    let attributes = statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::CreateGraphNode(statement) => &statement.attributes,
+            Statement::CreateEdge(statement) => &statement.attributes,
+            statement => panic!("unexpected statement {}", statement),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        attributes,
+        vec![
+            &vec![Attribute {
+                name: Identifier::from("kind"),
+                value: StringConstant {
+                    value: String::from("definition")
+                }
+                .into(),
+                operation: AttributeOperation::Set,
+            }],
+            &vec![],
+            &vec![
+                Attribute {
+                    name: Identifier::from("precedence"),
+                    value: IntegerConstant { value: 10 }.into(),
+                    operation: AttributeOperation::Set,
+                },
+                Attribute {
+                    name: Identifier::from("member"),
+                    value: Expression::TrueLiteral,
+                    operation: AttributeOperation::Set,
+                },
+            ],
+        ]
+    );
+    assert_eq!(
+        statements[2].to_string(),
+        "edge a -> b, precedence = 10, member = true at (6, 11)"
+    );
+}
+
+#[test]
+fn can_parse_reversed_edges() {
+    let reversed = File::from_str(
+        tree_sitter_python::language(),
+        r#"
+        (identifier)
+        {
+          node a
+          node b
+          edge a <- b
+          attr (a <- b) precedence = 1
+          delattr (a<-b) precedence
+        }
+    "#,
+    )
+    .expect("Cannot parse file");
+    let forward = File::from_str(
+        tree_sitter_python::language(),
+        r#"
+        (identifier)
+        {
+          node a
+          node b
+          edge b -> a
+          attr (b -> a) precedence = 1
+          delattr (b -> a) precedence
+        }
+    "#,
+    )
+    .expect("Cannot parse file");
+
+    let display = |file: &File| {
+        file.stanzas[0]
+            .statements
+            .iter()
+            .map(|statement| statement.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(display(&reversed), display(&forward));
+    // the graph nodes keep the locations where they are written
+    match &reversed.stanzas[0].statements[2] {
+        Statement::CreateEdge(statement) => {
+            assert_eq!(
+                statement.source,
+                UnscopedVariable {
+                    name: Identifier::from("b"),
+                    location: Location { row: 5, column: 20 },
+                }
+                .into()
+            );
+        }
+        statement => panic!("unexpected statement {}", statement),
+    }
+
+    let error = File::from_str(
+        tree_sitter_python::language(),
+        r#"
+        (identifier)
+        {
+          node a
+          edge a <- b
+        }
+    "#,
+    )
+    .expect_err("Parse succeeded unexpectedly");
+    assert!(
+        matches!(error, ParseError::Check(_)),
+        "unexpected error: {}",
+        error
+    );
+    assert_eq!(error.location(), Some(Location { row: 4, column: 20 }));
+}
+
 #[test]
 fn can_parse_print() {
     let source = r#"
@@ -493,6 +701,229 @@ fn can_parse_print() {
     );
 }
 
+#[test]
+fn can_parse_float_constants() {
+    let source = r#"
+        (identifier)
+        {
+          print 1.5, 2, 0.25
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+
+    let statements = file
+        .stanzas
+        .into_iter()
+        .map(|s| s.statements)
+        .collect::<Vec<_>>();
+    let float = |value| FloatConstant {
+        value: Float::new(value).unwrap(),
+    };
+    assert_eq!(
+        statements,
+        vec![vec![Print {
+            values: vec![
+                float(1.5).into(),
+                IntegerConstant { value: 2 }.into(),
+                float(0.25).into(),
+            ],
+            location: Location { row: 3, column: 10 },
+        }
+        .into()]]
+    );
+}
+
+#[test]
+fn can_parse_negative_constants() {
+    let source = r#"
+        (identifier)
+        {
+          print -1, -0.5, -9223372036854775808
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+
+    let statements = file
+        .stanzas
+        .into_iter()
+        .map(|s| s.statements)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statements,
+        vec![vec![Print {
+            values: vec![
+                IntegerConstant { value: -1 }.into(),
+                FloatConstant {
+                    value: Float::new(-0.5).unwrap(),
+                }
+                .into(),
+                IntegerConstant { value: i64::MIN }.into(),
+            ],
+            location: Location { row: 3, column: 10 },
+        }
+        .into()]]
+    );
+}
+
+#[test]
+fn cannot_parse_unsupported_predicates() {
+    for (predicate, message) in [
+        (
+            r#"(#eq-lower? @id "x")"#,
+            "#eq-lower? is not supported, supported predicates are #eq?",
+        ),
+        (r#"(#is? local)"#, "#is? is not supported"),
+        (
+            r#"(#any-of? "x" @id)"#,
+            "#any-of? expects a capture followed by strings",
+        ),
+    ] {
+        let source = format!("((identifier) @id {})\n{{\n}}\n", predicate);
+        match File::from_str(tree_sitter_python::language(), &source) {
+            Err(ParseError::InvalidPredicate(error, location)) => {
+                assert!(error.starts_with(message), "unexpected error: {}", error);
+                assert_eq!(location, Location { row: 0, column: 0 });
+            }
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn cannot_parse_out_of_range_integer_constant() {
+    let source = r#"
+        (identifier)
+        {
+          print 9223372036854775808
+        }
+    "#;
+    match File::from_str(tree_sitter_python::language(), source) {
+        Err(ParseError::InvalidIntegerConstant(_, location)) => {
+            assert_eq!(location, Location { row: 3, column: 16 })
+        }
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn cannot_parse_minus_sign_without_digits() {
+    let source = r#"
+        (identifier)
+        {
+          print - 1
+        }
+    "#;
+    assert!(File::from_str(tree_sitter_python::language(), source).is_err());
+}
+
+#[test]
+fn cannot_parse_out_of_range_float_constant() {
+    let source = format!(
+        r#"
+        (identifier)
+        {{
+          print 1{}.0
+        }}
+    "#,
+        "0".repeat(400)
+    );
+    match File::from_str(tree_sitter_python::language(), &source) {
+        Err(ParseError::InvalidFloatConstant(_, location)) => {
+            assert_eq!(location, Location { row: 3, column: 16 })
+        }
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+// The minimal regex engine does not support named groups.
+#[cfg(not(feature = "lite-regex"))]
+#[test]
+fn can_parse_named_regex_captures_and_match_offsets() {
+    let source = r#"
+        (module)
+        {
+          scan "abc" {
+            "(a)(?P<rest>.*)" {
+              print $rest, $1, $match_start, $match_end
+            }
+          }
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+    let values = match &file.stanzas[0].statements[0] {
+        Statement::Scan(scan) => match &scan.arms[0].statements[0] {
+            Statement::Print(print) => print.values.clone(),
+            statement => panic!("unexpected statement {}", statement),
+        },
+        statement => panic!("unexpected statement {}", statement),
+    };
+    assert_eq!(
+        values,
+        vec![
+            RegexCapture {
+                match_index: 2,
+                name: Some(Identifier::from("rest")),
+                location: Location { row: 5, column: 20 },
+            }
+            .into(),
+            RegexCapture {
+                match_index: 1,
+                name: None,
+                location: Location { row: 5, column: 27 },
+            }
+            .into(),
+            MatchOffset {
+                end: false,
+                location: Location { row: 5, column: 31 },
+            }
+            .into(),
+            MatchOffset {
+                end: true,
+                location: Location { row: 5, column: 45 },
+            }
+            .into(),
+        ]
+    );
+}
+
+#[cfg(not(feature = "lite-regex"))]
+#[test]
+fn cannot_parse_undefined_regex_captures() {
+    for (expression, location) in [
+        ("$file", Location { row: 5, column: 33 }),
+        ("$2", Location { row: 5, column: 33 }),
+        ("$match_start", Location { row: 3, column: 16 }),
+    ] {
+        let source = format!(
+            r#"
+        (module)
+        {{
+          print {}
+          scan "abc" {{
+            "(?P<dir>a)" {{ print {} }}
+          }}
+        }}
+    "#,
+            if expression.starts_with("$match") {
+                expression
+            } else {
+                "1"
+            },
+            expression,
+        );
+        match File::from_str(tree_sitter_python::language(), &source) {
+            Err(error @ ParseError::Check(_)) => {
+                assert_eq!(
+                    error.to_string(),
+                    format!("Undefined regex capture {} at {}", expression, location)
+                );
+                assert_eq!(error.location(), Some(location));
+            }
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+}
+
 #[test]
 fn cannot_parse_nullable_regex() {
     let source = r#"
@@ -510,6 +941,55 @@ fn cannot_parse_nullable_regex() {
     }
 }
 
+#[test]
+fn can_recover_from_errors_in_stanzas() {
+    let source = r#"
+        (module) {
+          node n
+        }
+        (identifier) {
+          print "unterminated
+          let x =
+        }
+        (unknown_node_type) {
+          print "{"
+        }
+        (integer) {
+          node n
+        }
+        (identifier) {
+          attr (n
+        }
+    "#;
+    let (file, errors) = File::from_str_with_recovery(tree_sitter_python::language(), source)
+        .expect("Cannot parse file");
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert!(matches!(errors[1], ParseError::QueryError(..)));
+    assert_eq!(file.stanzas.len(), 2);
+    assert_eq!(
+        file.invalid_stanzas
+            .iter()
+            .map(|range| range.start.row)
+            .collect::<Vec<_>>(),
+        vec![4, 8, 14]
+    );
+
+    // Without recovery, the first error fails the whole file.
+    assert!(File::from_str(tree_sitter_python::language(), source).is_err());
+}
+
+#[test]
+fn cannot_recover_from_missing_closing_brace() {
+    let source = r#"
+        (module) {
+          node n
+        }
+        (identifier) {
+          attr (n
+    "#;
+    assert!(File::from_str_with_recovery(tree_sitter_python::language(), source).is_err());
+}
+
 #[test]
 fn can_parse_star_capture() {
     let source = r#"
@@ -999,6 +1479,7 @@ fn can_parse_for_in() {
                 name: x.clone(),
                 location: Location { row: 3, column: 14 }
             },
+            index: None,
             value: Capture {
                 quantifier: ZeroOrMore,
                 name: xs.clone(),
@@ -1038,48 +1519,151 @@ fn cannot_parse_for_in_optional_capture() {
 }
 
 #[test]
-fn cannot_parse_scan_of_nonlocal_call_expression() {
+fn cannot_parse_for_in_single_capture() {
     let source = r#"
-      (function_definition
-      name: (identifier) @name)
-      {
-        node n
-        scan (source-text @name.val) {
-          "get_.*" {
-            attr (n) is_getter = #true
+        (module (_) @x)
+        {
+          for y in @x {
+            node n
           }
         }
-      }
     "#;
-    if let Ok(_) = File::from_str(tree_sitter_python::language(), source) {
-        panic!("Parse succeeded unexpectedly");
-    }
+    let error = File::from_str(tree_sitter_python::language(), source).unwrap_err();
+    assert!(
+        error.to_string().contains("Expected list value"),
+        "unexpected error: {}",
+        error
+    );
 }
 
 #[test]
-fn cannot_parse_scan_of_nonlocal_variable() {
+fn can_parse_for_in_with_index() {
     let source = r#"
-      (function_definition
-      name: (identifier) @name)
-      {
-        node n
-        let val = (source-text @name.val)
-        scan val {
-          "get_.*" {
-            attr (n) is_getter = #true
+        (module (_)* @xs)
+        {
+          for i, x in @xs {
+            print i, x
           }
         }
-      }
     "#;
-    if let Ok(_) = File::from_str(tree_sitter_python::language(), source) {
-        panic!("Parse succeeded unexpectedly");
-    }
-}
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
 
-#[test]
-fn can_parse_list_comprehension() {
-    let source = r#"
-        (module (_)* @xs)
+    let statements = file
+        .stanzas
+        .into_iter()
+        .map(|s| s.statements)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statements,
+        vec![vec![ForIn {
+            variable: UnscopedVariable {
+                name: "x".into(),
+                location: Location { row: 3, column: 17 }
+            },
+            index: Some(UnscopedVariable {
+                name: "i".into(),
+                location: Location { row: 3, column: 14 }
+            }),
+            value: Capture {
+                quantifier: ZeroOrMore,
+                name: "xs".into(),
+                file_capture_index: 0,
+                stanza_capture_index: 0,
+                location: Location { row: 3, column: 22 },
+            }
+            .into(),
+            statements: vec![Print {
+                values: vec![
+                    UnscopedVariable {
+                        name: "i".into(),
+                        location: Location { row: 4, column: 18 },
+                    }
+                    .into(),
+                    UnscopedVariable {
+                        name: "x".into(),
+                        location: Location { row: 4, column: 21 },
+                    }
+                    .into()
+                ],
+                location: Location { row: 4, column: 12 }
+            }
+            .into()],
+            location: Location { row: 3, column: 10 }
+        }
+        .into()]]
+    );
+}
+
+#[test]
+fn cannot_parse_for_in_with_same_index_and_variable() {
+    let source = r#"
+        (module (_)* @xs)
+        {
+          for x, x in @xs {
+            node n
+          }
+        }
+    "#;
+    File::from_str(tree_sitter_python::language(), source)
+        .expect_err("Parse succeeded unexpectedly");
+}
+
+#[test]
+fn can_parse_for_in_function_call() {
+    let source = r#"
+        (module)
+        {
+          for x in (concat [1] [2]) {
+            node n
+          }
+        }
+    "#;
+    File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+}
+
+#[test]
+fn cannot_parse_scan_of_nonlocal_call_expression() {
+    let source = r#"
+      (function_definition
+      name: (identifier) @name)
+      {
+        node n
+        scan (source-text @name.val) {
+          "get_.*" {
+            attr (n) is_getter = #true
+          }
+        }
+      }
+    "#;
+    if let Ok(_) = File::from_str(tree_sitter_python::language(), source) {
+        panic!("Parse succeeded unexpectedly");
+    }
+}
+
+#[test]
+fn cannot_parse_scan_of_nonlocal_variable() {
+    let source = r#"
+      (function_definition
+      name: (identifier) @name)
+      {
+        node n
+        let val = (source-text @name.val)
+        scan val {
+          "get_.*" {
+            attr (n) is_getter = #true
+          }
+        }
+      }
+    "#;
+    if let Ok(_) = File::from_str(tree_sitter_python::language(), source) {
+        panic!("Parse succeeded unexpectedly");
+    }
+}
+
+#[test]
+fn can_parse_list_comprehension() {
+    let source = r#"
+        (module (_)* @xs)
         {
           print [ (named-child-index x) for x in @xs ]
         }
@@ -1102,7 +1686,8 @@ fn can_parse_list_comprehension() {
                             name: "x".into(),
                             location: Location { row: 3, column: 37 }
                         }
-                        .into()]
+                        .into()],
+                        location: Location { row: 3, column: 18 }
                     }
                     .into()
                 ),
@@ -1155,7 +1740,8 @@ fn can_parse_set_comprehension() {
                             name: "x".into(),
                             location: Location { row: 3, column: 37 }
                         }
-                        .into()]
+                        .into()],
+                        location: Location { row: 3, column: 18 }
                     }
                     .into()
                 ),
@@ -1218,6 +1804,9 @@ fn can_parse_global() {
                     location: Location { row: 4, column: 15 },
                 }
                 .into(),
+                node_type: None,
+                defaults: vec![],
+                attributes: vec![],
                 location: Location { row: 4, column: 10 },
             }
             .into(),
@@ -1232,6 +1821,7 @@ fn can_parse_global() {
                     location: Location { row: 5, column: 20 },
                 }
                 .into(),
+                attributes: vec![],
                 location: Location { row: 5, column: 10 },
             }
             .into(),
@@ -1329,6 +1919,7 @@ fn can_parse_list_global() {
                 location: Location { row: 4, column: 14 },
             }
             .into(),
+            index: None,
             value: UnscopedVariable {
                 name: "roots".into(),
                 location: Location { row: 4, column: 22 },
@@ -1341,6 +1932,9 @@ fn can_parse_list_global() {
                         location: Location { row: 5, column: 17 },
                     }
                     .into(),
+                    node_type: None,
+                    defaults: vec![],
+                    attributes: vec![],
                     location: Location { row: 5, column: 12 },
                 }
                 .into(),
@@ -1355,6 +1949,7 @@ fn can_parse_list_global() {
                         location: Location { row: 6, column: 22 },
                     }
                     .into(),
+                    attributes: vec![],
                     location: Location { row: 6, column: 12 },
                 }
                 .into(),
@@ -1413,6 +2008,9 @@ fn can_parse_optional_global() {
                             location: Location { row: 5, column: 17 },
                         }
                         .into(),
+                        node_type: None,
+                        defaults: vec![],
+                        attributes: vec![],
                         location: Location { row: 5, column: 12 },
                     }
                     .into(),
@@ -1427,6 +2025,7 @@ fn can_parse_optional_global() {
                             location: Location { row: 6, column: 22 },
                         }
                         .into(),
+                        attributes: vec![],
                         location: Location { row: 6, column: 12 },
                     }
                     .into(),
@@ -1497,10 +2096,10 @@ fn can_parse_shorthand() {
         shorthands,
         vec![AttributeShorthand {
             name: "def".into(),
-            variable: UnscopedVariable {
+            variable: Some(UnscopedVariable {
                 name: "x".into(),
                 location: Location { row: 1, column: 24 }
-            },
+            }),
             attributes: vec![
                 Attribute {
                     name: "source_node".into(),
@@ -1508,7 +2107,8 @@ fn can_parse_shorthand() {
                         name: "x".into(),
                         location: Location { row: 1, column: 43 }
                     }
-                    .into()
+                    .into(),
+                    operation: AttributeOperation::Set,
                 },
                 Attribute {
                     name: "symbol".into(),
@@ -1518,9 +2118,11 @@ fn can_parse_shorthand() {
                             name: "x".into(),
                             location: Location { row: 1, column: 68 }
                         }
-                        .into()]
+                        .into()],
+                        location: Location { row: 1, column: 55 }
                     }
                     .into(),
+                    operation: AttributeOperation::Set,
                 }
             ],
             location: Location { row: 1, column: 18 }
@@ -1528,6 +2130,104 @@ fn can_parse_shorthand() {
     );
 }
 
+#[test]
+fn can_parse_shorthand_without_variable() {
+    let source = r#"
+        attribute def => kind = "definition"
+        (function_definition) {
+          node n
+          attr (n) def
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+
+    let shorthands = file.shorthands.into_iter().collect::<Vec<_>>();
+    assert_eq!(
+        shorthands,
+        vec![AttributeShorthand {
+            name: "def".into(),
+            variable: None,
+            attributes: vec![Attribute {
+                name: "kind".into(),
+                value: StringConstant {
+                    value: "definition".into()
+                }
+                .into(),
+                operation: AttributeOperation::Set,
+            }],
+            location: Location { row: 1, column: 18 }
+        }]
+    );
+}
+
+#[test]
+fn cannot_parse_recursive_shorthand() {
+    let source = r#"
+        attribute def => kind = "definition", ref
+        attribute ref => def
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert!(error.ends_with("expands to itself"), "{}", error);
+}
+
+#[test]
+fn cannot_parse_shorthand_that_conflicts_with_attribute() {
+    let source = r#"
+        attribute def => kind = "definition", is_exported = #true
+        (function_definition) {
+          node n
+          attr (n) def, kind = "function"
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Attribute kind is set twice at (5, 11), by shorthand def defined at (2, 19) and by the statement"
+    );
+}
+
+#[test]
+fn can_parse_shorthand_that_is_appended_to() {
+    let source = r#"
+        attribute def => tags += "definition"
+        (function_definition) {
+          node n
+          attr (n) def, tags += "function"
+        }
+    "#;
+    File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+}
+
+#[test]
+fn cannot_parse_shorthand_with_undefined_variable() {
+    let source = r#"
+        attribute def = x => source_node = y
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(error, "Undefined variable y at (2, 44)");
+}
+
+#[test]
+fn cannot_parse_shorthand_with_capture() {
+    let source = r#"
+        attribute def = x => source_node = @name
+        (function_definition name: (identifier) @name) {
+          node n
+          attr (n) def = @name
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(error, "Undefined syntax capture @name at (2, 44)");
+}
+
 #[test]
 fn cannot_parse_multiple_patterns() {
     let source = r#"
@@ -1541,6 +2241,44 @@ fn cannot_parse_multiple_patterns() {
     }
 }
 
+#[test]
+fn can_parse_capture_aggregates() {
+    let source = r#"
+        (function_definition parameters: (parameters (_)* @params))
+        {
+          let n = (count @params)
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse");
+    let statement = &file.stanzas[0].statements[0];
+    assert_eq!(statement.to_string(), "let n = (count @params) at (4, 11)");
+    match statement {
+        Statement::DeclareImmutable(DeclareImmutable {
+            value: Expression::CaptureAggregate(expr),
+            ..
+        }) => assert_eq!(expr.aggregate, Aggregate::Count),
+        _ => panic!("Unexpected statement {}", statement),
+    }
+}
+
+#[test]
+fn cannot_parse_capture_aggregate_of_unquantified_capture() {
+    let source = r#"
+        (function_definition name: (_) @name)
+        {
+          let n = (first @name)
+        }
+    "#;
+    let err = match File::from_str(tree_sitter_python::language(), source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(e) => e,
+    };
+    assert_eq!(
+        err.to_string(),
+        "Function first expects a capture quantified with * or +, but @name is not at (4, 19)"
+    );
+}
+
 #[test]
 fn query_parse_errors_have_file_location() {
     let source = r#"
@@ -1550,7 +2288,7 @@ fn query_parse_errors_have_file_location() {
     "#;
     let err = match File::from_str(tree_sitter_python::language(), source) {
         Ok(_) => panic!("Parse succeeded unexpectedly"),
-        Err(ParseError::QueryError(e)) => e,
+        Err(ParseError::QueryError(e, _)) => e,
         Err(e) => panic!("Unexpected error: {}", e),
     };
     assert_eq!(err.row, 2, "expected row 2, got {}", err.row);
@@ -1572,7 +2310,7 @@ fn multiline_query_parse_errors_have_file_location() {
     "#;
     let err = match File::from_str(tree_sitter_python::language(), source) {
         Ok(_) => panic!("Parse succeeded unexpectedly"),
-        Err(ParseError::QueryError(e)) => e,
+        Err(ParseError::QueryError(e, _)) => e,
         Err(e) => panic!("Unexpected error: {}", e),
     };
     assert_eq!(err.row, 6, "expected row 6, got {}", err.row);
@@ -1580,6 +2318,162 @@ fn multiline_query_parse_errors_have_file_location() {
     assert_eq!(err.offset, 112, "expected offset 112, got {}", err.offset);
 }
 
+#[test]
+fn query_errors_in_multi_pattern_stanzas_have_file_location() {
+    let source = r#"
+        (module) @root
+        {}
+        [
+          (function_definition)
+          (non_existing_node)
+          (class_definition)
+        ] @def
+        {}
+    "#;
+    let err = match File::from_str(tree_sitter_python::language(), source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(e) => e,
+    };
+    let (query_err, stanza) = match &err {
+        ParseError::QueryError(e, stanza) => (e, stanza),
+        e => panic!("Unexpected error: {}", e),
+    };
+    assert_eq!((query_err.row, query_err.column), (5, 11));
+    assert_eq!(
+        &source[query_err.offset..query_err.offset + 17],
+        "non_existing_node"
+    );
+    assert_eq!(*stanza, Location { row: 3, column: 8 });
+    assert_eq!(
+        err.to_string(),
+        "Invalid query pattern at (6, 12) in stanza at (4, 9): unknown node type `non_existing_node`"
+    );
+}
+
+#[test]
+fn query_errors_of_all_stanzas_are_reported() {
+    let source = r#"
+        (module (non_existing_node)) @_a
+        {}
+        (function_definition non_existing_field: (_)) @_b
+        {}
+        (module) @_c
+        {}
+        (module (pass_statement) (module)) @_d
+        {}
+    "#;
+    let err = match File::from_str(tree_sitter_python::language(), source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(e) => e,
+    };
+    let errors = match &err {
+        ParseError::QueryErrors(errors) => errors,
+        e => panic!("Unexpected error: {}", e),
+    };
+    let locations = errors
+        .iter()
+        .map(|(e, stanza)| (e.row, e.column, *stanza))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        locations,
+        vec![
+            (1, 17, Location { row: 1, column: 8 }),
+            (3, 29, Location { row: 3, column: 8 }),
+            (7, 33, Location { row: 7, column: 8 }),
+        ]
+    );
+    assert_eq!(
+        err.to_string(),
+        concat!(
+            "Invalid query pattern at (2, 18) in stanza at (2, 9): unknown node type `non_existing_node`\n",
+            "Invalid query pattern at (4, 30) in stanza at (4, 9): unknown field `non_existing_field`\n",
+            "Invalid query pattern at (8, 34) in stanza at (8, 9): impossible pattern\n",
+            "        (module (pass_statement) (module)) @_d\n",
+            "                                 ^",
+        )
+    );
+}
+
+#[test]
+fn query_errors_suggest_similar_names() {
+    let source = r#"
+        (module (identifer)) @_a
+        {}
+        (function_definition nme: (_)) @_b
+        {}
+    "#;
+    let err = match File::from_str(tree_sitter_python::language(), source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(e) => e,
+    };
+    let errors = match &err {
+        ParseError::QueryErrors(errors) => errors,
+        e => panic!("Unexpected error: {}", e),
+    };
+    let messages = errors
+        .iter()
+        .map(|(e, _)| e.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, vec!["identifer\nidentifier", "nme\nname"]);
+    assert_eq!(
+        err.to_string(),
+        concat!(
+            "Invalid query pattern at (2, 18) in stanza at (2, 9): unknown node type `identifer`, did you mean `identifier`?\n",
+            "Invalid query pattern at (4, 30) in stanza at (4, 9): unknown field `nme`, did you mean `name`?",
+        )
+    );
+}
+
+#[test]
+fn query_syntax_errors_quote_the_file() {
+    let source = r#"
+        (module) @root
+        {}
+        [
+          (function_definition)
+          (class_definition))
+          (pass_statement)
+        ]
+        {}
+    "#;
+    let err = match File::from_str(tree_sitter_python::language(), source) {
+        Ok(_) => panic!("Parse succeeded unexpectedly"),
+        Err(ParseError::QueryError(e, _)) => e,
+        Err(e) => panic!("Unexpected error: {}", e),
+    };
+    assert_eq!((err.row, err.column), (5, 28));
+    assert_eq!(
+        err.message,
+        "          (class_definition))\n                            ^"
+    );
+}
+
+#[test]
+fn can_parse_with_stats() {
+    let source = r#"
+        (module) @_root
+        {}
+
+          (function_definition) @_def
+        {}
+    "#;
+    let (file, stats) =
+        File::from_source_with_stats(tree_sitter_python::language(), source).expect("parse");
+    assert_eq!(file.stanzas.len(), 2);
+    let locations = stats
+        .per_stanza_query_compile
+        .iter()
+        .map(|(location, _)| *location)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        locations,
+        vec![
+            Location { row: 1, column: 8 },
+            Location { row: 4, column: 10 }
+        ]
+    );
+}
+
 #[test]
 fn cannot_parse_unused_capture() {
     let source = r#"
@@ -1608,3 +2502,621 @@ fn can_parse_inherit_directives() {
     let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
     assert!(file.inherited_variables.contains("scope".into()));
 }
+
+#[test]
+fn can_parse_inherit_attribute_directives() {
+    let source = r#"
+        inherit .scope
+        inherit module_path along edge kind = "contains"
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    assert!(file.inherited_variables.contains("scope"));
+    assert_eq!(
+        file.inherited_attributes,
+        vec![InheritedAttribute {
+            name: "module_path".into(),
+            edge_attribute: "kind".into(),
+            edge_value: "contains".into(),
+            location: Location { row: 2, column: 16 },
+        }]
+    );
+}
+
+#[test]
+fn cannot_parse_inherit_attribute_directive_without_edge() {
+    let source = r#"
+        inherit module_path
+    "#;
+    assert!(File::from_str(tree_sitter_python::language(), source).is_err());
+}
+
+#[test]
+fn can_parse_append_attributes() {
+    let source = r#"
+        (module)
+        {
+          node n
+          attr (n) tags += "first", name = "n"
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    let statements = &file.stanzas[0].statements;
+    let attributes = match &statements[1] {
+        Statement::AddGraphNodeAttribute(stmt) => &stmt.attributes,
+        stmt => panic!("Unexpected statement {}", stmt),
+    };
+    assert_eq!(
+        attributes,
+        &vec![
+            Attribute {
+                name: "tags".into(),
+                value: String::from("first").into(),
+                operation: AttributeOperation::Append,
+            },
+            Attribute {
+                name: "name".into(),
+                value: String::from("n").into(),
+                operation: AttributeOperation::Set,
+            },
+        ]
+    );
+    assert_eq!(
+        format!("{}", statements[1]),
+        r#"attr (n) tags += "first" name = "n" at (5, 11)"#
+    );
+}
+
+#[test]
+fn can_parse_override_and_remove_attributes() {
+    let source = r#"
+        (module)
+        {
+          node n
+          attr (n) kind := "call"
+          delattr (n) name, tags
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    let statements = &file.stanzas[0].statements;
+    let attributes = match &statements[1] {
+        Statement::AddGraphNodeAttribute(stmt) => &stmt.attributes,
+        stmt => panic!("Unexpected statement {}", stmt),
+    };
+    assert_eq!(
+        attributes,
+        &vec![Attribute {
+            name: "kind".into(),
+            value: String::from("call").into(),
+            operation: AttributeOperation::Override,
+        }]
+    );
+    assert_eq!(
+        format!("{}", statements[1]),
+        r#"attr (n) kind := "call" at (5, 11)"#
+    );
+    let names = match &statements[2] {
+        Statement::RemoveAttributes(stmt) => &stmt.names,
+        stmt => panic!("Unexpected statement {}", stmt),
+    };
+    assert_eq!(names, &vec![Identifier::from("name"), "tags".into()]);
+    assert_eq!(
+        format!("{}", statements[2]),
+        "delattr (n) name, tags at (6, 11)"
+    );
+}
+
+#[test]
+fn cannot_parse_append_outside_attributes() {
+    let source = r#"
+        (module)
+        {
+          var x = []
+          set x += 1
+        }
+    "#;
+    assert!(File::from_str(tree_sitter_python::language(), source).is_err());
+}
+
+#[test]
+fn can_parse_node_types() {
+    let source = r#"
+        nodetype definition { kind = "definition", is_exported = #false, tags = ["a", 1] }
+
+        (module)
+        {
+          node n : definition
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    let node_type = file
+        .node_types
+        .get(&"definition".into())
+        .expect("missing node type");
+    assert_eq!(node_type.location, Location { row: 1, column: 17 });
+    assert_eq!(node_type.attributes.len(), 3);
+    let statement = match &file.stanzas[0].statements[0] {
+        Statement::CreateGraphNode(stmt) => stmt,
+        stmt => panic!("Unexpected statement {}", stmt),
+    };
+    assert_eq!(statement.node_type, Some("definition".into()));
+    assert_eq!(
+        statement.defaults,
+        vec![
+            ("kind".into(), "definition".into()),
+            ("is_exported".into(), false.into()),
+            ("tags".into(), vec![Value::from("a"), Value::from(1)].into()),
+        ]
+    );
+    assert_eq!(format!("{}", statement), "node n : definition at (6, 11)");
+}
+
+#[test]
+fn cannot_parse_undefined_node_type() {
+    let source = r#"
+        (module)
+        {
+          node n : definition
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(error, "Undefined node type definition at (4, 11)");
+}
+
+#[test]
+fn cannot_parse_node_type_with_non_constant_default() {
+    let source = r#"
+        nodetype definition { kind = (plus 1 2) }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Expected constant value for attribute kind at (2, 18)"
+    );
+}
+
+#[test]
+fn cannot_parse_node_type_that_appends() {
+    let source = r#"
+        nodetype definition { tags += "x" }
+    "#;
+    assert!(File::from_str(tree_sitter_python::language(), source).is_err());
+}
+
+#[test]
+fn can_parse_phases() {
+    let source = r#"
+        (module) {}
+
+        phase declare
+        (identifier) {}
+
+        phase connect
+        (function_definition) {}
+
+        phase declare
+        (class_definition) {}
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    assert_eq!(
+        file.phases,
+        vec![Identifier::from("declare"), Identifier::from("connect")]
+    );
+    let stanzas = file
+        .stanzas
+        .iter()
+        .map(|stanza| (stanza.phase.clone(), stanza.range.start.row))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stanzas,
+        vec![
+            (None, 1),
+            (Some("declare".into()), 4),
+            (Some("declare".into()), 10),
+            (Some("connect".into()), 7),
+        ]
+    );
+}
+
+#[test]
+fn can_parse_stanza_names() {
+    let source = r#"
+        stanza modules
+        (module) {}
+
+        (identifier) {}
+
+        stanza functions (function_definition) {}
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    let names = file
+        .stanzas
+        .iter()
+        .map(|stanza| stanza.name.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![Some("modules".into()), None, Some("functions".into())]
+    );
+}
+
+#[test]
+fn cannot_parse_duplicate_stanza_names() {
+    let source = r#"
+        stanza modules
+        (module) {}
+
+        stanza modules
+        (module) {}
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(error, "Duplicate stanza name modules at (5, 16)");
+}
+
+#[test]
+fn cannot_read_scoped_variables_set_in_later_phase() {
+    let source = r#"
+        phase declare
+        (module) @m
+        {
+          node n
+          attr (n) value = @m.value
+        }
+
+        phase connect
+        (module) @m
+        {
+          let @m.value = 1
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Scoped variable value is read before the phase in which it is set at (6, 31)"
+    );
+}
+
+#[test]
+fn cannot_set_scoped_variables_that_are_only_declared_immutable() {
+    let source = r#"
+        (module) @m
+        {
+          let @m.kind = "module"
+        }
+
+        (module) @m
+        {
+          set @m.kind = "package"
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Cannot set scoped variable @m.kind at (9, 18), which is only declared with let"
+    );
+}
+
+#[test]
+fn cannot_set_scoped_variables_that_are_never_declared() {
+    let source = r#"
+        (module) @m
+        {
+          set @m.kind = "package"
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Cannot set scoped variable @m.kind at (4, 18), which is never declared"
+    );
+}
+
+#[test]
+fn can_parse_version_directives() {
+    let file =
+        File::from_str(tree_sitter_python::language(), "(module) {}").expect("parse to succeed");
+    assert_eq!(file.declared_version, None);
+    assert_eq!(file.dsl_version(), tree_sitter_graph::DSL_VERSION);
+
+    let source = r#"
+        ;; Rules for Python modules
+        ;; tsg 1
+        (module) {}
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    assert_eq!(file.declared_version, Some(1));
+    assert_eq!(file.dsl_version(), 1);
+
+    let source = r#"
+        version 2
+        (module) {}
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    assert_eq!(file.dsl_version(), 2);
+
+    for &(source, version) in &[
+        (";tsg 1\r\n(module) {}", Some(1)),
+        (";;;\ttsg\t1 \t\n(module) {}", Some(1)),
+        (";; tsg1\n(module) {}", None),
+        (";; tsg 1 2\n(module) {}", None),
+        (";; tsg\n(module) {}", None),
+        ("; not tsg 1\n(module) {}", None),
+    ] {
+        let file =
+            File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+        assert_eq!(file.declared_version, version, "{:?}", source);
+    }
+}
+
+#[test]
+fn cannot_parse_unsupported_versions() {
+    let source = r#"
+        version 3
+        (module) {}
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Unsupported DSL version 3 at (2, 9), supported versions are 1 to 2"
+    );
+
+    let source = r#"
+        ;; tsg 0
+        (module) {}
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Unsupported DSL version 0 at (2, 9), supported versions are 1 to 2"
+    );
+}
+
+#[test]
+fn can_parse_condition_keywords_per_version() {
+    let stanza = r#"
+        (module (pass_statement)? @pass)
+        {
+          let _found = @pass
+          let none_found = #true
+          if none_found {}
+        }
+    "#;
+    fn condition(file: &File) -> &Condition {
+        match &file.stanzas[0].statements[2] {
+            Statement::If(statement) => &statement.arms[0].conditions[0],
+            statement => panic!("unexpected statement {}", statement),
+        }
+    }
+
+    // Version 1 parses the keyword prefix of the identifier as a keyword.
+    let source = format!(";; tsg 1\n{}", stanza);
+    let file = File::from_str(tree_sitter_python::language(), &source).expect("parse to succeed");
+    match condition(&file) {
+        Condition::None { value, .. } => assert_eq!(value.to_string(), "_found"),
+        condition => panic!("unexpected condition {}", condition),
+    }
+
+    let source = format!(";; tsg 2\n{}", stanza);
+    let file = File::from_str(tree_sitter_python::language(), &source).expect("parse to succeed");
+    match condition(&file) {
+        Condition::Bool { value, .. } => assert_eq!(value.to_string(), "none_found"),
+        condition => panic!("unexpected condition {}", condition),
+    }
+}
+
+#[test]
+fn can_parse_attributes_for_several_targets() {
+    let source = r#"
+        (module)
+        {
+          node a
+          node b
+          attr (a, b) kind = "definition"
+          attr (a -> b, b -> a) precedence = 1
+          attr (a) kind = "reference"
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("parse to succeed");
+    let statements = &file.stanzas[0].statements;
+    let targets = match &statements[2] {
+        Statement::AddAttributes(stmt) => &stmt.targets,
+        stmt => panic!("Unexpected statement {}", stmt),
+    };
+    assert_eq!(targets.len(), 2);
+    assert!(matches!(targets[0], AttributeTarget::GraphNode(_)));
+    assert_eq!(
+        format!("{}", statements[2]),
+        r#"attr (a, b) kind = "definition" at (6, 11)"#
+    );
+    assert_eq!(
+        format!("{}", statements[3]),
+        r#"attr (a -> b, b -> a) precedence = 1 at (7, 11)"#
+    );
+    assert!(matches!(statements[4], Statement::AddGraphNodeAttribute(_)));
+}
+
+#[test]
+fn cannot_parse_mixed_attribute_targets() {
+    let source = r#"
+        (module)
+        {
+          node a
+          node b
+          attr (a, a -> b) kind = "definition"
+        }
+    "#;
+    let error = File::from_str(tree_sitter_python::language(), source)
+        .expect_err("parse to fail")
+        .to_string();
+    assert_eq!(
+        error,
+        "Cannot mix graph node and edge targets in attr statement at (6, 11)"
+    );
+}
+
+#[test]
+fn can_report_shadowing_warnings() {
+    let source = r#"
+        global root
+        (function_definition name: (identifier) @root) @filename
+        {
+          node n
+          attr (n) def = @filename
+          let name = (source-text @root)
+          if (eq name "f") {
+            let name = "g"
+          }
+          scan name {
+            "x" {
+              let name = "y"
+              let local = name
+            }
+          }
+          let filename = name
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+    let mut globals = Variables::new();
+    globals
+        .add(Identifier::from("filename"), Value::from("test.py"))
+        .unwrap();
+
+    let warnings = file.check_warnings(None);
+    assert_eq!(
+        warnings,
+        vec![
+            CheckWarning::CaptureShadowsGlobal(
+                "root".into(),
+                Location { row: 2, column: 8 },
+                Some(Location { row: 1, column: 15 }),
+            ),
+            CheckWarning::LocalShadowsLocal(
+                "name".into(),
+                Location { row: 8, column: 16 },
+                Location { row: 6, column: 14 },
+            ),
+            CheckWarning::LocalShadowsLocal(
+                "name".into(),
+                Location {
+                    row: 12,
+                    column: 18
+                },
+                Location { row: 6, column: 14 },
+            ),
+        ]
+    );
+
+    let warnings = file
+        .check_warnings(Some(&globals))
+        .into_iter()
+        .map(|warning| warning.to_string())
+        .collect::<Vec<_>>();
+    assert!(warnings.contains(
+        &"Capture @filename in stanza at (3, 9) has the same name as global variable filename"
+            .to_string()
+    ));
+    assert!(warnings.contains(
+        &"Local variable filename at (17, 15) shadows global variable filename".to_string()
+    ));
+    assert_eq!(warnings.len(), 5);
+}
+
+#[test]
+fn can_suppress_shadowing_warnings() {
+    let source = r#"
+        global root
+        ; #allow(shadow)
+        (function_definition name: (identifier) @root)
+        {
+          let name = (source-text @root)
+          if (eq name "f") {
+            let name = "g" ; #allow(shadow)
+          }
+          scan name {
+            "x" {
+              ;; #allow(shadow) intentionally reuses the name
+              let name = "y"
+            }
+          }
+        }
+    "#;
+    let file = File::from_str(tree_sitter_python::language(), source).expect("Cannot parse file");
+    assert_eq!(file.check_warnings(None), vec![]);
+}
+
+#[test]
+fn cannot_parse_too_deeply_nested_expressions() {
+    let nested = |depth: usize| {
+        format!(
+            "(module) {{\n  let x = {}1{}\n}}\n",
+            "[".repeat(depth),
+            "]".repeat(depth)
+        )
+    };
+    // the stanza block accounts for one level
+    File::from_str(
+        tree_sitter_python::language(),
+        &nested(MAX_NESTING_DEPTH - 2),
+    )
+    .expect("Cannot parse file");
+    let err = File::from_str(tree_sitter_python::language(), &nested(100_000))
+        .expect_err("Parse succeeded unexpectedly");
+    assert!(matches!(err, ParseError::NestingTooDeep(..)), "{}", err);
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Nesting deeper than {} levels at (2, {})",
+            MAX_NESTING_DEPTH,
+            MAX_NESTING_DEPTH + 10
+        )
+    );
+
+    let source = format!(
+        "(module) @mod {{\n  let x = @mod{}\n}}\n",
+        ".x".repeat(100_000)
+    );
+    let err = File::from_str(tree_sitter_python::language(), &source)
+        .expect_err("Parse succeeded unexpectedly");
+    assert!(matches!(err, ParseError::NestingTooDeep(..)), "{}", err);
+
+    let source = format!(
+        "(module) {{\n{}{}}}\n",
+        "if #true {\n".repeat(100_000),
+        "}\n".repeat(100_000)
+    );
+    let err = File::from_str(tree_sitter_python::language(), &source)
+        .expect_err("Parse succeeded unexpectedly");
+    assert!(matches!(err, ParseError::NestingTooDeep(..)), "{}", err);
+}
+
+#[test]
+fn can_configure_max_nesting_depth() {
+    // the stanza block accounts for one level
+    let source = format!(
+        "(module) {{\n  let x = {}1{}\n}}\n",
+        "[".repeat(98),
+        "]".repeat(98)
+    );
+    File::from_str(tree_sitter_python::language(), &source)
+        .expect_err("Parse succeeded unexpectedly");
+    File::from_str_with_max_nesting_depth(tree_sitter_python::language(), &source, 100)
+        .expect("Cannot parse file");
+    let err = File::from_str_with_max_nesting_depth(tree_sitter_python::language(), &source, 10)
+        .expect_err("Parse succeeded unexpectedly");
+    assert_eq!(err.to_string(), "Nesting deeper than 10 levels at (2, 20)");
+}
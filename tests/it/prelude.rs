@@ -0,0 +1,47 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+// Every name in the prelude is imported explicitly, so that removing one from the prelude fails
+// to compile.  Add new names here when adding them to the prelude.
+#[allow(unused_imports)]
+use tree_sitter_graph::prelude::{
+    Arguments, CancelAfter, CancellationError, CancellationFlag, Edge, ErrorPolicy,
+    ExecutionConfig, ExecutionError, ExecutionProgress, File, Function, Functions, Graph,
+    GraphNode, GraphNodeRef, Identifier, Location, NoCancellation, ParameterType, Parameters,
+    ParseError, Signature, SyntaxNodeRef, Value, ValueFunction, VariableError, Variables,
+};
+use tree_sitter_graph::tree_sitter::Parser;
+
+#[test]
+fn can_execute_with_prelude() {
+    let python_source = "pass";
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        r#"
+          (module) @m
+          {
+            node n
+            attr (n) kind = (node-type @m)
+          }
+        "#,
+    )
+    .expect("Cannot parse file");
+    let functions = Functions::stdlib();
+    let globals = Variables::new();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph: Graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    let node: GraphNodeRef = graph.iter_nodes().next().unwrap();
+    assert_eq!(
+        graph[node].attributes.get(&Identifier::from("kind")),
+        Some(&Value::from("module"))
+    );
+}
@@ -0,0 +1,184 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use regex::Regex;
+use tree_sitter_graph::ast::File;
+use tree_sitter_graph::regex_engine::LiteRegex;
+use tree_sitter_graph::regex_engine::LiteRegexError;
+use tree_sitter_graph::Location;
+use tree_sitter_graph::ParseError;
+
+const PATTERNS: &[&str] = &[
+    "abc",
+    "a|ab|abc",
+    "ab|a",
+    "([^/]+)/",
+    r"([^/]+)\.py$",
+    "^a",
+    "b$",
+    "^$",
+    "a*",
+    "a+?",
+    "a*?b",
+    "(a|b)*c",
+    "(a*)*b",
+    "(a|ab)(c|bcd)(d*)",
+    "(?:ab)+",
+    "a{2}",
+    "a{2,}",
+    "a{1,3}?",
+    "(a)|(b)",
+    "x(a)?y",
+    r"\d+",
+    r"\w+\s*=\s*\w+",
+    r"\D\W\S",
+    "[a-c-]+",
+    "[]a]",
+    r"[\d.]+",
+    "[^a-z]+",
+    r"\n|\t",
+    "é+",
+    ".+",
+    r"\(\)\[\]\{\}\*\+\?\.\|\^\$\\",
+];
+
+const TEXTS: &[&str] = &[
+    "",
+    "abc",
+    "aab",
+    "ab",
+    "abcd",
+    "alpha/beta/gamma/delta.py",
+    "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac",
+    "xay xy",
+    "ba",
+    "name = value",
+    "a-b]c",
+    "3.14 and 42",
+    "line\nline\tend",
+    r"()[]{}*+?.|^$\",
+];
+
+#[test]
+fn lite_regex_agrees_with_regex_crate() {
+    for pattern in PATTERNS {
+        let expected = Regex::new(pattern).unwrap();
+        let actual = LiteRegex::new(pattern).unwrap();
+        for text in TEXTS {
+            assert_eq!(
+                actual.find(text),
+                expected.find(text).map(|m| m.range()),
+                "find /{}/ in {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(
+                actual.captures(text),
+                expected
+                    .captures(text)
+                    .map(|c| c.iter().map(|m| m.map(|m| m.range())).collect()),
+                "captures /{}/ in {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+}
+
+#[test]
+fn lite_regex_matches_non_ascii_text() {
+    let regex = LiteRegex::new("(é+)(.)").unwrap();
+    assert_eq!(
+        regex.captures("caféé!"),
+        Some(vec![Some(3..8), Some(3..7), Some(7..8)])
+    );
+    // Unlike in the regex crate, \w only matches ASCII word characters
+    let regex = LiteRegex::new(r"\w+").unwrap();
+    assert_eq!(regex.find("caféé!"), Some(0..3));
+}
+
+#[test]
+fn lite_regex_matches_in_linear_time() {
+    let text = "a".repeat(10_000);
+    let regex = LiteRegex::new("(a*)*b").unwrap();
+    assert_eq!(regex.find(&text), None);
+}
+
+#[test]
+fn lite_regex_finds_successive_matches_in_linear_time() {
+    let text = "ab".repeat(500_000);
+    let regex = LiteRegex::new("a|b").unwrap();
+    let mut offset = 0;
+    let mut count = 0;
+    while let Some(range) = regex.find(&text[offset..]) {
+        offset += range.end;
+        count += 1;
+    }
+    assert_eq!(count, 1_000_000);
+}
+
+#[test]
+fn lite_regex_rejects_unsupported_constructs() {
+    for (pattern, offset) in [
+        (r"\bword\b", 0),
+        (r"a\Bb", 1),
+        (r"\p{L}", 0),
+        (r"\x41", 0),
+        ("(?i)abc", 0),
+        ("(?P<name>a)", 0),
+        ("[[a-z]]", 1),
+        ("[a-z&&[^aeiou]]", 4),
+        ("a{1001}", 1),
+    ] {
+        match LiteRegex::new(pattern) {
+            Err(LiteRegexError::Unsupported(_, actual)) => {
+                assert_eq!(actual, offset, "offset in /{}/", pattern)
+            }
+            result => panic!("Expected /{}/ to be unsupported, got {:?}", pattern, result),
+        }
+    }
+}
+
+#[test]
+fn lite_regex_rejects_invalid_patterns() {
+    for pattern in ["(a", "a)", "[a", "*a", "a{2,1}", "[z-a]", r"a\"] {
+        match LiteRegex::new(pattern) {
+            Err(LiteRegexError::Invalid(..)) => {}
+            result => panic!("Expected /{}/ to be invalid, got {:?}", pattern, result),
+        }
+    }
+}
+
+#[test]
+fn can_parse_scan_arms_with_the_configured_engine() {
+    let source = r#"
+        (module)
+        {
+          scan "abc" {
+            "\\bb" {
+            }
+          }
+        }
+    "#;
+    let result = File::from_str(tree_sitter_python::language(), source);
+    if cfg!(feature = "lite-regex") {
+        match result {
+            Err(ParseError::UnsupportedRegex(pattern, construct, location)) => {
+                assert_eq!(pattern, r"\bb");
+                assert_eq!(
+                    construct,
+                    "a boundary assertion at offset 0 is not supported"
+                );
+                assert_eq!(location, Location { row: 4, column: 12 });
+            }
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Parse succeeded unexpectedly"),
+        }
+    } else if let Err(err) = result {
+        panic!("Parse failed unexpectedly: {}", err);
+    }
+}
@@ -0,0 +1,169 @@
+// -*- coding: utf-8 -*-
+// ------------------------------------------------------------------------------------------------
+// Copyright © 2024, tree-sitter authors.
+// Licensed under either of Apache License, Version 2.0, or MIT license, at your option.
+// Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
+// ------------------------------------------------------------------------------------------------
+
+use std::sync::Mutex;
+
+use indoc::indoc;
+use tree_sitter_graph::assert_graph_eq;
+use tree_sitter_graph::graph::Graph;
+use tree_sitter_graph::testing::assert_graph;
+use tree_sitter_graph::testing::run_corpus;
+use tree_sitter_graph::testing::Expectation;
+use tree_sitter_graph::testing::UPDATE_EXPECTATIONS;
+use tree_sitter_graph::Identifier;
+
+/// Serializes the tests that depend on the value of the update environment variable.
+static UPDATE_EXPECTATIONS_LOCK: Mutex<()> = Mutex::new(());
+
+fn test_graph() -> Graph<'static> {
+    let mut graph = Graph::new();
+    let node0 = graph.add_graph_node();
+    let node1 = graph.add_graph_node();
+    graph[node0]
+        .attributes
+        .add(Identifier::from("kind"), "definition")
+        .unwrap();
+    let _ = graph[node0].add_edge(node1);
+    graph
+}
+
+#[test]
+fn can_assert_graph_ignoring_trailing_whitespace() {
+    let graph = test_graph();
+    assert_graph(
+        &graph,
+        "node 0  \n  kind: \"definition\"\nedge 0 -> 1\nnode 1\n\n\n",
+    );
+    assert_graph_eq!(
+        graph,
+        indoc! {r#"
+          node 0
+            kind: "definition"
+          edge 0 -> 1
+          node 1
+        "#}
+    );
+}
+
+#[test]
+fn can_report_graph_differences() {
+    let graph = test_graph();
+    let panic = std::panic::catch_unwind(|| {
+        assert_graph(
+            &graph,
+            indoc! {r#"
+              node 0
+                kind: "reference"
+              edge 0 -> 1
+              node 1
+            "#},
+        )
+    })
+    .expect_err("assertion succeeded unexpectedly");
+    let message = panic
+        .downcast_ref::<String>()
+        .expect("panic message is not a string");
+    assert!(message.contains("  node 0\n"), "{}", message);
+    assert!(message.contains("- ") && message.contains("kind: \"reference\""));
+    assert!(message.contains("+ ") && message.contains("kind: \"definition\""));
+    assert!(message.contains("  edge 0 -> 1\n"), "{}", message);
+}
+
+#[test]
+fn can_update_file_expectations() {
+    let _lock = UPDATE_EXPECTATIONS_LOCK.lock().unwrap();
+    let graph = test_graph();
+    let path = std::env::temp_dir().join(format!(
+        "tree-sitter-graph-expectation-{}.graph",
+        std::process::id()
+    ));
+    std::fs::write(&path, "node 0\n").unwrap();
+    std::env::set_var(UPDATE_EXPECTATIONS, "1");
+    assert_graph(&graph, Expectation::File(path.clone()));
+    std::env::remove_var(UPDATE_EXPECTATIONS);
+    assert_graph(&graph, Expectation::File(path.clone()));
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        graph.pretty_print().to_string()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn python(language: &str) -> Option<tree_sitter::Language> {
+    match language {
+        "python" => Some(tree_sitter_python::language()),
+        _ => None,
+    }
+}
+
+#[test]
+fn can_run_corpus() {
+    let _lock = UPDATE_EXPECTATIONS_LOCK.lock().unwrap();
+    let count = run_corpus(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus"), python);
+    assert!(count > 0);
+}
+
+#[test]
+fn can_report_and_update_corpus_failures() {
+    let _lock = UPDATE_EXPECTATIONS_LOCK.lock().unwrap();
+    let dir = std::env::temp_dir().join(format!("tree-sitter-graph-corpus-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let case = indoc! {r#"
+      A case whose expectation is out of date.
+
+      ```tsg
+      (identifier) @id {
+        node @id.def
+        attr (@id.def) name = (source-text @id)
+      }
+      ```
+
+      ```python
+      x = 1
+      ```
+
+      ```graph
+      node 0
+        name: "y"
+      ```
+
+      Text after the expectation is kept.
+    "#};
+    let failing = dir.join("failing.md");
+    std::fs::write(&failing, case).unwrap();
+    std::fs::write(
+        dir.join("unknown_language.md"),
+        "```tsg\n```\n```cobol\n```\n```graph\n```\n",
+    )
+    .unwrap();
+
+    let panic = std::panic::catch_unwind(|| run_corpus(&dir, python))
+        .expect_err("corpus succeeded unexpectedly");
+    let message = panic
+        .downcast_ref::<String>()
+        .expect("panic message is not a string");
+    assert!(
+        message.starts_with("2 of 2 corpus cases failed"),
+        "{}",
+        message
+    );
+    assert!(message.contains("failing.md: graph does not match expectation"));
+    assert!(message.contains("- ") && message.contains("name: \"y\""));
+    assert!(message.contains("+ ") && message.contains("name: \"x\""));
+    assert!(message.contains("unknown_language.md: unknown language cobol"));
+
+    std::fs::remove_file(dir.join("unknown_language.md")).unwrap();
+    std::env::set_var(UPDATE_EXPECTATIONS, "1");
+    run_corpus(&dir, python);
+    std::env::remove_var(UPDATE_EXPECTATIONS);
+    assert_eq!(run_corpus(&dir, python), 1);
+    assert_eq!(
+        std::fs::read_to_string(&failing).unwrap(),
+        case.replace("name: \"y\"", "name: \"x\"")
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
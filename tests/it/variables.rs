@@ -5,6 +5,22 @@
 // Please see the LICENSE-APACHE or LICENSE-MIT files in this distribution for license details.
 // ------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "serde")]
+use indoc::indoc;
+#[cfg(feature = "serde")]
+use tree_sitter::Parser;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::ast::File;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::functions::Functions;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::graph::Float;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::graph::Value;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::ExecutionConfig;
+#[cfg(feature = "serde")]
+use tree_sitter_graph::NoCancellation;
 use tree_sitter_graph::Variables;
 
 #[test]
@@ -19,3 +35,94 @@ fn can_create_nested_variables() {
     let w = f(&v);
     w.get(&"foo".into()).expect("Failed to get foo");
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_load_globals_from_json() {
+    let globals = Variables::from_json(
+        r#"{ "path": "a.py", "depth": 3, "ratio": 0.5, "flag": true, "none": null, "names": ["a", 1] }"#,
+    )
+    .expect("Cannot load globals");
+    assert_eq!(globals.get(&"path".into()), Some(&Value::from("a.py")));
+    assert_eq!(globals.get(&"depth".into()), Some(&Value::Integer(3)));
+    assert_eq!(
+        globals.get(&"ratio".into()),
+        Some(&Value::Float(Float::new(0.5).unwrap()))
+    );
+    assert_eq!(globals.get(&"flag".into()), Some(&Value::Boolean(true)));
+    assert_eq!(globals.get(&"none".into()), Some(&Value::Null));
+    assert_eq!(
+        globals.get(&"names".into()),
+        Some(&Value::List(vec![Value::from("a"), Value::Integer(1)]))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn cannot_load_globals_from_invalid_json() {
+    assert!(Variables::from_json(r#"["a"]"#).is_err());
+    assert!(Variables::from_json(r#"{ "a": "#).is_err());
+    match Variables::from_json(r#"{ "config": { "a": 1 } }"#) {
+        Err(error) => assert!(error.to_string().contains("global variable config")),
+        Ok(_) => panic!("Loaded a global variable with an object value"),
+    }
+    match Variables::from_json(r#"{ "big": 18446744073709551615 }"#) {
+        Err(error) => assert!(error.to_string().contains("out of range")),
+        Ok(_) => panic!("Loaded a global variable with an out of range integer"),
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_convert_json_values() {
+    assert_eq!(Value::from_json("-7").unwrap(), Value::Integer(-7));
+    assert_eq!(Value::from_json(r#""x""#).unwrap(), Value::from("x"));
+    assert_eq!(
+        Value::from_json("[[], [false]]").unwrap(),
+        Value::List(vec![
+            Value::List(vec![]),
+            Value::List(vec![Value::Boolean(false)])
+        ])
+    );
+    assert!(Value::from_json("{}").is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn can_read_globals_loaded_from_json() {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_python::language()).unwrap();
+    let python_source = "pass";
+    let tree = parser.parse(python_source, None).unwrap();
+    let file = File::from_str(
+        tree_sitter_python::language(),
+        indoc! {r#"
+          global FILE_PATH
+          global max_depth
+          global exports
+          (module) {
+            node n
+            attr (n) path = FILE_PATH, depth = (plus max_depth 1), exports = exports
+          }
+        "#},
+    )
+    .expect("Cannot parse file");
+    let globals = Variables::from_json(
+        r#"{ "FILE_PATH": "src/main.py", "max_depth": 3, "exports": ["main"] }"#,
+    )
+    .expect("Cannot load globals");
+    let functions = Functions::stdlib();
+    let config = ExecutionConfig::new(&functions, &globals);
+    let graph = file
+        .execute(&tree, python_source, &config, &NoCancellation)
+        .expect("Cannot execute file");
+    assert_eq!(
+        graph.pretty_print().to_string(),
+        indoc! {r#"
+          node 0
+            depth: 4
+            exports: ["main"]
+            path: "src/main.py"
+        "#}
+    );
+}